@@ -1,12 +1,17 @@
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
 use high_performance_server::buffer::Buffer;
+use high_performance_server::cached_response::CachedResponse;
+use high_performance_server::connection::{Connection, WriteStatus};
 use high_performance_server::http::{HttpParser, Method, Request, Response, Status};
 use high_performance_server::memory::MemoryManager;
+use high_performance_server::metrics::{Counter, Histogram};
+use high_performance_server::scan::{self, scalar};
 use std::io::{Cursor, Read, Write};
 use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 fn benchmark_buffer_read_write(c: &mut Criterion) {
     let mut group = c.benchmark_group("buffer");
@@ -42,6 +47,37 @@ fn benchmark_buffer_read_write(c: &mut Criterion) {
     group.finish();
 }
 
+/// Pipelined keep-alive traffic: each read brings several requests plus part
+/// of the next, so a partial request is always left behind to make room around
+fn benchmark_buffer_pipelining(c: &mut Criterion) {
+    let mut group = c.benchmark_group("buffer_pipelining");
+    
+    let request = b"GET /index.html HTTP/1.1\r\nHost: localhost\r\nConnection: keep-alive\r\n\r\n";
+    let stream: Vec<u8> = request.iter().copied().cycle().take(request.len() * 64).collect();
+    let chunk_size = request.len() * 3 + request.len() / 2;
+    group.throughput(Throughput::Bytes(stream.len() as u64));
+    
+    for ring in [false, true] {
+        let name = if ring { "ring" } else { "compacting" };
+        group.bench_function(name, |b| {
+            let mut buffer = if ring { Buffer::ring(4096) } else { Buffer::new(4096) };
+            b.iter(|| {
+                for chunk in stream.chunks(chunk_size) {
+                    let mut cursor = Cursor::new(chunk);
+                    while buffer.read_from(&mut cursor).unwrap() > 0 {}
+                    
+                    // Consume every complete request, leaving the partial one
+                    let complete = buffer.available_data() / request.len() * request.len();
+                    black_box(buffer.peek(complete));
+                    buffer.consume(complete).unwrap();
+                }
+            })
+        });
+    }
+    
+    group.finish();
+}
+
 fn benchmark_http_parsing(c: &mut Criterion) {
     let mut group = c.benchmark_group("http_parser");
     
@@ -82,6 +118,53 @@ fn benchmark_http_parsing(c: &mut Criterion) {
     group.finish();
 }
 
+/// The parser's scans over a browser-sized request head, vectorized and byte by byte
+fn benchmark_header_scanning(c: &mut Criterion) {
+    let mut group = c.benchmark_group("header_scan");
+    
+    let head = "GET /assets/app.js?v=3 HTTP/1.1\r\n\
+                Host: www.example.com\r\n\
+                User-Agent: Mozilla/5.0 (X11; Linux x86_64; rv:128.0) Gecko/20100101 Firefox/128.0\r\n\
+                Accept: text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8\r\n\
+                Accept-Language: en-US,en;q=0.5\r\n\
+                Accept-Encoding: gzip, deflate, br\r\n\
+                Referer: https://www.example.com/products/index.html\r\n\
+                Cookie: session=4f1c9a7e2b8d4c6f9e0a1b2c3d4e5f60; theme=dark; consent=1\r\n\
+                Connection: keep-alive\r\n\
+                \r\n";
+    let lines: Vec<(&str, &str)> = head
+        .split("\r\n")
+        .skip(1)
+        .filter_map(|line| line.split_once(": "))
+        .collect();
+    group.throughput(Throughput::Bytes(head.len() as u64));
+    
+    let simd = if scan::simd_available() { "simd" } else { "fallback" };
+    group.bench_function(format!("find_head_end/{}", simd), |b| {
+        b.iter(|| assert!(scan::find_head_end(black_box(head.as_bytes())).is_some()))
+    });
+    group.bench_function("find_head_end/scalar", |b| {
+        b.iter(|| assert!(scalar::find_head_end(black_box(head.as_bytes())).is_some()))
+    });
+    
+    group.bench_function(format!("validate_headers/{}", simd), |b| {
+        b.iter(|| {
+            for (name, value) in black_box(&lines) {
+                assert!(scan::is_token(name.as_bytes()) && scan::is_header_value(value.as_bytes()));
+            }
+        })
+    });
+    group.bench_function("validate_headers/scalar", |b| {
+        b.iter(|| {
+            for (name, value) in black_box(&lines) {
+                assert!(scalar::is_token(name.as_bytes()) && scalar::is_header_value(value.as_bytes()));
+            }
+        })
+    });
+    
+    group.finish();
+}
+
 fn benchmark_memory_pool(c: &mut Criterion) {
     let mut group = c.benchmark_group("memory_pool");
     
@@ -134,6 +217,56 @@ fn benchmark_memory_pool(c: &mut Criterion) {
     group.finish();
 }
 
+/// Many threads counting into one counter and one histogram, as workers do
+/// under load, against a single shared atomic as counters used to be
+fn benchmark_metrics_contention(c: &mut Criterion) {
+    let mut group = c.benchmark_group("metrics_contention");
+    let per_thread = 10_000;
+    
+    let run = |threads: usize, iterations: u64, record: Arc<dyn Fn() + Send + Sync>| {
+        let started = Instant::now();
+        for _ in 0..iterations {
+            let handles: Vec<_> = (0..threads)
+                .map(|_| {
+                    let record = record.clone();
+                    thread::spawn(move || (0..per_thread).for_each(|_| record()))
+                })
+                .collect();
+            handles.into_iter().for_each(|handle| handle.join().unwrap());
+        }
+        started.elapsed()
+    };
+    
+    for threads in [16, 32] {
+        group.throughput(Throughput::Elements((threads * per_thread) as u64));
+        group.bench_function(format!("shared_atomic/{}", threads), |b| {
+            let shared = Arc::new(AtomicUsize::new(0));
+            b.iter_custom(|iterations| {
+                let shared = shared.clone();
+                run(threads, iterations, Arc::new(move || {
+                    shared.fetch_add(1, Ordering::Relaxed);
+                }))
+            })
+        });
+        group.bench_function(format!("counter/{}", threads), |b| {
+            let counter = Arc::new(Counter::new(0));
+            b.iter_custom(|iterations| {
+                let counter = counter.clone();
+                run(threads, iterations, Arc::new(move || counter.increment(1)))
+            })
+        });
+        group.bench_function(format!("histogram/{}", threads), |b| {
+            let histogram = Arc::new(Histogram::exponential(1.0, 2.0, 24));
+            b.iter_custom(|iterations| {
+                let histogram = histogram.clone();
+                run(threads, iterations, Arc::new(move || histogram.record(black_box(100.0))))
+            })
+        });
+    }
+    
+    group.finish();
+}
+
 fn benchmark_response_serialization(c: &mut Criterion) {
     let mut group = c.benchmark_group("response");
     
@@ -164,14 +297,175 @@ fn benchmark_response_serialization(c: &mut Criterion) {
         })
     });
     
+    let mut response = Response::new(Status::Ok);
+    response.set_header("Cache-Control", "no-store");
+    response.set_body(b"{\"status\":\"ok\"}");
+    
+    // A cached response copying its prepared head, against building and formatting it every time
+    group.bench_function("build_and_serialize", |b| {
+        let mut buffer = Buffer::new(16 * 1024);
+        b.iter(|| {
+            let response = black_box(&response).clone();
+            buffer.ensure_capacity(response.head_len_hint());
+            response.serialize_head(&mut buffer).unwrap();
+            buffer.reset();
+        })
+    });
+    group.bench_function("cached_response", |b| {
+        let cached = CachedResponse::new(response.clone());
+        let mut buffer = Buffer::new(16 * 1024);
+        b.iter(|| {
+            let response = black_box(&cached).response();
+            buffer.ensure_capacity(response.head_len_hint());
+            response.serialize_head(&mut buffer).unwrap();
+            buffer.reset();
+        })
+    });
+    
+    // The head serialized into a fresh vector, then copied into a write buffer
+    group.bench_function("head_via_vec", |b| {
+        let mut buffer = Buffer::new(16 * 1024);
+        b.iter(|| {
+            let mut head = Vec::new();
+            black_box(&response).serialize_head(&mut head).unwrap();
+            buffer.write(&head).unwrap();
+            buffer.reset();
+        })
+    });
+    
+    // The head serialized straight into the write buffer, as `Connection::send_response` does
+    group.bench_function("head_in_place", |b| {
+        let mut buffer = Buffer::new(16 * 1024);
+        b.iter(|| {
+            buffer.ensure_capacity(black_box(&response).head_len_hint());
+            response.serialize_head(&mut buffer).unwrap();
+            buffer.reset();
+        })
+    });
+    
     group.finish();
 }
 
+/// Connect a server-side Connection to a client socket drained by a background thread
+fn drained_connection() -> Connection {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (stream, peer_addr) = listener.accept().unwrap();
+    stream.set_nonblocking(true).unwrap();
+    
+    thread::spawn(move || {
+        let mut buf = [0u8; 64 * 1024];
+        while matches!(client.read(&mut buf), Ok(n) if n > 0) {}
+    });
+    
+    Connection::new(stream, peer_addr, 0).unwrap()
+}
+
+/// Write until the connection has nothing left queued
+fn finish_writes(conn: &mut Connection, mut status: WriteStatus) {
+    while status != WriteStatus::Complete {
+        thread::yield_now();
+        status = conn.flush().unwrap();
+    }
+}
+
+fn benchmark_small_response_writes(c: &mut Criterion) {
+    let mut group = c.benchmark_group("small_response_write");
+    group.throughput(Throughput::Elements(1));
+    
+    let mut response = Response::new(Status::Ok);
+    response.set_body(b"{\"status\":\"ok\"}");
+    let mut head = Vec::new();
+    response.serialize_head(&mut head).unwrap();
+    let body = response.body.clone();
+    
+    // Head and body as two writes, the way two buffer operations would send them
+    group.bench_function("separate_writes", |b| {
+        let mut conn = drained_connection();
+        b.iter(|| {
+            let status = conn.write_all(black_box(&head)).unwrap();
+            finish_writes(&mut conn, status);
+            let status = conn.write_all(black_box(&body)).unwrap();
+            finish_writes(&mut conn, status);
+        })
+    });
+    
+    // Head and body coalesced into a single vectored write
+    group.bench_function("vectored_write", |b| {
+        let mut conn = drained_connection();
+        b.iter(|| {
+            let status = conn.write_response(black_box(&head), black_box(&body)).unwrap();
+            finish_writes(&mut conn, status);
+        })
+    });
+    
+    group.finish();
+}
+
+/// Time accepting connections already waiting in the backlog, a batch at a time
+#[cfg(target_os = "linux")]
+fn time_accepts(iters: u64, accept: impl Fn(&TcpListener)) -> Duration {
+    const BATCH: u64 = 64;
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut elapsed = Duration::ZERO;
+    for _ in 0..iters.div_ceil(BATCH) {
+        let clients: Vec<TcpStream> = (0..BATCH).map(|_| TcpStream::connect(addr).unwrap()).collect();
+        let start = Instant::now();
+        for _ in 0..BATCH {
+            accept(&listener);
+        }
+        elapsed += start.elapsed();
+        drop(clients);
+    }
+    elapsed * iters as u32 / (iters.div_ceil(BATCH) * BATCH) as u32
+}
+
+#[cfg(target_os = "linux")]
+fn benchmark_accept(c: &mut Criterion) {
+    use socket2::SockRef;
+    
+    let mut group = c.benchmark_group("accept");
+    group.throughput(Throughput::Elements(1));
+    
+    // Accept, then make the socket non-blocking with a second system call
+    group.bench_function("accept_then_set_nonblocking", |b| {
+        b.iter_custom(|iters| {
+            time_accepts(iters, |listener| {
+                let (stream, _) = listener.accept().unwrap();
+                stream.set_nonblocking(true).unwrap();
+                black_box(stream);
+            })
+        })
+    });
+    
+    // Create the socket non-blocking in the accept itself
+    group.bench_function("accept4_nonblocking", |b| {
+        b.iter_custom(|iters| {
+            time_accepts(iters, |listener| {
+                let flags = libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC;
+                let (socket, _) = SockRef::from(listener).accept4(flags).unwrap();
+                black_box(TcpStream::from(socket));
+            })
+        })
+    });
+    
+    group.finish();
+}
+
+#[cfg(not(target_os = "linux"))]
+fn benchmark_accept(_c: &mut Criterion) {}
+
 criterion_group!(
     benches,
     benchmark_buffer_read_write,
+    benchmark_buffer_pipelining,
     benchmark_http_parsing,
+    benchmark_header_scanning,
     benchmark_memory_pool,
-    benchmark_response_serialization
+    benchmark_metrics_contention,
+    benchmark_response_serialization,
+    benchmark_small_response_writes,
+    benchmark_accept
 );
 criterion_main!(benches);
\ No newline at end of file
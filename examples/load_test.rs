@@ -1,183 +1,604 @@
-use std::io::{Read, Write};
+use high_performance_server::{ConnectionAcceptor, EventLoop, Response, Router, ServerConfig, Status};
+use std::env;
+use std::io::{self, Read, Write};
 use std::net::TcpStream;
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
 use std::thread;
 use std::time::{Duration, Instant};
 
-fn main() {
-    // Configuration
-    let server_address = "127.0.0.1:8080";
-    let num_threads = 10;
-    let requests_per_thread = 1000;
-    let request_interval_ms = 10; // Milliseconds between requests
-    
-    println!("Load testing server at {}", server_address);
-    println!("Threads: {}", num_threads);
-    println!("Requests per thread: {}", requests_per_thread);
-    println!("Total requests: {}", num_threads * requests_per_thread);
-    println!("Request interval: {} ms", request_interval_ms);
-    
-    // Statistics
-    let total_requests = Arc::new(AtomicUsize::new(0));
-    let successful_requests = Arc::new(AtomicUsize::new(0));
-    let failed_requests = Arc::new(AtomicUsize::new(0));
-    let request_times = Arc::new(Mutex::new(Vec::with_capacity(num_threads * requests_per_thread)));
-    
-    // Start time
-    let start_time = Instant::now();
+const USAGE: &str = "\
+Usage: load_test [options] [address]
+
+Options:
+  -c, --concurrency N     Parallel client connections (default 10)
+  -n, --requests N        Measured requests to send in total (default 10000)
+  -d, --duration SECS     Run for a fixed time instead of a request count
+  -w, --warmup SECS       Unmeasured warmup before recording (default 1)
+      --close             Open a new connection per request instead of keep-alive
+      --body-size BYTES   POST a request body of this size (default 0, a GET)
+      --response-size N   Response body size served in self-test mode (default 128)
+      --path PATH         Request path (default /bench)
+      --format FORMAT     text, csv or json (default text)
+      --self-test         Start the server in-process on a free port
+      --workers N         Event loops for the in-process server (default 1)
+";
+
+/// How results are printed
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Text,
+    Csv,
+    Json,
+}
+
+/// Load test configuration
+#[derive(Debug, Clone)]
+struct Options {
+    address: String,
+    concurrency: usize,
+    requests: usize,
+    duration: Option<Duration>,
+    warmup: Duration,
+    keep_alive: bool,
+    body_size: usize,
+    response_size: usize,
+    path: String,
+    format: Format,
+    self_test: bool,
+    workers: usize,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            address: "127.0.0.1:8080".to_string(),
+            concurrency: 10,
+            requests: 10_000,
+            duration: None,
+            warmup: Duration::from_secs(1),
+            keep_alive: true,
+            body_size: 0,
+            response_size: 128,
+            path: "/bench".to_string(),
+            format: Format::Text,
+            self_test: false,
+            workers: 1,
+        }
+    }
+}
+
+// Parse command-line arguments
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+    let mut options = Options::default();
+    
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or_else(|| format!("{} needs a value", name));
+        match arg.as_str() {
+            "-c" | "--concurrency" => options.concurrency = parse_number(&arg, &value(&arg)?)?,
+            "-n" | "--requests" => options.requests = parse_number(&arg, &value(&arg)?)?,
+            "-d" | "--duration" => {
+                options.duration = Some(Duration::from_secs_f64(parse_number(&arg, &value(&arg)?)?))
+            }
+            "-w" | "--warmup" => options.warmup = Duration::from_secs_f64(parse_number(&arg, &value(&arg)?)?),
+            "--close" => options.keep_alive = false,
+            "--body-size" => options.body_size = parse_number(&arg, &value(&arg)?)?,
+            "--response-size" => options.response_size = parse_number(&arg, &value(&arg)?)?,
+            "--path" => options.path = value(&arg)?,
+            "--format" => {
+                options.format = match value(&arg)?.as_str() {
+                    "text" => Format::Text,
+                    "csv" => Format::Csv,
+                    "json" => Format::Json,
+                    other => return Err(format!("Unknown format: {}", other)),
+                }
+            }
+            "--self-test" => options.self_test = true,
+            "--workers" => options.workers = parse_number(&arg, &value(&arg)?)?,
+            "-h" | "--help" => return Err(String::new()),
+            _ if arg.starts_with('-') => return Err(format!("Unknown option: {}", arg)),
+            _ => options.address = arg,
+        }
+    }
+    
+    if options.concurrency == 0 || options.workers == 0 {
+        return Err("--concurrency and --workers must be at least 1".to_string());
+    }
+    Ok(options)
+}
+
+fn parse_number<T: std::str::FromStr>(name: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("Invalid value for {}: {}", name, value))
+}
+
+/// A latency histogram with HDR-style log-linear buckets
+///
+/// Values below 128 get an exact bucket, and every power of two above that
+/// is split into 64 buckets, so any recorded value is reported to within
+/// about 1.5% while the whole range of `u64` fits in under 4000 counters.
+#[derive(Debug, Clone)]
+struct LatencyHistogram {
+    counts: Vec<u64>,
+    total: u64,
+    min: u64,
+    max: u64,
+    sum: u128,
+}
+
+const SUB_BUCKETS: u64 = 64;
+
+impl LatencyHistogram {
+    fn new() -> Self {
+        Self {
+            counts: vec![0; Self::index_of(u64::MAX) + 1],
+            total: 0,
+            min: u64::MAX,
+            max: 0,
+            sum: 0,
+        }
+    }
+    
+    fn index_of(value: u64) -> usize {
+        if value < 2 * SUB_BUCKETS {
+            return value as usize;
+        }
+        let shift = 63 - value.leading_zeros() as u64 - 6;
+        (shift * SUB_BUCKETS + (value >> shift)) as usize
+    }
+    
+    /// The highest value that lands in a bucket
+    fn highest_in(index: usize) -> u64 {
+        let index = index as u64;
+        if index < 2 * SUB_BUCKETS {
+            return index;
+        }
+        let shift = index / SUB_BUCKETS - 1;
+        let sub = index - shift * SUB_BUCKETS;
+        ((sub + 1) << shift) - 1
+    }
+    
+    fn record(&mut self, value: u64) {
+        self.counts[Self::index_of(value)] += 1;
+        self.total += 1;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.sum += value as u128;
+    }
+    
+    fn merge(&mut self, other: &LatencyHistogram) {
+        for (count, other) in self.counts.iter_mut().zip(&other.counts) {
+            *count += other;
+        }
+        self.total += other.total;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.sum += other.sum;
+    }
     
-    // Spawn worker threads
-    let mut handles = Vec::with_capacity(num_threads);
+    fn percentile(&self, percentile: f64) -> u64 {
+        if self.total == 0 {
+            return 0;
+        }
+        let target = ((self.total as f64 * percentile / 100.0).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (index, count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= target {
+                return Self::highest_in(index).min(self.max);
+            }
+        }
+        self.max
+    }
     
-    for thread_id in 0..num_threads {
-        let total_requests = total_requests.clone();
-        let successful_requests = successful_requests.clone();
-        let failed_requests = failed_requests.clone();
-        let request_times = request_times.clone();
-        let server_address = server_address.to_string();
+    fn mean(&self) -> f64 {
+        if self.total == 0 {
+            0.0
+        } else {
+            self.sum as f64 / self.total as f64
+        }
+    }
+}
+
+/// What one client thread saw
+struct WorkerResult {
+    latencies: LatencyHistogram,
+    successful: u64,
+    failed: u64,
+    bytes_received: u64,
+}
+
+/// A single client connection that sends requests until told to stop
+struct Client {
+    options: Arc<Options>,
+    request: Vec<u8>,
+    stream: Option<TcpStream>,
+    buffer: Vec<u8>,
+}
+
+impl Client {
+    fn new(options: Arc<Options>) -> Self {
+        let method = if options.body_size > 0 { "POST" } else { "GET" };
+        let mut request = format!(
+            "{} {} HTTP/1.1\r\n\
+             Host: localhost\r\n\
+             User-Agent: load-test-client\r\n\
+             Connection: {}\r\n\
+             Content-Length: {}\r\n\
+             \r\n",
+            method,
+            options.path,
+            if options.keep_alive { "keep-alive" } else { "close" },
+            options.body_size
+        )
+        .into_bytes();
+        request.resize(request.len() + options.body_size, b'x');
         
-        let handle = thread::spawn(move || {
-            for i in 0..requests_per_thread {
-                let request_id = (thread_id * requests_per_thread) + i;
-                
-                // Make a request
-                let request_start = Instant::now();
-                match make_request(&server_address, request_id) {
-                    Ok(response_size) => {
-                        let request_time = request_start.elapsed();
-                        successful_requests.fetch_add(1, Ordering::Relaxed);
-                        
-                        // Record the request time
-                        let mut times = request_times.lock().unwrap();
-                        times.push(request_time.as_micros() as f64 / 1000.0); // Convert to milliseconds
-                    }
-                    Err(e) => {
-                        eprintln!("Request {} failed: {}", request_id, e);
-                        failed_requests.fetch_add(1, Ordering::Relaxed);
+        Self {
+            options,
+            request,
+            stream: None,
+            buffer: Vec::with_capacity(16 * 1024),
+        }
+    }
+    
+    // Send one request and read the whole response, returning its size
+    fn send(&mut self) -> io::Result<usize> {
+        if self.stream.is_none() {
+            let stream = TcpStream::connect(&self.options.address)?;
+            stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+            stream.set_write_timeout(Some(Duration::from_secs(5)))?;
+            stream.set_nodelay(true)?;
+            self.stream = Some(stream);
+        }
+        
+        let result = self.exchange();
+        // Drop the connection after an error or when not reusing it
+        match &result {
+            Ok((_, true)) => {}
+            _ => self.stream = None,
+        }
+        result.map(|(size, _)| size)
+    }
+    
+    fn exchange(&mut self) -> io::Result<(usize, bool)> {
+        let stream = self.stream.as_mut().expect("connected");
+        stream.write_all(&self.request)?;
+        
+        // Read until the end of the head
+        self.buffer.clear();
+        let mut chunk = [0u8; 16 * 1024];
+        let head_end = loop {
+            if let Some(pos) = self.buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+                break pos + 4;
+            }
+            let n = stream.read(&mut chunk)?;
+            if n == 0 {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed mid-response"));
+            }
+            self.buffer.extend_from_slice(&chunk[..n]);
+        };
+        
+        let head = String::from_utf8_lossy(&self.buffer[..head_end]).to_ascii_lowercase();
+        if !head.starts_with("http/1.1 2") {
+            let status = head.lines().next().unwrap_or_default().to_string();
+            return Err(io::Error::other(format!("unexpected status: {}", status)));
+        }
+        let content_length = head
+            .lines()
+            .find_map(|line| line.strip_prefix("content-length:"))
+            .and_then(|value| value.trim().parse::<usize>().ok());
+        let reusable = self.options.keep_alive && content_length.is_some() && !head.contains("connection: close");
+        
+        // Read the body, either to its declared length or to the end of the stream
+        match content_length {
+            Some(length) => {
+                while self.buffer.len() < head_end + length {
+                    let n = stream.read(&mut chunk)?;
+                    if n == 0 {
+                        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed mid-body"));
                     }
+                    self.buffer.extend_from_slice(&chunk[..n]);
                 }
-                
-                total_requests.fetch_add(1, Ordering::Relaxed);
-                
-                // Sleep between requests to avoid overwhelming the server
-                thread::sleep(Duration::from_millis(request_interval_ms));
             }
-        });
+            None => {
+                stream.read_to_end(&mut self.buffer)?;
+            }
+        }
         
-        handles.push(handle);
+        Ok((self.buffer.len(), reusable))
     }
+}
+
+// Run one client until the shared stop condition is reached
+fn run_worker(options: Arc<Options>, measure_from: Instant, issued: Arc<AtomicUsize>, stop: Arc<AtomicBool>) -> WorkerResult {
+    let mut client = Client::new(options.clone());
+    let mut result = WorkerResult {
+        latencies: LatencyHistogram::new(),
+        successful: 0,
+        failed: 0,
+        bytes_received: 0,
+    };
     
-    // Status reporting thread
-    let status_thread = {
-        let total_requests = total_requests.clone();
-        let successful_requests = successful_requests.clone();
-        let failed_requests = failed_requests.clone();
+    while !stop.load(Ordering::Relaxed) {
+        let request_start = Instant::now();
+        let measured = request_start >= measure_from;
+        
+        // In request-count mode, claim a slot before sending a measured request
+        if measured && options.duration.is_none() && issued.fetch_add(1, Ordering::Relaxed) >= options.requests {
+            break;
+        }
         
-        thread::spawn(move || {
-            let total_expected = num_threads * requests_per_thread;
-            
-            while total_requests.load(Ordering::Relaxed) < total_expected {
-                let completed = total_requests.load(Ordering::Relaxed);
-                let successful = successful_requests.load(Ordering::Relaxed);
-                let failed = failed_requests.load(Ordering::Relaxed);
-                let elapsed = start_time.elapsed().as_secs_f64();
-                let rps = completed as f64 / elapsed;
-                
-                println!(
-                    "Progress: {}/{} ({:.1}%) - Success: {} Failed: {} - {:.1} req/sec",
-                    completed,
-                    total_expected,
-                    (completed as f64 / total_expected as f64) * 100.0,
-                    successful,
-                    failed,
-                    rps
-                );
-                
-                thread::sleep(Duration::from_secs(1));
+        let outcome = client.send();
+        if !measured {
+            continue;
+        }
+        
+        match outcome {
+            Ok(size) => {
+                result.latencies.record(request_start.elapsed().as_micros() as u64);
+                result.successful += 1;
+                result.bytes_received += size as u64;
             }
-        })
-    };
+            Err(e) => {
+                if result.failed == 0 {
+                    eprintln!("Request failed: {}", e);
+                }
+                result.failed += 1;
+            }
+        }
+    }
     
-    // Wait for all worker threads to complete
-    for handle in handles {
-        handle.join().unwrap();
-    }
-    
-    // Total elapsed time
-    let total_time = start_time.elapsed();
-    
-    // Calculate statistics
-    let total_completed = total_requests.load(Ordering::Relaxed);
-    let successful = successful_requests.load(Ordering::Relaxed);
-    let failed = failed_requests.load(Ordering::Relaxed);
-    let elapsed_secs = total_time.as_secs_f64();
-    let requests_per_second = total_completed as f64 / elapsed_secs;
-    
-    // Calculate percentiles
-    let mut times = request_times.lock().unwrap();
-    times.sort_by(|a, b| a.partial_cmp(b).unwrap());
-    
-    let p50 = percentile(&times, 0.5);
-    let p90 = percentile(&times, 0.9);
-    let p95 = percentile(&times, 0.95);
-    let p99 = percentile(&times, 0.99);
-    
-    // Print final statistics
-    println!("\nLoad Test Results");
-    println!("=================");
-    println!("Total time: {:.2} seconds", elapsed_secs);
-    println!("Total requests: {}", total_completed);
-    println!("Successful requests: {}", successful);
-    println!("Failed requests: {}", failed);
-    println!("Requests per second: {:.2}", requests_per_second);
-    println!("\nLatency (ms):");
-    println!("  50th percentile: {:.2}", p50);
-    println!("  90th percentile: {:.2}", p90);
-    println!("  95th percentile: {:.2}", p95);
-    println!("  99th percentile: {:.2}", p99);
-    println!("  min: {:.2}", times.first().unwrap_or(&0.0));
-    println!("  max: {:.2}", times.last().unwrap_or(&0.0));
-    
-    // Wait for status thread to finish
-    status_thread.join().unwrap();
+    result
 }
 
-// Make a simple HTTP request to the server
-fn make_request(server_address: &str, request_id: usize) -> Result<usize, Box<dyn std::error::Error>> {
-    // Connect to the server
-    let mut stream = TcpStream::connect(server_address)?;
-    
-    // Set timeouts
-    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
-    stream.set_write_timeout(Some(Duration::from_secs(5)))?;
-    
-    // Create a simple HTTP request
-    let request = format!(
-        "GET /load-test/{} HTTP/1.1\r\n\
-         Host: localhost\r\n\
-         User-Agent: load-test-client\r\n\
-         Connection: close\r\n\
-         \r\n",
-        request_id
-    );
-    
-    // Send the request
-    stream.write_all(request.as_bytes())?;
-    
-    // Read the response
-    let mut response = Vec::new();
-    stream.read_to_end(&mut response)?;
-    
-    Ok(response.len())
+/// Aggregated results of a run
+struct Report {
+    options: Arc<Options>,
+    elapsed: Duration,
+    latencies: LatencyHistogram,
+    successful: u64,
+    failed: u64,
+    bytes_received: u64,
 }
 
-// Calculate percentile from a sorted array
-fn percentile(sorted_data: &[f64], percentile: f64) -> f64 {
-    if sorted_data.is_empty() {
-        return 0.0;
+const PERCENTILES: [(&str, f64); 6] = [
+    ("p50", 50.0),
+    ("p90", 90.0),
+    ("p95", 95.0),
+    ("p99", 99.0),
+    ("p99.9", 99.9),
+    ("p99.99", 99.99),
+];
+
+impl Report {
+    fn requests_per_second(&self) -> f64 {
+        self.successful as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+    
+    fn min(&self) -> u64 {
+        if self.latencies.total == 0 { 0 } else { self.latencies.min }
     }
     
-    let index = (sorted_data.len() as f64 * percentile).ceil() as usize - 1;
-    sorted_data[index.min(sorted_data.len() - 1)]
+    fn print_text(&self) {
+        println!("\nLoad Test Results");
+        println!("=================");
+        println!("Target: {}", self.options.address);
+        println!(
+            "Concurrency: {} ({})",
+            self.options.concurrency,
+            if self.options.keep_alive { "keep-alive" } else { "connection per request" }
+        );
+        println!("Measured time: {:.2} seconds", self.elapsed.as_secs_f64());
+        println!("Successful requests: {}", self.successful);
+        println!("Failed requests: {}", self.failed);
+        println!("Requests per second: {:.2}", self.requests_per_second());
+        println!(
+            "Transfer rate: {:.2} MiB/s",
+            self.bytes_received as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON) / (1024.0 * 1024.0)
+        );
+        println!("\nLatency (ms):");
+        for (name, percentile) in PERCENTILES {
+            println!("  {:>7}: {:.3}", name, self.latencies.percentile(percentile) as f64 / 1000.0);
+        }
+        println!("  {:>7}: {:.3}", "mean", self.latencies.mean() / 1000.0);
+        println!("  {:>7}: {:.3}", "min", self.min() as f64 / 1000.0);
+        println!("  {:>7}: {:.3}", "max", self.latencies.max as f64 / 1000.0);
+    }
+    
+    fn print_csv(&self) {
+        let mut header = vec![
+            "concurrency", "keep_alive", "body_size", "seconds", "successful", "failed", "rps", "mean_us", "min_us", "max_us",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect::<Vec<_>>();
+        header.extend(PERCENTILES.iter().map(|(name, _)| format!("{}_us", name.replace('.', "_"))));
+        
+        let mut row = vec![
+            self.options.concurrency.to_string(),
+            self.options.keep_alive.to_string(),
+            self.options.body_size.to_string(),
+            format!("{:.3}", self.elapsed.as_secs_f64()),
+            self.successful.to_string(),
+            self.failed.to_string(),
+            format!("{:.2}", self.requests_per_second()),
+            format!("{:.1}", self.latencies.mean()),
+            self.min().to_string(),
+            self.latencies.max.to_string(),
+        ];
+        row.extend(PERCENTILES.iter().map(|(_, p)| self.latencies.percentile(*p).to_string()));
+        
+        println!("{}", header.join(","));
+        println!("{}", row.join(","));
+    }
+    
+    fn print_json(&self) {
+        let percentiles: serde_json::Map<String, serde_json::Value> = PERCENTILES
+            .iter()
+            .map(|(name, p)| (name.to_string(), self.latencies.percentile(*p).into()))
+            .collect();
+        let report = serde_json::json!({
+            "target": self.options.address,
+            "concurrency": self.options.concurrency,
+            "keep_alive": self.options.keep_alive,
+            "body_size": self.options.body_size,
+            "seconds": self.elapsed.as_secs_f64(),
+            "successful": self.successful,
+            "failed": self.failed,
+            "requests_per_second": self.requests_per_second(),
+            "bytes_received": self.bytes_received,
+            "latency_us": {
+                "mean": self.latencies.mean(),
+                "min": self.min(),
+                "max": self.latencies.max,
+                "percentiles": percentiles,
+            },
+        });
+        println!("{}", serde_json::to_string_pretty(&report).unwrap_or_default());
+    }
+}
+
+// Start the server in-process on a free port, returning its address and a way to stop it
+fn spawn_server(options: &Options) -> io::Result<(String, Arc<AtomicBool>, Vec<thread::JoinHandle<()>>)> {
+    let acceptor = Arc::new(ConnectionAcceptor::new("127.0.0.1:0")?);
+    let address = acceptor.local_addr()?.to_string();
+    let drain = Arc::new(AtomicBool::new(false));
+    
+    let body = Arc::new(vec![b'x'; options.response_size]);
+    let mut router = Router::new();
+    let mut paths = vec!["/bench", options.path.as_str()];
+    paths.dedup();
+    for path in paths {
+        let get_body = body.clone();
+        router.get(path, move |_| {
+            let mut response = Response::new(Status::Ok);
+            response.set_body(&get_body);
+            Ok(response)
+        });
+        let post_body = body.clone();
+        router.post(path, move |_| {
+            let mut response = Response::new(Status::Ok);
+            response.set_body(&post_body);
+            Ok(response)
+        });
+    }
+    let router = Arc::new(router);
+    
+    let config = ServerConfig::default().with_backlog_size(options.concurrency.max(128) as u32);
+    let handles = (0..options.workers)
+        .map(|id| {
+            let acceptor = acceptor.clone();
+            let config = config.clone();
+            let router = router.clone();
+            let drain = drain.clone();
+            thread::spawn(move || {
+                let mut event_loop = EventLoop::with_config(id as u32, acceptor, config);
+                event_loop.set_router(router);
+                event_loop.set_drain_signal(drain);
+                if let Err(e) = event_loop.run() {
+                    eprintln!("In-process server failed: {}", e);
+                }
+            })
+        })
+        .collect();
+    
+    Ok((address, drain, handles))
+}
+
+fn main() {
+    let mut options = match parse_args(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            if !message.is_empty() {
+                eprintln!("{}\n", message);
+            }
+            eprint!("{}", USAGE);
+            std::process::exit(if message.is_empty() { 0 } else { 2 });
+        }
+    };
+    
+    // Self-test mode measures the server without any external setup
+    let server = if options.self_test {
+        match spawn_server(&options) {
+            Ok((address, drain, handles)) => {
+                options.address = address;
+                Some((drain, handles))
+            }
+            Err(e) => {
+                eprintln!("Failed to start in-process server: {}", e);
+                std::process::exit(1);
+            }
+        }
+    } else {
+        None
+    };
+    let options = Arc::new(options);
+    
+    if options.format == Format::Text {
+        println!("Load testing server at {}", options.address);
+        match options.duration {
+            Some(duration) => println!("Duration: {:.1} seconds", duration.as_secs_f64()),
+            None => println!("Requests: {}", options.requests),
+        }
+        println!("Warmup: {:.1} seconds", options.warmup.as_secs_f64());
+    }
+    
+    // Start time; measurement begins once the warmup is over
+    let start_time = Instant::now();
+    let measure_from = start_time + options.warmup;
+    let issued = Arc::new(AtomicUsize::new(0));
+    let stop = Arc::new(AtomicBool::new(false));
+    
+    // Spawn client threads
+    let handles: Vec<_> = (0..options.concurrency)
+        .map(|_| {
+            let options = options.clone();
+            let issued = issued.clone();
+            let stop = stop.clone();
+            thread::spawn(move || run_worker(options, measure_from, issued, stop))
+        })
+        .collect();
+    
+    if let Some(duration) = options.duration {
+        thread::sleep(options.warmup + duration);
+        stop.store(true, Ordering::Relaxed);
+    }
+    
+    // Wait for all client threads and merge what they saw
+    let mut latencies = LatencyHistogram::new();
+    let (mut successful, mut failed, mut bytes_received) = (0, 0, 0);
+    for handle in handles {
+        let result = handle.join().expect("client thread panicked");
+        latencies.merge(&result.latencies);
+        successful += result.successful;
+        failed += result.failed;
+        bytes_received += result.bytes_received;
+    }
+    let elapsed = Instant::now().saturating_duration_since(measure_from);
+    
+    if let Some((drain, handles)) = server {
+        drain.store(true, Ordering::SeqCst);
+        for handle in handles {
+            let _ = handle.join();
+        }
+    }
+    
+    let report = Report {
+        options,
+        elapsed,
+        latencies,
+        successful,
+        failed,
+        bytes_received,
+    };
+    match report.options.format {
+        Format::Text => report.print_text(),
+        Format::Csv => report.print_csv(),
+        Format::Json => report.print_json(),
+    }
+    
+    // A failing run should fail the script running it
+    if report.failed > 0 {
+        std::process::exit(1);
+    }
 }
\ No newline at end of file
@@ -11,12 +11,17 @@ use std::time::Duration;
 
 fn main() -> io::Result<()> {
     // Parse command-line arguments
-    let args: Vec<String> = std::env::args().collect();
+    // --dev reloads open pages whenever a file under the static directory changes
+    let dev_mode = std::env::args().any(|arg| arg == "--dev");
+    let args: Vec<String> = std::env::args().filter(|arg| arg != "--dev").collect();
     let address = args.get(1).map(|s| s.as_str()).unwrap_or("127.0.0.1:8080");
     let static_dir = args.get(2).map(|s| s.to_string()).unwrap_or("static".to_string());
     
     println!("Starting static file server on {}", address);
     println!("Serving files from directory: {}", static_dir);
+    if dev_mode {
+        println!("Development mode: pages reload when files change");
+    }
     
     // Create a router for API endpoints
     let mut router = Router::new();
@@ -44,6 +49,8 @@ fn main() -> io::Result<()> {
         directory_listing: true,                 // Enable directory listings
         max_file_size: 10 * 1024 * 1024,         // 10 MB
         cache_control: "public, max-age=3600".to_string(),
+        live_reload: dev_mode,
+        ..StaticFileConfig::default()
     };
     
     // Add static file routes to the router
@@ -1,8 +1,17 @@
+use crate::config::TcpOptions;
 use crate::connection::Connection;
-use socket2::{Domain, Protocol, Socket, Type};
+use crate::metrics::MetricsRegistry;
+use socket2::{Domain, Protocol, SockRef, Socket, TcpKeepalive, Type};
+use std::collections::HashMap;
+use std::fs::File;
 use std::io;
-use std::net::{SocketAddr, TcpListener, ToSocketAddrs};
+use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
 use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Source of connection IDs, shared so IDs stay unique across acceptors
+static NEXT_CONNECTION_ID: AtomicUsize = AtomicUsize::new(0);
 
 /// The ConnectionAcceptor is responsible for accepting new TCP connections
 /// and distributing them across worker threads using a consistent hashing scheme.
@@ -11,11 +20,21 @@ pub struct ConnectionAcceptor {
     address: String,
     connection_count: AtomicUsize,
     backlog_size: usize,
+    tcp_options: TcpOptions,
+    ip_guard: Option<Arc<IpGuard>>,
+    /// A descriptor held back for shedding connections once the process runs out
+    reserve_fd: Mutex<Option<File>>,
+    shed_connections: AtomicUsize,
 }
 
 impl ConnectionAcceptor {
     /// Create a new connection acceptor bound to the specified address
     pub fn new<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        Self::with_options(addr, 1024, TcpOptions::default())
+    }
+    
+    /// Create a new connection acceptor with an explicit backlog and socket options
+    pub fn with_options<A: ToSocketAddrs>(addr: A, backlog_size: u32, tcp_options: TcpOptions) -> io::Result<Self> {
         // Convert the address to a string for later use
         let socket_addr = addr.to_socket_addrs()?.next().ok_or_else(|| {
             io::Error::new(io::ErrorKind::InvalidInput, "No socket addresses found")
@@ -23,27 +42,148 @@ impl ConnectionAcceptor {
         let addr_str = socket_addr.to_string();
         
         // Create a socket with optimized settings
-        let socket = Self::create_socket(&socket_addr)?;
+        let socket = Self::create_socket(&socket_addr, backlog_size, &tcp_options)?;
         let listener = socket.into();
         
         Ok(Self {
             listener,
             address: addr_str,
             connection_count: AtomicUsize::new(0),
+            backlog_size: backlog_size as usize,
+            tcp_options,
+            ip_guard: None,
+            reserve_fd: Mutex::new(open_reserve_fd()),
+            shed_connections: AtomicUsize::new(0),
+        })
+    }
+    
+    /// Create a connection acceptor from an already bound and listening socket
+    pub fn from_listener(listener: TcpListener) -> io::Result<Self> {
+        listener.set_nonblocking(true)?;
+        let address = listener.local_addr()?.to_string();
+        
+        Ok(Self {
+            listener,
+            address,
+            connection_count: AtomicUsize::new(0),
             backlog_size: 1024, // Default backlog size
+            tcp_options: TcpOptions::default(),
+            ip_guard: None,
+            reserve_fd: Mutex::new(open_reserve_fd()),
+            shed_connections: AtomicUsize::new(0),
         })
     }
     
+    /// Set the options applied to accepted connections
+    ///
+    /// Used for listeners created elsewhere, whose listening socket options
+    /// were already chosen by whoever created them.
+    pub fn with_tcp_options(mut self, tcp_options: TcpOptions) -> Self {
+        self.tcp_options = tcp_options;
+        self
+    }
+    
+    /// Enforce per-IP connection caps and bans on accepted connections
+    pub fn with_ip_limits(mut self, limits: IpLimits) -> Self {
+        self.ip_guard = Some(Arc::new(IpGuard::new(limits)));
+        self
+    }
+    
+    /// Get the per-IP guard, if limits are enabled
+    pub fn ip_guard(&self) -> Option<&Arc<IpGuard>> {
+        self.ip_guard.as_ref()
+    }
+    
     /// Accept a new connection
+    ///
+    /// With IP limits enabled, connections from banned IPs or IPs at their cap
+    /// are reset straight away and the next pending connection is tried.
+    ///
+    /// When the process or system is out of file descriptors, the connection
+    /// at the head of the backlog is shed before the error is returned; see
+    /// [`is_fd_exhaustion`].
     pub fn accept(&self) -> io::Result<Connection> {
+        loop {
+            let (stream, addr) = match self.accept_nonblocking() {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    if is_fd_exhaustion(&e) {
+                        self.shed_pending();
+                    }
+                    return Err(e);
+                }
+            };
+            
+            let slot = match &self.ip_guard {
+                Some(guard) => match IpGuard::admit(guard, addr.ip()) {
+                    Ok(slot) => Some(slot),
+                    Err(_) => {
+                        // Reset rather than close so no TIME_WAIT state is kept for it
+                        let _ = SockRef::from(&stream).set_linger(Some(Duration::ZERO));
+                        continue;
+                    }
+                },
+                None => None,
+            };
+            
+            self.connection_count.fetch_add(1, Ordering::Relaxed);
+            let id = NEXT_CONNECTION_ID.fetch_add(1, Ordering::Relaxed);
+            
+            self.configure_stream(&stream)?;
+            
+            // Create a new connection
+            let mut connection = Connection::new(stream, addr, id)?;
+            if let Some(slot) = slot {
+                connection.set_ip_slot(slot);
+            }
+            return Ok(connection);
+        }
+    }
+    
+    /// Accept the next pending connection as a non-blocking stream
+    ///
+    /// On Linux, accept4 creates the socket non-blocking and close-on-exec,
+    /// saving a system call per connection.
+    #[cfg(target_os = "linux")]
+    fn accept_nonblocking(&self) -> io::Result<(TcpStream, SocketAddr)> {
+        let (socket, addr) = SockRef::from(&self.listener).accept4(libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC)?;
+        let addr = addr
+            .as_socket()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "accepted a non-IP connection"))?;
+        Ok((socket.into(), addr))
+    }
+    
+    /// Accept the next pending connection as a non-blocking stream
+    #[cfg(not(target_os = "linux"))]
+    fn accept_nonblocking(&self) -> io::Result<(TcpStream, SocketAddr)> {
         let (stream, addr) = self.listener.accept()?;
-        let count = self.connection_count.fetch_add(1, Ordering::Relaxed);
-        
-        // Configure the stream for non-blocking operation
         stream.set_nonblocking(true)?;
+        Ok((stream, addr))
+    }
+    
+    /// Turn away the connection at the head of the backlog while out of descriptors
+    ///
+    /// Closing the reserve descriptor frees just enough to accept the
+    /// connection and reset it, so its client fails fast instead of waiting
+    /// in the backlog. The reserve is reopened afterwards.
+    fn shed_pending(&self) {
+        let mut reserve = self.reserve_fd.lock().unwrap();
+        if reserve.take().is_none() {
+            // Lost on an earlier attempt; try to get it back for the next one
+            *reserve = open_reserve_fd();
+            return;
+        }
         
-        // Create a new connection
-        Connection::new(stream, addr, count)
+        if let Ok((stream, _)) = self.listener.accept() {
+            let _ = SockRef::from(&stream).set_linger(Some(Duration::ZERO));
+            self.shed_connections.fetch_add(1, Ordering::Relaxed);
+        }
+        *reserve = open_reserve_fd();
+    }
+    
+    /// Get the number of connections shed because no file descriptors were left
+    pub fn shed_connections(&self) -> usize {
+        self.shed_connections.load(Ordering::Relaxed)
     }
     
     /// Get the local address this acceptor is bound to
@@ -51,8 +191,38 @@ impl ConnectionAcceptor {
         self.listener.local_addr()
     }
     
+    /// Get the listen backlog size
+    pub fn backlog_size(&self) -> usize {
+        self.backlog_size
+    }
+    
+    /// Apply per-connection socket options to an accepted stream
+    fn configure_stream(&self, stream: &TcpStream) -> io::Result<()> {
+        let options = &self.tcp_options;
+        if !options.keepalive_enabled() {
+            return Ok(());
+        }
+        
+        let mut keepalive = TcpKeepalive::new();
+        if let Some(time) = options.keepalive_time {
+            keepalive = keepalive.with_time(time);
+        }
+        
+        #[cfg(any(target_os = "linux", target_os = "macos"))]
+        {
+            if let Some(interval) = options.keepalive_interval {
+                keepalive = keepalive.with_interval(interval);
+            }
+            if let Some(probes) = options.keepalive_probes {
+                keepalive = keepalive.with_retries(probes);
+            }
+        }
+        
+        SockRef::from(stream).set_tcp_keepalive(&keepalive)
+    }
+    
     /// Create a properly configured socket
-    fn create_socket(addr: &SocketAddr) -> io::Result<Socket> {
+    fn create_socket(addr: &SocketAddr, backlog_size: u32, options: &TcpOptions) -> io::Result<Socket> {
         let domain = if addr.is_ipv6() {
             Domain::IPV6
         } else {
@@ -65,15 +235,37 @@ impl ConnectionAcceptor {
         socket.set_nonblocking(true)?;
         socket.set_reuse_address(true)?;
         
+        if let (true, Some(only_v6)) = (addr.is_ipv6(), options.ipv6_only) {
+            socket.set_only_v6(only_v6)?;
+        }
+        
         #[cfg(unix)]
         socket.set_reuse_port(true)?;
         
+        // Accepted sockets inherit the buffer sizes of the listener
+        if let Some(size) = options.recv_buffer_size {
+            socket.set_recv_buffer_size(size)?;
+        }
+        if let Some(size) = options.send_buffer_size {
+            socket.set_send_buffer_size(size)?;
+        }
+        
+        #[cfg(target_os = "linux")]
+        if let Some(timeout) = options.defer_accept {
+            set_tcp_option(&socket, libc::TCP_DEFER_ACCEPT, timeout.as_secs().min(i32::MAX as u64) as libc::c_int)?;
+        }
+        
         // Bind the socket - fixing for cross-platform compatibility
         let sock_addr = socket2::SockAddr::from(*addr);
         socket.bind(&sock_addr)?;
         
-        // Start listening with a large backlog
-        socket.listen(1024)?;
+        socket.listen(backlog_size.min(i32::MAX as u32) as i32)?;
+        
+        // Fast open has to be enabled on a listening socket
+        #[cfg(any(target_os = "linux", target_os = "macos"))]
+        if let Some(queue) = options.fast_open_queue {
+            set_tcp_option(&socket, libc::TCP_FASTOPEN, queue.min(i32::MAX as u32) as libc::c_int)?;
+        }
         
         Ok(socket)
     }
@@ -84,4 +276,362 @@ impl ConnectionAcceptor {
         // In a production system, this would use a more sophisticated consistent hashing approach
         self.connection_count.load(Ordering::Relaxed) % thread_count
     }
+}
+
+/// Set an integer IPPROTO_TCP option that socket2 doesn't cover
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn set_tcp_option(socket: &Socket, option: libc::c_int, value: libc::c_int) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+    
+    let ret = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::IPPROTO_TCP,
+            option,
+            &value as *const libc::c_int as *const libc::c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    
+    Ok(())
+}
+
+/// Per-IP connection limits and the ban policy for misbehaving clients
+#[derive(Debug, Clone)]
+pub struct IpLimits {
+    /// Most connections a single IP may hold open at once
+    pub max_connections_per_ip: usize,
+    
+    /// Malformed requests tolerated within `strike_window` before banning
+    pub max_garbage_strikes: u32,
+    
+    /// How long strikes are remembered
+    pub strike_window: Duration,
+    
+    /// How long a banned IP is refused
+    pub ban_duration: Duration,
+}
+
+impl Default for IpLimits {
+    fn default() -> Self {
+        Self {
+            max_connections_per_ip: 64,
+            max_garbage_strikes: 5,
+            strike_window: Duration::from_secs(60),
+            ban_duration: Duration::from_secs(300),
+        }
+    }
+}
+
+impl IpLimits {
+    /// Create the default limits
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Set the most connections a single IP may hold open
+    pub fn with_max_connections_per_ip(mut self, max: usize) -> Self {
+        self.max_connections_per_ip = max;
+        self
+    }
+    
+    /// Ban IPs that send `strikes` malformed requests within `window`, for `duration`
+    pub fn with_ban(mut self, strikes: u32, window: Duration, duration: Duration) -> Self {
+        self.max_garbage_strikes = strikes;
+        self.strike_window = window;
+        self.ban_duration = duration;
+        self
+    }
+}
+
+/// Why a connection was refused
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IpRejection {
+    /// The IP already holds its maximum number of connections
+    OverLimit,
+    /// The IP is temporarily banned
+    Banned,
+}
+
+#[derive(Debug, Default)]
+struct IpState {
+    active: usize,
+    strikes: u32,
+    first_strike: Option<Instant>,
+    banned_until: Option<Instant>,
+}
+
+impl IpState {
+    fn is_banned(&self, now: Instant) -> bool {
+        self.banned_until.is_some_and(|until| until > now)
+    }
+    
+    /// Check whether the entry carries no information worth keeping
+    fn is_idle(&self, now: Instant, window: Duration) -> bool {
+        self.active == 0
+            && !self.is_banned(now)
+            && self.first_strike.is_none_or(|first| now.duration_since(first) >= window)
+    }
+}
+
+/// Check whether an error means the process or system ran out of file descriptors
+///
+/// These are EMFILE and ENFILE. Accepting fails with them for as long as the
+/// shortage lasts, so callers should back off rather than retry at once.
+pub fn is_fd_exhaustion(error: &io::Error) -> bool {
+    #[cfg(unix)]
+    {
+        matches!(error.raw_os_error(), Some(libc::EMFILE) | Some(libc::ENFILE))
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = error;
+        false
+    }
+}
+
+/// Open the descriptor an acceptor keeps in reserve
+fn open_reserve_fd() -> Option<File> {
+    File::open("/dev/null").ok()
+}
+
+/// Tracks active connections and garbage strikes per source IP
+///
+/// Shared by every event loop accepting from the same listener.
+#[derive(Debug)]
+pub struct IpGuard {
+    limits: IpLimits,
+    states: Mutex<HashMap<IpAddr, IpState>>,
+    rejected_over_limit: AtomicUsize,
+    rejected_banned: AtomicUsize,
+    bans: AtomicUsize,
+}
+
+impl IpGuard {
+    /// Create a guard enforcing the given limits
+    pub fn new(limits: IpLimits) -> Self {
+        Self {
+            limits,
+            states: Mutex::new(HashMap::new()),
+            rejected_over_limit: AtomicUsize::new(0),
+            rejected_banned: AtomicUsize::new(0),
+            bans: AtomicUsize::new(0),
+        }
+    }
+    
+    /// Admit a connection from an IP, returning a slot that frees itself when dropped
+    pub fn admit(guard: &Arc<Self>, ip: IpAddr) -> Result<IpSlot, IpRejection> {
+        let now = Instant::now();
+        let mut states = guard.states.lock().unwrap();
+        let state = states.entry(ip).or_default();
+        
+        if state.is_banned(now) {
+            guard.rejected_banned.fetch_add(1, Ordering::Relaxed);
+            return Err(IpRejection::Banned);
+        }
+        if state.active >= guard.limits.max_connections_per_ip {
+            guard.rejected_over_limit.fetch_add(1, Ordering::Relaxed);
+            return Err(IpRejection::OverLimit);
+        }
+        
+        state.active += 1;
+        Ok(IpSlot {
+            guard: Arc::clone(guard),
+            ip,
+        })
+    }
+    
+    /// Record a malformed request from an IP, returning true if it is now banned
+    pub fn report_garbage(&self, ip: IpAddr) -> bool {
+        let now = Instant::now();
+        let mut states = self.states.lock().unwrap();
+        let state = states.entry(ip).or_default();
+        
+        if state.first_strike.is_none_or(|first| now.duration_since(first) >= self.limits.strike_window) {
+            state.strikes = 0;
+            state.first_strike = Some(now);
+        }
+        state.strikes += 1;
+        
+        if state.strikes >= self.limits.max_garbage_strikes && !state.is_banned(now) {
+            state.banned_until = Some(now + self.limits.ban_duration);
+            state.strikes = 0;
+            state.first_strike = None;
+            self.bans.fetch_add(1, Ordering::Relaxed);
+        }
+        
+        state.is_banned(now)
+    }
+    
+    /// Check whether an IP is currently banned
+    pub fn is_banned(&self, ip: IpAddr) -> bool {
+        let states = self.states.lock().unwrap();
+        states.get(&ip).is_some_and(|state| state.is_banned(Instant::now()))
+    }
+    
+    /// Get the number of open connections from an IP
+    pub fn active(&self, ip: IpAddr) -> usize {
+        let states = self.states.lock().unwrap();
+        states.get(&ip).map(|state| state.active).unwrap_or(0)
+    }
+    
+    /// Get the number of connections refused because their IP was at its cap
+    pub fn rejected_over_limit(&self) -> usize {
+        self.rejected_over_limit.load(Ordering::Relaxed)
+    }
+    
+    /// Get the number of connections refused because their IP was banned
+    pub fn rejected_banned(&self) -> usize {
+        self.rejected_banned.load(Ordering::Relaxed)
+    }
+    
+    /// Publish rejection counts and the number of banned IPs as gauges
+    pub fn publish(&self, registry: &MetricsRegistry) {
+        let now = Instant::now();
+        let banned = {
+            let mut states = self.states.lock().unwrap();
+            states.retain(|_, state| !state.is_idle(now, self.limits.strike_window));
+            states.values().filter(|state| state.is_banned(now)).count()
+        };
+        
+        registry.gauge("connections.rejected_over_ip_limit").set(self.rejected_over_limit());
+        registry.gauge("connections.rejected_banned").set(self.rejected_banned());
+        registry.gauge("connections.ip_bans").set(self.bans.load(Ordering::Relaxed));
+        registry.gauge("connections.banned_ips").set(banned);
+    }
+    
+    fn release(&self, ip: IpAddr) {
+        let now = Instant::now();
+        let mut states = self.states.lock().unwrap();
+        if let Some(state) = states.get_mut(&ip) {
+            state.active = state.active.saturating_sub(1);
+            if state.is_idle(now, self.limits.strike_window) {
+                states.remove(&ip);
+            }
+        }
+    }
+}
+
+/// One connection's share of its IP's limit, released when dropped
+#[derive(Debug)]
+pub struct IpSlot {
+    guard: Arc<IpGuard>,
+    ip: IpAddr,
+}
+
+impl IpSlot {
+    /// Record a malformed request on this connection, returning true if its IP is now banned
+    pub fn report_garbage(&self) -> bool {
+        self.guard.report_garbage(self.ip)
+    }
+}
+
+impl Drop for IpSlot {
+    fn drop(&mut self) {
+        self.guard.release(self.ip);
+    }
+}
+
+#[cfg(unix)]
+impl std::os::unix::io::AsRawFd for ConnectionAcceptor {
+    fn as_raw_fd(&self) -> std::os::unix::io::RawFd {
+        self.listener.as_raw_fd()
+    }
+}
+
+/// Shared per-worker connection counts used to even out accept load
+///
+/// All workers accept from the same listener, so whichever loop wakes first
+/// wins. Workers holding noticeably more connections than the mean skip
+/// accept rounds, leaving new connections to less loaded workers.
+#[derive(Debug)]
+pub struct WorkerLoad {
+    active: Vec<AtomicUsize>,
+    skipped_accepts: Vec<AtomicUsize>,
+    tolerance: usize,
+}
+
+impl WorkerLoad {
+    /// Create load tracking for the given number of workers
+    pub fn new(workers: usize) -> Self {
+        Self {
+            active: (0..workers).map(|_| AtomicUsize::new(0)).collect(),
+            skipped_accepts: (0..workers).map(|_| AtomicUsize::new(0)).collect(),
+            tolerance: 1,
+        }
+    }
+    
+    /// Set how many connections above the mean a worker may hold and still accept
+    pub fn with_tolerance(mut self, tolerance: usize) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+    
+    /// Record a connection accepted by a worker
+    pub fn connection_opened(&self, worker: usize) {
+        if let Some(active) = self.active.get(worker) {
+            active.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+    
+    /// Record a connection closed by a worker
+    pub fn connection_closed(&self, worker: usize) {
+        if let Some(active) = self.active.get(worker) {
+            let _ = active.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |v| Some(v.saturating_sub(1)));
+        }
+    }
+    
+    /// Get the number of active connections on a worker
+    pub fn active(&self, worker: usize) -> usize {
+        self.active.get(worker).map(|a| a.load(Ordering::Relaxed)).unwrap_or(0)
+    }
+    
+    /// Get the mean number of active connections across workers
+    pub fn mean(&self) -> f64 {
+        if self.active.is_empty() {
+            return 0.0;
+        }
+        
+        let total: usize = self.active.iter().map(|a| a.load(Ordering::Relaxed)).sum();
+        total as f64 / self.active.len() as f64
+    }
+    
+    /// Decide whether a worker should sit out this accept round
+    ///
+    /// Skipped rounds are counted so the imbalance shows up in metrics.
+    pub fn should_skip_accept(&self, worker: usize) -> bool {
+        let skip = self.active(worker) as f64 > self.mean() + self.tolerance as f64;
+        if skip {
+            if let Some(skipped) = self.skipped_accepts.get(worker) {
+                skipped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        skip
+    }
+    
+    /// Get the difference between the busiest and the idlest worker
+    pub fn skew(&self) -> usize {
+        let counts = self.active.iter().map(|a| a.load(Ordering::Relaxed));
+        let max = counts.clone().max().unwrap_or(0);
+        let min = counts.min().unwrap_or(0);
+        max - min
+    }
+    
+    /// Publish per-worker load and the current skew as gauges
+    pub fn publish(&self, registry: &MetricsRegistry) {
+        for (worker, active) in self.active.iter().enumerate() {
+            registry
+                .gauge(&format!("workers.{}.active_connections", worker))
+                .set(active.load(Ordering::Relaxed));
+            registry
+                .gauge(&format!("workers.{}.skipped_accepts", worker))
+                .set(self.skipped_accepts[worker].load(Ordering::Relaxed));
+        }
+        
+        registry.gauge("workers.load_skew").set(self.skew());
+    }
 }
\ No newline at end of file
@@ -0,0 +1,188 @@
+use crate::error::ServerResult;
+use crate::http::{Request, Response};
+use crate::middleware::MiddlewareNext;
+use crate::redaction::Redactor;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Rules deciding which requests make it into the access log
+#[derive(Debug, Clone)]
+pub struct AccessLogRules {
+    /// Fraction of successful (2xx) requests to log, from 0.0 to 1.0
+    pub success_sample_rate: f64,
+    
+    /// Always log requests slower than this
+    pub slow_threshold: Option<Duration>,
+    
+    /// Path prefixes (e.g. health checks) whose non-error requests are never logged
+    pub exclude_paths: Vec<String>,
+    
+    /// Request headers added to each line, when the request has them
+    pub headers: Vec<String>,
+}
+
+impl Default for AccessLogRules {
+    fn default() -> Self {
+        Self {
+            success_sample_rate: 1.0,
+            slow_threshold: None,
+            exclude_paths: Vec::new(),
+            headers: Vec::new(),
+        }
+    }
+}
+
+impl AccessLogRules {
+    /// Create rules that log every request
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Log only this fraction of successful requests
+    pub fn with_success_sample_rate(mut self, rate: f64) -> Self {
+        self.success_sample_rate = rate.clamp(0.0, 1.0);
+        self
+    }
+    
+    /// Always log requests slower than `threshold`
+    pub fn with_slow_threshold(mut self, threshold: Duration) -> Self {
+        self.slow_threshold = Some(threshold);
+        self
+    }
+    
+    /// Skip requests under `prefix` unless they fail
+    pub fn exclude_path(mut self, prefix: &str) -> Self {
+        self.exclude_paths.push(prefix.to_string());
+        self
+    }
+    
+    /// Add the request header `name` to each line
+    pub fn with_header(mut self, name: &str) -> Self {
+        self.headers.push(name.to_string());
+        self
+    }
+}
+
+/// An access log that writes one line per request it decides to keep
+///
+/// Sensitive query parameters and headers are masked by the log's
+/// `Redactor` before the line is written.
+pub struct AccessLog {
+    rules: AccessLogRules,
+    sink: Mutex<Box<dyn Write + Send>>,
+    successes_seen: AtomicUsize,
+    redactor: Redactor,
+}
+
+impl AccessLog {
+    /// Create an access log writing to standard output
+    pub fn stdout(rules: AccessLogRules) -> Self {
+        Self::with_writer(rules, io::stdout())
+    }
+    
+    /// Create an access log writing to any sink
+    pub fn with_writer<W: Write + Send + 'static>(rules: AccessLogRules, writer: W) -> Self {
+        Self {
+            rules,
+            sink: Mutex::new(Box::new(writer)),
+            successes_seen: AtomicUsize::new(0),
+            redactor: Redactor::default(),
+        }
+    }
+    
+    /// Mask what `redactor` names instead of the default secrets
+    pub fn with_redactor(mut self, redactor: Redactor) -> Self {
+        self.redactor = redactor;
+        self
+    }
+    
+    /// Get the rules in use
+    pub fn rules(&self) -> &AccessLogRules {
+        &self.rules
+    }
+    
+    /// Decide whether a finished request should be logged
+    ///
+    /// Errors (4xx/5xx) and slow requests are always kept. Other requests on
+    /// excluded paths are dropped, and 2xx responses are sampled evenly at
+    /// the configured rate.
+    pub fn should_log(&self, path: &str, status: u16, elapsed: Duration) -> bool {
+        if status >= 400 {
+            return true;
+        }
+        
+        if let Some(threshold) = self.rules.slow_threshold {
+            if elapsed > threshold {
+                return true;
+            }
+        }
+        
+        if self.rules.exclude_paths.iter().any(|prefix| path.starts_with(prefix.as_str())) {
+            return false;
+        }
+        
+        if (200..300).contains(&status) {
+            return self.sample_success();
+        }
+        
+        true
+    }
+    
+    /// Keep every 1/rate-th success, spreading them evenly rather than randomly
+    fn sample_success(&self) -> bool {
+        let rate = self.rules.success_sample_rate;
+        if rate >= 1.0 {
+            return true;
+        }
+        
+        let seen = self.successes_seen.fetch_add(1, Ordering::Relaxed) as f64;
+        ((seen + 1.0) * rate).floor() > (seen * rate).floor()
+    }
+    
+    /// Write a log line for a request if the rules keep it
+    pub fn record(&self, request: &Request, status: u16, elapsed: Duration) {
+        if !self.should_log(request.path(), status, elapsed) {
+            return;
+        }
+        
+        let mut line = format!(
+            "[Access] {} {} {} {:.3}ms",
+            request.method.as_str(),
+            self.redactor.uri(&request.uri),
+            status,
+            elapsed.as_secs_f64() * 1000.0
+        );
+        for name in &self.rules.headers {
+            if let Some(value) = request.get_header(name) {
+                line.push_str(&format!(" {}={:?}", name.to_lowercase(), self.redactor.header(name, value)));
+            }
+        }
+        line.push('\n');
+        
+        let mut sink = self.sink.lock().unwrap();
+        let _ = sink.write_all(line.as_bytes());
+        let _ = sink.flush();
+    }
+}
+
+/// Access log middleware - logs requests according to the access log's rules
+///
+/// Handler errors are logged as 500.
+pub fn access_log_middleware(
+    access_log: Arc<AccessLog>,
+) -> impl Fn(&Request, MiddlewareNext) -> ServerResult<Response> + Send + Sync {
+    move |request, next| {
+        let start_time = Instant::now();
+        let response = next(request);
+        
+        let status = match &response {
+            Ok(resp) => resp.status as u16,
+            Err(_) => 500,
+        };
+        access_log.record(request, status, start_time.elapsed());
+        
+        response
+    }
+}
\ No newline at end of file
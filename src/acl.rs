@@ -0,0 +1,350 @@
+//! Access control lists declared in the config
+//!
+//! Operators lock paths down by listing rules in the `acl` section: which
+//! paths and methods a rule covers, and which client addresses, auth method
+//! and roles it requires. The rules are compiled once at startup and checked
+//! on every request before it reaches its handler, after any middleware that
+//! identifies the client.
+
+use crate::credentials::{authenticated, parse_basic_auth, AuthIdentity, CredentialStore, HtpasswdFile};
+use crate::error::{ErrorResponse, ServerError, ServerResult};
+use crate::http::{Request, Response, Status};
+use crate::middleware::MiddlewareNext;
+use crate::tenants::TenantId;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::net::IpAddr;
+use std::sync::Arc;
+
+/// How a request has to identify itself
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthMethod {
+    /// A username and password, checked by the basic auth middleware or
+    /// against the ACL's htpasswd file
+    Basic,
+    
+    /// An API key, matched to a tenant by the tenant middleware
+    ApiKey,
+    
+    /// Either of the above
+    Any,
+}
+
+/// One access rule
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AclRule {
+    /// Path the rule covers, by segment: `*` matches any one segment and a
+    /// trailing `**` any number, so `/admin/**` covers `/admin` and below.
+    /// Case and trailing slashes are ignored, as a router may ignore them.
+    pub path: String,
+    
+    /// Methods the rule covers (empty = all)
+    pub methods: Vec<String>,
+    
+    /// Client addresses allowed, as addresses or CIDR ranges such as
+    /// `10.0.0.0/8` (empty = any)
+    pub ip_ranges: Vec<String>,
+    
+    /// How the request has to be authenticated (None = not at all, unless
+    /// roles are required)
+    pub auth: Option<AuthMethod>,
+    
+    /// Roles of which the client needs at least one (empty = none)
+    pub roles: Vec<String>,
+}
+
+/// Settings for access control
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AclConfig {
+    /// Rules checked in order; the first one covering a request applies,
+    /// and requests no rule covers are allowed
+    pub rules: Vec<AclRule>,
+    
+    /// Usernames and tenant IDs holding each role
+    pub roles: BTreeMap<String, Vec<String>>,
+    
+    /// htpasswd file that basic credentials are checked against when no
+    /// earlier middleware has authenticated the request
+    pub htpasswd: Option<String>,
+    
+    /// Realm named in the `WWW-Authenticate` challenge
+    pub realm: String,
+}
+
+impl Default for AclConfig {
+    fn default() -> Self {
+        Self {
+            rules: Vec::new(),
+            roles: BTreeMap::new(),
+            htpasswd: None,
+            realm: "Server".to_string(),
+        }
+    }
+}
+
+/// A range of addresses given by a network address and prefix length
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpRange {
+    network: IpAddr,
+    prefix: u32,
+}
+
+impl IpRange {
+    /// Parse `10.0.0.0/8`, `2001:db8::/32` or a single address
+    pub fn parse(range: &str) -> ServerResult<Self> {
+        let invalid = || ServerError::Config(format!("Invalid IP range {:?}", range));
+        let (address, prefix) = match range.trim().split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix.parse::<u32>().map_err(|_| invalid())?)),
+            None => (range.trim(), None),
+        };
+        let network: IpAddr = address.parse().map_err(|_| invalid())?;
+        let bits = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = prefix.unwrap_or(bits);
+        if prefix > bits {
+            return Err(invalid());
+        }
+        Ok(Self { network, prefix })
+    }
+    
+    /// Check whether `address` is in the range; IPv4-mapped IPv6 addresses count as IPv4
+    pub fn contains(&self, address: IpAddr) -> bool {
+        let shift = |bits: u32| if self.prefix == 0 { 0 } else { !0u128 << (bits - self.prefix) };
+        match (self.network, address.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(address)) => {
+                let mask = shift(32) as u32;
+                u32::from(network) & mask == u32::from(address) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(address)) => {
+                let mask = shift(128);
+                u128::from(network) & mask == u128::from(address) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// A path pattern split into segments
+#[derive(Debug, Clone)]
+struct PathPattern {
+    segments: Vec<String>,
+    /// Whether the pattern ended in `**`
+    rest: bool,
+}
+
+impl PathPattern {
+    fn parse(pattern: &str) -> ServerResult<Self> {
+        if !pattern.starts_with('/') {
+            return Err(ServerError::Config(format!("ACL path {:?} must start with /", pattern)));
+        }
+        let mut segments: Vec<String> = pattern.split('/').filter(|s| !s.is_empty()).map(str::to_string).collect();
+        let rest = segments.last().is_some_and(|last| last == "**");
+        if rest {
+            segments.pop();
+        }
+        if segments.iter().any(|segment| segment == "**") {
+            return Err(ServerError::Config(format!("ACL path {:?} may only end in **", pattern)));
+        }
+        Ok(Self { segments, rest })
+    }
+    
+    /// Check whether the pattern covers `path`
+    ///
+    /// Segments compare ignoring ASCII case, and empty segments (from doubled
+    /// or trailing slashes) are skipped, so a rule covers every spelling of a
+    /// path that a router could match under any `MatchPolicy`.
+    fn matches(&self, path: &str) -> bool {
+        let mut parts = path.split('/').filter(|s| !s.is_empty());
+        for segment in &self.segments {
+            match parts.next() {
+                Some(part) if segment == "*" || segment.eq_ignore_ascii_case(part) => {}
+                _ => return false,
+            }
+        }
+        self.rest || parts.next().is_none()
+    }
+}
+
+/// A rule ready to be checked
+#[derive(Debug)]
+struct CompiledRule {
+    path: PathPattern,
+    methods: Vec<String>,
+    ip_ranges: Vec<IpRange>,
+    auth: Option<AuthMethod>,
+    /// Principals holding any of the rule's roles (None = no roles required)
+    principals: Option<HashSet<String>>,
+}
+
+/// Compiled access rules
+pub struct Acl {
+    rules: Vec<CompiledRule>,
+    credentials: Option<Arc<dyn CredentialStore>>,
+    realm: String,
+}
+
+impl Acl {
+    /// Compile the rules in `config`, loading its htpasswd file if one is given
+    ///
+    /// Fails on malformed paths and ranges, unknown roles and an unreadable
+    /// htpasswd file, so mistakes show up at startup.
+    pub fn new(config: &AclConfig) -> ServerResult<Self> {
+        let mut rules = Vec::with_capacity(config.rules.len());
+        for rule in &config.rules {
+            let methods = rule.methods.iter().map(|method| method.to_ascii_uppercase()).collect();
+            let ip_ranges = rule.ip_ranges.iter().map(|range| IpRange::parse(range)).collect::<ServerResult<_>>()?;
+            let principals = if rule.roles.is_empty() {
+                None
+            } else {
+                let mut principals = HashSet::new();
+                for role in &rule.roles {
+                    let members = config.roles.get(role).ok_or_else(|| {
+                        ServerError::Config(format!("ACL rule for {} names unknown role {:?}", rule.path, role))
+                    })?;
+                    principals.extend(members.iter().cloned());
+                }
+                Some(principals)
+            };
+            rules.push(CompiledRule {
+                path: PathPattern::parse(&rule.path)?,
+                methods,
+                ip_ranges,
+                // Roles belong to an identity, so they imply authentication
+                auth: rule.auth.or(principals.as_ref().map(|_| AuthMethod::Any)),
+                principals,
+            });
+        }
+        
+        let credentials = match &config.htpasswd {
+            Some(path) => Some(Arc::new(HtpasswdFile::load(path)?) as Arc<dyn CredentialStore>),
+            None => None,
+        };
+        Ok(Self {
+            rules,
+            credentials,
+            realm: config.realm.clone(),
+        })
+    }
+    
+    /// Check basic credentials against `store` instead of the htpasswd file
+    pub fn with_credentials(mut self, store: Arc<dyn CredentialStore>) -> Self {
+        self.credentials = Some(store);
+        self
+    }
+    
+    /// Check whether no rules are configured
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+    
+    /// Check a request against the first rule covering it
+    ///
+    /// Returns the request to pass on, with an `AuthIdentity` attached if the
+    /// ACL checked its credentials itself, or the response refusing it.
+    pub fn check(&self, request: &Request) -> Result<Option<Request>, Box<Response>> {
+        let path = request.path();
+        let method = request.method.as_str();
+        let rule = match self.rules.iter().find(|rule| {
+            (rule.methods.is_empty() || rule.methods.iter().any(|m| m == method)) && rule.path.matches(path)
+        }) {
+            Some(rule) => rule,
+            None => return Ok(None),
+        };
+        
+        if !rule.ip_ranges.is_empty() {
+            let allowed = request
+                .peer_addr
+                .is_some_and(|peer| rule.ip_ranges.iter().any(|range| range.contains(peer.ip())));
+            if !allowed {
+                return Err(Box::new(forbidden("Access from this address is not allowed")));
+            }
+        }
+        
+        let method = match rule.auth {
+            Some(method) => method,
+            None => return Ok(None),
+        };
+        let mut authenticated_request = None;
+        let mut principal = match method {
+            AuthMethod::Basic => None,
+            _ => TenantId::of(request).map(str::to_string),
+        };
+        if principal.is_none() && method != AuthMethod::ApiKey {
+            principal = AuthIdentity::of(request).map(|identity| identity.username.clone());
+            if principal.is_none() {
+                if let Some(username) = self.verify_basic(request)? {
+                    authenticated_request = Some(authenticated(request, &username));
+                    principal = Some(username);
+                }
+            }
+        }
+        let principal = match principal {
+            Some(principal) => principal,
+            None if method == AuthMethod::ApiKey => {
+                let body = ErrorResponse::new("unauthorized", "An API key is required");
+                return Err(Box::new(body.into_response(Status::Unauthorized)));
+            }
+            None => return Err(Box::new(self.challenge())),
+        };
+        
+        if let Some(principals) = &rule.principals {
+            if !principals.contains(&principal) {
+                return Err(Box::new(forbidden("A role this path requires is missing")));
+            }
+        }
+        Ok(authenticated_request)
+    }
+    
+    /// Check the request's basic credentials against the credential store, if there is one
+    fn verify_basic(&self, request: &Request) -> Result<Option<String>, Box<Response>> {
+        let (store, (username, password)) = match (&self.credentials, parse_basic_auth(request)) {
+            (Some(store), Some(credentials)) => (store, credentials),
+            _ => return Ok(None),
+        };
+        match store.verify(&username, &password) {
+            Ok(true) => Ok(Some(username)),
+            Ok(false) => Err(Box::new(self.challenge())),
+            Err(e) => {
+                log::error!("Credential store error: {}", e);
+                let body = ErrorResponse::new("unavailable", "Authentication unavailable");
+                Err(Box::new(body.into_response(Status::ServiceUnavailable)))
+            }
+        }
+    }
+    
+    fn challenge(&self) -> Response {
+        let body = ErrorResponse::new("unauthorized", "Credentials are required");
+        let mut response = body.into_response(Status::Unauthorized);
+        response.set_header("WWW-Authenticate", &format!("Basic realm=\"{}\"", self.realm.replace('"', "")));
+        response
+    }
+}
+
+impl std::fmt::Debug for Acl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Acl")
+            .field("rules", &self.rules)
+            .field("credentials", &self.credentials.is_some())
+            .field("realm", &self.realm)
+            .finish()
+    }
+}
+
+fn forbidden(message: &str) -> Response {
+    ErrorResponse::new("forbidden", message).into_response(Status::Forbidden)
+}
+
+/// ACL middleware - enforces the access rules for requests passing through
+///
+/// For chains built in code; the server itself checks the rules from the
+/// config's `acl` section after its own chain's middleware. Put it after any
+/// auth or tenant middleware whose identities the rules rely on.
+pub fn acl_middleware(acl: Arc<Acl>) -> impl Fn(&Request, MiddlewareNext) -> ServerResult<Response> + Send + Sync {
+    move |request, next| match acl.check(request) {
+        Ok(Some(authenticated)) => next(&authenticated),
+        Ok(None) => next(request),
+        Err(response) => Ok(*response),
+    }
+}
\ No newline at end of file
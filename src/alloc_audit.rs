@@ -0,0 +1,66 @@
+//! Heap allocation counting, for the `alloc-audit` feature
+//!
+//! The feature installs a global allocator that counts the allocations
+//! made on each thread. The event loop reads the count around every
+//! request and records the difference in the `request_allocations`
+//! windowed histogram, so its p50 and p99 appear with the other metrics.
+//! Counting costs a thread-local increment per allocation; the feature is
+//! meant for builds that profile the parser and router, not production.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+/// The system allocator, counting allocations per thread
+pub struct CountingAllocator;
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+thread_local! {
+    /// Allocations made on this thread so far
+    static ALLOCATIONS: Cell<u64> = const { Cell::new(0) };
+    /// The count when the current request started
+    static REQUEST_START: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Count an allocation, unless the thread is already tearing down its locals
+fn count() {
+    let _ = ALLOCATIONS.try_with(|allocations| allocations.set(allocations.get() + 1));
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count();
+        System.alloc(layout)
+    }
+    
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        count();
+        System.alloc_zeroed(layout)
+    }
+    
+    // A reallocation may move the data, so it counts like an allocation
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count();
+        System.realloc(ptr, layout, new_size)
+    }
+    
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+/// Get the number of allocations made on this thread so far
+pub fn thread_allocations() -> u64 {
+    ALLOCATIONS.try_with(Cell::get).unwrap_or(0)
+}
+
+/// Start counting allocations for a request handled on this thread
+pub fn start_request() {
+    REQUEST_START.set(thread_allocations());
+}
+
+/// Get the allocations made on this thread since `start_request`
+pub fn request_allocations() -> u64 {
+    thread_allocations() - REQUEST_START.get()
+}
\ No newline at end of file
@@ -0,0 +1,293 @@
+use crate::credentials::AuthIdentity;
+use crate::error::{ServerError, ServerResult};
+use crate::hash::{self, Sha256};
+use crate::http::{Request, Response};
+use crate::middleware::MiddlewareNext;
+use crate::redaction::Redactor;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The `prev` hash of the first entry in a chain
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// One audited action
+///
+/// Each entry carries the hash of the entry before it and a hash over its own
+/// fields (including that link), so editing, removing or reordering entries
+/// breaks the chain from that point on.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub seq: u64,
+    /// Unix seconds
+    pub timestamp: u64,
+    /// The authenticated user, if any
+    pub identity: Option<String>,
+    pub method: String,
+    pub path: String,
+    pub status: u16,
+    pub request_id: Option<String>,
+    /// Request headers the log keeps, redacted
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    /// The request body, redacted, when the log keeps bodies
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+    /// Hash of the previous entry
+    pub prev: String,
+    /// Hash of this entry
+    pub hash: String,
+}
+
+/// The hashed fields of an entry, in a fixed order
+#[derive(Serialize)]
+struct UnsealedEntry<'a> {
+    seq: u64,
+    timestamp: u64,
+    identity: &'a Option<String>,
+    method: &'a str,
+    path: &'a str,
+    status: u16,
+    request_id: &'a Option<String>,
+    // Left out when empty, so entries from before they existed still verify
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    headers: &'a BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    body: &'a Option<String>,
+    prev: &'a str,
+}
+
+impl AuditEntry {
+    /// Compute the hash this entry should carry
+    pub fn compute_hash(&self) -> String {
+        let unsealed = UnsealedEntry {
+            seq: self.seq,
+            timestamp: self.timestamp,
+            identity: &self.identity,
+            method: &self.method,
+            path: &self.path,
+            status: self.status,
+            request_id: &self.request_id,
+            headers: &self.headers,
+            body: &self.body,
+            prev: &self.prev,
+        };
+        let bytes = serde_json::to_vec(&unsealed).unwrap_or_default();
+        hash::to_hex(&Sha256::digest(&bytes))
+    }
+}
+
+/// Check an audit log's hash chain, returning the number of entries
+///
+/// The error names the first line that doesn't parse or doesn't chain.
+pub fn verify_audit_log<R: BufRead>(reader: R) -> Result<u64, String> {
+    let mut prev = GENESIS_HASH.to_string();
+    let mut count = 0;
+    
+    for (number, line) in reader.lines().enumerate() {
+        let line = line.map_err(|e| format!("Failed to read audit log: {}", e))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        
+        let entry: AuditEntry =
+            serde_json::from_str(&line).map_err(|_| format!("Line {} is not an audit entry", number + 1))?;
+        if entry.seq != count + 1 || entry.prev != prev || entry.hash != entry.compute_hash() {
+            return Err(format!("Audit chain broken at line {}", number + 1));
+        }
+        
+        prev = entry.hash;
+        count += 1;
+    }
+    
+    Ok(count)
+}
+
+struct ChainState {
+    sink: Box<dyn Write + Send>,
+    seq: u64,
+    last_hash: String,
+}
+
+/// An append-only, hash-chained log of who did what
+///
+/// Kept headers and bodies are masked by the log's `Redactor` before they
+/// are hashed and written, so secrets never enter the chain.
+pub struct AuditLog {
+    state: Mutex<ChainState>,
+    request_id_header: String,
+    authenticated_only: bool,
+    headers: Vec<String>,
+    keep_bodies: bool,
+    redactor: Redactor,
+}
+
+impl AuditLog {
+    /// Create an audit log starting a new chain on any sink
+    pub fn with_writer<W: Write + Send + 'static>(writer: W) -> Self {
+        Self {
+            state: Mutex::new(ChainState {
+                sink: Box::new(writer),
+                seq: 0,
+                last_hash: GENESIS_HASH.to_string(),
+            }),
+            request_id_header: "X-Request-Id".to_string(),
+            authenticated_only: false,
+            headers: Vec::new(),
+            keep_bodies: false,
+            redactor: Redactor::default(),
+        }
+    }
+    
+    /// Open an audit log file for appending, continuing its existing chain
+    ///
+    /// Fails if the existing file has been tampered with, rather than
+    /// extending a chain that no longer verifies.
+    pub fn open<P: AsRef<Path>>(path: P) -> ServerResult<Self> {
+        let path = path.as_ref();
+        let (seq, last_hash) = match fs::File::open(path) {
+            Ok(file) => {
+                verify_audit_log(BufReader::new(file)).map_err(ServerError::Config)?;
+                last_entry(path)?
+                    .map(|entry| (entry.seq, entry.hash))
+                    .unwrap_or((0, GENESIS_HASH.to_string()))
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (0, GENESIS_HASH.to_string()),
+            Err(e) => return Err(e.into()),
+        };
+        
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let log = Self::with_writer(file);
+        {
+            let mut state = log.state.lock().unwrap();
+            state.seq = seq;
+            state.last_hash = last_hash;
+        }
+        Ok(log)
+    }
+    
+    /// Read the request ID from a different header
+    pub fn with_request_id_header(mut self, header: &str) -> Self {
+        self.request_id_header = header.to_string();
+        self
+    }
+    
+    /// Only record requests that carry an `AuthIdentity`
+    pub fn with_authenticated_only(mut self, authenticated_only: bool) -> Self {
+        self.authenticated_only = authenticated_only;
+        self
+    }
+    
+    /// Keep the request header `name` in each entry
+    pub fn with_header(mut self, name: &str) -> Self {
+        self.headers.push(name.to_lowercase());
+        self
+    }
+    
+    /// Keep request bodies in entries
+    pub fn with_request_bodies(mut self, keep_bodies: bool) -> Self {
+        self.keep_bodies = keep_bodies;
+        self
+    }
+    
+    /// Mask what `redactor` names instead of the default secrets
+    pub fn with_redactor(mut self, redactor: Redactor) -> Self {
+        self.redactor = redactor;
+        self
+    }
+    
+    /// Append an entry for a finished request, returning it if one was written
+    ///
+    /// The path is recorded without its query string, which may hold secrets.
+    pub fn record(&self, request: &Request, status: u16) -> Option<AuditEntry> {
+        let identity = AuthIdentity::of(request).map(|identity| identity.username.clone());
+        if self.authenticated_only && identity.is_none() {
+            return None;
+        }
+        
+        let headers = self
+            .headers
+            .iter()
+            .filter_map(|name| {
+                let value = request.get_header(name)?;
+                Some((name.clone(), self.redactor.header(name, value).to_string()))
+            })
+            .collect();
+        let body = (self.keep_bodies && !request.body.is_empty()).then(|| {
+            let content_type = request.get_header("content-type").map(String::as_str);
+            String::from_utf8_lossy(&self.redactor.body(content_type, &request.body)).into_owned()
+        });
+        
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        
+        let mut state = self.state.lock().unwrap();
+        let mut entry = AuditEntry {
+            seq: state.seq + 1,
+            timestamp,
+            identity,
+            method: request.method.as_str().to_string(),
+            path: request.path().to_string(),
+            status,
+            request_id: request.get_header(&self.request_id_header).cloned(),
+            headers,
+            body,
+            prev: state.last_hash.clone(),
+            hash: String::new(),
+        };
+        entry.hash = entry.compute_hash();
+        
+        let mut line = match serde_json::to_vec(&entry) {
+            Ok(line) => line,
+            Err(e) => {
+                log::error!("Failed to encode audit entry: {}", e);
+                return None;
+            }
+        };
+        line.push(b'\n');
+        
+        // Only advance the chain once the entry is safely written
+        if let Err(e) = state.sink.write_all(&line).and_then(|_| state.sink.flush()) {
+            log::error!("Failed to write audit entry: {}", e);
+            return None;
+        }
+        state.seq = entry.seq;
+        state.last_hash = entry.hash.clone();
+        Some(entry)
+    }
+}
+
+/// Read the last entry of an audit log file
+fn last_entry(path: &Path) -> ServerResult<Option<AuditEntry>> {
+    let content = fs::read_to_string(path)?;
+    match content.lines().rev().find(|line| !line.trim().is_empty()) {
+        Some(line) => Ok(Some(serde_json::from_str(line)?)),
+        None => Ok(None),
+    }
+}
+
+/// Audit middleware - records each request's identity, method, path, status and request ID
+///
+/// Add it after the auth middleware so it sees the `AuthIdentity` that
+/// middleware attaches. Handler errors are recorded as 500.
+pub fn audit_middleware(
+    audit_log: Arc<AuditLog>,
+) -> impl Fn(&Request, MiddlewareNext) -> ServerResult<Response> + Send + Sync {
+    move |request, next| {
+        let response = next(request);
+        
+        let status = match &response {
+            Ok(resp) => resp.status as u16,
+            Err(_) => 500,
+        };
+        audit_log.record(request, status);
+        
+        response
+    }
+}
\ No newline at end of file
@@ -0,0 +1,242 @@
+use crate::events::{EventBus, ServerEvent};
+use crate::hash::{to_hex, Sha256};
+use crate::http::Request;
+use crate::http_client::HttpUrl;
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How long a sticky upstream cookie lasts, in seconds (1 day)
+const STICKY_MAX_AGE: u64 = 24 * 60 * 60;
+
+/// How a client is kept on the same upstream
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Stickiness {
+    /// Spread requests round robin
+    None,
+    /// Pin each client with a cookie of this name naming its upstream
+    Cookie(String),
+    /// Map each client IP onto an upstream by hashing
+    IpHash,
+}
+
+/// When an upstream is taken out of rotation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HealthPolicy {
+    /// Consecutive failed requests that mark an upstream down
+    pub max_failures: u32,
+    
+    /// How long a down upstream gets no traffic before it is tried again
+    pub cooldown: Duration,
+}
+
+impl Default for HealthPolicy {
+    fn default() -> Self {
+        Self {
+            max_failures: 3,
+            cooldown: Duration::from_secs(10),
+        }
+    }
+}
+
+/// An upstream and what we have seen of its health
+#[derive(Debug)]
+struct Upstream {
+    url: HttpUrl,
+    /// Stable name for the upstream, used in sticky cookies
+    id: String,
+    failures: AtomicU32,
+    down_until: Mutex<Option<Instant>>,
+}
+
+/// The upstream chosen for a request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pick {
+    /// Position of the upstream in the balancer
+    pub index: usize,
+    pub url: HttpUrl,
+    /// `Set-Cookie` value pinning the client to this upstream, when it has
+    /// just been assigned one
+    pub pin: Option<String>,
+}
+
+/// Spreads requests over several upstreams, keeping clients on one if asked
+///
+/// Upstreams are watched passively: after `max_failures` requests in a row
+/// fail to get an answer, an upstream gets no traffic for the cooldown,
+/// then is tried again. Sticky clients of a down upstream are moved to a
+/// healthy one and, with cookie stickiness, pinned there afresh. IP hashing
+/// uses rendezvous hashing, so only the clients of an upstream that goes
+/// down move. When every upstream is down, they are all tried anyway.
+///
+/// With cookie stickiness, clients without a valid cookie are placed by
+/// their IP as with `IpHash`, so they land on the same upstream even when
+/// the pinning cookie can't be set.
+#[derive(Debug)]
+pub struct Balancer {
+    upstreams: Vec<Upstream>,
+    stickiness: Stickiness,
+    health: HealthPolicy,
+    next: AtomicUsize,
+    /// Told when an upstream is taken out of rotation
+    events: Option<Arc<EventBus>>,
+}
+
+impl Balancer {
+    /// Create a round robin balancer over `upstreams`
+    pub fn new(upstreams: Vec<HttpUrl>) -> Self {
+        let upstreams = upstreams
+            .into_iter()
+            .map(|url| Upstream {
+                id: to_hex(&Sha256::digest(url.address.as_bytes())[..6]),
+                url,
+                failures: AtomicU32::new(0),
+                down_until: Mutex::new(None),
+            })
+            .collect();
+        
+        Self {
+            upstreams,
+            stickiness: Stickiness::None,
+            health: HealthPolicy::default(),
+            next: AtomicUsize::new(0),
+            events: None,
+        }
+    }
+    
+    /// Keep clients on the same upstream
+    pub fn sticky(mut self, stickiness: Stickiness) -> Self {
+        self.stickiness = stickiness;
+        self
+    }
+    
+    /// Set when upstreams are taken out of rotation
+    pub fn with_health(mut self, health: HealthPolicy) -> Self {
+        self.health = health;
+        self
+    }
+    
+    /// Emit `UpstreamUnhealthy` on `events` whenever an upstream is taken out of rotation
+    pub fn with_events(mut self, events: Arc<EventBus>) -> Self {
+        self.events = Some(events);
+        self
+    }
+    
+    /// Get the number of upstreams
+    pub fn len(&self) -> usize {
+        self.upstreams.len()
+    }
+    
+    /// Check whether there are no upstreams
+    pub fn is_empty(&self) -> bool {
+        self.upstreams.is_empty()
+    }
+    
+    /// Get the upstream at `index`
+    pub fn upstream(&self, index: usize) -> Option<&HttpUrl> {
+        self.upstreams.get(index).map(|upstream| &upstream.url)
+    }
+    
+    /// Check whether the upstream at `index` is in rotation
+    pub fn is_healthy(&self, index: usize) -> bool {
+        self.upstreams.get(index).is_some_and(|upstream| {
+            let down_until = upstream.down_until.lock().unwrap();
+            !down_until.is_some_and(|until| Instant::now() < until)
+        })
+    }
+    
+    /// Choose the upstream for `request`, skipping the indexes in `tried`
+    ///
+    /// Returns None once every upstream has been tried.
+    pub fn pick(&self, request: &Request, tried: &[usize]) -> Option<Pick> {
+        let untried: Vec<usize> = (0..self.upstreams.len()).filter(|index| !tried.contains(index)).collect();
+        let healthy: Vec<usize> = untried.iter().copied().filter(|&index| self.is_healthy(index)).collect();
+        let candidates = if healthy.is_empty() { untried } else { healthy };
+        if candidates.is_empty() {
+            return None;
+        }
+        
+        let client_ip = request.peer_addr.map(|addr| addr.ip().to_string());
+        let (index, pin) = match &self.stickiness {
+            Stickiness::None => (self.round_robin(&candidates), None),
+            Stickiness::IpHash => match &client_ip {
+                Some(ip) => (self.rendezvous(ip, &candidates), None),
+                None => (self.round_robin(&candidates), None),
+            },
+            Stickiness::Cookie(name) => {
+                let pinned = request
+                    .cookie(name)
+                    .and_then(|id| candidates.iter().copied().find(|&index| self.upstreams[index].id == id));
+                match pinned {
+                    Some(index) => (index, None),
+                    None => {
+                        let index = match &client_ip {
+                            Some(ip) => self.rendezvous(ip, &candidates),
+                            None => self.round_robin(&candidates),
+                        };
+                        let cookie = format!(
+                            "{}={}; Path=/; Max-Age={}; HttpOnly",
+                            name, self.upstreams[index].id, STICKY_MAX_AGE
+                        );
+                        (index, Some(cookie))
+                    }
+                }
+            }
+        };
+        
+        Some(Pick {
+            index,
+            url: self.upstreams[index].url.clone(),
+            pin,
+        })
+    }
+    
+    /// Record that the upstream at `index` answered
+    pub fn report_success(&self, index: usize) {
+        if let Some(upstream) = self.upstreams.get(index) {
+            upstream.failures.store(0, Ordering::Relaxed);
+            *upstream.down_until.lock().unwrap() = None;
+        }
+    }
+    
+    /// Record that a request to the upstream at `index` got no answer
+    pub fn report_failure(&self, index: usize) {
+        let upstream = match self.upstreams.get(index) {
+            Some(upstream) => upstream,
+            None => return,
+        };
+        let failures = upstream.failures.fetch_add(1, Ordering::Relaxed) + 1;
+        if failures >= self.health.max_failures {
+            let mut down_until = upstream.down_until.lock().unwrap();
+            if !down_until.is_some_and(|until| Instant::now() < until) {
+                log::warn!("Upstream {} is down after {} failures", upstream.url.address, failures);
+                *down_until = Some(Instant::now() + self.health.cooldown);
+                drop(down_until);
+                if let Some(events) = &self.events {
+                    events.emit(ServerEvent::UpstreamUnhealthy {
+                        upstream: upstream.url.address.clone(),
+                        reason: format!("{} requests in a row got no answer", failures),
+                    });
+                }
+            }
+        }
+    }
+    
+    fn round_robin(&self, candidates: &[usize]) -> usize {
+        candidates[self.next.fetch_add(1, Ordering::Relaxed) % candidates.len()]
+    }
+    
+    /// Pick the candidate scoring highest for `key`, which only changes for
+    /// keys whose upstream leaves the candidates
+    fn rendezvous(&self, key: &str, candidates: &[usize]) -> usize {
+        let score = |index: usize| {
+            let mut hasher = Sha256::new();
+            hasher.update(key.as_bytes());
+            hasher.update(b":");
+            hasher.update(self.upstreams[index].id.as_bytes());
+            let digest = hasher.finalize();
+            u64::from_be_bytes(digest[..8].try_into().unwrap())
+        };
+        candidates.iter().copied().max_by_key(|&index| score(index)).unwrap()
+    }
+}
\ No newline at end of file
@@ -1,31 +1,126 @@
 use crate::error::{ServerError, ServerResult};
+use crate::leaks::{Instance, Tracked};
 use std::io::{self, Read, Write};
 use std::ptr;
 
+/// The memory behind a buffer
+enum Storage {
+    /// A plain vector; unread data is moved back to the start to make room
+    Linear(Vec<u8>),
+    /// A circular region mapped twice in a row, so data that wraps around
+    /// its end can still be handed out as one contiguous slice
+    #[cfg(target_os = "linux")]
+    Ring(MirroredRegion),
+}
+
+impl Storage {
+    /// Bytes the storage can hold
+    fn size(&self) -> usize {
+        match self {
+            Storage::Linear(data) => data.len(),
+            #[cfg(target_os = "linux")]
+            Storage::Ring(region) => region.size,
+        }
+    }
+    
+    /// The bytes from `start` up to `end`; for a ring, a window of at most
+    /// its size that may run on into the mirror
+    fn window(&self, start: usize, end: usize) -> &[u8] {
+        match self {
+            Storage::Linear(data) => &data[start..end],
+            #[cfg(target_os = "linux")]
+            Storage::Ring(region) => region.window(start, end),
+        }
+    }
+    
+    fn window_mut(&mut self, start: usize, end: usize) -> &mut [u8] {
+        match self {
+            Storage::Linear(data) => &mut data[start..end],
+            #[cfg(target_os = "linux")]
+            Storage::Ring(region) => region.window_mut(start, end),
+        }
+    }
+}
+
 /// A resizable buffer with efficient memory management
 pub struct Buffer {
-    data: Vec<u8>,
+    storage: Storage,
     read_pos: usize,
     write_pos: usize,
+    max_capacity: usize,
+    /// Read position to return to on `rollback`
+    mark: Option<usize>,
+    _instance: Instance,
 }
 
 impl Buffer {
     /// Create a new buffer with the specified capacity
     pub fn new(capacity: usize) -> Self {
+        Self::with_storage(Storage::Linear(vec![0; capacity]))
+    }
+    
+    /// Create a circular buffer with at least the specified capacity
+    ///
+    /// A ring buffer never moves unread data to make room, which saves a copy
+    /// per request when pipelined requests keep a partial one buffered. The
+    /// capacity is rounded up to whole pages. Where the mirrored mapping
+    /// isn't available this falls back to a regular buffer.
+    pub fn ring(capacity: usize) -> Self {
+        #[cfg(target_os = "linux")]
+        if let Ok(region) = MirroredRegion::new(capacity) {
+            return Self::with_storage(Storage::Ring(region));
+        }
+        
+        Self::new(capacity)
+    }
+    
+    fn with_storage(storage: Storage) -> Self {
         Self {
-            data: vec![0; capacity],
+            storage,
             read_pos: 0,
             write_pos: 0,
+            max_capacity: usize::MAX,
+            mark: None,
+            _instance: Instance::new(Tracked::Buffer),
         }
     }
     
+    /// Check whether this is a circular buffer
+    pub fn is_ring(&self) -> bool {
+        !matches!(self.storage, Storage::Linear(_))
+    }
+    
+    /// Stop the buffer from growing beyond `max_capacity` bytes
+    ///
+    /// A buffer already larger than that keeps its size but won't grow.
+    pub fn set_max_capacity(&mut self, max_capacity: usize) {
+        self.max_capacity = max_capacity;
+    }
+    
+    /// Get the size the buffer may grow to
+    pub fn max_capacity(&self) -> usize {
+        self.max_capacity
+    }
+    
+    /// Check whether the buffer holds as much as it ever can
+    pub fn is_full(&self) -> bool {
+        self.write_pos - self.retained_from() >= self.storage.size().max(self.max_capacity)
+    }
+    
     /// Read data from a reader into the buffer
+    ///
+    /// Fails with `ErrorKind::OutOfMemory` once the buffer is full and may
+    /// not grow, rather than reading nothing, which would look like EOF.
     pub fn read_from<R: Read>(&mut self, reader: &mut R) -> io::Result<usize> {
         // Ensure we have space
         self.ensure_capacity(1024);
+        if self.remaining_capacity() == 0 {
+            return Err(io::Error::new(io::ErrorKind::OutOfMemory, "Buffer size limit reached"));
+        }
         
         // Read directly into the buffer at the write position
-        let bytes_read = reader.read(&mut self.data[self.write_pos..])?;
+        let end = self.window_end();
+        let bytes_read = reader.read(self.storage.window_mut(self.write_pos, end))?;
         self.write_pos += bytes_read;
         
         Ok(bytes_read)
@@ -38,44 +133,75 @@ impl Buffer {
             return Ok(0);
         }
         
-        let bytes_written = writer.write(&self.data[self.read_pos..self.write_pos])?;
+        let bytes_written = writer.write(self.slice())?;
         self.read_pos += bytes_written;
-        
-        // If we've read everything, reset positions
-        if self.read_pos == self.write_pos {
-            self.reset();
-        }
+        self.reset_if_drained();
         
         Ok(bytes_written)
     }
     
     /// Ensure the buffer has at least the specified additional capacity
+    ///
+    /// Growth stops at the maximum capacity, so afterwards there may be less
+    /// room than asked for.
     pub fn ensure_capacity(&mut self, additional: usize) {
-        let available_capacity = self.data.len() - self.write_pos;
-        
-        if available_capacity >= additional {
+        if self.remaining_capacity() >= additional {
             return;
         }
         
-        // Compact the buffer if possible
-        if self.read_pos > 0 {
-            let len = self.write_pos - self.read_pos;
-            unsafe {
-                ptr::copy(
-                    self.data.as_ptr().add(self.read_pos),
-                    self.data.as_mut_ptr(),
-                    len,
-                );
+        let size = self.storage.size();
+        match &mut self.storage {
+            Storage::Linear(data) => {
+                // Compact the buffer if possible, keeping marked data
+                let keep_from = self.mark.unwrap_or(self.read_pos);
+                if keep_from > 0 {
+                    let len = self.write_pos - keep_from;
+                    unsafe {
+                        ptr::copy(
+                            data.as_ptr().add(keep_from),
+                            data.as_mut_ptr(),
+                            len,
+                        );
+                    }
+                    self.write_pos = len;
+                    self.read_pos -= keep_from;
+                    self.mark = self.mark.map(|_| 0);
+                }
+                
+                // Resize if still needed
+                let available_after_compact = data.len() - self.write_pos;
+                if available_after_compact < additional {
+                    let new_capacity = (data.len() + additional)
+                        .max(data.len() * 2)
+                        .min(self.max_capacity);
+                    if new_capacity > data.len() {
+                        data.resize(new_capacity, 0);
+                    }
+                }
+            }
+            #[cfg(target_os = "linux")]
+            Storage::Ring(region) => {
+                // A ring only runs out of room when it is full, so grow it
+                let new_capacity = (size + additional).max(size * 2).min(self.max_capacity);
+                if new_capacity <= size {
+                    return;
+                }
+                let mut grown = match MirroredRegion::new(new_capacity) {
+                    Ok(grown) => grown,
+                    Err(e) => {
+                        log::warn!("Failed to grow ring buffer: {}", e);
+                        return;
+                    }
+                };
+                
+                let keep_from = self.mark.unwrap_or(self.read_pos);
+                let len = self.write_pos - keep_from;
+                grown.window_mut(0, len).copy_from_slice(region.window(keep_from, self.write_pos));
+                *region = grown;
+                self.write_pos = len;
+                self.read_pos -= keep_from;
+                self.mark = self.mark.map(|_| 0);
             }
-            self.write_pos = len;
-            self.read_pos = 0;
-        }
-        
-        // Resize if still needed
-        let available_after_compact = self.data.len() - self.write_pos;
-        if available_after_compact < additional {
-            let new_capacity = (self.data.len() + additional).max(self.data.len() * 2);
-            self.data.resize(new_capacity, 0);
         }
     }
     
@@ -83,6 +209,80 @@ impl Buffer {
     pub fn reset(&mut self) {
         self.read_pos = 0;
         self.write_pos = 0;
+        self.mark = None;
+    }
+    
+    /// Where the oldest data the buffer must keep starts
+    fn retained_from(&self) -> usize {
+        self.mark.unwrap_or(self.read_pos)
+    }
+    
+    /// The end of the space data may be written into
+    fn window_end(&self) -> usize {
+        match self.storage {
+            Storage::Linear(ref data) => data.len(),
+            #[cfg(target_os = "linux")]
+            Storage::Ring(ref region) => self.retained_from() + region.size,
+        }
+    }
+    
+    /// Rewind to the start once everything has been read, unless a mark
+    /// still refers to the data
+    ///
+    /// A ring instead moves its positions back out of the mirror once
+    /// reading has passed into it.
+    fn reset_if_drained(&mut self) {
+        if self.read_pos == self.write_pos && self.mark.is_none() {
+            self.reset();
+            return;
+        }
+        
+        #[cfg(target_os = "linux")]
+        if let Storage::Ring(ref region) = self.storage {
+            if self.retained_from() >= region.size {
+                self.read_pos -= region.size;
+                self.write_pos -= region.size;
+                self.mark = self.mark.map(|mark| mark - region.size);
+            }
+        }
+    }
+    
+    /// Look at up to `n` unread bytes without consuming them
+    pub fn peek(&self, n: usize) -> &[u8] {
+        let end = self.read_pos + n.min(self.available_data());
+        self.storage.window(self.read_pos, end)
+    }
+    
+    /// Consume `n` unread bytes
+    pub fn consume(&mut self, n: usize) -> ServerResult<()> {
+        self.advance_read(n)
+    }
+    
+    /// Remember the current read position so it can be returned to
+    ///
+    /// Data from the mark on is kept until `rollback` or `unmark`, so a
+    /// partial message can be consumed speculatively and given back.
+    pub fn mark(&mut self) {
+        self.mark = Some(self.read_pos);
+    }
+    
+    /// Return to the marked read position, un-consuming everything since
+    ///
+    /// Returns false if there was no mark.
+    pub fn rollback(&mut self) -> bool {
+        match self.mark.take() {
+            Some(mark) => {
+                self.read_pos = mark;
+                true
+            }
+            None => false,
+        }
+    }
+    
+    /// Drop the mark, committing to everything consumed since
+    pub fn unmark(&mut self) {
+        self.mark = None;
+        self.reset_if_drained();
     }
     
     /// Get the amount of data available to read
@@ -92,7 +292,7 @@ impl Buffer {
     
     /// Get the remaining capacity in the buffer
     pub fn remaining_capacity(&self) -> usize {
-        self.data.len() - self.write_pos
+        self.window_end() - self.write_pos
     }
     
     /// Write a slice of data to the buffer
@@ -100,7 +300,8 @@ impl Buffer {
         self.ensure_capacity(data.len());
         
         let to_copy = data.len().min(self.remaining_capacity());
-        self.data[self.write_pos..self.write_pos + to_copy].copy_from_slice(&data[..to_copy]);
+        let start = self.write_pos;
+        self.storage.window_mut(start, start + to_copy).copy_from_slice(&data[..to_copy]);
         self.write_pos += to_copy;
         
         Ok(to_copy)
@@ -114,30 +315,27 @@ impl Buffer {
         }
         
         let to_copy = data.len().min(available);
-        data[..to_copy].copy_from_slice(&self.data[self.read_pos..self.read_pos + to_copy]);
+        data[..to_copy].copy_from_slice(self.storage.window(self.read_pos, self.read_pos + to_copy));
         self.read_pos += to_copy;
-        
-        // If we've read everything, reset positions
-        if self.read_pos == self.write_pos {
-            self.reset();
-        }
+        self.reset_if_drained();
         
         Ok(to_copy)
     }
     
     /// Get a slice of the buffer's data
     pub fn slice(&self) -> &[u8] {
-        &self.data[self.read_pos..self.write_pos]
+        self.storage.window(self.read_pos, self.write_pos)
     }
     
     /// Get a mutable slice of the buffer's data
     pub fn slice_mut(&mut self) -> &mut [u8] {
-        &mut self.data[self.read_pos..self.write_pos]
+        let (start, end) = (self.read_pos, self.write_pos);
+        self.storage.window_mut(start, end)
     }
     
     /// Get the total capacity of the buffer
     pub fn capacity(&self) -> usize {
-        self.data.len()
+        self.storage.size()
     }
     
     /// Advance the read position by the specified amount
@@ -148,12 +346,121 @@ impl Buffer {
         }
         
         self.read_pos += amount;
-        
-        // If we've read everything, reset positions
-        if self.read_pos == self.write_pos {
-            self.reset();
-        }
+        self.reset_if_drained();
         
         Ok(())
     }
+}
+
+/// Lets responses be formatted straight into a buffer
+///
+/// A write past the maximum capacity is cut short, which `write_all`
+/// reports as `WriteZero`.
+impl Write for Buffer {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        Buffer::write(self, data).map_err(|e| io::Error::other(e.to_string()))
+    }
+    
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Memory mapped twice back to back, so byte `i` and byte `i + size` are the same
+///
+/// Any window of up to `size` bytes starting inside the first mapping is
+/// contiguous, however it wraps around the end of the ring. The region is
+/// only ever handed out in such windows, made from the raw pointer, so no
+/// slice covers a byte and its mirror at once.
+///
+/// The mirror is made by remapping shared anonymous memory, so unlike a
+/// memfd-backed mapping it costs no file descriptor.
+#[cfg(target_os = "linux")]
+struct MirroredRegion {
+    ptr: *mut u8,
+    size: usize,
+}
+
+// SAFETY: the region is owned exclusively and only reached through &self/&mut self
+#[cfg(target_os = "linux")]
+unsafe impl Send for MirroredRegion {}
+
+#[cfg(target_os = "linux")]
+impl MirroredRegion {
+    /// Map a region of at least `min_size` bytes, rounded up to whole pages
+    fn new(min_size: usize) -> io::Result<Self> {
+        let page = unsafe { libc::sysconf(libc::_SC_PAGESIZE) }.max(4096) as usize;
+        let size = min_size.max(1).div_ceil(page) * page;
+        
+        unsafe {
+            // Reserve room for both views, then put the memory in the first
+            // half and a second mapping of it in the other
+            let base = libc::mmap(
+                ptr::null_mut(),
+                size * 2,
+                libc::PROT_NONE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            );
+            if base == libc::MAP_FAILED {
+                return Err(io::Error::last_os_error());
+            }
+            let first = libc::mmap(
+                base,
+                size,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED | libc::MAP_ANONYMOUS | libc::MAP_FIXED,
+                -1,
+                0,
+            );
+            // Remapping zero bytes of a shared mapping maps the same pages again
+            let mirrored = first != libc::MAP_FAILED
+                && libc::mremap(
+                    first,
+                    0,
+                    size,
+                    libc::MREMAP_MAYMOVE | libc::MREMAP_FIXED,
+                    (base as *mut u8).add(size) as *mut libc::c_void,
+                ) != libc::MAP_FAILED;
+            if !mirrored {
+                let error = io::Error::last_os_error();
+                libc::munmap(base, size * 2);
+                return Err(error);
+            }
+            Ok(Self { ptr: base as *mut u8, size })
+        }
+    }
+    
+    /// The bytes from `start` up to `end`, which may be at most `size` apart
+    fn window(&self, start: usize, end: usize) -> &[u8] {
+        self.check_window(start, end);
+        // SAFETY: the window lies within the mapping and covers no byte twice
+        unsafe { std::slice::from_raw_parts(self.ptr.add(start), end - start) }
+    }
+    
+    fn window_mut(&mut self, start: usize, end: usize) -> &mut [u8] {
+        self.check_window(start, end);
+        // SAFETY: as for `window`, and &mut self rules out any other window
+        unsafe { std::slice::from_raw_parts_mut(self.ptr.add(start), end - start) }
+    }
+    
+    fn check_window(&self, start: usize, end: usize) {
+        assert!(
+            start <= end && end <= self.size * 2 && end - start <= self.size,
+            "ring window {}..{} out of bounds for size {}",
+            start,
+            end,
+            self.size
+        );
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for MirroredRegion {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.ptr as *mut libc::c_void, self.size * 2);
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,73 @@
+//! Responses serialized once and replayed on every request
+//!
+//! Health checks and other tiny fixed endpoints spend most of their time
+//! building a response and formatting its head. A `CachedResponse` does
+//! that once and hands out copies sharing the result, so a hit only
+//! copies the prepared bytes into the connection's write buffer.
+
+use crate::error::ServerResult;
+use crate::http::{PreparedResponse, Request, Response};
+use std::sync::{Arc, RwLock};
+
+/// A response built and serialized once, until it is invalidated
+///
+/// Clones share the cached response, so one kept aside after registering
+/// the route acts as its invalidation handle.
+#[derive(Clone)]
+pub struct CachedResponse {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    build: Box<dyn Fn() -> Response + Send + Sync>,
+    prepared: RwLock<Option<Arc<PreparedResponse>>>,
+}
+
+impl CachedResponse {
+    /// Cache a fixed response
+    pub fn new(response: Response) -> Self {
+        Self::with_builder(move || response.clone())
+    }
+    
+    /// Cache the response `build` makes, calling it again on the first request after each `invalidate`
+    pub fn with_builder<F>(build: F) -> Self
+    where
+        F: Fn() -> Response + Send + Sync + 'static,
+    {
+        Self {
+            inner: Arc::new(Inner {
+                build: Box::new(build),
+                prepared: RwLock::new(None),
+            }),
+        }
+    }
+    
+    /// Get a copy of the cached response, building it first if needed
+    pub fn response(&self) -> Response {
+        if let Some(prepared) = self.inner.prepared.read().unwrap().as_ref() {
+            return Response::from_prepared(prepared.clone());
+        }
+        
+        let mut prepared = self.inner.prepared.write().unwrap();
+        let prepared = prepared.get_or_insert_with(|| Arc::new(PreparedResponse::new((self.inner.build)())));
+        Response::from_prepared(prepared.clone())
+    }
+    
+    /// Drop the cached response, so the next request builds it again
+    ///
+    /// Requests already holding a copy still send the old one.
+    pub fn invalidate(&self) {
+        *self.inner.prepared.write().unwrap() = None;
+    }
+    
+    /// Check whether the response is built and waiting to be served
+    pub fn is_cached(&self) -> bool {
+        self.inner.prepared.read().unwrap().is_some()
+    }
+    
+    /// Get a route handler serving the cached response
+    pub fn handler(&self) -> impl Fn(&Request) -> ServerResult<Response> + Send + Sync + 'static {
+        let cached = self.clone();
+        move |_| Ok(cached.response())
+    }
+}
\ No newline at end of file
@@ -0,0 +1,196 @@
+//! Startup capacity planning from the file descriptor limit
+//!
+//! Every connection costs a descriptor, so RLIMIT_NOFILE decides how many
+//! the process can hold. At startup the server reads the limit, raises
+//! its soft value when `raise_fd_limit` asks for it, and works out how
+//! many connections each worker may hold and how many events its poller
+//! should ask for at once. A configured connection cap the limit can't
+//! cover is reported rather than left to fail with EMFILE under load.
+
+use crate::config::ServerConfig;
+use std::io;
+
+/// Descriptors kept free for everything besides connections: standard
+/// streams, log and pid files, static files being served, outgoing
+/// connections made by proxies and exporters
+pub const RESERVED_FDS: u64 = 64;
+
+/// Highest soft limit raised to without a connection cap; Linux refuses
+/// more than `fs.nr_open`, which defaults to this, even under an unlimited
+/// hard limit
+pub const MAX_RAISED_FDS: u64 = 1 << 20;
+
+/// Fewest events a poller asks for at once
+pub const MIN_POLL_EVENTS: usize = 64;
+
+/// Most events a poller asks for at once; more would only grow the buffer
+pub const MAX_POLL_EVENTS: usize = 4096;
+
+/// Events a poller asks for when its worker's connections aren't capped
+pub const DEFAULT_POLL_EVENTS: usize = 1024;
+
+/// Get how many events a worker's poller should ask for at once
+///
+/// A worker never has more sockets ready than its connections and
+/// listeners, so a capped worker asks for that many, within bounds.
+pub fn poll_events(max_connections: Option<usize>, listeners: usize) -> usize {
+    match max_connections {
+        Some(max) => max.saturating_add(listeners).clamp(MIN_POLL_EVENTS, MAX_POLL_EVENTS),
+        None => DEFAULT_POLL_EVENTS,
+    }
+}
+
+/// The process's soft and hard limits on open file descriptors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FdLimit {
+    /// The limit in force, which the process may raise up to `hard`
+    pub soft: u64,
+    pub hard: u64,
+}
+
+impl FdLimit {
+    /// Read the current limit
+    #[cfg(unix)]
+    pub fn current() -> io::Result<Self> {
+        let mut limit = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+        if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self {
+            soft: limit.rlim_cur,
+            hard: limit.rlim_max,
+        })
+    }
+    
+    /// Read the current limit
+    #[cfg(not(unix))]
+    pub fn current() -> io::Result<Self> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "file descriptor limits are only known on Unix"))
+    }
+    
+    /// Raise the soft limit towards `target`, as far as the hard limit allows
+    ///
+    /// Returns the limit now in force; a limit already at or above `target`
+    /// is left alone.
+    #[cfg(unix)]
+    pub fn raise_to(self, target: u64) -> io::Result<Self> {
+        let soft = target.min(self.hard);
+        if soft <= self.soft {
+            return Ok(self);
+        }
+        
+        let limit = libc::rlimit {
+            rlim_cur: soft as libc::rlim_t,
+            rlim_max: self.hard as libc::rlim_t,
+        };
+        if unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &limit) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self { soft, hard: self.hard })
+    }
+    
+    /// Raise the soft limit towards `target`, as far as the hard limit allows
+    #[cfg(not(unix))]
+    pub fn raise_to(self, _target: u64) -> io::Result<Self> {
+        Ok(self)
+    }
+}
+
+/// How many connections and poll events each worker is planned for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapacityPlan {
+    /// The limit the plan was made for
+    pub fd_limit: FdLimit,
+    
+    /// Descriptors set aside for listeners, pollers and everything else
+    pub reserved_fds: u64,
+    
+    /// Most connections one worker holds open at once
+    pub max_connections_per_worker: usize,
+    
+    /// Events each worker's poller asks for at once, to begin with
+    pub poll_events: usize,
+    
+    /// Problems with the configuration the limit can't cover, one per line
+    pub warnings: Vec<String>,
+}
+
+impl CapacityPlan {
+    /// Plan for `config` under `fd_limit`, with `listeners` listening sockets
+    ///
+    /// A configured `max_connections_per_worker` is kept, with a warning if
+    /// the limit can't cover it; otherwise the descriptors left after the
+    /// reserve are split evenly between the workers.
+    pub fn new(config: &ServerConfig, fd_limit: FdLimit, listeners: usize) -> Self {
+        let workers = config.worker_threads.max(1);
+        let reserved_fds = Self::reserve(config, listeners);
+        let available = fd_limit.soft.saturating_sub(reserved_fds);
+        let derived = usize::try_from(available / workers as u64).unwrap_or(usize::MAX).max(1);
+        
+        let mut warnings = Vec::new();
+        let max_connections_per_worker = match config.max_connections_per_worker {
+            Some(max) => {
+                let needed = Self::fds_needed(config, listeners).unwrap_or(reserved_fds);
+                if needed > fd_limit.soft {
+                    warnings.push(format!(
+                        "max_connections_per_worker ({}) x worker_threads ({}) needs {} file descriptors, \
+                         but the limit is {}; raise it with `ulimit -n`{}",
+                        max,
+                        workers,
+                        needed,
+                        fd_limit.soft,
+                        if needed <= fd_limit.hard { " or set raise_fd_limit" } else { "" },
+                    ));
+                }
+                max
+            }
+            None => derived,
+        };
+        
+        Self {
+            fd_limit,
+            reserved_fds,
+            max_connections_per_worker,
+            poll_events: config
+                .poll_events
+                .unwrap_or_else(|| poll_events(Some(max_connections_per_worker), listeners)),
+            warnings,
+        }
+    }
+    
+    /// Read the limit, raise it if `config` asks for that, and plan for it
+    ///
+    /// With `raise_fd_limit`, the soft limit is raised to cover a configured
+    /// connection cap, or as far as the hard limit and `MAX_RAISED_FDS`
+    /// allow without one.
+    pub fn for_process(config: &ServerConfig, listeners: usize) -> io::Result<Self> {
+        let mut fd_limit = FdLimit::current()?;
+        let mut raise_error = None;
+        if config.raise_fd_limit {
+            let target = Self::fds_needed(config, listeners).unwrap_or(MAX_RAISED_FDS);
+            match fd_limit.raise_to(target) {
+                Ok(raised) => fd_limit = raised,
+                Err(e) => raise_error = Some(e),
+            }
+        }
+        
+        let mut plan = Self::new(config, fd_limit, listeners);
+        if let Some(e) = raise_error {
+            plan.warnings.push(format!("couldn't raise the file descriptor limit: {}", e));
+        }
+        Ok(plan)
+    }
+    
+    /// Descriptors a configured connection cap needs, reserve included
+    fn fds_needed(config: &ServerConfig, listeners: usize) -> Option<u64> {
+        let max = config.max_connections_per_worker? as u64;
+        let connections = max.saturating_mul(config.worker_threads.max(1) as u64);
+        Some(connections.saturating_add(Self::reserve(config, listeners)))
+    }
+    
+    /// Descriptors not available to connections: one poller per worker, and
+    /// each listener with the spare descriptor its acceptor holds
+    fn reserve(config: &ServerConfig, listeners: usize) -> u64 {
+        RESERVED_FDS + config.worker_threads.max(1) as u64 + 2 * listeners as u64
+    }
+}
\ No newline at end of file
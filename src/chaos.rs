@@ -0,0 +1,253 @@
+//! Fault injection for testing clients against a misbehaving server
+//!
+//! When enabled, the chaos middleware makes a share of requests slow, fails
+//! them with a 5xx, cuts their responses short or resets their connections,
+//! with probabilities set per path prefix. Clients' retries, timeouts and
+//! error handling can then be exercised against the real server.
+
+use crate::error::{ErrorResponse, ServerError, ServerResult};
+use crate::http::{Request, Response, Status};
+use crate::metrics::MetricsRegistry;
+use crate::middleware::MiddlewareNext;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Faults injected into requests under one path prefix
+///
+/// Probabilities run from 0.0 to 1.0. Latency is added independently of the
+/// other faults; at most one of a reset, an error or a truncated response
+/// happens to a request, so their probabilities may add up to at most 1.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChaosRule {
+    /// Path prefix the rule covers, by whole segments (`/` = everything)
+    pub path: String,
+    
+    /// Chance of delaying a request
+    pub latency_probability: f64,
+    
+    /// Delay added to a request picked for latency
+    pub latency: Duration,
+    
+    /// Up to this much more delay, at random
+    pub latency_jitter: Duration,
+    
+    /// Chance of answering with `error_status` instead of the real response
+    pub error_probability: f64,
+    
+    /// Status of injected errors
+    pub error_status: u16,
+    
+    /// Chance of sending only the first half of the response body and closing
+    pub truncate_probability: f64,
+    
+    /// Chance of resetting the connection instead of answering
+    pub reset_probability: f64,
+}
+
+impl Default for ChaosRule {
+    fn default() -> Self {
+        Self {
+            path: "/".to_string(),
+            latency_probability: 0.0,
+            latency: Duration::from_millis(500),
+            latency_jitter: Duration::ZERO,
+            error_probability: 0.0,
+            error_status: 503,
+            truncate_probability: 0.0,
+            reset_probability: 0.0,
+        }
+    }
+}
+
+/// Settings for fault injection
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChaosConfig {
+    /// Whether faults are injected; can be changed at runtime with `Chaos::set_enabled`
+    pub enabled: bool,
+    
+    /// Rules by path prefix; the longest prefix covering a request applies
+    pub rules: Vec<ChaosRule>,
+    
+    /// Seed for the random choices, to make a run repeatable (None = seeded from the clock)
+    pub seed: Option<u64>,
+}
+
+/// What happens to one request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// Nothing beyond any added latency
+    None,
+    /// Answer with this status instead of the real response
+    Error(Status),
+    /// Send part of the real response, then close the connection
+    Truncate,
+    /// Reset the connection instead of answering
+    Reset,
+}
+
+/// Injects faults by the rules in a `ChaosConfig`
+///
+/// With metrics, injected faults count `chaos.delayed`, `chaos.errors`,
+/// `chaos.truncated` and `chaos.resets`.
+#[derive(Debug)]
+pub struct Chaos {
+    enabled: AtomicBool,
+    /// Rules with normalized prefixes, longest first
+    rules: Vec<ChaosRule>,
+    rng: AtomicU64,
+    metrics: Option<Arc<MetricsRegistry>>,
+}
+
+impl Chaos {
+    /// Check the rules in `config`
+    pub fn new(config: ChaosConfig) -> ServerResult<Self> {
+        Self::build(config, None)
+    }
+    
+    /// Check the rules in `config`, counting injected faults in `registry`
+    pub fn with_metrics(config: ChaosConfig, registry: Arc<MetricsRegistry>) -> ServerResult<Self> {
+        Self::build(config, Some(registry))
+    }
+    
+    fn build(config: ChaosConfig, metrics: Option<Arc<MetricsRegistry>>) -> ServerResult<Self> {
+        let mut rules = config.rules;
+        for rule in &mut rules {
+            let invalid = |problem: &str| ServerError::Config(format!("Chaos rule for {}: {}", rule.path, problem));
+            let probabilities = [
+                rule.latency_probability,
+                rule.error_probability,
+                rule.truncate_probability,
+                rule.reset_probability,
+            ];
+            if probabilities.iter().any(|p| !(0.0..=1.0).contains(p)) {
+                return Err(invalid("probabilities must be between 0 and 1"));
+            }
+            if rule.error_probability + rule.truncate_probability + rule.reset_probability > 1.0 {
+                return Err(invalid("error, truncate and reset probabilities add up to more than 1"));
+            }
+            if !(500..600).contains(&rule.error_status) || Status::from_code(rule.error_status).is_none() {
+                return Err(invalid(&format!("{} is not a supported 5xx status", rule.error_status)));
+            }
+            rule.path = format!("/{}", rule.path.trim_matches('/'));
+        }
+        rules.sort_by_key(|rule| std::cmp::Reverse(rule.path.len()));
+        
+        let seed = config.seed.unwrap_or_else(|| {
+            SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_nanos() as u64)
+        });
+        Ok(Self {
+            enabled: AtomicBool::new(config.enabled),
+            rules,
+            rng: AtomicU64::new(seed),
+            metrics,
+        })
+    }
+    
+    /// Start or stop injecting faults
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::SeqCst);
+    }
+    
+    /// Check whether faults are being injected
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::SeqCst)
+    }
+    
+    /// Decide the delay and fault for a request to `path`
+    pub fn decide(&self, path: &str) -> (Duration, Fault) {
+        let rule = match self.rule_for(path) {
+            Some(rule) if self.is_enabled() => rule,
+            _ => return (Duration::ZERO, Fault::None),
+        };
+        
+        let delay = if self.chance() < rule.latency_probability {
+            rule.latency + rule.latency_jitter.mul_f64(self.chance())
+        } else {
+            Duration::ZERO
+        };
+        
+        let roll = self.chance();
+        let fault = if roll < rule.reset_probability {
+            Fault::Reset
+        } else if roll < rule.reset_probability + rule.error_probability {
+            Fault::Error(Status::from_code(rule.error_status).unwrap_or(Status::ServiceUnavailable))
+        } else if roll < rule.reset_probability + rule.error_probability + rule.truncate_probability {
+            Fault::Truncate
+        } else {
+            Fault::None
+        };
+        (delay, fault)
+    }
+    
+    /// Find the rule with the longest prefix covering `path`
+    fn rule_for(&self, path: &str) -> Option<&ChaosRule> {
+        self.rules.iter().find(|rule| {
+            let prefix = rule.path.trim_end_matches('/');
+            path.strip_prefix(prefix).is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        })
+    }
+    
+    /// Get a random number in [0, 1)
+    fn chance(&self) -> f64 {
+        // splitmix64, which stays sound when threads share the state
+        const GAMMA: u64 = 0x9E37_79B9_7F4A_7C15;
+        let mut z = self.rng.fetch_add(GAMMA, Ordering::Relaxed).wrapping_add(GAMMA);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        (z >> 11) as f64 / (1u64 << 53) as f64
+    }
+    
+    fn count(&self, name: &str) {
+        if let Some(metrics) = &self.metrics {
+            metrics.counter(name).increment(1);
+        }
+    }
+}
+
+/// Chaos middleware - injects the faults its rules pick for each request
+///
+/// Added latency blocks the worker handling the request, as a slow handler
+/// would. Truncated responses keep the full `Content-Length` and close the
+/// connection, so clients see the body end early. Put it first in the
+/// chain so every other middleware's work is subject to it.
+pub fn chaos_middleware(
+    chaos: Arc<Chaos>,
+) -> impl Fn(&Request, MiddlewareNext) -> ServerResult<Response> + Send + Sync {
+    move |request, next| {
+        let (delay, fault) = chaos.decide(request.path());
+        if !delay.is_zero() {
+            chaos.count("chaos.delayed");
+            thread::sleep(delay);
+        }
+        
+        match fault {
+            Fault::None => next(request),
+            Fault::Reset => {
+                chaos.count("chaos.resets");
+                let mut response = Response::new(Status::InternalServerError);
+                response.reset_connection = true;
+                Ok(response)
+            }
+            Fault::Error(status) => {
+                chaos.count("chaos.errors");
+                Ok(ErrorResponse::new("chaos", "Injected failure").into_response(status))
+            }
+            Fault::Truncate => {
+                let mut response = next(request)?;
+                if response.body.len() > 1 {
+                    chaos.count("chaos.truncated");
+                    response.set_header("Content-Length", &response.body.len().to_string());
+                    response.body.truncate(response.body.len() / 2);
+                    response.set_header("Connection", "close");
+                }
+                Ok(response)
+            }
+        }
+    }
+}
\ No newline at end of file
@@ -1,23 +1,56 @@
-use crate::error::ServerResult;
+use crate::acl::{Acl, AclConfig};
+use crate::error::{ServerError, ServerResult};
+use crate::exporter::MetricsExportConfig;
+use crate::http::Request;
+use crate::http_client::HttpUrl;
+use crate::maintenance::MaintenanceConfig;
+use crate::resolver::ResolverConfig;
+use crate::secrets::Secret;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::Path;
 use std::time::Duration;
 
 /// Server configuration
+///
+/// Unknown keys are rejected when loading, so a misspelt setting is
+/// reported instead of silently keeping its default.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct ServerConfig {
     // Network configuration
     pub listen_address: String,
     pub port: u16,
     pub backlog_size: u32,
+    /// Additional `address:port` pairs to listen on; when set, these replace
+    /// `listen_address` and `port` (e.g. `0.0.0.0:8080` and `[::]:8080`)
+    #[serde(default)]
+    pub listen_addresses: Vec<String>,
     
     // Connection settings
     pub connection_timeout: Duration,
     pub initial_buffer_size: usize,
+    /// Most connections one worker holds open; the rest wait in the listen
+    /// backlog (None = split the file descriptor limit between workers)
+    #[serde(default)]
+    pub max_connections_per_worker: Option<usize>,
+    /// Raise the soft file descriptor limit at startup, up to the hard
+    /// limit, to cover `max_connections_per_worker`
+    #[serde(default)]
+    pub raise_fd_limit: bool,
+    /// Most connections a worker accepts from one listener per loop
+    /// iteration; the batch starts small and grows while connections keep
+    /// waiting
+    #[serde(default = "default_max_accept_batch")]
+    pub max_accept_batch: usize,
     
     // Thread configuration
     pub worker_threads: usize,
+    /// Events each worker's poller returns at once to begin with; doubled
+    /// while polls keep filling it (None = sized from `max_connections_per_worker`)
+    #[serde(default)]
+    pub poll_events: Option<usize>,
     
     // Memory configuration
     pub memory_pools_initial_size: usize,
@@ -25,8 +58,318 @@ pub struct ServerConfig {
     // HTTP configuration
     pub max_header_size: usize,
     pub max_request_size: usize,
+    /// Most bytes a connection may buffer while receiving a request; a
+    /// client that sends more is answered with 431 or 413 and disconnected
+    #[serde(default = "default_max_buffer_size")]
+    pub max_buffer_size: usize,
+    /// Use circular read buffers, which never move unread data to make room
+    /// for more; helps when clients pipeline many requests
+    #[serde(default)]
+    pub ring_buffers: bool,
     pub keep_alive: bool,
     pub keep_alive_timeout: Duration,
+    /// Alt-Svc header value added to responses, such as `h3=":443"; ma=86400`.
+    /// It points clients at an alternative endpoint for the same origin,
+    /// such as the experimental HTTP/3 listener (`h3` feature) or a proxy.
+    #[serde(default)]
+    pub alt_svc: Option<String>,
+    /// Add a `Server-Timing` header with the request's phase timings
+    #[serde(default)]
+    pub server_timing: bool,
+    
+    /// Close a connection whose client accepts no response bytes for this
+    /// long (None = rely on `connection_timeout` alone)
+    #[serde(default = "default_write_timeout")]
+    pub write_timeout: Option<Duration>,
+    
+    /// How long to wait for the peer to close after we shut down our write
+    /// side, before closing the socket anyway
+    #[serde(default = "default_lingering_close_timeout")]
+    pub lingering_close_timeout: Duration,
+    
+    // Traffic shaping
+    /// Maximum bytes per second written to a single connection (None = unlimited)
+    #[serde(default)]
+    pub connection_bandwidth_limit: Option<u64>,
+    
+    /// Hold small responses briefly so several go out in one write
+    /// (None = write every response as soon as it is ready)
+    #[serde(default)]
+    pub write_batching: Option<WriteBatching>,
+    
+    // Metrics
+    /// Push metrics to StatsD or a Pushgateway (None = only print them)
+    #[serde(default)]
+    pub metrics_export: Option<MetricsExportConfig>,
+    
+    // Lifecycle events
+    /// URL (plain http://) to post lifecycle events to as JSON; may be an
+    /// `@file:` or `@env:` reference when it carries a token
+    #[serde(default)]
+    pub event_webhook: Option<Secret>,
+    
+    // Maintenance
+    /// Answer requests with 503 during planned maintenance
+    #[serde(default)]
+    pub maintenance: MaintenanceConfig,
+    
+    // Admin routes
+    /// Routes for managing the running server, behind their own credentials
+    #[serde(default)]
+    pub admin: AdminConfig,
+    
+    // TCP tuning
+    /// Socket options applied to the listening socket and accepted connections
+    #[serde(default)]
+    pub tcp: TcpOptions,
+    
+    // Outbound name resolution
+    /// How proxies, LDAP and Redis clients look up the hosts they connect to
+    #[serde(default)]
+    pub resolver: ResolverConfig,
+    
+    // Per-route settings
+    /// Settings for requests under a path prefix such as `/api/uploads`,
+    /// merged over those of enclosing prefixes and the global settings
+    #[serde(default)]
+    pub routes: BTreeMap<String, RouteOverrides>,
+    
+    // Access control
+    /// Rules restricting paths by client address, authentication and role
+    #[serde(default)]
+    pub acl: AclConfig,
+}
+
+fn default_write_timeout() -> Option<Duration> {
+    Some(Duration::from_secs(10))
+}
+
+fn default_max_buffer_size() -> usize {
+    1024 * 1024 + 16 * 1024
+}
+
+fn default_max_accept_batch() -> usize {
+    64
+}
+
+fn default_lingering_close_timeout() -> Duration {
+    Duration::from_secs(2)
+}
+
+/// How small responses are batched into fewer writes
+///
+/// Responses to keep-alive requests that fit in `max_bytes` are queued
+/// rather than written, and the connection goes on to any request the
+/// client pipelined behind them. Everything queued on a connection is
+/// written together once `max_delay` has passed since the first of it,
+/// or sooner when no more fits.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WriteBatching {
+    /// Longest a response waits for others to join its write
+    pub max_delay: Duration,
+    
+    /// Most bytes held back on one connection
+    pub max_bytes: usize,
+}
+
+impl Default for WriteBatching {
+    fn default() -> Self {
+        Self {
+            max_delay: Duration::from_micros(100),
+            max_bytes: 16 * 1024,
+        }
+    }
+}
+
+/// Admin routes served by the server binary
+///
+/// Every admin route asks for Basic auth with `username` and `password`,
+/// locking an account out after repeated failures, whatever `require_auth`
+/// says for its path.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdminConfig {
+    /// Path of the maintenance switch (see `mount_maintenance`); None = not served
+    pub maintenance_path: Option<String>,
+    
+    /// Username the admin routes accept
+    pub username: String,
+    
+    /// Password the admin routes accept; may be an `@file:` or `@env:` reference
+    pub password: Secret,
+}
+
+/// Low-level TCP socket options (None = leave the OS default)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TcpOptions {
+    /// SO_RCVBUF size in bytes
+    pub recv_buffer_size: Option<usize>,
+    
+    /// SO_SNDBUF size in bytes
+    pub send_buffer_size: Option<usize>,
+    
+    /// TCP_FASTOPEN queue length for pending fast open requests
+    pub fast_open_queue: Option<u32>,
+    
+    /// TCP_DEFER_ACCEPT timeout; only wake the acceptor once data arrives (Linux only)
+    pub defer_accept: Option<Duration>,
+    
+    /// Idle time before TCP keepalive probes are sent
+    pub keepalive_time: Option<Duration>,
+    
+    /// Time between TCP keepalive probes
+    pub keepalive_interval: Option<Duration>,
+    
+    /// Number of unanswered keepalive probes before the connection is dropped
+    pub keepalive_probes: Option<u32>,
+    
+    /// IPV6_V6ONLY for IPv6 listeners; set to `true` when also listening on
+    /// the same port over IPv4
+    pub ipv6_only: Option<bool>,
+}
+
+/// Settings overridden for requests under one path prefix (None = inherit)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RouteOverrides {
+    /// Time each request is given by `deadline_middleware`
+    pub request_timeout: Option<Duration>,
+    
+    /// Largest request body accepted; bodies also have to fit in `max_buffer_size`
+    pub max_request_size: Option<usize>,
+    
+    /// Whether `compression_middleware` compresses responses
+    pub compression: Option<bool>,
+    
+    /// Whether the basic auth middlewares demand credentials
+    pub require_auth: Option<bool>,
+    
+    /// Whether handlers run once the request head is in, leaving the body on
+    /// the connection for them to stream (see `StreamedBody`)
+    pub stream_body: Option<bool>,
+}
+
+/// The settings that apply to one request, after merging route overrides
+///
+/// The event loop attaches these to every request; middleware reads them
+/// back with `RouteSettings::of`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RouteSettings {
+    /// Time the request is given (None = the deadline middleware's own timeout)
+    pub request_timeout: Option<Duration>,
+    pub max_request_size: usize,
+    pub compression: bool,
+    pub require_auth: bool,
+    pub stream_body: bool,
+}
+
+impl RouteSettings {
+    /// Get the settings attached to `request`, if any
+    pub fn of(request: &Request) -> Option<&RouteSettings> {
+        request.extensions.get::<RouteSettings>()
+    }
+    
+    fn apply(mut self, overrides: &RouteOverrides) -> Self {
+        self.request_timeout = overrides.request_timeout.or(self.request_timeout);
+        self.max_request_size = overrides.max_request_size.unwrap_or(self.max_request_size);
+        self.compression = overrides.compression.unwrap_or(self.compression);
+        self.require_auth = overrides.require_auth.unwrap_or(self.require_auth);
+        self.stream_body = overrides.stream_body.unwrap_or(self.stream_body);
+        self
+    }
+}
+
+/// Route overrides merged ahead of time for lookups by path
+///
+/// A prefix matches whole path segments, so `/api` covers `/api` and
+/// `/api/users` but not `/apis`. Case and empty segments are ignored, so a
+/// path a router matches leniently, such as `/API//users`, gets the same
+/// settings. Looking up a path walks a tree of prefix segments, one level
+/// per path segment, without allocating.
+#[derive(Debug, Clone)]
+pub struct RouteConfig {
+    global: RouteSettings,
+    prefixes: PrefixNode,
+}
+
+/// Settings for one prefix segment, and the longer prefixes below it
+#[derive(Debug, Clone, Default)]
+struct PrefixNode {
+    settings: Option<RouteSettings>,
+    /// Lowercased segments and their nodes
+    children: Vec<(String, PrefixNode)>,
+}
+
+impl RouteConfig {
+    /// Merge the route overrides in `config`
+    pub fn new(config: &ServerConfig) -> Self {
+        let mut routes = Self {
+            global: RouteSettings {
+                request_timeout: None,
+                max_request_size: config.max_request_size,
+                compression: true,
+                require_auth: true,
+                stream_body: false,
+            },
+            prefixes: PrefixNode::default(),
+        };
+        
+        // Enclosing prefixes have fewer segments, so they are merged first
+        let mut prefixes: Vec<(&str, &RouteOverrides)> =
+            config.routes.iter().map(|(prefix, overrides)| (prefix.as_str(), overrides)).collect();
+        prefixes.sort_by_key(|(prefix, _)| segments(prefix).count());
+        for (prefix, overrides) in prefixes {
+            let settings = routes.settings(prefix).apply(overrides);
+            if segments(prefix).next().is_none() {
+                routes.global = settings;
+                continue;
+            }
+            let mut node = &mut routes.prefixes;
+            for segment in segments(prefix) {
+                let index = match node.children.iter().position(|(name, _)| name.eq_ignore_ascii_case(segment)) {
+                    Some(index) => index,
+                    None => {
+                        node.children.push((segment.to_ascii_lowercase(), PrefixNode::default()));
+                        node.children.len() - 1
+                    }
+                };
+                node = &mut node.children[index].1;
+            }
+            node.settings = Some(settings);
+        }
+        routes
+    }
+    
+    /// Get the settings for a request path; any query string is ignored
+    pub fn settings(&self, path: &str) -> RouteSettings {
+        let path = path.split('?').next().unwrap_or("");
+        let mut node = &self.prefixes;
+        let mut settings = self.global;
+        for segment in segments(path) {
+            match node.children.iter().find(|(name, _)| name.eq_ignore_ascii_case(segment)) {
+                Some((_, child)) => {
+                    node = child;
+                    settings = child.settings.unwrap_or(settings);
+                }
+                None => break,
+            }
+        }
+        settings
+    }
+}
+
+/// Split a path into its non-empty segments, so `//api/` is just `api`
+fn segments(path: &str) -> impl Iterator<Item = &str> {
+    path.trim().split('/').filter(|segment| !segment.is_empty())
+}
+
+impl TcpOptions {
+    /// Check whether any keepalive option is set
+    pub fn keepalive_enabled(&self) -> bool {
+        self.keepalive_time.is_some() || self.keepalive_interval.is_some() || self.keepalive_probes.is_some()
+    }
 }
 
 impl Default for ServerConfig {
@@ -35,18 +378,49 @@ impl Default for ServerConfig {
             listen_address: "127.0.0.1".to_string(),
             port: 8080,
             backlog_size: 1024,
+            listen_addresses: Vec::new(),
             
             connection_timeout: Duration::from_secs(30),
             initial_buffer_size: 16 * 1024, // 16 KB
+            max_connections_per_worker: None,
+            raise_fd_limit: false,
+            max_accept_batch: default_max_accept_batch(),
             
             worker_threads: num_cpus::get(),
+            poll_events: None,
             
             memory_pools_initial_size: 16,
             
             max_header_size: 16 * 1024, // 16 KB
             max_request_size: 1024 * 1024, // 1 MB
+            max_buffer_size: default_max_buffer_size(),
+            ring_buffers: false,
             keep_alive: true,
             keep_alive_timeout: Duration::from_secs(5),
+            alt_svc: None,
+            server_timing: false,
+            
+            write_timeout: default_write_timeout(),
+            lingering_close_timeout: default_lingering_close_timeout(),
+            
+            connection_bandwidth_limit: None,
+            write_batching: None,
+            
+            metrics_export: None,
+            
+            event_webhook: None,
+            
+            maintenance: MaintenanceConfig::default(),
+            
+            admin: AdminConfig::default(),
+            
+            tcp: TcpOptions::default(),
+            
+            resolver: ResolverConfig::default(),
+            
+            routes: BTreeMap::new(),
+            
+            acl: AclConfig::default(),
         }
     }
 }
@@ -70,28 +444,301 @@ impl ServerConfig {
         self
     }
     
+    /// Set how long a client may accept no response bytes before being disconnected
+    pub fn with_write_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.write_timeout = timeout;
+        self
+    }
+    
+    /// Set how long a lingering close waits for the peer to close
+    pub fn with_lingering_close_timeout(mut self, timeout: Duration) -> Self {
+        self.lingering_close_timeout = timeout;
+        self
+    }
+    
+    /// Set how small responses are batched into fewer writes (None = write each at once)
+    pub fn with_write_batching(mut self, batching: Option<WriteBatching>) -> Self {
+        self.write_batching = batching;
+        self
+    }
+    
     /// Set the number of worker threads
     pub fn with_worker_threads(mut self, threads: usize) -> Self {
         self.worker_threads = threads;
         self
     }
     
+    /// Cap the connections one worker holds open
+    pub fn with_max_connections_per_worker(mut self, max: usize) -> Self {
+        self.max_connections_per_worker = Some(max);
+        self
+    }
+    
+    /// Raise the soft file descriptor limit at startup to cover the connection cap
+    pub fn with_raise_fd_limit(mut self, raise: bool) -> Self {
+        self.raise_fd_limit = raise;
+        self
+    }
+    
+    /// Set the most connections accepted from one listener per loop iteration
+    pub fn with_max_accept_batch(mut self, max: usize) -> Self {
+        self.max_accept_batch = max;
+        self
+    }
+    
+    /// Set how many events each poll returns at first
+    pub fn with_poll_events(mut self, events: usize) -> Self {
+        self.poll_events = Some(events);
+        self
+    }
+    
     /// Set the initial buffer size for connections
     pub fn with_initial_buffer_size(mut self, size: usize) -> Self {
         self.initial_buffer_size = size;
         self
     }
     
+    /// Set the most bytes a connection may buffer while receiving a request
+    pub fn with_max_buffer_size(mut self, size: usize) -> Self {
+        self.max_buffer_size = size;
+        self
+    }
+    
+    /// Enable or disable circular read buffers
+    pub fn with_ring_buffers(mut self, enabled: bool) -> Self {
+        self.ring_buffers = enabled;
+        self
+    }
+    
+    /// Set the largest request body accepted
+    pub fn with_max_request_size(mut self, size: usize) -> Self {
+        self.max_request_size = size;
+        self
+    }
+    
+    /// Set the largest request header section accepted
+    pub fn with_max_header_size(mut self, size: usize) -> Self {
+        self.max_header_size = size;
+        self
+    }
+    
+    /// Listen on several addresses instead of `listen_address` and `port`
+    pub fn with_listen_addresses<S: Into<String>>(mut self, addresses: impl IntoIterator<Item = S>) -> Self {
+        self.listen_addresses = addresses.into_iter().map(Into::into).collect();
+        self
+    }
+    
+    /// Add an `Alt-Svc` header to responses that don't set their own
+    pub fn with_alt_svc(mut self, alt_svc: &str) -> Self {
+        self.alt_svc = Some(alt_svc.to_string());
+        self
+    }
+    
+    /// Report per-request phase timings in a `Server-Timing` header
+    pub fn with_server_timing(mut self, enabled: bool) -> Self {
+        self.server_timing = enabled;
+        self
+    }
+    
+    /// Periodically push metrics to StatsD or a Pushgateway
+    pub fn with_metrics_export(mut self, export: MetricsExportConfig) -> Self {
+        self.metrics_export = Some(export);
+        self
+    }
+    
+    /// Start the server in maintenance mode, answering requests with 503
+    pub fn with_maintenance(mut self, maintenance: MaintenanceConfig) -> Self {
+        self.maintenance = maintenance;
+        self
+    }
+    
+    /// Serve admin routes, such as the maintenance switch
+    pub fn with_admin(mut self, admin: AdminConfig) -> Self {
+        self.admin = admin;
+        self
+    }
+    
+    /// Set the listen backlog size
+    pub fn with_backlog_size(mut self, backlog_size: u32) -> Self {
+        self.backlog_size = backlog_size;
+        self
+    }
+    
+    /// Set the TCP socket options
+    pub fn with_tcp_options(mut self, tcp: TcpOptions) -> Self {
+        self.tcp = tcp;
+        self
+    }
+    
+    /// Set how outbound clients resolve host names
+    pub fn with_resolver(mut self, resolver: ResolverConfig) -> Self {
+        self.resolver = resolver;
+        self
+    }
+    
+    /// Limit the bytes per second written to each connection
+    pub fn with_connection_bandwidth_limit(mut self, bytes_per_second: u64) -> Self {
+        self.connection_bandwidth_limit = Some(bytes_per_second);
+        self
+    }
+    
+    /// Override settings for requests under `prefix`
+    pub fn with_route(mut self, prefix: &str, overrides: RouteOverrides) -> Self {
+        self.routes.insert(prefix.to_string(), overrides);
+        self
+    }
+    
     /// Get the full address string (address:port)
     pub fn socket_address(&self) -> String {
         format!("{}:{}", self.listen_address, self.port)
     }
     
-    /// Load configuration from a JSON file
+    /// Get every address string the server should listen on
+    pub fn socket_addresses(&self) -> Vec<String> {
+        if self.listen_addresses.is_empty() {
+            vec![self.socket_address()]
+        } else {
+            self.listen_addresses.clone()
+        }
+    }
+    
+    /// Parse and validate configuration from JSON
+    ///
+    /// Errors give the line and column of the offending key, and suggest
+    /// the closest known key for unknown ones.
+    pub fn from_json(content: &str) -> ServerResult<Self> {
+        let config: Self = serde_json::from_str(content).map_err(|e| describe_json_error(content, &e))?;
+        config.validate()?;
+        Ok(config)
+    }
+    
+    /// Load and validate configuration from a JSON file
     pub fn from_json_file<P: AsRef<Path>>(path: P) -> ServerResult<Self> {
+        let path = path.as_ref();
         let content = fs::read_to_string(path)?;
-        let config = serde_json::from_str(&content)?;
-        Ok(config)
+        Self::from_json(&content).map_err(|e| match e {
+            ServerError::Config(message) => ServerError::Config(format!("{}: {}", path.display(), message)),
+            other => other,
+        })
+    }
+    
+    /// Check the settings serde can't: value ranges, and settings that
+    /// have to agree with each other
+    ///
+    /// Every problem is reported, one per line, naming its key.
+    pub fn validate(&self) -> ServerResult<()> {
+        let mut problems = Vec::new();
+        let mut check = |ok: bool, key: &str, problem: &str| {
+            if !ok {
+                problems.push(format!("`{}` {}", key, problem));
+            }
+        };
+        
+        if self.listen_addresses.is_empty() {
+            check(self.port != 0, "port", "must not be 0");
+        }
+        for (i, address) in self.listen_addresses.iter().enumerate() {
+            let port = address.rsplit_once(':').and_then(|(_, port)| port.parse::<u16>().ok());
+            check(
+                port.is_some_and(|port| port != 0),
+                &format!("listen_addresses[{}]", i),
+                &format!("must be `address:port` with a port other than 0, not {:?}", address),
+            );
+        }
+        check(self.backlog_size >= 1, "backlog_size", "must be at least 1");
+        check(self.worker_threads >= 1, "worker_threads", "must be at least 1");
+        check(
+            self.max_connections_per_worker != Some(0),
+            "max_connections_per_worker",
+            "must be at least 1 or null",
+        );
+        check(self.poll_events != Some(0), "poll_events", "must be at least 1 or null");
+        check(self.max_accept_batch >= 1, "max_accept_batch", "must be at least 1");
+        check(
+            self.alt_svc.as_ref().is_none_or(|value| !value.trim().is_empty() && !value.contains(char::is_control)),
+            "alt_svc",
+            "must be a non-empty header value without control characters, or null",
+        );
+        
+        check(!self.connection_timeout.is_zero(), "connection_timeout", "must be greater than 0");
+        check(!self.keep_alive_timeout.is_zero(), "keep_alive_timeout", "must be greater than 0");
+        check(self.write_timeout != Some(Duration::ZERO), "write_timeout", "must be greater than 0 or null");
+        check(
+            self.connection_bandwidth_limit != Some(0),
+            "connection_bandwidth_limit",
+            "must be greater than 0 or null",
+        );
+        if let Some(batching) = &self.write_batching {
+            check(batching.max_bytes >= 1, "write_batching.max_bytes", "must be at least 1");
+            check(
+                batching.max_delay <= Duration::from_millis(100),
+                "write_batching.max_delay",
+                "must be at most 100ms; it delays every batched response",
+            );
+        }
+        
+        check(self.initial_buffer_size >= 1, "initial_buffer_size", "must be at least 1");
+        check(self.max_header_size >= 1, "max_header_size", "must be at least 1");
+        check(self.max_request_size >= 1, "max_request_size", "must be at least 1");
+        check(
+            self.initial_buffer_size <= self.max_buffer_size,
+            "initial_buffer_size",
+            &format!("must not exceed max_buffer_size ({})", self.max_buffer_size),
+        );
+        check(
+            self.max_header_size <= self.max_buffer_size,
+            "max_header_size",
+            &format!("must not exceed max_buffer_size ({}), or no request fits", self.max_buffer_size),
+        );
+        
+        if let Some(export) = &self.metrics_export {
+            check(!export.interval.is_zero(), "metrics_export.interval", "must be greater than 0");
+        }
+        if let Some(url) = &self.event_webhook {
+            if let Err(e) = HttpUrl::parse(url.expose()) {
+                check(false, "event_webhook", &format!("is not a usable URL: {}", e));
+            }
+        }
+        
+        if let Some(path) = &self.admin.maintenance_path {
+            check(
+                path.starts_with('/') && !path.contains(['*', ':']),
+                "admin.maintenance_path",
+                "must be a path starting with `/`, without parameters or wildcards",
+            );
+            check(!self.admin.username.is_empty(), "admin.username", "must be set to serve admin routes");
+            check(!self.admin.password.expose().is_empty(), "admin.password", "must be set to serve admin routes");
+        }
+        
+        for (prefix, overrides) in &self.routes {
+            let key = |field: &str| format!("routes[{:?}]{}", prefix, field);
+            check(prefix.starts_with('/'), &key(""), "must be a path starting with `/`");
+            if let Some(timeout) = overrides.request_timeout {
+                check(!timeout.is_zero(), &key(".request_timeout"), "must be greater than 0");
+            }
+            if let Some(size) = overrides.max_request_size {
+                check(size >= 1, &key(".max_request_size"), "must be at least 1");
+                check(
+                    size <= self.max_buffer_size,
+                    &key(".max_request_size"),
+                    &format!("must not exceed max_buffer_size ({}), which bounds every body", self.max_buffer_size),
+                );
+            }
+        }
+        
+        if let Err(e) = Acl::new(&self.acl) {
+            let message = match e {
+                ServerError::Config(message) => message,
+                other => other.to_string(),
+            };
+            check(false, "acl", &format!("is invalid: {}", message));
+        }
+        
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ServerError::Config(format!("invalid settings:\n  {}", problems.join("\n  "))))
+        }
     }
     
     /// Save configuration to a JSON file
@@ -100,4 +747,54 @@ impl ServerConfig {
         fs::write(path, content)?;
         Ok(())
     }
+}
+
+/// Describe a JSON error by its position, with a suggestion for unknown keys
+fn describe_json_error(content: &str, error: &serde_json::Error) -> ServerError {
+    let mut message = error.to_string();
+    // serde_json appends the position, which is given separately below
+    if let Some(end) = message.rfind(" at line ") {
+        message.truncate(end);
+    }
+    
+    // "unknown field `prot`, expected one of `port`, ..." lists every key;
+    // name the closest one instead
+    let unknown = [("unknown field `", "key"), ("unknown variant `", "value")]
+        .iter()
+        .find_map(|(prefix, what)| message.strip_prefix(prefix).map(|rest| (rest.to_string(), *what)));
+    if let Some((rest, what)) = unknown {
+        let mut parts = rest.split('`');
+        let unknown = parts.next().unwrap_or_default().to_string();
+        let closest = parts
+            .skip(1)
+            .step_by(2)
+            .map(|known| (edit_distance(&unknown, known), known))
+            .min()
+            .filter(|(distance, _)| *distance <= unknown.len().max(2) / 2 + 1);
+        message = match closest {
+            Some((_, known)) => format!("unknown {} `{}`; did you mean `{}`?", what, unknown, known),
+            None => format!("unknown {} `{}`", what, unknown),
+        };
+    }
+    
+    if error.line() == 0 {
+        return ServerError::Config(message);
+    }
+    let line = content.lines().nth(error.line() - 1).unwrap_or_default().trim();
+    ServerError::Config(format!("line {}, column {}: {}\n  {}", error.line(), error.column(), message, line))
+}
+
+/// Count the single-character edits turning `a` into `b`
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
 }
\ No newline at end of file
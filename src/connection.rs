@@ -1,28 +1,90 @@
+use crate::acceptor::IpSlot;
 use crate::buffer::Buffer;
-use std::io::{self, Write};
+use crate::error::ServerResult;
+use crate::http::{HttpParser, HttpParserState, Response};
+use crate::leaks::{Instance, Tracked};
+use crate::throttle::TokenBucket;
+use crate::timeline::{Phase, RequestTimeline};
+use socket2::SockRef;
+use std::io::{self, IoSlice, Write};
 use std::net::{SocketAddr, TcpStream};
 use std::time::{Duration, Instant};
 
 /// Represents the current state of a connection
+///
+/// A connection moves `New → Reading → Processing → Writing`, then back to
+/// `Reading` for the next request on a keep-alive connection, or on to
+/// `Closing` and `Closed`. `HalfClosed` means the peer finished sending
+/// while a response is still being written.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ConnectionState {
     New,
     Reading,
     Processing,
     Writing,
+    HalfClosed,
     Closing,
     Closed,
 }
 
+impl ConnectionState {
+    /// Check whether a connection may move from this state to `next`
+    pub fn can_transition_to(self, next: ConnectionState) -> bool {
+        use ConnectionState::*;
+        
+        match (self, next) {
+            (_, Closed) => true,
+            (Closed, _) => false,
+            (New, Reading) => true,
+            (Reading, Reading | Processing) => true,
+            (Processing, Writing) => true,
+            (Writing, Writing | Reading) => true,
+            (Reading | Processing | Writing, HalfClosed) => true,
+            (New | Reading | Processing | Writing | HalfClosed | Closing, Closing) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Outcome of flushing a connection's outbound queue
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteStatus {
+    /// Everything queued has been written to the socket
+    Complete,
+    
+    /// The socket buffer is full; wait for the connection to become writable
+    WouldBlock,
+    
+    /// The rate limits don't allow more data right now
+    Throttled,
+}
+
 /// Represents a TCP connection with a client
 pub struct Connection {
     stream: TcpStream,
     peer_addr: SocketAddr,
     id: usize,
     state: ConnectionState,
+    invalid_transitions: usize,
+    /// Whether to wait for another request once the current response is sent
+    keep_alive: bool,
     buffer: Buffer,
+    parser: HttpParser,
+    write_buffer: Buffer,
     last_activity: Instant,
     timeout: Duration,
+    /// Whether queued output only holds responses already finished with `queue_response`
+    deferred_flush: bool,
+    /// When queued output last made progress, while any is queued
+    write_progress: Option<Instant>,
+    write_deadline: Option<Duration>,
+    /// When our write side was shut down for a lingering close
+    lingering_since: Option<Instant>,
+    rate_limiter: Option<TokenBucket>,
+    response_rate_limiter: Option<TokenBucket>,
+    timeline: RequestTimeline,
+    ip_slot: Option<IpSlot>,
+    _instance: Instance,
 }
 
 impl Connection {
@@ -36,55 +98,349 @@ impl Connection {
             peer_addr,
             id,
             state: ConnectionState::New,
+            invalid_transitions: 0,
+            keep_alive: true,
             buffer: Buffer::new(16 * 1024), // 16KB initial buffer
+            parser: HttpParser::new(),
+            write_buffer: Buffer::new(16 * 1024),
             last_activity: Instant::now(),
             timeout: Duration::from_secs(30), // 30 second default timeout
+            deferred_flush: false,
+            write_progress: None,
+            write_deadline: None,
+            lingering_since: None,
+            rate_limiter: None,
+            response_rate_limiter: None,
+            timeline: RequestTimeline::new(),
+            ip_slot: None,
+            _instance: Instance::new(Tracked::Connection),
         })
     }
     
     /// Read data from the connection into the buffer
+    ///
+    /// A read of 0 bytes means the peer closed its sending side; what that
+    /// means for the connection is up to the caller.
     pub fn read(&mut self) -> io::Result<usize> {
-        self.state = ConnectionState::Reading;
+        if self.state == ConnectionState::New {
+            self.transition_to(ConnectionState::Reading);
+        }
         let bytes_read = self.buffer.read_from(&mut self.stream)?;
         self.last_activity = Instant::now();
         
-        if bytes_read == 0 {
-            // Remote end closed the connection
-            self.state = ConnectionState::Closing;
-        }
-        
         Ok(bytes_read)
     }
     
     /// Write data to the connection
+    ///
+    /// This is a single `write` on the socket and may send only part of
+    /// `data`; use `write_all` to have the rest queued and sent later.
     pub fn write(&mut self, data: &[u8]) -> io::Result<usize> {
-        self.state = ConnectionState::Writing;
+        self.start_writing();
         let result = self.stream.write(data);
         self.last_activity = Instant::now();
         result
     }
     
+    /// Queue data for writing and send as much of it as possible right away
+    ///
+    /// Whatever can't be written now stays queued until `flush` is called
+    /// again, typically when the connection becomes writable.
+    pub fn write_all(&mut self, data: &[u8]) -> io::Result<WriteStatus> {
+        self.deferred_flush = false;
+        self.queue(data)?;
+        self.flush()
+    }
+    
+    /// Send a response head and body, coalescing them into as few packets as possible
+    ///
+    /// With nothing already queued, both parts go out in a single vectored
+    /// write, so a small response leaves as one segment even with Nagle's
+    /// algorithm disabled. Whatever isn't written is queued like `write_all`.
+    pub fn write_response(&mut self, head: &[u8], body: &[u8]) -> io::Result<WriteStatus> {
+        self.deferred_flush = false;
+        let queued = self.has_pending_writes();
+        self.queue(head)?;
+        if queued {
+            self.queue(body)?;
+            return self.flush();
+        }
+        self.write_queued_with(body)
+    }
+    
+    /// Send a response, serializing its head straight into the write buffer
+    ///
+    /// Room for the head, and for the body when it has to wait behind
+    /// earlier output, is reserved up front. The head is then written with
+    /// the body in one vectored write like `write_response`, with no
+    /// intermediate allocation for either.
+    pub fn send_response(&mut self, response: &Response) -> io::Result<WriteStatus> {
+        self.deferred_flush = false;
+        let queued = self.has_pending_writes();
+        let body = response.body_bytes();
+        let body_room = if queued { body.len() } else { 0 };
+        self.queue_head(response, body_room)?;
+        if queued {
+            self.queue(body)?;
+            return self.flush();
+        }
+        self.write_queued_with(body)
+    }
+    
+    /// Queue a response to go out with the next `flush`, without writing anything now
+    ///
+    /// The response counts as finished: the connection may go back to
+    /// reading and queue responses to later requests behind it, so that a
+    /// single flush sends them all.
+    pub fn queue_response(&mut self, response: &Response) -> io::Result<()> {
+        self.start_writing();
+        self.queue_head(response, response.body_bytes().len())?;
+        self.queue(response.body_bytes())?;
+        self.deferred_flush = true;
+        Ok(())
+    }
+    
+    /// Serialize a response head onto the outbound queue, leaving room for `body_room` more bytes
+    fn queue_head(&mut self, response: &Response, body_room: usize) -> io::Result<()> {
+        if self.write_progress.is_none() {
+            self.write_progress = Some(Instant::now());
+        }
+        self.write_buffer.ensure_capacity(response.head_len_hint() + body_room);
+        response
+            .serialize_head(&mut self.write_buffer)
+            .map_err(|e| io::Error::other(e.to_string()))
+    }
+    
+    /// Write the queued output and then `body` in one vectored write, queueing whatever isn't sent
+    fn write_queued_with(&mut self, body: &[u8]) -> io::Result<WriteStatus> {
+        let queued = self.write_buffer.available_data();
+        let total = queued + body.len();
+        if self.write_allowance(total) < total {
+            self.queue(body)?;
+            return self.flush();
+        }
+        
+        self.start_writing();
+        let written = loop {
+            match self.stream.write_vectored(&[IoSlice::new(self.write_buffer.slice()), IoSlice::new(body)]) {
+                Ok(written) => break written,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break 0,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        };
+        
+        if written > 0 {
+            self.last_activity = Instant::now();
+            self.timeline.mark(Phase::FirstByteWritten);
+            self.consume_write_allowance(written);
+            self.write_buffer
+                .advance_read(written.min(queued))
+                .map_err(|e| io::Error::other(e.to_string()))?;
+        }
+        if written == total {
+            self.write_progress = None;
+            return Ok(WriteStatus::Complete);
+        }
+        
+        // Queue the rest of the body behind what's left of the queued output
+        self.queue(&body[written.saturating_sub(queued)..])?;
+        self.flush()
+    }
+    
+    /// Check and clear whether the queued output held only responses from `queue_response`
+    pub fn take_deferred_flush(&mut self) -> bool {
+        std::mem::take(&mut self.deferred_flush)
+    }
+    
+    /// Add data to the outbound queue without writing it yet
+    fn queue(&mut self, data: &[u8]) -> io::Result<()> {
+        if self.write_progress.is_none() {
+            self.write_progress = Some(Instant::now());
+        }
+        self.write_buffer
+            .write(data)
+            .map(|_| ())
+            .map_err(|e| io::Error::other(e.to_string()))
+    }
+    
+    /// Write queued data until the queue is empty, the socket would block or
+    /// the rate limits run out
+    pub fn flush(&mut self) -> io::Result<WriteStatus> {
+        if self.write_buffer.available_data() > 0 {
+            self.start_writing();
+        }
+        
+        while self.write_buffer.available_data() > 0 {
+            let allowance = self.write_allowance(self.write_buffer.available_data());
+            if allowance == 0 {
+                // Waiting on our own rate limit isn't the client's fault
+                self.write_progress = Some(Instant::now());
+                return Ok(WriteStatus::Throttled);
+            }
+            
+            match self.stream.write(&self.write_buffer.slice()[..allowance]) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(bytes_written) => {
+                    self.last_activity = Instant::now();
+                    self.write_progress = Some(self.last_activity);
+                    // Batched responses are finished already; the timeline is the next request's
+                    if !self.deferred_flush {
+                        self.timeline.mark(Phase::FirstByteWritten);
+                    }
+                    self.consume_write_allowance(bytes_written);
+                    self.write_buffer
+                        .advance_read(bytes_written)
+                        .map_err(|e| io::Error::other(e.to_string()))?;
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(WriteStatus::WouldBlock),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        
+        self.write_progress = None;
+        Ok(WriteStatus::Complete)
+    }
+    
+    /// Enter `Writing`, unless the connection is already past it
+    fn start_writing(&mut self) {
+        if !matches!(
+            self.state,
+            ConnectionState::Writing | ConnectionState::HalfClosed | ConnectionState::Closing
+        ) {
+            self.transition_to(ConnectionState::Writing);
+        }
+    }
+    
+    /// Check whether any queued data is still waiting to be written
+    pub fn has_pending_writes(&self) -> bool {
+        self.write_buffer.available_data() > 0
+    }
+    
+    /// Get the number of queued bytes not yet written
+    pub fn pending_write_bytes(&self) -> usize {
+        self.write_buffer.available_data()
+    }
+    
     /// Close the connection
     pub fn close(&mut self) -> io::Result<()> {
-        self.state = ConnectionState::Closed;
+        self.transition_to(ConnectionState::Closed);
         self.stream.shutdown(std::net::Shutdown::Both)
     }
     
+    /// Close the connection with a TCP reset rather than an orderly shutdown
+    ///
+    /// The socket is reset once the connection is dropped; the peer sees
+    /// `ECONNRESET` instead of a response.
+    pub fn reset(&mut self) -> io::Result<()> {
+        self.transition_to(ConnectionState::Closed);
+        SockRef::from(&self.stream).set_linger(Some(Duration::ZERO))
+    }
+    
+    /// Record that the peer half-closed the connection after sending its request
+    ///
+    /// The connection stays open so the pending response can still be sent.
+    pub fn mark_read_closed(&mut self) -> bool {
+        self.transition_to(ConnectionState::HalfClosed)
+    }
+    
+    /// Check whether the peer has finished sending while a response is pending
+    pub fn is_read_closed(&self) -> bool {
+        self.state == ConnectionState::HalfClosed
+    }
+    
+    /// Shut down our write side and keep the socket open briefly
+    ///
+    /// Closing outright while the peer may still send data would answer that
+    /// data with a reset, which can destroy response bytes not yet read by
+    /// the peer. Sending FIN first and waiting for the peer to close avoids it.
+    pub fn start_lingering_close(&mut self) -> io::Result<()> {
+        self.transition_to(ConnectionState::Closing);
+        if self.lingering_since.is_none() {
+            self.lingering_since = Some(Instant::now());
+            self.stream.shutdown(std::net::Shutdown::Write)?;
+        }
+        Ok(())
+    }
+    
+    /// Check whether the connection is in a lingering close
+    pub fn is_lingering(&self) -> bool {
+        self.lingering_since.is_some()
+    }
+    
+    /// Check whether a lingering close has lasted longer than `timeout`
+    pub fn linger_expired(&self, timeout: Duration) -> bool {
+        self.lingering_since.is_some_and(|since| since.elapsed() > timeout)
+    }
+    
     /// Check if the connection has timed out
     pub fn is_timed_out(&self) -> bool {
         self.last_activity.elapsed() > self.timeout
     }
     
+    /// Get the earliest moment one of the connection's timeouts could expire
+    ///
+    /// This covers the idle timeout, the write deadline and, given its
+    /// timeout, a lingering close.
+    pub fn next_deadline(&self, linger_timeout: Duration) -> Option<Instant> {
+        let idle = Some(self.last_activity + self.timeout);
+        let linger = self.lingering_since.map(|since| since + linger_timeout);
+        let stall = match (self.write_deadline, self.write_progress) {
+            (Some(deadline), Some(progress)) if self.has_pending_writes() => Some(progress + deadline),
+            _ => None,
+        };
+        [idle, linger, stall].into_iter().flatten().min()
+    }
+    
+    /// Check whether queued output has made no progress within the write deadline
+    ///
+    /// This catches clients that stop reading, leaving the socket buffer full.
+    pub fn is_write_stalled(&self) -> bool {
+        match (self.write_deadline, self.write_progress) {
+            (Some(deadline), Some(progress)) => self.has_pending_writes() && progress.elapsed() > deadline,
+            _ => false,
+        }
+    }
+    
     /// Get the connection's peer address
     pub fn peer_addr(&self) -> SocketAddr {
         self.peer_addr
     }
     
+    /// Count this connection against its IP's limit until it is dropped
+    pub fn set_ip_slot(&mut self, slot: IpSlot) {
+        self.ip_slot = Some(slot);
+    }
+    
+    /// Record that the peer sent a malformed request, returning true if its IP is now banned
+    pub fn report_garbage(&self) -> bool {
+        self.ip_slot.as_ref().is_some_and(|slot| slot.report_garbage())
+    }
+    
     /// Get the connection's unique ID
     pub fn id(&self) -> usize {
         self.id
     }
     
+    /// Cap how much of an incoming request may be buffered
+    pub fn set_max_buffer_size(&mut self, size: usize) {
+        self.buffer.set_max_capacity(size);
+    }
+    
+    /// Switch the read buffer to a circular one of the same capacity
+    ///
+    /// Only takes effect while the buffer is empty; returns whether the
+    /// buffer is now circular.
+    pub fn use_ring_buffer(&mut self) -> bool {
+        if !self.buffer.is_ring() && self.buffer.available_data() == 0 {
+            let mut ring = Buffer::ring(self.buffer.capacity());
+            ring.set_max_capacity(self.buffer.max_capacity());
+            self.buffer = ring;
+        }
+        self.buffer.is_ring()
+    }
+    
     /// Get a reference to the connection's buffer
     pub fn buffer(&self) -> &Buffer {
         &self.buffer
@@ -95,14 +451,80 @@ impl Connection {
         &mut self.buffer
     }
     
+    /// Run the request parser over what has been buffered, consuming what it uses
+    ///
+    /// Marks the headers as parsed on the request's timeline once they are.
+    pub fn parse(&mut self) -> ServerResult<()> {
+        self.parser.parse_buffer(&mut self.buffer)?;
+        if matches!(self.parser.state, HttpParserState::Body | HttpParserState::Complete) {
+            self.timeline.mark(Phase::HeadersParsed);
+        }
+        Ok(())
+    }
+    
+    /// Get the parser for requests on this connection
+    pub fn parser(&self) -> &HttpParser {
+        &self.parser
+    }
+    
+    /// Get a mutable reference to the parser for requests on this connection
+    pub fn parser_mut(&mut self) -> &mut HttpParser {
+        &mut self.parser
+    }
+    
     /// Get the current state of the connection
     pub fn state(&self) -> ConnectionState {
         self.state
     }
     
-    /// Set the state of the connection
-    pub fn set_state(&mut self, state: ConnectionState) {
-        self.state = state;
+    /// Move the connection to `next`, returning false if that isn't allowed
+    ///
+    /// Invalid transitions leave the state unchanged and are logged and
+    /// counted. Returning to `Reading` with response data still queued is
+    /// invalid, since the next request would overtake it.
+    pub fn transition_to(&mut self, next: ConnectionState) -> bool {
+        let allowed = self.state.can_transition_to(next)
+            && !(next == ConnectionState::Reading
+                && self.state == ConnectionState::Writing
+                && self.has_pending_writes()
+                && !self.deferred_flush);
+        
+        if allowed {
+            self.state = next;
+        } else {
+            self.invalid_transitions += 1;
+            log::warn!(
+                "Connection {}: invalid state transition {:?} -> {:?}",
+                self.id,
+                self.state,
+                next
+            );
+        }
+        allowed
+    }
+    
+    /// Get the number of invalid transitions attempted on this connection
+    pub fn invalid_transitions(&self) -> usize {
+        self.invalid_transitions
+    }
+    
+    /// Check whether the connection is waiting for its next request
+    ///
+    /// Only idle connections can be closed without cutting a request short.
+    pub fn is_idle(&self) -> bool {
+        matches!(self.state, ConnectionState::New | ConnectionState::Reading)
+            && self.buffer.available_data() == 0
+            && !self.has_pending_writes()
+    }
+    
+    /// Set whether to wait for another request after the current response
+    pub fn set_keep_alive(&mut self, keep_alive: bool) {
+        self.keep_alive = keep_alive;
+    }
+    
+    /// Check whether the connection will wait for another request after the current response
+    pub fn keep_alive(&self) -> bool {
+        self.keep_alive
     }
     
     /// Set the connection timeout
@@ -110,6 +532,82 @@ impl Connection {
         self.timeout = timeout;
     }
     
+    /// Set how long queued output may go without progress (None = no deadline)
+    pub fn set_write_deadline(&mut self, deadline: Option<Duration>) {
+        self.write_deadline = deadline;
+    }
+    
+    /// Limit the bytes per second written to this connection
+    pub fn set_rate_limit(&mut self, bytes_per_second: Option<u64>) {
+        self.rate_limiter = bytes_per_second.map(TokenBucket::new);
+    }
+    
+    /// Limit the bytes per second for the response currently being written
+    pub fn set_response_rate_limit(&mut self, bytes_per_second: Option<u64>) {
+        self.response_rate_limiter = bytes_per_second.map(TokenBucket::new);
+    }
+    
+    /// Check whether writes to this connection are being shaped
+    pub fn is_throttled(&self) -> bool {
+        self.rate_limiter.is_some() || self.response_rate_limiter.is_some()
+    }
+    
+    /// Get how many of `wanted` bytes may be written right now
+    pub fn write_allowance(&mut self, wanted: usize) -> usize {
+        let mut allowance = wanted;
+        
+        if let Some(bucket) = &mut self.rate_limiter {
+            allowance = allowance.min(bucket.available());
+        }
+        
+        if let Some(bucket) = &mut self.response_rate_limiter {
+            allowance = allowance.min(bucket.available());
+        }
+        
+        allowance
+    }
+    
+    /// Charge bytes that were written against the rate limits
+    pub fn consume_write_allowance(&mut self, bytes: usize) {
+        if let Some(bucket) = &mut self.rate_limiter {
+            bucket.consume(bytes);
+        }
+        
+        if let Some(bucket) = &mut self.response_rate_limiter {
+            bucket.consume(bytes);
+        }
+    }
+    
+    /// Get the time until the rate limits allow more data to be written
+    pub fn throttle_delay(&mut self) -> Duration {
+        let mut delay = Duration::ZERO;
+        
+        if let Some(bucket) = &mut self.rate_limiter {
+            delay = delay.max(bucket.time_until_available());
+        }
+        
+        if let Some(bucket) = &mut self.response_rate_limiter {
+            delay = delay.max(bucket.time_until_available());
+        }
+        
+        delay
+    }
+    
+    /// Get the timeline of the request currently being handled
+    pub fn timeline(&self) -> &RequestTimeline {
+        &self.timeline
+    }
+    
+    /// Get a mutable reference to the current request's timeline
+    pub fn timeline_mut(&mut self) -> &mut RequestTimeline {
+        &mut self.timeline
+    }
+    
+    /// Finish the current request's timeline and start one for the next request
+    pub fn take_timeline(&mut self) -> RequestTimeline {
+        std::mem::take(&mut self.timeline)
+    }
+    
     /// Get a reference to the underlying TcpStream
     pub fn stream(&self) -> &TcpStream {
         &self.stream
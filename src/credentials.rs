@@ -0,0 +1,608 @@
+use crate::config::RouteSettings;
+use crate::error::{ServerError, ServerResult};
+use crate::hash;
+use crate::http::{Request, Response, Status};
+use crate::middleware::MiddlewareNext;
+use crate::password::{self, HashFormat};
+use crate::protocol_upgrade::{answer_deferred, UpgradeResponse};
+use crate::resolver;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// A source of username/password credentials
+pub trait CredentialStore: Send + Sync {
+    /// Check a username and password
+    ///
+    /// Returns `Ok(false)` for wrong credentials and `Err` when the store
+    /// itself couldn't be consulted (e.g. the LDAP server is down).
+    fn verify(&self, username: &str, password: &str) -> ServerResult<bool>;
+    
+    /// Check a username and password if it can be done without blocking
+    ///
+    /// `None` means only `verify` can tell, after a wait on something slow
+    /// such as a network round trip. Stores that never wait answer here.
+    fn try_verify(&self, username: &str, password: &str) -> Option<ServerResult<bool>> {
+        Some(self.verify(username, password))
+    }
+}
+
+/// A single fixed username and password
+pub struct StaticCredentials {
+    username: String,
+    password: String,
+}
+
+impl StaticCredentials {
+    /// Create a store accepting exactly one username and password
+    pub fn new(username: &str, password: &str) -> Self {
+        Self {
+            username: username.to_string(),
+            password: password.to_string(),
+        }
+    }
+}
+
+impl CredentialStore for StaticCredentials {
+    fn verify(&self, username: &str, password: &str) -> ServerResult<bool> {
+        // Check both halves so the timing doesn't reveal which one was wrong
+        let user_ok = hash::constant_time_eq(username.as_bytes(), self.username.as_bytes());
+        let password_ok = hash::constant_time_eq(password.as_bytes(), self.password.as_bytes());
+        Ok(user_ok & password_ok)
+    }
+}
+
+/// Users loaded from an Apache htpasswd file (bcrypt or `$apr1$` hashes)
+pub struct HtpasswdFile {
+    users: HashMap<String, String>,
+    /// Hash checked for unknown users, as slow to check as the slowest entry
+    dummy: Option<String>,
+}
+
+impl HtpasswdFile {
+    /// Load an htpasswd file
+    pub fn load<P: AsRef<Path>>(path: P) -> ServerResult<Self> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+    
+    /// Parse htpasswd content; blank lines and `#` comments are skipped
+    ///
+    /// Entries using a hash format other than bcrypt or `$apr1$` (such as
+    /// `{SHA}`, crypt or plain text) are rejected rather than silently ignored.
+    pub fn parse(content: &str) -> ServerResult<Self> {
+        let mut users = HashMap::new();
+        
+        for (number, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            
+            let (username, stored) = line
+                .split_once(':')
+                .ok_or_else(|| ServerError::Config(format!("htpasswd line {} has no ':'", number + 1)))?;
+            if HashFormat::detect(stored).is_none() {
+                return Err(ServerError::Config(format!(
+                    "htpasswd line {} uses an unsupported hash format",
+                    number + 1
+                )));
+            }
+            
+            users.insert(username.to_string(), stored.to_string());
+        }
+        
+        let dummy = users
+            .values()
+            .max_by_key(|stored| password::bcrypt_cost(stored).unwrap_or(0))
+            .and_then(|stored| password::dummy_hash_like(stored));
+        Ok(Self { users, dummy })
+    }
+    
+    /// Get the number of users
+    pub fn len(&self) -> usize {
+        self.users.len()
+    }
+    
+    /// Check whether the file has no users
+    pub fn is_empty(&self) -> bool {
+        self.users.is_empty()
+    }
+}
+
+impl CredentialStore for HtpasswdFile {
+    fn verify(&self, username: &str, password: &str) -> ServerResult<bool> {
+        match self.users.get(username) {
+            Some(stored) => Ok(password::verify_password(password, stored)),
+            None => {
+                // Spend comparable time on unknown users to avoid revealing who exists
+                if let Some(dummy) = &self.dummy {
+                    let _ = password::verify_password(password, dummy);
+                }
+                Ok(false)
+            }
+        }
+    }
+}
+
+/// Checks credentials with an LDAPv3 simple bind
+///
+/// The username is escaped and substituted into a DN template such as
+/// `uid={username},ou=people,dc=example,dc=com`. The connection is plain TCP;
+/// run it over a trusted network or through a TLS tunnel, since the password
+/// is sent as-is.
+///
+/// A bind blocks the calling thread: the address lookup, connect and bind
+/// each wait up to the timeout. `basic_auth_store_middleware` makes binds
+/// off the event loop, answering those requests from a thread of their own.
+/// Successful binds are cached for a few seconds, so clients sending the
+/// same credentials again are answered without one.
+pub struct LdapBind {
+    address: String,
+    dn_template: String,
+    timeout: Duration,
+    cache_ttl: Duration,
+    /// When each set of credentials last bound successfully, by their digest
+    cache: Mutex<HashMap<[u8; 32], Instant>>,
+    cache_key: [u8; 32],
+}
+
+impl LdapBind {
+    /// Create a backend binding to the server at `address` (`host:port`)
+    pub fn new(address: &str, dn_template: &str) -> Self {
+        Self {
+            address: address.to_string(),
+            dn_template: dn_template.to_string(),
+            timeout: Duration::from_secs(2),
+            cache_ttl: Duration::from_secs(10),
+            cache: Mutex::new(HashMap::new()),
+            cache_key: random_key(),
+        }
+    }
+    
+    /// Set the connect, read and write timeout
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+    
+    /// Set how long a successful bind is remembered (zero = not at all)
+    ///
+    /// This is how long a password changed or revoked on the LDAP server
+    /// keeps working for clients that already used it: 10 seconds unless
+    /// set otherwise.
+    pub fn with_cache_ttl(mut self, ttl: Duration) -> Self {
+        self.cache_ttl = ttl;
+        self
+    }
+    
+    /// Build the bind DN for a username
+    pub fn bind_dn(&self, username: &str) -> String {
+        self.dn_template.replace("{username}", &escape_dn_value(username))
+    }
+    
+    /// Get the cache key for a username and password
+    fn digest(&self, username: &str, password: &str) -> [u8; 32] {
+        let mut credentials = Vec::with_capacity(8 + username.len() + password.len());
+        credentials.extend_from_slice(&(username.len() as u64).to_be_bytes());
+        credentials.extend_from_slice(username.as_bytes());
+        credentials.extend_from_slice(password.as_bytes());
+        hash::hmac_sha256(&self.cache_key, &credentials)
+    }
+    
+    /// Bind as `username` on a fresh connection
+    fn bind(&self, username: &str, password: &str) -> ServerResult<bool> {
+        let address = resolver::shared()
+            .resolve_address(&self.address)?
+            .into_iter()
+            .next()
+            .ok_or_else(|| ServerError::Config(format!("Invalid LDAP address: {}", self.address)))?;
+        let mut stream = TcpStream::connect_timeout(&address, self.timeout)?;
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+        
+        stream.write_all(&ldap_bind_request(1, &self.bind_dn(username), password))?;
+        let result_code = read_bind_response(&mut stream)?;
+        
+        match result_code {
+            0 => Ok(true),
+            // invalidCredentials
+            49 => Ok(false),
+            code => Err(ServerError::Protocol(format!("LDAP bind failed with result code {}", code))),
+        }
+    }
+}
+
+impl CredentialStore for LdapBind {
+    fn verify(&self, username: &str, password: &str) -> ServerResult<bool> {
+        if let Some(result) = self.try_verify(username, password) {
+            return result;
+        }
+        
+        let bound = self.bind(username, password)?;
+        let mut cache = self.cache.lock().unwrap();
+        cache.retain(|_, at| at.elapsed() < self.cache_ttl);
+        if bound && !self.cache_ttl.is_zero() {
+            cache.insert(self.digest(username, password), Instant::now());
+        }
+        Ok(bound)
+    }
+    
+    fn try_verify(&self, username: &str, password: &str) -> Option<ServerResult<bool>> {
+        // An empty password makes an "unauthenticated" bind, which servers accept
+        if username.is_empty() || password.is_empty() {
+            return Some(Ok(false));
+        }
+        
+        let cache = self.cache.lock().unwrap();
+        let at = cache.get(&self.digest(username, password))?;
+        (at.elapsed() < self.cache_ttl).then_some(Ok(true))
+    }
+}
+
+/// A secret key for digesting cached credentials, different in every process
+fn random_key() -> [u8; 32] {
+    let mut key = [0u8; 32];
+    let mut filled = 0;
+    while filled < key.len() {
+        let rest = &mut key[filled..];
+        let read = unsafe { libc::getrandom(rest.as_mut_ptr().cast(), rest.len(), 0) };
+        if read < 0 {
+            let error = io::Error::last_os_error();
+            assert_eq!(error.kind(), io::ErrorKind::Interrupted, "getrandom failed: {}", error);
+            continue;
+        }
+        filled += read as usize;
+    }
+    key
+}
+
+/// Escape a value for use inside a DN (RFC 4514)
+fn escape_dn_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for (i, c) in value.chars().enumerate() {
+        let leading = i == 0 && (c == ' ' || c == '#');
+        let trailing = i + 1 == value.chars().count() && c == ' ';
+        if matches!(c, ',' | '+' | '"' | '\\' | '<' | '>' | ';' | '=') || leading || trailing {
+            escaped.push('\\');
+            escaped.push(c);
+        } else if c == '\0' {
+            escaped.push_str("\\00");
+        } else {
+            escaped.push(c);
+        }
+    }
+    escaped
+}
+
+/// Encode a BER length
+fn ber_length(len: usize, out: &mut Vec<u8>) {
+    if len < 0x80 {
+        out.push(len as u8);
+    } else {
+        let bytes = (len as u32).to_be_bytes();
+        let skip = bytes.iter().take_while(|&&b| b == 0).count();
+        out.push(0x80 | (4 - skip) as u8);
+        out.extend_from_slice(&bytes[skip..]);
+    }
+}
+
+/// Encode a BER element
+fn ber_element(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    ber_length(content.len(), &mut out);
+    out.extend_from_slice(content);
+    out
+}
+
+/// Build an LDAPv3 simple BindRequest message
+fn ldap_bind_request(message_id: u8, dn: &str, password: &str) -> Vec<u8> {
+    let mut bind = ber_element(0x02, &[3]);
+    bind.extend(ber_element(0x04, dn.as_bytes()));
+    bind.extend(ber_element(0x80, password.as_bytes()));
+    
+    let mut message = ber_element(0x02, &[message_id]);
+    message.extend(ber_element(0x60, &bind));
+    ber_element(0x30, &message)
+}
+
+/// Read a BER element header, returning its tag and content length
+fn read_ber_header<R: Read>(reader: &mut R) -> ServerResult<(u8, usize)> {
+    let mut byte = [0u8; 1];
+    reader.read_exact(&mut byte)?;
+    let tag = byte[0];
+    
+    reader.read_exact(&mut byte)?;
+    let len = if byte[0] & 0x80 == 0 {
+        byte[0] as usize
+    } else {
+        let count = (byte[0] & 0x7f) as usize;
+        if count == 0 || count > 4 {
+            return Err(ServerError::Protocol("Unsupported BER length".to_string()));
+        }
+        let mut len = 0usize;
+        for _ in 0..count {
+            reader.read_exact(&mut byte)?;
+            len = (len << 8) | byte[0] as usize;
+        }
+        len
+    };
+    
+    Ok((tag, len))
+}
+
+/// Read a BindResponse and return its result code
+fn read_bind_response<R: Read>(reader: &mut R) -> ServerResult<u8> {
+    let (tag, len) = read_ber_header(reader)?;
+    if tag != 0x30 || len > 64 * 1024 {
+        return Err(ServerError::Protocol("Malformed LDAP response".to_string()));
+    }
+    
+    let mut message = vec![0u8; len];
+    reader.read_exact(&mut message)?;
+    let mut cursor = &message[..];
+    
+    // Skip the message ID
+    let (_, id_len) = read_ber_header(&mut cursor)?;
+    cursor = cursor
+        .get(id_len..)
+        .ok_or_else(|| ServerError::Protocol("Truncated LDAP response".to_string()))?;
+    
+    let (op, _) = read_ber_header(&mut cursor)?;
+    if op != 0x61 {
+        return Err(ServerError::Protocol("Expected an LDAP BindResponse".to_string()));
+    }
+    
+    let (result_tag, result_len) = read_ber_header(&mut cursor)?;
+    match (result_tag, cursor.first()) {
+        (0x0a, Some(&code)) if result_len == 1 => Ok(code),
+        _ => Err(ServerError::Protocol("Malformed LDAP result code".to_string())),
+    }
+}
+
+/// When to lock an account after repeated failed logins
+#[derive(Debug, Clone, Copy)]
+pub struct LockoutPolicy {
+    /// Failures tolerated within `window` before locking
+    pub max_failures: u32,
+    
+    /// Failures older than this are forgotten
+    pub window: Duration,
+    
+    /// How long a locked account stays locked
+    pub lockout: Duration,
+}
+
+impl Default for LockoutPolicy {
+    fn default() -> Self {
+        Self {
+            max_failures: 5,
+            window: Duration::from_secs(300),
+            lockout: Duration::from_secs(900),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct FailureRecord {
+    failures: u32,
+    first_failure: Instant,
+    locked_until: Option<Instant>,
+}
+
+/// Counts failed logins per username and locks accounts that exceed the policy
+pub struct LoginLockout {
+    policy: LockoutPolicy,
+    records: Mutex<HashMap<String, FailureRecord>>,
+}
+
+impl LoginLockout {
+    /// Create a tracker
+    pub fn new(policy: LockoutPolicy) -> Self {
+        Self {
+            policy,
+            records: Mutex::new(HashMap::new()),
+        }
+    }
+    
+    /// Get how much longer an account stays locked, if it is
+    pub fn locked_for(&self, username: &str) -> Option<Duration> {
+        let now = Instant::now();
+        let records = self.records.lock().unwrap();
+        records
+            .get(username)
+            .and_then(|record| record.locked_until)
+            .filter(|until| *until > now)
+            .map(|until| until - now)
+    }
+    
+    /// Record a failed login, returning true if the account is now locked
+    pub fn record_failure(&self, username: &str) -> bool {
+        let now = Instant::now();
+        let mut records = self.records.lock().unwrap();
+        
+        // Forget stale entries so the map doesn't grow without bound
+        let policy = self.policy;
+        records.retain(|_, record| {
+            record.locked_until.is_some_and(|until| until > now)
+                || now.duration_since(record.first_failure) < policy.window
+        });
+        
+        let record = records.entry(username.to_string()).or_insert(FailureRecord {
+            failures: 0,
+            first_failure: now,
+            locked_until: None,
+        });
+        if record.locked_until.is_some_and(|until| until <= now) {
+            *record = FailureRecord {
+                failures: 0,
+                first_failure: now,
+                locked_until: None,
+            };
+        }
+        
+        record.failures += 1;
+        if record.failures >= policy.max_failures {
+            record.locked_until = Some(now + policy.lockout);
+        }
+        record.locked_until.is_some()
+    }
+    
+    /// Record a successful login, clearing the failure count
+    pub fn record_success(&self, username: &str) {
+        self.records.lock().unwrap().remove(username);
+    }
+    
+    /// Get the number of recent failures for an account
+    pub fn failures(&self, username: &str) -> u32 {
+        self.records
+            .lock()
+            .unwrap()
+            .get(username)
+            .map(|record| record.failures)
+            .unwrap_or(0)
+    }
+}
+
+/// The authenticated user, attached to request extensions by the auth middleware
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthIdentity {
+    pub username: String,
+}
+
+impl AuthIdentity {
+    /// Get the identity attached to a request, if it was authenticated
+    pub fn of(request: &Request) -> Option<&AuthIdentity> {
+        request.extensions.get::<AuthIdentity>()
+    }
+}
+
+/// Copy a request with the authenticated identity attached
+pub(crate) fn authenticated(request: &Request, username: &str) -> Request {
+    let mut request = request.clone();
+    request.extensions.insert(AuthIdentity {
+        username: username.to_string(),
+    });
+    request
+}
+
+/// Decode the username and password from a `Basic` Authorization header
+pub fn parse_basic_auth(request: &Request) -> Option<(String, String)> {
+    let auth = request.get_header("authorization")?;
+    let (scheme, encoded) = auth.trim().split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("Basic") {
+        return None;
+    }
+    
+    let decoded = String::from_utf8(base64::decode(encoded.trim()).ok()?).ok()?;
+    // Passwords may contain ':', usernames may not
+    let (username, password) = decoded.split_once(':')?;
+    Some((username.to_string(), password.to_string()))
+}
+
+/// Build the 401 response asking for Basic credentials
+fn unauthorized(realm: &str) -> Response {
+    let mut response = Response::new(Status::Unauthorized);
+    response.set_header("WWW-Authenticate", &format!("Basic realm=\"{}\"", realm.replace('"', "")));
+    response.set_body(b"Unauthorized");
+    response
+}
+
+/// How long a client has to take a response answered off the event loop
+const DEFERRED_WRITE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Basic auth middleware backed by a credential store
+///
+/// Authenticated requests reach later middleware with an `AuthIdentity`
+/// extension.
+///
+/// With a lockout policy, accounts that fail too often get 429 with a
+/// `Retry-After` header until the lockout ends, without consulting the store.
+/// Store errors (such as an unreachable LDAP server) give 503. Routes
+/// configured with `require_auth: false` pass through unchecked.
+///
+/// Credentials the store can't check without blocking, such as an LDAP bind
+/// that isn't cached, are checked off the event loop (see
+/// `UpgradeResponse::defer`): the rest of the chain runs on that thread too,
+/// and the connection closes once the answer is sent. Middleware ahead of
+/// this one only sees the placeholder response.
+pub fn basic_auth_store_middleware(
+    store: Arc<dyn CredentialStore>,
+    realm: String,
+    lockout: Option<LockoutPolicy>,
+) -> impl Fn(&Request, MiddlewareNext) -> ServerResult<Response> + Send + Sync {
+    let lockout = lockout.map(|policy| Arc::new(LoginLockout::new(policy)));
+    let realm = Arc::new(realm);
+    
+    move |request, next| {
+        if RouteSettings::of(request).is_some_and(|settings| !settings.require_auth) {
+            return next(request);
+        }
+        
+        let (username, password) = match parse_basic_auth(request) {
+            Some(credentials) => credentials,
+            None => return Ok(unauthorized(&realm)),
+        };
+        
+        if let Some(remaining) = lockout.as_ref().and_then(|l| l.locked_for(&username)) {
+            let mut response = Response::new(Status::TooManyRequests);
+            response.set_header("Retry-After", &remaining.as_secs().max(1).to_string());
+            response.set_body(b"Too many failed login attempts");
+            return Ok(response);
+        }
+        
+        if let Some(verified) = store.try_verify(&username, &password) {
+            return login(verified, request, &username, lockout.as_deref(), &realm, &next);
+        }
+        
+        let (store, lockout, realm, request) = (store.clone(), lockout.clone(), realm.clone(), request.clone());
+        Ok(UpgradeResponse::defer(move |mut client, head| {
+            let verified = store.verify(&username, &password);
+            let response = login(verified, &request, &username, lockout.as_deref(), &realm, &next)
+                .unwrap_or_else(|e| e.to_response(&request));
+            
+            let stream = client.stream_mut();
+            let prepared = stream
+                .set_nonblocking(false)
+                .and_then(|_| stream.set_write_timeout(Some(DEFERRED_WRITE_TIMEOUT)));
+            if let Err(e) = prepared.and_then(|_| answer_deferred(client, head, response)) {
+                log::debug!("Authenticated response cut short: {}", e);
+            }
+        })
+        .into())
+    }
+}
+
+/// Answer a request once its credentials have been checked
+fn login(
+    verified: ServerResult<bool>,
+    request: &Request,
+    username: &str,
+    lockout: Option<&LoginLockout>,
+    realm: &str,
+    next: &MiddlewareNext,
+) -> ServerResult<Response> {
+    match verified {
+        Ok(true) => {
+            if let Some(lockout) = lockout {
+                lockout.record_success(username);
+            }
+            next(&authenticated(request, username))
+        }
+        Ok(false) => {
+            if let Some(lockout) = lockout {
+                lockout.record_failure(username);
+            }
+            Ok(unauthorized(realm))
+        }
+        Err(e) => {
+            log::error!("Credential store error: {}", e);
+            let mut response = Response::new(Status::ServiceUnavailable);
+            response.set_body(b"Authentication unavailable");
+            Ok(response)
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,158 @@
+use crate::error::{ServerError, ServerResult};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+
+/// An exclusively locked pid file, removed again when dropped
+#[derive(Debug)]
+pub struct PidFile {
+    path: PathBuf,
+    file: File,
+}
+
+impl PidFile {
+    /// Lock the pid file and write the current process id into it
+    ///
+    /// Fails if another running instance holds the lock.
+    pub fn acquire<P: AsRef<Path>>(path: P) -> ServerResult<Self> {
+        Self::acquire_with(path.as_ref(), false)
+    }
+    
+    /// Wait until the pid file is released, then lock it and write our pid
+    ///
+    /// Used by a process started through a binary upgrade, whose predecessor
+    /// keeps the pid file until it has finished draining.
+    pub fn acquire_blocking<P: AsRef<Path>>(path: P) -> ServerResult<Self> {
+        Self::acquire_with(path.as_ref(), true)
+    }
+    
+    fn acquire_with(path: &Path, blocking: bool) -> ServerResult<Self> {
+        let path = path.to_path_buf();
+        let mut file = Self::lock(&path, blocking)?;
+        
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        writeln!(file, "{}", std::process::id())?;
+        file.sync_all()?;
+        
+        Ok(Self { path, file })
+    }
+    
+    /// Check that no running instance holds the pid file, without taking it
+    pub fn check<P: AsRef<Path>>(path: P) -> ServerResult<()> {
+        // The lock is released again when the file is closed
+        Self::lock(path.as_ref(), false).map(|_| ())
+    }
+    
+    /// Open the pid file and take an exclusive lock on it
+    fn lock(path: &Path, blocking: bool) -> ServerResult<File> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        
+        let operation = if blocking { libc::LOCK_EX } else { libc::LOCK_EX | libc::LOCK_NB };
+        if unsafe { libc::flock(file.as_raw_fd(), operation) } < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::WouldBlock {
+                return Err(ServerError::Io(err));
+            }
+            
+            let mut contents = String::new();
+            let _ = file.read_to_string(&mut contents);
+            return Err(ServerError::Config(format!(
+                "Another instance (pid {}) holds the pid file {}",
+                contents.trim(),
+                path.display()
+            )));
+        }
+        
+        Ok(file)
+    }
+    
+    /// Get the path of the pid file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        // Only remove the file if it still records our pid
+        let mut contents = String::new();
+        let _ = self.file.seek(SeekFrom::Start(0));
+        if self.file.read_to_string(&mut contents).is_ok()
+            && contents.trim() == std::process::id().to_string()
+        {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Detach from the controlling terminal and continue in the background
+///
+/// Uses the classic double fork so the daemon can never reacquire a terminal.
+/// Standard input is redirected to `/dev/null`, standard output and error are
+/// appended to `log_file` (or discarded when none is given). The working
+/// directory is left unchanged so relative paths in the config keep working.
+///
+/// Must be called before any threads are spawned.
+pub fn daemonize(log_file: Option<&Path>) -> ServerResult<()> {
+    // Open the log file first so errors are still reported on the terminal
+    let log = match log_file {
+        Some(path) => OpenOptions::new().create(true).append(true).open(path)?,
+        None => OpenOptions::new().write(true).open("/dev/null")?,
+    };
+    let null = File::open("/dev/null")?;
+    
+    fork_and_exit_parent()?;
+    
+    if unsafe { libc::setsid() } < 0 {
+        return Err(ServerError::Io(io::Error::last_os_error()));
+    }
+    
+    fork_and_exit_parent()?;
+    
+    unsafe {
+        libc::umask(0o027);
+    }
+    
+    redirect_fd(null.as_raw_fd(), libc::STDIN_FILENO)?;
+    redirect_fd(log.as_raw_fd(), libc::STDOUT_FILENO)?;
+    redirect_fd(log.as_raw_fd(), libc::STDERR_FILENO)?;
+    
+    Ok(())
+}
+
+/// Reopen the log file standard output and error are appended to
+///
+/// Called after the file was moved away (e.g. by logrotate) so output goes
+/// to a fresh file at the original path.
+pub fn reopen_log(log_file: &Path) -> ServerResult<()> {
+    let log = OpenOptions::new().create(true).append(true).open(log_file)?;
+    redirect_fd(log.as_raw_fd(), libc::STDOUT_FILENO)?;
+    redirect_fd(log.as_raw_fd(), libc::STDERR_FILENO)?;
+    
+    Ok(())
+}
+
+/// Fork, letting only the child continue
+fn fork_and_exit_parent() -> ServerResult<()> {
+    match unsafe { libc::fork() } {
+        -1 => Err(ServerError::Io(io::Error::last_os_error())),
+        0 => Ok(()),
+        _ => unsafe { libc::_exit(0) },
+    }
+}
+
+/// Point `target` at the same open file as `source`
+fn redirect_fd(source: i32, target: i32) -> ServerResult<()> {
+    if unsafe { libc::dup2(source, target) } < 0 {
+        return Err(ServerError::Io(io::Error::last_os_error()));
+    }
+    
+    Ok(())
+}
\ No newline at end of file
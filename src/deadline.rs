@@ -0,0 +1,130 @@
+use crate::config::RouteSettings;
+use crate::error::{ServerError, ServerResult};
+use crate::http::{Request, Response};
+use crate::middleware::MiddlewareNext;
+use std::io;
+use std::time::{Duration, Instant};
+
+/// Header carrying a request's remaining time budget, in milliseconds
+pub const DEADLINE_HEADER: &str = "X-Request-Deadline";
+
+/// Settings for per-request deadlines
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeadlineConfig {
+    /// Time each request is given from when it reaches the middleware
+    pub timeout: Duration,
+    
+    /// Header the budget is read from and passed upstream in (None = neither)
+    pub header: Option<String>,
+    
+    /// Shorten the budget to the one a downstream proxy sent in `header`
+    pub honor_incoming: bool,
+}
+
+impl Default for DeadlineConfig {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(30),
+            header: Some(DEADLINE_HEADER.to_string()),
+            honor_incoming: true,
+        }
+    }
+}
+
+/// The time by which a request must be answered
+///
+/// Attached to requests by `deadline_middleware` and read back with
+/// `Deadline::of`. Outbound calls made for the request use it to bound
+/// their connect and read timeouts and to tell the upstream how long it
+/// has left.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Deadline {
+    expires: Instant,
+    header: Option<String>,
+}
+
+impl Deadline {
+    /// A deadline `timeout` from now, passed upstream in `X-Request-Deadline`
+    pub fn after(timeout: Duration) -> Self {
+        Self {
+            expires: Instant::now() + timeout,
+            header: Some(DEADLINE_HEADER.to_string()),
+        }
+    }
+    
+    /// Pass the deadline upstream in `header` instead (None = not at all)
+    pub fn with_header(mut self, header: Option<&str>) -> Self {
+        self.header = header.map(str::to_string);
+        self
+    }
+    
+    /// Get the deadline attached to `request`, if any
+    pub fn of(request: &Request) -> Option<&Deadline> {
+        request.extensions.get::<Deadline>()
+    }
+    
+    /// Get the instant the deadline passes
+    pub fn expires(&self) -> Instant {
+        self.expires
+    }
+    
+    /// Get the time left, zero once the deadline has passed
+    pub fn remaining(&self) -> Duration {
+        self.expires.saturating_duration_since(Instant::now())
+    }
+    
+    /// Check whether the deadline has passed
+    pub fn is_expired(&self) -> bool {
+        self.remaining().is_zero()
+    }
+    
+    /// Get the name of the header the deadline is passed upstream in
+    pub fn header(&self) -> Option<&str> {
+        self.header.as_deref()
+    }
+    
+    /// Get the header that passes the remaining budget upstream, if one is set
+    pub fn upstream_header(&self) -> Option<(&str, String)> {
+        let header = self.header.as_deref()?;
+        Some((header, self.remaining().as_millis().to_string()))
+    }
+}
+
+/// Deadline middleware - gives each request a time budget for the handler and its upstream calls
+///
+/// The budget is the route's `request_timeout` if it has one and
+/// `config.timeout` otherwise, or less when `honor_incoming` is set and a
+/// proxy in front sent a smaller one in the deadline header. Requests
+/// that arrive with no budget left get `504 Gateway Timeout` without
+/// reaching the handler. A handler that is already running is not
+/// interrupted; the deadline instead bounds the outbound calls it makes.
+pub fn deadline_middleware(
+    config: DeadlineConfig,
+) -> impl Fn(&Request, MiddlewareNext) -> ServerResult<Response> + Send + Sync {
+    move |request, next| {
+        let mut timeout = RouteSettings::of(request)
+            .and_then(|settings| settings.request_timeout)
+            .unwrap_or(config.timeout);
+        if config.honor_incoming {
+            let incoming = config
+                .header
+                .as_deref()
+                .and_then(|header| request.get_header(header))
+                .and_then(|value| value.trim().parse::<u64>().ok());
+            if let Some(millis) = incoming {
+                timeout = timeout.min(Duration::from_millis(millis));
+            }
+        }
+        
+        if timeout.is_zero() {
+            let error = ServerError::Io(io::Error::new(io::ErrorKind::TimedOut, "Request deadline has passed"));
+            return Ok(error.to_response(request));
+        }
+        
+        let mut request = request.clone();
+        request
+            .extensions
+            .insert(Deadline::after(timeout).with_header(config.header.as_deref()));
+        next(&request)
+    }
+}
\ No newline at end of file
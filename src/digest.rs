@@ -0,0 +1,162 @@
+use crate::error::ServerResult;
+use crate::hash::{self, Md5, Sha256};
+use crate::http::{Request, Response, Status};
+use crate::middleware::MiddlewareNext;
+
+/// Which digest headers to add to responses and check on requests
+#[derive(Debug, Clone)]
+pub struct DigestConfig {
+    /// Add `Repr-Digest: sha-256=:...:` (RFC 9530) to responses with a body
+    pub repr_digest: bool,
+    
+    /// Add the legacy `Content-MD5` header to responses with a body
+    pub content_md5: bool,
+    
+    /// Reject requests whose digest headers don't match their body
+    pub verify_requests: bool,
+    
+    /// Reject requests with a body but no digest header we can check
+    pub require_request_digest: bool,
+}
+
+impl Default for DigestConfig {
+    fn default() -> Self {
+        Self {
+            repr_digest: true,
+            content_md5: false,
+            verify_requests: true,
+            require_request_digest: false,
+        }
+    }
+}
+
+impl DigestConfig {
+    /// Create the default configuration: `Repr-Digest` on responses, verification of requests
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Enable or disable `Repr-Digest` on responses
+    pub fn with_repr_digest(mut self, enabled: bool) -> Self {
+        self.repr_digest = enabled;
+        self
+    }
+    
+    /// Enable or disable `Content-MD5` on responses
+    pub fn with_content_md5(mut self, enabled: bool) -> Self {
+        self.content_md5 = enabled;
+        self
+    }
+    
+    /// Enable or disable checking request digests
+    pub fn with_verify_requests(mut self, enabled: bool) -> Self {
+        self.verify_requests = enabled;
+        self
+    }
+    
+    /// Require a checkable digest on every request with a body
+    pub fn with_require_request_digest(mut self, required: bool) -> Self {
+        self.require_request_digest = required;
+        self
+    }
+}
+
+/// Build a `Repr-Digest` value for a body
+pub fn repr_digest_value(body: &[u8]) -> String {
+    format!("sha-256=:{}:", base64::encode(Sha256::digest(body)))
+}
+
+/// Build a `Content-MD5` value for a body
+pub fn content_md5_value(body: &[u8]) -> String {
+    base64::encode(Md5::digest(body))
+}
+
+/// Find the `sha-256` member of a `Repr-Digest`/`Content-Digest` dictionary
+///
+/// Returns `None` when no sha-256 member is present, since other algorithms
+/// are not supported and must be ignored.
+fn sha256_member(value: &str) -> Option<Option<Vec<u8>>> {
+    value.split(',').find_map(|member| {
+        let (algorithm, encoded) = member.trim().split_once('=')?;
+        if !algorithm.trim().eq_ignore_ascii_case("sha-256") {
+            return None;
+        }
+        
+        let encoded = encoded.trim().strip_prefix(':')?.strip_suffix(':')?;
+        Some(base64::decode(encoded).ok())
+    })
+}
+
+/// Check a request's digest headers against its body
+///
+/// `Repr-Digest`, `Content-Digest` (equivalent here as the body is never
+/// content-coded when it reaches handlers) and `Content-MD5` are checked when
+/// present. Returns a description of the problem on mismatch.
+pub fn verify_request_digest(request: &Request, require: bool) -> Result<(), String> {
+    let mut checked = false;
+    
+    for header in ["Repr-Digest", "Content-Digest"] {
+        if let Some(value) = request.get_header(header) {
+            match sha256_member(value) {
+                Some(Some(expected)) => {
+                    if !hash::constant_time_eq(&expected, &Sha256::digest(&request.body)) {
+                        return Err(format!("{} does not match the request body", header));
+                    }
+                    checked = true;
+                }
+                Some(None) => return Err(format!("Malformed {} header", header)),
+                None => {}
+            }
+        }
+    }
+    
+    if let Some(value) = request.get_header("Content-MD5") {
+        let expected = base64::decode(value.trim()).map_err(|_| "Malformed Content-MD5 header".to_string())?;
+        if !hash::constant_time_eq(&expected, &Md5::digest(&request.body)) {
+            return Err("Content-MD5 does not match the request body".to_string());
+        }
+        checked = true;
+    }
+    
+    if require && !checked && !request.body.is_empty() {
+        return Err("A sha-256 Repr-Digest or Content-MD5 header is required".to_string());
+    }
+    
+    Ok(())
+}
+
+/// Add the configured digest headers to a response
+pub fn apply_digest_headers(response: &mut Response, config: &DigestConfig) {
+    if response.body.is_empty() {
+        return;
+    }
+    
+    if config.repr_digest {
+        response.set_header("Repr-Digest", &repr_digest_value(&response.body));
+    }
+    if config.content_md5 {
+        response.set_header("Content-MD5", &content_md5_value(&response.body));
+    }
+}
+
+/// Digest middleware - verifies request digests (400 on mismatch) and adds digests to responses
+///
+/// Add it to the chain before compression middleware, so that it wraps it
+/// and the digest covers the encoded body that is actually sent.
+pub fn digest_middleware(
+    config: DigestConfig,
+) -> impl Fn(&Request, MiddlewareNext) -> ServerResult<Response> + Send + Sync {
+    move |request, next| {
+        if config.verify_requests {
+            if let Err(message) = verify_request_digest(request, config.require_request_digest) {
+                let mut response = Response::new(Status::BadRequest);
+                response.set_body(message.as_bytes());
+                return Ok(response);
+            }
+        }
+        
+        let mut response = next(request)?;
+        apply_digest_headers(&mut response, &config);
+        Ok(response)
+    }
+}
\ No newline at end of file
@@ -1,6 +1,11 @@
+use crate::http::{Request, Response, Status};
+use serde::{Deserialize, Serialize};
 use std::io;
 use thiserror::Error;
 
+/// Header a client's request ID is read from and echoed back in
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
 /// Main error type for the server
 #[derive(Error, Debug)]
 pub enum ServerError {
@@ -10,6 +15,12 @@ pub enum ServerError {
     #[error("HTTP parsing error: {0}")]
     HttpParse(String),
     
+    #[error("Not implemented: {0}")]
+    NotImplemented(String),
+    
+    #[error("Unsupported HTTP version: {0}")]
+    HttpVersion(String),
+    
     #[error("Buffer error: {0}")]
     Buffer(String),
     
@@ -28,8 +39,168 @@ pub enum ServerError {
     #[error("Configuration error: {0}")]
     Config(String),
     
+    #[error("Startup error: {0}")]
+    Startup(String),
+    
     #[error("JSON error: {0}")]
     Json(#[from] serde_json::Error),
 }
 
-pub type ServerResult<T> = Result<T, ServerError>;
\ No newline at end of file
+pub type ServerResult<T> = Result<T, ServerError>;
+
+/// What went wrong, as far as the client is concerned
+///
+/// Each kind has the status and machine-readable code sent for it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// The request could not be parsed or its body was malformed
+    BadRequest,
+    /// A file or other resource the request named does not exist
+    NotFound,
+    /// The server may not access what the request named
+    Forbidden,
+    /// The request did not fit in its buffer
+    TooLarge,
+    /// An upstream service sent something we could not use
+    Upstream,
+    /// The request ran out of time, usually waiting on an upstream
+    Timeout,
+    /// The server is out of resources for now
+    Unavailable,
+    /// The request needs a method or transfer coding the server doesn't implement
+    NotImplemented,
+    /// The request used an HTTP major version other than 1
+    VersionNotSupported,
+    /// Anything else; a bug or misconfiguration on our side
+    Internal,
+}
+
+impl ErrorKind {
+    /// Get the status sent for errors of this kind
+    pub fn status(&self) -> Status {
+        match *self {
+            ErrorKind::BadRequest => Status::BadRequest,
+            ErrorKind::NotFound => Status::NotFound,
+            ErrorKind::Forbidden => Status::Forbidden,
+            ErrorKind::TooLarge => Status::PayloadTooLarge,
+            ErrorKind::Upstream => Status::BadGateway,
+            ErrorKind::Timeout => Status::GatewayTimeout,
+            ErrorKind::Unavailable => Status::ServiceUnavailable,
+            ErrorKind::NotImplemented => Status::NotImplemented,
+            ErrorKind::VersionNotSupported => Status::HttpVersionNotSupported,
+            ErrorKind::Internal => Status::InternalServerError,
+        }
+    }
+    
+    /// Get the machine-readable code sent for errors of this kind
+    pub fn code(&self) -> &'static str {
+        match *self {
+            ErrorKind::BadRequest => "bad_request",
+            ErrorKind::NotFound => "not_found",
+            ErrorKind::Forbidden => "forbidden",
+            ErrorKind::TooLarge => "payload_too_large",
+            ErrorKind::Upstream => "upstream_error",
+            ErrorKind::Timeout => "timeout",
+            ErrorKind::Unavailable => "service_unavailable",
+            ErrorKind::NotImplemented => "not_implemented",
+            ErrorKind::VersionNotSupported => "http_version_not_supported",
+            ErrorKind::Internal => "internal_error",
+        }
+    }
+}
+
+impl ServerError {
+    /// Get the kind of this error
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            ServerError::Io(e) => match e.kind() {
+                io::ErrorKind::NotFound => ErrorKind::NotFound,
+                io::ErrorKind::PermissionDenied => ErrorKind::Forbidden,
+                io::ErrorKind::OutOfMemory => ErrorKind::Unavailable,
+                io::ErrorKind::TimedOut => ErrorKind::Timeout,
+                _ => ErrorKind::Internal,
+            },
+            ServerError::HttpParse(_) | ServerError::Json(_) => ErrorKind::BadRequest,
+            ServerError::NotImplemented(_) => ErrorKind::NotImplemented,
+            ServerError::HttpVersion(_) => ErrorKind::VersionNotSupported,
+            ServerError::Buffer(_) => ErrorKind::TooLarge,
+            ServerError::Memory(_) => ErrorKind::Unavailable,
+            ServerError::Protocol(_) => ErrorKind::Upstream,
+            ServerError::Connection(_) | ServerError::EventLoop(_) | ServerError::Config(_) => ErrorKind::Internal,
+            ServerError::Startup(_) => ErrorKind::Internal,
+        }
+    }
+    
+    /// Get the status sent to the client for this error
+    pub fn status(&self) -> Status {
+        self.kind().status()
+    }
+    
+    /// Build the response sent for this error while handling `request`
+    ///
+    /// The request's `X-Request-Id`, if any, is echoed in the body and headers.
+    pub fn to_response(&self, request: &Request) -> Response {
+        let request_id = request.get_header(REQUEST_ID_HEADER).map(String::as_str);
+        ErrorResponse::from_error(self, request_id).into_response(self.status())
+    }
+}
+
+/// JSON body sent with error responses
+///
+/// ```json
+/// {"code": "bad_request", "message": "HTTP parsing error: Invalid UTF-8", "request_id": "req-42"}
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    pub code: String,
+    pub message: String,
+    pub request_id: Option<String>,
+}
+
+impl ErrorResponse {
+    /// Create an error body with no request ID
+    pub fn new(code: &str, message: &str) -> Self {
+        Self {
+            code: code.to_string(),
+            message: message.to_string(),
+            request_id: None,
+        }
+    }
+    
+    /// Describe `error` for a client
+    ///
+    /// Server-side errors only carry their status text, so internal details
+    /// such as file paths stay in the logs.
+    pub fn from_error(error: &ServerError, request_id: Option<&str>) -> Self {
+        let kind = error.kind();
+        let status = kind.status();
+        let message = if (status as u16) < 500 {
+            error.to_string()
+        } else {
+            status.as_str().to_string()
+        };
+        
+        Self {
+            code: kind.code().to_string(),
+            message,
+            request_id: request_id.map(str::to_string),
+        }
+    }
+    
+    /// Attach the ID of the request that failed
+    pub fn with_request_id(mut self, request_id: &str) -> Self {
+        self.request_id = Some(request_id.to_string());
+        self
+    }
+    
+    /// Build a response with this body and `status`
+    pub fn into_response(self, status: Status) -> Response {
+        let mut response = Response::new(status);
+        response.set_body(&serde_json::to_vec(&self).unwrap_or_default());
+        response.set_header("Content-Type", "application/json");
+        if let Some(request_id) = &self.request_id {
+            response.set_header(REQUEST_ID_HEADER, request_id);
+        }
+        response
+    }
+}
\ No newline at end of file
@@ -1,19 +1,61 @@
-use crate::acceptor::ConnectionAcceptor;
-use crate::connection::{Connection, ConnectionState};
-use crate::error::{ServerError, ServerResult};
-use crate::http::{HttpParser, Request, Response, Status};
+use crate::acceptor::{self, ConnectionAcceptor, WorkerLoad};
+use crate::acl::{acl_middleware, Acl};
+use crate::capacity;
+use crate::config::{RouteConfig, ServerConfig};
+use crate::connection::{Connection, ConnectionState, WriteStatus};
+use crate::error::{ErrorResponse, ServerError, ServerResult};
+use crate::header_name::HeaderName;
+use crate::http::{Request, Response, Status};
+use crate::maintenance::MaintenanceMode;
+use crate::metrics::{EventLoopMetrics, MetricsCollector};
+use crate::profiler;
+use crate::protocol_upgrade::{StreamedBody, Upgrade};
+use crate::timeline::Phase;
+use crate::timer_wheel::TimerWheel;
 use std::collections::HashMap;
-use std::io::{self, ErrorKind, Write};
+use std::io::{self, ErrorKind};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use std::time::Instant;
+use std::thread;
+use std::time::{Duration, Instant};
 
 #[cfg(target_os = "linux")]
-use libc::{EPOLLERR, EPOLLET, EPOLLIN, EPOLLOUT, EPOLLRDHUP};
+use std::os::unix::io::{AsRawFd, RawFd};
+#[cfg(target_os = "linux")]
+use libc::{EPOLLERR, EPOLLET, EPOLLHUP, EPOLLIN, EPOLLOUT, EPOLLRDHUP};
 
 #[cfg(target_os = "macos")]
-use std::os::unix::io::AsRawFd;
+use std::os::unix::io::{AsRawFd, RawFd};
 #[cfg(target_os = "macos")]
-use libc::{kqueue, kevent, timespec, EVFILT_READ, EVFILT_WRITE, EV_ADD, EV_DELETE, EV_EOF, EV_ERROR};
+use libc::{
+    kqueue, kevent, timespec, EVFILT_READ, EVFILT_WRITE, EV_ADD, EV_CLEAR, EV_DELETE, EV_DISABLE, EV_ENABLE, EV_EOF,
+    EV_ERROR,
+};
+
+/// Failed polls in a row after which the poller is replaced
+const MAX_POLL_FAILURES: u32 = 3;
+
+/// Longest a poll blocks, which bounds how long a drain or stop goes unnoticed
+const MAX_POLL_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// How long accepting pauses after running out of file descriptors
+const FD_EXHAUSTION_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Connections accepted from a listener per iteration to begin with, and
+/// the fewest the batch shrinks back to
+const MIN_ACCEPT_BATCH: usize = 8;
+
+/// Polls in a row that fill the poller's buffer before it is doubled
+const FULL_POLLS_BEFORE_GROWTH: u32 = 3;
+
+/// Poller token of the first listener; the others count down from it
+const LISTENER_TOKEN: usize = usize::MAX;
+
+/// How finely connection deadlines are told apart
+const TIMER_TICK: Duration = Duration::from_millis(1);
+
+/// Ticks in a turn of the timer wheel, so deadlines within a few seconds land in slots of their own
+const TIMER_SLOTS: usize = 4096;
 
 /// An abstraction for platform-specific event polling
 #[cfg(target_os = "linux")]
@@ -44,16 +86,72 @@ pub struct EventPoller {
     max_events: usize,
 }
 
+/// What a failed poller call says about the connection or poller involved
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PollerFault {
+    /// The descriptor is closed, so whatever used it is gone (EBADF)
+    ClosedDescriptor,
+    /// The descriptor isn't registered, e.g. after a racy close and reopen (ENOENT)
+    NotRegistered,
+    /// The descriptor is registered already (EEXIST)
+    AlreadyRegistered,
+    /// The kernel is out of memory or watch slots (ENOMEM, ENOSPC)
+    Exhausted,
+    /// A signal interrupted the call (EINTR)
+    Interrupted,
+    /// Anything else
+    Other,
+}
+
+impl PollerFault {
+    /// Classify an error returned by an `EventPoller` call
+    pub fn classify(error: &ServerError) -> Self {
+        let errno = match error {
+            ServerError::Io(e) => e.raw_os_error(),
+            _ => None,
+        };
+        
+        match errno {
+            Some(libc::EBADF) => PollerFault::ClosedDescriptor,
+            Some(libc::ENOENT) => PollerFault::NotRegistered,
+            Some(libc::EEXIST) => PollerFault::AlreadyRegistered,
+            Some(libc::ENOMEM) | Some(libc::ENOSPC) => PollerFault::Exhausted,
+            Some(libc::EINTR) => PollerFault::Interrupted,
+            _ => PollerFault::Other,
+        }
+    }
+    
+    /// Get the name the fault is counted under
+    pub fn name(self) -> &'static str {
+        match self {
+            PollerFault::ClosedDescriptor => "closed_descriptor",
+            PollerFault::NotRegistered => "not_registered",
+            PollerFault::AlreadyRegistered => "already_registered",
+            PollerFault::Exhausted => "exhausted",
+            PollerFault::Interrupted => "interrupted",
+            PollerFault::Other => "other",
+        }
+    }
+}
+
+impl EventPoller {
+    /// Get the most events one poll returns
+    pub fn max_events(&self) -> usize {
+        self.max_events
+    }
+    
+    /// Let each poll return up to `max_events` events; the buffer never shrinks
+    pub fn grow(&mut self, max_events: usize) {
+        self.max_events = self.max_events.max(max_events);
+    }
+}
+
 // Linux implementation
 #[cfg(target_os = "linux")]
 impl EventPoller {
     /// Create a new event poller
     pub fn new(max_events: usize) -> ServerResult<Self> {
-        let epoll_fd = unsafe { libc::epoll_create1(0) };
-        if epoll_fd < 0 {
-            return Err(ServerError::Io(io::Error::last_os_error()));
-        }
-        
+        let epoll_fd = Self::create_epoll()?;
         let events = Vec::with_capacity(max_events);
         
         Ok(Self {
@@ -67,7 +165,7 @@ impl EventPoller {
     pub fn register(&mut self, connection: &Connection) -> ServerResult<()> {
         let fd = connection.stream().as_raw_fd();
         let mut event = libc::epoll_event {
-            events: (EPOLLIN | EPOLLOUT | EPOLLET | EPOLLRDHUP) as u32,
+            events: (EPOLLIN | EPOLLET | EPOLLRDHUP) as u32,
             u64: connection.id() as u64,
         };
         
@@ -87,6 +185,61 @@ impl EventPoller {
         Ok(())
     }
     
+    /// Register a listening socket, reported under `token` when connections are waiting
+    ///
+    /// Every poller a listener is shared with is woken for each new
+    /// connection, so an idle loop doesn't sit out its timeout first.
+    pub fn register_listener(&mut self, fd: RawFd, token: usize) -> ServerResult<()> {
+        let mut event = libc::epoll_event {
+            events: (EPOLLIN | EPOLLET) as u32,
+            u64: token as u64,
+        };
+        
+        let ret = unsafe {
+            libc::epoll_ctl(
+                self.epoll_fd,
+                libc::EPOLL_CTL_ADD,
+                fd,
+                &mut event as *mut _,
+            )
+        };
+        
+        if ret < 0 {
+            return Err(ServerError::Io(io::Error::last_os_error()));
+        }
+        
+        Ok(())
+    }
+    
+    /// Enable or disable writable notifications for a connection
+    pub fn set_write_interest(&mut self, connection: &Connection, enabled: bool) -> ServerResult<()> {
+        let fd = connection.stream().as_raw_fd();
+        let mut events = EPOLLIN | EPOLLET | EPOLLRDHUP;
+        if enabled {
+            events |= EPOLLOUT;
+        }
+        
+        let mut event = libc::epoll_event {
+            events: events as u32,
+            u64: connection.id() as u64,
+        };
+        
+        let ret = unsafe {
+            libc::epoll_ctl(
+                self.epoll_fd,
+                libc::EPOLL_CTL_MOD,
+                fd,
+                &mut event as *mut _,
+            )
+        };
+        
+        if ret < 0 {
+            return Err(ServerError::Io(io::Error::last_os_error()));
+        }
+        
+        Ok(())
+    }
+    
     /// Deregister a connection from the poller
     pub fn deregister(&mut self, connection: &Connection) -> ServerResult<()> {
         let fd = connection.stream().as_raw_fd();
@@ -108,8 +261,10 @@ impl EventPoller {
     
     /// Poll for events with a timeout
     pub fn poll(&mut self, timeout_ms: i32) -> ServerResult<Vec<(usize, u32)>> {
-        self.events.clear();
-        self.events.resize(self.max_events, libc::epoll_event { events: 0, u64: 0 });
+        // The kernel fills in the events it returns, so the buffer is only set up when it grows
+        if self.events.len() < self.max_events {
+            self.events.resize(self.max_events, libc::epoll_event { events: 0, u64: 0 });
+        }
         
         let num_events = unsafe {
             libc::epoll_wait(
@@ -136,6 +291,36 @@ impl EventPoller {
         
         Ok(result)
     }
+    
+    /// Check whether the poller's own descriptor is still open
+    pub fn is_healthy(&self) -> bool {
+        unsafe { libc::fcntl(self.epoll_fd, libc::F_GETFD) >= 0 }
+    }
+    
+    /// Swap in a new epoll instance, leaving no connection registered
+    ///
+    /// The old descriptor is only closed if it is still open, since the
+    /// number of a closed one may already belong to something else.
+    pub fn replace(&mut self) -> ServerResult<()> {
+        let healthy = self.is_healthy();
+        let epoll_fd = Self::create_epoll()?;
+        if healthy {
+            unsafe {
+                libc::close(self.epoll_fd);
+            }
+        }
+        self.epoll_fd = epoll_fd;
+        Ok(())
+    }
+    
+    /// Create an epoll instance that isn't inherited by processes the server spawns
+    fn create_epoll() -> ServerResult<i32> {
+        let epoll_fd = unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) };
+        if epoll_fd < 0 {
+            return Err(ServerError::Io(io::Error::last_os_error()));
+        }
+        Ok(epoll_fd)
+    }
 }
 
 // macOS implementation
@@ -143,11 +328,7 @@ impl EventPoller {
 impl EventPoller {
     /// Create a new event poller using kqueue (macOS)
     pub fn new(max_events: usize) -> ServerResult<Self> {
-        let kqueue_fd = unsafe { kqueue() };
-        if kqueue_fd < 0 {
-            return Err(ServerError::Io(io::Error::last_os_error()));
-        }
-        
+        let kqueue_fd = Self::create_kqueue()?;
         let events = Vec::with_capacity(max_events);
         
         Ok(Self {
@@ -173,11 +354,11 @@ impl EventPoller {
             udata: conn_id as *mut libc::c_void,
         };
         
-        // Set up write event
+        // Set up write event, disabled until there is data waiting to be written
         let write_event = libc::kevent {
             ident: fd as usize,
             filter: EVFILT_WRITE as i16,
-            flags: EV_ADD as u16,
+            flags: (EV_ADD | EV_DISABLE) as u16,
             fflags: 0,
             data: 0,
             udata: conn_id as *mut libc::c_void,
@@ -206,6 +387,70 @@ impl EventPoller {
         Ok(())
     }
     
+    /// Register a listening socket, reported under `token` when connections are waiting
+    ///
+    /// Every poller a listener is shared with is woken for each new
+    /// connection, so an idle loop doesn't sit out its timeout first.
+    pub fn register_listener(&mut self, fd: RawFd, token: usize) -> ServerResult<()> {
+        let read_event = libc::kevent {
+            ident: fd as usize,
+            filter: EVFILT_READ as i16,
+            flags: (EV_ADD | EV_CLEAR) as u16,
+            fflags: 0,
+            data: 0,
+            udata: token as *mut libc::c_void,
+        };
+        
+        let ret = unsafe {
+            kevent(
+                self.kqueue_fd,
+                &read_event,
+                1,
+                std::ptr::null_mut(),
+                0,
+                std::ptr::null(),
+            )
+        };
+        
+        if ret < 0 {
+            return Err(ServerError::Io(io::Error::last_os_error()));
+        }
+        
+        Ok(())
+    }
+    
+    /// Enable or disable writable notifications for a connection
+    pub fn set_write_interest(&mut self, connection: &Connection, enabled: bool) -> ServerResult<()> {
+        let fd = connection.stream().as_raw_fd();
+        let flags = if enabled { EV_ENABLE } else { EV_DISABLE };
+        
+        let write_event = libc::kevent {
+            ident: fd as usize,
+            filter: EVFILT_WRITE as i16,
+            flags: flags as u16,
+            fflags: 0,
+            data: 0,
+            udata: connection.id() as *mut libc::c_void,
+        };
+        
+        let ret = unsafe {
+            kevent(
+                self.kqueue_fd,
+                &write_event,
+                1,
+                std::ptr::null_mut(),
+                0,
+                std::ptr::null(),
+            )
+        };
+        
+        if ret < 0 {
+            return Err(ServerError::Io(io::Error::last_os_error()));
+        }
+        
+        Ok(())
+    }
+    
     /// Deregister a connection from the poller
     pub fn deregister(&mut self, connection: &Connection) -> ServerResult<()> {
         let fd = connection.stream().as_raw_fd();
@@ -260,8 +505,10 @@ impl EventPoller {
     
     /// Poll for events with a timeout
     pub fn poll(&mut self, timeout_ms: i32) -> ServerResult<Vec<(usize, u32)>> {
-        self.events.clear();
-        self.events.resize(self.max_events, unsafe { std::mem::zeroed() });
+        // The kernel fills in the events it returns, so the buffer is only set up when it grows
+        if self.events.len() < self.max_events {
+            self.events.resize(self.max_events, unsafe { std::mem::zeroed() });
+        }
         
         // Set up timeout
         let timeout = timespec {
@@ -321,6 +568,45 @@ impl EventPoller {
         
         Ok(result)
     }
+    
+    /// Check whether the poller's own descriptor is still open
+    pub fn is_healthy(&self) -> bool {
+        unsafe { libc::fcntl(self.kqueue_fd, libc::F_GETFD) >= 0 }
+    }
+    
+    /// Swap in a new kqueue, leaving no connection registered
+    ///
+    /// The old descriptor is only closed if it is still open, since the
+    /// number of a closed one may already belong to something else.
+    pub fn replace(&mut self) -> ServerResult<()> {
+        let healthy = self.is_healthy();
+        let kqueue_fd = Self::create_kqueue()?;
+        if healthy {
+            unsafe {
+                libc::close(self.kqueue_fd);
+            }
+        }
+        self.kqueue_fd = kqueue_fd;
+        self.conn_map.clear();
+        Ok(())
+    }
+    
+    /// Create a kqueue that isn't inherited by processes the server spawns
+    ///
+    /// kqueue takes no flags, so close-on-exec is set right after.
+    fn create_kqueue() -> ServerResult<i32> {
+        let kqueue_fd = unsafe { kqueue() };
+        if kqueue_fd < 0 {
+            return Err(ServerError::Io(io::Error::last_os_error()));
+        }
+        if let Err(e) = crate::upgrade::set_cloexec(kqueue_fd, true) {
+            unsafe {
+                libc::close(kqueue_fd);
+            }
+            return Err(e);
+        }
+        Ok(kqueue_fd)
+    }
 }
 
 // Windows implementation (stub)
@@ -335,6 +621,14 @@ impl EventPoller {
         unimplemented!("Windows support not yet implemented");
     }
     
+    pub fn register_listener(&mut self, _fd: usize, _token: usize) -> ServerResult<()> {
+        unimplemented!("Windows support not yet implemented");
+    }
+    
+    pub fn set_write_interest(&mut self, _connection: &Connection, _enabled: bool) -> ServerResult<()> {
+        unimplemented!("Windows support not yet implemented");
+    }
+    
     pub fn deregister(&mut self, _connection: &Connection) -> ServerResult<()> {
         unimplemented!("Windows support not yet implemented");
     }
@@ -342,6 +636,14 @@ impl EventPoller {
     pub fn poll(&mut self, _timeout_ms: i32) -> ServerResult<Vec<(usize, u32)>> {
         unimplemented!("Windows support not yet implemented");
     }
+    
+    pub fn is_healthy(&self) -> bool {
+        true
+    }
+    
+    pub fn replace(&mut self) -> ServerResult<()> {
+        unimplemented!("Windows support not yet implemented");
+    }
 }
 
 // Fallback implementation for other platforms (stubs)
@@ -355,6 +657,14 @@ impl EventPoller {
         Err(ServerError::EventLoop("Unsupported platform".to_string()))
     }
     
+    pub fn register_listener(&mut self, _fd: i32, _token: usize) -> ServerResult<()> {
+        Err(ServerError::EventLoop("Unsupported platform".to_string()))
+    }
+    
+    pub fn set_write_interest(&mut self, _connection: &Connection, _enabled: bool) -> ServerResult<()> {
+        Err(ServerError::EventLoop("Unsupported platform".to_string()))
+    }
+    
     pub fn deregister(&mut self, _connection: &Connection) -> ServerResult<()> {
         Err(ServerError::EventLoop("Unsupported platform".to_string()))
     }
@@ -362,6 +672,14 @@ impl EventPoller {
     pub fn poll(&mut self, _timeout_ms: i32) -> ServerResult<Vec<(usize, u32)>> {
         Err(ServerError::EventLoop("Unsupported platform".to_string()))
     }
+    
+    pub fn is_healthy(&self) -> bool {
+        true
+    }
+    
+    pub fn replace(&mut self) -> ServerResult<()> {
+        Err(ServerError::EventLoop("Unsupported platform".to_string()))
+    }
 }
 
 impl Drop for EventPoller {
@@ -383,48 +701,142 @@ pub struct EventLoop {
     thread_id: u32,
     poller: EventPoller,
     connections: HashMap<usize, Connection>,
-    acceptor: Arc<ConnectionAcceptor>,
-    parsers: HashMap<usize, HttpParser>,
+    /// Protocol switches waiting for their 101 response to be sent
+    upgrades: HashMap<usize, Upgrade>,
+    acceptors: Vec<Arc<ConnectionAcceptor>>,
     running: bool,
-    router: Option<Arc<crate::router::Router>>,
+    router: Option<Arc<crate::router::RouteTable>>,
     middleware_chain: Option<Arc<crate::middleware::MiddlewareChain>>,
+    config: ServerConfig,
+    /// Per-route settings merged from `config`
+    routes: RouteConfig,
+    drain_signal: Option<Arc<AtomicBool>>,
+    worker_load: Option<Arc<WorkerLoad>>,
+    metrics: Option<Arc<MetricsCollector>>,
+    loop_metrics: Option<EventLoopMetrics>,
+    maintenance: Arc<MaintenanceMode>,
+    acl: Option<Arc<Acl>>,
+    /// Polls that failed since the last one that worked
+    poll_failures: u32,
+    /// Polls in a row that returned as many events as the poller could hold
+    full_polls: u32,
+    /// Whether a listener may still have connections waiting after the last accept batch
+    accept_backlog: bool,
+    /// When accepting may resume after the process ran out of file descriptors
+    accept_paused_until: Option<Instant>,
+    /// Connections accepted from a listener per iteration, adapted to how many wait
+    accept_batch: usize,
+    /// Connections with batched responses waiting to be written, and since when
+    batched: HashMap<usize, Instant>,
+    /// When each connection's idle timeout, lingering close or write deadline is next due
+    timers: TimerWheel,
 }
 
 impl EventLoop {
     /// Create a new event loop
     pub fn new(thread_id: u32, acceptor: Arc<ConnectionAcceptor>) -> Self {
-        let poller = EventPoller::new(1024).expect("Failed to create event poller");
+        Self::with_config(thread_id, acceptor, ServerConfig::default())
+    }
+    
+    /// Create a new event loop using the given server configuration
+    pub fn with_config(thread_id: u32, acceptor: Arc<ConnectionAcceptor>, config: ServerConfig) -> Self {
+        Self::with_acceptors(thread_id, vec![acceptor], config)
+    }
+    
+    /// Create a new event loop accepting connections from several listeners
+    pub fn with_acceptors(thread_id: u32, acceptors: Vec<Arc<ConnectionAcceptor>>, config: ServerConfig) -> Self {
+        let max_events = config
+            .poll_events
+            .unwrap_or_else(|| capacity::poll_events(config.max_connections_per_worker, acceptors.len()));
+        let poller = EventPoller::new(max_events).expect("Failed to create event poller");
+        let maintenance = Arc::new(MaintenanceMode::new(config.maintenance.clone()));
+        let routes = RouteConfig::new(&config);
         
         Self {
             thread_id,
             poller,
             connections: HashMap::new(),
-            acceptor,
-            parsers: HashMap::new(),
+            upgrades: HashMap::new(),
+            acceptors,
             running: false,
             router: None,
             middleware_chain: None,
+            config,
+            routes,
+            drain_signal: None,
+            worker_load: None,
+            metrics: None,
+            loop_metrics: None,
+            maintenance,
+            acl: None,
+            poll_failures: 0,
+            full_polls: 0,
+            accept_backlog: false,
+            accept_paused_until: None,
+            accept_batch: MIN_ACCEPT_BATCH,
+            batched: HashMap::new(),
+            timers: TimerWheel::new(TIMER_TICK, TIMER_SLOTS),
         }
     }
     
     /// Run the event loop
     pub fn run(&mut self) -> ServerResult<()> {
         self.running = true;
+        profiler::register_thread(&format!("event_loop-{}", self.thread_id));
+        self.watch_listeners()?;
         
         while self.running {
+            let iteration_start = Instant::now();
+            
+            // When draining, stop accepting and exit once every connection is done
+            let draining = self.is_draining();
+            if draining {
+                self.close_idle_connections()?;
+            }
+            if draining && self.connections.is_empty() {
+                break;
+            }
+            
             // Accept new connections
-            self.accept_connections()?;
+            if !draining {
+                let _scope = profiler::scope("accept");
+                self.accept_connections()?;
+            }
             
             // Poll for events
-            let events = self.poller.poll(100)?;
+            let timeout_ms = self.poll_timeout_ms();
+            let poll_start = Instant::now();
+            let events = {
+                let _scope = profiler::scope("poll");
+                self.poll_events(timeout_ms)?
+            };
+            let poll_wait = poll_start.elapsed();
+            let event_count = events.len();
             
             // Process events
-            for (conn_id, event_bits) in events {
-                self.process_connection_event(conn_id, event_bits)?;
+            for (token, event_bits) in events {
+                if !self.is_listener(token) {
+                    self.process_connection_event(token, event_bits)?;
+                    self.schedule_timeout(token);
+                } else if !draining {
+                    let _scope = profiler::scope("accept");
+                    self.accept_connections()?;
+                }
             }
             
+            // Write batches whose delay is up
+            self.flush_batched_writes()?;
+            
+            // Resume writes that were paused by bandwidth limits
+            self.flush_throttled_writes()?;
+            
             // Check for timed out connections
-            self.check_timeouts()?;
+            {
+                let _scope = profiler::scope("timeouts");
+                self.check_timeouts()?;
+            }
+            
+            self.record_loop_metrics(iteration_start.elapsed(), poll_wait, event_count);
         }
         
         Ok(())
@@ -435,9 +847,77 @@ impl EventLoop {
         self.running = false;
     }
     
+    /// Set a shared flag that, once raised, makes the loop drain and exit
+    pub fn set_drain_signal(&mut self, drain_signal: Arc<AtomicBool>) {
+        self.drain_signal = Some(drain_signal);
+    }
+    
+    /// Share per-worker load tracking so busy workers can back off from accepting
+    pub fn set_worker_load(&mut self, worker_load: Arc<WorkerLoad>) {
+        self.worker_load = Some(worker_load);
+    }
+    
+    /// Record request metrics, such as per-phase latencies, into a collector
+    pub fn set_metrics(&mut self, metrics: Arc<MetricsCollector>) {
+        self.loop_metrics = Some(EventLoopMetrics::new(&metrics.registry(), self.thread_id as usize));
+        self.metrics = Some(metrics);
+    }
+    
+    /// Record how long an iteration took and how much output is queued
+    fn record_loop_metrics(&self, iteration: Duration, poll_wait: Duration, events: usize) {
+        let loop_metrics = match &self.loop_metrics {
+            Some(loop_metrics) => loop_metrics,
+            None => return,
+        };
+        
+        loop_metrics.record_iteration(iteration, poll_wait, events);
+        
+        let (pending_connections, pending_bytes) = self.connections
+            .values()
+            .filter(|conn| conn.has_pending_writes())
+            .fold((0, 0), |(count, bytes), conn| (count + 1, bytes + conn.pending_write_bytes()));
+        loop_metrics.set_queue_depth(self.connections.len(), pending_connections, pending_bytes);
+    }
+    
+    /// Check whether the loop has been asked to drain
+    fn is_draining(&self) -> bool {
+        self.drain_signal
+            .as_ref()
+            .map(|signal| signal.load(Ordering::Relaxed))
+            .unwrap_or(false)
+    }
+    
+    /// Share a maintenance switch, so that one toggle covers every event loop
+    pub fn set_maintenance(&mut self, maintenance: Arc<MaintenanceMode>) {
+        self.maintenance = maintenance;
+    }
+    
+    /// Get the maintenance switch this event loop checks
+    pub fn maintenance(&self) -> Arc<MaintenanceMode> {
+        self.maintenance.clone()
+    }
+    
+    /// Check every request against `acl`
+    ///
+    /// With a middleware chain the rules run after its middleware, so the
+    /// identities auth and tenant middleware attach count; a router gets
+    /// requests only once they pass.
+    pub fn set_acl(&mut self, acl: Arc<Acl>) {
+        self.acl = Some(acl);
+    }
+    
     /// Set the router for handling requests
     pub fn set_router(&mut self, router: Arc<crate::router::Router>) {
-        self.router = Some(router);
+        let router = Arc::try_unwrap(router).unwrap_or_else(|shared| (*shared).clone());
+        self.router = Some(Arc::new(crate::router::RouteTable::new(router)));
+    }
+    
+    /// Set a route table that can be changed while the loop is running
+    ///
+    /// Share the same table between every event loop so that a route added
+    /// or removed through it takes effect on all workers.
+    pub fn set_route_table(&mut self, table: Arc<crate::router::RouteTable>) {
+        self.router = Some(table);
     }
     
     /// Set the middleware chain for handling requests
@@ -445,59 +925,256 @@ impl EventLoop {
         self.middleware_chain = Some(middleware_chain);
     }
     
-    /// Accept new connections
-    fn accept_connections(&mut self) -> ServerResult<()> {
-        // Try to accept multiple connections in a batch
-        for _ in 0..10 {
-            match self.acceptor.accept() {
-                Ok(conn) => {
-                    let conn_id = conn.id();
-                    
-                    // Register with the poller
-                    self.poller.register(&conn)?;
-                    
-                    // Create a parser for this connection
-                    let parser = HttpParser::new();
-                    
-                    // Store the connection and parser
-                    self.connections.insert(conn_id, conn);
-                    self.parsers.insert(conn_id, parser);
-                }
-                Err(ref e) if e.kind() == ErrorKind::WouldBlock => {
-                    // No more connections to accept right now
-                    break;
-                }
-                Err(e) => {
-                    return Err(ServerError::Io(e));
-                }
+    /// Compute how long to wait for events
+    ///
+    /// The poll doesn't block while accepts are left over, unless accepting
+    /// is paused, and otherwise wakes for the earliest connection deadline
+    /// in the timer wheel, throttled write or end of an accept pause due.
+    fn poll_timeout_ms(&mut self) -> i32 {
+        let now = Instant::now();
+        let mut wait = MAX_POLL_TIMEOUT;
+        match self.accept_paused_until {
+            Some(until) => wait = wait.min(until.saturating_duration_since(now)),
+            None if self.accept_backlog => return 0,
+            None => {}
+        }
+        
+        for conn in self.connections.values_mut() {
+            if conn.is_throttled() && conn.has_pending_writes() {
+                wait = wait.min(conn.throttle_delay().max(Duration::from_millis(1)));
             }
         }
+        if let Some(deadline) = self.timers.next_expiry(wait) {
+            wait = wait.min(deadline.saturating_duration_since(now));
+        }
+        // Round up, so a timeout isn't polled for over and over in its last millisecond
+        let mut timeout_ms = wait.as_micros().div_ceil(1000) as i32;
+        
+        // A batch due in under a millisecond is waited for by polling without blocking
+        if let (Some(batching), Some(oldest)) = (&self.config.write_batching, self.batched.values().min()) {
+            let remaining = (*oldest + batching.max_delay).saturating_duration_since(Instant::now());
+            timeout_ms = timeout_ms.min(remaining.as_millis() as i32);
+        }
+        
+        timeout_ms
+    }
+    
+    /// Finish a response that was queued to share a write with later ones
+    ///
+    /// Its timeline ends when it is queued. The connection goes on to any
+    /// request pipelined behind it while the batch waits to be written.
+    fn finish_batched_response(&mut self, conn_id: usize) -> ServerResult<()> {
+        let connection = match self.connections.get_mut(&conn_id) {
+            Some(conn) => conn,
+            None => return Ok(()),
+        };
+        
+        connection.timeline_mut().mark(Phase::Completed);
+        let timeline = connection.take_timeline();
+        if let Some(metrics) = &self.metrics {
+            metrics.record_request_timeline(&timeline);
+            metrics.requests().record_batched();
+        }
+        self.batched.entry(conn_id).or_insert_with(Instant::now);
         
+        if !connection.transition_to(ConnectionState::Reading) {
+            return self.close_connection(conn_id);
+        }
         Ok(())
     }
     
-    /// Process an event for a connection
-    fn process_connection_event(&mut self, conn_id: usize, event_bits: u32) -> ServerResult<()> {
-        // Define constants for our platform-agnostic event types
-        const EVENT_READ: u32 = 0x001;  // EPOLLIN equivalent
-        const EVENT_WRITE: u32 = 0x004; // EPOLLOUT equivalent
-        const EVENT_HUP: u32 = 0x008;   // EPOLLRDHUP equivalent
-        const EVENT_ERR: u32 = 0x010;   // EPOLLERR equivalent
+    /// Write the batched responses of connections whose batch delay is up
+    fn flush_batched_writes(&mut self) -> ServerResult<()> {
+        let max_delay = match &self.config.write_batching {
+            Some(batching) if !self.batched.is_empty() => batching.max_delay,
+            _ => return Ok(()),
+        };
         
-        #[cfg(target_os = "linux")]
-        {
-            let readable = (event_bits & EPOLLIN as u32) != 0;
-            let writable = (event_bits & EPOLLOUT as u32) != 0;
-            let error = (event_bits & (EPOLLERR | EPOLLRDHUP) as u32) != 0;
-            
-            // Handle error condition
+        let now = Instant::now();
+        let due: Vec<usize> = self.batched
+            .iter()
+            .filter(|(_, since)| now.duration_since(**since) >= max_delay)
+            .map(|(id, _)| *id)
+            .collect();
+        
+        for conn_id in due {
+            self.batched.remove(&conn_id);
+            self.handle_write(conn_id)?;
+            self.schedule_timeout(conn_id);
+        }
+        
+        Ok(())
+    }
+    
+    /// Retry pending writes on throttled connections
+    ///
+    /// Edge-triggered polling won't report a throttled socket as writable again,
+    /// so connections paused by their token bucket are revisited every iteration.
+    fn flush_throttled_writes(&mut self) -> ServerResult<()> {
+        let pending: Vec<usize> = self.connections
+            .iter()
+            .filter(|(_, conn)| {
+                conn.is_throttled() && conn.has_pending_writes()
+            })
+            .map(|(id, _)| *id)
+            .collect();
+        
+        for conn_id in pending {
+            self.handle_write(conn_id)?;
+            self.schedule_timeout(conn_id);
+        }
+        
+        Ok(())
+    }
+    
+    /// Accept new connections
+    fn accept_connections(&mut self) -> ServerResult<()> {
+        // Connections keep waiting in the backlog until the pause is over
+        if let Some(until) = self.accept_paused_until {
+            if Instant::now() < until {
+                return Ok(());
+            }
+            self.accept_paused_until = None;
+        }
+        self.accept_backlog = false;
+        
+        // At the cap, connections wait in the backlog until some close
+        if self.at_connection_cap() {
+            return Ok(());
+        }
+        
+        // Leave new connections to less loaded workers
+        if let Some(worker_load) = &self.worker_load {
+            if worker_load.should_skip_accept(self.thread_id as usize) {
+                return Ok(());
+            }
+        }
+        
+        for index in 0..self.acceptors.len() {
+            self.accept_from(index)?;
+        }
+        
+        Ok(())
+    }
+    
+    /// Check whether this worker holds as many connections as it may
+    fn at_connection_cap(&self) -> bool {
+        self.config.max_connections_per_worker.is_some_and(|max| self.connections.len() >= max)
+    }
+    
+    /// Have the poller report connections waiting on any listener
+    fn watch_listeners(&mut self) -> ServerResult<()> {
+        #[cfg(any(target_os = "linux", target_os = "macos"))]
+        for (index, acceptor) in self.acceptors.iter().enumerate() {
+            self.poller.register_listener(acceptor.as_raw_fd(), LISTENER_TOKEN - index)?;
+        }
+        Ok(())
+    }
+    
+    /// Check whether a poller token stands for a listener rather than a connection
+    fn is_listener(&self, token: usize) -> bool {
+        LISTENER_TOKEN - token < self.acceptors.len()
+    }
+    
+    /// Accept a batch of new connections from one listener
+    ///
+    /// The batch doubles, up to `max_accept_batch`, each time connections
+    /// are still waiting once it is used up, and halves when the listener
+    /// runs dry with most of it unused. A large batch keeps up with a burst
+    /// of connections; a small one keeps accepting from delaying the
+    /// connections already open.
+    fn accept_from(&mut self, index: usize) -> ServerResult<()> {
+        let max_batch = self.config.max_accept_batch.max(1);
+        let batch = self.accept_batch.clamp(MIN_ACCEPT_BATCH.min(max_batch), max_batch);
+        for accepted in 0..batch {
+            if self.at_connection_cap() {
+                return Ok(());
+            }
+            match self.acceptors[index].accept() {
+                Ok(mut conn) => {
+                    let conn_id = conn.id();
+                    conn.set_rate_limit(self.config.connection_bandwidth_limit);
+                    conn.set_write_deadline(self.config.write_timeout);
+                    conn.set_max_buffer_size(self.config.max_buffer_size);
+                    if self.config.ring_buffers {
+                        conn.use_ring_buffer();
+                    }
+                    
+                    // Store the connection
+                    self.connections.insert(conn_id, conn);
+                    self.schedule_timeout(conn_id);
+                    
+                    if let Some(worker_load) = &self.worker_load {
+                        worker_load.connection_opened(self.thread_id as usize);
+                    }
+                    
+                    // Register with the poller
+                    if let Err(e) = self.poller.register(&self.connections[&conn_id]) {
+                        self.recover_connection(conn_id, "register", e)?;
+                    }
+                }
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => {
+                    // No more connections to accept right now
+                    if accepted < batch / 4 {
+                        self.accept_batch = (batch / 2).max(MIN_ACCEPT_BATCH);
+                    }
+                    return Ok(());
+                }
+                Err(ref e) if acceptor::is_fd_exhaustion(e) => {
+                    self.pause_accepting(e);
+                    return Ok(());
+                }
+                Err(e) => {
+                    return Err(ServerError::Io(e));
+                }
+            }
+        }
+        
+        // Connections left waiting won't make the listener report again
+        self.accept_backlog = true;
+        self.accept_batch = (batch * 2).min(max_batch);
+        Ok(())
+    }
+    
+    /// Stop accepting for a while after running out of file descriptors
+    ///
+    /// Retrying straight away would fail the same way and spin the loop.
+    /// The acceptor has already shed one waiting connection; the others stay
+    /// in the backlog until the pause ends, when they are accepted if
+    /// descriptors have been freed by then.
+    fn pause_accepting(&mut self, error: &std::io::Error) {
+        println!("Worker {} pausing accepts: {}", self.thread_id, error);
+        self.accept_paused_until = Some(Instant::now() + FD_EXHAUSTION_BACKOFF);
+        self.accept_backlog = true;
+        if let Some(metrics) = &self.metrics {
+            metrics.registry().counter("connections.fd_exhausted").increment(1);
+        }
+    }
+    
+    /// Process an event for a connection
+    fn process_connection_event(&mut self, conn_id: usize, event_bits: u32) -> ServerResult<()> {
+        // Define constants for our platform-agnostic event types
+        const EVENT_READ: u32 = 0x001;  // EPOLLIN equivalent
+        const EVENT_WRITE: u32 = 0x004; // EPOLLOUT equivalent
+        const EVENT_HUP: u32 = 0x008;   // EPOLLRDHUP equivalent
+        const EVENT_ERR: u32 = 0x010;   // EPOLLERR equivalent
+        
+        #[cfg(target_os = "linux")]
+        {
+            let readable = (event_bits & EPOLLIN as u32) != 0;
+            let writable = (event_bits & EPOLLOUT as u32) != 0;
+            let error = (event_bits & EPOLLERR as u32) != 0;
+            let hangup = (event_bits & (EPOLLRDHUP | EPOLLHUP) as u32) != 0;
+            
+            // Handle error condition
             if error {
                 self.close_connection(conn_id)?;
                 return Ok(());
             }
             
-            // Handle readable event
-            if readable {
+            // Handle readable event; a hangup may arrive together with the
+            // request, which still has to be read and answered
+            if readable || hangup {
                 self.handle_read(conn_id)?;
             }
             
@@ -505,13 +1182,18 @@ impl EventLoop {
             if writable {
                 self.handle_write(conn_id)?;
             }
+            
+            if hangup {
+                self.handle_peer_close(conn_id)?;
+            }
         }
         
         #[cfg(target_os = "macos")]
         {
             let readable = (event_bits & EVENT_READ) != 0;
             let writable = (event_bits & EVENT_WRITE) != 0;
-            let error = (event_bits & (EVENT_HUP | EVENT_ERR)) != 0;
+            let error = (event_bits & EVENT_ERR) != 0;
+            let hangup = (event_bits & EVENT_HUP) != 0;
             
             // Handle error condition
             if error {
@@ -519,8 +1201,9 @@ impl EventLoop {
                 return Ok(());
             }
             
-            // Handle readable event
-            if readable {
+            // Handle readable event; a hangup may arrive together with the
+            // request, which still has to be read and answered
+            if readable || hangup {
                 self.handle_read(conn_id)?;
             }
             
@@ -528,6 +1211,10 @@ impl EventLoop {
             if writable {
                 self.handle_write(conn_id)?;
             }
+            
+            if hangup {
+                self.handle_peer_close(conn_id)?;
+            }
         }
         
         #[cfg(not(any(target_os = "linux", target_os = "macos")))]
@@ -560,18 +1247,28 @@ impl EventLoop {
     
     /// Handle a read event
     fn handle_read(&mut self, conn_id: usize) -> ServerResult<()> {
+        let _scope = profiler::scope("read");
         let connection = match self.connections.get_mut(&conn_id) {
             Some(conn) => conn,
             None => return Ok(()),
         };
         
+        // Nothing more comes from a peer that half-closed
+        if connection.is_read_closed() {
+            return Ok(());
+        }
+        
         // Read data from the connection
         match connection.read() {
             Ok(0) => {
-                // Connection closed by peer
-                self.close_connection(conn_id)?;
+                // The peer closed its side, though it may still read our response
+                self.handle_peer_close(conn_id)?;
                 return Ok(());
             }
+            Ok(_) if connection.is_lingering() => {
+                // Discard anything sent while we wait for the peer to close
+                connection.buffer_mut().reset();
+            }
             Ok(_) => {
                 // Process the received data
                 self.process_data(conn_id)?;
@@ -579,6 +1276,10 @@ impl EventLoop {
             Err(ref e) if e.kind() == ErrorKind::WouldBlock => {
                 // Nothing to read right now
             }
+            Err(ref e) if e.kind() == ErrorKind::OutOfMemory => {
+                // The request outgrew the buffer limit
+                self.reject_oversized(conn_id)?;
+            }
             Err(e) => {
                 // Error reading
                 println!("Error reading from connection {}: {}", conn_id, e);
@@ -590,58 +1291,236 @@ impl EventLoop {
     }
     
     /// Process received data
+    ///
+    /// Pipelined requests are answered one after another for as long as each
+    /// response goes out in full. This loops rather than recursing through
+    /// the write path, so no number of buffered requests can exhaust the stack.
     fn process_data(&mut self, conn_id: usize) -> ServerResult<()> {
-        // Check if we have a connection
-        if !self.connections.contains_key(&conn_id) {
-            return Ok(());
+        while self.process_request(conn_id)? {
+            let more = self.connections.get(&conn_id).is_some_and(|conn| {
+                conn.state() == ConnectionState::Reading && conn.buffer().available_data() > 0
+            });
+            if !more {
+                break;
+            }
+        }
+        Ok(())
+    }
+    
+    /// Parse and answer one buffered request, returning whether one was answered
+    fn process_request(&mut self, conn_id: usize) -> ServerResult<bool> {
+        // Only start on a request once the previous response has gone out;
+        // anything received meanwhile stays buffered until then
+        match self.connections.get(&conn_id).map(|conn| conn.state()) {
+            None | Some(ConnectionState::Writing | ConnectionState::HalfClosed | ConnectionState::Closing) => {
+                return Ok(false)
+            }
+            _ => {}
         }
         
-        // We need to clone the buffer data to avoid borrow checker conflicts
-        let buffer_data = {
-            let connection = self.connections.get(&conn_id).unwrap();
-            let buffer = connection.buffer();
-            buffer.slice().to_vec()
-        };
+        #[cfg(feature = "alloc-audit")]
+        crate::alloc_audit::start_request();
         
         // Now parse the data
         {
-            let parser = self.parsers.get_mut(&conn_id).unwrap();
-            parser.parse(&buffer_data)?;
+            let connection = self.connections.get_mut(&conn_id).unwrap();
+            let parsed = {
+                let _scope = profiler::scope("parse");
+                connection.parse()
+            };
+            if let Err(e) = parsed {
+                return self.close_malformed(conn_id, &e).map(|_| false);
+            }
+            
+            // The body limit depends on the route, and applies even when the
+            // whole body arrived at once
+            let parser = connection.parser();
+            // Looked up once, then attached to the request below
+            let settings = self.routes.settings(target_path(parser.uri.as_deref().unwrap_or("")));
+            let body_too_large = !parser.in_head() && parser.content_length > settings.max_request_size;
+            // A streamed body is left on the connection for the handler to read
+            let streaming = settings.stream_body && !parser.in_head() && !parser.is_complete() && !body_too_large;
             
             // If we don't have a complete request, return early
-            if !parser.is_complete() {
-                return Ok(());
+            if !parser.is_complete() && !streaming {
+                let head_too_large =
+                    parser.in_head() && connection.buffer().available_data() > self.config.max_header_size;
+                if head_too_large || body_too_large || connection.buffer().is_full() {
+                    return self.reject_oversized(conn_id).map(|_| false);
+                }
+                return Ok(false);
+            }
+            if body_too_large {
+                return self.reject_oversized(conn_id).map(|_| false);
             }
             
-            // Get the request before we borrow self again
-            let request = parser.get_request()?;
+            let parser = connection.parser();
+            let parsed = if streaming { parser.get_partial_request() } else { parser.get_request() };
+            let mut request_clone = match parsed {
+                Ok(request) => request,
+                Err(e) => return self.close_malformed(conn_id, &e).map(|_| false),
+            };
+            request_clone.peer_addr = Some(connection.peer_addr());
+            request_clone.extensions.insert(settings);
+            if streaming {
+                let remaining = parser.content_length - parser.body.len();
+                request_clone.extensions.insert(StreamedBody { remaining });
+            }
+            let client_keep_alive = wants_keep_alive(connection.parser().version.as_deref(), &request_clone);
+            connection.parser_mut().reset();
             
+            connection.transition_to(ConnectionState::Processing);
+            connection.timeline_mut().mark(Phase::HandlerStart);
             
-            // Clone the request to avoid borrow issues
-            let request_clone = request.clone();
+            // Get the response (here we use &self, not &mut self)
+            let result = {
+                let _scope = profiler::scope("handler");
+                self.handle_request(&request_clone)
+            };
+            let mut response = result.unwrap_or_else(|e| {
+                println!("Error handling {} {}: {}", request_clone.method.as_str(), request_clone.uri, e);
+                e.to_response(&request_clone)
+            });
             
-            // Reset the parser early to release the mutable borrow
-            parser.reset();
+            if let Some(metrics) = &self.metrics {
+                metrics.requests().record(request_clone.method, response.status as u16);
+            }
+            if response.reset_connection {
+                return self.reset_connection(conn_id).map(|_| false);
+            }
             
-            // Get the response (here we use &self, not &mut self)
-            let response = self.handle_request(&request_clone)?;
+            let connection = self.connections.get_mut(&conn_id).unwrap();
+            connection.timeline_mut().mark(Phase::HandlerEnd);
+            
+            if self.config.server_timing {
+                response.append_header("Server-Timing", &connection.timeline().server_timing());
+            }
+            
+            // Advertise the alternative service unless the handler chose its own, in any case
+            if let Some(alt_svc) = &self.config.alt_svc {
+                if response.header("Alt-Svc").is_none() {
+                    response.set_header("Alt-Svc", alt_svc);
+                }
+            }
+            
+            // A protocol switch is only allowed when the client asked for one
+            let mut upgrade = response.upgrade.take();
+            let switching = response.status == Status::SwitchingProtocols;
+            if let Some(pending) = &upgrade {
+                if switching && !wants_upgrade(&request_clone) {
+                    let protocol = pending.protocol().to_string();
+                    upgrade = None;
+                    response = Response::new(Status::UpgradeRequired);
+                    response.set_body(b"This resource requires a protocol upgrade\n");
+                    response.set_header("Upgrade", &protocol);
+                }
+            }
             
-            // Now we can encode the response outside of any borrows
-            let mut encoded = Vec::new();
-            response.serialize(&mut encoded)?;
+            // Keep the connection open only if the client, the handler and the server all allow it
+            let handler_close = response.header("Connection").is_some_and(|value| has_token(value, "close"));
+            // The rest of a streamed body is only thrown away by closing
+            let keep_alive =
+                self.config.keep_alive && client_keep_alive && !handler_close && !streaming && !self.is_draining();
+            // Prepared heads never carry Connection, so only the handler's own spelling needs replacing
+            response.headers.retain(|name, _| !name.eq_ignore_ascii_case("connection"));
+            if upgrade.is_some() {
+                // A streamed body ends when the connection closes
+                response.set_header("Connection", if switching { "Upgrade" } else { "close" });
+            } else {
+                response.set_header("Connection", if keep_alive { "keep-alive" } else { "close" });
+            }
             
+            #[cfg(feature = "alloc-audit")]
+            self.record_request_allocations();
             
             // Finally get a mutable reference to the connection
             let connection = self.connections.get_mut(&conn_id).unwrap();
-            connection.set_state(ConnectionState::Processing);
-            connection.buffer_mut().write(&encoded)?;
-            connection.set_state(ConnectionState::Writing);
+            connection.set_keep_alive(keep_alive);
+            if let Some(upgrade) = upgrade {
+                let deferred = upgrade.is_deferred();
+                self.upgrades.insert(conn_id, upgrade.clone());
+                if deferred {
+                    // The handler answers once anything queued ahead of it is out
+                    upgrade.bind_head(response);
+                    self.batched.remove(&conn_id);
+                    let result = connection.flush();
+                    return self.handle_write_result(conn_id, result).map(|_| true);
+                }
+            }
             
-            // Immediately try to write the response to the TCP stream
-            self.handle_write(conn_id)?;
+            connection.set_response_rate_limit(response.bandwidth_limit);
+            
+            // Small responses on kept-alive connections may wait to share a write
+            let batch_room = match &self.config.write_batching {
+                Some(batching) if keep_alive && !self.upgrades.contains_key(&conn_id) && !connection.is_throttled() => {
+                    batching.max_bytes.saturating_sub(connection.pending_write_bytes())
+                }
+                _ => 0,
+            };
+            if response.head_len_hint() + response.body_bytes().len() <= batch_room {
+                if let Err(e) = connection.queue_response(&response) {
+                    println!("Error queueing response on connection {}: {}", conn_id, e);
+                    return self.close_connection(conn_id).map(|_| false);
+                }
+                return self.finish_batched_response(conn_id).map(|_| true);
+            }
+            
+            // Any batch waiting on this connection goes out ahead of this response
+            self.batched.remove(&conn_id);
+            let result = {
+                let _scope = profiler::scope("write");
+                connection.send_response(&response)
+            };
+            self.handle_write_result(conn_id, result)?;
         }
         
-        Ok(())
+        Ok(true)
+    }
+    
+    /// Record the heap allocations made for the request just answered
+    ///
+    /// They are counted from the read that completed the request until its
+    /// response is ready to send.
+    #[cfg(feature = "alloc-audit")]
+    fn record_request_allocations(&self) {
+        let allocations = crate::alloc_audit::request_allocations();
+        if let Some(metrics) = &self.metrics {
+            metrics.requests().record_allocations(allocations);
+        }
+    }
+    
+    /// Answer a request that is too large to buffer and close the connection
+    ///
+    /// Oversized headers get 431 and anything else 413. The rest of the
+    /// request is discarded during the lingering close.
+    fn reject_oversized(&mut self, conn_id: usize) -> ServerResult<()> {
+        let connection = match self.connections.get_mut(&conn_id) {
+            Some(conn) => conn,
+            None => return Ok(()),
+        };
+        
+        let status = if connection.parser().in_head() {
+            Status::RequestHeaderFieldsTooLarge
+        } else {
+            Status::PayloadTooLarge
+        };
+        println!("Connection {} sent an oversized request ({})", conn_id, status as u16);
+        if let Some(metrics) = &self.metrics {
+            metrics.record_connection("oversized_requests");
+        }
+        
+        let mut response = Response::new(status);
+        response.set_body(format!("{}\n", status.as_str()).as_bytes());
+        response.set_header("Connection", "close");
+        
+        connection.buffer_mut().reset();
+        connection.parser_mut().reset();
+        connection.set_keep_alive(false);
+        if !connection.transition_to(ConnectionState::Processing) {
+            return self.close_connection(conn_id);
+        }
+        let result = connection.send_response(&response);
+        self.handle_write_result(conn_id, result)
     }
     
     /// Handle a write event
@@ -651,75 +1530,420 @@ impl EventLoop {
             None => return Ok(()),
         };
         
-        // Check conditions before taking mutable references
-        let should_write = connection.state() == ConnectionState::Writing && 
-                          connection.buffer().available_data() > 0;
+        if !connection.has_pending_writes() {
+            return Ok(());
+        }
         
-        if should_write {
-            // Create a temporary buffer to hold data we'll write
-            let data_to_write = connection.buffer().slice().to_vec();
-            
-            // Now write that buffer to the stream
-            match connection.stream_mut().write(&data_to_write) {
-                Ok(0) => {
-                    // Connection closed
-                    connection.set_state(ConnectionState::Closed);
-                    // Return first, then close after we release the mutable borrow
-                    return self.close_connection(conn_id);
-                }
-                Ok(bytes_written) => {
-                    // Update the buffer position by advancing the read position
-                    if let Err(e) = connection.buffer_mut().advance_read(bytes_written) {
-                        println!("Error advancing buffer read position: {}", e);
-                        connection.set_state(ConnectionState::Closed);
-                        return self.close_connection(conn_id);
-                    }
-                    
-                    // If no more data to write, we're done with this request
-                    if connection.buffer().available_data() == 0 {
-                        // Check if we're keeping the connection alive
-                        connection.set_state(ConnectionState::Reading);
+        let result = {
+            let _scope = profiler::scope("write");
+            connection.flush()
+        };
+        self.handle_write_result(conn_id, result)?;
+        
+        // Pick up requests that arrived while the response was going out
+        let pipelined = self.connections.get(&conn_id).is_some_and(|conn| {
+            conn.state() == ConnectionState::Reading && conn.buffer().available_data() > 0
+        });
+        if pipelined {
+            self.process_data(conn_id)?;
+        }
+        Ok(())
+    }
+    
+    /// Update a connection after writing part of its outbound queue
+    fn handle_write_result(&mut self, conn_id: usize, result: io::Result<WriteStatus>) -> ServerResult<()> {
+        let connection = match self.connections.get_mut(&conn_id) {
+            Some(conn) => conn,
+            None => return Ok(()),
+        };
+        
+        match result {
+            Ok(WriteStatus::Complete) => {
+                // The response has been sent, wait for the next request; the
+                // timelines of batched responses ended when they were queued
+                if !connection.take_deferred_flush() {
+                    connection.timeline_mut().mark(Phase::Completed);
+                    let timeline = connection.take_timeline();
+                    if let Some(metrics) = &self.metrics {
+                        metrics.record_request_timeline(&timeline);
                     }
                 }
-                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
-                    // Would block, try again later
+                
+                connection.set_response_rate_limit(None);
+                if !self.set_write_interest(conn_id, false)? {
+                    return Ok(());
                 }
-                Err(e) => {
-                    // Error writing
-                    println!("Error writing to connection {}: {}", conn_id, e);
-                    connection.set_state(ConnectionState::Closed);
+                let connection = match self.connections.get_mut(&conn_id) {
+                    Some(conn) => conn,
+                    None => return Ok(()),
+                };
+                
+                if self.upgrades.contains_key(&conn_id) {
+                    return self.hand_off_upgraded(conn_id);
+                }
+                
+                if connection.is_read_closed() || !connection.keep_alive() {
+                    // The peer is done sending or won't get another response
+                    if connection.start_lingering_close().is_err() {
+                        return self.close_connection(conn_id);
+                    }
+                } else if !connection.transition_to(ConnectionState::Reading) {
                     return self.close_connection(conn_id);
                 }
             }
+            Ok(WriteStatus::WouldBlock) => {
+                // Resume once the socket has room again
+                self.set_write_interest(conn_id, true)?;
+            }
+            Ok(WriteStatus::Throttled) => {
+                // Picked up again by flush_throttled_writes
+            }
+            Err(e) => {
+                println!("Error writing to connection {}: {}", conn_id, e);
+                return self.close_connection(conn_id);
+            }
         }
         
         Ok(())
     }
     
-    /// Close a connection
-    fn close_connection(&mut self, conn_id: usize) -> ServerResult<()> {
-        if let Some(mut conn) = self.connections.remove(&conn_id) {
-            self.poller.deregister(&conn)?;
-            let _ = conn.close();
+    /// Remove a connection that switched protocols and run its upgrade handler
+    ///
+    /// The handler gets a thread of its own, since it drives the connection
+    /// with whatever I/O its protocol needs.
+    fn hand_off_upgraded(&mut self, conn_id: usize) -> ServerResult<()> {
+        let handler = self.upgrades.remove(&conn_id).and_then(|upgrade| upgrade.take_handler());
+        let mut conn = match self.connections.remove(&conn_id) {
+            Some(conn) => conn,
+            None => return Ok(()),
+        };
+        
+        if let Some(worker_load) = &self.worker_load {
+            worker_load.connection_closed(self.thread_id as usize);
+        }
+        self.unwatch(&conn)?;
+        
+        let handler = match handler {
+            Some(handler) => handler,
+            None => {
+                let _ = conn.close();
+                return Ok(());
+            }
+        };
+        conn.transition_to(ConnectionState::Reading);
+        if let Some(metrics) = &self.metrics {
+            metrics.record_connection("upgraded");
         }
         
-        self.parsers.remove(&conn_id);
+        // A failed spawn drops the connection, which closes it
+        if let Err(e) = thread::Builder::new()
+            .name(format!("upgrade-{}", conn_id))
+            .spawn(move || handler(conn))
+        {
+            println!("Failed to start upgrade handler for connection {}: {}", conn_id, e);
+        }
         
         Ok(())
     }
     
-    /// Check for timed out connections
-    fn check_timeouts(&mut self) -> ServerResult<()> {
-        let now = Instant::now();
-        let timed_out: Vec<usize> = self.connections
+    /// Handle the peer closing its sending side
+    ///
+    /// A response still being written is finished first; the socket is then
+    /// closed gracefully once it has been sent. A read of end-of-stream and a
+    /// hangup in the same event both land here, so a connection already
+    /// half-closed is left to finish its response.
+    fn handle_peer_close(&mut self, conn_id: usize) -> ServerResult<()> {
+        let connection = match self.connections.get_mut(&conn_id) {
+            Some(conn) => conn,
+            None => return Ok(()),
+        };
+        
+        if connection.is_read_closed() {
+            return Ok(());
+        }
+        
+        if connection.has_pending_writes() && connection.mark_read_closed() {
+            return Ok(());
+        }
+        
+        self.close_connection(conn_id)
+    }
+    
+    /// Close connections waiting for their next request, so a drain doesn't
+    /// wait out their keep-alive timeout
+    fn close_idle_connections(&mut self) -> ServerResult<()> {
+        let idle: Vec<usize> = self.connections
             .iter()
-            .filter(|(_, conn)| conn.is_timed_out())
+            .filter(|(_, conn)| conn.is_idle())
             .map(|(id, _)| *id)
             .collect();
         
-        for conn_id in timed_out {
-            println!("Connection {} timed out", conn_id);
+        for conn_id in idle {
+            self.close_connection(conn_id)?;
+        }
+        
+        Ok(())
+    }
+    
+    /// Wait for events, replacing the poller if it stops working
+    ///
+    /// A failed poll is counted and yields no events. The poller is
+    /// replaced once its descriptor is gone or polls keep failing.
+    fn poll_events(&mut self, timeout_ms: i32) -> ServerResult<Vec<(usize, u32)>> {
+        match self.poller.poll(timeout_ms) {
+            Ok(events) => {
+                self.poll_failures = 0;
+                self.track_full_polls(events.len());
+                Ok(events)
+            }
+            Err(e) => {
+                self.poller_fault("poll", &e);
+                self.poll_failures += 1;
+                if !self.poller.is_healthy() || self.poll_failures >= MAX_POLL_FAILURES {
+                    self.recreate_poller()?;
+                } else {
+                    // Don't spin on a failure that persists
+                    thread::sleep(Duration::from_millis(10));
+                }
+                Ok(Vec::new())
+            }
+        }
+    }
+    
+    /// Grow the poller's buffer once polls keep filling it
+    ///
+    /// Sockets left over from a full poll wait for the next iteration, after
+    /// batched writes and timeouts, so a burst takes several iterations to
+    /// see. The buffer doubles, up to `capacity::MAX_POLL_EVENTS`, after a
+    /// few full polls in a row rather than on a single spike.
+    fn track_full_polls(&mut self, returned: usize) {
+        let max_events = self.poller.max_events();
+        if returned < max_events {
+            self.full_polls = 0;
+            return;
+        }
+        
+        self.full_polls += 1;
+        if self.full_polls >= FULL_POLLS_BEFORE_GROWTH && max_events < capacity::MAX_POLL_EVENTS {
+            self.poller.grow((max_events * 2).min(capacity::MAX_POLL_EVENTS));
+            self.full_polls = 0;
+            if let Some(metrics) = &self.metrics {
+                metrics.registry().counter("poller.growths").increment(1);
+            }
+        }
+    }
+    
+    /// Count a failed poller call and classify it
+    fn poller_fault(&self, operation: &str, error: &ServerError) -> PollerFault {
+        let fault = PollerFault::classify(error);
+        if let Some(metrics) = &self.metrics {
+            metrics
+                .registry()
+                .counter(&format!("poller.errors.{}.{}", operation, fault.name()))
+                .increment(1);
+        }
+        println!("Worker {}: poller {} failed ({}): {}", self.thread_id, operation, fault.name(), error);
+        fault
+    }
+    
+    /// Register a connection, with write interest if it has output queued
+    fn watch(poller: &mut EventPoller, conn: &Connection) -> ServerResult<()> {
+        poller.register(conn)?;
+        if conn.has_pending_writes() {
+            poller.set_write_interest(conn, true)?;
+        }
+        Ok(())
+    }
+    
+    /// Turn writable notifications for a connection on or off, returning whether it is still watched
+    fn set_write_interest(&mut self, conn_id: usize, enabled: bool) -> ServerResult<bool> {
+        let result = match self.connections.get(&conn_id) {
+            Some(conn) => self.poller.set_write_interest(conn, enabled),
+            None => return Ok(false),
+        };
+        match result {
+            Ok(()) => Ok(true),
+            Err(e) => self.recover_connection(conn_id, "modify", e),
+        }
+    }
+    
+    /// Recover from a failed poller call on a connection, returning whether it is still watched
+    ///
+    /// A connection the poller lost track of is registered again, and one
+    /// it already had is re-armed. One that can't be watched any more is
+    /// closed rather than left to its timeout, and the worker carries on.
+    fn recover_connection(&mut self, conn_id: usize, operation: &str, error: ServerError) -> ServerResult<bool> {
+        let fault = self.poller_fault(operation, &error);
+        if !self.poller.is_healthy() {
+            // Replacing the poller registers every connection again
+            self.recreate_poller()?;
+            return Ok(self.connections.contains_key(&conn_id));
+        }
+        
+        let conn = match self.connections.get(&conn_id) {
+            Some(conn) => conn,
+            None => return Ok(false),
+        };
+        let recovered = match fault {
+            PollerFault::NotRegistered => Self::watch(&mut self.poller, conn).is_ok(),
+            PollerFault::AlreadyRegistered => self.poller.set_write_interest(conn, conn.has_pending_writes()).is_ok(),
+            _ => false,
+        };
+        
+        if recovered {
+            if let Some(metrics) = &self.metrics {
+                metrics.registry().counter("poller.reregistrations").increment(1);
+            }
+            Ok(true)
+        } else {
             self.close_connection(conn_id)?;
+            Ok(false)
+        }
+    }
+    
+    /// Deregister a connection that is going away
+    ///
+    /// Its descriptor may already be closed or unknown to the poller, which
+    /// is counted but otherwise harmless.
+    fn unwatch(&mut self, conn: &Connection) -> ServerResult<()> {
+        if let Err(e) = self.poller.deregister(conn) {
+            self.poller_fault("deregister", &e);
+            if !self.poller.is_healthy() {
+                self.recreate_poller()?;
+            }
+        }
+        Ok(())
+    }
+    
+    /// Replace a broken poller and register every connection with the new one
+    ///
+    /// Connections that can't be registered again are closed. Fails only if
+    /// no new poller can be created, which ends the worker.
+    fn recreate_poller(&mut self) -> ServerResult<()> {
+        self.poller.replace()?;
+        self.poll_failures = 0;
+        if let Some(metrics) = &self.metrics {
+            metrics.registry().counter("poller.recreations").increment(1);
+        }
+        println!("Worker {}: recreated its poller", self.thread_id);
+        self.watch_listeners()?;
+        
+        let lost: Vec<usize> = self
+            .connections
+            .iter()
+            .filter(|(_, conn)| Self::watch(&mut self.poller, conn).is_err())
+            .map(|(&conn_id, _)| conn_id)
+            .collect();
+        for conn_id in lost {
+            self.close_connection(conn_id)?;
+        }
+        Ok(())
+    }
+    
+    /// Close a connection
+    fn close_connection(&mut self, conn_id: usize) -> ServerResult<()> {
+        self.remove_connection(conn_id, false)
+    }
+    
+    /// Close a connection with a TCP reset, as a handler asked
+    fn reset_connection(&mut self, conn_id: usize) -> ServerResult<()> {
+        self.remove_connection(conn_id, true)
+    }
+    
+    fn remove_connection(&mut self, conn_id: usize, reset: bool) -> ServerResult<()> {
+        self.upgrades.remove(&conn_id);
+        self.batched.remove(&conn_id);
+        self.timers.cancel(conn_id);
+        if let Some(mut conn) = self.connections.remove(&conn_id) {
+            if let Some(worker_load) = &self.worker_load {
+                worker_load.connection_closed(self.thread_id as usize);
+            }
+            
+            if let (Some(metrics), true) = (&self.metrics, conn.invalid_transitions() > 0) {
+                metrics
+                    .registry()
+                    .counter("connections.invalid_state_transitions")
+                    .increment(conn.invalid_transitions());
+            }
+            
+            self.unwatch(&conn)?;
+            let _ = if reset { conn.reset() } else { conn.close() };
+        }
+        
+        Ok(())
+    }
+    
+    /// Answer a request we couldn't parse and close the connection
+    ///
+    /// The status comes from the error: 400 for a malformed request, 501
+    /// for a method or transfer coding we don't implement and 505 for
+    /// another HTTP version. Anything sent after it is discarded during the
+    /// lingering close. For a malformed request the peer's IP also gets a
+    /// strike, and enough strikes get it banned.
+    fn close_malformed(&mut self, conn_id: usize, error: &ServerError) -> ServerResult<()> {
+        let connection = match self.connections.get_mut(&conn_id) {
+            Some(conn) => conn,
+            None => return Ok(()),
+        };
+        
+        if matches!(error, ServerError::HttpParse(_)) && connection.report_garbage() {
+            println!("Banning {} after repeated malformed requests", connection.peer_addr().ip());
+        } else {
+            println!("Malformed request on connection {}: {}", conn_id, error);
+        }
+        
+        let mut response = ErrorResponse::from_error(error, None).into_response(error.status());
+        response.set_header("Connection", "close");
+        connection.buffer_mut().reset();
+        connection.parser_mut().reset();
+        connection.set_keep_alive(false);
+        if !connection.transition_to(ConnectionState::Processing) {
+            return self.close_connection(conn_id);
+        }
+        let result = connection.send_response(&response);
+        self.handle_write_result(conn_id, result)
+    }
+    
+    /// Put a connection's next deadline in the timer wheel
+    ///
+    /// The wheel keeps the earliest deadline it was given, so this is called
+    /// whenever activity may have brought a deadline forward; one that moved
+    /// later is found when the earlier one fires and gets checked.
+    fn schedule_timeout(&mut self, conn_id: usize) {
+        let linger_timeout = self.config.lingering_close_timeout;
+        if let Some(at) = self.connections.get(&conn_id).and_then(|conn| conn.next_deadline(linger_timeout)) {
+            self.timers.schedule(conn_id, at);
+        }
+    }
+    
+    /// Close connections whose deadline in the timer wheel has passed
+    ///
+    /// Only connections with a deadline due are looked at. One whose
+    /// deadline was pushed back by activity since is scheduled again.
+    fn check_timeouts(&mut self) -> ServerResult<()> {
+        let linger_timeout = self.config.lingering_close_timeout;
+        for conn_id in self.timers.expire(Instant::now()) {
+            let conn = match self.connections.get(&conn_id) {
+                Some(conn) => conn,
+                None => continue,
+            };
+            
+            if conn.is_timed_out() {
+                println!("Connection {} timed out", conn_id);
+                self.close_connection(conn_id)?;
+            } else if conn.linger_expired(linger_timeout) {
+                // Finish a lingering close the peer never completed
+                self.close_connection(conn_id)?;
+            } else if conn.is_write_stalled() {
+                // Evict a client that stopped reading its response
+                println!("Connection {} stopped accepting response data", conn_id);
+                if let Some(metrics) = &self.metrics {
+                    metrics.record_connection("write_stall_evictions");
+                }
+                self.close_connection(conn_id)?;
+            } else {
+                self.schedule_timeout(conn_id);
+            }
         }
         
         Ok(())
@@ -727,6 +1951,26 @@ impl EventLoop {
     
     /// Handle an HTTP request
     fn handle_request(&self, request: &Request) -> ServerResult<Response> {
+        if let Some(response) = self.maintenance.check(request) {
+            return Ok(response);
+        }
+        
+        // Auth and tenant middleware attach the identities the access rules
+        // check, so the rules run after them, just before the handler
+        if self.router.is_none() {
+            if let (Some(middleware_chain), Some(acl)) = (&self.middleware_chain, &self.acl) {
+                return middleware_chain.handle_with(request, Arc::new(acl_middleware(acl.clone())));
+            }
+        }
+        
+        // Nothing runs ahead of a router, so the rules check the request as it came
+        let authenticated = match self.acl.as_ref().map(|acl| acl.check(request)) {
+            Some(Err(response)) => return Ok(*response),
+            Some(Ok(authenticated)) => authenticated,
+            None => None,
+        };
+        let request = authenticated.as_ref().unwrap_or(request);
+        
         // If we have a router set, use it to handle the request
         if let Some(router) = &self.router {
             router.handle_request(request)
@@ -741,4 +1985,44 @@ impl EventLoop {
             Ok(response)
         }
     }
+}
+
+/// Get the path of a request target as sent, without copying it
+///
+/// Absolute-form targets lose their scheme and authority; authority and
+/// asterisk forms have no path.
+fn target_path(target: &str) -> &str {
+    if target.starts_with('/') {
+        return target;
+    }
+    match target.split_once("://") {
+        Some((_, rest)) => rest.find('/').map_or("", |start| &rest[start..]),
+        None => "",
+    }
+}
+
+/// Check whether a client wants its connection kept open after the response
+///
+/// HTTP/1.1 connections stay open unless the client says `close`; HTTP/1.0
+/// ones close unless it says `keep-alive`.
+fn wants_keep_alive(version: Option<&str>, request: &Request) -> bool {
+    match request.headers.get(&HeaderName::CONNECTION) {
+        Some(value) if has_token(value, "close") => false,
+        Some(value) if has_token(value, "keep-alive") => true,
+        _ => version == Some("HTTP/1.1"),
+    }
+}
+
+/// Check whether a comma-separated header value contains `token`
+fn has_token(value: &str, token: &str) -> bool {
+    value.split(',').any(|part| part.trim().eq_ignore_ascii_case(token))
+}
+
+/// Whether a request asks to switch protocols
+fn wants_upgrade(request: &Request) -> bool {
+    request.headers.get(&HeaderName::UPGRADE).is_some_and(|protocols| !protocols.trim().is_empty())
+        && request
+            .headers
+            .get(&HeaderName::CONNECTION)
+            .is_some_and(|value| has_token(value, "upgrade"))
 }
\ No newline at end of file
@@ -0,0 +1,176 @@
+use crate::error::{ServerError, ServerResult};
+use crate::http_client::{self, HttpUrl};
+use serde::Serialize;
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Longest a panicking thread waits for its event to be posted
+const PANIC_DELIVERY_GRACE: Duration = Duration::from_secs(5);
+
+/// Something that happened in the life of the server
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ServerEvent {
+    /// The server is listening and its workers are running
+    ServerStarted {
+        addresses: Vec<String>,
+        workers: usize,
+    },
+    
+    /// An event loop thread panicked; with `panic = "abort"` this is emitted
+    /// from the panic hook, as the process ends right after it
+    WorkerPanicked {
+        worker: usize,
+        message: String,
+    },
+    
+    /// Hot-reloaded configuration changed, such as a feature flag file;
+    /// `path` is None for configuration read from a shared store
+    ConfigReloaded {
+        path: Option<String>,
+    },
+    
+    /// A TLS certificate was reloaded from disk; `domain` is the configured
+    /// domain, or the certificate file when none is configured
+    CertificateRenewed {
+        domain: String,
+    },
+    
+    /// A balancer took an upstream out of rotation
+    UpstreamUnhealthy {
+        upstream: String,
+        reason: String,
+    },
+}
+
+/// A callback invoked for every emitted event
+pub type EventSubscriber = Arc<dyn Fn(&ServerEvent) + Send + Sync>;
+
+/// Fans server events out to registered subscribers
+#[derive(Default)]
+pub struct EventBus {
+    subscribers: RwLock<Vec<EventSubscriber>>,
+}
+
+impl std::fmt::Debug for EventBus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EventBus").field("subscribers", &self.subscriber_count()).finish()
+    }
+}
+
+impl EventBus {
+    /// Create an event bus without subscribers
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Register a callback for every future event
+    ///
+    /// Subscribers run on the emitting thread and should return quickly.
+    pub fn subscribe<F>(&self, subscriber: F)
+    where
+        F: Fn(&ServerEvent) + Send + Sync + 'static,
+    {
+        self.subscribers.write().unwrap().push(Arc::new(subscriber));
+    }
+    
+    /// Post every future event as JSON to a webhook URL
+    pub fn subscribe_webhook(&self, url: &str) -> ServerResult<()> {
+        let sink = WebhookSink::new(url)?;
+        self.subscribe(move |event| sink.send(event));
+        Ok(())
+    }
+    
+    /// Deliver an event to every subscriber
+    pub fn emit(&self, event: ServerEvent) {
+        let subscribers = self.subscribers.read().unwrap().clone();
+        for subscriber in subscribers {
+            subscriber(&event);
+        }
+    }
+    
+    /// Get the number of registered subscribers
+    pub fn subscriber_count(&self) -> usize {
+        self.subscribers.read().unwrap().len()
+    }
+}
+
+/// The JSON body posted to webhooks
+#[derive(Serialize)]
+struct WebhookPayload<'a> {
+    timestamp: u64,
+    #[serde(flatten)]
+    event: &'a ServerEvent,
+}
+
+/// An event queued for the webhook thread, with who to tell once it is posted
+type Queued = (ServerEvent, Option<Sender<()>>);
+
+/// Posts events to a webhook from a background thread
+///
+/// Delivery is best effort: failures are reported on stderr and not retried.
+/// A panicking thread still hands its event to the background thread, but
+/// waits up to `PANIC_DELIVERY_GRACE` for it to be posted, since a panic may
+/// abort the process as soon as its hook is done.
+pub struct WebhookSink {
+    sender: Mutex<Sender<Queued>>,
+}
+
+impl WebhookSink {
+    /// Start a sink posting to `url` (plain `http://` only)
+    pub fn new(url: &str) -> ServerResult<Self> {
+        let url = HttpUrl::parse(url)?;
+        let (sender, receiver) = mpsc::channel::<Queued>();
+        
+        std::thread::spawn(move || {
+            for (event, delivered) in receiver {
+                if let Err(e) = post_event(&url, &event) {
+                    eprintln!("Failed to deliver {:?} to webhook: {}", event, e);
+                }
+                if let Some(delivered) = delivered {
+                    let _ = delivered.send(());
+                }
+            }
+        });
+        
+        Ok(Self {
+            sender: Mutex::new(sender),
+        })
+    }
+    
+    /// Queue an event for delivery; from a panicking thread, also wait a
+    /// little for it to be posted
+    pub fn send(&self, event: &ServerEvent) {
+        // Another thread may have panicked while queueing
+        let sender = self.sender.lock().unwrap_or_else(|e| e.into_inner());
+        if !std::thread::panicking() {
+            let _ = sender.send((event.clone(), None));
+            return;
+        }
+        
+        let (delivered_tx, delivered_rx) = mpsc::channel();
+        if sender.send((event.clone(), Some(delivered_tx))).is_ok() {
+            drop(sender);
+            let _ = delivered_rx.recv_timeout(PANIC_DELIVERY_GRACE);
+        }
+    }
+}
+
+fn post_event(url: &HttpUrl, event: &ServerEvent) -> ServerResult<()> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let body = serde_json::to_vec(&WebhookPayload { timestamp, event })?;
+    
+    let response = http_client::send("POST", url, "application/json", &body)?;
+    if !response.is_success() {
+        return Err(ServerError::Protocol(format!(
+            "Webhook responded with status {}",
+            response.status
+        )));
+    }
+    
+    Ok(())
+}
\ No newline at end of file
@@ -0,0 +1,282 @@
+use crate::error::{ServerError, ServerResult};
+use crate::http_client::{self, HttpUrl};
+use crate::metrics::MetricsRegistry;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::UdpSocket;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// Largest StatsD datagram we send, small enough to avoid IP fragmentation
+const MAX_STATSD_PACKET: usize = 1432;
+
+/// Where metrics are pushed to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+pub enum ExportTarget {
+    /// A StatsD (or DogStatsD, when tags are given) agent listening on UDP
+    StatsD {
+        address: String,
+        #[serde(default)]
+        tags: Vec<(String, String)>,
+    },
+    
+    /// A Prometheus Pushgateway reachable over plain HTTP
+    Pushgateway {
+        address: String,
+        job: String,
+    },
+}
+
+/// Settings for periodically pushing metrics
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MetricsExportConfig {
+    pub target: ExportTarget,
+    
+    /// Time between flushes
+    #[serde(default = "default_export_interval")]
+    pub interval: Duration,
+    
+    /// Prefix prepended to every metric name
+    #[serde(default)]
+    pub prefix: String,
+}
+
+fn default_export_interval() -> Duration {
+    Duration::from_secs(10)
+}
+
+impl MetricsExportConfig {
+    /// Push to a StatsD agent at `address`
+    pub fn statsd(address: &str) -> Self {
+        Self {
+            target: ExportTarget::StatsD {
+                address: address.to_string(),
+                tags: Vec::new(),
+            },
+            interval: default_export_interval(),
+            prefix: String::new(),
+        }
+    }
+    
+    /// Push to a Prometheus Pushgateway at `address` (host:port) under `job`
+    pub fn pushgateway(address: &str, job: &str) -> Self {
+        Self {
+            target: ExportTarget::Pushgateway {
+                address: address.to_string(),
+                job: job.to_string(),
+            },
+            interval: default_export_interval(),
+            prefix: String::new(),
+        }
+    }
+    
+    /// Add a DogStatsD tag to every StatsD metric
+    pub fn with_tag(mut self, key: &str, value: &str) -> Self {
+        if let ExportTarget::StatsD { tags, .. } = &mut self.target {
+            tags.push((key.to_string(), value.to_string()));
+        }
+        self
+    }
+    
+    /// Set the time between flushes
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+    
+    /// Set the prefix prepended to every metric name
+    pub fn with_prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.to_string();
+        self
+    }
+}
+
+/// Pushes the contents of a MetricsRegistry to StatsD or a Pushgateway
+pub struct MetricsExporter {
+    registry: Arc<MetricsRegistry>,
+    config: MetricsExportConfig,
+    socket: Option<UdpSocket>,
+    
+    /// Counter values at the previous flush, as StatsD expects deltas
+    last_counters: HashMap<String, usize>,
+}
+
+impl MetricsExporter {
+    /// Create an exporter for a registry
+    pub fn new(registry: Arc<MetricsRegistry>, config: MetricsExportConfig) -> Self {
+        Self {
+            registry,
+            config,
+            socket: None,
+            last_counters: HashMap::new(),
+        }
+    }
+    
+    /// Push the current metrics once
+    pub fn flush(&mut self) -> ServerResult<()> {
+        match self.config.target.clone() {
+            ExportTarget::StatsD { address, tags } => {
+                let lines = self.statsd_lines(&tags);
+                self.send_statsd(&address, &lines)
+            }
+            ExportTarget::Pushgateway { address, job } => {
+                let body = self.prometheus_text();
+                push_to_gateway(&address, &job, &body)
+            }
+        }
+    }
+    
+    /// Flush in a background thread every configured interval until stopped
+    pub fn spawn(mut self) -> ExporterHandle {
+        let running = Arc::new(AtomicBool::new(true));
+        let running_clone = running.clone();
+        
+        let thread = std::thread::spawn(move || {
+            while running_clone.load(Ordering::Relaxed) {
+                std::thread::sleep(self.config.interval);
+                if let Err(e) = self.flush() {
+                    eprintln!("Failed to export metrics: {}", e);
+                }
+            }
+        });
+        
+        ExporterHandle { running, thread }
+    }
+    
+    /// Render the registry as StatsD lines
+    ///
+    /// Counters are sent as the change since the last flush, gauges as their
+    /// value, and histograms as a count delta plus mean and max gauges.
+    pub fn statsd_lines(&mut self, tags: &[(String, String)]) -> Vec<String> {
+        let suffix = if tags.is_empty() {
+            String::new()
+        } else {
+            let tags: Vec<String> = tags.iter().map(|(k, v)| format!("{}:{}", k, v)).collect();
+            format!("|#{}", tags.join(","))
+        };
+        
+        let mut lines = Vec::new();
+        
+        for (name, value) in self.registry.counters() {
+            let delta = self.counter_delta(&name, value);
+            lines.push(format!("{}:{}|c{}", self.metric_name(&name), delta, suffix));
+        }
+        
+        for (name, value) in self.registry.gauges() {
+            lines.push(format!("{}:{}|g{}", self.metric_name(&name), value, suffix));
+        }
+        
+        for (name, histogram) in self.registry.histograms() {
+            let delta = self.counter_delta(&format!("{}.count", name), histogram.count());
+            let name = self.metric_name(&name);
+            lines.push(format!("{}.count:{}|c{}", name, delta, suffix));
+            lines.push(format!("{}.mean:{:.2}|g{}", name, histogram.mean(), suffix));
+            lines.push(format!("{}.max:{}|g{}", name, histogram.max(), suffix));
+        }
+        
+        lines
+    }
+    
+    /// Render the registry in the Prometheus text exposition format
+    pub fn prometheus_text(&self) -> String {
+        let mut text = String::new();
+        
+        for (name, value) in self.registry.counters() {
+            let name = prometheus_name(&self.metric_name(&name));
+            text.push_str(&format!("# TYPE {} counter\n{} {}\n", name, name, value));
+        }
+        
+        for (name, value) in self.registry.gauges() {
+            let name = prometheus_name(&self.metric_name(&name));
+            text.push_str(&format!("# TYPE {} gauge\n{} {}\n", name, name, value));
+        }
+        
+        for (name, histogram) in self.registry.histograms() {
+            let name = prometheus_name(&self.metric_name(&name));
+            text.push_str(&format!("# TYPE {} summary\n", name));
+            text.push_str(&format!("{}_sum {}\n", name, histogram.sum()));
+            text.push_str(&format!("{}_count {}\n", name, histogram.count()));
+        }
+        
+        text
+    }
+    
+    /// Apply the configured prefix to a metric name
+    fn metric_name(&self, name: &str) -> String {
+        if self.config.prefix.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}.{}", self.config.prefix, name)
+        }
+    }
+    
+    /// Get how much a counter grew since the previous flush
+    fn counter_delta(&mut self, name: &str, value: usize) -> usize {
+        let previous = self.last_counters.insert(name.to_string(), value).unwrap_or(0);
+        value.saturating_sub(previous)
+    }
+    
+    /// Send lines to a StatsD agent, packing as many as fit into each datagram
+    fn send_statsd(&mut self, address: &str, lines: &[String]) -> ServerResult<()> {
+        if self.socket.is_none() {
+            self.socket = Some(UdpSocket::bind("0.0.0.0:0")?);
+        }
+        let socket = self.socket.as_ref().unwrap();
+        
+        let mut packet = String::new();
+        for line in lines {
+            if !packet.is_empty() && packet.len() + 1 + line.len() > MAX_STATSD_PACKET {
+                socket.send_to(packet.as_bytes(), address)?;
+                packet.clear();
+            }
+            
+            if !packet.is_empty() {
+                packet.push('\n');
+            }
+            packet.push_str(line);
+        }
+        
+        if !packet.is_empty() {
+            socket.send_to(packet.as_bytes(), address)?;
+        }
+        
+        Ok(())
+    }
+}
+
+/// Handle to a running background exporter
+pub struct ExporterHandle {
+    running: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+impl ExporterHandle {
+    /// Stop exporting after the current interval and wait for the thread
+    pub fn stop(self) {
+        self.running.store(false, Ordering::Relaxed);
+        let _ = self.thread.join();
+    }
+}
+
+/// Replace characters Prometheus doesn't allow in metric names
+fn prometheus_name(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == ':' { c } else { '_' })
+        .collect()
+}
+
+/// Replace the metrics of `job` on a Pushgateway
+fn push_to_gateway(address: &str, job: &str, body: &str) -> ServerResult<()> {
+    let url = HttpUrl::parse(&format!("http://{}/metrics/job/{}", address, job))?;
+    let response = http_client::send("PUT", &url, "text/plain; version=0.0.4", body.as_bytes())?;
+    
+    if !response.is_success() {
+        return Err(ServerError::Protocol(format!("Pushgateway responded with status {}", response.status)));
+    }
+    
+    Ok(())
+}
\ No newline at end of file
@@ -0,0 +1,403 @@
+use crate::error::{ServerError, ServerResult};
+use crate::events::{EventBus, ServerEvent};
+use crate::hash::Sha256;
+use crate::http::{Request, Response, Status};
+use crate::kv::KvStore;
+use crate::middleware::MiddlewareNext;
+use crate::router::Router;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime};
+
+/// How often a flag file or store is checked for changes
+const RELOAD_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Times a flag change is retried when other replicas change flags at once
+const SET_ATTEMPTS: usize = 5;
+
+/// A single feature flag
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Flag {
+    /// Master switch; a disabled flag is off for everyone
+    pub enabled: bool,
+    
+    /// Percentage of clients the flag is on for, from 0 to 100
+    pub rollout: f64,
+}
+
+impl Default for Flag {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            rollout: 100.0,
+        }
+    }
+}
+
+impl Flag {
+    /// A flag that is on for `percent` of clients
+    pub fn rollout(percent: f64) -> Self {
+        Self {
+            enabled: true,
+            rollout: percent,
+        }
+    }
+    
+    /// A flag that is off for everyone
+    pub fn disabled() -> Self {
+        Self {
+            enabled: false,
+            ..Self::default()
+        }
+    }
+}
+
+/// A set of flags and how clients are told apart, as read from a flag file
+///
+/// ```json
+/// {
+///   "cookie": "uid",
+///   "flags": {
+///     "new_checkout": {"rollout": 25},
+///     "dark_mode": {"enabled": false}
+///   }
+/// }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FlagSet {
+    /// Cookie identifying a client for rollouts; the client IP is used
+    /// when it is not set or the request has no such cookie
+    pub cookie: Option<String>,
+    
+    pub flags: BTreeMap<String, Flag>,
+}
+
+/// Where flags were loaded from and when that was last looked at
+#[derive(Debug)]
+struct FlagSource {
+    origin: FlagOrigin,
+    checked: Instant,
+}
+
+enum FlagOrigin {
+    File {
+        path: PathBuf,
+        modified: Option<SystemTime>,
+    },
+    /// A flag set stored as JSON under `key`, and the bytes last read
+    Store {
+        store: Arc<dyn KvStore>,
+        key: String,
+        last: Option<Vec<u8>>,
+    },
+}
+
+impl std::fmt::Debug for FlagOrigin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FlagOrigin::File { path, .. } => f.debug_struct("File").field("path", path).finish(),
+            FlagOrigin::Store { key, .. } => f.debug_struct("Store").field("key", key).finish(),
+        }
+    }
+}
+
+impl FlagOrigin {
+    /// Read the flags, or None if they haven't changed since last time and `force` isn't set
+    fn load(&mut self, force: bool) -> ServerResult<Option<FlagSet>> {
+        match self {
+            FlagOrigin::File { path, modified } => {
+                let current = fs::metadata(&path).and_then(|meta| meta.modified()).ok();
+                if !force && current == *modified {
+                    return Ok(None);
+                }
+                *modified = current;
+                read_flag_file(path).map(Some)
+            }
+            FlagOrigin::Store { store, key, last } => {
+                let current = store.get(key)?;
+                if !force && current == *last {
+                    return Ok(None);
+                }
+                *last = current.clone();
+                parse_stored_flags(key, current.as_deref()).map(Some)
+            }
+        }
+    }
+}
+
+/// Feature flags with percentage rollouts
+///
+/// A client is in a flag's rollout when a stable hash of the flag name and
+/// the client's cookie or IP falls below the rollout percentage, so the
+/// same client keeps getting the same answer and raising the percentage
+/// only adds clients. Flags loaded with `load` or `with_store` are re-read
+/// when their file or stored value changes; flags that fail to parse leave
+/// the previous ones in place.
+#[derive(Debug)]
+pub struct FeatureFlags {
+    flags: RwLock<FlagSet>,
+    source: Option<Mutex<FlagSource>>,
+    /// Told whenever flags are reloaded from their file or store
+    events: Option<Arc<EventBus>>,
+}
+
+impl FeatureFlags {
+    /// Create flags from a fixed set
+    pub fn new(flags: FlagSet) -> Self {
+        Self {
+            flags: RwLock::new(flags),
+            source: None,
+            events: None,
+        }
+    }
+    
+    /// Load flags from a JSON file, re-reading it whenever it changes
+    pub fn load<P: AsRef<Path>>(path: P) -> ServerResult<Self> {
+        let path = path.as_ref().to_path_buf();
+        Self::from_origin(FlagOrigin::File { path, modified: None })
+    }
+    
+    /// Load flags stored as JSON under `key` in `store`, re-reading them whenever they change
+    ///
+    /// Every replica using the same store sees the same flags, and changes
+    /// made through `set` are written back for the others to pick up. A
+    /// missing key is an empty flag set.
+    pub fn with_store(store: Arc<dyn KvStore>, key: &str) -> ServerResult<Self> {
+        Self::from_origin(FlagOrigin::Store {
+            store,
+            key: key.to_string(),
+            last: None,
+        })
+    }
+    
+    fn from_origin(mut origin: FlagOrigin) -> ServerResult<Self> {
+        let flags = origin.load(true)?.unwrap_or_default();
+        Ok(Self {
+            flags: RwLock::new(flags),
+            source: Some(Mutex::new(FlagSource {
+                origin,
+                checked: Instant::now(),
+            })),
+            events: None,
+        })
+    }
+    
+    /// Emit `ConfigReloaded` on `events` whenever the flags are reloaded
+    pub fn with_events(mut self, events: Arc<EventBus>) -> Self {
+        self.events = Some(events);
+        self
+    }
+    
+    /// Check whether flag `name` is on for the client that sent `request`
+    ///
+    /// Unknown flags are off.
+    pub fn enabled(&self, name: &str, request: &Request) -> bool {
+        self.reload_if_changed();
+        
+        let flags = self.flags.read().unwrap();
+        let flag = match flags.flags.get(name) {
+            Some(flag) if flag.enabled => flag,
+            _ => return false,
+        };
+        if flag.rollout >= 100.0 {
+            return true;
+        }
+        
+        match client_key(request, flags.cookie.as_deref()) {
+            Some(key) => rollout_bucket(name, &key) < flag.rollout,
+            None => false,
+        }
+    }
+    
+    /// Replace one flag
+    ///
+    /// With flags in a store, the change is written there for every replica
+    /// to see. With flags from a file, it lasts until the file next changes.
+    pub fn set(&self, name: &str, flag: Flag) -> ServerResult<()> {
+        if let Some(source) = &self.source {
+            let mut source = source.lock().unwrap();
+            if let FlagOrigin::Store { store, key, last } = &mut source.origin {
+                for _ in 0..SET_ATTEMPTS {
+                    let current = store.get(key)?;
+                    let mut flags = parse_stored_flags(key, current.as_deref())?;
+                    flags.flags.insert(name.to_string(), flag.clone());
+                    let updated = serde_json::to_vec(&flags)?;
+                    if store.cas(key, current.as_deref(), Some(&updated), None)? {
+                        *last = Some(updated);
+                        *self.flags.write().unwrap() = flags;
+                        return Ok(());
+                    }
+                }
+                return Err(ServerError::Protocol(format!(
+                    "Flags under {} kept changing; flag {} was not set",
+                    key, name
+                )));
+            }
+        }
+        self.flags.write().unwrap().flags.insert(name.to_string(), flag);
+        Ok(())
+    }
+    
+    /// Get the flags currently in effect
+    pub fn snapshot(&self) -> FlagSet {
+        self.reload_if_changed();
+        self.flags.read().unwrap().clone()
+    }
+    
+    /// Re-read the flag file or store now, returning whether there is one
+    pub fn reload(&self) -> ServerResult<bool> {
+        let source = match &self.source {
+            Some(source) => source,
+            None => return Ok(false),
+        };
+        
+        let mut source = source.lock().unwrap();
+        source.checked = Instant::now();
+        if let Some(flags) = source.origin.load(true)? {
+            self.replace(&source.origin, flags);
+        }
+        Ok(true)
+    }
+    
+    /// Reload the flags if they changed, checking at most once per interval
+    fn reload_if_changed(&self) {
+        let source = match &self.source {
+            Some(source) => source,
+            None => return,
+        };
+        
+        let mut source = match source.try_lock() {
+            Ok(source) => source,
+            // Another request is already checking
+            Err(_) => return,
+        };
+        if source.checked.elapsed() < RELOAD_INTERVAL {
+            return;
+        }
+        source.checked = Instant::now();
+        
+        match source.origin.load(false) {
+            Ok(Some(flags)) => self.replace(&source.origin, flags),
+            Ok(None) => {}
+            Err(e) => log::warn!("Keeping previous feature flags: {}", e),
+        }
+    }
+    
+    /// Put flags reloaded from `origin` in effect
+    fn replace(&self, origin: &FlagOrigin, flags: FlagSet) {
+        *self.flags.write().unwrap() = flags;
+        if let Some(events) = &self.events {
+            let path = match origin {
+                FlagOrigin::File { path, .. } => Some(path.display().to_string()),
+                FlagOrigin::Store { .. } => None,
+            };
+            events.emit(ServerEvent::ConfigReloaded { path });
+        }
+    }
+}
+
+fn read_flag_file(path: &Path) -> ServerResult<FlagSet> {
+    let contents = fs::read(path)?;
+    serde_json::from_slice(&contents)
+        .map_err(|e| ServerError::Config(format!("Invalid flag file {}: {}", path.display(), e)))
+}
+
+fn parse_stored_flags(key: &str, value: Option<&[u8]>) -> ServerResult<FlagSet> {
+    match value {
+        Some(value) => serde_json::from_slice(value)
+            .map_err(|e| ServerError::Config(format!("Invalid flags stored under {}: {}", key, e))),
+        None => Ok(FlagSet::default()),
+    }
+}
+
+/// Identify the client for rollouts by cookie, falling back to its IP
+fn client_key(request: &Request, cookie: Option<&str>) -> Option<String> {
+    if let Some(value) = cookie.and_then(|name| request.cookie(name)) {
+        return Some(value.to_string());
+    }
+    request.peer_addr.map(|addr| addr.ip().to_string())
+}
+
+/// Map a flag and client to a stable percentage in [0, 100)
+///
+/// Hashing the flag name in puts a client in different buckets for
+/// different flags, so the same clients don't get every new feature first.
+fn rollout_bucket(name: &str, key: &str) -> f64 {
+    let mut hasher = Sha256::new();
+    hasher.update(name.as_bytes());
+    hasher.update(b":");
+    hasher.update(key.as_bytes());
+    let digest = hasher.finalize();
+    let value = u32::from_be_bytes([digest[0], digest[1], digest[2], digest[3]]);
+    (value % 10_000) as f64 / 100.0
+}
+
+/// Check a flag from a handler behind `feature_flags_middleware`
+///
+/// Returns false when the middleware is not in the chain.
+pub fn flag_enabled(request: &Request, name: &str) -> bool {
+    request
+        .extensions
+        .get::<Arc<FeatureFlags>>()
+        .is_some_and(|flags| flags.enabled(name, request))
+}
+
+/// Feature flag middleware - makes `flags` available to later handlers through `flag_enabled`
+pub fn feature_flags_middleware(
+    flags: Arc<FeatureFlags>,
+) -> impl Fn(&Request, MiddlewareNext) -> ServerResult<Response> + Send + Sync {
+    move |request, next| {
+        let mut request = request.clone();
+        request.extensions.insert(flags.clone());
+        next(&request)
+    }
+}
+
+/// Register a feature flag admin route at `path`
+///
+/// - `GET <path>` reports the flags in effect as JSON
+/// - `PUT <path>?name=new_checkout` replaces one flag from a JSON body like
+///   `{"enabled": true, "rollout": 50}`, as `FeatureFlags::set` does
+///
+/// Put this route behind authentication middleware or a listener that is
+/// not publicly reachable.
+pub fn mount_feature_flags(router: &mut Router, path: &str, flags: Arc<FeatureFlags>) {
+    let list = flags.clone();
+    router.get(path, move |_| {
+        let body = serde_json::to_vec(&list.snapshot())?;
+        let mut response = Response::new(Status::Ok);
+        response.set_body(&body);
+        response.set_header("Content-Type", "application/json");
+        Ok(response)
+    });
+    
+    router.put(path, move |req| {
+        let name = match req.query_params.get("name") {
+            Some(name) if !name.is_empty() => name,
+            _ => return Ok(text_response(Status::BadRequest, "A flag name is required\n")),
+        };
+        let flag: Flag = match serde_json::from_slice(&req.body) {
+            Ok(flag) => flag,
+            Err(e) => return Ok(text_response(Status::BadRequest, &format!("Invalid flag: {}\n", e))),
+        };
+        if !(0.0..=100.0).contains(&flag.rollout) {
+            return Ok(text_response(Status::BadRequest, "rollout must be between 0 and 100\n"));
+        }
+        
+        flags.set(name, flag)?;
+        let mut response = Response::new(Status::NoContent);
+        response.set_header("Content-Length", "0");
+        Ok(response)
+    });
+}
+
+fn text_response(status: Status, body: &str) -> Response {
+    let mut response = Response::new(status);
+    response.set_body(body.as_bytes());
+    response
+}
\ No newline at end of file
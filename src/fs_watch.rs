@@ -0,0 +1,146 @@
+//! Directory change notifications through inotify
+//!
+//! Only built on Linux with the `fs-notify` feature; callers fall back to
+//! checking modification times where it is missing.
+
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::RawFd;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+/// Changes to a directory's entries worth reporting
+const WATCH_MASK: u32 = libc::IN_CREATE
+    | libc::IN_DELETE
+    | libc::IN_MODIFY
+    | libc::IN_ATTRIB
+    | libc::IN_MOVED_FROM
+    | libc::IN_MOVED_TO
+    | libc::IN_DELETE_SELF
+    | libc::IN_MOVE_SELF;
+
+/// How often the watcher thread checks whether it should stop
+const POLL_INTERVAL_MS: libc::c_int = 200;
+
+/// Watches directories and reports changes inside them
+///
+/// Watching is not recursive; each directory of interest is added with
+/// `watch`. The callback runs on the watcher's own thread with the
+/// directory that changed, once per change.
+pub struct DirWatcher {
+    fd: RawFd,
+    watches: Arc<Mutex<HashMap<i32, PathBuf>>>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl DirWatcher {
+    /// Start a watcher calling `on_change` for every change it sees
+    pub fn new<F>(on_change: F) -> io::Result<Self>
+    where
+        F: Fn(&Path) + Send + 'static,
+    {
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        
+        let watches = Arc::new(Mutex::new(HashMap::new()));
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let watches = watches.clone();
+            let stop = stop.clone();
+            thread::Builder::new()
+                .name("fs-watch".to_string())
+                .spawn(move || watch_loop(fd, &watches, &stop, on_change))
+        };
+        let thread = match thread {
+            Ok(thread) => thread,
+            Err(e) => {
+                unsafe { libc::close(fd) };
+                return Err(e);
+            }
+        };
+        
+        Ok(Self {
+            fd,
+            watches,
+            stop,
+            thread: Some(thread),
+        })
+    }
+    
+    /// Report changes to the entries of `dir`; watching it again is harmless
+    pub fn watch(&self, dir: &Path) -> io::Result<()> {
+        let path = CString::new(dir.as_os_str().as_bytes())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Path contains a NUL byte"))?;
+        let wd = unsafe { libc::inotify_add_watch(self.fd, path.as_ptr(), WATCH_MASK) };
+        if wd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        
+        self.watches.lock().unwrap().insert(wd, dir.to_path_buf());
+        Ok(())
+    }
+    
+    /// Number of directories being watched
+    pub fn watched(&self) -> usize {
+        self.watches.lock().unwrap().len()
+    }
+}
+
+impl Drop for DirWatcher {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        unsafe { libc::close(self.fd) };
+    }
+}
+
+fn watch_loop<F: Fn(&Path)>(fd: RawFd, watches: &Mutex<HashMap<i32, PathBuf>>, stop: &AtomicBool, on_change: F) {
+    // Aligned for inotify_event, with room for many events at once
+    let mut buf = vec![0u64; 1024];
+    let header = std::mem::size_of::<libc::inotify_event>();
+    
+    while !stop.load(Ordering::SeqCst) {
+        let mut pollfd = libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        if unsafe { libc::poll(&mut pollfd, 1, POLL_INTERVAL_MS) } <= 0 {
+            continue;
+        }
+        
+        let len = unsafe { libc::read(fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len() * 8) };
+        if len <= 0 {
+            continue;
+        }
+        let bytes = unsafe { std::slice::from_raw_parts(buf.as_ptr() as *const u8, len as usize) };
+        
+        let mut offset = 0;
+        while offset + header <= bytes.len() {
+            let event = unsafe { std::ptr::read_unaligned(bytes[offset..].as_ptr() as *const libc::inotify_event) };
+            offset += header + event.len as usize;
+            
+            let dir = {
+                let mut watches = watches.lock().unwrap();
+                if event.mask & libc::IN_IGNORED != 0 {
+                    // The directory is gone or no longer watched
+                    watches.remove(&event.wd)
+                } else {
+                    watches.get(&event.wd).cloned()
+                }
+            };
+            if let Some(dir) = dir {
+                on_change(&dir);
+            }
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,360 @@
+//! Experimental HTTP/3 listener over QUIC
+//!
+//! Built on quinn and h3 behind the `h3` feature. Each request is turned into
+//! the server's own `Request` and answered by the same `Router` the HTTP/1.1
+//! listener uses, so pointing `ServerConfig::alt_svc` at this listener serves
+//! the same routes over both. Handlers are synchronous and run on tokio's
+//! blocking pool; protocol upgrades and streamed bodies aren't supported.
+//! The certificate is loaded again whenever its files change.
+
+use crate::error::{ErrorResponse, ServerError, ServerResult};
+use crate::events::{EventBus, ServerEvent};
+use crate::http::{Method, Request, Response, Status};
+use crate::router::Router;
+use bytes::{Buf, Bytes};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime};
+
+/// Headers HTTP/3 doesn't allow, as the connection is managed by QUIC
+const CONNECTION_HEADERS: [&str; 5] = ["connection", "keep-alive", "proxy-connection", "transfer-encoding", "upgrade"];
+
+/// Settings for the HTTP/3 listener
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct H3Config {
+    /// UDP address to listen on
+    pub listen: SocketAddr,
+    
+    /// PEM file with the certificate chain, leaf first
+    pub cert_path: PathBuf,
+    
+    /// PEM file with the private key
+    pub key_path: PathBuf,
+    
+    /// Domain the certificate is for, named in `CertificateRenewed` events
+    #[serde(default)]
+    pub domain: Option<String>,
+    
+    /// How often to check the certificate and key files for changes; a
+    /// changed pair is loaded for new connections
+    #[serde(default = "default_cert_check_interval")]
+    pub cert_check_interval: Duration,
+    
+    /// Largest request body accepted; bigger ones get 413
+    #[serde(default = "default_max_body_size")]
+    pub max_body_size: usize,
+}
+
+fn default_cert_check_interval() -> Duration {
+    Duration::from_secs(60)
+}
+
+fn default_max_body_size() -> usize {
+    1024 * 1024
+}
+
+impl H3Config {
+    /// Listen on `listen` with the certificate and key in the given PEM files
+    pub fn new(listen: SocketAddr, cert_path: &str, key_path: &str) -> Self {
+        Self {
+            listen,
+            cert_path: PathBuf::from(cert_path),
+            key_path: PathBuf::from(key_path),
+            domain: None,
+            cert_check_interval: default_cert_check_interval(),
+            max_body_size: default_max_body_size(),
+        }
+    }
+    
+    /// Name the domain the certificate is for
+    pub fn with_domain(mut self, domain: &str) -> Self {
+        self.domain = Some(domain.to_string());
+        self
+    }
+    
+    /// Set how often the certificate files are checked for changes
+    pub fn with_cert_check_interval(mut self, interval: Duration) -> Self {
+        self.cert_check_interval = interval;
+        self
+    }
+    
+    /// Set the largest request body accepted
+    pub fn with_max_body_size(mut self, max_body_size: usize) -> Self {
+        self.max_body_size = max_body_size;
+        self
+    }
+    
+    /// Load the certificate and key into a QUIC server configuration
+    fn server_config(&self) -> ServerResult<quinn::ServerConfig> {
+        let certs = CertificateDer::pem_file_iter(&self.cert_path)
+            .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+            .map_err(|e| ServerError::Config(format!("Cannot read {}: {}", self.cert_path.display(), e)))?;
+        let key = PrivateKeyDer::from_pem_file(&self.key_path)
+            .map_err(|e| ServerError::Config(format!("Cannot read {}: {}", self.key_path.display(), e)))?;
+        
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let mut tls = rustls::ServerConfig::builder_with_provider(provider)
+            .with_protocol_versions(&[&rustls::version::TLS13])
+            .and_then(|builder| builder.with_no_client_auth().with_single_cert(certs, key))
+            .map_err(|e| ServerError::Config(format!("Invalid HTTP/3 certificate: {}", e)))?;
+        tls.alpn_protocols = vec![b"h3".to_vec()];
+        let crypto = quinn::crypto::rustls::QuicServerConfig::try_from(tls)
+            .map_err(|e| ServerError::Config(format!("Invalid HTTP/3 TLS settings: {}", e)))?;
+        Ok(quinn::ServerConfig::with_crypto(Arc::new(crypto)))
+    }
+    
+    /// Modification times of the certificate and key files
+    fn modified(&self) -> (Option<SystemTime>, Option<SystemTime>) {
+        let modified = |path: &PathBuf| fs::metadata(path).and_then(|meta| meta.modified()).ok();
+        (modified(&self.cert_path), modified(&self.key_path))
+    }
+}
+
+/// A bound HTTP/3 endpoint, not yet accepting connections
+pub struct H3Listener {
+    runtime: tokio::runtime::Runtime,
+    endpoint: quinn::Endpoint,
+    router: Arc<Router>,
+    config: H3Config,
+    events: Option<Arc<EventBus>>,
+}
+
+impl H3Listener {
+    /// Load the certificate and key and bind the UDP socket
+    pub fn bind(config: &H3Config, router: Arc<Router>) -> ServerResult<Self> {
+        let server_config = config.server_config()?;
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .thread_name("h3")
+            .enable_all()
+            .build()?;
+        // quinn drives the socket from the runtime it is created in
+        let endpoint = {
+            let _entered = runtime.enter();
+            quinn::Endpoint::server(server_config, config.listen)
+                .map_err(|e| ServerError::Startup(format!("Cannot bind HTTP/3 on {}: {}", config.listen, e)))?
+        };
+        
+        Ok(Self {
+            runtime,
+            endpoint,
+            router,
+            config: config.clone(),
+            events: None,
+        })
+    }
+    
+    /// Emit `CertificateRenewed` on `events` whenever the certificate is reloaded
+    pub fn with_events(mut self, events: Arc<EventBus>) -> Self {
+        self.events = Some(events);
+        self
+    }
+    
+    /// Get the address the endpoint is bound to
+    pub fn local_addr(&self) -> ServerResult<SocketAddr> {
+        Ok(self.endpoint.local_addr()?)
+    }
+    
+    /// Accept connections in a background thread until stopped
+    pub fn spawn(self) -> H3Handle {
+        let endpoint = self.endpoint.clone();
+        let thread = std::thread::spawn(move || {
+            let Self { runtime, endpoint, router, config, events } = self;
+            runtime.block_on(async move {
+                let max_body_size = config.max_body_size;
+                tokio::spawn(watch_certificate(endpoint.clone(), config, events));
+                while let Some(incoming) = endpoint.accept().await {
+                    tokio::spawn(serve_connection(incoming, router.clone(), max_body_size));
+                }
+                endpoint.wait_idle().await;
+            });
+        });
+        
+        H3Handle { endpoint, thread }
+    }
+}
+
+/// Load the certificate again whenever its files change
+///
+/// Runs until the listener's runtime shuts down. A pair that fails to load,
+/// such as a certificate whose new key isn't written yet, is retried once
+/// either file changes again.
+async fn watch_certificate(endpoint: quinn::Endpoint, config: H3Config, events: Option<Arc<EventBus>>) {
+    let mut loaded = config.modified();
+    let mut interval = tokio::time::interval(config.cert_check_interval);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        let modified = config.modified();
+        if modified == loaded {
+            continue;
+        }
+        loaded = modified;
+        
+        match config.server_config() {
+            Ok(server_config) => {
+                endpoint.set_server_config(Some(server_config));
+                log::info!("Reloaded HTTP/3 certificate {}", config.cert_path.display());
+                if let Some(events) = &events {
+                    let domain = config.domain.clone().unwrap_or_else(|| config.cert_path.display().to_string());
+                    events.emit(ServerEvent::CertificateRenewed { domain });
+                }
+            }
+            Err(e) => log::warn!("Keeping the current HTTP/3 certificate: {}", e),
+        }
+    }
+}
+
+/// Handle to a running HTTP/3 listener
+pub struct H3Handle {
+    endpoint: quinn::Endpoint,
+    thread: JoinHandle<()>,
+}
+
+impl H3Handle {
+    /// Close every connection and wait for the listener thread
+    pub fn stop(self) {
+        self.endpoint.close(0u32.into(), b"shutting down");
+        let _ = self.thread.join();
+    }
+}
+
+type RequestStream = ::h3::server::RequestStream<h3_quinn::BidiStream<Bytes>, Bytes>;
+
+/// Answer the requests on one connection until the client closes it
+async fn serve_connection(incoming: quinn::Incoming, router: Arc<Router>, max_body_size: usize) {
+    let connection = match incoming.await {
+        Ok(connection) => connection,
+        Err(e) => {
+            log::debug!("HTTP/3 handshake failed: {}", e);
+            return;
+        }
+    };
+    let peer_addr = connection.remote_address();
+    let mut connection = match ::h3::server::Connection::new(h3_quinn::Connection::new(connection)).await {
+        Ok(connection) => connection,
+        Err(e) => {
+            log::debug!("HTTP/3 connection from {} failed: {}", peer_addr, e);
+            return;
+        }
+    };
+    
+    loop {
+        match connection.accept().await {
+            Ok(Some(resolver)) => {
+                let router = router.clone();
+                tokio::spawn(async move {
+                    match resolver.resolve_request().await {
+                        Ok((head, stream)) => {
+                            if let Err(e) = serve_request(head, stream, router, peer_addr, max_body_size).await {
+                                log::debug!("HTTP/3 request from {} failed: {}", peer_addr, e);
+                            }
+                        }
+                        Err(e) => log::debug!("Bad HTTP/3 request from {}: {}", peer_addr, e),
+                    }
+                });
+            }
+            Ok(None) => break,
+            Err(e) => {
+                log::debug!("HTTP/3 connection from {} closed: {}", peer_addr, e);
+                break;
+            }
+        }
+    }
+}
+
+/// Read one request, run it through the router and send the response
+async fn serve_request(
+    head: ::http::Request<()>,
+    mut stream: RequestStream,
+    router: Arc<Router>,
+    peer_addr: SocketAddr,
+    max_body_size: usize,
+) -> ServerResult<()> {
+    let response = match to_request(&head, peer_addr) {
+        Ok(mut request) => match read_body(&mut stream, max_body_size).await? {
+            Some(body) => {
+                request.body = body;
+                // A handler that panics gets a 500 rather than a reset stream
+                tokio::task::spawn_blocking(move || {
+                    router.handle_request(&request).unwrap_or_else(|e| e.to_response(&request))
+                })
+                .await
+                .unwrap_or_else(|_| Response::new(Status::InternalServerError))
+            }
+            None => Response::new(Status::PayloadTooLarge),
+        },
+        Err(e) => ErrorResponse::from_error(&e, None).into_response(e.status()),
+    };
+    
+    let (head, body) = from_response(response)?;
+    stream.send_response(head).await.map_err(stream_error)?;
+    if !body.is_empty() {
+        stream.send_data(Bytes::from(body)).await.map_err(stream_error)?;
+    }
+    stream.finish().await.map_err(stream_error)
+}
+
+/// Read the request body, or None if it is larger than `max_body_size`
+async fn read_body(stream: &mut RequestStream, max_body_size: usize) -> ServerResult<Option<Vec<u8>>> {
+    let mut body = Vec::new();
+    while let Some(mut chunk) = stream.recv_data().await.map_err(stream_error)? {
+        if body.len() + chunk.remaining() > max_body_size {
+            return Ok(None);
+        }
+        while chunk.has_remaining() {
+            let part = chunk.chunk();
+            body.extend_from_slice(part);
+            let len = part.len();
+            chunk.advance(len);
+        }
+    }
+    Ok(Some(body))
+}
+
+/// Convert an HTTP/3 request head into a `Request`
+fn to_request(head: &::http::Request<()>, peer_addr: SocketAddr) -> ServerResult<Request> {
+    let method = Method::from_str(head.method().as_str())?;
+    let target = head.uri().path_and_query().map_or("/", |target| target.as_str());
+    let mut request = Request::new(method, target);
+    for (name, value) in head.headers() {
+        let value = value
+            .to_str()
+            .map_err(|_| ServerError::HttpParse(format!("Header {} is not visible ASCII", name)))?;
+        request.set_header(name.as_str(), value);
+    }
+    // :authority stands in for Host
+    if let Some(authority) = head.uri().authority() {
+        if request.get_header("host").is_none() {
+            request.set_header("Host", authority.as_str());
+        }
+    }
+    request.peer_addr = Some(peer_addr);
+    Ok(request)
+}
+
+/// Convert a `Response` into an HTTP/3 response head and body
+fn from_response(mut response: Response) -> ServerResult<(::http::Response<()>, Vec<u8>)> {
+    response.thaw();
+    let mut head = ::http::Response::builder().status(response.status as u16);
+    for (name, value) in &response.headers {
+        if CONNECTION_HEADERS.iter().any(|header| name.eq_ignore_ascii_case(header)) {
+            continue;
+        }
+        head = head.header(name.as_str(), value.as_str());
+    }
+    let head = head
+        .body(())
+        .map_err(|e| ServerError::Protocol(format!("Cannot send response over HTTP/3: {}", e)))?;
+    Ok((head, response.body))
+}
+
+fn stream_error(e: ::h3::error::StreamError) -> ServerError {
+    ServerError::Connection(format!("HTTP/3 stream error: {}", e))
+}
\ No newline at end of file
@@ -0,0 +1,532 @@
+//! Request captures in HAR format
+//!
+//! An opt-in recorder that writes a sample of request/response pairs to
+//! HTTP Archive (HAR 1.2) files, which browsers' dev tools and most HTTP
+//! debugging tools can open. Everything written goes through a `Redactor`
+//! first, so credentials and sensitive fields never reach the capture.
+//!
+//! Each capture file is a complete HAR document once it is rotated away.
+//! The file still being written lacks its closing brackets until then;
+//! `read_har` accepts it either way.
+
+use crate::error::{ServerError, ServerResult};
+use crate::http::{Request, Response};
+use crate::log_file::{reopen_generation, RotatingFile};
+use crate::middleware::MiddlewareNext;
+use crate::preconditions::{civil_from_days, days_from_civil};
+use crate::redaction::{RedactionConfig, Redactor};
+use flate2::read::GzDecoder;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Closes the entries array and the document
+const FOOTER: &str = "\n]}}\n";
+
+/// Settings for capturing requests to HAR files
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HarConfig {
+    /// File captures are written to; rotated files get `.1`, `.2`, ...
+    pub path: String,
+    
+    /// Share of requests captured, from 0 to 100
+    pub percent: f64,
+    
+    /// Path prefixes to capture (empty = all)
+    pub paths: Vec<String>,
+    
+    /// Bytes of each body kept; longer bodies are cut short
+    pub max_body_size: usize,
+    
+    /// Start a new file once the current one would grow beyond this
+    pub max_file_size: u64,
+    
+    /// Start a new file once the current one is this old
+    pub max_file_age: Option<Duration>,
+    
+    /// Rotated files kept
+    pub keep: usize,
+    
+    /// Gzip rotated files
+    pub compress: bool,
+    
+    /// Headers and fields masked before anything is written
+    pub redaction: RedactionConfig,
+}
+
+impl Default for HarConfig {
+    fn default() -> Self {
+        Self {
+            path: "capture.har".to_string(),
+            percent: 100.0,
+            paths: Vec::new(),
+            max_body_size: 64 * 1024,
+            max_file_size: 64 * 1024 * 1024,
+            max_file_age: None,
+            keep: 5,
+            compress: false,
+            redaction: RedactionConfig::default(),
+        }
+    }
+}
+
+/// A header, query parameter or cookie
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HarNameValue {
+    pub name: String,
+    pub value: String,
+}
+
+/// A request body
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HarPostData {
+    pub mime_type: String,
+    pub text: String,
+    /// `base64` for bodies that aren't UTF-8 (HAR has no standard field for this)
+    #[serde(rename = "_encoding", default, skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<String>,
+    /// Notes such as the body having been cut short
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+}
+
+/// A captured request
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HarRequest {
+    pub method: String,
+    pub url: String,
+    pub http_version: String,
+    /// Always empty; cookies are kept (redacted) in `headers`
+    pub cookies: Vec<HarNameValue>,
+    pub headers: Vec<HarNameValue>,
+    pub query_string: Vec<HarNameValue>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post_data: Option<HarPostData>,
+    /// -1, as the head isn't kept in its original form
+    pub headers_size: i64,
+    pub body_size: i64,
+}
+
+/// A response body
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HarContent {
+    /// Size of the whole body, even when `text` was cut short
+    pub size: i64,
+    pub mime_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    /// `base64` for bodies that aren't UTF-8
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<String>,
+    /// Notes such as the body having been cut short
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+}
+
+/// A captured response
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HarResponse {
+    pub status: u16,
+    pub status_text: String,
+    pub http_version: String,
+    /// Always empty; cookies are kept (redacted) in `headers`
+    pub cookies: Vec<HarNameValue>,
+    pub headers: Vec<HarNameValue>,
+    pub content: HarContent,
+    #[serde(rename = "redirectURL")]
+    pub redirect_url: String,
+    pub headers_size: i64,
+    pub body_size: i64,
+}
+
+/// How long each part of the exchange took, in milliseconds
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HarTimings {
+    pub send: f64,
+    /// Time spent handling the request
+    pub wait: f64,
+    pub receive: f64,
+}
+
+/// One captured request/response pair
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HarEntry {
+    /// When the request started, as an ISO 8601 UTC timestamp
+    pub started_date_time: String,
+    /// Total time in milliseconds
+    pub time: f64,
+    pub request: HarRequest,
+    pub response: HarResponse,
+    pub cache: BTreeMap<String, Value>,
+    pub timings: HarTimings,
+}
+
+#[derive(Deserialize)]
+struct HarDocument {
+    log: HarLog,
+}
+
+#[derive(Deserialize)]
+struct HarLog {
+    entries: Vec<HarEntry>,
+}
+
+/// The capture file being written
+struct CaptureFile {
+    file: RotatingFile,
+    generation: usize,
+    header: String,
+}
+
+impl CaptureFile {
+    /// Append one serialized entry, starting a new file first if the current one is full
+    fn write_entry(&mut self, entry: &str, max_size: u64, max_age: Option<Duration>) -> io::Result<()> {
+        // A reopen request means the file was moved away; carry on in a new one
+        let generation = reopen_generation();
+        if generation != self.generation {
+            self.generation = generation;
+            self.file.reopen()?;
+        }
+        
+        let incoming = (entry.len() + FOOTER.len()) as u64;
+        let too_big = self.file.size() + incoming > max_size;
+        let too_old = max_age.is_some_and(|age| self.file.age() >= age);
+        if self.file.size() > self.header.len() as u64 && (too_big || too_old) {
+            self.file.write_all(FOOTER.as_bytes())?;
+            self.file.rotate()?;
+        }
+        
+        if self.file.size() == 0 {
+            self.file.write_all(self.header.as_bytes())?;
+        } else if self.file.size() > self.header.len() as u64 {
+            self.file.write_all(b",\n")?;
+        }
+        self.file.write_all(entry.as_bytes())?;
+        self.file.flush()
+    }
+    
+    /// Close the document in the current file
+    fn finish(&mut self) -> io::Result<()> {
+        if self.file.size() > 0 {
+            self.file.write_all(FOOTER.as_bytes())?;
+            self.file.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Records a sample of requests and their responses to HAR files
+///
+/// Requests are picked evenly rather than at random, as with the mirror:
+/// at 10 percent, every tenth request under the configured paths is
+/// captured. A capture file left over from an earlier run is rotated away
+/// on startup, so every file holds one document.
+pub struct HarRecorder {
+    capture: Mutex<CaptureFile>,
+    paths: Vec<String>,
+    percent: f64,
+    seen: AtomicU64,
+    max_body_size: usize,
+    max_file_size: u64,
+    max_file_age: Option<Duration>,
+    redactor: Redactor,
+}
+
+impl HarRecorder {
+    /// Open the capture file in `config`
+    pub fn new(config: HarConfig) -> ServerResult<Self> {
+        if !(0.0..=100.0).contains(&config.percent) {
+            return Err(ServerError::Config(format!(
+                "Capture percent must be between 0 and 100, not {}",
+                config.percent
+            )));
+        }
+        
+        let mut file = RotatingFile::open(&config.path)?.with_keep(config.keep).with_compression(config.compress);
+        if file.size() > 0 {
+            file.rotate()?;
+        }
+        let creator = serde_json::json!({"name": env!("CARGO_PKG_NAME"), "version": env!("CARGO_PKG_VERSION")});
+        let header = format!("{{\"log\":{{\"version\":\"1.2\",\"creator\":{},\"entries\":[\n", creator);
+        Ok(Self {
+            capture: Mutex::new(CaptureFile {
+                file,
+                generation: reopen_generation(),
+                header,
+            }),
+            paths: config.paths,
+            percent: config.percent,
+            seen: AtomicU64::new(0),
+            max_body_size: config.max_body_size,
+            max_file_size: config.max_file_size,
+            max_file_age: config.max_file_age,
+            redactor: Redactor::new(config.redaction),
+        })
+    }
+    
+    /// Decide whether to capture `request`
+    ///
+    /// Each call for a request under the configured paths counts towards
+    /// the sampled share, so call it once per request.
+    pub fn should_record(&self, request: &Request) -> bool {
+        if !self.paths.is_empty() && !self.paths.iter().any(|prefix| request.path().starts_with(prefix.as_str())) {
+            return false;
+        }
+        let n = self.seen.fetch_add(1, Ordering::Relaxed) as f64;
+        ((n + 1.0) * self.percent / 100.0).floor() > (n * self.percent / 100.0).floor()
+    }
+    
+    /// Build the redacted entry for an exchange that started at `started` and took `elapsed`
+    pub fn entry(&self, request: &Request, response: &Response, started: SystemTime, elapsed: Duration) -> HarEntry {
+        let uri = self.redactor.uri(&request.uri);
+        let query_string = uri
+            .split_once('?')
+            .map(|(_, query)| {
+                query
+                    .split('&')
+                    .filter(|pair| !pair.is_empty())
+                    .map(|pair| {
+                        let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+                        HarNameValue {
+                            name: name.to_string(),
+                            value: value.to_string(),
+                        }
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        
+        let request_type = request.get_header("content-type").map(String::as_str);
+        let post_data = if request.body.is_empty() {
+            None
+        } else {
+            let (text, encoding, comment) = self.body_text(request_type, &request.body);
+            Some(HarPostData {
+                mime_type: request_type.unwrap_or("").to_string(),
+                text,
+                encoding,
+                comment,
+            })
+        };
+        
+        let response_type = response
+            .headers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case("content-type"))
+            .map(|(_, value)| value.as_str());
+        let (text, encoding, comment) = self.body_text(response_type, &response.body);
+        let redirect_url = response
+            .headers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case("location"))
+            .map_or_else(String::new, |(_, value)| value.clone());
+        
+        let millis = elapsed.as_secs_f64() * 1000.0;
+        HarEntry {
+            started_date_time: format_timestamp(started),
+            time: millis,
+            request: HarRequest {
+                method: request.method.as_str().to_string(),
+                url: format!("http://{}{}", request.host.as_deref().unwrap_or("localhost"), uri),
+                http_version: "HTTP/1.1".to_string(),
+                cookies: Vec::new(),
+                headers: self.headers(&request.headers),
+                query_string,
+                post_data,
+                headers_size: -1,
+                body_size: request.body.len() as i64,
+            },
+            response: HarResponse {
+                status: response.status as u16,
+                status_text: response.status.as_str().to_string(),
+                http_version: "HTTP/1.1".to_string(),
+                cookies: Vec::new(),
+                headers: self.headers(&response.headers),
+                content: HarContent {
+                    size: response.body.len() as i64,
+                    mime_type: response_type.unwrap_or("").to_string(),
+                    text: (!response.body.is_empty()).then_some(text),
+                    encoding,
+                    comment,
+                },
+                redirect_url,
+                headers_size: -1,
+                body_size: response.body.len() as i64,
+            },
+            cache: BTreeMap::new(),
+            timings: HarTimings {
+                send: 0.0,
+                wait: millis,
+                receive: 0.0,
+            },
+        }
+    }
+    
+    /// Write an exchange to the capture file
+    ///
+    /// Failures to write are logged rather than passed on, so a full disk
+    /// never fails the request being captured.
+    pub fn record(&self, request: &Request, response: &Response, started: SystemTime, elapsed: Duration) {
+        let entry = match serde_json::to_string(&self.entry(request, response, started, elapsed)) {
+            Ok(entry) => entry,
+            Err(e) => {
+                log::warn!("Failed to serialize HAR entry: {}", e);
+                return;
+            }
+        };
+        let mut capture = self.capture.lock().unwrap();
+        if let Err(e) = capture.write_entry(&entry, self.max_file_size, self.max_file_age) {
+            log::warn!("Failed to write HAR capture {}: {}", capture.file.path().display(), e);
+        }
+    }
+    
+    /// Sorted, redacted headers
+    fn headers<K: AsRef<str>>(&self, headers: &HashMap<K, String>) -> Vec<HarNameValue> {
+        let mut headers: Vec<HarNameValue> = headers
+            .iter()
+            .map(|(name, value)| HarNameValue {
+                name: name.as_ref().to_string(),
+                value: self.redactor.header(name.as_ref(), value).to_string(),
+            })
+            .collect();
+        headers.sort_by(|a, b| a.name.cmp(&b.name));
+        headers
+    }
+    
+    /// Redact a body and cut it to `max_body_size`, returning its text, encoding and a comment
+    fn body_text(&self, content_type: Option<&str>, body: &[u8]) -> (String, Option<String>, Option<String>) {
+        let redacted = self.redactor.body(content_type, body);
+        let kept = &redacted[..redacted.len().min(self.max_body_size)];
+        let comment = (kept.len() < redacted.len()).then(|| format!("truncated to {} bytes", kept.len()));
+        let text = match std::str::from_utf8(kept) {
+            Ok(text) => Some(text),
+            // Cutting the body short may have split the last character
+            Err(e) if e.error_len().is_none() && comment.is_some() => {
+                std::str::from_utf8(&kept[..e.valid_up_to()]).ok()
+            }
+            Err(_) => None,
+        };
+        match text {
+            Some(text) => (text.to_string(), None, comment),
+            None => (base64::encode(kept), Some("base64".to_string()), comment),
+        }
+    }
+}
+
+impl Drop for HarRecorder {
+    fn drop(&mut self) {
+        if let Err(e) = self.capture.get_mut().unwrap().finish() {
+            log::warn!("Failed to finish HAR capture: {}", e);
+        }
+    }
+}
+
+impl std::fmt::Debug for HarRecorder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HarRecorder")
+            .field("paths", &self.paths)
+            .field("percent", &self.percent)
+            .field("max_body_size", &self.max_body_size)
+            .finish()
+    }
+}
+
+/// Format a time as an ISO 8601 UTC timestamp with milliseconds
+fn format_timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs() as i64;
+    let rem = secs.rem_euclid(86_400);
+    let (year, month, day) = civil_from_days(secs.div_euclid(86_400));
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        (rem % 3600) / 60,
+        rem % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+/// Parse an ISO 8601 UTC timestamp as written by `format_timestamp`
+///
+/// Fractions of a second are optional and kept to the millisecond.
+pub(crate) fn parse_timestamp(value: &str) -> Option<SystemTime> {
+    let value = value.strip_suffix('Z')?;
+    let (date, time) = value.split_once('T')?;
+    let mut date = date.splitn(3, '-').map(str::parse::<i64>);
+    let (year, month, day) = (date.next()?.ok()?, date.next()?.ok()?, date.next()?.ok()?);
+    let (time, fraction) = time.split_once('.').unwrap_or((time, "0"));
+    let mut time = time.splitn(3, ':').map(str::parse::<u64>);
+    let (hour, minute, second) = (time.next()?.ok()?, time.next()?.ok()?, time.next()?.ok()?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+    let millis: u64 = format!("{:0<3}", fraction).get(..3)?.parse().ok()?;
+    let days = u64::try_from(days_from_civil(year, month as u32, day as u32)).ok()?;
+    let secs = days * 86_400 + hour * 3600 + minute * 60 + second;
+    Some(UNIX_EPOCH + Duration::from_secs(secs) + Duration::from_millis(millis))
+}
+
+/// Parse the entries of a HAR document
+///
+/// A capture file still being written, which lacks its closing brackets,
+/// is accepted too.
+pub fn parse_har(content: &str) -> ServerResult<Vec<HarEntry>> {
+    match serde_json::from_str::<HarDocument>(content) {
+        Ok(document) => Ok(document.log.entries),
+        Err(e) => {
+            let closed = format!("{}{}", content.trim_end().trim_end_matches(','), FOOTER);
+            match serde_json::from_str::<HarDocument>(&closed) {
+                Ok(document) => Ok(document.log.entries),
+                Err(_) => Err(e.into()),
+            }
+        }
+    }
+}
+
+/// Read the entries of a HAR file, gunzipping it if its name ends in `.gz`
+pub fn read_har<P: AsRef<Path>>(path: P) -> ServerResult<Vec<HarEntry>> {
+    let path = path.as_ref();
+    let mut content = String::new();
+    if path.extension().is_some_and(|extension| extension == "gz") {
+        GzDecoder::new(File::open(path)?).read_to_string(&mut content)?;
+    } else {
+        File::open(path)?.read_to_string(&mut content)?;
+    }
+    parse_har(&content)
+}
+
+/// HAR middleware - captures a sample of requests and their responses
+///
+/// Place it outside `error_middleware` to capture the error responses too;
+/// requests whose handler fails with an error are not captured.
+pub fn har_middleware(
+    recorder: Arc<HarRecorder>,
+) -> impl Fn(&Request, MiddlewareNext) -> ServerResult<Response> + Send + Sync {
+    move |request, next| {
+        if !recorder.should_record(request) {
+            return next(request);
+        }
+        let started = SystemTime::now();
+        let start = Instant::now();
+        let response = next(request);
+        if let Ok(response) = &response {
+            recorder.record(request, response, started, start.elapsed());
+        }
+        response
+    }
+}
\ No newline at end of file
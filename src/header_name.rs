@@ -0,0 +1,248 @@
+//! Interned request header names
+//!
+//! Request header names are matched case-insensitively, so they are kept
+//! lowercase. The common ones are stored as a `StandardHeader` instead of
+//! a string, which spares an allocation per header per request and lets
+//! two of them be compared by discriminant. Anything else falls back to
+//! an owned lowercase string.
+
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+
+macro_rules! standard_headers {
+    ($($variant:ident, $constant:ident => $name:literal;)+) => {
+        /// A header name common enough to be interned
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum StandardHeader {
+            $($variant,)+
+        }
+        
+        impl StandardHeader {
+            /// Every interned header name
+            pub const ALL: &'static [StandardHeader] = &[$(StandardHeader::$variant,)+];
+            
+            /// Get the lowercase name
+            pub fn as_str(self) -> &'static str {
+                match self {
+                    $(StandardHeader::$variant => $name,)+
+                }
+            }
+            
+            /// Find the interned header for an already lowercase name
+            pub fn from_lowercase(name: &str) -> Option<Self> {
+                match name {
+                    $($name => Some(StandardHeader::$variant),)+
+                    _ => None,
+                }
+            }
+        }
+        
+        impl HeaderName {
+            $(pub const $constant: HeaderName = HeaderName(Repr::Standard(StandardHeader::$variant));)+
+        }
+    };
+}
+
+standard_headers! {
+    Accept, ACCEPT => "accept";
+    AcceptCharset, ACCEPT_CHARSET => "accept-charset";
+    AcceptEncoding, ACCEPT_ENCODING => "accept-encoding";
+    AcceptLanguage, ACCEPT_LANGUAGE => "accept-language";
+    AccessControlRequestHeaders, ACCESS_CONTROL_REQUEST_HEADERS => "access-control-request-headers";
+    AccessControlRequestMethod, ACCESS_CONTROL_REQUEST_METHOD => "access-control-request-method";
+    Authorization, AUTHORIZATION => "authorization";
+    CacheControl, CACHE_CONTROL => "cache-control";
+    Connection, CONNECTION => "connection";
+    ContentEncoding, CONTENT_ENCODING => "content-encoding";
+    ContentLength, CONTENT_LENGTH => "content-length";
+    ContentType, CONTENT_TYPE => "content-type";
+    Cookie, COOKIE => "cookie";
+    Date, DATE => "date";
+    Dnt, DNT => "dnt";
+    Expect, EXPECT => "expect";
+    Forwarded, FORWARDED => "forwarded";
+    Host, HOST => "host";
+    IfMatch, IF_MATCH => "if-match";
+    IfModifiedSince, IF_MODIFIED_SINCE => "if-modified-since";
+    IfNoneMatch, IF_NONE_MATCH => "if-none-match";
+    IfRange, IF_RANGE => "if-range";
+    IfUnmodifiedSince, IF_UNMODIFIED_SINCE => "if-unmodified-since";
+    KeepAlive, KEEP_ALIVE => "keep-alive";
+    Origin, ORIGIN => "origin";
+    Pragma, PRAGMA => "pragma";
+    Priority, PRIORITY => "priority";
+    ProxyAuthorization, PROXY_AUTHORIZATION => "proxy-authorization";
+    Range, RANGE => "range";
+    Referer, REFERER => "referer";
+    SecChUa, SEC_CH_UA => "sec-ch-ua";
+    SecChUaMobile, SEC_CH_UA_MOBILE => "sec-ch-ua-mobile";
+    SecChUaPlatform, SEC_CH_UA_PLATFORM => "sec-ch-ua-platform";
+    SecFetchDest, SEC_FETCH_DEST => "sec-fetch-dest";
+    SecFetchMode, SEC_FETCH_MODE => "sec-fetch-mode";
+    SecFetchSite, SEC_FETCH_SITE => "sec-fetch-site";
+    SecFetchUser, SEC_FETCH_USER => "sec-fetch-user";
+    SecWebSocketExtensions, SEC_WEBSOCKET_EXTENSIONS => "sec-websocket-extensions";
+    SecWebSocketKey, SEC_WEBSOCKET_KEY => "sec-websocket-key";
+    SecWebSocketProtocol, SEC_WEBSOCKET_PROTOCOL => "sec-websocket-protocol";
+    SecWebSocketVersion, SEC_WEBSOCKET_VERSION => "sec-websocket-version";
+    Te, TE => "te";
+    Traceparent, TRACEPARENT => "traceparent";
+    TransferEncoding, TRANSFER_ENCODING => "transfer-encoding";
+    Upgrade, UPGRADE => "upgrade";
+    UpgradeInsecureRequests, UPGRADE_INSECURE_REQUESTS => "upgrade-insecure-requests";
+    UserAgent, USER_AGENT => "user-agent";
+    Via, VIA => "via";
+    XForwardedFor, X_FORWARDED_FOR => "x-forwarded-for";
+    XForwardedHost, X_FORWARDED_HOST => "x-forwarded-host";
+    XForwardedProto, X_FORWARDED_PROTO => "x-forwarded-proto";
+    XRealIp, X_REAL_IP => "x-real-ip";
+    XRequestId, X_REQUEST_ID => "x-request-id";
+}
+
+/// Longest name lowercased on the stack while looking for its interned form
+const MAX_STACK_NAME: usize = 64;
+
+/// A lowercase request header name, interned when it is a common one
+///
+/// It hashes and borrows as its name, so a map keyed by `HeaderName` can
+/// still be looked up with a lowercase `&str`. Looking up with a
+/// `HeaderName`, such as `HeaderName::HOST`, compares interned names
+/// without touching their bytes.
+#[derive(Clone)]
+pub struct HeaderName(Repr);
+
+#[derive(Clone)]
+enum Repr {
+    Standard(StandardHeader),
+    /// Never the name of a standard header, so equal names share a variant
+    Custom(Box<str>),
+}
+
+impl HeaderName {
+    /// Make a header name from one in any case
+    pub fn new(name: &str) -> Self {
+        if name.len() <= MAX_STACK_NAME {
+            let mut lower = [0u8; MAX_STACK_NAME];
+            let lower = &mut lower[..name.len()];
+            lower.copy_from_slice(name.as_bytes());
+            lower.make_ascii_lowercase();
+            // Lowercasing ASCII bytes keeps the string valid UTF-8
+            if let Some(standard) = std::str::from_utf8(lower).ok().and_then(StandardHeader::from_lowercase) {
+                return HeaderName(Repr::Standard(standard));
+            }
+        }
+        HeaderName(Repr::Custom(name.to_lowercase().into_boxed_str()))
+    }
+    
+    /// Get the interned header, if this is one
+    pub fn standard(&self) -> Option<StandardHeader> {
+        match self.0 {
+            Repr::Standard(standard) => Some(standard),
+            Repr::Custom(_) => None,
+        }
+    }
+    
+    /// Get the lowercase name
+    pub fn as_str(&self) -> &str {
+        match &self.0 {
+            Repr::Standard(standard) => standard.as_str(),
+            Repr::Custom(name) => name,
+        }
+    }
+}
+
+impl From<StandardHeader> for HeaderName {
+    fn from(standard: StandardHeader) -> Self {
+        HeaderName(Repr::Standard(standard))
+    }
+}
+
+impl From<&str> for HeaderName {
+    fn from(name: &str) -> Self {
+        HeaderName::new(name)
+    }
+}
+
+impl From<String> for HeaderName {
+    fn from(name: String) -> Self {
+        HeaderName::new(&name)
+    }
+}
+
+impl PartialEq for HeaderName {
+    fn eq(&self, other: &Self) -> bool {
+        match (&self.0, &other.0) {
+            (Repr::Standard(a), Repr::Standard(b)) => a == b,
+            (Repr::Custom(a), Repr::Custom(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for HeaderName {}
+
+impl PartialEq<str> for HeaderName {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for HeaderName {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl Hash for HeaderName {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // Must match `str`, for lookups through `Borrow<str>`
+        self.as_str().hash(state);
+    }
+}
+
+impl PartialOrd for HeaderName {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for HeaderName {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.as_str().cmp(other.as_str())
+    }
+}
+
+impl Borrow<str> for HeaderName {
+    fn borrow(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl Deref for HeaderName {
+    type Target = str;
+    
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl AsRef<str> for HeaderName {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl fmt::Display for HeaderName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Debug for HeaderName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
\ No newline at end of file
@@ -1,10 +1,23 @@
+use crate::buffer::Buffer;
 use crate::error::{ServerError, ServerResult};
+use crate::header_name::{HeaderName, StandardHeader};
+use crate::leaks::{Instance, Tracked};
+use crate::preconditions::{self, PreconditionOutcome, Preconditions};
+use crate::protocol_upgrade::Upgrade;
+use crate::scan;
+use crate::timeline::ServerTimings;
+use std::any::{Any, TypeId};
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::fmt;
 use std::io::Write;
+use std::net::SocketAddr;
 use std::str;
+use std::sync::Arc;
+use std::time::SystemTime;
 
 /// HTTP Status Codes
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Status {
     Continue = 100,
     SwitchingProtocols = 101,
@@ -13,10 +26,13 @@ pub enum Status {
     Created = 201,
     Accepted = 202,
     NoContent = 204,
+    PartialContent = 206,
     
     MovedPermanently = 301,
     Found = 302,
     NotModified = 304,
+    TemporaryRedirect = 307,
+    PermanentRedirect = 308,
     
     BadRequest = 400,
     Unauthorized = 401,
@@ -24,15 +40,62 @@ pub enum Status {
     NotFound = 404,
     MethodNotAllowed = 405,
     RequestTimeout = 408,
+    Conflict = 409,
+    PreconditionFailed = 412,
     PayloadTooLarge = 413,
+    UnprocessableEntity = 422,
+    UpgradeRequired = 426,
+    TooManyRequests = 429,
+    RequestHeaderFieldsTooLarge = 431,
     
     InternalServerError = 500,
     NotImplemented = 501,
     BadGateway = 502,
     ServiceUnavailable = 503,
+    GatewayTimeout = 504,
+    HttpVersionNotSupported = 505,
 }
 
 impl Status {
+    /// Look up the status for a numeric code, if it is one we support
+    pub fn from_code(code: u16) -> Option<Self> {
+        [
+            Status::Continue,
+            Status::SwitchingProtocols,
+            Status::Ok,
+            Status::Created,
+            Status::Accepted,
+            Status::NoContent,
+            Status::PartialContent,
+            Status::MovedPermanently,
+            Status::Found,
+            Status::NotModified,
+            Status::TemporaryRedirect,
+            Status::PermanentRedirect,
+            Status::BadRequest,
+            Status::Unauthorized,
+            Status::Forbidden,
+            Status::NotFound,
+            Status::MethodNotAllowed,
+            Status::RequestTimeout,
+            Status::Conflict,
+            Status::PreconditionFailed,
+            Status::PayloadTooLarge,
+            Status::UnprocessableEntity,
+            Status::UpgradeRequired,
+            Status::TooManyRequests,
+            Status::RequestHeaderFieldsTooLarge,
+            Status::InternalServerError,
+            Status::NotImplemented,
+            Status::BadGateway,
+            Status::ServiceUnavailable,
+            Status::GatewayTimeout,
+            Status::HttpVersionNotSupported,
+        ]
+        .into_iter()
+        .find(|status| *status as u16 == code)
+    }
+    
     /// Get the text description for this status code
     pub fn as_str(&self) -> &'static str {
         match *self {
@@ -43,10 +106,13 @@ impl Status {
             Status::Created => "Created",
             Status::Accepted => "Accepted",
             Status::NoContent => "No Content",
+            Status::PartialContent => "Partial Content",
             
             Status::MovedPermanently => "Moved Permanently",
             Status::Found => "Found",
             Status::NotModified => "Not Modified",
+            Status::TemporaryRedirect => "Temporary Redirect",
+            Status::PermanentRedirect => "Permanent Redirect",
             
             Status::BadRequest => "Bad Request",
             Status::Unauthorized => "Unauthorized",
@@ -54,12 +120,20 @@ impl Status {
             Status::NotFound => "Not Found",
             Status::MethodNotAllowed => "Method Not Allowed",
             Status::RequestTimeout => "Request Timeout",
+            Status::Conflict => "Conflict",
+            Status::PreconditionFailed => "Precondition Failed",
             Status::PayloadTooLarge => "Payload Too Large",
+            Status::UnprocessableEntity => "Unprocessable Entity",
+            Status::UpgradeRequired => "Upgrade Required",
+            Status::TooManyRequests => "Too Many Requests",
+            Status::RequestHeaderFieldsTooLarge => "Request Header Fields Too Large",
             
             Status::InternalServerError => "Internal Server Error",
             Status::NotImplemented => "Not Implemented",
             Status::BadGateway => "Bad Gateway",
             Status::ServiceUnavailable => "Service Unavailable",
+            Status::GatewayTimeout => "Gateway Timeout",
+            Status::HttpVersionNotSupported => "HTTP Version Not Supported",
         }
     }
 }
@@ -79,6 +153,19 @@ pub enum Method {
 }
 
 impl Method {
+    /// Every method, in declaration order
+    pub const ALL: [Method; 9] = [
+        Method::Get,
+        Method::Head,
+        Method::Post,
+        Method::Put,
+        Method::Delete,
+        Method::Options,
+        Method::Trace,
+        Method::Connect,
+        Method::Patch,
+    ];
+    
     /// Parse a method from a string
     pub fn from_str(s: &str) -> ServerResult<Self> {
         match s {
@@ -111,6 +198,128 @@ impl Method {
     }
 }
 
+/// The form of a request target (RFC 7230 section 5.3)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestTarget {
+    /// `/path?query` - the usual form sent to origin servers
+    Origin,
+    /// `http://host[:port]/path?query` - sent by clients talking to a proxy
+    Absolute,
+    /// `host:port` - only used with CONNECT
+    Authority,
+    /// `*` - only used with server-wide OPTIONS
+    Asterisk,
+}
+
+impl RequestTarget {
+    /// Classify a raw request target and split it into host and origin-form URI
+    ///
+    /// For absolute-form targets the returned URI is rewritten to origin-form so
+    /// that routing works the same way as for a direct request.
+    pub fn parse(method: Method, target: &str) -> ServerResult<(Self, Option<String>, String)> {
+        if target.is_empty() {
+            return Err(ServerError::HttpParse("Empty request target".to_string()));
+        }
+        
+        if target == "*" {
+            if method != Method::Options {
+                return Err(ServerError::HttpParse(
+                    "Asterisk-form target is only allowed with OPTIONS".to_string(),
+                ));
+            }
+            return Ok((RequestTarget::Asterisk, None, target.to_string()));
+        }
+        
+        if method == Method::Connect {
+            if target.starts_with('/') || target.contains("://") || !target.contains(':') {
+                return Err(ServerError::HttpParse(
+                    "CONNECT requires an authority-form target".to_string(),
+                ));
+            }
+            return Ok((RequestTarget::Authority, Some(target.to_string()), target.to_string()));
+        }
+        
+        if target.starts_with('/') {
+            return Ok((RequestTarget::Origin, None, target.to_string()));
+        }
+        
+        if let Some(scheme_end) = target.find("://") {
+            let scheme = &target[..scheme_end];
+            if !scheme.eq_ignore_ascii_case("http") && !scheme.eq_ignore_ascii_case("https") {
+                return Err(ServerError::HttpParse(format!("Unsupported URI scheme: {}", scheme)));
+            }
+            
+            let rest = &target[scheme_end + 3..];
+            let authority_end = rest.find(['/', '?']).unwrap_or(rest.len());
+            let authority = &rest[..authority_end];
+            if authority.is_empty() {
+                return Err(ServerError::HttpParse("Missing host in absolute URI".to_string()));
+            }
+            
+            // Drop any userinfo component, it never identifies the host
+            let host = authority.rsplit('@').next().unwrap_or(authority);
+            
+            let uri = match &rest[authority_end..] {
+                "" => "/".to_string(),
+                path if path.starts_with('?') => format!("/{}", path),
+                path => path.to_string(),
+            };
+            
+            return Ok((RequestTarget::Absolute, Some(host.to_string()), uri));
+        }
+        
+        Err(ServerError::HttpParse(format!("Invalid request target: {}", target)))
+    }
+}
+
+/// Check whether a header name is a valid token (RFC 9110 section 5.1)
+pub fn is_valid_header_name(name: &str) -> bool {
+    scan::is_token(name.as_bytes())
+}
+
+/// Check whether a header value holds only visible characters, spaces and tabs
+///
+/// Bytes above 0x7F (obs-text, which includes UTF-8) are allowed as RFC 9110
+/// permits; control characters such as CR, LF and NUL are not.
+pub fn is_valid_header_value(value: &str) -> bool {
+    scan::is_header_value(value.as_bytes())
+}
+
+/// Remove the characters a header value may not contain
+pub fn sanitize_header_value(value: &str) -> Cow<'_, str> {
+    if is_valid_header_value(value) {
+        Cow::Borrowed(value)
+    } else {
+        Cow::Owned(value.chars().filter(|c| !c.is_control() || *c == '\t').collect())
+    }
+}
+
+/// Parse the version in a request line
+///
+/// Any HTTP/1 minor version is served as the highest one we support
+/// (RFC 9110 section 6.2); other major versions get 505.
+fn parse_version(version: &str) -> ServerResult<&'static str> {
+    match version.strip_prefix("HTTP/").map(str::as_bytes) {
+        Some(&[b'1', b'.', b'0']) => Ok("HTTP/1.0"),
+        Some(&[b'1', b'.', minor]) if minor.is_ascii_digit() => Ok("HTTP/1.1"),
+        Some(&[major, b'.', minor]) if major.is_ascii_digit() && minor.is_ascii_digit() => {
+            Err(ServerError::HttpVersion(version.to_string()))
+        }
+        _ => Err(ServerError::HttpParse(format!("Invalid HTTP version: {:?}", version))),
+    }
+}
+
+/// Check a header name and value, describing the first problem found
+fn check_header(name: &str, value: &str) -> ServerResult<()> {
+    if !is_valid_header_name(name) {
+        return Err(ServerError::HttpParse(format!("Invalid header name: {:?}", name)));
+    }
+    if !is_valid_header_value(value) {
+        return Err(ServerError::HttpParse(format!("Invalid characters in header {}", name)));
+    }
+    Ok(())
+}
+
 /// HTTP Parser State
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum HttpParserState {
@@ -125,10 +334,13 @@ pub struct HttpParser {
     pub state: HttpParserState,
     pub method: Option<Method>,
     pub uri: Option<String>,
+    pub target_form: RequestTarget,
+    pub target_host: Option<String>,
     pub version: Option<String>,
-    pub headers: HashMap<String, String>,
+    pub headers: HashMap<HeaderName, String>,
     pub body: Vec<u8>,
     pub content_length: usize,
+    _instance: Instance,
 }
 
 impl HttpParser {
@@ -138,10 +350,13 @@ impl HttpParser {
             state: HttpParserState::RequestLine,
             method: None,
             uri: None,
+            target_form: RequestTarget::Origin,
+            target_host: None,
             version: None,
             headers: HashMap::new(),
             body: Vec::new(),
             content_length: 0,
+            _instance: Instance::new(Tracked::HttpParser),
         }
     }
     
@@ -159,75 +374,96 @@ impl HttpParser {
         };
         
         // Find the end of headers marker
-        if let Some(headers_end) = data_str.find("\r\n\r\n") {
-            let headers_part = &data_str[0..headers_end];
-            
-            // Process headers section line by line
-            let lines: Vec<&str> = headers_part.split("\r\n").collect();
-            if !lines.is_empty() {
-                // Handle request line (first line)
-                if self.state == HttpParserState::RequestLine {
-                    self.parse_request_line(lines[0])?;
-                    self.state = HttpParserState::Headers;
-                }
+        if let Some(headers_end) = scan::find_head_end(data) {
+            if self.in_head() {
+                self.parse_head(&data_str[0..headers_end])?;
                 
-                // Parse headers (subsequent lines)
-                if self.state == HttpParserState::Headers {
-                    for line in &lines[1..] {
-                        if !line.is_empty() {
-                            self.parse_header(line)?;
-                        }
-                    }
-                    
-                    // Check for content length
-                    if let Some(content_length) = self.headers.get("content-length") {
-                        self.content_length = content_length.parse().unwrap_or(0);
-                    }
-                    
-                    // Body starts after headers end marker
-                    let body_start = headers_end + 4; // +4 for \r\n\r\n
-                    
-                    if self.content_length > 0 && body_start < data.len() {
-                        // Add body data
-                        self.body.extend_from_slice(&data[body_start..]);
-                        
-                        // Check if we have the complete body
-                        if self.body.len() >= self.content_length {
-                            // Trim any excess data
-                            if self.body.len() > self.content_length {
-                                self.body.truncate(self.content_length);
-                            }
-                            self.state = HttpParserState::Complete;
-                        } else {
-                            self.state = HttpParserState::Body;
-                        }
-                    } else if self.content_length == 0 {
-                        // No body expected
-                        self.state = HttpParserState::Complete;
-                    } else {
-                        // Expecting body but none in this chunk
-                        self.state = HttpParserState::Body;
-                    }
+                // Body starts after headers end marker
+                let body_start = headers_end + 4; // +4 for \r\n\r\n
+                if self.state == HttpParserState::Body {
+                    self.take_body(&data[body_start.min(data.len())..]);
                 }
             }
         } else if self.state == HttpParserState::Body {
             // We're in body state but didn't get the headers part in this chunk
             // Just add everything to body
-            self.body.extend_from_slice(data);
-            
-            // Check if we now have the complete body
-            if self.body.len() >= self.content_length {
-                // Trim any excess data
-                if self.body.len() > self.content_length {
-                    self.body.truncate(self.content_length);
-                }
-                self.state = HttpParserState::Complete;
+            self.take_body(data);
+        }
+        
+        Ok(())
+    }
+    
+    /// Parse as much of a request as `buffer` holds, consuming what was used
+    ///
+    /// The head is only consumed once it is complete, and only the declared
+    /// body length is taken, so a pipelined request that follows stays in
+    /// the buffer for the next call.
+    pub fn parse_buffer(&mut self, buffer: &mut Buffer) -> ServerResult<()> {
+        if self.state == HttpParserState::Complete {
+            self.reset();
+        }
+        
+        if self.in_head() {
+            let available = buffer.peek(buffer.available_data());
+            let headers_end = match scan::find_head_end(available) {
+                Some(position) => position,
+                None => return Ok(()),
+            };
+            let head = str::from_utf8(&available[..headers_end])
+                .map_err(|_| ServerError::HttpParse("Invalid UTF-8".to_string()))?;
+            self.parse_head(head)?;
+            buffer.consume(headers_end + 4)?;
+        }
+        
+        if self.state == HttpParserState::Body {
+            let used = self.take_body(buffer.peek(self.content_length - self.body.len()));
+            buffer.consume(used)?;
+        }
+        
+        Ok(())
+    }
+    
+    /// Parse the request line and headers, leaving the parser ready for the body
+    fn parse_head(&mut self, head: &str) -> ServerResult<()> {
+        // Process headers section line by line
+        let mut lines = head.split("\r\n");
+        
+        // Handle request line (first line)
+        if self.state == HttpParserState::RequestLine {
+            self.parse_request_line(lines.next().unwrap_or_default())?;
+            self.state = HttpParserState::Headers;
+        }
+        
+        // Parse headers (subsequent lines)
+        for line in lines {
+            if !line.is_empty() {
+                self.parse_header(line)?;
             }
         }
         
+        self.check_framing()?;
+        
+        self.state = if self.content_length == 0 {
+            // No body expected
+            HttpParserState::Complete
+        } else {
+            HttpParserState::Body
+        };
         Ok(())
     }
     
+    /// Add body bytes up to the declared length, returning how many were used
+    fn take_body(&mut self, data: &[u8]) -> usize {
+        let used = data.len().min(self.content_length - self.body.len());
+        self.body.extend_from_slice(&data[..used]);
+        
+        // Check if we have the complete body
+        if self.body.len() == self.content_length {
+            self.state = HttpParserState::Complete;
+        }
+        used
+    }
+    
     /// Parse a request line
     fn parse_request_line(&mut self, line: &str) -> ServerResult<()> {
         let parts: Vec<&str> = line.split_whitespace().collect();
@@ -237,19 +473,79 @@ impl HttpParser {
             ));
         }
         
-        self.method = Some(Method::from_str(parts[0])?);
-        self.uri = Some(parts[1].to_string());
-        self.version = Some(parts[2].to_string());
+        // A well-formed method we don't know is answered 501 rather than 400,
+        // but only once the version shows the line really is a request line
+        let version = parse_version(parts[2])?;
+        let method = Method::from_str(parts[0]).map_err(|e| {
+            if scan::is_token(parts[0].as_bytes()) {
+                ServerError::NotImplemented(format!("Method {}", parts[0]))
+            } else {
+                e
+            }
+        })?;
+        let (target_form, target_host, uri) = RequestTarget::parse(method, parts[1])?;
+        
+        self.method = Some(method);
+        self.uri = Some(uri);
+        self.target_form = target_form;
+        self.target_host = target_host;
+        self.version = Some(version.to_string());
+        
+        Ok(())
+    }
+    
+    /// Check the headers that decide where the request ends and which host it is for
+    ///
+    /// An HTTP/1.1 request needs exactly one Host (RFC 9112 section 3.2).
+    /// Content-Length must be a plain number, and request bodies with a
+    /// transfer coding aren't supported; a request declaring both is
+    /// rejected outright, since the two could disagree about where it ends.
+    fn check_framing(&mut self) -> ServerResult<()> {
+        if self.version.as_deref() == Some("HTTP/1.1") && !self.headers.contains_key(&HeaderName::HOST) {
+            return Err(ServerError::HttpParse("Missing Host header".to_string()));
+        }
+        
+        let content_length = self.headers.get(&HeaderName::CONTENT_LENGTH);
+        if self.headers.contains_key(&HeaderName::TRANSFER_ENCODING) {
+            return Err(match content_length {
+                Some(_) => ServerError::HttpParse("Both Transfer-Encoding and Content-Length".to_string()),
+                None => ServerError::NotImplemented("Request Transfer-Encoding".to_string()),
+            });
+        }
         
+        if let Some(value) = content_length {
+            if value.is_empty() || !value.bytes().all(|byte| byte.is_ascii_digit()) {
+                return Err(ServerError::HttpParse(format!("Invalid Content-Length: {:?}", value)));
+            }
+            self.content_length = value
+                .parse()
+                .map_err(|_| ServerError::HttpParse(format!("Content-Length too large: {}", value)))?;
+        }
         Ok(())
     }
     
     /// Parse a header line
+    ///
+    /// Whitespace before the colon, folded lines and control characters are
+    /// rejected rather than passed on to handlers.
     fn parse_header(&mut self, line: &str) -> ServerResult<()> {
-        if let Some(colon_idx) = line.find(':') {
-            let key = line[..colon_idx].trim().to_lowercase();
-            let value = line[colon_idx + 1..].trim().to_string();
-            self.headers.insert(key, value);
+        if let Some(colon_idx) = scan::find_byte(b':', line.as_bytes()) {
+            let name = &line[..colon_idx];
+            let value = line[colon_idx + 1..].trim_matches(|c| c == ' ' || c == '\t');
+            check_header(name, value)?;
+            let name = HeaderName::new(name);
+            let repeated = match self.headers.get(&name) {
+                None => false,
+                Some(previous) => match name.standard() {
+                    Some(StandardHeader::Host) => true,
+                    Some(StandardHeader::ContentLength) => previous != value,
+                    _ => false,
+                },
+            };
+            if repeated {
+                return Err(ServerError::HttpParse(format!("Conflicting {} headers", name)));
+            }
+            self.headers.insert(name, value.to_string());
             Ok(())
         } else {
             Err(ServerError::HttpParse("Invalid header".to_string()))
@@ -261,11 +557,18 @@ impl HttpParser {
         self.state == HttpParserState::Complete
     }
     
+    /// Check if the parser is still waiting for the end of the request head
+    pub fn in_head(&self) -> bool {
+        matches!(self.state, HttpParserState::RequestLine | HttpParserState::Headers)
+    }
+    
     /// Reset the parser for a new request
     pub fn reset(&mut self) {
         self.state = HttpParserState::RequestLine;
         self.method = None;
         self.uri = None;
+        self.target_form = RequestTarget::Origin;
+        self.target_host = None;
         self.version = None;
         self.headers.clear();
         self.body.clear();
@@ -279,7 +582,21 @@ impl HttpParser {
                 "Request not complete".to_string(),
             ));
         }
-        
+        self.build_request()
+    }
+    
+    /// Get the parsed request once its head is in, with as much body as has arrived
+    pub fn get_partial_request(&self) -> ServerResult<Request> {
+        if self.in_head() {
+            return Err(ServerError::HttpParse(
+                "Request head not complete".to_string(),
+            ));
+        }
+        self.build_request()
+    }
+    
+    /// Build a request from what has been parsed so far
+    fn build_request(&self) -> ServerResult<Request> {
         let method = self.method.ok_or_else(|| {
             ServerError::HttpParse("Method not set".to_string())
         })?;
@@ -302,30 +619,101 @@ impl HttpParser {
             }
         }
         
+        // An absolute-form target overrides the Host header
+        let host = self.target_host.clone().or_else(|| self.headers.get(&HeaderName::HOST).cloned());
+        
         Ok(Request {
             method,
             uri,
+            target_form: self.target_form,
+            host,
             headers: self.headers.clone(),
             body: self.body.clone(),
             query_params,
+            timings: ServerTimings::new(),
+            extensions: Extensions::new(),
+            peer_addr: None,
         })
     }
 }
 
+/// Typed values attached to a request, at most one per type
+///
+/// Middleware that learns something about a request (such as who sent it)
+/// passes a copy of the request with the value inserted on to `next`.
+#[derive(Clone, Default)]
+pub struct Extensions {
+    values: HashMap<TypeId, Arc<dyn Any + Send + Sync>>,
+}
+
+impl Extensions {
+    /// Create an empty set of extensions
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Attach a value, replacing any earlier value of the same type
+    pub fn insert<T: Any + Send + Sync>(&mut self, value: T) {
+        self.values.insert(TypeId::of::<T>(), Arc::new(value));
+    }
+    
+    /// Get the value of a type, if one is attached
+    pub fn get<T: Any + Send + Sync>(&self) -> Option<&T> {
+        self.values.get(&TypeId::of::<T>()).and_then(|value| value.downcast_ref())
+    }
+    
+    /// Remove the value of a type
+    pub fn remove<T: Any + Send + Sync>(&mut self) -> bool {
+        self.values.remove(&TypeId::of::<T>()).is_some()
+    }
+    
+    /// Get the number of attached values
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+    
+    /// Check whether no values are attached
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+impl fmt::Debug for Extensions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Extensions").field("len", &self.values.len()).finish()
+    }
+}
+
 /// HTTP Request
 #[derive(Debug, Clone)]
 pub struct Request {
     pub method: Method,
+    /// The request target in origin-form (or the raw target for authority/asterisk forms)
     pub uri: String,
-    pub headers: HashMap<String, String>,
+    /// The form the request target was sent in
+    pub target_form: RequestTarget,
+    /// The target host, taken from an absolute/authority-form target or the Host header
+    pub host: Option<String>,
+    pub headers: HashMap<HeaderName, String>,
     pub body: Vec<u8>,
     /// Query parameters parsed from the URI
     pub query_params: HashMap<String, String>,
+    /// Timings recorded by handlers for the `Server-Timing` header
+    pub timings: ServerTimings,
+    /// Values attached by middleware for later middleware and handlers
+    pub extensions: Extensions,
+    /// Address of the client, set by the event loop
+    pub peer_addr: Option<SocketAddr>,
 }
 
 impl Request {
     /// Create a new request
     pub fn new(method: Method, uri: &str) -> Self {
+        // Fall back to treating the target as opaque origin-form if it doesn't parse
+        let (target_form, host, uri) = RequestTarget::parse(method, uri)
+            .unwrap_or_else(|_| (RequestTarget::Origin, None, uri.to_string()));
+        let uri = uri.as_str();
+        
         // Parse query parameters if present
         let mut query_params = HashMap::new();
         let (path, query) = match uri.find('?') {
@@ -351,20 +739,62 @@ impl Request {
         Self {
             method,
             uri: uri.to_string(),
+            target_form,
+            host,
             headers: HashMap::new(),
             body: Vec::new(),
             query_params,
+            timings: ServerTimings::new(),
+            extensions: Extensions::new(),
+            peer_addr: None,
+        }
+    }
+    
+    /// Get the path component of the request URI, without the query string
+    pub fn path(&self) -> &str {
+        match self.uri.find('?') {
+            Some(pos) => &self.uri[..pos],
+            None => &self.uri,
         }
     }
     
+    /// Get the value of a cookie sent in the `Cookie` header
+    pub fn cookie(&self, name: &str) -> Option<&str> {
+        self.get_header("cookie")?
+            .split(';')
+            .filter_map(|pair| pair.trim().split_once('='))
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value.trim_matches('"'))
+    }
+    
     /// Set a header
+    ///
+    /// Headers with an invalid name are dropped and control characters are
+    /// stripped from values; use `try_set_header` to get an error instead.
     pub fn set_header(&mut self, name: &str, value: &str) {
-        self.headers.insert(name.to_lowercase(), value.to_string());
+        if !is_valid_header_name(name) {
+            log::warn!("Dropping request header with invalid name {:?}", name);
+            return;
+        }
+        
+        let name = HeaderName::new(name);
+        let value = sanitize_header_value(value).into_owned();
+        if name == HeaderName::HOST && matches!(self.target_form, RequestTarget::Origin | RequestTarget::Asterisk) {
+            self.host = Some(value.clone());
+        }
+        self.headers.insert(name, value);
+    }
+    
+    /// Set a header, rejecting invalid names and values
+    pub fn try_set_header(&mut self, name: &str, value: &str) -> ServerResult<()> {
+        check_header(name, value)?;
+        self.set_header(name, value);
+        Ok(())
     }
     
     /// Get a header
     pub fn get_header(&self, name: &str) -> Option<&String> {
-        self.headers.get(&name.to_lowercase())
+        self.headers.get(&HeaderName::new(name))
     }
     
     /// Set the body
@@ -380,6 +810,14 @@ pub struct Response {
     pub status: Status,
     pub headers: HashMap<String, String>,
     pub body: Vec<u8>,
+    /// Maximum bytes per second to send this response at (None = unlimited)
+    pub bandwidth_limit: Option<u64>,
+    /// Protocol switch to perform once this response has been sent
+    pub upgrade: Option<Upgrade>,
+    /// Reset the connection instead of sending this response
+    pub reset_connection: bool,
+    /// Headers and body serialized ahead of time, sent before `headers` and instead of `body`
+    prepared: Option<Arc<PreparedResponse>>,
 }
 
 impl Response {
@@ -387,45 +825,247 @@ impl Response {
     pub fn new(status: Status) -> Self {
         let mut headers = HashMap::new();
         headers.insert("Server".to_string(), "High-Performance-Server/0.1".to_string());
-        headers.insert("Connection".to_string(), "close".to_string());
         
         Self {
             status,
             headers,
             body: Vec::new(),
+            bandwidth_limit: None,
+            upgrade: None,
+            reset_connection: false,
+            prepared: None,
+        }
+    }
+    
+    /// Create a response that sends a prepared head and body
+    ///
+    /// Nothing is copied until it is written out. Headers set afterwards
+    /// go out after the prepared ones; changing one of those, or the body,
+    /// turns it back into an ordinary response first (see `thaw`).
+    pub fn from_prepared(prepared: Arc<PreparedResponse>) -> Self {
+        Self {
+            status: prepared.source.status,
+            headers: HashMap::new(),
+            body: Vec::new(),
+            bandwidth_limit: prepared.source.bandwidth_limit,
+            upgrade: None,
+            reset_connection: false,
+            prepared: Some(prepared),
+        }
+    }
+    
+    /// Check whether this response sends a prepared head and body
+    pub fn is_prepared(&self) -> bool {
+        self.prepared.is_some()
+    }
+    
+    /// Copy a prepared head and body into `headers` and `body`
+    ///
+    /// Code that reads or changes those fields directly must call this on
+    /// a response that may be prepared; the methods here do it themselves.
+    pub fn thaw(&mut self) {
+        if let Some(prepared) = self.prepared.take() {
+            let added = std::mem::replace(&mut self.headers, prepared.source.headers.clone());
+            self.headers.extend(added);
+            self.body = prepared.source.body.clone();
+        }
+    }
+    
+    /// Thaw before changing `name`, if the prepared head already has it
+    fn thaw_for(&mut self, name: &str) {
+        if self.prepared.as_ref().is_some_and(|prepared| prepared.source.headers.contains_key(name)) {
+            self.thaw();
+        }
+    }
+    
+    /// Get a header, including one in the prepared head
+    ///
+    /// Names match in any case, as in HTTP; handlers may set `connection`
+    /// as well as `Connection`.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        find_header(&self.headers, name)
+            .or_else(|| find_header(&self.prepared.as_ref()?.source.headers, name))
+            .map(String::as_str)
+    }
+    
+    /// Get the body, including a prepared one
+    pub fn body_bytes(&self) -> &[u8] {
+        match &self.prepared {
+            Some(prepared) => &prepared.source.body,
+            None => &self.body,
         }
     }
     
     /// Set a header
+    ///
+    /// Headers with an invalid name are dropped and control characters are
+    /// stripped from values, so untrusted input can't inject extra headers;
+    /// use `try_set_header` to get an error instead.
     pub fn set_header(&mut self, name: &str, value: &str) {
+        if !is_valid_header_name(name) {
+            log::warn!("Dropping response header with invalid name {:?}", name);
+            return;
+        }
+        self.thaw_for(name);
+        self.headers.insert(name.to_string(), sanitize_header_value(value).into_owned());
+    }
+    
+    /// Set a header, rejecting invalid names and values
+    pub fn try_set_header(&mut self, name: &str, value: &str) -> ServerResult<()> {
+        check_header(name, value)?;
+        self.thaw_for(name);
         self.headers.insert(name.to_string(), value.to_string());
+        Ok(())
+    }
+    
+    /// Append a value to a header, joining it to any existing value with a comma
+    pub fn append_header(&mut self, name: &str, value: &str) {
+        let value = match self.header(name) {
+            Some(existing) if !existing.is_empty() => format!("{}, {}", existing, value),
+            _ => value.to_string(),
+        };
+        self.set_header(name, &value);
     }
     
     /// Set the body and update content-length
     pub fn set_body(&mut self, body: &[u8]) {
+        self.thaw();
         self.body = body.to_vec();
         self.set_header("Content-Length", &body.len().to_string());
         self.set_header("Content-Type", "text/plain");
     }
     
+    /// Set the entity tag, including its quotes (e.g. `"v1"` or `W/"v1"`)
+    pub fn set_etag(&mut self, etag: &str) {
+        self.set_header("ETag", etag);
+    }
+    
+    /// Set the `Last-Modified` header
+    pub fn set_last_modified(&mut self, time: SystemTime) {
+        self.set_header("Last-Modified", &preconditions::format_http_date(time));
+    }
+    
+    /// Replace this response with a 304 if the request's cached copy is still fresh
+    ///
+    /// Compares the request's `If-None-Match`/`If-Modified-Since` headers
+    /// against this response's `ETag` and `Last-Modified`.
+    pub fn not_modified_if(self, request: &Request) -> Response {
+        if Preconditions::from_response(&self).evaluate(request) == PreconditionOutcome::NotModified {
+            preconditions::not_modified_from(&self)
+        } else {
+            self
+        }
+    }
+    
+    /// Limit the rate this response is written to the client at
+    pub fn set_bandwidth_limit(&mut self, bytes_per_second: u64) {
+        self.bandwidth_limit = Some(bytes_per_second);
+    }
+    
     /// Serialize the response to a byte vector
+    ///
+    /// Headers with invalid names are left out and control characters are
+    /// stripped from values, so CR/LF can never start a new header line.
     pub fn serialize(&self, writer: &mut Vec<u8>) -> ServerResult<()> {
-        // Write status line
-        write!(writer, "HTTP/1.1 {} {}\r\n", self.status as u16, self.status.as_str())
-            .map_err(|e| ServerError::Io(e))?;
+        writer.reserve(self.head_len_hint() + self.body_bytes().len());
+        self.serialize_head(writer)?;
+        
+        // Write body
+        writer.extend_from_slice(self.body_bytes());
+        
+        Ok(())
+    }
+    
+    /// Serialize the status line and headers, up to and including the blank line
+    ///
+    /// `Connection::send_response` serializes it straight into the
+    /// connection's write buffer.
+    pub fn serialize_head<W: Write>(&self, writer: &mut W) -> ServerResult<()> {
+        // Write status line, or the prepared status line and headers
+        match &self.prepared {
+            Some(prepared) => writer.write_all(&prepared.head),
+            None => write!(writer, "HTTP/1.1 {} {}\r\n", self.status as u16, self.status.as_str()),
+        }
+        .map_err(|e| ServerError::Io(e))?;
         
-        // Write headers
+        // Write headers, guarding against ones put straight into the map
+        // that would otherwise split the response
         for (name, value) in &self.headers {
-            write!(writer, "{}: {}\r\n", name, value)
+            if !is_valid_header_name(name) {
+                log::warn!("Not sending response header with invalid name {:?}", name);
+                continue;
+            }
+            write!(writer, "{}: {}\r\n", name, sanitize_header_value(value))
                 .map_err(|e| ServerError::Io(e))?;
         }
         
         // Write blank line
         write!(writer, "\r\n").map_err(|e| ServerError::Io(e))?;
         
-        // Write body
-        writer.extend_from_slice(&self.body);
-        
         Ok(())
     }
+    
+    /// Get an upper bound on the length of the serialized head
+    ///
+    /// Sanitizing and skipping invalid headers only ever shortens the head,
+    /// so room for this many bytes is enough to serialize it without growing.
+    pub fn head_len_hint(&self) -> usize {
+        // "HTTP/1.1 200 " and CRLF, then ": " and CRLF per header, then the blank line
+        let status_line = match &self.prepared {
+            Some(prepared) => prepared.head.len(),
+            None => 13 + self.status.as_str().len() + 2,
+        };
+        let headers: usize = self.headers.iter().map(|(name, value)| name.len() + value.len() + 4).sum();
+        status_line + headers + 2
+    }
+}
+
+/// Look up a response header by name in any case, trying the exact name first
+fn find_header<'a>(headers: &'a HashMap<String, String>, name: &str) -> Option<&'a String> {
+    headers
+        .get(name)
+        .or_else(|| headers.iter().find(|(header, _)| header.eq_ignore_ascii_case(name)).map(|(_, value)| value))
+}
+
+/// A response whose status line and headers are serialized once, ahead of time
+///
+/// Copies made with `Response::from_prepared` share it, so sending one
+/// only copies bytes. `Connection` is left out, since the server decides
+/// it for each request. Upgrades and resets aren't kept.
+#[derive(Debug)]
+pub struct PreparedResponse {
+    /// The response as built, for `thaw` and header lookups
+    source: Response,
+    /// Status line and headers, each ending in CRLF, without the blank line
+    head: Box<[u8]>,
+}
+
+impl PreparedResponse {
+    /// Serialize `response` ahead of time
+    pub fn new(mut response: Response) -> Self {
+        response.thaw();
+        response.headers.retain(|name, _| !name.eq_ignore_ascii_case("connection"));
+        response.upgrade = None;
+        response.reset_connection = false;
+        
+        let mut head = Vec::with_capacity(response.head_len_hint());
+        // Writing to a vector can't fail
+        let _ = response.serialize_head(&mut head);
+        head.truncate(head.len() - 2);
+        
+        Self {
+            source: response,
+            head: head.into_boxed_slice(),
+        }
+    }
+    
+    /// Get the serialized status line and headers
+    pub fn head(&self) -> &[u8] {
+        &self.head
+    }
+    
+    /// Get the body
+    pub fn body(&self) -> &[u8] {
+        &self.source.body
+    }
 }
\ No newline at end of file
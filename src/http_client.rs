@@ -0,0 +1,462 @@
+use crate::deadline::Deadline;
+use crate::error::{ServerError, ServerResult};
+use crate::resolver;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+#[cfg(unix)]
+use socket2::{Domain, Protocol, Socket, Type};
+#[cfg(unix)]
+use std::os::unix::io::AsRawFd;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Timeout for connecting, writing and reading outbound requests
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Head start each connection attempt gets before the next one begins,
+/// as recommended by RFC 8305
+pub const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// A parsed `http://host:port/path` URL
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpUrl {
+    /// `host:port`, with port 80 filled in when missing
+    pub address: String,
+    pub host: String,
+    pub path: String,
+}
+
+impl HttpUrl {
+    /// Parse a plain HTTP URL
+    pub fn parse(url: &str) -> ServerResult<Self> {
+        let rest = url
+            .strip_prefix("http://")
+            .ok_or_else(|| ServerError::Config(format!("Only http:// URLs are supported: {}", url)))?;
+        
+        let (authority, path) = match rest.find('/') {
+            Some(pos) => (&rest[..pos], &rest[pos..]),
+            None => (rest, "/"),
+        };
+        
+        if authority.is_empty() {
+            return Err(ServerError::Config(format!("Missing host in URL: {}", url)));
+        }
+        
+        // A colon after the closing bracket of an IPv6 literal marks the port
+        let has_port = match authority.rfind(']') {
+            Some(bracket) => authority[bracket..].contains(':'),
+            None => authority.contains(':'),
+        };
+        let address = if has_port {
+            authority.to_string()
+        } else {
+            format!("{}:80", authority)
+        };
+        
+        Ok(Self {
+            address,
+            host: authority.to_string(),
+            path: path.to_string(),
+        })
+    }
+}
+
+/// A response to an outbound request
+#[derive(Debug, Clone)]
+pub struct ClientResponse {
+    pub status: u16,
+    pub body: Vec<u8>,
+}
+
+impl ClientResponse {
+    /// Check for a 2xx status
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+}
+
+/// Send a blocking HTTP/1.1 request and read the whole response
+///
+/// Meant for small control-plane calls (metrics pushes, webhooks), not for
+/// proxying traffic; the connection is closed after each request.
+pub fn send(method: &str, url: &HttpUrl, content_type: &str, body: &[u8]) -> ServerResult<ClientResponse> {
+    let stream = connect(url, Instant::now() + CLIENT_TIMEOUT)?;
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+    
+    let head = format!(
+        "{} {} HTTP/1.1\r\nHost: {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        method,
+        url.path,
+        url.host,
+        content_type,
+        body.len()
+    );
+    exchange(stream, head.as_bytes(), body)
+}
+
+/// Write a request on a fresh connection and read the whole response
+pub(crate) fn exchange(mut stream: TcpStream, head: &[u8], body: &[u8]) -> ServerResult<ClientResponse> {
+    stream.write_all(head)?;
+    stream.write_all(body)?;
+    
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).map_err(|e| match e.kind() {
+        // An expired read timeout shows up as WouldBlock on some platforms
+        io::ErrorKind::WouldBlock => io::Error::new(io::ErrorKind::TimedOut, "Upstream response timed out"),
+        _ => e,
+    })?;
+    
+    let header_end = response
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .ok_or_else(|| ServerError::Protocol("Incomplete HTTP response".to_string()))?;
+    
+    let status = String::from_utf8_lossy(&response[..header_end])
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse::<u16>().ok())
+        .ok_or_else(|| ServerError::Protocol("Invalid HTTP status line".to_string()))?;
+    
+    Ok(ClientResponse {
+        status,
+        body: response[header_end + 4..].to_vec(),
+    })
+}
+
+/// How idempotent requests are retried across upstreams
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Most requests sent in total, hedges included
+    pub max_attempts: u32,
+    
+    /// Wait before the first retry, doubling for each one after
+    pub initial_backoff: Duration,
+    
+    /// Longest wait between retries
+    pub max_backoff: Duration,
+    
+    /// Send a duplicate request to the next upstream when an answer takes
+    /// this long, using whichever succeeds first (None = never hedge)
+    pub hedge_after: Option<Duration>,
+    
+    /// Time allowed for the whole exchange, retries and hedges included
+    pub deadline: Duration,
+    
+    /// Deadline of the request these calls are made for, which also bounds
+    /// the exchange and is passed to upstreams in its header
+    pub request_deadline: Option<Deadline>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(50),
+            max_backoff: Duration::from_secs(1),
+            hedge_after: None,
+            deadline: Duration::from_secs(10),
+            request_deadline: None,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Bound the exchange by `deadline` too and pass it on to upstreams
+    pub fn within(mut self, deadline: &Deadline) -> Self {
+        self.request_deadline = Some(deadline.clone());
+        self
+    }
+    
+    /// Get the wait before retry number `retry`, counting from 1
+    fn backoff(&self, retry: u32) -> Duration {
+        let factor = 1u32.checked_shl(retry.saturating_sub(1)).unwrap_or(u32::MAX);
+        self.initial_backoff.saturating_mul(factor).min(self.max_backoff)
+    }
+}
+
+/// Check whether a response is worth retrying on another upstream
+fn is_retryable(response: &ClientResponse) -> bool {
+    matches!(response.status, 502..=504)
+}
+
+/// Send a GET or HEAD request, retrying and hedging across `upstreams`
+///
+/// Attempts go to the upstreams in turn. Connection errors and 502, 503 or
+/// 504 responses are retried after an exponential backoff; any other
+/// response is returned as is. Once the deadline passes, the last failure
+/// is returned, or a timeout if no attempt has finished. Hedged requests
+/// that lose the race run to completion in the background.
+///
+/// Only idempotent methods are accepted, since an upstream may act on a
+/// request whose response never arrives.
+pub fn send_idempotent(method: &str, upstreams: &[HttpUrl], policy: &RetryPolicy) -> ServerResult<ClientResponse> {
+    if !matches!(method, "GET" | "HEAD") {
+        return Err(ServerError::Config(format!("Only GET and HEAD requests are retried, not {}", method)));
+    }
+    if upstreams.is_empty() {
+        return Err(ServerError::Config("No upstreams to send to".to_string()));
+    }
+    
+    let mut deadline = Instant::now() + policy.deadline;
+    let mut deadline_header = None;
+    if let Some(request_deadline) = &policy.request_deadline {
+        deadline = deadline.min(request_deadline.expires());
+        deadline_header = request_deadline.header().map(str::to_string);
+    }
+    
+    let (results, received) = mpsc::channel();
+    let start_attempt = |attempt: u32| {
+        let url = upstreams[attempt as usize % upstreams.len()].clone();
+        let method = method.to_string();
+        let deadline_header = deadline_header.clone();
+        let results = results.clone();
+        thread::spawn(move || {
+            let _ = results.send(send_before(&method, &url, deadline, deadline_header.as_deref()));
+        });
+    };
+    
+    start_attempt(0);
+    let mut started = 1;
+    let mut in_flight = 1;
+    let mut retry_at: Option<Instant> = None;
+    let mut last_error: Option<ServerResult<ClientResponse>> = None;
+    
+    loop {
+        let now = Instant::now();
+        if now >= deadline {
+            break;
+        }
+        
+        // Wake for the next result, a pending retry, or the hedge timer
+        let mut wake = deadline;
+        if let Some(at) = retry_at {
+            wake = wake.min(at);
+        }
+        let can_start = started < policy.max_attempts;
+        if let (Some(hedge_after), true, None) = (policy.hedge_after, can_start, retry_at) {
+            wake = wake.min(now + hedge_after);
+        }
+        
+        let result = if in_flight > 0 {
+            received.recv_timeout(wake.saturating_duration_since(now))
+        } else {
+            thread::sleep(wake.saturating_duration_since(now));
+            Err(RecvTimeoutError::Timeout)
+        };
+        
+        match result {
+            Ok(result) => {
+                in_flight -= 1;
+                match result {
+                    Ok(response) if !is_retryable(&response) => return Ok(response),
+                    failure => last_error = Some(failure),
+                }
+                if in_flight == 0 && can_start && retry_at.is_none() {
+                    retry_at = Some(Instant::now() + policy.backoff(started));
+                } else if in_flight == 0 && !can_start {
+                    break;
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                let now = Instant::now();
+                let retry_due = retry_at.is_some_and(|at| now >= at);
+                let hedge_due = in_flight > 0 && retry_at.is_none() && policy.hedge_after.is_some();
+                if can_start && now < deadline && (retry_due || hedge_due) {
+                    retry_at = None;
+                    start_attempt(started);
+                    started += 1;
+                    in_flight += 1;
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+    
+    last_error.unwrap_or_else(|| {
+        Err(ServerError::Io(io::Error::new(io::ErrorKind::TimedOut, "Upstream request deadline exceeded")))
+    })
+}
+
+/// Send a bodiless request, giving up at `deadline`
+///
+/// With `deadline_header`, the milliseconds left are sent in that header.
+fn send_before(
+    method: &str,
+    url: &HttpUrl,
+    deadline: Instant,
+    deadline_header: Option<&str>,
+) -> ServerResult<ClientResponse> {
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    if remaining.is_zero() {
+        return Err(ServerError::Io(io::Error::new(io::ErrorKind::TimedOut, "Upstream request deadline exceeded")));
+    }
+    
+    let stream = connect(url, deadline)?;
+    let remaining = deadline.saturating_duration_since(Instant::now()).max(Duration::from_millis(1));
+    stream.set_read_timeout(Some(remaining))?;
+    stream.set_write_timeout(Some(remaining))?;
+    
+    let mut head = format!("{} {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n", method, url.path, url.host);
+    if let Some(header) = deadline_header {
+        head.push_str(&format!("{}: {}\r\n", header, remaining.as_millis()));
+    }
+    head.push_str("\r\n");
+    exchange(stream, head.as_bytes(), &[])
+}
+
+/// Resolve `url` and connect to it, racing its addresses
+pub(crate) fn connect(url: &HttpUrl, deadline: Instant) -> ServerResult<TcpStream> {
+    let addrs = resolver::shared().resolve_address(&url.address)?;
+    if addrs.is_empty() {
+        return Err(ServerError::Config(format!("No address for {}", url.host)));
+    }
+    Ok(connect_happy_eyeballs(&addrs, CONNECTION_ATTEMPT_DELAY, deadline)?)
+}
+
+/// Connect to the first of `addrs` that answers, Happy Eyeballs style (RFC 8305)
+///
+/// Addresses are tried in `interleave_families` order. Each attempt gets
+/// `attempt_delay` to itself before the next one starts alongside it, and a
+/// failed attempt starts the next one at once, so an unreachable address
+/// family costs one delay rather than a full connect timeout. Attempts are
+/// non-blocking sockets raced on the calling thread; those still pending
+/// when one succeeds are closed. The winner is returned in blocking mode.
+#[cfg(unix)]
+pub fn connect_happy_eyeballs(
+    addrs: &[SocketAddr],
+    attempt_delay: Duration,
+    deadline: Instant,
+) -> io::Result<TcpStream> {
+    let mut pending = interleave_families(addrs).into_iter();
+    let mut attempts: Vec<Socket> = Vec::new();
+    let mut next_start = Instant::now();
+    let mut last_error = None;
+    
+    loop {
+        let now = Instant::now();
+        if now >= deadline {
+            break;
+        }
+        if attempts.is_empty() || now >= next_start {
+            match pending.next() {
+                Some(addr) => {
+                    match start_connect(addr) {
+                        Ok(socket) => {
+                            attempts.push(socket);
+                            next_start = now + attempt_delay;
+                        }
+                        Err(e) => last_error = Some(e),
+                    }
+                    continue;
+                }
+                None if attempts.is_empty() => break,
+                None => {}
+            }
+        }
+        
+        let wait_until = if pending.len() > 0 { next_start.min(deadline) } else { deadline };
+        let ready = wait_writable(&attempts, wait_until.saturating_duration_since(now))?;
+        for index in ready.into_iter().rev() {
+            let socket = attempts.swap_remove(index);
+            match connect_result(&socket) {
+                Ok(()) => {
+                    socket.set_nonblocking(false)?;
+                    return Ok(socket.into());
+                }
+                // A failure starts the next address without waiting out the delay
+                Err(e) => {
+                    last_error = Some(e);
+                    next_start = Instant::now();
+                }
+            }
+        }
+    }
+    
+    Err(last_error.unwrap_or_else(|| io::Error::new(io::ErrorKind::TimedOut, "Connection attempts timed out")))
+}
+
+/// Connect to the first of `addrs` that answers, one address at a time
+///
+/// Without `poll` to race attempts on, each address gets `attempt_delay`
+/// before the next is tried.
+#[cfg(not(unix))]
+pub fn connect_happy_eyeballs(
+    addrs: &[SocketAddr],
+    attempt_delay: Duration,
+    deadline: Instant,
+) -> io::Result<TcpStream> {
+    let mut last_error = None;
+    for addr in interleave_families(addrs) {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            break;
+        }
+        match TcpStream::connect_timeout(&addr, remaining.min(attempt_delay.max(Duration::from_millis(1)))) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(last_error.unwrap_or_else(|| io::Error::new(io::ErrorKind::TimedOut, "Connection attempts timed out")))
+}
+
+/// Start a non-blocking connection attempt to `addr`
+#[cfg(unix)]
+fn start_connect(addr: SocketAddr) -> io::Result<Socket> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    socket.set_nonblocking(true)?;
+    match socket.connect(&addr.into()) {
+        Ok(()) => Ok(socket),
+        Err(e) if e.raw_os_error() == Some(libc::EINPROGRESS) || e.kind() == io::ErrorKind::WouldBlock => Ok(socket),
+        Err(e) => Err(e),
+    }
+}
+
+/// Wait up to `timeout` for any of `attempts` to finish, returning the indexes of those that did
+#[cfg(unix)]
+fn wait_writable(attempts: &[Socket], timeout: Duration) -> io::Result<Vec<usize>> {
+    let mut fds: Vec<libc::pollfd> = attempts
+        .iter()
+        .map(|socket| libc::pollfd {
+            fd: socket.as_raw_fd(),
+            events: libc::POLLOUT,
+            revents: 0,
+        })
+        .collect();
+    // Round up, so a wait never ends just short of the moment it was for
+    let timeout_ms = timeout.as_micros().div_ceil(1000).min(i32::MAX as u128) as i32;
+    if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, timeout_ms) } < 0 {
+        let e = io::Error::last_os_error();
+        return if e.kind() == io::ErrorKind::Interrupted { Ok(Vec::new()) } else { Err(e) };
+    }
+    Ok(fds.iter().enumerate().filter(|(_, fd)| fd.revents != 0).map(|(index, _)| index).collect())
+}
+
+/// Get how a finished connection attempt went
+#[cfg(unix)]
+fn connect_result(socket: &Socket) -> io::Result<()> {
+    if let Some(e) = socket.take_error()? {
+        return Err(e);
+    }
+    socket.peer_addr().map(|_| ())
+}
+
+/// Order addresses for connecting, alternating IPv6 and IPv4
+///
+/// The family of the first address goes first, and the order within each
+/// family is kept.
+pub fn interleave_families(addrs: &[SocketAddr]) -> Vec<SocketAddr> {
+    let first_is_v6 = addrs.first().is_some_and(SocketAddr::is_ipv6);
+    let (mut first, mut second): (Vec<_>, Vec<_>) = addrs.iter().partition(|addr| addr.is_ipv6() == first_is_v6);
+    first.reverse();
+    second.reverse();
+    
+    let mut ordered = Vec::with_capacity(addrs.len());
+    loop {
+        match (first.pop(), second.pop()) {
+            (None, None) => return ordered,
+            (a, b) => ordered.extend(a.into_iter().chain(b).copied()),
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,267 @@
+use crate::error::ServerResult;
+use crate::http::{Request, Response};
+use crate::middleware::MiddlewareNext;
+use crate::redaction::REDACTED;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+fn redacted() -> Value {
+    Value::String(REDACTED.to_string())
+}
+
+/// One change to a JSON body
+///
+/// Paths are JSON pointers (RFC 6901), such as `/user/email`, where a `*`
+/// segment stands for every element of an array or field of an object:
+/// `/items/*/secret` reaches the `secret` of each item.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum JsonRule {
+    /// Set the field at `path`, creating missing objects on the way; a last
+    /// segment of `-` appends to an array
+    Add { path: String, value: Value },
+    
+    /// Drop the field or array element at `path`
+    Remove { path: String },
+    
+    /// Give the field at `path` the name `to`, in the same object
+    Rename { path: String, to: String },
+    
+    /// Replace the value at `path`, if there is one, with `with`
+    Redact {
+        path: String,
+        #[serde(default = "redacted")]
+        with: Value,
+    },
+}
+
+impl JsonRule {
+    fn path(&self) -> &str {
+        match self {
+            JsonRule::Add { path, .. }
+            | JsonRule::Remove { path }
+            | JsonRule::Rename { path, .. }
+            | JsonRule::Redact { path, .. } => path,
+        }
+    }
+    
+    /// Apply the rule to `body`, doing nothing where its path leads nowhere
+    pub fn apply(&self, body: &mut Value) {
+        let tokens = match parse_pointer(self.path()) {
+            Some(tokens) if !tokens.is_empty() => tokens,
+            _ => return,
+        };
+        let (parents, last) = tokens.split_at(tokens.len() - 1);
+        let create = matches!(self, JsonRule::Add { .. });
+        visit_parents(body, parents, create, &mut |parent| self.apply_at(parent, &last[0]));
+    }
+    
+    /// Apply the rule to the child `key` of `parent`
+    fn apply_at(&self, parent: &mut Value, key: &str) {
+        match (self, parent) {
+            (JsonRule::Add { value, .. }, Value::Object(map)) => {
+                if key == "*" {
+                    map.values_mut().for_each(|field| *field = value.clone());
+                } else {
+                    map.insert(key.to_string(), value.clone());
+                }
+            }
+            (JsonRule::Add { value, .. }, Value::Array(items)) => match key {
+                "-" => items.push(value.clone()),
+                "*" => items.iter_mut().for_each(|item| *item = value.clone()),
+                _ => {
+                    if let Some(item) = key.parse::<usize>().ok().and_then(|index| items.get_mut(index)) {
+                        *item = value.clone();
+                    }
+                }
+            },
+            (JsonRule::Remove { .. }, Value::Object(map)) => {
+                if key == "*" {
+                    map.clear();
+                } else {
+                    map.remove(key);
+                }
+            }
+            (JsonRule::Remove { .. }, Value::Array(items)) => {
+                if key == "*" {
+                    items.clear();
+                } else if let Some(index) = key.parse::<usize>().ok().filter(|&index| index < items.len()) {
+                    items.remove(index);
+                }
+            }
+            (JsonRule::Rename { to, .. }, Value::Object(map)) => {
+                if let Some(field) = map.remove(key) {
+                    map.insert(to.clone(), field);
+                }
+            }
+            (JsonRule::Redact { with, .. }, parent) => {
+                for_each_child(parent, key, &mut |child| *child = with.clone());
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Split a JSON pointer into its unescaped segments
+///
+/// Returns None for a pointer that doesn't start with `/` and isn't empty.
+fn parse_pointer(pointer: &str) -> Option<Vec<String>> {
+    if pointer.is_empty() {
+        return Some(Vec::new());
+    }
+    let rest = pointer.strip_prefix('/')?;
+    Some(
+        rest.split('/')
+            .map(|token| token.replace("~1", "/").replace("~0", "~"))
+            .collect(),
+    )
+}
+
+/// Call `f` on every child of `value` matching `key`
+fn for_each_child(value: &mut Value, key: &str, f: &mut dyn FnMut(&mut Value)) {
+    match value {
+        Value::Object(map) if key == "*" => map.values_mut().for_each(f),
+        Value::Object(map) => {
+            if let Some(child) = map.get_mut(key) {
+                f(child);
+            }
+        }
+        Value::Array(items) if key == "*" => items.iter_mut().for_each(f),
+        Value::Array(items) => {
+            if let Some(child) = key.parse::<usize>().ok().and_then(|index| items.get_mut(index)) {
+                f(child);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Call `f` on every value `tokens` lead to, creating missing objects if `create` is set
+fn visit_parents(value: &mut Value, tokens: &[String], create: bool, f: &mut dyn FnMut(&mut Value)) {
+    let (token, rest) = match tokens.split_first() {
+        Some(split) => split,
+        None => return f(value),
+    };
+    if create && token != "*" {
+        if let Value::Object(map) = value {
+            map.entry(token.clone()).or_insert_with(|| Value::Object(Default::default()));
+        }
+    }
+    for_each_child(value, token, &mut |child| visit_parents(child, rest, create, f));
+}
+
+/// Rules for rewriting the JSON bodies of requests and responses
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JsonTransformConfig {
+    /// Rules applied, in order, to request bodies before the handler sees them
+    pub request: Vec<JsonRule>,
+    
+    /// Rules applied, in order, to response bodies before they are sent
+    pub response: Vec<JsonRule>,
+    
+    /// Path prefixes the rules apply under (empty = every path)
+    pub paths: Vec<String>,
+    
+    /// Largest body rewritten; bigger ones pass through untouched
+    pub max_body_size: usize,
+}
+
+impl Default for JsonTransformConfig {
+    fn default() -> Self {
+        Self {
+            request: Vec::new(),
+            response: Vec::new(),
+            paths: Vec::new(),
+            max_body_size: 1024 * 1024,
+        }
+    }
+}
+
+/// Check whether a `Content-Type` value names JSON, including `+json` types
+fn is_json(content_type: &str) -> bool {
+    let media_type = content_type.split(';').next().unwrap_or("").trim().to_ascii_lowercase();
+    media_type == "application/json" || (media_type.starts_with("application/") && media_type.ends_with("+json"))
+}
+
+/// Apply `rules` to a JSON `body`, returning the new body if it parsed
+fn transform(body: &[u8], rules: &[JsonRule]) -> Option<Vec<u8>> {
+    let mut value: Value = match serde_json::from_slice(body) {
+        Ok(value) => value,
+        Err(e) => {
+            log::debug!("Leaving invalid JSON body untouched: {}", e);
+            return None;
+        }
+    };
+    for rule in rules {
+        rule.apply(&mut value);
+    }
+    serde_json::to_vec(&value).ok()
+}
+
+/// JSON transform middleware - rewrites JSON request and response bodies by rule
+///
+/// Only bodies whose `Content-Type` is JSON are touched, and bodies that
+/// don't parse are passed on as they are. Bodies are rewritten whole:
+/// requests are already read in full before middleware runs, and handlers
+/// return their bodies whole. Responses streamed by the proxy are passed
+/// through untouched. A rewritten response's strong `ETag` is weakened,
+/// since its bytes no longer match.
+pub fn json_transform_middleware(
+    config: JsonTransformConfig,
+) -> impl Fn(&Request, MiddlewareNext) -> ServerResult<Response> + Send + Sync {
+    for rule in config.request.iter().chain(&config.response) {
+        if parse_pointer(rule.path()).is_none_or(|tokens| tokens.is_empty()) {
+            log::warn!("JSON rule path {:?} is not a JSON pointer to a field; it will match nothing", rule.path());
+        }
+    }
+    
+    move |request, next| {
+        let path = request.path();
+        if !config.paths.is_empty() && !config.paths.iter().any(|prefix| path.starts_with(prefix.as_str())) {
+            return next(request);
+        }
+        
+        let request_json = request.get_header("content-type").is_some_and(|value| is_json(value));
+        let rewritten = if request_json && !config.request.is_empty() && request.body.len() <= config.max_body_size {
+            transform(&request.body, &config.request).map(|body| {
+                let mut request = request.clone();
+                request.set_body(&body);
+                request
+            })
+        } else {
+            None
+        };
+        let mut response = next(rewritten.as_ref().unwrap_or(request))?;
+        
+        let content_type = response
+            .headers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case("content-type"))
+            .map(|(_, value)| value.clone());
+        let encoded = response.headers.iter().any(|(name, value)| {
+            name.eq_ignore_ascii_case("content-encoding") && !value.eq_ignore_ascii_case("identity")
+        });
+        let rewrite_response = !config.response.is_empty()
+            && response.upgrade.is_none()
+            && !encoded
+            && response.body.len() <= config.max_body_size
+            && content_type.as_deref().is_some_and(is_json);
+        if rewrite_response {
+            if let Some(body) = transform(&response.body, &config.response) {
+                let content_type = content_type.unwrap_or_default();
+                response.headers.retain(|name, _| !name.eq_ignore_ascii_case("content-type"));
+                response.set_body(&body);
+                response.set_header("Content-Type", &content_type);
+                
+                let etag = response.headers.iter().find(|(name, _)| name.eq_ignore_ascii_case("etag"));
+                if let Some((name, etag)) = etag.map(|(name, etag)| (name.clone(), etag.clone())) {
+                    if !etag.starts_with("W/") {
+                        response.headers.insert(name, format!("W/{}", etag));
+                    }
+                }
+            }
+        }
+        Ok(response)
+    }
+}
\ No newline at end of file
@@ -0,0 +1,388 @@
+//! Key-value storage for state shared between requests and replicas
+//!
+//! Features keep their state through the `KvStore` trait instead of
+//! talking to one store directly, so the same code runs against memory in
+//! tests and a single process, files on one host, or Redis across a cluster.
+
+use crate::error::{ServerError, ServerResult};
+use crate::hash::{to_hex, Sha256};
+use crate::resp::{RespClient, RespValue};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// A store of byte values under string keys, with optional expiry
+///
+/// Keys that have expired behave as if they were never set.
+pub trait KvStore: Send + Sync {
+    /// Get the value of `key`
+    fn get(&self, key: &str) -> ServerResult<Option<Vec<u8>>>;
+    
+    /// Set `key` to `value`, expiring after `ttl` if given
+    fn set(&self, key: &str, value: &[u8], ttl: Option<Duration>) -> ServerResult<()>;
+    
+    /// Delete `key`, returning whether it was set
+    fn del(&self, key: &str) -> ServerResult<bool>;
+    
+    /// Get the time left before `key` expires
+    ///
+    /// Returns None when the key isn't set or never expires.
+    fn ttl(&self, key: &str) -> ServerResult<Option<Duration>>;
+    
+    /// Replace the value of `key` only if it is still `expected`
+    ///
+    /// An `expected` of None means the key must not be set, and a `value`
+    /// of None deletes it. Returns whether the swap happened; callers that
+    /// lose a race read the new value and try again.
+    fn cas(
+        &self,
+        key: &str,
+        expected: Option<&[u8]>,
+        value: Option<&[u8]>,
+        ttl: Option<Duration>,
+    ) -> ServerResult<bool>;
+}
+
+#[derive(Debug)]
+struct MemoryEntry {
+    value: Vec<u8>,
+    expires: Option<Instant>,
+}
+
+impl MemoryEntry {
+    fn live(&self, now: Instant) -> bool {
+        self.expires.is_none_or(|expires| expires > now)
+    }
+}
+
+/// Writes between sweeps of expired entries
+const SWEEP_INTERVAL: usize = 1024;
+
+/// Values kept in this process
+#[derive(Debug, Default)]
+pub struct MemoryKvStore {
+    entries: Mutex<HashMap<String, MemoryEntry>>,
+    writes: AtomicUsize,
+}
+
+impl MemoryKvStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Get the number of keys held, expired ones not yet swept included
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+    
+    /// Check whether no keys are held
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    
+    fn write(
+        &self,
+        entries: &mut HashMap<String, MemoryEntry>,
+        key: &str,
+        value: Option<&[u8]>,
+        ttl: Option<Duration>,
+    ) {
+        match value {
+            Some(value) => {
+                let entry = MemoryEntry {
+                    value: value.to_vec(),
+                    expires: ttl.map(|ttl| Instant::now() + ttl),
+                };
+                entries.insert(key.to_string(), entry);
+            }
+            None => {
+                entries.remove(key);
+            }
+        }
+        if self.writes.fetch_add(1, Ordering::Relaxed) % SWEEP_INTERVAL == SWEEP_INTERVAL - 1 {
+            let now = Instant::now();
+            entries.retain(|_, entry| entry.live(now));
+        }
+    }
+}
+
+impl KvStore for MemoryKvStore {
+    fn get(&self, key: &str) -> ServerResult<Option<Vec<u8>>> {
+        let entries = self.entries.lock().unwrap();
+        let now = Instant::now();
+        Ok(entries.get(key).filter(|entry| entry.live(now)).map(|entry| entry.value.clone()))
+    }
+    
+    fn set(&self, key: &str, value: &[u8], ttl: Option<Duration>) -> ServerResult<()> {
+        let mut entries = self.entries.lock().unwrap();
+        self.write(&mut entries, key, Some(value), ttl);
+        Ok(())
+    }
+    
+    fn del(&self, key: &str) -> ServerResult<bool> {
+        let now = Instant::now();
+        Ok(self.entries.lock().unwrap().remove(key).is_some_and(|entry| entry.live(now)))
+    }
+    
+    fn ttl(&self, key: &str) -> ServerResult<Option<Duration>> {
+        let entries = self.entries.lock().unwrap();
+        let now = Instant::now();
+        Ok(entries
+            .get(key)
+            .filter(|entry| entry.live(now))
+            .and_then(|entry| entry.expires)
+            .map(|expires| expires - now))
+    }
+    
+    fn cas(
+        &self,
+        key: &str,
+        expected: Option<&[u8]>,
+        value: Option<&[u8]>,
+        ttl: Option<Duration>,
+    ) -> ServerResult<bool> {
+        let mut entries = self.entries.lock().unwrap();
+        let now = Instant::now();
+        let current = entries.get(key).filter(|entry| entry.live(now)).map(|entry| entry.value.as_slice());
+        if current != expected {
+            return Ok(false);
+        }
+        self.write(&mut entries, key, value, ttl);
+        Ok(true)
+    }
+}
+
+/// Longest key whose file is named after the key itself
+const MAX_PLAIN_KEY: usize = 100;
+
+/// Values kept as files in a directory, one per key
+///
+/// Values survive restarts and can be shared by processes on one host.
+/// File names are the hex of the key, and each file starts with a line
+/// holding its expiry in Unix milliseconds (`-` for none). Files are
+/// replaced by renaming, so readers never see half a value. `cas` is only
+/// atomic between users of the same `FileKvStore`.
+#[derive(Debug)]
+pub struct FileKvStore {
+    dir: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl FileKvStore {
+    /// Keep values in `dir`, creating it if needed
+    pub fn open<P: AsRef<Path>>(dir: P) -> ServerResult<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir,
+            lock: Mutex::new(()),
+        })
+    }
+    
+    fn path(&self, key: &str) -> PathBuf {
+        // Long keys are hashed to stay within file name limits
+        if key.len() > MAX_PLAIN_KEY {
+            return self.dir.join(format!("h{}", to_hex(&Sha256::digest(key.as_bytes()))));
+        }
+        self.dir.join(to_hex(key.as_bytes()))
+    }
+    
+    /// Read a key's value and expiry, deleting it if it has expired
+    fn read(&self, key: &str) -> ServerResult<Option<(Vec<u8>, Option<SystemTime>)>> {
+        let path = self.path(key);
+        let contents = match fs::read(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let invalid = || ServerError::Config(format!("Invalid store file {}", path.display()));
+        let newline = contents.iter().position(|&b| b == b'\n').ok_or_else(invalid)?;
+        let expires = match &contents[..newline] {
+            b"-" => None,
+            millis => {
+                let millis = std::str::from_utf8(millis).ok().and_then(|millis| millis.parse().ok());
+                Some(UNIX_EPOCH + Duration::from_millis(millis.ok_or_else(invalid)?))
+            }
+        };
+        if expires.is_some_and(|expires| expires <= SystemTime::now()) {
+            let _ = fs::remove_file(&path);
+            return Ok(None);
+        }
+        Ok(Some((contents[newline + 1..].to_vec(), expires)))
+    }
+    
+    fn write(&self, key: &str, value: Option<&[u8]>, ttl: Option<Duration>) -> ServerResult<bool> {
+        let path = self.path(key);
+        let value = match value {
+            Some(value) => value,
+            None => {
+                return match fs::remove_file(&path) {
+                    Ok(()) => Ok(true),
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+                    Err(e) => Err(e.into()),
+                };
+            }
+        };
+        let expiry = match ttl {
+            Some(ttl) => {
+                let expires = SystemTime::now() + ttl;
+                expires.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis().to_string()
+            }
+            None => "-".to_string(),
+        };
+        let mut contents = Vec::with_capacity(expiry.len() + 1 + value.len());
+        contents.extend_from_slice(expiry.as_bytes());
+        contents.push(b'\n');
+        contents.extend_from_slice(value);
+        
+        let temp = path.with_extension(format!("tmp{}", std::process::id()));
+        fs::write(&temp, &contents)?;
+        fs::rename(&temp, &path)?;
+        Ok(true)
+    }
+}
+
+impl KvStore for FileKvStore {
+    fn get(&self, key: &str) -> ServerResult<Option<Vec<u8>>> {
+        Ok(self.read(key)?.map(|(value, _)| value))
+    }
+    
+    fn set(&self, key: &str, value: &[u8], ttl: Option<Duration>) -> ServerResult<()> {
+        let _lock = self.lock.lock().unwrap();
+        self.write(key, Some(value), ttl).map(|_| ())
+    }
+    
+    fn del(&self, key: &str) -> ServerResult<bool> {
+        let _lock = self.lock.lock().unwrap();
+        // An expired file is deleted by the read and doesn't count
+        Ok(self.read(key)?.is_some() && self.write(key, None, None)?)
+    }
+    
+    fn ttl(&self, key: &str) -> ServerResult<Option<Duration>> {
+        let expires = self.read(key)?.and_then(|(_, expires)| expires);
+        Ok(expires.map(|expires| expires.duration_since(SystemTime::now()).unwrap_or_default()))
+    }
+    
+    fn cas(
+        &self,
+        key: &str,
+        expected: Option<&[u8]>,
+        value: Option<&[u8]>,
+        ttl: Option<Duration>,
+    ) -> ServerResult<bool> {
+        let _lock = self.lock.lock().unwrap();
+        let current = self.read(key)?.map(|(value, _)| value);
+        if current.as_deref() != expected {
+            return Ok(false);
+        }
+        self.write(key, value, ttl)?;
+        Ok(true)
+    }
+}
+
+/// Compare-and-swap run inside Redis, so that the check and write are atomic
+///
+/// ARGV: whether a value is expected, the expected value, whether there is
+/// a new value, the new value and its TTL in milliseconds (0 for none).
+const CAS_SCRIPT: &str = "\
+local current = redis.call('GET', KEYS[1])
+if ARGV[1] == '1' then
+  if current ~= ARGV[2] then return 0 end
+elseif current then
+  return 0
+end
+if ARGV[3] == '0' then
+  redis.call('DEL', KEYS[1])
+elseif ARGV[5] ~= '0' then
+  redis.call('SET', KEYS[1], ARGV[4], 'PX', ARGV[5])
+else
+  redis.call('SET', KEYS[1], ARGV[4])
+end
+return 1";
+
+/// Values kept in a Redis server, shared by every replica using it
+#[derive(Debug)]
+pub struct RedisKvStore {
+    client: Arc<RespClient>,
+    prefix: String,
+}
+
+impl RedisKvStore {
+    /// Keep values in the Redis server at `address` (`host:port`)
+    pub fn new(address: &str) -> Self {
+        Self::with_client(Arc::new(RespClient::new(address)))
+    }
+    
+    /// Keep values through `client`, which may be shared with other users
+    pub fn with_client(client: Arc<RespClient>) -> Self {
+        Self {
+            client,
+            prefix: String::new(),
+        }
+    }
+    
+    /// Put `prefix` in front of every key
+    pub fn with_prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.to_string();
+        self
+    }
+    
+    fn key(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, key)
+    }
+}
+
+impl KvStore for RedisKvStore {
+    fn get(&self, key: &str) -> ServerResult<Option<Vec<u8>>> {
+        self.client.get(&self.key(key))
+    }
+    
+    fn set(&self, key: &str, value: &[u8], ttl: Option<Duration>) -> ServerResult<()> {
+        self.client.set(&self.key(key), value, ttl)
+    }
+    
+    fn del(&self, key: &str) -> ServerResult<bool> {
+        self.client.del(&self.key(key))
+    }
+    
+    fn ttl(&self, key: &str) -> ServerResult<Option<Duration>> {
+        let reply = self.client.command(&["PTTL", self.key(key).as_str()])?;
+        match reply.as_integer() {
+            // -2 is a missing key and -1 a key that never expires
+            Some(millis) if millis >= 0 => Ok(Some(Duration::from_millis(millis as u64))),
+            Some(_) => Ok(None),
+            None => Err(ServerError::Protocol(format!("Unexpected reply to PTTL: {:?}", reply))),
+        }
+    }
+    
+    fn cas(
+        &self,
+        key: &str,
+        expected: Option<&[u8]>,
+        value: Option<&[u8]>,
+        ttl: Option<Duration>,
+    ) -> ServerResult<bool> {
+        let flag = |present: bool| if present { b"1" as &[u8] } else { b"0" };
+        let millis = ttl.map_or(0, |ttl| ttl.as_millis().max(1)).to_string();
+        let key = self.key(key);
+        let reply = self.client.command(&[
+            b"EVAL" as &[u8],
+            CAS_SCRIPT.as_bytes(),
+            b"1",
+            key.as_bytes(),
+            flag(expected.is_some()),
+            expected.unwrap_or_default(),
+            flag(value.is_some()),
+            value.unwrap_or_default(),
+            millis.as_bytes(),
+        ])?;
+        match reply {
+            RespValue::Integer(swapped) => Ok(swapped == 1),
+            other => Err(ServerError::Protocol(format!("Unexpected reply to compare-and-swap: {:?}", other))),
+        }
+    }
+}
\ No newline at end of file
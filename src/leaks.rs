@@ -0,0 +1,122 @@
+//! Instance accounting for leak detection
+//!
+//! Connections, parsers, buffers and memory handles count themselves as
+//! they are created and dropped. A live count that keeps climbing under
+//! steady traffic, or that isn't back where it started once the event
+//! loops have exited, points at something holding on to them.
+
+use crate::error::{ServerError, ServerResult};
+use crate::metrics::MetricsRegistry;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// A kind of instance that is accounted for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Tracked {
+    Connection,
+    HttpParser,
+    Buffer,
+    MemoryHandle,
+}
+
+impl Tracked {
+    /// Every tracked kind
+    pub const ALL: [Tracked; 4] = [Tracked::Connection, Tracked::HttpParser, Tracked::Buffer, Tracked::MemoryHandle];
+    
+    /// Get the name the kind is published under
+    pub fn name(self) -> &'static str {
+        match self {
+            Tracked::Connection => "connection",
+            Tracked::HttpParser => "http_parser",
+            Tracked::Buffer => "buffer",
+            Tracked::MemoryHandle => "memory_handle",
+        }
+    }
+}
+
+impl fmt::Display for Tracked {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+static CREATED: [AtomicUsize; 4] = [const { AtomicUsize::new(0) }; 4];
+static DESTROYED: [AtomicUsize; 4] = [const { AtomicUsize::new(0) }; 4];
+
+/// Counts one instance of a kind for as long as it lives
+///
+/// Embed one in the tracked type, so that every way of creating the type
+/// goes through `new` and dropping it, however it happens, is counted.
+#[derive(Debug)]
+pub(crate) struct Instance(Tracked);
+
+impl Instance {
+    pub(crate) fn new(kind: Tracked) -> Self {
+        CREATED[kind as usize].fetch_add(1, Ordering::Relaxed);
+        Self(kind)
+    }
+}
+
+impl Drop for Instance {
+    fn drop(&mut self) {
+        DESTROYED[self.0 as usize].fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// How many instances of a kind were created and dropped so far
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct InstanceCounts {
+    pub created: usize,
+    pub destroyed: usize,
+}
+
+impl InstanceCounts {
+    /// Get the number of instances still alive
+    pub fn live(&self) -> usize {
+        self.created.saturating_sub(self.destroyed)
+    }
+}
+
+/// Get the counts for one kind
+pub fn counts(kind: Tracked) -> InstanceCounts {
+    // Destroyed first, so a drop racing with the reads can't make it exceed created
+    let destroyed = DESTROYED[kind as usize].load(Ordering::Relaxed);
+    InstanceCounts {
+        created: CREATED[kind as usize].load(Ordering::Relaxed),
+        destroyed,
+    }
+}
+
+/// Get the counts for every kind, in the order of `Tracked::ALL`
+pub fn snapshot() -> Vec<(Tracked, InstanceCounts)> {
+    Tracked::ALL.iter().map(|&kind| (kind, counts(kind))).collect()
+}
+
+/// Publish the counts as gauges under `instances.{kind}.`
+pub fn publish(registry: &MetricsRegistry) {
+    for (kind, counts) in snapshot() {
+        registry.gauge(&format!("instances.{}.created", kind)).set(counts.created);
+        registry.gauge(&format!("instances.{}.destroyed", kind)).set(counts.destroyed);
+        registry.gauge(&format!("instances.{}.live", kind)).set(counts.live());
+    }
+}
+
+/// Check that no more instances are alive than in `baseline`
+///
+/// Take the baseline before starting the event loops and check once they
+/// have all exited; anything still alive beyond the baseline has leaked.
+pub fn check_balanced(baseline: &[(Tracked, InstanceCounts)]) -> ServerResult<()> {
+    let leaked: Vec<String> = snapshot()
+        .into_iter()
+        .filter_map(|(kind, now)| {
+            let before = baseline.iter().find(|(k, _)| *k == kind).map_or(0, |(_, counts)| counts.live());
+            (now.live() > before).then(|| format!("{} {}", now.live() - before, kind))
+        })
+        .collect();
+    
+    if leaked.is_empty() {
+        Ok(())
+    } else {
+        Err(ServerError::EventLoop(format!("Instances still alive after shutdown: {}", leaked.join(", "))))
+    }
+}
\ No newline at end of file
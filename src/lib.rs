@@ -1,29 +1,144 @@
+pub mod access_log;
 pub mod acceptor;
+pub mod acl;
+#[cfg(feature = "alloc-audit")]
+pub mod alloc_audit;
+pub mod audit;
+pub mod balancer;
 pub mod buffer;
+pub mod cached_response;
+pub mod capacity;
+pub mod chaos;
 pub mod config;
 pub mod connection;
+pub mod credentials;
+#[cfg(unix)]
+pub mod daemon;
+pub mod deadline;
+pub mod digest;
 pub mod error;
 pub mod event_loop;
+pub mod events;
+pub mod exporter;
+pub mod feature_flags;
+#[cfg(all(feature = "fs-notify", target_os = "linux"))]
+pub mod fs_watch;
+#[cfg(feature = "h3")]
+pub mod h3;
+pub mod har;
+pub mod hash;
+pub mod header_name;
 pub mod http;
+pub mod http_client;
+pub mod json_transform;
+pub mod kv;
+pub mod leaks;
+pub mod lifecycle;
+pub mod log_file;
+pub mod maintenance;
 pub mod memory;
 pub mod metrics;
 pub mod middleware;
+pub mod mirror;
+pub mod openapi;
+pub mod password;
+pub mod preconditions;
+pub mod profiler;
+pub mod protocol_upgrade;
+pub mod proxy;
+pub mod rate_limit;
+pub mod redaction;
+pub mod replay;
+pub mod resolver;
+pub mod resp;
 pub mod router;
+pub mod scan;
+pub mod secrets;
+pub mod scheduler;
+pub mod signature;
+pub mod single_flight;
 pub mod static_files;
+#[cfg(unix)]
+pub mod systemd;
+pub mod tenants;
+pub mod throttle;
+pub mod timeline;
+pub mod timer_wheel;
+pub mod traffic_split;
+#[cfg(unix)]
+pub mod upgrade;
+pub mod validation;
 
 /// Re-exports of common components for easier access
-pub use acceptor::ConnectionAcceptor;
-pub use config::ServerConfig;
-pub use connection::Connection;
-pub use error::{ServerError, ServerResult};
-pub use event_loop::{EventLoop, EventPoller};
-pub use http::{HttpParser, Method, Request, Response, Status};
-pub use memory::{MemoryHandle, MemoryManager, MemoryPool};
-pub use metrics::{Counter, Histogram, MetricsCollector, Timer};
+pub use access_log::{AccessLog, AccessLogRules, access_log_middleware};
+pub use acceptor::{ConnectionAcceptor, IpGuard, IpLimits, IpRejection, WorkerLoad};
+pub use acl::{Acl, AclConfig, AclRule, AuthMethod, IpRange, acl_middleware};
+pub use audit::{AuditEntry, AuditLog, audit_middleware, verify_audit_log};
+pub use balancer::{Balancer, HealthPolicy, Pick, Stickiness};
+pub use cached_response::CachedResponse;
+pub use capacity::{CapacityPlan, FdLimit};
+pub use chaos::{Chaos, ChaosConfig, ChaosRule, Fault, chaos_middleware};
+pub use config::{AdminConfig, RouteConfig, RouteOverrides, RouteSettings, ServerConfig, TcpOptions, WriteBatching};
+pub use connection::{Connection, ConnectionState, WriteStatus};
+pub use credentials::{
+    AuthIdentity, CredentialStore, HtpasswdFile, LdapBind, LockoutPolicy, LoginLockout, StaticCredentials,
+    basic_auth_store_middleware,
+};
+pub use deadline::{DEADLINE_HEADER, Deadline, DeadlineConfig, deadline_middleware};
+pub use digest::{DigestConfig, digest_middleware};
+pub use error::{ErrorKind, ErrorResponse, ServerError, ServerResult};
+pub use event_loop::{EventLoop, EventPoller, PollerFault};
+pub use events::{EventBus, ServerEvent, WebhookSink};
+pub use exporter::{ExportTarget, MetricsExportConfig, MetricsExporter};
+pub use feature_flags::{
+    FeatureFlags, Flag, FlagSet, feature_flags_middleware, flag_enabled, mount_feature_flags,
+};
+pub use har::{HarConfig, HarEntry, HarRecorder, har_middleware, read_har};
+pub use json_transform::{JsonRule, JsonTransformConfig, json_transform_middleware};
+pub use kv::{FileKvStore, KvStore, MemoryKvStore, RedisKvStore};
+pub use leaks::{InstanceCounts, Tracked};
+pub use lifecycle::Lifecycle;
+pub use log_file::RotatingFile;
+pub use maintenance::{MaintenanceConfig, MaintenanceMode, mount_maintenance};
+#[cfg(feature = "h3")]
+pub use self::h3::{H3Config, H3Handle, H3Listener};
+pub use http::{Extensions, HttpParser, Method, PreparedResponse, Request, RequestTarget, Response, Status};
+pub use memory::{MemoryHandle, MemoryManager, MemoryPool, PoolStats};
+pub use metrics::{
+    Counter, EventLoopMetrics, Gauge, Histogram, MetricsCollector, PercentileSnapshot, Timer, WindowedCounter,
+    WindowedHistogram, WindowedRates,
+};
 pub use middleware::{
     MiddlewareChain, MiddlewareFn, MiddlewareNext,
-    basic_auth_middleware, compression_middleware, content_type_middleware, 
-    cors_middleware, logging_middleware,
+    bandwidth_limit_middleware, basic_auth_middleware, compression_middleware,
+    content_type_middleware, cors_middleware, error_middleware, logging_middleware, server_timing_middleware,
+};
+pub use mirror::{Mirror, MirrorConfig};
+pub use openapi::{OpenApiInfo, ParamLocation, RouteDoc};
+pub use preconditions::{PreconditionOutcome, Preconditions};
+pub use profiler::{Profile, mount_profiling};
+pub use protocol_upgrade::{DeferredHandler, StreamedBody, Upgrade, UpgradeHandler, UpgradeResponse};
+pub use proxy::{HeaderEdits, HeaderRules, Proxy, ProxyCompression, ProxyConfig, mount_proxy, proxy_handler};
+pub use rate_limit::{
+    MemoryRateLimiter, RateDecision, RateLimit, RateLimitBackend, RedisRateLimiter, SyncedRateLimiter,
+};
+pub use redaction::{RedactionConfig, Redactor};
+pub use replay::{ReplayOptions, ReplayReport, replay};
+pub use resolver::{Resolver, ResolverConfig};
+pub use resp::{Pipeline, RespClient, RespValue};
+pub use router::{MatchPolicy, RouteGroup, RouteTable, Router, TrailingSlash};
+pub use secrets::Secret;
+pub use scheduler::{Cron, Job, MissedRuns, Schedule, Scheduler};
+pub use signature::{SignatureConfig, SignatureScheme, signature_middleware};
+pub use single_flight::{SingleFlight, single_flight_middleware};
+pub use static_files::{
+    AssetManifest, CacheRule, CorsRule, EmbeddedDir, LIVE_RELOAD_PATH, StaticFileConfig, add_static_file_routes, static_files_middleware,
 };
-pub use router::Router;
-pub use static_files::{StaticFileConfig, add_static_file_routes, static_files_middleware};
\ No newline at end of file
+pub use tenants::{TenantConfig, TenantId, Tenants, TenantsConfig, tenant_middleware};
+pub use throttle::TokenBucket;
+pub use validation::{ParamRule, ParamType, ValidationError};
+pub use timeline::{Phase, RequestTimeline, ServerTimings};
+pub use timer_wheel::TimerWheel;
+pub use traffic_split::{
+    RollbackPolicy, TrafficSplit, VARIANT_HEADER, VariantStatus, mount_traffic_split, traffic_split_middleware,
+};
\ No newline at end of file
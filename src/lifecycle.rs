@@ -0,0 +1,115 @@
+//! Hooks run as the server starts and stops
+//!
+//! Applications register work that belongs at fixed points in the life of
+//! the process: warming caches before the first connection is accepted,
+//! opening a database pool on each worker, flushing state on the way out.
+
+use crate::error::{ServerError, ServerResult};
+use std::panic::{self, AssertUnwindSafe};
+
+type StartHook = Box<dyn Fn() -> ServerResult<()> + Send + Sync>;
+type WorkerHook = Box<dyn Fn(usize) -> ServerResult<()> + Send + Sync>;
+
+/// Hooks registered for server startup, worker startup and shutdown
+///
+/// Startup hooks run in the order they were added and stop at the first
+/// failure, which is returned as a `ServerError::Startup` so the server
+/// refuses to start. Shutdown hooks run in reverse order, like destructors,
+/// and all of them run even if some fail.
+#[derive(Default)]
+pub struct Lifecycle {
+    start: Vec<(String, StartHook)>,
+    worker_start: Vec<(String, WorkerHook)>,
+    shutdown: Vec<(String, StartHook)>,
+}
+
+impl Lifecycle {
+    /// Create a lifecycle without hooks
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Run `hook` once before any worker starts
+    pub fn on_start<F>(mut self, name: &str, hook: F) -> Self
+    where
+        F: Fn() -> ServerResult<()> + Send + Sync + 'static,
+    {
+        self.start.push((name.to_string(), Box::new(hook)));
+        self
+    }
+    
+    /// Run `hook` on each worker thread, given its worker ID, before it handles connections
+    pub fn on_worker_start<F>(mut self, name: &str, hook: F) -> Self
+    where
+        F: Fn(usize) -> ServerResult<()> + Send + Sync + 'static,
+    {
+        self.worker_start.push((name.to_string(), Box::new(hook)));
+        self
+    }
+    
+    /// Run `hook` once as the server shuts down
+    pub fn on_shutdown<F>(mut self, name: &str, hook: F) -> Self
+    where
+        F: Fn() -> ServerResult<()> + Send + Sync + 'static,
+    {
+        self.shutdown.push((name.to_string(), Box::new(hook)));
+        self
+    }
+    
+    /// Run the startup hooks
+    pub fn start(&self) -> ServerResult<()> {
+        for (name, hook) in &self.start {
+            run_hook("start", name, hook)?;
+        }
+        Ok(())
+    }
+    
+    /// Run the worker startup hooks for worker `worker_id`
+    pub fn start_worker(&self, worker_id: usize) -> ServerResult<()> {
+        for (name, hook) in &self.worker_start {
+            run_hook(&format!("worker {} start", worker_id), name, || hook(worker_id))?;
+        }
+        Ok(())
+    }
+    
+    /// Run the shutdown hooks, returning the first failure
+    pub fn shutdown(&self) -> ServerResult<()> {
+        let mut first_error = None;
+        for (name, hook) in self.shutdown.iter().rev() {
+            if let Err(e) = run_hook("shutdown", name, hook) {
+                log::error!("{}", e);
+                first_error.get_or_insert(e);
+            }
+        }
+        first_error.map_or(Ok(()), Err)
+    }
+}
+
+impl std::fmt::Debug for Lifecycle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fn names<T>(hooks: &[(String, T)]) -> Vec<&str> {
+            hooks.iter().map(|(name, _)| name.as_str()).collect()
+        }
+        f.debug_struct("Lifecycle")
+            .field("start", &names(&self.start))
+            .field("worker_start", &names(&self.worker_start))
+            .field("shutdown", &names(&self.shutdown))
+            .finish()
+    }
+}
+
+/// Run one hook, turning its error or panic into a startup error naming it
+fn run_hook(stage: &str, name: &str, hook: impl FnOnce() -> ServerResult<()>) -> ServerResult<()> {
+    match panic::catch_unwind(AssertUnwindSafe(hook)) {
+        Ok(Ok(())) => Ok(()),
+        Ok(Err(e)) => Err(ServerError::Startup(format!("{} hook {} failed: {}", stage, name, e))),
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic".to_string());
+            Err(ServerError::Startup(format!("{} hook {} panicked: {}", stage, name, message)))
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,215 @@
+use crate::error::ServerResult;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// Bumped whenever log files should be reopened (e.g. on SIGUSR1)
+static REOPEN_GENERATION: AtomicUsize = AtomicUsize::new(0);
+
+#[cfg(unix)]
+extern "C" fn handle_reopen_signal(_signal: libc::c_int) {
+    REOPEN_GENERATION.fetch_add(1, Ordering::SeqCst);
+}
+
+/// Install a SIGUSR1 handler that makes every log file reopen its path
+///
+/// This lets external tools like logrotate move the file away and have the
+/// server continue writing to a fresh file at the original path.
+#[cfg(unix)]
+pub fn install_reopen_signal() -> ServerResult<()> {
+    let handler = handle_reopen_signal as extern "C" fn(libc::c_int);
+    let previous = unsafe { libc::signal(libc::SIGUSR1, handler as libc::sighandler_t) };
+    if previous == libc::SIG_ERR {
+        return Err(io::Error::last_os_error().into());
+    }
+    
+    Ok(())
+}
+
+/// Ask every log file to reopen its path before its next write
+pub fn request_reopen() {
+    REOPEN_GENERATION.fetch_add(1, Ordering::SeqCst);
+}
+
+/// Get the current reopen generation, to notice reopen requests
+pub fn reopen_generation() -> usize {
+    REOPEN_GENERATION.load(Ordering::SeqCst)
+}
+
+/// A log file that rotates by size and/or age and keeps a bounded history
+///
+/// Rotated files are named `<path>.1` (newest) up to `<path>.<keep>`, with a
+/// `.gz` suffix when compression is enabled.
+pub struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    opened_at: Instant,
+    max_size: Option<u64>,
+    max_age: Option<Duration>,
+    keep: usize,
+    compress: bool,
+    generation: usize,
+}
+
+impl RotatingFile {
+    /// Open (or create) a log file for appending
+    pub fn open<P: AsRef<Path>>(path: P) -> ServerResult<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = Self::open_file(&path)?;
+        let size = file.metadata()?.len();
+        
+        Ok(Self {
+            path,
+            file,
+            size,
+            opened_at: Instant::now(),
+            max_size: None,
+            max_age: None,
+            keep: 7,
+            compress: false,
+            generation: reopen_generation(),
+        })
+    }
+    
+    /// Rotate once the file would grow beyond `bytes`
+    pub fn with_max_size(mut self, bytes: u64) -> Self {
+        self.max_size = Some(bytes);
+        self
+    }
+    
+    /// Rotate once the file has been written to for `age`
+    pub fn with_max_age(mut self, age: Duration) -> Self {
+        self.max_age = Some(age);
+        self
+    }
+    
+    /// Keep at most `count` rotated files
+    pub fn with_keep(mut self, count: usize) -> Self {
+        self.keep = count;
+        self
+    }
+    
+    /// Gzip rotated files
+    pub fn with_compression(mut self, compress: bool) -> Self {
+        self.compress = compress;
+        self
+    }
+    
+    /// Get the path being written to
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    
+    /// Get the size of the current file in bytes
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+    
+    /// Get how long the current file has been written to
+    pub fn age(&self) -> Duration {
+        self.opened_at.elapsed()
+    }
+    
+    fn open_file(path: &Path) -> io::Result<File> {
+        OpenOptions::new().create(true).append(true).open(path)
+    }
+    
+    /// Close the file and open whatever is at the path now
+    pub fn reopen(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        self.file = Self::open_file(&self.path)?;
+        self.size = self.file.metadata()?.len();
+        self.opened_at = Instant::now();
+        Ok(())
+    }
+    
+    /// Get the path of the `index`-th rotated file
+    fn rotated_path(&self, index: usize, compressed: bool) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", index));
+        if compressed {
+            name.push(".gz");
+        }
+        PathBuf::from(name)
+    }
+    
+    /// Move the current file into the rotated history and start a new one
+    pub fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        
+        if self.keep == 0 {
+            fs::remove_file(&self.path)?;
+            return self.reopen();
+        }
+        
+        // Drop the oldest file, then shift the rest up by one
+        for compressed in [false, true] {
+            let _ = fs::remove_file(self.rotated_path(self.keep, compressed));
+        }
+        for index in (1..self.keep).rev() {
+            for compressed in [false, true] {
+                let from = self.rotated_path(index, compressed);
+                if from.exists() {
+                    fs::rename(&from, self.rotated_path(index + 1, compressed))?;
+                }
+            }
+        }
+        
+        let newest = self.rotated_path(1, false);
+        fs::rename(&self.path, &newest)?;
+        self.reopen()?;
+        
+        if self.compress {
+            compress_file(&newest, &self.rotated_path(1, true))?;
+        }
+        
+        Ok(())
+    }
+    
+    /// Check whether writing `incoming` bytes should start a new file first
+    fn needs_rotation(&self, incoming: usize) -> bool {
+        if self.size == 0 {
+            return false;
+        }
+        
+        let too_big = self.max_size.is_some_and(|max| self.size + incoming as u64 > max);
+        let too_old = self.max_age.is_some_and(|age| self.opened_at.elapsed() >= age);
+        too_big || too_old
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let generation = reopen_generation();
+        if generation != self.generation {
+            self.generation = generation;
+            self.reopen()?;
+        }
+        
+        if self.needs_rotation(buf.len()) {
+            self.rotate()?;
+        }
+        
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+    
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Gzip `source` into `target` and remove the original
+fn compress_file(source: &Path, target: &Path) -> io::Result<()> {
+    let mut input = File::open(source)?;
+    let mut encoder = GzEncoder::new(File::create(target)?, Compression::default());
+    io::copy(&mut input, &mut encoder)?;
+    encoder.finish()?;
+    fs::remove_file(source)
+}
\ No newline at end of file
@@ -1,35 +1,164 @@
-use high_performance_server::{ConnectionAcceptor, EventLoop, MetricsCollector, ServerConfig, ServerResult};
+use high_performance_server::{
+    Acl, AdminConfig, CapacityPlan, ConnectionAcceptor, EventBus, EventLoop, Lifecycle, LockoutPolicy,
+    MaintenanceMode, MetricsCollector, MetricsExporter, MiddlewareChain, ReplayOptions, Request, Response, RouteSettings,
+    Router, ServerConfig, ServerError, ServerEvent, ServerResult, StaticCredentials, Status, WorkerLoad,
+    basic_auth_store_middleware, mount_maintenance, read_har, replay,
+};
+#[cfg(unix)]
+use high_performance_server::daemon::{self, PidFile};
+use high_performance_server::leaks;
+use high_performance_server::resolver::{self, Resolver};
 use std::io;
 use std::num::NonZeroUsize;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
 use std::sync::Arc;
 use std::path::Path;
 use std::env;
 use std::fs;
 use std::time::Duration;
 
+const REPLAY_USAGE: &str = "\
+Usage: server replay [options] CAPTURE.har...
+
+Sends the requests in HAR captures to a server at their recorded pace.
+
+Options:
+  -t, --target URL        Server to replay against (default http://127.0.0.1:8080)
+  -s, --speed X           Times faster than recorded; 0 sends as fast as possible (default 1)
+  -c, --concurrency N     Requests in flight at once (default 8)
+      --timeout SECS      Longest time spent on one request (default 5)
+";
+
 fn main() -> ServerResult<()> {
+    if env::args().nth(1).as_deref() == Some("replay") {
+        replay_command(env::args().skip(2));
+        return Ok(());
+    }
+    
     // Parse command-line arguments
-    let args: Vec<String> = env::args().collect();
-    let config = if args.len() > 1 && Path::new(&args[1]).exists() {
-        // Load configuration from file
-        ServerConfig::from_json_file(&args[1])?
-    } else {
+    let mut config_path = None;
+    let mut daemon = false;
+    let mut pid_file = None;
+    let mut log_file = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--daemon" => daemon = true,
+            "--pid-file" => pid_file = args.next(),
+            "--log-file" => log_file = args.next(),
+            _ => config_path = Some(arg),
+        }
+    }
+    
+    let mut config = match config_path {
+        // Load configuration from file, explaining what is wrong with it if anything
+        Some(path) if Path::new(&path).exists() => ServerConfig::from_json_file(&path).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }),
         // Use default configuration
-        ServerConfig::new()
+        _ => ServerConfig::new(),
     };
     
     // Create metrics collector
     let metrics = Arc::new(MetricsCollector::new());
     let metrics_clone = metrics.clone();
     
-    // Create a connection acceptor that will bind to a specific address
-    let address = config.socket_address();
-    let acceptor = ConnectionAcceptor::new(&address)?;
+    // Create a connection acceptor for every listen address, or take over the
+    // listening sockets handed down by a previous process or by systemd
+    let addresses = config.socket_addresses();
+    #[cfg(unix)]
+    let upgraded = high_performance_server::upgrade::inherited_acceptors()?;
+    #[cfg(not(unix))]
+    let upgraded: Vec<ConnectionAcceptor> = Vec::new();
+    let is_upgrade_successor = !upgraded.is_empty();
+    
+    #[cfg(unix)]
+    let inherited = if is_upgrade_successor {
+        upgraded
+    } else {
+        high_performance_server::systemd::listen_fds()?
+    };
+    #[cfg(not(unix))]
+    let inherited = upgraded;
+    
+    // Refuse to start if another instance is running before binding anything
+    #[cfg(unix)]
+    if let Some(path) = &pid_file {
+        if !is_upgrade_successor {
+            PidFile::check(path)?;
+        }
+    }
     
-    println!("Starting server on {} with {} worker threads", address, config.worker_threads);
+    let acceptors = if inherited.is_empty() {
+        addresses
+            .iter()
+            .map(|address| ConnectionAcceptor::with_options(address, config.backlog_size, config.tcp.clone()))
+            .collect::<io::Result<Vec<_>>>()?
+    } else {
+        inherited
+            .into_iter()
+            .map(|acceptor| acceptor.with_tcp_options(config.tcp.clone()))
+            .collect()
+    };
     
-    // Create a shared acceptor
-    let acceptor = Arc::new(acceptor);
+    let bound: Vec<String> = acceptors
+        .iter()
+        .filter_map(|acceptor| acceptor.local_addr().ok())
+        .map(|addr| addr.to_string())
+        .collect();
+    println!("Starting server on {} with {} worker threads", bound.join(", "), config.worker_threads);
+    
+    // Fit the connections each worker holds to the file descriptor limit
+    match CapacityPlan::for_process(&config, acceptors.len()) {
+        Ok(plan) => {
+            for warning in &plan.warnings {
+                eprintln!("Warning: {}", warning);
+            }
+            println!(
+                "File descriptor limit {}: up to {} connections per worker",
+                plan.fd_limit.soft, plan.max_connections_per_worker
+            );
+            config.max_connections_per_worker = Some(plan.max_connections_per_worker);
+        }
+        Err(e) => eprintln!("Failed to read the file descriptor limit: {}", e),
+    }
+    
+    // Detach into the background once startup errors can no longer happen.
+    // A process started by a binary upgrade is already detached.
+    #[cfg(unix)]
+    if daemon && !is_upgrade_successor {
+        daemon::daemonize(log_file.as_ref().map(Path::new))?;
+    }
+    #[cfg(not(unix))]
+    if daemon || log_file.is_some() {
+        eprintln!("--daemon is only supported on Unix platforms");
+    }
+    
+    // Hold the pid file for the lifetime of the process. An upgraded process
+    // waits in the background for its predecessor to drain and release it.
+    #[cfg(unix)]
+    let _pid_file = match &pid_file {
+        Some(path) if is_upgrade_successor => {
+            let path = path.clone();
+            std::thread::spawn(move || match PidFile::acquire_blocking(&path) {
+                Ok(pid_file) => std::mem::forget(pid_file),
+                Err(e) => eprintln!("Failed to acquire pid file {}: {}", path, e),
+            });
+            None
+        }
+        Some(path) => Some(PidFile::acquire(path)?),
+        None => None,
+    };
+    
+    // Share the acceptors between all event loops
+    let acceptors: Vec<Arc<ConnectionAcceptor>> = acceptors.into_iter().map(Arc::new).collect();
+    
+    // Per-worker connection counts shared by all event loops
+    let worker_load = Arc::new(WorkerLoad::new(config.worker_threads));
+    let worker_load_clone = worker_load.clone();
     
     // Start a metrics printer thread
     let metrics_thread = std::thread::spawn(move || {
@@ -38,36 +167,325 @@ fn main() -> ServerResult<()> {
             std::thread::sleep(Duration::from_secs(10));
             
             // Print current metrics
+            worker_load_clone.publish(&metrics_clone.registry());
+            leaks::publish(&metrics_clone.registry());
             println!("\n===== Server Metrics =====");
             println!("{}", metrics_clone.format());
             println!("==========================\n");
         }
     });
     
+    // Push metrics to an external collector if configured
+    let _exporter = config
+        .metrics_export
+        .clone()
+        .map(|export| MetricsExporter::new(metrics.registry(), export).spawn());
+    
+    // Outbound clients resolve host names through one shared, caching resolver
+    let resolver = Resolver::with_metrics(config.resolver.clone(), metrics.registry());
+    if resolver::set_shared(Arc::new(resolver)).is_err() {
+        eprintln!("Warning: the resolver was already in use; its settings are left as they were");
+    }
+    
+    // Lifecycle events, optionally forwarded to a webhook
+    let events = Arc::new(EventBus::new());
+    if let Some(url) = &config.event_webhook {
+        events.subscribe_webhook(url.expose())?;
+    }
+    
+    // Hooks run around startup and shutdown. Startup hooks failing stops the
+    // server before it reports itself started.
+    #[cfg(unix)]
+    let lifecycle = Lifecycle::new().on_shutdown("systemd", || {
+        high_performance_server::systemd::notify_stopping().map(|_| ())
+    });
+    #[cfg(not(unix))]
+    let lifecycle = Lifecycle::new();
+    let lifecycle = Arc::new(lifecycle);
+    lifecycle.start()?;
+    
+    // When the release profile's `panic = "abort"` ends the process, only
+    // the hook gets to report a worker's panic. Unwinding panics are
+    // reported where the worker catches them instead, so ones the lifecycle
+    // catches in start hooks aren't mistaken for a dead worker.
+    if cfg!(panic = "abort") {
+        let default_hook = panic::take_hook();
+        let events_for_hook = events.clone();
+        panic::set_hook(Box::new(move |info| {
+            default_hook(info);
+            let name = std::thread::current().name().map(str::to_string);
+            if let Some(worker) = name.and_then(|name| name.strip_prefix("worker-")?.parse().ok()) {
+                let message = panic_message(info.payload());
+                events_for_hook.emit(ServerEvent::WorkerPanicked { worker, message });
+            }
+        }));
+    }
+    
+    // Shared flag telling the event loops to stop accepting and drain
+    let drain_signal = Arc::new(AtomicBool::new(false));
+    
+    // One maintenance switch for all event loops
+    let maintenance = Arc::new(MaintenanceMode::new(config.maintenance.clone()));
+    
+    // Admin routes, if any are configured, shared by all event loops
+    let admin = admin_chain(&config.admin, maintenance.clone());
+    
+    // Access rules from the config, compiled once for all event loops
+    let acl = Arc::new(Acl::new(&config.acl)?);
+    
+    // Instances alive before the event loops start, which must be all that's left once they stop
+    let instances = leaks::snapshot();
+    
     // Spawn one event loop per worker thread
     let mut handles = Vec::with_capacity(config.worker_threads);
+    let (started_tx, started_rx) = mpsc::channel();
     
     for id in 0..config.worker_threads {
-        let acceptor_clone = acceptor.clone();
-        let handle = std::thread::spawn(move || {
-            let mut event_loop = EventLoop::new(id as u32, acceptor_clone);
-            event_loop.run()
-        });
+        let acceptors_clone = acceptors.clone();
+        let config_clone = config.clone();
+        let drain_signal_clone = drain_signal.clone();
+        let maintenance_clone = maintenance.clone();
+        let acl_clone = acl.clone();
+        let admin_clone = admin.clone();
+        let worker_load_clone = worker_load.clone();
+        let metrics_for_loop = metrics.clone();
+        let lifecycle_clone = lifecycle.clone();
+        let events_clone = events.clone();
+        let started_tx = started_tx.clone();
+        let handle = std::thread::Builder::new().name(format!("worker-{}", id)).spawn(move || {
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                // The main thread reports a failed start
+                let started = lifecycle_clone.start_worker(id);
+                let failed = started.is_err();
+                let _ = started_tx.send(started);
+                if failed {
+                    return Ok(());
+                }
+                
+                let mut event_loop = EventLoop::with_acceptors(id as u32, acceptors_clone, config_clone);
+                event_loop.set_drain_signal(drain_signal_clone);
+                event_loop.set_maintenance(maintenance_clone);
+                if let Some(admin) = admin_clone {
+                    event_loop.set_middleware_chain(admin);
+                }
+                if !acl_clone.is_empty() {
+                    event_loop.set_acl(acl_clone);
+                }
+                event_loop.set_worker_load(worker_load_clone);
+                event_loop.set_metrics(metrics_for_loop);
+                event_loop.run()
+            }));
+            
+            result.unwrap_or_else(|payload| {
+                let message = panic_message(&*payload);
+                events_clone.emit(ServerEvent::WorkerPanicked { worker: id, message: message.clone() });
+                Err(ServerError::EventLoop(format!("Worker {} panicked: {}", id, message)))
+            })
+        })?;
         handles.push(handle);
     }
+    drop(started_tx);
     
-    // Set up a signal handler for graceful shutdown
+    // Wait for every worker's start hooks to finish
+    for started in started_rx.iter().take(config.worker_threads) {
+        if let Err(e) = started {
+            drain_signal.store(true, Ordering::SeqCst);
+            let _ = lifecycle.shutdown();
+            return Err(e);
+        }
+    }
+    
+    events.emit(ServerEvent::ServerStarted {
+        addresses: bound.clone(),
+        workers: config.worker_threads,
+    });
+    
+    // Set up a signal handler for graceful shutdown: the workers drain and
+    // exit, and the end of `main` checks for leaks and runs the
+    // shutdown hooks. A second signal stops the server without waiting.
+    let drain_signal_clone = drain_signal.clone();
     ctrlc::set_handler(move || {
-        println!("Received shutdown signal. Stopping server...");
-        std::process::exit(0);
+        if drain_signal_clone.swap(true, Ordering::SeqCst) {
+            println!("Received second shutdown signal. Exiting now.");
+            std::process::exit(1);
+        }
+        println!("Received shutdown signal. Draining connections...");
     }).expect("Error setting Ctrl-C handler");
     
-    // Wait for all threads to complete (they shouldn't unless there's an error)
+    // Let systemd know we're up and keep its watchdog fed
+    #[cfg(unix)]
+    {
+        use high_performance_server::systemd;
+        
+        if let Err(e) = systemd::notify_ready() {
+            eprintln!("Failed to notify systemd: {}", e);
+        }
+        
+        if let Some(interval) = systemd::watchdog_interval() {
+            std::thread::spawn(move || loop {
+                std::thread::sleep(interval / 2);
+                let _ = systemd::notify_watchdog();
+            });
+        }
+    }
+    
+    // On SIGUSR2, hand the listening socket to a freshly exec'd binary and drain
+    #[cfg(unix)]
+    {
+        use high_performance_server::log_file::{install_reopen_signal, reopen_generation};
+        use high_performance_server::upgrade;
+        
+        upgrade::install_upgrade_signal()?;
+        install_reopen_signal()?;
+        let mut log_generation = reopen_generation();
+        
+        while !handles.iter().all(|handle| handle.is_finished()) {
+            // On SIGUSR1, reopen the daemon's log file for logrotate
+            let generation = reopen_generation();
+            if generation != log_generation {
+                log_generation = generation;
+                if let (true, Some(path)) = (daemon, &log_file) {
+                    if let Err(e) = daemon::reopen_log(Path::new(path)) {
+                        eprintln!("Failed to reopen log file {}: {}", path, e);
+                    }
+                }
+            }
+            
+            if upgrade::take_upgrade_request() && !drain_signal.load(Ordering::SeqCst) {
+                match upgrade::spawn_successor(&acceptors) {
+                    Ok(child) => {
+                        println!("Started new server process {}. Draining connections...", child.id());
+                        drain_signal.store(true, Ordering::SeqCst);
+                    }
+                    Err(e) => eprintln!("Binary upgrade failed: {}", e),
+                }
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+    }
+    
+    // Wait for all threads to complete (they shouldn't unless there's an error or a drain)
     for handle in handles {
         let _ = handle.join();
     }
+    let balanced = leaks::check_balanced(&instances);
     
-    Ok(())
+    lifecycle.shutdown()?;
+    balanced
+}
+
+// Build the chain serving the admin routes the config asks for, if any
+//
+// Admin routes ask for Basic auth; every other request gets the event
+// loop's usual answer, as if there were no chain.
+fn admin_chain(admin: &AdminConfig, maintenance: Arc<MaintenanceMode>) -> Option<Arc<MiddlewareChain>> {
+    let path = admin.maintenance_path.clone()?;
+    let mut router = Router::new();
+    mount_maintenance(&mut router, &path, maintenance);
+    router.set_not_found_handler(|_| {
+        let mut response = Response::new(Status::Ok);
+        response.set_body(b"Hello, World!\n");
+        Ok(response)
+    });
+    
+    let store = Arc::new(StaticCredentials::new(&admin.username, admin.password.expose()));
+    let auth = basic_auth_store_middleware(store, "Admin".to_string(), Some(LockoutPolicy::default()));
+    let mut chain = MiddlewareChain::new();
+    chain.add(move |request, next| {
+        if !is_admin_path(request, &path) {
+            return next(request);
+        }
+        // `require_auth: false` in the route settings doesn't open up admin routes
+        let mut request = request.clone();
+        request.extensions.remove::<RouteSettings>();
+        auth(&request, next)
+    });
+    chain.set_handler(move |request| router.handle_request(request));
+    Some(Arc::new(chain))
+}
+
+// Check whether a request is for an admin route at `path`
+//
+// Compares segments the way the router does, skipping empty ones, but
+// ignoring case too, so no spelling the router accepts goes unchecked.
+fn is_admin_path(request: &Request, path: &str) -> bool {
+    let mut requested = request.path().split('/').filter(|segment| !segment.is_empty());
+    let mut admin = path.split('/').filter(|segment| !segment.is_empty());
+    loop {
+        match (requested.next(), admin.next()) {
+            (Some(a), Some(b)) if a.eq_ignore_ascii_case(b) => {}
+            (None, None) => return true,
+            _ => return false,
+        }
+    }
+}
+
+// Get the message a panic was raised with
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string())
+}
+
+// Replay HAR captures against a server and print what happened
+fn replay_command<I: Iterator<Item = String>>(mut args: I) {
+    let mut options = ReplayOptions::default();
+    let mut captures = Vec::new();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().unwrap_or_else(|| replay_usage(&format!("{} needs a value", arg)));
+        match arg.as_str() {
+            "-t" | "--target" => options.target = value(),
+            "-s" | "--speed" => options.speed = parse_replay_number(&arg, &value()),
+            "-c" | "--concurrency" => options.concurrency = parse_replay_number(&arg, &value()),
+            "--timeout" => {
+                let seconds: f64 = parse_replay_number(&arg, &value());
+                options.timeout = Duration::from_secs_f64(seconds.max(0.001));
+            }
+            "-h" | "--help" => replay_usage(""),
+            _ if arg.starts_with('-') => replay_usage(&format!("Unknown option: {}", arg)),
+            _ => captures.push(arg),
+        }
+    }
+    if captures.is_empty() {
+        replay_usage("No capture files given");
+    }
+    
+    let mut entries = Vec::new();
+    for path in &captures {
+        entries.extend(read_har(path).unwrap_or_else(|e| {
+            eprintln!("Failed to read {}: {}", path, e);
+            std::process::exit(1);
+        }));
+    }
+    println!("Replaying {} requests from {} against {}", entries.len(), captures.join(", "), options.target);
+    let report = replay(&entries, &options).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+    
+    println!("Completed: {}", report.completed);
+    println!("Failed: {}", report.failed);
+    println!("Status differs from capture: {}", report.status_mismatches);
+    println!("Elapsed: {:.2}s", report.elapsed.as_secs_f64());
+    println!("Furthest behind schedule: {:.1}ms", report.max_lag.as_secs_f64() * 1000.0);
+    for (name, percentile) in [("p50", 50.0), ("p90", 90.0), ("p99", 99.0), ("max", 100.0)] {
+        println!("Latency {}: {:.1}ms", name, report.percentile(percentile).as_secs_f64() * 1000.0);
+    }
+}
+
+// Print what is wrong with the replay arguments and how to use them, then exit
+fn replay_usage(message: &str) -> ! {
+    if !message.is_empty() {
+        eprintln!("{}\n", message);
+    }
+    eprint!("{}", REPLAY_USAGE);
+    std::process::exit(2);
+}
+
+fn parse_replay_number<T: std::str::FromStr>(name: &str, value: &str) -> T {
+    value.parse().unwrap_or_else(|_| replay_usage(&format!("Invalid value for {}: {}", name, value)))
 }
 
 // Save default configuration to a file
@@ -0,0 +1,201 @@
+use crate::error::ServerResult;
+use crate::http::{Request, Response, Status};
+use crate::router::Router;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+
+/// Settings for answering requests with 503 during planned maintenance
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MaintenanceConfig {
+    /// Start the server in maintenance mode
+    pub enabled: bool,
+    
+    /// Seconds sent in the `Retry-After` header (None = no header)
+    pub retry_after_secs: Option<u64>,
+    
+    /// Plain text body of the 503 response
+    pub body: String,
+    
+    /// Path prefixes still served normally, such as admin routes
+    pub exempt_paths: Vec<String>,
+}
+
+impl Default for MaintenanceConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            retry_after_secs: Some(300),
+            body: "The server is down for maintenance\n".to_string(),
+            exempt_paths: Vec::new(),
+        }
+    }
+}
+
+/// A maintenance switch shared by every event loop
+///
+/// While enabled, requests outside the exempt paths get `503 Service
+/// Unavailable` instead of reaching the router. Listeners and open
+/// connections are left alone, so turning it off resumes service at once.
+#[derive(Debug)]
+pub struct MaintenanceMode {
+    enabled: AtomicBool,
+    settings: RwLock<MaintenanceConfig>,
+}
+
+impl MaintenanceMode {
+    /// Create a switch from its configuration
+    pub fn new(config: MaintenanceConfig) -> Self {
+        Self {
+            enabled: AtomicBool::new(config.enabled),
+            settings: RwLock::new(config),
+        }
+    }
+    
+    /// Check whether maintenance mode is on
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::SeqCst)
+    }
+    
+    /// Start answering requests with 503
+    pub fn enable(&self) {
+        self.set_enabled(true);
+    }
+    
+    /// Resume serving requests
+    pub fn disable(&self) {
+        self.set_enabled(false);
+    }
+    
+    fn set_enabled(&self, enabled: bool) {
+        self.settings.write().unwrap().enabled = enabled;
+        self.enabled.store(enabled, Ordering::SeqCst);
+    }
+    
+    /// Set the `Retry-After` seconds sent with the 503 (None = no header)
+    pub fn set_retry_after(&self, seconds: Option<u64>) {
+        self.settings.write().unwrap().retry_after_secs = seconds;
+    }
+    
+    /// Set the body of the 503 response
+    pub fn set_body(&self, body: &str) {
+        self.settings.write().unwrap().body = body.to_string();
+    }
+    
+    /// Keep serving requests under `prefix` during maintenance
+    pub fn exempt(&self, prefix: &str) {
+        let mut settings = self.settings.write().unwrap();
+        if !settings.exempt_paths.iter().any(|path| path == prefix) {
+            settings.exempt_paths.push(prefix.to_string());
+        }
+    }
+    
+    /// Get the current settings
+    pub fn settings(&self) -> MaintenanceConfig {
+        self.settings.read().unwrap().clone()
+    }
+    
+    /// Get the 503 response for `request`, or None if it should be served
+    pub fn check(&self, request: &Request) -> Option<Response> {
+        if !self.is_enabled() {
+            return None;
+        }
+        
+        let settings = self.settings.read().unwrap();
+        let path = request.uri.split('?').next().unwrap_or("");
+        if settings.exempt_paths.iter().any(|prefix| path_has_prefix(path, prefix)) {
+            return None;
+        }
+        
+        let mut response = Response::new(Status::ServiceUnavailable);
+        response.set_body(settings.body.as_bytes());
+        if let Some(seconds) = settings.retry_after_secs {
+            response.set_header("Retry-After", &seconds.to_string());
+        }
+        Some(response)
+    }
+}
+
+impl Default for MaintenanceMode {
+    fn default() -> Self {
+        Self::new(MaintenanceConfig::default())
+    }
+}
+
+/// Check whether `path` is `prefix` or lies below it
+fn path_has_prefix(path: &str, prefix: &str) -> bool {
+    let prefix = prefix.trim_end_matches('/');
+    path.strip_prefix(prefix).is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// Changes accepted by the admin route; missing fields are left as they are
+#[derive(Deserialize)]
+struct MaintenanceUpdate {
+    enabled: Option<bool>,
+    #[serde(default, with = "double_option")]
+    retry_after_secs: Option<Option<u64>>,
+    body: Option<String>,
+}
+
+/// Tell an explicit `null` apart from a missing field
+mod double_option {
+    use serde::{Deserialize, Deserializer};
+    
+    pub fn deserialize<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+    where
+        D: Deserializer<'de>,
+        T: Deserialize<'de>,
+    {
+        Option::<T>::deserialize(deserializer).map(Some)
+    }
+}
+
+/// Register a maintenance admin route at `path`, which is exempted from maintenance
+///
+/// - `GET <path>` reports the current settings as JSON
+/// - `PUT <path>` changes them from a JSON body like
+///   `{"enabled": true, "retry_after_secs": 600, "body": "Back soon\n"}`;
+///   fields left out keep their value and `"retry_after_secs": null` drops
+///   the header
+///
+/// Anyone who can reach this route can take the site down, so put it behind
+/// authentication middleware or a listener that is not publicly reachable.
+pub fn mount_maintenance(router: &mut Router, path: &str, maintenance: Arc<MaintenanceMode>) {
+    maintenance.exempt(path);
+    
+    let status = maintenance.clone();
+    router.get(path, move |_| settings_response(&status));
+    
+    router.put(path, move |req| {
+        let update: MaintenanceUpdate = match serde_json::from_slice(&req.body) {
+            Ok(update) => update,
+            Err(e) => {
+                let mut response = Response::new(Status::BadRequest);
+                response.set_body(format!("Invalid maintenance settings: {}\n", e).as_bytes());
+                return Ok(response);
+            }
+        };
+        
+        if let Some(retry_after) = update.retry_after_secs {
+            maintenance.set_retry_after(retry_after);
+        }
+        if let Some(body) = &update.body {
+            maintenance.set_body(body);
+        }
+        match update.enabled {
+            Some(true) => maintenance.enable(),
+            Some(false) => maintenance.disable(),
+            None => {}
+        }
+        settings_response(&maintenance)
+    });
+}
+
+fn settings_response(maintenance: &MaintenanceMode) -> ServerResult<Response> {
+    let body = serde_json::to_vec(&maintenance.settings())?;
+    let mut response = Response::new(Status::Ok);
+    response.set_body(&body);
+    response.set_header("Content-Type", "application/json");
+    Ok(response)
+}
\ No newline at end of file
@@ -1,4 +1,5 @@
 use crate::error::{ServerError, ServerResult};
+use crate::leaks::{Instance, Tracked};
 use std::ptr::{NonNull};
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
@@ -31,6 +32,10 @@ pub struct MemoryPool {
     size_class: usize,
 }
 
+// SAFETY: block pointers only point into the heap chunks the pool owns,
+// which stay put when the pool moves to another thread
+unsafe impl Send for MemoryPool {}
+
 impl MemoryPool {
     /// Create a new memory pool with blocks of the specified size
     pub fn new(block_size: usize, initial_blocks: usize) -> Self {
@@ -145,6 +150,28 @@ impl MemoryPool {
     }
 }
 
+/// Usage of one size class at a point in time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolStats {
+    pub size_class: usize,
+    /// Blocks the pool owns
+    pub capacity: usize,
+    /// Blocks currently handed out
+    pub in_use: usize,
+}
+
+impl PoolStats {
+    /// Bytes reserved by the pool
+    pub fn reserved_bytes(&self) -> usize {
+        self.capacity * self.size_class
+    }
+    
+    /// Bytes in blocks currently handed out
+    pub fn in_use_bytes(&self) -> usize {
+        self.in_use * self.size_class
+    }
+}
+
 /// A thread-safe memory allocator that manages multiple pools
 pub struct MemoryAllocator {
     // Pools for different size classes
@@ -212,6 +239,19 @@ impl MemoryAllocator {
         
         pool.deallocate(ptr)
     }
+    
+    /// Take a snapshot of every pool's usage, smallest size class first
+    pub fn pool_stats(&self) -> Vec<PoolStats> {
+        let pools = self.pools.lock().unwrap();
+        pools
+            .iter()
+            .map(|pool| PoolStats {
+                size_class: pool.size_class(),
+                capacity: pool.capacity(),
+                in_use: pool.in_use(),
+            })
+            .collect()
+    }
 }
 
 /// A reference-counted wrapper for memory allocation
@@ -235,6 +275,7 @@ impl MemoryManager {
             ptr,
             size_class,
             allocator: self.allocator.clone(),
+            _instance: Instance::new(Tracked::MemoryHandle),
         })
     }
     
@@ -242,6 +283,11 @@ impl MemoryManager {
     pub fn create_buffer(&self, size: usize) -> ServerResult<MemoryHandle> {
         self.allocate(size)
     }
+    
+    /// Take a snapshot of every pool's usage, smallest size class first
+    pub fn pool_stats(&self) -> Vec<PoolStats> {
+        self.allocator.pool_stats()
+    }
 }
 
 impl Default for MemoryManager {
@@ -255,6 +301,7 @@ pub struct MemoryHandle {
     ptr: NonNull<u8>,
     size_class: usize,
     allocator: Arc<MemoryAllocator>,
+    _instance: Instance,
 }
 
 impl MemoryHandle {
@@ -1,30 +1,66 @@
+use crate::http::Method;
+use crate::timeline::{Phase, RequestTimeline};
 use std::collections::HashMap;
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
 use std::time::{Duration, Instant};
 
-/// A simple counter that can be incremented atomically
+/// Fewest shards a sharded metric gets, so 16 workers don't share even on a small machine
+const MIN_SHARDS: usize = 16;
+
+/// Most shards a sharded metric gets, bounding its memory
+const MAX_SHARDS: usize = 64;
+
+/// Number of shards in each counter and histogram, a power of two
+fn shard_count() -> usize {
+    static SHARDS: OnceLock<usize> = OnceLock::new();
+    *SHARDS.get_or_init(|| num_cpus::get().max(MIN_SHARDS).next_power_of_two().min(MAX_SHARDS))
+}
+
+/// Get the shard the current thread records into
+///
+/// Threads are numbered as they first record, so worker threads started
+/// together land on different shards until there are more than shards.
+fn thread_shard() -> usize {
+    static NEXT_THREAD: AtomicUsize = AtomicUsize::new(0);
+    thread_local! {
+        static THREAD: usize = NEXT_THREAD.fetch_add(1, Ordering::Relaxed);
+    }
+    THREAD.with(|thread| *thread) & (shard_count() - 1)
+}
+
+/// A value on a cache line of its own, so that writes to neighbours don't contend
+///
+/// 128 bytes covers CPUs that fetch cache lines in pairs.
+#[derive(Debug, Default)]
+#[repr(align(128))]
+struct Padded<T>(T);
+
+/// A counter that can be incremented atomically
+///
+/// Each thread increments a shard of its own, so workers counting the
+/// same thing don't fight over one cache line; reading adds them up.
 #[derive(Debug)]
 pub struct Counter {
-    value: AtomicUsize,
+    shards: Box<[Padded<AtomicUsize>]>,
 }
 
 impl Counter {
     /// Create a new counter with an initial value
     pub fn new(initial_value: usize) -> Self {
-        Self {
-            value: AtomicUsize::new(initial_value),
-        }
+        let shards: Box<[Padded<AtomicUsize>]> = (0..shard_count()).map(|_| Padded::default()).collect();
+        shards[0].0.store(initial_value, Ordering::Relaxed);
+        Self { shards }
     }
     
     /// Increment the counter by a specific amount
     pub fn increment(&self, amount: usize) {
-        self.value.fetch_add(amount, Ordering::Relaxed);
+        self.shards[thread_shard()].0.fetch_add(amount, Ordering::Relaxed);
     }
     
     /// Get the current value of the counter
     pub fn value(&self) -> usize {
-        self.value.load(Ordering::Relaxed)
+        self.shards.iter().fold(0, |total, shard| total.wrapping_add(shard.0.load(Ordering::Relaxed)))
     }
 }
 
@@ -34,10 +70,57 @@ impl Default for Counter {
     }
 }
 
+/// A gauge holding a value that can go up and down
+#[derive(Debug, Default)]
+pub struct Gauge {
+    value: AtomicUsize,
+}
+
+impl Gauge {
+    /// Create a new gauge with an initial value
+    pub fn new(initial_value: usize) -> Self {
+        Self {
+            value: AtomicUsize::new(initial_value),
+        }
+    }
+    
+    /// Set the gauge to a specific value
+    pub fn set(&self, value: usize) {
+        self.value.store(value, Ordering::Relaxed);
+    }
+    
+    /// Increment the gauge by a specific amount
+    pub fn increment(&self, amount: usize) {
+        self.value.fetch_add(amount, Ordering::Relaxed);
+    }
+    
+    /// Decrement the gauge by a specific amount, saturating at zero
+    pub fn decrement(&self, amount: usize) {
+        let _ = self.value.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |v| {
+            Some(v.saturating_sub(amount))
+        });
+    }
+    
+    /// Get the current value of the gauge
+    pub fn value(&self) -> usize {
+        self.value.load(Ordering::Relaxed)
+    }
+}
+
 /// A histogram for tracking distribution of values
+///
+/// Like `Counter`, each thread records into a shard of its own and the
+/// statistics are combined when read.
 #[derive(Debug)]
 pub struct Histogram {
-    buckets: RwLock<Vec<(f64, AtomicUsize)>>,
+    boundaries: Vec<f64>,
+    shards: Box<[Padded<HistogramShard>]>,
+}
+
+/// One thread's share of a histogram
+#[derive(Debug)]
+struct HistogramShard {
+    buckets: Box<[AtomicUsize]>,
     count: AtomicUsize,
     sum: AtomicUsize,
     min: AtomicUsize,
@@ -47,18 +130,21 @@ pub struct Histogram {
 impl Histogram {
     /// Create a new histogram with specified buckets
     pub fn new(bucket_boundaries: &[f64]) -> Self {
-        let mut buckets = Vec::with_capacity(bucket_boundaries.len());
-        
-        for &boundary in bucket_boundaries {
-            buckets.push((boundary, AtomicUsize::new(0)));
-        }
+        let shards = (0..shard_count())
+            .map(|_| {
+                Padded(HistogramShard {
+                    buckets: bucket_boundaries.iter().map(|_| AtomicUsize::new(0)).collect(),
+                    count: AtomicUsize::new(0),
+                    sum: AtomicUsize::new(0),
+                    min: AtomicUsize::new(usize::MAX),
+                    max: AtomicUsize::new(0),
+                })
+            })
+            .collect();
         
         Self {
-            buckets: RwLock::new(buckets),
-            count: AtomicUsize::new(0),
-            sum: AtomicUsize::new(0),
-            min: AtomicUsize::new(usize::MAX),
-            max: AtomicUsize::new(0),
+            boundaries: bucket_boundaries.to_vec(),
+            shards,
         }
     }
     
@@ -77,41 +163,17 @@ impl Histogram {
     
     /// Record a value in the histogram
     pub fn record(&self, value: f64) {
+        let shard = &self.shards[thread_shard()].0;
+        
         // Update basic statistics
         let value_as_usize = value as usize;
-        self.count.fetch_add(1, Ordering::Relaxed);
-        self.sum.fetch_add(value_as_usize, Ordering::Relaxed);
-        
-        // Update min/max values
-        let mut current_min = self.min.load(Ordering::Relaxed);
-        while value_as_usize < current_min {
-            match self.min.compare_exchange_weak(
-                current_min,
-                value_as_usize,
-                Ordering::Relaxed,
-                Ordering::Relaxed,
-            ) {
-                Ok(_) => break,
-                Err(new_min) => current_min = new_min,
-            }
-        }
-        
-        let mut current_max = self.max.load(Ordering::Relaxed);
-        while value_as_usize > current_max {
-            match self.max.compare_exchange_weak(
-                current_max,
-                value_as_usize,
-                Ordering::Relaxed,
-                Ordering::Relaxed,
-            ) {
-                Ok(_) => break,
-                Err(new_max) => current_max = new_max,
-            }
-        }
+        shard.count.fetch_add(1, Ordering::Relaxed);
+        shard.sum.fetch_add(value_as_usize, Ordering::Relaxed);
+        shard.min.fetch_min(value_as_usize, Ordering::Relaxed);
+        shard.max.fetch_max(value_as_usize, Ordering::Relaxed);
         
         // Update bucket counters
-        let buckets = self.buckets.read().unwrap();
-        for (boundary, counter) in buckets.iter() {
+        for (boundary, counter) in self.boundaries.iter().zip(shard.buckets.iter()) {
             if value <= *boundary {
                 counter.fetch_add(1, Ordering::Relaxed);
             }
@@ -120,22 +182,22 @@ impl Histogram {
     
     /// Get the count of values in the histogram
     pub fn count(&self) -> usize {
-        self.count.load(Ordering::Relaxed)
+        self.shards.iter().map(|shard| shard.0.count.load(Ordering::Relaxed)).sum()
     }
     
     /// Get the sum of values in the histogram
     pub fn sum(&self) -> usize {
-        self.sum.load(Ordering::Relaxed)
+        self.shards.iter().fold(0, |total, shard| total.wrapping_add(shard.0.sum.load(Ordering::Relaxed)))
     }
     
     /// Get the minimum value recorded
     pub fn min(&self) -> usize {
-        self.min.load(Ordering::Relaxed)
+        self.shards.iter().map(|shard| shard.0.min.load(Ordering::Relaxed)).min().unwrap_or(usize::MAX)
     }
     
     /// Get the maximum value recorded
     pub fn max(&self) -> usize {
-        self.max.load(Ordering::Relaxed)
+        self.shards.iter().map(|shard| shard.0.max.load(Ordering::Relaxed)).max().unwrap_or(0)
     }
     
     /// Get the mean value
@@ -150,14 +212,240 @@ impl Histogram {
     
     /// Get the bucket counts
     pub fn buckets(&self) -> Vec<(f64, usize)> {
-        let buckets = self.buckets.read().unwrap();
-        buckets
+        self.boundaries
             .iter()
-            .map(|(boundary, counter)| (*boundary, counter.load(Ordering::Relaxed)))
+            .enumerate()
+            .map(|(index, &boundary)| {
+                let count = self.shards.iter().map(|shard| shard.0.buckets[index].load(Ordering::Relaxed)).sum();
+                (boundary, count)
+            })
             .collect()
     }
 }
 
+/// Number of one-second slots kept by windowed metrics (15 minutes)
+const WINDOW_SLOTS: usize = 15 * 60;
+
+/// Bucket boundaries used by windowed histograms: 1us to ~16s, doubling
+const WINDOW_BUCKETS: usize = 25;
+
+/// A ring buffer of per-second slots, each stamped with the second it belongs to
+#[derive(Debug)]
+struct SlotRing<T> {
+    start: Instant,
+    slots: Vec<(u64, T)>,
+}
+
+impl<T: Default + Clone> SlotRing<T> {
+    fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            slots: vec![(u64::MAX, T::default()); WINDOW_SLOTS],
+        }
+    }
+    
+    fn second(&self, now: Instant) -> u64 {
+        now.saturating_duration_since(self.start).as_secs()
+    }
+    
+    /// Get the slot for `now`, clearing it if it still holds an older second
+    fn slot_mut(&mut self, now: Instant) -> &mut T {
+        let second = self.second(now);
+        let slot = &mut self.slots[(second % WINDOW_SLOTS as u64) as usize];
+        if slot.0 != second {
+            *slot = (second, T::default());
+        }
+        &mut slot.1
+    }
+    
+    /// Visit the slots within `window` of `now`
+    fn within(&self, window: Duration, now: Instant) -> impl Iterator<Item = &T> {
+        let second = self.second(now);
+        let seconds = window.as_secs().clamp(1, WINDOW_SLOTS as u64);
+        let oldest = (second + 1).saturating_sub(seconds);
+        self.slots
+            .iter()
+            .filter(move |(stamp, _)| *stamp != u64::MAX && *stamp >= oldest && *stamp <= second)
+            .map(|(_, value)| value)
+    }
+}
+
+/// A counter that can report how much it grew over the last 1/5/15 minutes
+#[derive(Debug)]
+pub struct WindowedCounter {
+    ring: Mutex<SlotRing<usize>>,
+}
+
+impl WindowedCounter {
+    /// Create a new windowed counter
+    pub fn new() -> Self {
+        Self {
+            ring: Mutex::new(SlotRing::new()),
+        }
+    }
+    
+    /// Increment the counter by a specific amount
+    pub fn increment(&self, amount: usize) {
+        self.increment_at(amount, Instant::now());
+    }
+    
+    /// Increment the counter as of a specific instant
+    pub fn increment_at(&self, amount: usize, now: Instant) {
+        *self.ring.lock().unwrap().slot_mut(now) += amount;
+    }
+    
+    /// Get the total added during the window ending now
+    pub fn total(&self, window: Duration) -> usize {
+        self.total_at(window, Instant::now())
+    }
+    
+    /// Get the total added during the window ending at `now`
+    pub fn total_at(&self, window: Duration, now: Instant) -> usize {
+        self.ring.lock().unwrap().within(window, now).sum()
+    }
+    
+    /// Get the average rate per second over the window ending now
+    pub fn rate(&self, window: Duration) -> f64 {
+        self.rate_at(window, Instant::now())
+    }
+    
+    /// Get the average rate per second over the window ending at `now`
+    pub fn rate_at(&self, window: Duration, now: Instant) -> f64 {
+        let seconds = window.as_secs().clamp(1, WINDOW_SLOTS as u64);
+        self.total_at(window, now) as f64 / seconds as f64
+    }
+    
+    /// Forget everything counted so far
+    pub fn reset(&self) {
+        *self.ring.lock().unwrap() = SlotRing::new();
+    }
+    
+    /// Get the 1, 5 and 15 minute rates per second
+    pub fn rates(&self) -> WindowedRates {
+        let now = Instant::now();
+        WindowedRates {
+            one_minute: self.rate_at(Duration::from_secs(60), now),
+            five_minutes: self.rate_at(Duration::from_secs(300), now),
+            fifteen_minutes: self.rate_at(Duration::from_secs(900), now),
+        }
+    }
+}
+
+impl Default for WindowedCounter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Average rates per second over the standard windows
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WindowedRates {
+    pub one_minute: f64,
+    pub five_minutes: f64,
+    pub fifteen_minutes: f64,
+}
+
+/// A histogram that only reflects values recorded within a recent window
+#[derive(Debug)]
+pub struct WindowedHistogram {
+    ring: Mutex<SlotRing<Vec<usize>>>,
+}
+
+impl WindowedHistogram {
+    /// Create a new windowed histogram
+    pub fn new() -> Self {
+        Self {
+            ring: Mutex::new(SlotRing::new()),
+        }
+    }
+    
+    /// Get the bucket a value falls into; bucket `i` holds values up to `2^i`
+    fn bucket(value: f64) -> usize {
+        let mut boundary = 1.0;
+        for index in 0..WINDOW_BUCKETS - 1 {
+            if value <= boundary {
+                return index;
+            }
+            boundary *= 2.0;
+        }
+        WINDOW_BUCKETS - 1
+    }
+    
+    /// Record a value
+    pub fn record(&self, value: f64) {
+        self.record_at(value, Instant::now());
+    }
+    
+    /// Record a value as of a specific instant
+    pub fn record_at(&self, value: f64, now: Instant) {
+        let mut ring = self.ring.lock().unwrap();
+        let slot = ring.slot_mut(now);
+        if slot.is_empty() {
+            slot.resize(WINDOW_BUCKETS, 0);
+        }
+        slot[Self::bucket(value)] += 1;
+    }
+    
+    /// Summarize the values recorded during the window ending now
+    pub fn snapshot(&self, window: Duration) -> PercentileSnapshot {
+        self.snapshot_at(window, Instant::now())
+    }
+    
+    /// Summarize the values recorded during the window ending at `now`
+    ///
+    /// Percentiles are estimated as the upper boundary of the bucket they
+    /// fall into.
+    pub fn snapshot_at(&self, window: Duration, now: Instant) -> PercentileSnapshot {
+        let mut merged = [0usize; WINDOW_BUCKETS];
+        {
+            let ring = self.ring.lock().unwrap();
+            for slot in ring.within(window, now) {
+                for (total, count) in merged.iter_mut().zip(slot) {
+                    *total += count;
+                }
+            }
+        }
+        
+        let count: usize = merged.iter().sum();
+        let percentile = |p: f64| -> f64 {
+            if count == 0 {
+                return 0.0;
+            }
+            let rank = ((p * count as f64).ceil() as usize).max(1);
+            let mut seen = 0;
+            for (index, bucket_count) in merged.iter().enumerate() {
+                seen += bucket_count;
+                if seen >= rank {
+                    return 2f64.powi(index as i32);
+                }
+            }
+            2f64.powi(WINDOW_BUCKETS as i32 - 1)
+        };
+        
+        PercentileSnapshot {
+            count,
+            p50: percentile(0.50),
+            p90: percentile(0.90),
+            p99: percentile(0.99),
+        }
+    }
+}
+
+impl Default for WindowedHistogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Count and estimated percentiles of a windowed histogram
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PercentileSnapshot {
+    pub count: usize,
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+}
+
 /// A timer for measuring durations
 #[derive(Debug)]
 pub struct Timer {
@@ -196,7 +484,10 @@ impl Drop for Timer {
 #[derive(Debug, Default)]
 pub struct MetricsRegistry {
     counters: RwLock<HashMap<String, Arc<Counter>>>,
+    gauges: RwLock<HashMap<String, Arc<Gauge>>>,
     histograms: RwLock<HashMap<String, Arc<Histogram>>>,
+    windowed_counters: RwLock<HashMap<String, Arc<WindowedCounter>>>,
+    windowed_histograms: RwLock<HashMap<String, Arc<WindowedHistogram>>>,
 }
 
 impl MetricsRegistry {
@@ -204,11 +495,17 @@ impl MetricsRegistry {
     pub fn new() -> Self {
         Self {
             counters: RwLock::new(HashMap::new()),
+            gauges: RwLock::new(HashMap::new()),
             histograms: RwLock::new(HashMap::new()),
+            windowed_counters: RwLock::new(HashMap::new()),
+            windowed_histograms: RwLock::new(HashMap::new()),
         }
     }
     
     /// Get or create a counter
+    ///
+    /// Looking it up takes a lock, so keep the handle where a counter is
+    /// incremented often rather than looking it up each time.
     pub fn counter(&self, name: &str) -> Arc<Counter> {
         {
             let counters = self.counters.read().unwrap();
@@ -223,6 +520,19 @@ impl MetricsRegistry {
         counter
     }
     
+    /// Get or create a gauge
+    pub fn gauge(&self, name: &str) -> Arc<Gauge> {
+        {
+            let gauges = self.gauges.read().unwrap();
+            if let Some(gauge) = gauges.get(name) {
+                return gauge.clone();
+            }
+        }
+        
+        let mut gauges = self.gauges.write().unwrap();
+        gauges.entry(name.to_string()).or_default().clone()
+    }
+    
     /// Get or create a histogram
     pub fn histogram(&self, name: &str, bucket_boundaries: &[f64]) -> Arc<Histogram> {
         {
@@ -259,6 +569,32 @@ impl MetricsRegistry {
         histogram
     }
     
+    /// Get or create a counter that tracks recent rates
+    pub fn windowed_counter(&self, name: &str) -> Arc<WindowedCounter> {
+        {
+            let counters = self.windowed_counters.read().unwrap();
+            if let Some(counter) = counters.get(name) {
+                return counter.clone();
+            }
+        }
+        
+        let mut counters = self.windowed_counters.write().unwrap();
+        counters.entry(name.to_string()).or_default().clone()
+    }
+    
+    /// Get or create a histogram that tracks recent percentiles
+    pub fn windowed_histogram(&self, name: &str) -> Arc<WindowedHistogram> {
+        {
+            let histograms = self.windowed_histograms.read().unwrap();
+            if let Some(histogram) = histograms.get(name) {
+                return histogram.clone();
+            }
+        }
+        
+        let mut histograms = self.windowed_histograms.write().unwrap();
+        histograms.entry(name.to_string()).or_default().clone()
+    }
+    
     /// Create a timer for measuring operation duration
     pub fn timer(&self, name: &str) -> Timer {
         // Default histogram for timing operations (in microseconds)
@@ -267,6 +603,24 @@ impl MetricsRegistry {
         Timer::new(histogram)
     }
     
+    /// Get the current value of every counter
+    pub fn counters(&self) -> Vec<(String, usize)> {
+        let counters = self.counters.read().unwrap();
+        counters.iter().map(|(name, counter)| (name.clone(), counter.value())).collect()
+    }
+    
+    /// Get the current value of every gauge
+    pub fn gauges(&self) -> Vec<(String, usize)> {
+        let gauges = self.gauges.read().unwrap();
+        gauges.iter().map(|(name, gauge)| (name.clone(), gauge.value())).collect()
+    }
+    
+    /// Get every registered histogram
+    pub fn histograms(&self) -> Vec<(String, Arc<Histogram>)> {
+        let histograms = self.histograms.read().unwrap();
+        histograms.iter().map(|(name, histogram)| (name.clone(), histogram.clone())).collect()
+    }
+    
     /// Get metrics as a formatted string
     pub fn format(&self) -> String {
         let mut result = String::new();
@@ -279,6 +633,38 @@ impl MetricsRegistry {
             }
         }
         
+        // Format gauges
+        {
+            let gauges = self.gauges.read().unwrap();
+            for (name, gauge) in gauges.iter() {
+                result.push_str(&format!("{}: {}\n", name, gauge.value()));
+            }
+        }
+        
+        // Format windowed counters as recent rates
+        {
+            let counters = self.windowed_counters.read().unwrap();
+            for (name, counter) in counters.iter() {
+                let rates = counter.rates();
+                result.push_str(&format!(
+                    "{}: 1m={:.2}/s, 5m={:.2}/s, 15m={:.2}/s\n",
+                    name, rates.one_minute, rates.five_minutes, rates.fifteen_minutes
+                ));
+            }
+        }
+        
+        // Format windowed histograms as last-minute percentiles
+        {
+            let histograms = self.windowed_histograms.read().unwrap();
+            for (name, histogram) in histograms.iter() {
+                let snapshot = histogram.snapshot(Duration::from_secs(60));
+                result.push_str(&format!(
+                    "{} (1m): count={}, p50<={}, p90<={}, p99<={}\n",
+                    name, snapshot.count, snapshot.p50, snapshot.p90, snapshot.p99
+                ));
+            }
+        }
+        
         // Format histograms
         {
             let histograms = self.histograms.read().unwrap();
@@ -304,16 +690,160 @@ impl MetricsRegistry {
     }
 }
 
+/// Health metrics for one event loop, exported under `workers.{id}.`
+///
+/// Handles are looked up once so recording on every iteration stays cheap.
+#[derive(Debug)]
+pub struct EventLoopMetrics {
+    iteration_time: Arc<Histogram>,
+    poll_wait: Arc<Histogram>,
+    events_per_wake: Arc<Histogram>,
+    pending_write_connections: Arc<Gauge>,
+    pending_write_bytes: Arc<Gauge>,
+    open_connections: Arc<Gauge>,
+}
+
+impl EventLoopMetrics {
+    /// Register the health metrics of worker `worker_id`
+    pub fn new(registry: &MetricsRegistry, worker_id: usize) -> Self {
+        let prefix = format!("workers.{}", worker_id);
+        
+        Self {
+            iteration_time: registry.exponential_histogram(&format!("{}.loop_iteration_us", prefix), 1.0, 2.0, 24),
+            poll_wait: registry.exponential_histogram(&format!("{}.poll_wait_us", prefix), 1.0, 2.0, 24),
+            events_per_wake: registry.exponential_histogram(&format!("{}.events_per_wake", prefix), 1.0, 2.0, 11),
+            pending_write_connections: registry.gauge(&format!("{}.pending_write_connections", prefix)),
+            pending_write_bytes: registry.gauge(&format!("{}.pending_write_bytes", prefix)),
+            open_connections: registry.gauge(&format!("{}.open_connections", prefix)),
+        }
+    }
+    
+    /// Record one loop iteration
+    ///
+    /// `iteration` is the whole iteration including `poll_wait`, so time
+    /// spent doing work is the difference between the two.
+    pub fn record_iteration(&self, iteration: Duration, poll_wait: Duration, events: usize) {
+        self.iteration_time.record(iteration.as_micros() as f64);
+        self.poll_wait.record(poll_wait.as_micros() as f64);
+        self.events_per_wake.record(events as f64);
+    }
+    
+    /// Update the outbound queue depth and connection count
+    pub fn set_queue_depth(&self, open_connections: usize, pending_connections: usize, pending_bytes: usize) {
+        self.open_connections.set(open_connections);
+        self.pending_write_connections.set(pending_connections);
+        self.pending_write_bytes.set(pending_bytes);
+    }
+}
+
+/// Lowest status code counted through a pre-registered handle
+const FIRST_STATUS: u16 = 100;
+
+/// Status codes per method counted through pre-registered handles (100-599)
+const STATUSES: usize = 500;
+
+/// Handles for the metrics recorded on every request
+///
+/// They are looked up once, when the collector is created, so recording
+/// is an atomic add on the caller's shard: no lock, no formatted name and
+/// no map lookup. Counters for a method and status are registered the
+/// first time that pair is seen.
+#[derive(Debug)]
+pub struct RequestMetrics {
+    registry: Arc<MetricsRegistry>,
+    requests: Arc<WindowedCounter>,
+    latency: Arc<WindowedHistogram>,
+    phases: [Arc<Histogram>; 6],
+    by_status: Box<[OnceLock<Arc<Counter>>]>,
+    bytes_received: Arc<Counter>,
+    bytes_sent: Arc<Counter>,
+    batched: Arc<Counter>,
+    #[cfg(feature = "alloc-audit")]
+    allocations: Arc<WindowedHistogram>,
+}
+
+impl RequestMetrics {
+    /// Register the request metrics in `registry`
+    pub fn new(registry: Arc<MetricsRegistry>) -> Self {
+        Self {
+            requests: registry.windowed_counter("requests"),
+            latency: registry.windowed_histogram("request_latency"),
+            phases: RequestTimeline::SPAN_NAMES
+                .map(|name| registry.exponential_histogram(&format!("request_phase.{}", name), 1.0, 2.0, 24)),
+            by_status: (0..Method::ALL.len() * STATUSES).map(|_| OnceLock::new()).collect(),
+            bytes_received: registry.counter("bytes_received"),
+            bytes_sent: registry.counter("bytes_sent"),
+            batched: registry.counter("responses.batched"),
+            #[cfg(feature = "alloc-audit")]
+            allocations: registry.windowed_histogram("request_allocations"),
+            registry,
+        }
+    }
+    
+    /// Count a request answered with `status`
+    pub fn record(&self, method: Method, status: u16) {
+        self.status_counter(method, status).increment(1);
+        self.requests.increment(1);
+    }
+    
+    /// Get the counter of requests with `method` answered with `status`
+    fn status_counter(&self, method: Method, status: u16) -> Arc<Counter> {
+        let name = || format!("requests.{}.{}", method.as_str(), status);
+        if !(FIRST_STATUS..FIRST_STATUS + STATUSES as u16).contains(&status) {
+            return self.registry.counter(&name());
+        }
+        
+        let slot = &self.by_status[method as usize * STATUSES + usize::from(status - FIRST_STATUS)];
+        slot.get_or_init(|| self.registry.counter(&name())).clone()
+    }
+    
+    /// Record how long each phase of a completed request took
+    pub fn record_timeline(&self, timeline: &RequestTimeline) {
+        for (histogram, duration) in self.phases.iter().zip(timeline.span_durations()) {
+            if let Some(duration) = duration {
+                histogram.record(duration.as_micros() as f64);
+            }
+        }
+        if let Some(total) = timeline.between(Phase::Accepted, Phase::Completed) {
+            self.latency.record(total.as_micros() as f64);
+        }
+    }
+    
+    /// Count bytes read from clients
+    pub fn record_bytes_received(&self, bytes: usize) {
+        self.bytes_received.increment(bytes);
+    }
+    
+    /// Count bytes written to clients
+    pub fn record_bytes_sent(&self, bytes: usize) {
+        self.bytes_sent.increment(bytes);
+    }
+    
+    /// Count a response queued to share a write with others
+    pub fn record_batched(&self) {
+        self.batched.increment(1);
+    }
+    
+    /// Record the heap allocations made while handling a request
+    #[cfg(feature = "alloc-audit")]
+    pub fn record_allocations(&self, allocations: u64) {
+        self.allocations.record(allocations as f64);
+    }
+}
+
 /// The metrics collector for the server
 pub struct MetricsCollector {
     registry: Arc<MetricsRegistry>,
+    requests: RequestMetrics,
 }
 
 impl MetricsCollector {
     /// Create a new metrics collector
     pub fn new() -> Self {
+        let registry = Arc::new(MetricsRegistry::new());
         Self {
-            registry: Arc::new(MetricsRegistry::new()),
+            requests: RequestMetrics::new(registry.clone()),
+            registry,
         }
     }
     
@@ -322,6 +852,11 @@ impl MetricsCollector {
         self.registry.clone()
     }
     
+    /// Get the pre-registered handles for per-request metrics
+    pub fn requests(&self) -> &RequestMetrics {
+        &self.requests
+    }
+    
     /// Record a connection event
     pub fn record_connection(&self, event_type: &str) {
         let counter = self.registry.counter(&format!("connections.{}", event_type));
@@ -330,8 +865,13 @@ impl MetricsCollector {
     
     /// Record a request event
     pub fn record_request(&self, method: &str, status: u16) {
-        let counter = self.registry.counter(&format!("requests.{}.{}", method, status));
-        counter.increment(1);
+        match Method::from_str(method) {
+            Ok(method) => self.requests.record(method, status),
+            Err(_) => {
+                self.registry.counter(&format!("requests.{}.{}", method, status)).increment(1);
+                self.requests.requests.increment(1);
+            }
+        }
     }
     
     /// Time a request
@@ -339,16 +879,19 @@ impl MetricsCollector {
         self.registry.timer(&format!("request_time.{}", method))
     }
     
+    /// Record how long each phase of a completed request took
+    pub fn record_request_timeline(&self, timeline: &RequestTimeline) {
+        self.requests.record_timeline(timeline);
+    }
+    
     /// Record bytes received
     pub fn record_bytes_received(&self, bytes: usize) {
-        let counter = self.registry.counter("bytes_received");
-        counter.increment(bytes);
+        self.requests.record_bytes_received(bytes);
     }
     
     /// Record bytes sent
     pub fn record_bytes_sent(&self, bytes: usize) {
-        let counter = self.registry.counter("bytes_sent");
-        counter.increment(bytes);
+        self.requests.record_bytes_sent(bytes);
     }
     
     /// Get a formatted string of all metrics
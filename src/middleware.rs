@@ -1,3 +1,5 @@
+use crate::config::RouteSettings;
+use crate::credentials::{CredentialStore, StaticCredentials, authenticated, parse_basic_auth};
 use crate::error::ServerResult;
 use crate::http::{Request, Response};
 use std::sync::Arc;
@@ -47,6 +49,16 @@ impl MiddlewareChain {
     
     /// Process a request through the middleware chain
     pub fn handle(&self, request: &Request) -> ServerResult<Response> {
+        self.run(request, None)
+    }
+    
+    /// Process a request through the middleware chain, running `last` after
+    /// every other middleware, just before the handler
+    pub fn handle_with(&self, request: &Request, last: MiddlewareFn) -> ServerResult<Response> {
+        self.run(request, Some(last))
+    }
+    
+    fn run(&self, request: &Request, last: Option<MiddlewareFn>) -> ServerResult<Response> {
         if let Some(handler) = &self.handler {
             // Add explicit type annotation
             let chain: Vec<MiddlewareNext> = Vec::with_capacity(self.middleware.len());
@@ -54,7 +66,7 @@ impl MiddlewareChain {
             // Build the middleware chain in reverse order
             let mut next: MiddlewareNext = handler.clone();
             
-            for middleware in self.middleware.iter().rev() {
+            for middleware in last.iter().chain(self.middleware.iter().rev()) {
                 let current = middleware.clone();
                 let prev_next = next.clone();
                 
@@ -113,6 +125,13 @@ pub fn logging_middleware(request: &Request, next: MiddlewareNext) -> ServerResu
     response
 }
 
+/// Error middleware - turns errors from later middleware and handlers into JSON error responses
+///
+/// Added first, it wraps the whole chain and catches every error in it.
+pub fn error_middleware(request: &Request, next: MiddlewareNext) -> ServerResult<Response> {
+    Ok(next(request).unwrap_or_else(|e| e.to_response(request)))
+}
+
 /// CORS middleware - adds CORS headers to responses
 pub fn cors_middleware(allowed_origins: Vec<String>) -> impl Fn(&Request, MiddlewareNext) -> ServerResult<Response> + Send + Sync {
     move |request, next| {
@@ -132,27 +151,24 @@ pub fn cors_middleware(allowed_origins: Vec<String>) -> impl Fn(&Request, Middle
 }
 
 /// Basic auth middleware - requires basic authentication for requests
+///
+/// Checks a single username and password in constant time. Use
+/// `basic_auth_store_middleware` for htpasswd or LDAP backends and lockout.
+/// Routes configured with `require_auth: false` pass through unchecked.
 pub fn basic_auth_middleware(
     username: String,
     password: String,
 ) -> impl Fn(&Request, MiddlewareNext) -> ServerResult<Response> + Send + Sync {
+    let store = StaticCredentials::new(&username, &password);
+    
     move |request, next| {
-        // Check for the Authorization header
-        if let Some(auth) = request.get_header("authorization") {
-            if auth.starts_with("Basic ") {
-                // Extract the credentials
-                let base64_credentials = &auth[6..];
-                let decoded = base64::decode(base64_credentials);
-                
-                if let Ok(bytes) = decoded {
-                    if let Ok(credentials) = String::from_utf8(bytes) {
-                        // Check if the credentials match
-                        let parts: Vec<&str> = credentials.split(':').collect();
-                        if parts.len() == 2 && parts[0] == username && parts[1] == password {
-                            return next(request);
-                        }
-                    }
-                }
+        if RouteSettings::of(request).is_some_and(|settings| !settings.require_auth) {
+            return next(request);
+        }
+        
+        if let Some((user, pass)) = parse_basic_auth(request) {
+            if store.verify(&user, &pass)? {
+                return next(&authenticated(request, &user));
             }
         }
         
@@ -181,8 +197,13 @@ pub fn content_type_middleware(
 }
 
 /// Compression middleware - compresses response bodies
+///
+/// Routes configured with `compression: false` are left uncompressed.
 pub fn compression_middleware(request: &Request, next: MiddlewareNext) -> ServerResult<Response> {
     let mut response = next(request)?;
+    if RouteSettings::of(request).is_some_and(|settings| !settings.compression) {
+        return Ok(response);
+    }
     
     // Check if the client supports compression
     if let Some(accept_encoding) = request.get_header("accept-encoding") {
@@ -209,6 +230,39 @@ pub fn compression_middleware(request: &Request, next: MiddlewareNext) -> Server
     Ok(response)
 }
 
+/// Bandwidth limit middleware - throttles responses for paths under a prefix
+pub fn bandwidth_limit_middleware(
+    path_prefix: String,
+    bytes_per_second: u64,
+) -> impl Fn(&Request, MiddlewareNext) -> ServerResult<Response> + Send + Sync {
+    move |request, next| {
+        let mut response = next(request)?;
+        
+        if request.path().starts_with(&path_prefix) {
+            // Keep a stricter limit if the handler already set one
+            let limit = response.bandwidth_limit.map_or(bytes_per_second, |l| l.min(bytes_per_second));
+            response.set_bandwidth_limit(limit);
+        }
+        
+        Ok(response)
+    }
+}
+
+/// Server-Timing middleware - sends the timings handlers recorded on the request
+///
+/// Handlers add entries through `request.timings` (e.g. `db` or `render`);
+/// the middleware appends the total handler duration and writes them all to
+/// the `Server-Timing` response header.
+pub fn server_timing_middleware(request: &Request, next: MiddlewareNext) -> ServerResult<Response> {
+    let start_time = Instant::now();
+    let mut response = next(request)?;
+    
+    request.timings.record("total", start_time.elapsed());
+    response.append_header("Server-Timing", &request.timings.header_value());
+    
+    Ok(response)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -343,4 +397,45 @@ mod tests {
         
         assert_eq!(response.status, Status::Unauthorized);
     }
+    
+    #[test]
+    fn test_bandwidth_limit_middleware() {
+        let mut chain = MiddlewareChain::new();
+        
+        chain.add(bandwidth_limit_middleware("/downloads".to_string(), 64 * 1024));
+        
+        chain.set_handler(|_| {
+            let mut response = Response::new(Status::Ok);
+            response.set_body(b"payload");
+            Ok(response)
+        });
+        
+        let request = Request::new(Method::Get, "/downloads/big.iso");
+        let response = chain.handle(&request).unwrap();
+        assert_eq!(response.bandwidth_limit, Some(64 * 1024));
+        
+        let request = Request::new(Method::Get, "/api/status");
+        let response = chain.handle(&request).unwrap();
+        assert_eq!(response.bandwidth_limit, None);
+    }
+    
+    #[test]
+    fn test_server_timing_middleware() {
+        let mut chain = MiddlewareChain::new();
+        
+        chain.add(server_timing_middleware);
+        
+        chain.set_handler(|request| {
+            request.timings.record_ms("db", 12.3);
+            request.timings.measure("render", || ());
+            Ok(Response::new(Status::Ok))
+        });
+        
+        let request = Request::new(Method::Get, "/");
+        let response = chain.handle(&request).unwrap();
+        
+        let header = response.headers.get("Server-Timing").unwrap();
+        assert!(header.starts_with("db;dur=12.300, render;dur="));
+        assert!(header.contains(", total;dur="));
+    }
 }
\ No newline at end of file
@@ -0,0 +1,185 @@
+//! Shadow traffic for trying out a new backend
+//!
+//! A share of requests is copied to a shadow upstream in the background and
+//! its answers thrown away, so the client never waits on it or sees it. The
+//! copies queue for a small pool of sender threads; when the queue is full,
+//! copies are dropped rather than slowing the real traffic down.
+
+use crate::error::{ServerError, ServerResult};
+use crate::http::Request;
+use crate::http_client::{self, HttpUrl};
+use crate::metrics::MetricsRegistry;
+use crate::protocol_upgrade::StreamedBody;
+use crate::proxy::{self, HeaderRules};
+use serde::{Deserialize, Serialize};
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Settings for mirroring requests to a shadow upstream
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MirrorConfig {
+    /// URL of the shadow upstream, whose path is prefixed to request paths
+    pub upstream: String,
+    
+    /// Share of requests copied, from 0 to 100
+    pub percent: f64,
+    
+    /// Copies waiting to be sent before more are dropped
+    pub queue_size: usize,
+    
+    /// Threads sending copies
+    pub threads: usize,
+    
+    /// Longest time spent on one copy, connecting included
+    pub timeout: Duration,
+}
+
+impl Default for MirrorConfig {
+    fn default() -> Self {
+        Self {
+            upstream: String::new(),
+            percent: 100.0,
+            queue_size: 256,
+            threads: 2,
+            timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+/// A request copy waiting to be sent
+struct Copy {
+    head: String,
+    body: Vec<u8>,
+}
+
+/// Copies a share of requests to a shadow upstream, discarding its responses
+///
+/// Requests are picked evenly rather than at random: at 10 percent, every
+/// tenth request is copied. Upgrade requests are never copied, since the
+/// shadow can't take over the client's connection. With metrics, copies
+/// count `mirror.sent`, `mirror.dropped` (queue full) and `mirror.failures`,
+/// and record `mirror.latency_us`.
+#[derive(Debug)]
+pub struct Mirror {
+    upstream: HttpUrl,
+    percent: f64,
+    seen: AtomicU64,
+    copies: SyncSender<Copy>,
+    metrics: Option<Arc<MetricsRegistry>>,
+}
+
+impl Mirror {
+    /// Start mirroring to the upstream in `config`
+    pub fn new(config: MirrorConfig) -> ServerResult<Self> {
+        Self::build(config, None)
+    }
+    
+    /// Start mirroring, recording metrics in `registry`
+    pub fn with_metrics(config: MirrorConfig, registry: Arc<MetricsRegistry>) -> ServerResult<Self> {
+        Self::build(config, Some(registry))
+    }
+    
+    fn build(config: MirrorConfig, metrics: Option<Arc<MetricsRegistry>>) -> ServerResult<Self> {
+        if !(0.0..=100.0).contains(&config.percent) {
+            return Err(ServerError::Config(format!(
+                "Mirror percent must be between 0 and 100, not {}",
+                config.percent
+            )));
+        }
+        let upstream = HttpUrl::parse(&config.upstream)?;
+        
+        let (copies, queue) = mpsc::sync_channel::<Copy>(config.queue_size.max(1));
+        let queue = Arc::new(Mutex::new(queue));
+        for i in 0..config.threads.max(1) {
+            let queue = queue.clone();
+            let upstream = upstream.clone();
+            let metrics = metrics.clone();
+            let timeout = config.timeout;
+            let _ = thread::Builder::new().name(format!("mirror-{}", i)).spawn(move || loop {
+                // The lock is released before the copy is sent
+                let copy = queue.lock().unwrap().recv();
+                match copy {
+                    Ok(copy) => send_copy(&upstream, copy, timeout, metrics.as_deref()),
+                    Err(_) => break,
+                }
+            });
+        }
+        
+        Ok(Self {
+            upstream,
+            percent: config.percent,
+            seen: AtomicU64::new(0),
+            copies,
+            metrics,
+        })
+    }
+    
+    /// Get the shadow upstream
+    pub fn upstream(&self) -> &HttpUrl {
+        &self.upstream
+    }
+    
+    /// Queue a copy of `request` for the shadow if it falls in the mirrored share
+    ///
+    /// Returns whether a copy was queued; never blocks. Upgrades and
+    /// requests whose body is still being streamed aren't copied.
+    pub fn mirror(&self, request: &Request) -> bool {
+        let streamed = StreamedBody::of(request).is_some();
+        if proxy::requested_upgrade(request).is_some() || streamed || !self.sampled() {
+            return false;
+        }
+        let copy = Copy {
+            head: proxy::request_head(&self.upstream, request, None, &HeaderRules::default()),
+            body: request.body.clone(),
+        };
+        match self.copies.try_send(copy) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) | Err(TrySendError::Disconnected(_)) => {
+                if let Some(metrics) = &self.metrics {
+                    metrics.counter("mirror.dropped").increment(1);
+                }
+                false
+            }
+        }
+    }
+    
+    /// Check whether the next request is one of the share copied
+    fn sampled(&self) -> bool {
+        let n = self.seen.fetch_add(1, Ordering::Relaxed) as f64;
+        // Copy whenever the running total of the share crosses a whole request
+        ((n + 1.0) * self.percent / 100.0).floor() > (n * self.percent / 100.0).floor()
+    }
+}
+
+/// Send one copy and read the answer to the end, throwing it away
+fn send_copy(upstream: &HttpUrl, copy: Copy, timeout: Duration, metrics: Option<&MetricsRegistry>) {
+    let start = Instant::now();
+    let result = (|| -> ServerResult<()> {
+        let mut stream = http_client::connect(upstream, start + timeout)?;
+        stream.set_write_timeout(Some(timeout))?;
+        stream.set_read_timeout(Some(timeout))?;
+        stream.write_all(copy.head.as_bytes())?;
+        stream.write_all(&copy.body)?;
+        // The request asks the shadow to close when done
+        io::copy(&mut stream, &mut io::sink())?;
+        Ok(())
+    })();
+    
+    if let Some(metrics) = metrics {
+        match &result {
+            Ok(()) => metrics.counter("mirror.sent").increment(1),
+            Err(_) => metrics.counter("mirror.failures").increment(1),
+        }
+        metrics
+            .exponential_histogram("mirror.latency_us", 1.0, 2.0, 24)
+            .record(start.elapsed().as_micros() as f64);
+    }
+    if let Err(e) = result {
+        log::debug!("Mirrored request to {} failed: {}", upstream.address, e);
+    }
+}
\ No newline at end of file
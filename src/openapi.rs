@@ -0,0 +1,378 @@
+use crate::http::{Method, Response, Status};
+use crate::router::Router;
+use crate::validation::{self, ParamRule};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+
+/// Where a documented parameter is read from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParamLocation {
+    Path,
+    Query,
+    Header,
+}
+
+impl ParamLocation {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match *self {
+            ParamLocation::Path => "path",
+            ParamLocation::Query => "query",
+            ParamLocation::Header => "header",
+        }
+    }
+}
+
+/// Documentation for a single route parameter
+#[derive(Debug, Clone)]
+pub struct ParamDoc {
+    pub name: String,
+    pub location: ParamLocation,
+    pub description: Option<String>,
+    pub required: bool,
+    /// JSON schema of the value, e.g. `{"type": "integer"}`
+    pub schema: Value,
+}
+
+/// Documentation for one possible response of a route
+#[derive(Debug, Clone)]
+pub struct ResponseDoc {
+    pub status: u16,
+    pub description: String,
+    pub schema: Option<Value>,
+}
+
+/// Optional documentation attached to a route, used to generate OpenAPI documents
+#[derive(Debug, Clone, Default)]
+pub struct RouteDoc {
+    pub summary: Option<String>,
+    pub description: Option<String>,
+    pub tags: Vec<String>,
+    pub params: Vec<ParamDoc>,
+    pub request_schema: Option<Value>,
+    pub responses: Vec<ResponseDoc>,
+    /// Leave the route out of generated documents
+    pub hidden: bool,
+}
+
+impl RouteDoc {
+    /// Create documentation with a one-line summary
+    pub fn new(summary: &str) -> Self {
+        Self {
+            summary: Some(summary.to_string()),
+            ..Self::default()
+        }
+    }
+    
+    /// Create documentation that hides the route from generated documents
+    pub fn hidden() -> Self {
+        Self {
+            hidden: true,
+            ..Self::default()
+        }
+    }
+    
+    /// Set a longer description
+    pub fn with_description(mut self, description: &str) -> Self {
+        self.description = Some(description.to_string());
+        self
+    }
+    
+    /// Group the route under a tag
+    pub fn with_tag(mut self, tag: &str) -> Self {
+        self.tags.push(tag.to_string());
+        self
+    }
+    
+    /// Document a parameter
+    pub fn with_param(mut self, name: &str, location: ParamLocation, schema: Value, description: &str) -> Self {
+        self.params.push(ParamDoc {
+            name: name.to_string(),
+            location,
+            description: Some(description.to_string()),
+            required: location == ParamLocation::Path,
+            schema,
+        });
+        self
+    }
+    
+    /// Document the JSON request body with a schema
+    pub fn with_request_schema(mut self, schema: Value) -> Self {
+        self.request_schema = Some(schema);
+        self
+    }
+    
+    /// Document the JSON request body by example
+    pub fn with_request_example<T: Serialize>(self, example: &T) -> Self {
+        self.with_request_schema(schema_of(example))
+    }
+    
+    /// Document a response without a body schema
+    pub fn with_response(mut self, status: u16, description: &str) -> Self {
+        self.responses.push(ResponseDoc {
+            status,
+            description: description.to_string(),
+            schema: None,
+        });
+        self
+    }
+    
+    /// Document a JSON response by example
+    pub fn with_response_example<T: Serialize>(mut self, status: u16, description: &str, example: &T) -> Self {
+        self.responses.push(ResponseDoc {
+            status,
+            description: description.to_string(),
+            schema: Some(schema_of(example)),
+        });
+        self
+    }
+}
+
+/// Derive a JSON schema from the serialized shape of an example value
+///
+/// Serde doesn't describe types on its own, so the schema is inferred from
+/// what `example` serializes to: objects list their fields as required
+/// properties and arrays take the schema of their first element.
+pub fn schema_of<T: Serialize>(example: &T) -> Value {
+    match serde_json::to_value(example) {
+        Ok(value) => schema_of_value(&value),
+        Err(_) => json!({}),
+    }
+}
+
+fn schema_of_value(value: &Value) -> Value {
+    match value {
+        Value::Null => json!({ "nullable": true }),
+        Value::Bool(_) => json!({ "type": "boolean" }),
+        Value::Number(n) if n.is_f64() => json!({ "type": "number" }),
+        Value::Number(_) => json!({ "type": "integer" }),
+        Value::String(_) => json!({ "type": "string" }),
+        Value::Array(items) => {
+            let items = items.first().map(schema_of_value).unwrap_or_else(|| json!({}));
+            json!({ "type": "array", "items": items })
+        }
+        Value::Object(fields) => {
+            let properties: Map<String, Value> = fields
+                .iter()
+                .map(|(name, value)| (name.clone(), schema_of_value(value)))
+                .collect();
+            let required: Vec<&String> = fields.keys().collect();
+            json!({ "type": "object", "properties": properties, "required": required })
+        }
+    }
+}
+
+/// Top-level information for a generated document
+#[derive(Debug, Clone)]
+pub struct OpenApiInfo {
+    pub title: String,
+    pub version: String,
+    pub description: Option<String>,
+}
+
+impl OpenApiInfo {
+    /// Create document information
+    pub fn new(title: &str, version: &str) -> Self {
+        Self {
+            title: title.to_string(),
+            version: version.to_string(),
+            description: None,
+        }
+    }
+    
+    /// Set a description for the API
+    pub fn with_description(mut self, description: &str) -> Self {
+        self.description = Some(description.to_string());
+        self
+    }
+}
+
+/// Convert a router pattern (`/users/:id`, `/files/*`) to an OpenAPI path (`/users/{id}`)
+fn openapi_path(pattern: &str) -> (String, Vec<String>) {
+    let mut params = Vec::new();
+    let segments: Vec<String> = pattern
+        .split('/')
+        .map(|segment| {
+            if let Some(name) = segment.strip_prefix(':') {
+                let (name, _) = validation::split_typed_param(name);
+                params.push(name.to_string());
+                format!("{{{}}}", name)
+            } else if let Some(name) = segment.strip_prefix('*').filter(|name| !name.is_empty()) {
+                params.push(name.to_string());
+                format!("{{{}}}", name)
+            } else if segment == "*" {
+                params.push("wildcard".to_string());
+                "{wildcard}".to_string()
+            } else {
+                segment.to_string()
+            }
+        })
+        .collect();
+    
+    (segments.join("/"), params)
+}
+
+fn operation(method: Method, pattern: &str, doc: Option<&RouteDoc>, rules: &[ParamRule]) -> Value {
+    let (_, path_params) = openapi_path(pattern);
+    let mut operation = Map::new();
+    operation.insert(
+        "operationId".to_string(),
+        json!(format!("{} {}", method.as_str(), pattern)),
+    );
+    
+    let mut params: Vec<Value> = Vec::new();
+    let mut documented: Vec<&str> = Vec::new();
+    if let Some(doc) = doc {
+        if let Some(summary) = &doc.summary {
+            operation.insert("summary".to_string(), json!(summary));
+        }
+        if let Some(description) = &doc.description {
+            operation.insert("description".to_string(), json!(description));
+        }
+        if !doc.tags.is_empty() {
+            operation.insert("tags".to_string(), json!(doc.tags));
+        }
+        
+        for param in &doc.params {
+            let mut value = json!({
+                "name": param.name,
+                "in": param.location.as_str(),
+                "required": param.required,
+                "schema": param.schema,
+            });
+            if let Some(description) = &param.description {
+                value["description"] = json!(description);
+            }
+            params.push(value);
+            documented.push(&param.name);
+        }
+        
+        if let Some(schema) = &doc.request_schema {
+            operation.insert(
+                "requestBody".to_string(),
+                json!({ "content": { "application/json": { "schema": schema } } }),
+            );
+        }
+    }
+    
+    // Validation rules describe parameters the docs may have left out
+    for rule in rules {
+        if !documented.contains(&rule.name.as_str()) {
+            params.push(json!({
+                "name": rule.name,
+                "in": rule.location.as_str(),
+                "required": rule.required,
+                "schema": rule.schema(),
+            }));
+            documented.push(&rule.name);
+        }
+    }
+    
+    // Path parameters are always required, so list any still missing
+    for name in &path_params {
+        if !documented.contains(&name.as_str()) {
+            params.push(json!({
+                "name": name,
+                "in": "path",
+                "required": true,
+                "schema": { "type": "string" },
+            }));
+        }
+    }
+    if !params.is_empty() {
+        operation.insert("parameters".to_string(), Value::Array(params));
+    }
+    
+    let mut responses = Map::new();
+    for response in doc.map(|doc| doc.responses.as_slice()).unwrap_or(&[]) {
+        let mut value = json!({ "description": response.description });
+        if let Some(schema) = &response.schema {
+            value["content"] = json!({ "application/json": { "schema": schema } });
+        }
+        responses.insert(response.status.to_string(), value);
+    }
+    if responses.is_empty() {
+        responses.insert("default".to_string(), json!({ "description": "Response" }));
+    }
+    operation.insert("responses".to_string(), Value::Object(responses));
+    
+    Value::Object(operation)
+}
+
+/// Generate an OpenAPI 3 document describing every visible route of a router
+pub fn generate(router: &Router, info: &OpenApiInfo) -> Value {
+    let mut paths = Map::new();
+    for (method, pattern, doc, rules) in router.documented_routes() {
+        if doc.is_some_and(|doc| doc.hidden) {
+            continue;
+        }
+        
+        let (path, _) = openapi_path(pattern);
+        let item = paths.entry(path).or_insert_with(|| json!({}));
+        let key = method.as_str().to_ascii_lowercase();
+        // The first registration wins, matching how requests are routed
+        if item.get(&key).is_none() {
+            item[key] = operation(method, pattern, doc, rules);
+        }
+    }
+    
+    let mut info_value = json!({ "title": info.title, "version": info.version });
+    if let Some(description) = &info.description {
+        info_value["description"] = json!(description);
+    }
+    
+    json!({
+        "openapi": "3.0.3",
+        "info": info_value,
+        "paths": paths,
+    })
+}
+
+/// Build a Swagger UI page that loads the document at `spec_url`
+///
+/// The page itself is bundled; the Swagger UI scripts and styles are loaded
+/// from the unpkg CDN by the browser.
+pub fn swagger_ui_html(title: &str, spec_url: &str) -> String {
+    SWAGGER_UI_TEMPLATE
+        .replace("{{title}}", &escape_html(title))
+        .replace("{{spec_url}}", &escape_html(spec_url))
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+const SWAGGER_UI_TEMPLATE: &str = r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8">
+  <title>{{title}}</title>
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css">
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+  <script>
+    window.ui = SwaggerUIBundle({ url: "{{spec_url}}", dom_id: "#swagger-ui" });
+  </script>
+</body>
+</html>
+"##;
+
+/// Create a JSON response holding a generated document
+pub fn document_response(document: &Value) -> Response {
+    let mut response = Response::new(Status::Ok);
+    response.set_body(document.to_string().as_bytes());
+    response.set_header("Content-Type", "application/json");
+    response
+}
+
+/// Create an HTML response holding the Swagger UI page
+pub fn swagger_ui_response(title: &str, spec_url: &str) -> Response {
+    let mut response = Response::new(Status::Ok);
+    response.set_body(swagger_ui_html(title, spec_url).as_bytes());
+    response.set_header("Content-Type", "text/html; charset=utf-8");
+    response
+}
\ No newline at end of file
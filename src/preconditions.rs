@@ -0,0 +1,265 @@
+use crate::http::{Method, Request, Response, Status};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const DAY_NAMES: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+const MONTH_NAMES: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Headers copied from a full response onto the 304 that replaces it
+const NOT_MODIFIED_HEADERS: [&str; 7] = [
+    "ETag",
+    "Last-Modified",
+    "Cache-Control",
+    "Content-Location",
+    "Date",
+    "Expires",
+    "Vary",
+];
+
+/// Convert days since the Unix epoch to a (year, month, day) civil date
+pub(crate) fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Convert a civil date to days since the Unix epoch
+pub(crate) fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let month = i64::from(month);
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// Format a time as an HTTP date (`Sun, 06 Nov 1994 08:49:37 GMT`)
+pub fn format_http_date(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0) as i64;
+    let days = secs.div_euclid(86_400);
+    let rem = secs.rem_euclid(86_400);
+    let (year, month, day) = civil_from_days(days);
+    
+    format!(
+        "{}, {:02} {} {:04} {:02}:{:02}:{:02} GMT",
+        DAY_NAMES[days.rem_euclid(7) as usize],
+        day,
+        MONTH_NAMES[month as usize - 1],
+        year,
+        rem / 3600,
+        (rem % 3600) / 60,
+        rem % 60
+    )
+}
+
+/// Parse an HTTP date in the preferred IMF-fixdate format
+///
+/// The obsolete RFC 850 and asctime formats are not accepted; a date that
+/// doesn't parse makes the condition using it be ignored, as RFC 9110 asks.
+pub fn parse_http_date(value: &str) -> Option<SystemTime> {
+    let parts: Vec<&str> = value.split_whitespace().collect();
+    if parts.len() != 6 || !parts[0].ends_with(',') || parts[5] != "GMT" {
+        return None;
+    }
+    
+    let day: u32 = parts[1].parse().ok()?;
+    let month = MONTH_NAMES.iter().position(|name| *name == parts[2])? as u32 + 1;
+    let year: i64 = parts[3].parse().ok()?;
+    
+    let time: Vec<u64> = parts[4]
+        .split(':')
+        .map(|part| part.parse().ok())
+        .collect::<Option<Vec<u64>>>()?;
+    if time.len() != 3 || time[0] > 23 || time[1] > 59 || time[2] > 60 || !(1..=31).contains(&day) {
+        return None;
+    }
+    
+    let days = days_from_civil(year, month, day);
+    if days < 0 {
+        return None;
+    }
+    
+    let secs = days as u64 * 86_400 + time[0] * 3600 + time[1] * 60 + time[2];
+    Some(UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+/// Truncate a time to whole seconds, the resolution of HTTP dates
+fn whole_seconds(time: SystemTime) -> SystemTime {
+    let secs = time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    UNIX_EPOCH + Duration::from_secs(secs)
+}
+
+/// Split an entity tag into its weakness and opaque value
+fn split_etag(tag: &str) -> (bool, &str) {
+    let tag = tag.trim();
+    match tag.strip_prefix("W/") {
+        Some(rest) => (true, rest),
+        None => (false, tag),
+    }
+}
+
+/// Check whether an `If-Match`/`If-None-Match` list matches the current entity tag
+///
+/// Strong comparison (for `If-Match`) requires both tags to be strong.
+fn etag_list_matches(list: &str, current: &str, strong: bool) -> bool {
+    let (current_weak, current_value) = split_etag(current);
+    list.split(',').any(|candidate| {
+        let (weak, value) = split_etag(candidate);
+        value == current_value && !(strong && (weak || current_weak))
+    })
+}
+
+/// What to do with a request after evaluating its preconditions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PreconditionOutcome {
+    /// Handle the request normally
+    Proceed,
+    /// Answer a GET or HEAD with 304 Not Modified
+    NotModified,
+    /// Refuse the request with 412 Precondition Failed
+    PreconditionFailed,
+}
+
+/// The validators of the current representation of a resource
+///
+/// Handlers fill this in from their own state and evaluate it before acting,
+/// which gives conditional GETs (304) and optimistic concurrency for writes
+/// (412 when the client's copy is stale).
+#[derive(Debug, Clone, Default)]
+pub struct Preconditions {
+    pub etag: Option<String>,
+    pub last_modified: Option<SystemTime>,
+}
+
+impl Preconditions {
+    /// Create preconditions for a resource without validators
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Set the current entity tag, including its quotes (e.g. `"v1"` or `W/"v1"`)
+    pub fn with_etag(mut self, etag: &str) -> Self {
+        self.etag = Some(etag.to_string());
+        self
+    }
+    
+    /// Set when the resource last changed
+    pub fn with_last_modified(mut self, time: SystemTime) -> Self {
+        self.last_modified = Some(whole_seconds(time));
+        self
+    }
+    
+    /// Check whether the resource exists, judged by it having any validator
+    ///
+    /// Use `Preconditions::new()` for a resource that doesn't exist yet, so
+    /// `If-None-Match: *` lets a create-only PUT through.
+    pub fn exists(&self) -> bool {
+        self.etag.is_some() || self.last_modified.is_some()
+    }
+    
+    /// Match an entity tag list header, where `*` matches any existing resource
+    fn matches(&self, list: &str, strong: bool) -> bool {
+        if list.trim() == "*" {
+            return self.exists();
+        }
+        
+        self.etag
+            .as_deref()
+            .is_some_and(|etag| etag_list_matches(list, etag, strong))
+    }
+    
+    /// Evaluate the request's conditional headers in the order of RFC 9110 section 13.2.2
+    pub fn evaluate(&self, request: &Request) -> PreconditionOutcome {
+        let read_only = matches!(request.method, Method::Get | Method::Head);
+        
+        if let Some(if_match) = request.get_header("If-Match") {
+            let matched = self.matches(if_match, true);
+            if !matched {
+                return PreconditionOutcome::PreconditionFailed;
+            }
+        } else if let (Some(since), Some(modified)) = (
+            request.get_header("If-Unmodified-Since").and_then(|v| parse_http_date(v)),
+            self.last_modified,
+        ) {
+            if modified > since {
+                return PreconditionOutcome::PreconditionFailed;
+            }
+        }
+        
+        if let Some(if_none_match) = request.get_header("If-None-Match") {
+            let matched = self.matches(if_none_match, false);
+            if matched {
+                return if read_only {
+                    PreconditionOutcome::NotModified
+                } else {
+                    PreconditionOutcome::PreconditionFailed
+                };
+            }
+        } else if read_only {
+            if let (Some(since), Some(modified)) = (
+                request.get_header("If-Modified-Since").and_then(|v| parse_http_date(v)),
+                self.last_modified,
+            ) {
+                if modified <= since {
+                    return PreconditionOutcome::NotModified;
+                }
+            }
+        }
+        
+        PreconditionOutcome::Proceed
+    }
+    
+    /// Get the response to send instead of handling the request, if any
+    pub fn check(&self, request: &Request) -> Option<Response> {
+        let status = match self.evaluate(request) {
+            PreconditionOutcome::Proceed => return None,
+            PreconditionOutcome::NotModified => Status::NotModified,
+            PreconditionOutcome::PreconditionFailed => Status::PreconditionFailed,
+        };
+        
+        let mut response = Response::new(status);
+        self.apply_headers(&mut response);
+        response.set_header("Content-Length", "0");
+        Some(response)
+    }
+    
+    /// Set the `ETag` and `Last-Modified` headers on a response
+    pub fn apply_headers(&self, response: &mut Response) {
+        if let Some(etag) = &self.etag {
+            response.set_header("ETag", etag);
+        }
+        if let Some(modified) = self.last_modified {
+            response.set_header("Last-Modified", &format_http_date(modified));
+        }
+    }
+    
+    /// Read the validators a response already carries
+    pub fn from_response(response: &Response) -> Self {
+        Self {
+            etag: response.header("ETag").map(str::to_string),
+            last_modified: response.header("Last-Modified").and_then(parse_http_date),
+        }
+    }
+}
+
+/// Turn a full response into a 304, keeping the headers a cache needs
+pub(crate) fn not_modified_from(response: &Response) -> Response {
+    let mut not_modified = Response::new(Status::NotModified);
+    for name in NOT_MODIFIED_HEADERS {
+        if let Some(value) = response.header(name) {
+            not_modified.set_header(name, value);
+        }
+    }
+    not_modified.set_header("Content-Length", "0");
+    not_modified
+}
\ No newline at end of file
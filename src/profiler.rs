@@ -0,0 +1,287 @@
+use crate::http::{Response, Status};
+use crate::memory::MemoryManager;
+use crate::router::Router;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The scopes a registered thread is currently inside, innermost last
+struct ThreadSlot {
+    name: String,
+    stack: Mutex<Vec<&'static str>>,
+}
+
+/// Every registered thread; entries die with their thread
+static THREADS: Mutex<Vec<Weak<ThreadSlot>>> = Mutex::new(Vec::new());
+
+/// Number of captures in progress; scopes are free while this is zero
+static ACTIVE_CAPTURES: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static CURRENT: RefCell<Option<Arc<ThreadSlot>>> = const { RefCell::new(None) };
+}
+
+/// Make the calling thread visible to the profiler under `name`
+///
+/// Only registered threads are sampled. Registering again renames the thread.
+pub fn register_thread(name: &str) {
+    let slot = Arc::new(ThreadSlot {
+        name: name.to_string(),
+        stack: Mutex::new(Vec::new()),
+    });
+    
+    let mut threads = THREADS.lock().unwrap();
+    threads.retain(|thread| thread.strong_count() > 0);
+    threads.push(Arc::downgrade(&slot));
+    CURRENT.with(|current| *current.borrow_mut() = Some(slot));
+}
+
+/// Mark the calling thread as inside `name` until the returned guard drops
+///
+/// Costs a single atomic load unless a profile is being captured.
+pub fn scope(name: &'static str) -> ProfileScope {
+    if ACTIVE_CAPTURES.load(Ordering::Relaxed) == 0 {
+        return ProfileScope { slot: None };
+    }
+    
+    let slot = CURRENT.with(|current| current.borrow().clone());
+    if let Some(slot) = &slot {
+        slot.stack.lock().unwrap().push(name);
+    }
+    ProfileScope { slot }
+}
+
+/// Guard returned by [`scope`]
+pub struct ProfileScope {
+    slot: Option<Arc<ThreadSlot>>,
+}
+
+impl Drop for ProfileScope {
+    fn drop(&mut self) {
+        if let Some(slot) = &self.slot {
+            slot.stack.lock().unwrap().pop();
+        }
+    }
+}
+
+/// A sampled CPU profile in folded-stack form
+///
+/// Each sample records which scopes every registered thread was inside.
+/// Threads outside any scope are counted under an `other` frame so the
+/// proportions stay honest. Scopes entered before the capture started are
+/// missing from the stacks they enclose.
+#[derive(Debug, Clone, Default)]
+pub struct Profile {
+    stacks: HashMap<String, u64>,
+    duration: Duration,
+    frequency: u32,
+}
+
+impl Profile {
+    /// Sample every registered thread `frequency` times a second for `duration`
+    ///
+    /// Blocks the calling thread for the whole capture.
+    pub fn capture(duration: Duration, frequency: u32) -> Profile {
+        let frequency = frequency.max(1);
+        let interval = Duration::from_secs(1) / frequency;
+        let mut profile = Profile {
+            stacks: HashMap::new(),
+            duration,
+            frequency,
+        };
+        
+        ACTIVE_CAPTURES.fetch_add(1, Ordering::SeqCst);
+        let start = Instant::now();
+        let mut next = start;
+        while start.elapsed() < duration {
+            profile.sample();
+            next += interval;
+            if let Some(wait) = next.checked_duration_since(Instant::now()) {
+                thread::sleep(wait);
+            }
+        }
+        ACTIVE_CAPTURES.fetch_sub(1, Ordering::SeqCst);
+        
+        profile
+    }
+    
+    fn sample(&mut self) {
+        let threads: Vec<Arc<ThreadSlot>> = THREADS
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(Weak::upgrade)
+            .collect();
+        
+        for thread in threads {
+            let mut key = thread.name.clone();
+            {
+                let stack = thread.stack.lock().unwrap();
+                if stack.is_empty() {
+                    key.push_str(";other");
+                }
+                for frame in stack.iter() {
+                    key.push(';');
+                    key.push_str(frame);
+                }
+            }
+            *self.stacks.entry(key).or_insert(0) += 1;
+        }
+    }
+    
+    /// Sample counts keyed by `thread;outer;inner` stack
+    pub fn stacks(&self) -> &HashMap<String, u64> {
+        &self.stacks
+    }
+    
+    /// Total samples taken across all threads
+    pub fn total_samples(&self) -> u64 {
+        self.stacks.values().sum()
+    }
+    
+    /// How long the capture ran
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+    
+    /// Samples taken per second per thread
+    pub fn frequency(&self) -> u32 {
+        self.frequency
+    }
+    
+    /// Render as folded stacks, one `stack count` line each
+    ///
+    /// This is the input format of `inferno-flamegraph` and `flamegraph.pl`.
+    pub fn to_folded(&self) -> String {
+        let mut stacks: Vec<_> = self.stacks.iter().collect();
+        stacks.sort();
+        
+        let mut folded = String::new();
+        for (stack, count) in stacks {
+            let _ = writeln!(folded, "{} {}", stack, count);
+        }
+        folded
+    }
+}
+
+/// The profile being captured or last captured through the admin routes
+#[derive(Default)]
+struct CaptureState {
+    running: bool,
+    last: Option<Profile>,
+}
+
+/// Longest capture the admin routes will start
+const MAX_CAPTURE_SECONDS: u64 = 300;
+
+/// Highest sampling rate the admin routes will accept
+const MAX_FREQUENCY: u32 = 1000;
+
+/// Register profiling admin routes under `prefix`
+///
+/// - `POST <prefix>/profile?seconds=10&hz=99` starts capturing a CPU profile
+///   in the background and answers `202 Accepted` at once
+/// - `GET <prefix>/profile` returns the last finished profile as folded
+///   stacks for `inferno-flamegraph`, or `202` while a capture is running
+/// - `GET <prefix>/heap` reports the memory pools' usage as JSON, when a
+///   memory manager is given
+///
+/// Only event loop threads are sampled. Profiles reveal request handling
+/// details, so put these routes behind authentication middleware or a
+/// listener that is not publicly reachable.
+pub fn mount_profiling(router: &mut Router, prefix: &str, memory: Option<Arc<MemoryManager>>) {
+    let prefix = prefix.trim_end_matches('/');
+    let state = Arc::new(Mutex::new(CaptureState::default()));
+    
+    let start = state.clone();
+    router.post(&format!("{}/profile", prefix), move |req| {
+        let seconds = match req.query_params.get("seconds").map(|s| s.parse::<u64>()) {
+            None => 10,
+            Some(Ok(seconds)) if (1..=MAX_CAPTURE_SECONDS).contains(&seconds) => seconds,
+            Some(_) => {
+                return Ok(text_response(
+                    Status::BadRequest,
+                    &format!("seconds must be between 1 and {}", MAX_CAPTURE_SECONDS),
+                ))
+            }
+        };
+        let frequency = match req.query_params.get("hz").map(|s| s.parse::<u32>()) {
+            None => 99,
+            Some(Ok(hz)) if (1..=MAX_FREQUENCY).contains(&hz) => hz,
+            Some(_) => {
+                return Ok(text_response(Status::BadRequest, &format!("hz must be between 1 and {}", MAX_FREQUENCY)))
+            }
+        };
+        
+        {
+            let mut state = start.lock().unwrap();
+            if state.running {
+                return Ok(text_response(Status::Conflict, "A profile is already being captured"));
+            }
+            state.running = true;
+        }
+        
+        // Capture off the event loop so this worker keeps serving
+        let state = start.clone();
+        thread::spawn(move || {
+            let profile = Profile::capture(Duration::from_secs(seconds), frequency);
+            let mut state = state.lock().unwrap();
+            state.running = false;
+            state.last = Some(profile);
+        });
+        
+        let mut response = text_response(Status::Accepted, &format!("Capturing for {} seconds at {} Hz\n", seconds, frequency));
+        response.set_header("Retry-After", &seconds.to_string());
+        Ok(response)
+    });
+    
+    let fetch = state;
+    router.get(&format!("{}/profile", prefix), move |_| {
+        let state = fetch.lock().unwrap();
+        if state.running {
+            return Ok(text_response(Status::Accepted, "A profile is still being captured\n"));
+        }
+        match &state.last {
+            Some(profile) => Ok(text_response(Status::Ok, &profile.to_folded())),
+            None => Ok(text_response(Status::NotFound, "No profile has been captured\n")),
+        }
+    });
+    
+    if let Some(memory) = memory {
+        router.get(&format!("{}/heap", prefix), move |_| {
+            let stats = memory.pool_stats();
+            let pools: Vec<serde_json::Value> = stats
+                .iter()
+                .map(|pool| {
+                    serde_json::json!({
+                        "size_class": pool.size_class,
+                        "capacity": pool.capacity,
+                        "in_use": pool.in_use,
+                        "reserved_bytes": pool.reserved_bytes(),
+                        "in_use_bytes": pool.in_use_bytes(),
+                    })
+                })
+                .collect();
+            let body = serde_json::to_vec(&serde_json::json!({
+                "reserved_bytes": stats.iter().map(|pool| pool.reserved_bytes()).sum::<usize>(),
+                "in_use_bytes": stats.iter().map(|pool| pool.in_use_bytes()).sum::<usize>(),
+                "pools": pools,
+            }))?;
+            
+            let mut response = Response::new(Status::Ok);
+            response.set_body(&body);
+            response.set_header("Content-Type", "application/json");
+            Ok(response)
+        });
+    }
+}
+
+fn text_response(status: Status, body: &str) -> Response {
+    let mut response = Response::new(status);
+    response.set_body(body.as_bytes());
+    response
+}
\ No newline at end of file
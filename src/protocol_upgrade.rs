@@ -0,0 +1,242 @@
+use crate::connection::Connection;
+use crate::http::{Request, Response, Status};
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+/// Callback that takes over a connection once it has switched protocols
+pub type UpgradeHandler = Box<dyn FnOnce(Connection) + Send>;
+
+/// Callback that writes the whole response itself, given the connection and
+/// the response the server would otherwise have sent
+pub type DeferredHandler = Box<dyn FnOnce(Connection, Response) + Send>;
+
+/// What takes over the connection, and when
+enum Handoff {
+    /// Runs once the response head has been written
+    AfterHead(UpgradeHandler),
+    /// Runs before anything is written, and answers the request itself
+    Deferred(DeferredHandler),
+}
+
+/// A pending hand-off of the connection, carried by a `101 Switching
+/// Protocols`, streaming or deferred response
+///
+/// Cloning shares the callback; whichever clone is taken first runs it.
+#[derive(Clone)]
+pub struct Upgrade {
+    protocol: String,
+    deferred: bool,
+    handler: Arc<Mutex<Option<Handoff>>>,
+}
+
+impl Upgrade {
+    /// Get the protocol being switched to, empty for a streaming response
+    pub fn protocol(&self) -> &str {
+        &self.protocol
+    }
+    
+    /// Check whether the handler writes the response itself
+    pub fn is_deferred(&self) -> bool {
+        self.deferred
+    }
+    
+    /// Take the callback, leaving nothing to run a second time
+    ///
+    /// A deferred callback is only handed out once `bind_head` has given it
+    /// the response it answers in place of.
+    pub fn take_handler(&self) -> Option<UpgradeHandler> {
+        let mut handler = self.handler.lock().unwrap();
+        match handler.take()? {
+            Handoff::AfterHead(handler) => Some(handler),
+            deferred => {
+                *handler = Some(deferred);
+                None
+            }
+        }
+    }
+    
+    /// Give a deferred callback the response the server would have sent
+    pub(crate) fn bind_head(&self, head: Response) {
+        let mut handler = self.handler.lock().unwrap();
+        if let Some(Handoff::Deferred(deferred)) = handler.take() {
+            *handler = Some(Handoff::AfterHead(Box::new(move |conn| deferred(conn, head))));
+        }
+    }
+}
+
+impl fmt::Debug for Upgrade {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Upgrade").field("protocol", &self.protocol).finish_non_exhaustive()
+    }
+}
+
+/// A response that switches the connection to another protocol
+///
+/// Once the `101 Switching Protocols` head has been written, the connection
+/// leaves the event loop and `handler` is run with it on a thread of its
+/// own, so it may block. The socket is still non-blocking and any bytes the
+/// client sent after the upgrade request are left in `Connection::buffer`.
+/// The connection is closed when the handler drops it.
+///
+/// ```ignore
+/// router.get("/tunnel", |req| {
+///     if !req.get_header("upgrade").is_some_and(|p| p.eq_ignore_ascii_case("echo")) {
+///         return Ok(Response::new(Status::UpgradeRequired));
+///     }
+///     Ok(UpgradeResponse::new("echo", |mut conn| {
+///         conn.stream().set_nonblocking(false).unwrap();
+///         let mut stream = conn.stream().try_clone().unwrap();
+///         std::io::copy(&mut stream, conn.stream_mut()).ok();
+///     })
+///     .into())
+/// });
+/// ```
+pub struct UpgradeResponse {
+    response: Response,
+    protocol: String,
+    handler: Handoff,
+}
+
+impl UpgradeResponse {
+    /// Create a response switching to `protocol`, handing the connection to `handler`
+    pub fn new<F>(protocol: &str, handler: F) -> Self
+    where
+        F: FnOnce(Connection) + Send + 'static,
+    {
+        let mut response = Response::new(Status::SwitchingProtocols);
+        response.set_header("Upgrade", protocol);
+        
+        Self {
+            response,
+            protocol: protocol.to_string(),
+            handler: Handoff::AfterHead(Box::new(handler)),
+        }
+    }
+    
+    /// Send `response`'s head, then hand the connection to `handler` for the body
+    ///
+    /// For bodies produced over time, such as server-sent events. No
+    /// Content-Length is sent and any body set on `response` is dropped; the
+    /// body ends when the handler closes the connection.
+    pub fn stream<F>(mut response: Response, handler: F) -> Self
+    where
+        F: FnOnce(Connection) + Send + 'static,
+    {
+        response.body.clear();
+        response.headers.remove("Content-Length");
+        
+        Self {
+            response,
+            protocol: String::new(),
+            handler: Handoff::AfterHead(Box::new(handler)),
+        }
+    }
+    
+    /// Hand the connection to `handler` before anything is written, for it to answer on
+    ///
+    /// For handlers that would otherwise hold up the event loop, such as a
+    /// proxy waiting on its upstream. Once any earlier responses have gone
+    /// out, `handler` gets the connection and the response the server would
+    /// have sent, carrying the headers middleware added, and writes the
+    /// whole response itself. Middleware and metrics only ever see that
+    /// placeholder, never the status the handler sends.
+    pub fn defer<F>(handler: F) -> Self
+    where
+        F: FnOnce(Connection, Response) + Send + 'static,
+    {
+        let mut response = Response::new(Status::Ok);
+        response.headers.remove("Server");
+        
+        Self {
+            response,
+            protocol: String::new(),
+            handler: Handoff::Deferred(Box::new(handler)),
+        }
+    }
+    
+    /// Set an extra header on the 101 response, e.g. a handshake token
+    pub fn set_header(&mut self, name: &str, value: &str) {
+        self.response.set_header(name, value);
+    }
+    
+    /// Set an extra header on the 101 response
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.set_header(name, value);
+        self
+    }
+}
+
+impl From<UpgradeResponse> for Response {
+    fn from(upgrade: UpgradeResponse) -> Response {
+        let mut response = upgrade.response;
+        response.upgrade = Some(Upgrade {
+            protocol: upgrade.protocol,
+            deferred: matches!(upgrade.handler, Handoff::Deferred(_)),
+            handler: Arc::new(Mutex::new(Some(upgrade.handler))),
+        });
+        response
+    }
+}
+/// Write the answer to a deferred request, in place of the placeholder `head`
+///
+/// Headers middleware put on the placeholder are kept unless the answer
+/// sets its own, and the connection closes once it has been sent. An answer
+/// that switches protocols or streams passes the connection on to its
+/// handler afterwards; one that is itself deferred answers in its place.
+pub(crate) fn answer_deferred(mut client: Connection, head: Response, mut response: Response) -> io::Result<()> {
+    for (name, value) in &head.headers {
+        let framing = ["Connection", "Content-Length", "Transfer-Encoding"]
+            .iter()
+            .any(|header| header.eq_ignore_ascii_case(name));
+        if !framing && response.header(name).is_none() {
+            response.set_header(name, value);
+        }
+    }
+    
+    let upgrade = response.upgrade.take();
+    if let Some(upgrade) = upgrade.as_ref().filter(|upgrade| upgrade.is_deferred()) {
+        upgrade.bind_head(response);
+        if let Some(handler) = upgrade.take_handler() {
+            handler(client);
+        }
+        return Ok(());
+    }
+    
+    let switching = response.status == Status::SwitchingProtocols;
+    response.set_header("Connection", if switching { "Upgrade" } else { "close" });
+    
+    let mut out = Vec::with_capacity(response.head_len_hint() + response.body_bytes().len());
+    let serialized = match upgrade {
+        Some(_) => response.serialize_head(&mut out),
+        None => response.serialize(&mut out),
+    };
+    serialized.map_err(|e| io::Error::other(e.to_string()))?;
+    client.stream_mut().write_all(&out)?;
+    
+    if let Some(handler) = upgrade.and_then(|upgrade| upgrade.take_handler()) {
+        // Tunnels may idle, whatever timeout the answer was read under
+        client.stream().set_read_timeout(None)?;
+        handler(client);
+    }
+    Ok(())
+}
+
+/// Marks a request whose body is still arriving when its handler runs
+///
+/// The event loop attaches it to requests on routes with `stream_body` set.
+/// `Request::body` holds what arrived with the head and the rest is left on
+/// the connection, so only a handler answering with `UpgradeResponse::defer`
+/// can read it; any other answer closes the connection once it is sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamedBody {
+    /// Body bytes still to be read from the connection
+    pub remaining: usize,
+}
+
+impl StreamedBody {
+    /// Get the marker attached to `request`, if its body is being streamed
+    pub fn of(request: &Request) -> Option<&StreamedBody> {
+        request.extensions.get::<StreamedBody>()
+    }
+}
\ No newline at end of file
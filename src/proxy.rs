@@ -0,0 +1,1079 @@
+use crate::balancer::{Balancer, Pick};
+use crate::connection::Connection;
+use crate::deadline::Deadline;
+use crate::error::{ErrorKind, ServerError, ServerResult};
+use crate::http::{is_valid_header_name, sanitize_header_value, Method, Request, Response, Status};
+use crate::http_client::{self, HttpUrl};
+use crate::mirror::{Mirror, MirrorConfig};
+use crate::protocol_upgrade::{answer_deferred, StreamedBody, UpgradeResponse};
+use crate::router::Router;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Bytes relayed per read when streaming a body
+pub const DEFAULT_CHUNK_SIZE: usize = 16 * 1024;
+
+/// Largest upstream response head accepted
+const MAX_HEAD_SIZE: usize = 64 * 1024;
+
+/// Headers describing a single connection rather than the message, which a
+/// proxy must not pass on
+///
+/// So are any `Proxy-*` headers and any named in the `Connection` header.
+const HOP_BY_HOP: [&str; 5] = ["connection", "keep-alive", "te", "trailer", "upgrade"];
+
+/// Changes made to the headers of messages passing through a proxy
+///
+/// Names match in any case. Headers are removed first, then renamed, then
+/// added, so a rule can drop a header the client sent and put its own in.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HeaderEdits {
+    /// Headers to drop
+    pub remove: Vec<String>,
+    
+    /// Headers to move to a new name, keeping their value (old name -> new name)
+    pub rename: BTreeMap<String, String>,
+    
+    /// Headers to set, replacing any value already there
+    pub add: BTreeMap<String, String>,
+}
+
+impl HeaderEdits {
+    /// Apply the edits to `headers`, storing names through `normalize`
+    fn apply(&self, headers: &mut HashMap<String, String>, normalize: fn(&str) -> String) {
+        for name in &self.remove {
+            headers.retain(|header, _| !header.eq_ignore_ascii_case(name));
+        }
+        for (from, to) in &self.rename {
+            let existing = headers.keys().find(|header| header.eq_ignore_ascii_case(from)).cloned();
+            if let Some(value) = existing.and_then(|header| headers.remove(&header)) {
+                set_edited(headers, to, &value, normalize);
+            }
+        }
+        for (name, value) in &self.add {
+            set_edited(headers, name, value, normalize);
+        }
+    }
+}
+
+/// Set a header named in a rule, replacing it in any case
+fn set_edited(headers: &mut HashMap<String, String>, name: &str, value: &str, normalize: fn(&str) -> String) {
+    if !is_valid_header_name(name) {
+        log::warn!("Skipping proxy header rule with invalid name {:?}", name);
+        return;
+    }
+    headers.retain(|header, _| !header.eq_ignore_ascii_case(name));
+    headers.insert(normalize(name), sanitize_header_value(value).into_owned());
+}
+
+/// How a proxy rewrites the headers it passes on
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HeaderRules {
+    /// Edits to requests on their way upstream
+    pub request: HeaderEdits,
+    
+    /// Edits to responses on their way back to the client
+    pub response: HeaderEdits,
+    
+    /// Tell the upstream about the client in the `X-Forwarded-*` headers
+    pub forwarded: bool,
+    
+    /// Scheme clients reach us over, sent in `X-Forwarded-Proto`
+    pub forwarded_proto: String,
+}
+
+impl Default for HeaderRules {
+    fn default() -> Self {
+        Self {
+            request: HeaderEdits::default(),
+            response: HeaderEdits::default(),
+            forwarded: true,
+            forwarded_proto: "http".to_string(),
+        }
+    }
+}
+
+/// When a proxy compresses upstream responses on the way through
+///
+/// Only bodies the upstream sent uncompressed are touched, and never when
+/// its `Cache-Control` says `no-transform`. Clients get brotli or gzip,
+/// whichever their `Accept-Encoding` prefers; brotli wins a tie.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProxyCompression {
+    /// Compress responses for clients that accept gzip or brotli
+    pub enabled: bool,
+    
+    /// gzip level, from 1 (fastest) to 9 (smallest)
+    pub level: u32,
+    
+    /// Offer brotli to clients that accept it
+    pub brotli: bool,
+    
+    /// brotli quality, from 0 (fastest) to 11 (smallest)
+    pub brotli_quality: u32,
+    
+    /// Smallest body compressed, when the upstream gives its length
+    pub min_length: usize,
+    
+    /// Media types compressed; `text/*` matches every text type
+    pub types: Vec<String>,
+}
+
+impl Default for ProxyCompression {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            level: 6,
+            brotli: true,
+            brotli_quality: 4,
+            min_length: 1024,
+            types: [
+                "text/*",
+                "application/json",
+                "application/javascript",
+                "application/xml",
+                "image/svg+xml",
+            ]
+            .iter()
+            .map(|media_type| media_type.to_string())
+            .collect(),
+        }
+    }
+}
+
+impl ProxyCompression {
+    /// Check whether a body of `content_type` is worth compressing
+    fn compresses(&self, content_type: &str) -> bool {
+        let media_type = content_type.split(';').next().unwrap_or("").trim();
+        self.types.iter().any(|pattern| match pattern.strip_suffix('*') {
+            Some(prefix) => media_type.get(..prefix.len()).is_some_and(|p| p.eq_ignore_ascii_case(prefix)),
+            None => media_type.eq_ignore_ascii_case(pattern),
+        })
+    }
+}
+
+/// Settings for a proxy route
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProxyConfig {
+    /// URLs of the upstream servers, spread over round robin
+    pub upstreams: Vec<String>,
+    
+    /// Longest wait for the upstream to answer or take the next chunk
+    pub timeout: Duration,
+    
+    /// Bytes relayed per read when streaming a body
+    pub chunk_size: usize,
+    
+    /// How headers are rewritten on the way through
+    pub headers: HeaderRules,
+    
+    /// When responses are compressed on the way through
+    pub compression: ProxyCompression,
+    
+    /// Shadow upstream a share of requests is copied to (None = no mirroring)
+    pub mirror: Option<MirrorConfig>,
+}
+
+impl Default for ProxyConfig {
+    fn default() -> Self {
+        Self {
+            upstreams: Vec::new(),
+            timeout: Duration::from_secs(30),
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            headers: HeaderRules::default(),
+            compression: ProxyCompression::default(),
+            mirror: None,
+        }
+    }
+}
+
+/// Forwards requests to an upstream server, streaming response bodies back
+///
+/// The handler returns straight away and the connection leaves the event
+/// loop (see `UpgradeResponse::defer`); connecting, sending the request and
+/// waiting for the answer all happen on a thread of its own, where upstream
+/// failures still become error responses. The body is then relayed to the
+/// client one chunk at a time: the next chunk is only read from the
+/// upstream once the client has taken the last one, so a slow client slows
+/// the upstream down through TCP flow control instead of piling the body up
+/// in memory. Bodies keep the framing the upstream sent them with.
+///
+/// Request bodies are normally read in full by the parser before any
+/// handler runs, and relayed from memory. On routes with `stream_body` set
+/// they are relayed as they arrive instead (see `StreamedBody`).
+///
+/// Upgrade requests, such as WebSocket handshakes, are passed on with their
+/// `Upgrade` header. If the upstream switches protocols, its `101` goes back
+/// to the client and the two connections are spliced together, bytes
+/// flowing both ways on a thread of their own until either side closes. If
+/// it declines, its answer is returned like any other.
+///
+/// With several upstreams, a `Balancer` picks one per request. Upstreams
+/// that can't be connected to are skipped in favour of the next one, since
+/// nothing has been sent yet; once a request is on its way, a failure is
+/// returned rather than risk sending it twice. Either way the upstream's
+/// health is reported to the balancer.
+///
+/// Headers are rewritten on the way through by the proxy's `HeaderRules`:
+/// hop-by-hop headers are always dropped, the client is described to the
+/// upstream in `X-Forwarded-*` headers, and the configured edits run last.
+///
+/// With compression on, uncompressed bodies are compressed with brotli or
+/// gzip as they stream through, flushed after each chunk read so slow
+/// streams aren't held back.
+/// The upstream's framing is decoded and the compressed body sent chunked.
+///
+/// A `Mirror` copies a share of requests to a shadow upstream as they come
+/// in; its answers are thrown away and never delay the real one.
+#[derive(Debug, Clone)]
+pub struct Proxy {
+    balancer: Arc<Balancer>,
+    chunk_size: usize,
+    timeout: Duration,
+    headers: HeaderRules,
+    compression: ProxyCompression,
+    mirror: Option<Arc<Mirror>>,
+}
+
+impl Proxy {
+    /// Create a proxy to `upstream`, whose path is prefixed to request paths
+    pub fn new(upstream: HttpUrl) -> Self {
+        Self::balanced(Balancer::new(vec![upstream]))
+    }
+    
+    /// Create a proxy spreading requests over the upstreams of `balancer`
+    pub fn balanced(balancer: Balancer) -> Self {
+        Self {
+            balancer: Arc::new(balancer),
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            timeout: Duration::from_secs(30),
+            headers: HeaderRules::default(),
+            compression: ProxyCompression::default(),
+            mirror: None,
+        }
+    }
+    
+    /// Create a proxy from its settings
+    pub fn from_config(config: &ProxyConfig) -> ServerResult<Self> {
+        if config.upstreams.is_empty() {
+            return Err(ServerError::Config("A proxy needs at least one upstream".to_string()));
+        }
+        let upstreams = config
+            .upstreams
+            .iter()
+            .map(|url| HttpUrl::parse(url))
+            .collect::<ServerResult<Vec<_>>>()?;
+        let mut proxy = Self::balanced(Balancer::new(upstreams))
+            .with_chunk_size(config.chunk_size)
+            .with_timeout(config.timeout)
+            .with_header_rules(config.headers.clone())
+            .with_compression(config.compression.clone());
+        if let Some(mirror) = &config.mirror {
+            proxy = proxy.with_mirror(Arc::new(Mirror::new(mirror.clone())?));
+        }
+        Ok(proxy)
+    }
+    
+    /// Set the bytes relayed per read, which bounds the memory a stream uses
+    pub fn with_chunk_size(mut self, bytes: usize) -> Self {
+        self.chunk_size = bytes.max(1);
+        self
+    }
+    
+    /// Set how long connecting, or any single read or write, may take
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+    
+    /// Set how headers are rewritten on the way through
+    pub fn with_header_rules(mut self, headers: HeaderRules) -> Self {
+        self.headers = headers;
+        self
+    }
+    
+    /// Set when responses are compressed on the way through
+    pub fn with_compression(mut self, compression: ProxyCompression) -> Self {
+        self.compression = compression;
+        self
+    }
+    
+    /// Copy a share of requests to a shadow upstream as well
+    pub fn with_mirror(mut self, mirror: Arc<Mirror>) -> Self {
+        self.mirror = Some(mirror);
+        self
+    }
+    
+    /// Get the balancer choosing upstreams
+    pub fn balancer(&self) -> &Balancer {
+        &self.balancer
+    }
+    
+    /// Send `request` upstream and stream back the response
+    ///
+    /// The exchange runs on a thread of its own once the connection has
+    /// left the event loop, so a slow upstream never holds up other
+    /// connections. A request deadline, if one is attached, also bounds the
+    /// wait for the response head and is passed on in its header.
+    pub fn forward(self: &Arc<Self>, request: &Request) -> ServerResult<Response> {
+        if let Some(mirror) = &self.mirror {
+            mirror.mirror(request);
+        }
+        let proxy = self.clone();
+        let request = request.clone();
+        Ok(UpgradeResponse::defer(move |client, head| proxy.answer(&request, client, head)).into())
+    }
+    
+    /// Answer `request` on `client` with the upstream's response, or an error response
+    ///
+    /// `head` carries the headers the server and its middleware added,
+    /// which the upstream's own take precedence over.
+    fn answer(&self, request: &Request, mut client: Connection, head: Response) {
+        let result = prepare_client(&mut client, self.timeout).map_err(ServerError::Io);
+        let response = result.and_then(|_| self.respond(request, &mut client)).unwrap_or_else(|e| {
+            log::warn!("Proxying {} {} failed: {}", request.method.as_str(), request.uri, e);
+            e.to_response(request)
+        });
+        
+        if let Err(e) = answer_deferred(client, head, response) {
+            log::debug!("Proxied response cut short: {}", e);
+        }
+    }
+    
+    /// Exchange `request` with an upstream and build the response to pass on
+    fn respond(&self, request: &Request, client: &mut Connection) -> ServerResult<Response> {
+        let (pick, response, upstream, early_body) = self.send(request, client)?;
+        let mut response = if response.status == Status::SwitchingProtocols {
+            switch_protocols(request, response, upstream, early_body)
+        } else {
+            self.stream_response(request, response, upstream, early_body)
+        };
+        self.headers.response.apply(&mut response.headers, str::to_string);
+        
+        if let Some(cookie) = &pick.pin {
+            // Headers hold one value each, so leave the upstream's own cookie alone
+            if !response.headers.contains_key("Set-Cookie") {
+                response.set_header("Set-Cookie", cookie);
+            }
+        }
+        Ok(response)
+    }
+    
+    /// Relay the response body from `upstream` as the client takes it
+    fn stream_response(
+        &self,
+        request: &Request,
+        mut response: Response,
+        upstream: TcpStream,
+        early_body: Vec<u8>,
+    ) -> Response {
+        strip_hop_by_hop(&mut response.headers);
+        if !has_body(request.method, response.status) {
+            return response;
+        }
+        
+        // Streaming drops the framing headers, so put back the upstream's
+        let mut content_length = take_header(&mut response.headers, "Content-Length");
+        let mut transfer_encoding = take_header(&mut response.headers, "Transfer-Encoding");
+        let coding = self.should_compress(request, &mut response, content_length.as_deref());
+        let framing = match (&transfer_encoding, &content_length) {
+            (Some(_), _) => Framing::Chunked,
+            (None, Some(length)) => length.trim().parse().map_or(Framing::Close, Framing::Length),
+            (None, None) => Framing::Close,
+        };
+        if let Some(coding) = coding {
+            content_length = None;
+            transfer_encoding = Some("chunked".to_string());
+            response.set_header("Content-Encoding", coding.name());
+            // The bytes change, so the upstream's strong validator no longer holds
+            if let Some(etag) = take_header(&mut response.headers, "ETag") {
+                let weak = if etag.starts_with("W/") { etag } else { format!("W/{}", etag) };
+                response.set_header("ETag", &weak);
+            }
+        }
+        
+        let chunk_size = self.chunk_size;
+        let timeout = self.timeout;
+        response = UpgradeResponse::stream(response, move |client| {
+            let result = match coding {
+                Some(coding) => relay_compressed(client, upstream, early_body, framing, chunk_size, timeout, coding),
+                None => relay(client, upstream, &early_body, chunk_size, timeout),
+            };
+            if let Err(e) = result {
+                log::debug!("Proxied response body cut short: {}", e);
+            }
+        })
+        .into();
+        if let Some(length) = content_length {
+            response.set_header("Content-Length", &length);
+        }
+        if let Some(encoding) = transfer_encoding {
+            response.set_header("Transfer-Encoding", &encoding);
+        }
+        response
+    }
+    
+    /// Pick how to compress the response, adding `Vary` when that depends on the client
+    fn should_compress(
+        &self,
+        request: &Request,
+        response: &mut Response,
+        content_length: Option<&str>,
+    ) -> Option<Coding> {
+        if !self.compression.enabled || response.status == Status::PartialContent {
+            return None;
+        }
+        let content_type = find_header(&response.headers, "Content-Type").map_or("", |(_, value)| value.as_str());
+        let no_transform = find_header(&response.headers, "Cache-Control").is_some_and(|(_, value)| {
+            value
+                .split(',')
+                .any(|directive| directive.trim().eq_ignore_ascii_case("no-transform"))
+        });
+        let encoded = find_header(&response.headers, "Content-Encoding")
+            .is_some_and(|(_, value)| !value.trim().eq_ignore_ascii_case("identity"));
+        if no_transform || encoded || !self.compression.compresses(content_type) {
+            return None;
+        }
+        let too_small = content_length
+            .and_then(|length| length.trim().parse::<usize>().ok())
+            .is_some_and(|length| length < self.compression.min_length);
+        if too_small {
+            return None;
+        }
+        
+        add_vary(&mut response.headers, "Accept-Encoding");
+        let accepted = request.get_header("accept-encoding")?;
+        let brotli = if self.compression.brotli { accepted_quality(accepted, "br") } else { 0.0 };
+        let gzip = accepted_quality(accepted, "gzip");
+        if brotli > 0.0 && brotli >= gzip {
+            Some(Coding::Brotli(self.compression.brotli_quality.min(11)))
+        } else {
+            (gzip > 0.0).then_some(Coding::Gzip(self.compression.level.min(9)))
+        }
+    }
+    
+    /// Send the request to an upstream and read the response head, leaving the body unread
+    fn send(&self, request: &Request, client: &mut Connection) -> ServerResult<(Pick, Response, TcpStream, Vec<u8>)> {
+        let deadline = Deadline::of(request);
+        let mut head_by = Instant::now() + self.timeout;
+        if let Some(deadline) = deadline {
+            head_by = head_by.min(deadline.expires());
+        }
+        
+        let mut tried = Vec::new();
+        let mut last_error = None;
+        let (pick, mut upstream) = loop {
+            let pick = match self.balancer.pick(request, &tried) {
+                Some(pick) => pick,
+                None => {
+                    let no_upstreams = || ServerError::Config("The proxy has no upstreams".to_string());
+                    return Err(last_error.unwrap_or_else(no_upstreams));
+                }
+            };
+            match http_client::connect(&pick.url, head_by) {
+                Ok(stream) => break (pick, stream),
+                Err(e) => {
+                    self.balancer.report_failure(pick.index);
+                    let error = upstream_error(&pick.url, e);
+                    // Out of time, so there is no point trying the others
+                    if error.kind() == ErrorKind::Timeout {
+                        return Err(error);
+                    }
+                    tried.push(pick.index);
+                    last_error = Some(error);
+                }
+            }
+        };
+        
+        match self.exchange(request, client, deadline, head_by, &pick.url, &mut upstream) {
+            Ok((response, early_body)) => {
+                self.balancer.report_success(pick.index);
+                Ok((pick, response, upstream, early_body))
+            }
+            // The client failed to send its body, which is no fault of the upstream's
+            Err(e) if e.kind() == ErrorKind::BadRequest => Err(e),
+            Err(e) => {
+                self.balancer.report_failure(pick.index);
+                Err(upstream_error(&pick.url, e))
+            }
+        }
+    }
+    
+    /// Write the request on a fresh connection and read the response head
+    ///
+    /// A streamed body is relayed from the client as it arrives.
+    fn exchange(
+        &self,
+        request: &Request,
+        client: &mut Connection,
+        deadline: Option<&Deadline>,
+        head_by: Instant,
+        url: &HttpUrl,
+        upstream: &mut TcpStream,
+    ) -> ServerResult<(Response, Vec<u8>)> {
+        upstream.set_write_timeout(Some(self.timeout))?;
+        upstream.write_all(request_head(url, request, deadline, &self.headers).as_bytes())?;
+        for chunk in request.body.chunks(self.chunk_size) {
+            upstream.write_all(chunk)?;
+        }
+        if let Some(streamed) = StreamedBody::of(request) {
+            relay_request_body(client, upstream, streamed.remaining, self.chunk_size)?;
+        }
+        
+        let remaining = head_by.saturating_duration_since(Instant::now()).max(Duration::from_millis(1));
+        upstream.set_read_timeout(Some(remaining))?;
+        let (response, early_body) = read_response_head(upstream, self.chunk_size)?;
+        upstream.set_read_timeout(Some(self.timeout))?;
+        Ok((response, early_body))
+    }
+}
+
+/// Turn an I/O failure talking to `url` into a gateway error
+fn upstream_error(url: &HttpUrl, error: ServerError) -> ServerError {
+    match error {
+        // Anything but running out of time is the upstream's fault
+        ServerError::Io(e) if e.kind() != io::ErrorKind::TimedOut => {
+            ServerError::Protocol(format!("Upstream {} failed: {}", url.address, e))
+        }
+        e => e,
+    }
+}
+
+/// Build the head of the request sent to `url`
+///
+/// A `Host` added by the header rules replaces the upstream's own.
+pub(crate) fn request_head(
+    url: &HttpUrl,
+    request: &Request,
+    deadline: Option<&Deadline>,
+    rules: &HeaderRules,
+) -> String {
+    let mut headers: HashMap<String, String> =
+        request.headers.iter().map(|(name, value)| (name.to_string(), value.clone())).collect();
+    headers.remove("host");
+    strip_hop_by_hop(&mut headers);
+    if rules.forwarded {
+        add_forwarded(request, &rules.forwarded_proto, &mut headers);
+    }
+    rules.request.apply(&mut headers, str::to_lowercase);
+    let host = headers.remove("host").unwrap_or_else(|| url.host.clone());
+    // The body is already here or on its way, so there is nothing to continue
+    for framing in ["content-length", "transfer-encoding", "expect"] {
+        headers.remove(framing);
+    }
+    
+    let target = match url.path.trim_end_matches('/') {
+        "" => request.uri.clone(),
+        prefix => format!("{}{}", prefix, request.uri),
+    };
+    let mut head = format!("{} {} HTTP/1.1\r\nHost: {}\r\n", request.method.as_str(), target, host);
+    for (name, value) in &headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    if let Some((name, millis)) = deadline.and_then(Deadline::upstream_header) {
+        head.push_str(&format!("{}: {}\r\n", name, millis));
+    }
+    let length = request.body.len() + StreamedBody::of(request).map_or(0, |streamed| streamed.remaining);
+    if length > 0 || matches!(request.method, Method::Post | Method::Put | Method::Patch) {
+        head.push_str(&format!("Content-Length: {}\r\n", length));
+    }
+    match requested_upgrade(request) {
+        Some(protocols) => head.push_str(&format!("Connection: Upgrade\r\nUpgrade: {}\r\n\r\n", protocols)),
+        None => head.push_str("Connection: close\r\n\r\n"),
+    }
+    head
+}
+
+/// Tell the upstream who the client is and how it reached us
+///
+/// The client's address is added to any `X-Forwarded-For` chain already
+/// there; the other headers describe this hop and replace what came in.
+fn add_forwarded(request: &Request, proto: &str, headers: &mut HashMap<String, String>) {
+    if let Some(addr) = request.peer_addr {
+        let client = addr.ip().to_string();
+        let chain = match headers.get("x-forwarded-for") {
+            Some(chain) if !chain.trim().is_empty() => format!("{}, {}", chain, client),
+            _ => client,
+        };
+        headers.insert("x-forwarded-for".to_string(), chain);
+    }
+    headers.insert("x-forwarded-proto".to_string(), proto.to_string());
+    
+    if let Some(host) = request.get_header("host") {
+        let default_port = if proto.eq_ignore_ascii_case("https") { "443" } else { "80" };
+        // A bare IPv6 literal ends in `]`, not a port
+        let port = host
+            .rsplit_once(':')
+            .map(|(_, port)| port)
+            .filter(|port| !port.is_empty() && port.bytes().all(|b| b.is_ascii_digit()))
+            .unwrap_or(default_port);
+        headers.insert("x-forwarded-port".to_string(), port.to_string());
+        headers.insert("x-forwarded-host".to_string(), host.clone());
+    }
+}
+
+/// Find a header by name in any case, returning the name as stored and its value
+fn find_header<'a>(headers: &'a HashMap<String, String>, name: &str) -> Option<(&'a String, &'a String)> {
+    headers.iter().find(|(header, _)| header.eq_ignore_ascii_case(name))
+}
+
+/// Remove a header by name in any case, returning its value
+fn take_header(headers: &mut HashMap<String, String>, name: &str) -> Option<String> {
+    let stored = find_header(headers, name)?.0.clone();
+    headers.remove(&stored)
+}
+
+/// Add `name` to the `Vary` header unless it is already covered
+fn add_vary(headers: &mut HashMap<String, String>, name: &str) {
+    match find_header(headers, "Vary").map(|(stored, value)| (stored.clone(), value.clone())) {
+        Some((stored, value)) => {
+            let covered = value
+                .split(',')
+                .any(|field| field.trim() == "*" || field.trim().eq_ignore_ascii_case(name));
+            if !covered {
+                headers.insert(stored, format!("{}, {}", value, name));
+            }
+        }
+        None => {
+            headers.insert("Vary".to_string(), name.to_string());
+        }
+    }
+}
+
+/// Get the weight an `Accept-Encoding` value gives `coding`, named or through `*`
+fn accepted_quality(accepted: &str, coding: &str) -> f32 {
+    let mut wildcard = 0.0;
+    for entry in accepted.split(',') {
+        let mut params = entry.split(';');
+        let name = params.next().unwrap_or("").trim();
+        let quality = params
+            .filter_map(|param| param.trim().strip_prefix("q="))
+            .find_map(|q| q.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+        if name.eq_ignore_ascii_case(coding) {
+            return quality;
+        }
+        if name == "*" {
+            wildcard = quality;
+        }
+    }
+    wildcard
+}
+
+/// Check whether `name`, in any case, is a hop-by-hop header
+fn is_hop_by_hop(name: &str) -> bool {
+    HOP_BY_HOP.iter().any(|header| header.eq_ignore_ascii_case(name))
+        || name.get(..6).is_some_and(|prefix| prefix.eq_ignore_ascii_case("proxy-"))
+}
+
+/// Drop the hop-by-hop headers, including any the `Connection` header names
+fn strip_hop_by_hop(headers: &mut HashMap<String, String>) {
+    let named: Vec<String> = headers
+        .iter()
+        .filter(|(name, _)| name.eq_ignore_ascii_case("connection"))
+        .flat_map(|(_, value)| value.split(',').map(|token| token.trim().to_string()))
+        .collect();
+    headers.retain(|name, _| !is_hop_by_hop(name) && !named.iter().any(|token| token.eq_ignore_ascii_case(name)));
+}
+
+/// Get the protocols a client asked to switch to, if it asked
+pub(crate) fn requested_upgrade(request: &Request) -> Option<&str> {
+    let upgrade = request.get_header("upgrade").filter(|protocols| !protocols.trim().is_empty())?;
+    let connection = request.get_header("connection")?;
+    connection
+        .split(',')
+        .any(|token| token.trim().eq_ignore_ascii_case("upgrade"))
+        .then_some(upgrade.as_str())
+}
+
+/// Check whether a response to `method` with `status` carries a body
+fn has_body(method: Method, status: Status) -> bool {
+    method != Method::Head && !matches!(status, Status::NoContent | Status::NotModified)
+}
+
+/// Read the upstream's response head, returning it and any body bytes read with it
+///
+/// Interim 1xx responses, such as `103 Early Hints`, are skipped; a `101
+/// Switching Protocols` is final. Hop-by-hop headers are left in.
+fn read_response_head(upstream: &mut TcpStream, chunk_size: usize) -> ServerResult<(Response, Vec<u8>)> {
+    let mut received = Vec::new();
+    let mut buf = vec![0u8; chunk_size.min(MAX_HEAD_SIZE)];
+    let (head, code) = loop {
+        let head_end = match received.windows(4).position(|window| window == b"\r\n\r\n") {
+            Some(pos) => pos,
+            None if received.len() > MAX_HEAD_SIZE => {
+                return Err(ServerError::Protocol("Upstream response head too large".to_string()));
+            }
+            None => {
+                let n = upstream.read(&mut buf).map_err(|e| match e.kind() {
+                    io::ErrorKind::WouldBlock => io::Error::new(io::ErrorKind::TimedOut, "Upstream response timed out"),
+                    _ => e,
+                })?;
+                if n == 0 {
+                    return Err(ServerError::Protocol("Upstream closed before responding".to_string()));
+                }
+                received.extend_from_slice(&buf[..n]);
+                continue;
+            }
+        };
+        
+        let head: Vec<u8> = received.drain(..head_end + 4).collect();
+        let head = String::from_utf8_lossy(&head[..head_end]).into_owned();
+        let code = head
+            .split_whitespace()
+            .nth(1)
+            .and_then(|code| code.parse::<u16>().ok())
+            .ok_or_else(|| ServerError::Protocol("Invalid upstream status line".to_string()))?;
+        if code == 101 || !(100..200).contains(&code) {
+            break (head, code);
+        }
+    };
+    
+    let mut response = Response::new(status_for(code));
+    // The upstream names itself, or no one does
+    response.headers.remove("Server");
+    for line in head.split("\r\n").skip(1) {
+        let (name, value) = match line.split_once(':') {
+            Some((name, value)) => (name.trim(), value.trim()),
+            None => continue,
+        };
+        response.append_header(name, value);
+    }
+    Ok((response, received))
+}
+
+/// Map an upstream status code onto one we can send
+///
+/// Codes we have no variant for fall back to the generic code of their class.
+fn status_for(code: u16) -> Status {
+    Status::from_code(code).unwrap_or(match code {
+        200..=299 => Status::Ok,
+        300..=399 => Status::Found,
+        400..=499 => Status::BadRequest,
+        _ => Status::BadGateway,
+    })
+}
+
+/// Pass the upstream's `101 Switching Protocols` on and tunnel the connection to it
+fn switch_protocols(
+    request: &Request,
+    response: Response,
+    upstream: TcpStream,
+    early_upstream: Vec<u8>,
+) -> Response {
+    let protocol = response
+        .headers
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("upgrade"))
+        .map(|(_, value)| value.clone())
+        .or_else(|| requested_upgrade(request).map(str::to_string))
+        .unwrap_or_default();
+    
+    let tunnel = protocol.clone();
+    let mut switched = UpgradeResponse::new(&protocol, move |client| {
+        if let Err(e) = splice(client, upstream, &early_upstream) {
+            log::debug!("Proxied {} tunnel closed: {}", tunnel, e);
+        }
+    });
+    // Handshake headers such as Sec-WebSocket-Accept go through untouched
+    let mut headers = response.headers;
+    strip_hop_by_hop(&mut headers);
+    for (name, value) in &headers {
+        switched.set_header(name, value);
+    }
+    switched.into()
+}
+
+/// Copy bytes both ways between the client and the upstream until either closes
+///
+/// Whichever side closes first has its close passed on, so the other side
+/// winds down too.
+fn splice(client: Connection, upstream: TcpStream, early_upstream: &[u8]) -> io::Result<()> {
+    let mut client_stream = client.stream().try_clone()?;
+    client_stream.set_nonblocking(false)?;
+    let early_client = client.buffer().slice().to_vec();
+    
+    let mut to_upstream = upstream.try_clone()?;
+    let mut from_client = client_stream.try_clone()?;
+    let uplink = thread::spawn(move || {
+        let result = to_upstream
+            .write_all(&early_client)
+            .and_then(|_| io::copy(&mut from_client, &mut to_upstream));
+        let _ = to_upstream.shutdown(Shutdown::Write);
+        result
+    });
+    
+    let mut from_upstream = upstream;
+    from_upstream.set_read_timeout(None)?;
+    let result = client_stream
+        .write_all(early_upstream)
+        .and_then(|_| io::copy(&mut from_upstream, &mut client_stream));
+    // Stop the uplink too; the upstream is gone
+    let _ = client_stream.shutdown(Shutdown::Both);
+    let _ = from_upstream.shutdown(Shutdown::Both);
+    
+    let uplink = uplink.join().unwrap_or_else(|_| Err(io::Error::other("uplink panicked")));
+    drop(client);
+    result.and(uplink).map(|_| ())
+}
+
+/// Switch the client's socket to blocking I/O, bounded by `timeout`
+fn prepare_client(client: &mut Connection, timeout: Duration) -> io::Result<()> {
+    let stream = client.stream_mut();
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))
+}
+
+/// Copy the `remaining` bytes of a streamed request body from the client to the upstream
+///
+/// Whatever the client sent ahead of the read goes first, then the rest is
+/// read a chunk at a time as the upstream takes it. The client falling
+/// short is a bad request rather than an upstream failure.
+fn relay_request_body(
+    client: &mut Connection,
+    upstream: &mut TcpStream,
+    mut remaining: usize,
+    chunk_size: usize,
+) -> ServerResult<()> {
+    let buffered = client.buffer().available_data().min(remaining);
+    upstream.write_all(&client.buffer().slice()[..buffered])?;
+    client.buffer_mut().consume(buffered)?;
+    remaining -= buffered;
+    
+    let mut buf = vec![0u8; chunk_size.min(remaining)];
+    while remaining > 0 {
+        let want = buf.len().min(remaining);
+        let n = match client.stream_mut().read(&mut buf[..want]) {
+            Ok(0) => return Err(ServerError::HttpParse("Request body cut short".to_string())),
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(ServerError::HttpParse(format!("Request body cut short: {}", e))),
+        };
+        upstream.write_all(&buf[..n])?;
+        remaining -= n;
+    }
+    Ok(())
+}
+
+/// Copy the rest of the upstream's body to the client a chunk at a time
+fn relay(
+    mut client: Connection,
+    mut upstream: TcpStream,
+    early_body: &[u8],
+    chunk_size: usize,
+    timeout: Duration,
+) -> io::Result<()> {
+    let stream = client.stream_mut();
+    stream.set_nonblocking(false)?;
+    stream.set_write_timeout(Some(timeout))?;
+    stream.write_all(early_body)?;
+    
+    let mut buf = vec![0u8; chunk_size];
+    loop {
+        let n = upstream.read(&mut buf)?;
+        if n == 0 {
+            return Ok(());
+        }
+        stream.write_all(&buf[..n])?;
+    }
+}
+
+/// How the upstream marks the end of its response body
+#[derive(Debug, Clone, Copy)]
+enum Framing {
+    Length(u64),
+    Chunked,
+    Close,
+}
+
+/// A content coding the proxy compresses bodies with, and how hard
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Coding {
+    /// gzip at a level from 1 to 9
+    Gzip(u32),
+    /// brotli at a quality from 0 to 11
+    Brotli(u32),
+}
+
+impl Coding {
+    /// Get the coding's name, as used in `Content-Encoding`
+    fn name(self) -> &'static str {
+        match self {
+            Coding::Gzip(_) => "gzip",
+            Coding::Brotli(_) => "br",
+        }
+    }
+}
+
+/// log2 of brotli's window; 256 KiB keeps each stream's encoder small
+const BROTLI_WINDOW_BITS: u32 = 18;
+
+/// Copy the upstream's body to the client compressed, a chunk at a time
+///
+/// The encoder is flushed after every read, so each piece the upstream
+/// sends reaches the client without waiting for more.
+fn relay_compressed(
+    mut client: Connection,
+    upstream: TcpStream,
+    early_body: Vec<u8>,
+    framing: Framing,
+    chunk_size: usize,
+    timeout: Duration,
+    coding: Coding,
+) -> io::Result<()> {
+    let stream = client.stream_mut();
+    stream.set_nonblocking(false)?;
+    stream.set_write_timeout(Some(timeout))?;
+    
+    let raw = io::Cursor::new(early_body).chain(upstream);
+    let mut body: Box<dyn Read> = match framing {
+        Framing::Length(length) => Box::new(raw.take(length)),
+        Framing::Chunked => Box::new(ChunkedReader::new(BufReader::with_capacity(chunk_size, raw))),
+        Framing::Close => Box::new(raw),
+    };
+    let mut buf = vec![0u8; chunk_size];
+    let ChunkedWriter(stream) = match coding {
+        Coding::Gzip(level) => {
+            let mut encoder = GzEncoder::new(ChunkedWriter(stream), Compression::new(level));
+            compress_body(&mut body, &mut encoder, &mut buf)?;
+            encoder.finish()?
+        }
+        Coding::Brotli(quality) => {
+            let writer = ChunkedWriter(stream);
+            let mut encoder = brotli::CompressorWriter::new(writer, chunk_size, quality, BROTLI_WINDOW_BITS);
+            compress_body(&mut body, &mut encoder, &mut buf)?;
+            // Finishing swallows write errors; the last chunk below runs into them instead
+            encoder.into_inner()
+        }
+    };
+    stream.write_all(b"0\r\n\r\n")
+}
+
+/// Feed all of `body` through `encoder`, flushing after every read
+fn compress_body<W: Write>(body: &mut dyn Read, encoder: &mut W, buf: &mut [u8]) -> io::Result<()> {
+    loop {
+        let n = body.read(buf)?;
+        if n == 0 {
+            return Ok(());
+        }
+        encoder.write_all(&buf[..n])?;
+        encoder.flush()?;
+    }
+}
+
+/// Writes everything it is given as one chunk of a chunked body
+struct ChunkedWriter<W: Write>(W);
+
+impl<W: Write> Write for ChunkedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !buf.is_empty() {
+            let mut chunk = format!("{:x}\r\n", buf.len()).into_bytes();
+            chunk.extend_from_slice(buf);
+            chunk.extend_from_slice(b"\r\n");
+            self.0.write_all(&chunk)?;
+        }
+        Ok(buf.len())
+    }
+    
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+/// Reads the data out of a chunked body, stopping after the last chunk
+struct ChunkedReader<R: BufRead> {
+    inner: R,
+    /// Bytes left in the current chunk
+    remaining: u64,
+    done: bool,
+}
+
+impl<R: BufRead> ChunkedReader<R> {
+    fn new(inner: R) -> Self {
+        Self {
+            inner,
+            remaining: 0,
+            done: false,
+        }
+    }
+    
+    fn read_line(&mut self) -> io::Result<String> {
+        let mut line = String::new();
+        if self.inner.read_line(&mut line)? == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Chunked body cut short"));
+        }
+        Ok(line)
+    }
+}
+
+impl<R: BufRead> Read for ChunkedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.done || buf.is_empty() {
+            return Ok(0);
+        }
+        if self.remaining == 0 {
+            let line = self.read_line()?;
+            let size = line.split(';').next().unwrap_or("").trim();
+            self.remaining = u64::from_str_radix(size, 16)
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Invalid chunk size"))?;
+            if self.remaining == 0 {
+                // Skip any trailers up to the closing blank line
+                while !self.read_line()?.trim().is_empty() {}
+                self.done = true;
+                return Ok(0);
+            }
+        }
+        
+        let want = buf.len().min(self.remaining.min(usize::MAX as u64) as usize);
+        let n = self.inner.read(&mut buf[..want])?;
+        if n == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Chunked body cut short"));
+        }
+        self.remaining -= n as u64;
+        if self.remaining == 0 {
+            self.read_line()?;
+        }
+        Ok(n)
+    }
+}
+
+/// Create a handler forwarding every request it gets through `proxy`
+pub fn proxy_handler(proxy: Arc<Proxy>) -> impl Fn(&Request) -> ServerResult<Response> + Send + Sync {
+    move |request| proxy.forward(request)
+}
+
+/// Forward every request under `prefix` through `proxy`, whatever its method
+///
+/// The prefix is kept in the forwarded path; give the upstream URL a path
+/// to add one in front of it.
+pub fn mount_proxy(router: &mut Router, prefix: &str, proxy: Arc<Proxy>) {
+    let prefix = prefix.trim_end_matches('/');
+    let methods = [
+        Method::Get,
+        Method::Head,
+        Method::Post,
+        Method::Put,
+        Method::Delete,
+        Method::Options,
+        Method::Patch,
+    ];
+    for method in methods {
+        let pattern = format!("{}/*", prefix);
+        router.add_route(method, &pattern, proxy_handler(proxy.clone()));
+        if !prefix.is_empty() {
+            router.add_route(method, prefix, proxy_handler(proxy.clone()));
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,342 @@
+//! Request rate limits that can be shared between replicas
+//!
+//! Limits are token buckets kept by a `RateLimitBackend`. The in-memory
+//! backend holds them in this process, so each replica enforces its own
+//! copy; the Redis backend keeps them in a Redis server, so every replica
+//! draws from the same bucket and the limits hold across the cluster.
+//! `SyncedRateLimiter` puts a local copy in front of a shared backend, so
+//! requests are admitted without waiting on the network.
+
+use crate::error::{ServerError, ServerResult};
+use crate::resp::{RespClient, RespValue};
+use crate::throttle::TokenBucket;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+
+/// How fast requests may arrive for one key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RateLimit {
+    /// Requests per second allowed on average
+    pub rate_per_sec: u64,
+    
+    /// Requests allowed at once above the average rate
+    pub burst: u64,
+}
+
+impl RateLimit {
+    /// Allow `rate_per_sec` requests per second with a burst of one second's worth
+    pub fn per_second(rate_per_sec: u64) -> Self {
+        Self {
+            rate_per_sec,
+            burst: rate_per_sec,
+        }
+    }
+}
+
+/// The outcome of asking a backend for tokens
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateDecision {
+    /// Whether the tokens were taken
+    pub allowed: bool,
+    
+    /// Whole tokens left in the bucket afterwards
+    pub remaining: u64,
+    
+    /// How long until the request would be allowed (zero when allowed)
+    pub retry_after: Duration,
+}
+
+/// Where rate-limit buckets are kept
+///
+/// Implement this to keep buckets in a store of your own. Implementations
+/// must take tokens atomically, since replicas and threads ask at once.
+pub trait RateLimitBackend: Send + Sync {
+    /// Take `cost` tokens from the bucket `key`, which refills at `limit`
+    ///
+    /// A bucket that doesn't exist yet starts full. Nothing is taken unless
+    /// all `cost` tokens are there.
+    fn acquire(&self, key: &str, limit: RateLimit, cost: u64) -> ServerResult<RateDecision>;
+}
+
+/// Buckets kept in this process
+#[derive(Debug, Default)]
+pub struct MemoryRateLimiter {
+    buckets: Mutex<HashMap<String, TokenBucket>>,
+}
+
+/// Buckets kept before full ones are dropped to save memory
+const MAX_IDLE_BUCKETS: usize = 10_000;
+
+impl MemoryRateLimiter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Get the number of buckets held
+    pub fn len(&self) -> usize {
+        self.buckets.lock().unwrap().len()
+    }
+    
+    /// Check whether no buckets are held
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl RateLimitBackend for MemoryRateLimiter {
+    fn acquire(&self, key: &str, limit: RateLimit, cost: u64) -> ServerResult<RateDecision> {
+        let mut buckets = self.buckets.lock().unwrap();
+        if !buckets.contains_key(key) && buckets.len() >= MAX_IDLE_BUCKETS {
+            // A full bucket is the same as a missing one
+            buckets.retain(|_, bucket| (bucket.available() as u64) < bucket.burst());
+        }
+        let bucket = buckets
+            .entry(key.to_string())
+            .or_insert_with(|| TokenBucket::with_burst(limit.rate_per_sec, limit.burst));
+        if bucket.rate() != limit.rate_per_sec.max(1) || bucket.burst() != limit.burst.max(1) {
+            *bucket = TokenBucket::with_burst(limit.rate_per_sec, limit.burst);
+        }
+        
+        let available = bucket.available() as u64;
+        if available >= cost {
+            bucket.consume(cost as usize);
+            return Ok(RateDecision {
+                allowed: true,
+                remaining: available - cost,
+                retry_after: Duration::ZERO,
+            });
+        }
+        Ok(RateDecision {
+            allowed: false,
+            remaining: available,
+            retry_after: bucket.time_until(cost),
+        })
+    }
+}
+
+/// Token bucket run inside Redis, so that taking tokens is atomic
+///
+/// Returns whether the tokens were taken, the whole tokens left and the
+/// milliseconds until `cost` tokens are there. Redis's own clock is used so
+/// replicas with skewed clocks still agree.
+const ACQUIRE_SCRIPT: &str = "\
+local rate = tonumber(ARGV[1])
+local burst = tonumber(ARGV[2])
+local cost = tonumber(ARGV[3])
+local time = redis.call('TIME')
+local now = tonumber(time[1]) + tonumber(time[2]) / 1000000
+local state = redis.call('HMGET', KEYS[1], 'tokens', 'at')
+local tokens = tonumber(state[1]) or burst
+local at = tonumber(state[2]) or now
+tokens = math.min(burst, tokens + math.max(0, now - at) * rate)
+local allowed = 0
+local wait = 0
+if tokens >= cost then
+  tokens = tokens - cost
+  allowed = 1
+else
+  wait = math.ceil((math.min(cost, burst) - tokens) / rate * 1000)
+end
+redis.call('HSET', KEYS[1], 'tokens', tostring(tokens), 'at', tostring(now))
+redis.call('PEXPIRE', KEYS[1], math.ceil(burst / rate * 1000) + 1000)
+return {allowed, math.floor(tokens), wait}";
+
+/// Buckets kept in a Redis server (or anything speaking its protocol)
+///
+/// Each bucket is a hash under `<prefix><key>` that expires once it would
+/// be full again.
+#[derive(Debug)]
+pub struct RedisRateLimiter {
+    client: Arc<RespClient>,
+    prefix: String,
+}
+
+impl RedisRateLimiter {
+    /// Keep buckets in the Redis server at `address` (`host:port`)
+    pub fn new(address: &str) -> Self {
+        Self::with_client(Arc::new(RespClient::new(address)))
+    }
+    
+    /// Keep buckets through `client`, which may be shared with other users
+    pub fn with_client(client: Arc<RespClient>) -> Self {
+        Self {
+            client,
+            prefix: "ratelimit:".to_string(),
+        }
+    }
+    
+    /// Put `prefix` in front of every bucket key
+    pub fn with_prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.to_string();
+        self
+    }
+}
+
+impl RateLimitBackend for RedisRateLimiter {
+    fn acquire(&self, key: &str, limit: RateLimit, cost: u64) -> ServerResult<RateDecision> {
+        let key = format!("{}{}", self.prefix, key);
+        let reply = self.client.command(&[
+            "EVAL".to_string(),
+            ACQUIRE_SCRIPT.to_string(),
+            "1".to_string(),
+            key,
+            limit.rate_per_sec.max(1).to_string(),
+            limit.burst.max(1).to_string(),
+            cost.to_string(),
+        ])?;
+        
+        let values: Option<Vec<i64>> = match &reply {
+            RespValue::Array(Some(items)) => items.iter().map(RespValue::as_integer).collect(),
+            _ => None,
+        };
+        match values.as_deref() {
+            Some([allowed, remaining, wait]) => Ok(RateDecision {
+                allowed: *allowed == 1,
+                remaining: (*remaining).max(0) as u64,
+                retry_after: Duration::from_millis((*wait).max(0) as u64),
+            }),
+            _ => Err(ServerError::Protocol(format!("Unexpected rate limit reply {:?}", reply))),
+        }
+    }
+}
+/// Buckets kept in this process and reconciled with a shared backend
+///
+/// `acquire` answers from a local bucket without any I/O. Every sync
+/// interval a background thread takes the tokens spent locally since the
+/// last sync from the shared bucket, then sets the local bucket to what the
+/// shared one has left. Replicas can together admit up to one interval's
+/// worth of requests more than the limit, and a key's first requests on a
+/// replica draw on a full local bucket. While the backend can't be reached,
+/// each replica enforces the limit on its own.
+pub struct SyncedRateLimiter {
+    state: Arc<SyncState>,
+}
+
+struct SyncState {
+    backend: Arc<dyn RateLimitBackend>,
+    buckets: Mutex<HashMap<String, SyncedBucket>>,
+}
+
+struct SyncedBucket {
+    bucket: TokenBucket,
+    limit: RateLimit,
+    /// Tokens taken locally that the shared bucket hasn't been charged yet
+    unsynced: u64,
+    /// Whether the bucket was asked for tokens since the last sync
+    touched: bool,
+}
+
+impl SyncedRateLimiter {
+    /// Keep local buckets in step with `backend`, syncing every `interval`
+    ///
+    /// The sync thread stops once the limiter is dropped.
+    pub fn new(backend: Arc<dyn RateLimitBackend>, interval: Duration) -> Self {
+        let state = Arc::new(SyncState {
+            backend,
+            buckets: Mutex::new(HashMap::new()),
+        });
+        
+        let weak = Arc::downgrade(&state);
+        std::thread::spawn(move || loop {
+            std::thread::sleep(interval);
+            match Weak::upgrade(&weak) {
+                Some(state) => state.sync(),
+                None => break,
+            }
+        });
+        
+        Self { state }
+    }
+    
+    /// Charge the shared backend for local use and catch up with it now
+    pub fn sync(&self) {
+        self.state.sync();
+    }
+}
+
+impl std::fmt::Debug for SyncedRateLimiter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SyncedRateLimiter")
+            .field("buckets", &self.state.buckets.lock().unwrap().len())
+            .finish()
+    }
+}
+
+impl RateLimitBackend for SyncedRateLimiter {
+    fn acquire(&self, key: &str, limit: RateLimit, cost: u64) -> ServerResult<RateDecision> {
+        let mut buckets = self.state.buckets.lock().unwrap();
+        if !buckets.contains_key(key) && buckets.len() >= MAX_IDLE_BUCKETS {
+            buckets.retain(|_, synced| synced.touched || (synced.bucket.available() as u64) < synced.bucket.burst());
+        }
+        let synced = buckets.entry(key.to_string()).or_insert_with(|| SyncedBucket {
+            bucket: TokenBucket::with_burst(limit.rate_per_sec, limit.burst),
+            limit,
+            unsynced: 0,
+            touched: false,
+        });
+        if synced.limit != limit {
+            synced.bucket = TokenBucket::with_burst(limit.rate_per_sec, limit.burst);
+            synced.limit = limit;
+        }
+        synced.touched = true;
+        
+        let available = synced.bucket.available() as u64;
+        if available >= cost {
+            synced.bucket.consume(cost as usize);
+            synced.unsynced += cost;
+            return Ok(RateDecision {
+                allowed: true,
+                remaining: available - cost,
+                retry_after: Duration::ZERO,
+            });
+        }
+        Ok(RateDecision {
+            allowed: false,
+            remaining: available,
+            retry_after: synced.bucket.time_until(cost),
+        })
+    }
+}
+
+impl SyncState {
+    fn sync(&self) {
+        // Take the work out of the lock, so requests aren't held up by the backend
+        let pending: Vec<(String, RateLimit, u64)> = {
+            let mut buckets = self.buckets.lock().unwrap();
+            buckets
+                .iter_mut()
+                .filter(|(_, synced)| synced.touched)
+                .map(|(key, synced)| {
+                    synced.touched = false;
+                    (key.clone(), synced.limit, std::mem::take(&mut synced.unsynced))
+                })
+                .collect()
+        };
+        
+        for (key, limit, spent) in pending {
+            match self.charge(&key, limit, spent) {
+                Ok(remaining) => {
+                    if let Some(synced) = self.buckets.lock().unwrap().get_mut(&key) {
+                        // Tokens taken while the backend was answering are still owed
+                        let remaining = remaining.saturating_sub(synced.unsynced);
+                        synced.bucket.set_available(remaining);
+                    }
+                }
+                Err(e) => log::warn!("Failed to sync rate limit {} with the shared backend: {}", key, e),
+            }
+        }
+    }
+    
+    /// Take `spent` tokens from the shared bucket, or all it has if that is
+    /// fewer, and return what is left
+    fn charge(&self, key: &str, limit: RateLimit, spent: u64) -> ServerResult<u64> {
+        let decision = self.backend.acquire(key, limit, spent)?;
+        if decision.allowed || decision.remaining == 0 {
+            return Ok(decision.remaining);
+        }
+        self.backend.acquire(key, limit, decision.remaining)?;
+        Ok(0)
+    }
+}
\ No newline at end of file
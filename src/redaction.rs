@@ -0,0 +1,193 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::borrow::Cow;
+
+/// What masked values are replaced with by default
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Which values are masked before anything is written to a log or capture
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RedactionConfig {
+    /// Header names whose values are masked, in any case
+    pub headers: Vec<String>,
+    
+    /// JSON fields, form fields and query parameters whose values are
+    /// masked, in any case and at any depth
+    pub fields: Vec<String>,
+    
+    /// Text masked values are replaced with
+    pub mask: String,
+}
+
+impl Default for RedactionConfig {
+    fn default() -> Self {
+        let headers = ["authorization", "proxy-authorization", "cookie", "set-cookie", "x-api-key"];
+        let fields = [
+            "password",
+            "passwd",
+            "secret",
+            "token",
+            "access_token",
+            "refresh_token",
+            "api_key",
+            "ssn",
+            "card_number",
+            "cvv",
+        ];
+        Self {
+            headers: headers.iter().map(|name| name.to_string()).collect(),
+            fields: fields.iter().map(|name| name.to_string()).collect(),
+            mask: REDACTED.to_string(),
+        }
+    }
+}
+
+/// Masks sensitive headers and fields so they never reach persistent storage
+///
+/// The access log, the audit log and request captures all run what they
+/// write through one of these. Only values are masked; names are kept so a
+/// reader can still tell a credential was sent.
+#[derive(Debug, Clone, Default)]
+pub struct Redactor {
+    config: RedactionConfig,
+}
+
+impl Redactor {
+    /// Create a redactor masking what `config` names
+    pub fn new(config: RedactionConfig) -> Self {
+        Self { config }
+    }
+    
+    /// Get the configuration in use
+    pub fn config(&self) -> &RedactionConfig {
+        &self.config
+    }
+    
+    /// Check whether values of the header `name` are masked
+    pub fn is_sensitive_header(&self, name: &str) -> bool {
+        self.config.headers.iter().any(|header| header.eq_ignore_ascii_case(name))
+    }
+    
+    /// Check whether values of the field or parameter `name` are masked
+    pub fn is_sensitive_field(&self, name: &str) -> bool {
+        self.config.fields.iter().any(|field| field.eq_ignore_ascii_case(name))
+    }
+    
+    /// Get a header value as it may be written down
+    pub fn header<'a>(&'a self, name: &str, value: &'a str) -> &'a str {
+        if self.is_sensitive_header(name) {
+            &self.config.mask
+        } else {
+            value
+        }
+    }
+    
+    /// Mask the values of sensitive query parameters in a request URI
+    pub fn uri<'a>(&self, uri: &'a str) -> Cow<'a, str> {
+        match uri.split_once('?') {
+            Some((path, query)) => match self.form(query) {
+                Cow::Borrowed(_) => Cow::Borrowed(uri),
+                Cow::Owned(query) => Cow::Owned(format!("{}?{}", path, query)),
+            },
+            None => Cow::Borrowed(uri),
+        }
+    }
+    
+    /// Mask the values of sensitive fields in `name=value&...` form data
+    fn form<'a>(&self, form: &'a str) -> Cow<'a, str> {
+        let sensitive = |pair: &str| {
+            let name = pair.split_once('=').map_or(pair, |(name, _)| name);
+            pair.contains('=') && self.is_sensitive_field(&percent_decode(name))
+        };
+        if !form.split('&').any(sensitive) {
+            return Cow::Borrowed(form);
+        }
+        let pairs: Vec<Cow<'_, str>> = form
+            .split('&')
+            .map(|pair| match pair.split_once('=') {
+                Some((name, _)) if sensitive(pair) => Cow::Owned(format!("{}={}", name, self.config.mask)),
+                _ => Cow::Borrowed(pair),
+            })
+            .collect();
+        Cow::Owned(pairs.join("&"))
+    }
+    
+    /// Mask sensitive fields anywhere in a JSON value
+    pub fn json(&self, value: &mut Value) {
+        match value {
+            Value::Object(map) => {
+                for (name, field) in map.iter_mut() {
+                    if self.is_sensitive_field(name) {
+                        *field = Value::String(self.config.mask.clone());
+                    } else {
+                        self.json(field);
+                    }
+                }
+            }
+            Value::Array(items) => items.iter_mut().for_each(|item| self.json(item)),
+            _ => {}
+        }
+    }
+    
+    /// Get a body as it may be written down, given its `Content-Type`
+    ///
+    /// JSON and form bodies have their sensitive fields masked. JSON that
+    /// doesn't parse is masked whole, since there is no telling what is in
+    /// it; other bodies are returned as they are.
+    pub fn body<'a>(&self, content_type: Option<&str>, body: &'a [u8]) -> Cow<'a, [u8]> {
+        let media_type = content_type
+            .and_then(|value| value.split(';').next())
+            .unwrap_or("")
+            .trim()
+            .to_ascii_lowercase();
+        if media_type == "application/json" || media_type.ends_with("+json") {
+            return match serde_json::from_slice::<Value>(body) {
+                Ok(mut value) => {
+                    self.json(&mut value);
+                    Cow::Owned(serde_json::to_vec(&value).unwrap_or_default())
+                }
+                Err(_) => Cow::Owned(self.config.mask.clone().into_bytes()),
+            };
+        }
+        if media_type == "application/x-www-form-urlencoded" {
+            if let Ok(form) = std::str::from_utf8(body) {
+                if let Cow::Owned(form) = self.form(form) {
+                    return Cow::Owned(form.into_bytes());
+                }
+            }
+        }
+        Cow::Borrowed(body)
+    }
+}
+
+/// Decode `%XX` escapes and `+` in a form field name
+fn percent_decode(name: &str) -> Cow<'_, str> {
+    if !name.contains(['%', '+']) {
+        return Cow::Borrowed(name);
+    }
+    let bytes = name.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = match bytes[i] {
+            b'%' => name.get(i + 1..i + 3).and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+            _ => None,
+        };
+        match (escaped, bytes[i]) {
+            (Some(byte), _) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (None, b'+') => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            (None, byte) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    Cow::Owned(String::from_utf8_lossy(&decoded).into_owned())
+}
\ No newline at end of file
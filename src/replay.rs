@@ -0,0 +1,235 @@
+//! Replaying captured traffic against a server
+//!
+//! Requests from HAR captures (see `har`) are sent to a target at the pace
+//! they were recorded, optionally sped up or slowed down, so a load test
+//! has the shape of real traffic. Run it with `server replay`.
+//!
+//! Captures are redacted and may have cut bodies short, so replayed
+//! requests are only as complete as the capture: masked headers are left
+//! out and truncated bodies are sent as they were kept.
+
+use crate::error::{ServerError, ServerResult};
+use crate::har::{self, HarEntry};
+use crate::http_client::{self, HttpUrl};
+use crate::redaction::REDACTED;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Headers describing the captured connection rather than the request
+const HOP_HEADERS: [&str; 8] = [
+    "connection",
+    "content-length",
+    "host",
+    "keep-alive",
+    "proxy-connection",
+    "te",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// How captured traffic is replayed
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplayOptions {
+    /// URL of the server to replay against; its path is prefixed to request paths
+    pub target: String,
+    
+    /// How many times faster than recorded to send requests (0 = as fast as possible)
+    pub speed: f64,
+    
+    /// Requests in flight at once; requests wait for a free slot, so too
+    /// few slots make the replay fall behind the recorded pace
+    pub concurrency: usize,
+    
+    /// Longest time spent on one request, connecting included
+    pub timeout: Duration,
+}
+
+impl Default for ReplayOptions {
+    fn default() -> Self {
+        Self {
+            target: "http://127.0.0.1:8080".to_string(),
+            speed: 1.0,
+            concurrency: 8,
+            timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+/// What a replay saw
+#[derive(Debug, Clone, Default)]
+pub struct ReplayReport {
+    /// Requests that got a response
+    pub completed: usize,
+    
+    /// Requests that failed to connect or got no complete response
+    pub failed: usize,
+    
+    /// Completed requests answered with a different status than captured
+    pub status_mismatches: usize,
+    
+    /// Time from the first request to the last response
+    pub elapsed: Duration,
+    
+    /// Furthest any request fell behind its scheduled time
+    pub max_lag: Duration,
+    
+    /// Latency of every completed request, sorted
+    pub latencies: Vec<Duration>,
+}
+
+impl ReplayReport {
+    /// Get the latency below which `percentile` percent of completed requests fell
+    pub fn percentile(&self, percentile: f64) -> Duration {
+        if self.latencies.is_empty() {
+            return Duration::ZERO;
+        }
+        let rank = (self.latencies.len() as f64 * percentile / 100.0).ceil() as usize;
+        self.latencies[rank.clamp(1, self.latencies.len()) - 1]
+    }
+}
+
+/// A captured request ready to be sent
+struct ReplayRequest {
+    /// When to send it, relative to the start of the replay
+    offset: Duration,
+    head: String,
+    body: Vec<u8>,
+    captured_status: u16,
+}
+
+/// Turn a captured entry into the request to send to `target`
+fn build_request(target: &HttpUrl, entry: &HarEntry, offset: Duration) -> ServerResult<ReplayRequest> {
+    let request = &entry.request;
+    let path = request
+        .url
+        .split_once("://")
+        .map_or(request.url.as_str(), |(_, rest)| rest.find('/').map_or("/", |pos| &rest[pos..]));
+    let body = match &request.post_data {
+        Some(post_data) if post_data.encoding.as_deref() == Some("base64") => base64::decode(&post_data.text)
+            .map_err(|e| ServerError::Config(format!("Captured body of {} is not base64: {}", request.url, e)))?,
+        Some(post_data) => post_data.text.clone().into_bytes(),
+        None => Vec::new(),
+    };
+    
+    let prefix = target.path.trim_end_matches('/');
+    let mut head = format!("{} {}{} HTTP/1.1\r\nHost: {}\r\n", request.method, prefix, path, target.host);
+    for header in &request.headers {
+        let hop = HOP_HEADERS.iter().any(|name| header.name.eq_ignore_ascii_case(name));
+        if !hop && header.value != REDACTED {
+            head.push_str(&format!("{}: {}\r\n", header.name, header.value));
+        }
+    }
+    head.push_str(&format!("Content-Length: {}\r\nConnection: close\r\n\r\n", body.len()));
+    
+    Ok(ReplayRequest {
+        offset,
+        head,
+        body,
+        captured_status: entry.response.status,
+    })
+}
+
+/// Replay captured entries against the target in `options`
+///
+/// Entries are sent in the order they started, each at its recorded
+/// offset from the first one divided by the speed. Entries whose start
+/// time doesn't parse keep the time of the entry captured before them.
+pub fn replay(entries: &[HarEntry], options: &ReplayOptions) -> ServerResult<ReplayReport> {
+    if options.speed.is_nan() || options.speed < 0.0 {
+        return Err(ServerError::Config(format!("Replay speed must be 0 or more, not {}", options.speed)));
+    }
+    if options.concurrency == 0 {
+        return Err(ServerError::Config("Replay concurrency must be at least 1".to_string()));
+    }
+    let target = HttpUrl::parse(&options.target)?;
+    
+    let mut previous = None;
+    let mut timed: Vec<(Option<SystemTime>, &HarEntry)> = entries
+        .iter()
+        .map(|entry| {
+            let started = har::parse_timestamp(&entry.started_date_time).or(previous);
+            previous = started;
+            (started, entry)
+        })
+        .collect();
+    timed.sort_by_key(|(started, _)| *started);
+    let first = timed.iter().find_map(|(started, _)| *started);
+    let mut requests = Vec::with_capacity(timed.len());
+    let mut offset = Duration::ZERO;
+    for (started, entry) in timed {
+        if let (Some(started), Some(first), true) = (started, first, options.speed > 0.0) {
+            let recorded = started.duration_since(first).unwrap_or_default();
+            offset = recorded.div_f64(options.speed);
+        }
+        requests.push(build_request(&target, entry, offset)?);
+    }
+    
+    let requests = Arc::new(requests);
+    let next = Arc::new(AtomicUsize::new(0));
+    let report = Arc::new(Mutex::new(ReplayReport::default()));
+    let start = Instant::now();
+    let workers: Vec<_> = (0..options.concurrency.min(requests.len()))
+        .map(|i| {
+            let (requests, next, report) = (requests.clone(), next.clone(), report.clone());
+            let (target, timeout) = (target.clone(), options.timeout);
+            thread::Builder::new().name(format!("replay-{}", i)).spawn(move || {
+                while let Some(request) = requests.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    replay_one(&target, request, start, timeout, &report);
+                }
+            })
+        })
+        .collect::<Result<_, _>>()?;
+    for worker in workers {
+        let _ = worker.join();
+    }
+    
+    let mut report = std::mem::take(&mut *report.lock().unwrap());
+    report.elapsed = start.elapsed();
+    report.latencies.sort();
+    Ok(report)
+}
+
+/// Send one request at its scheduled time and add the outcome to `report`
+fn replay_one(
+    target: &HttpUrl,
+    request: &ReplayRequest,
+    start: Instant,
+    timeout: Duration,
+    report: &Mutex<ReplayReport>,
+) {
+    let due = start + request.offset;
+    let now = Instant::now();
+    if due > now {
+        thread::sleep(due - now);
+    }
+    let sent = Instant::now();
+    let result = send(target, request, timeout);
+    let latency = sent.elapsed();
+    
+    let mut report = report.lock().unwrap();
+    report.max_lag = report.max_lag.max(sent.saturating_duration_since(due));
+    match result {
+        Ok(status) => {
+            report.completed += 1;
+            report.latencies.push(latency);
+            if status != request.captured_status {
+                report.status_mismatches += 1;
+            }
+        }
+        Err(e) => {
+            log::debug!("Replayed request to {} failed: {}", target.address, e);
+            report.failed += 1;
+        }
+    }
+}
+
+/// Send one request and read the response, returning its status
+fn send(target: &HttpUrl, request: &ReplayRequest, timeout: Duration) -> ServerResult<u16> {
+    let stream = http_client::connect(target, Instant::now() + timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    let response = http_client::exchange(stream, request.head.as_bytes(), &request.body)?;
+    Ok(response.status)
+}
\ No newline at end of file
@@ -0,0 +1,408 @@
+//! Outbound name resolution with caching
+//!
+//! Lookups run on a small pool of threads so callers on the event loop can
+//! hand a name off and carry on. With nameservers configured, queries are
+//! sent straight to them over UDP and answers are cached for their TTL;
+//! otherwise the system resolver is used and answers are cached for the
+//! configured positive TTL.
+//!
+//! Outbound clients (proxies, the LDAP authenticator and Redis) resolve
+//! through one shared resolver, which the server configures at startup.
+
+use crate::error::{ServerError, ServerResult};
+use crate::metrics::MetricsRegistry;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// DNS record types we ask for
+const TYPE_A: u16 = 1;
+const TYPE_AAAA: u16 = 28;
+
+/// Response code for a name that does not exist
+const RCODE_NXDOMAIN: u8 = 3;
+
+/// Largest DNS message accepted over UDP
+const MAX_MESSAGE: usize = 1232;
+
+/// The resolver outbound clients share
+static SHARED: OnceLock<Arc<Resolver>> = OnceLock::new();
+
+/// Get the shared resolver, starting one with the default settings on first use
+pub fn shared() -> Arc<Resolver> {
+    SHARED.get_or_init(|| Arc::new(Resolver::new(ResolverConfig::default()))).clone()
+}
+
+/// Make `resolver` the shared one
+///
+/// Only works before the shared resolver is first used; otherwise
+/// `resolver` is handed back.
+pub fn set_shared(resolver: Arc<Resolver>) -> Result<(), Arc<Resolver>> {
+    SHARED.set(resolver)
+}
+
+/// Settings for outbound name resolution
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ResolverConfig {
+    /// Nameservers queried in order (empty = the system resolver)
+    pub nameservers: Vec<SocketAddr>,
+    
+    /// Longest time an answer is cached; answers from the system resolver,
+    /// which carry no TTL, are cached this long
+    pub positive_ttl: Duration,
+    
+    /// How long a failed lookup is remembered
+    pub negative_ttl: Duration,
+    
+    /// Time to wait for each nameserver to answer
+    pub timeout: Duration,
+    
+    /// Threads performing lookups
+    pub threads: usize,
+}
+
+impl Default for ResolverConfig {
+    fn default() -> Self {
+        Self {
+            nameservers: Vec::new(),
+            positive_ttl: Duration::from_secs(300),
+            negative_ttl: Duration::from_secs(30),
+            timeout: Duration::from_secs(2),
+            threads: 2,
+        }
+    }
+}
+
+/// A cached answer
+#[derive(Debug, Clone)]
+struct CacheEntry {
+    /// None for a name that failed to resolve
+    addresses: Option<Vec<IpAddr>>,
+    expires: Instant,
+}
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// State shared between the resolver and its lookup threads
+struct Shared {
+    config: ResolverConfig,
+    cache: Mutex<HashMap<String, CacheEntry>>,
+    metrics: Option<Arc<MetricsRegistry>>,
+    next_id: AtomicU16,
+}
+
+/// A caching resolver backed by a thread pool
+///
+/// With metrics, lookups record `dns.lookup_us` and count `dns.cache_hits`,
+/// `dns.cache_misses` and `dns.failures`.
+pub struct Resolver {
+    shared: Arc<Shared>,
+    jobs: Mutex<Sender<Job>>,
+}
+
+impl Resolver {
+    /// Start a resolver and its lookup threads
+    pub fn new(config: ResolverConfig) -> Self {
+        Self::build(config, None)
+    }
+    
+    /// Start a resolver that records lookup metrics in `registry`
+    pub fn with_metrics(config: ResolverConfig, registry: Arc<MetricsRegistry>) -> Self {
+        Self::build(config, Some(registry))
+    }
+    
+    fn build(config: ResolverConfig, metrics: Option<Arc<MetricsRegistry>>) -> Self {
+        let (jobs, queue) = mpsc::channel::<Job>();
+        let queue = Arc::new(Mutex::new(queue));
+        for i in 0..config.threads.max(1) {
+            let queue = queue.clone();
+            let _ = thread::Builder::new().name(format!("resolver-{}", i)).spawn(move || loop {
+                // The lock is released before the job runs
+                let job = queue.lock().unwrap().recv();
+                match job {
+                    Ok(job) => job(),
+                    Err(_) => break,
+                }
+            });
+        }
+        
+        let seed = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |now| now.subsec_nanos());
+        Self {
+            shared: Arc::new(Shared {
+                config,
+                cache: Mutex::new(HashMap::new()),
+                metrics,
+                next_id: AtomicU16::new(seed as u16),
+            }),
+            jobs: Mutex::new(jobs),
+        }
+    }
+    
+    /// Get a cached answer without looking anything up
+    ///
+    /// Returns None when the name isn't cached, or Some(Err) for a cached failure.
+    pub fn cached(&self, host: &str) -> Option<ServerResult<Vec<IpAddr>>> {
+        if let Ok(ip) = host.parse::<IpAddr>() {
+            return Some(Ok(vec![ip]));
+        }
+        self.shared.cached(host)
+    }
+    
+    /// Resolve `host`, blocking until the answer is known
+    pub fn resolve(&self, host: &str) -> ServerResult<Vec<IpAddr>> {
+        match self.cached(host) {
+            Some(result) => result,
+            None => self.shared.lookup(host),
+        }
+    }
+    
+    /// Resolve the host of a `host:port` address, such as `example.com:80` or `[::1]:6379`
+    pub fn resolve_address(&self, address: &str) -> ServerResult<Vec<SocketAddr>> {
+        let invalid = || ServerError::Config(format!("Invalid address: {}", address));
+        let (host, port) = address.rsplit_once(':').ok_or_else(invalid)?;
+        let port: u16 = port.parse().map_err(|_| invalid())?;
+        let host = host.strip_prefix('[').and_then(|host| host.strip_suffix(']')).unwrap_or(host);
+        Ok(self.resolve(host)?.into_iter().map(|ip| SocketAddr::new(ip, port)).collect())
+    }
+    
+    /// Resolve `host` on a lookup thread, passing the answer to `callback`
+    ///
+    /// Cached answers are passed straight away on the calling thread.
+    pub fn resolve_async<F>(&self, host: &str, callback: F)
+    where
+        F: FnOnce(ServerResult<Vec<IpAddr>>) + Send + 'static,
+    {
+        if let Some(result) = self.cached(host) {
+            return callback(result);
+        }
+        
+        let shared = self.shared.clone();
+        let host = host.to_string();
+        let job: Job = Box::new(move || callback(shared.lookup(&host)));
+        if let Err(mpsc::SendError(job)) = self.jobs.lock().unwrap().send(job) {
+            // Every lookup thread has gone; resolve here rather than never
+            job();
+        }
+    }
+    
+    /// Forget every cached answer
+    pub fn clear_cache(&self) {
+        self.shared.cache.lock().unwrap().clear();
+    }
+}
+
+impl Shared {
+    fn cached(&self, host: &str) -> Option<ServerResult<Vec<IpAddr>>> {
+        let key = host.to_ascii_lowercase();
+        let mut cache = self.cache.lock().unwrap();
+        let entry = match cache.get(&key) {
+            Some(entry) if entry.expires > Instant::now() => entry.clone(),
+            Some(_) => {
+                cache.remove(&key);
+                return None;
+            }
+            None => return None,
+        };
+        drop(cache);
+        
+        self.count("dns.cache_hits");
+        Some(entry.addresses.ok_or_else(|| not_found(host)))
+    }
+    
+    fn lookup(&self, host: &str) -> ServerResult<Vec<IpAddr>> {
+        self.count("dns.cache_misses");
+        let start = Instant::now();
+        let result = if self.config.nameservers.is_empty() {
+            system_lookup(host).map(|addresses| (addresses, self.config.positive_ttl))
+        } else {
+            self.query_nameservers(host)
+        };
+        if let Some(registry) = &self.metrics {
+            registry
+                .exponential_histogram("dns.lookup_us", 1.0, 2.0, 24)
+                .record(start.elapsed().as_micros() as f64);
+        }
+        
+        let (addresses, ttl) = match result {
+            Ok((addresses, ttl)) if !addresses.is_empty() => (Some(addresses), ttl.min(self.config.positive_ttl)),
+            Ok(_) | Err(_) => {
+                self.count("dns.failures");
+                (None, self.config.negative_ttl)
+            }
+        };
+        self.cache.lock().unwrap().insert(
+            host.to_ascii_lowercase(),
+            CacheEntry {
+                addresses: addresses.clone(),
+                expires: Instant::now() + ttl,
+            },
+        );
+        addresses.ok_or_else(|| not_found(host))
+    }
+    
+    /// Ask each nameserver in turn for A and AAAA records
+    fn query_nameservers(&self, host: &str) -> ServerResult<(Vec<IpAddr>, Duration)> {
+        let mut last_error = None;
+        for nameserver in &self.config.nameservers {
+            let mut addresses = Vec::new();
+            let mut ttl = self.config.positive_ttl;
+            let mut answered = false;
+            for record_type in [TYPE_A, TYPE_AAAA] {
+                let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+                match query(*nameserver, host, record_type, id, self.config.timeout) {
+                    Ok(answer) => {
+                        answered = true;
+                        addresses.extend(answer.addresses);
+                        ttl = ttl.min(answer.ttl);
+                    }
+                    Err(e) => last_error = Some(e),
+                }
+            }
+            if answered {
+                return Ok((addresses, ttl));
+            }
+        }
+        Err(last_error.unwrap_or_else(|| not_found(host)))
+    }
+    
+    fn count(&self, name: &str) {
+        if let Some(registry) = &self.metrics {
+            registry.counter(name).increment(1);
+        }
+    }
+}
+
+fn not_found(host: &str) -> ServerError {
+    ServerError::Io(io::Error::new(io::ErrorKind::NotFound, format!("Could not resolve {}", host)))
+}
+
+fn system_lookup(host: &str) -> ServerResult<Vec<IpAddr>> {
+    let mut addresses: Vec<IpAddr> = (host, 0).to_socket_addrs()?.map(|addr| addr.ip()).collect();
+    addresses.dedup();
+    Ok(addresses)
+}
+
+/// The records of one type found for a name
+struct Answer {
+    addresses: Vec<IpAddr>,
+    /// Shortest TTL among the records, or zero when there are none
+    ttl: Duration,
+}
+
+/// Send a single query over UDP and read the answer
+fn query(nameserver: SocketAddr, host: &str, record_type: u16, id: u16, timeout: Duration) -> ServerResult<Answer> {
+    let bind: SocketAddr = if nameserver.is_ipv4() {
+        (Ipv4Addr::UNSPECIFIED, 0).into()
+    } else {
+        (Ipv6Addr::UNSPECIFIED, 0).into()
+    };
+    let socket = UdpSocket::bind(bind)?;
+    socket.connect(nameserver)?;
+    socket.set_read_timeout(Some(timeout))?;
+    socket.send(&encode_query(host, record_type, id)?)?;
+    
+    let deadline = Instant::now() + timeout;
+    let mut buf = [0u8; MAX_MESSAGE];
+    loop {
+        let len = socket.recv(&mut buf)?;
+        // Ignore stray datagrams that don't answer this query
+        if len >= 2 && u16::from_be_bytes([buf[0], buf[1]]) == id {
+            return decode_answer(&buf[..len], record_type);
+        }
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(ServerError::Io(io::Error::new(io::ErrorKind::TimedOut, "DNS query timed out")));
+        }
+        socket.set_read_timeout(Some(remaining))?;
+    }
+}
+
+/// Build a recursive query for one record type
+fn encode_query(host: &str, record_type: u16, id: u16) -> ServerResult<Vec<u8>> {
+    let mut message = Vec::with_capacity(host.len() + 18);
+    message.extend_from_slice(&id.to_be_bytes());
+    // Recursion desired, one question
+    message.extend_from_slice(&[0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+    for label in host.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(ServerError::Config(format!("Invalid host name: {}", host)));
+        }
+        message.push(label.len() as u8);
+        message.extend_from_slice(label.as_bytes());
+    }
+    message.push(0);
+    message.extend_from_slice(&record_type.to_be_bytes());
+    message.extend_from_slice(&1u16.to_be_bytes());
+    Ok(message)
+}
+
+/// Pull the records of `record_type` out of a response
+fn decode_answer(message: &[u8], record_type: u16) -> ServerResult<Answer> {
+    let malformed = || ServerError::Protocol("Malformed DNS response".to_string());
+    if message.len() < 12 || message[2] & 0x80 == 0 {
+        return Err(malformed());
+    }
+    match message[3] & 0x0f {
+        0 => {}
+        RCODE_NXDOMAIN => {
+            return Ok(Answer {
+                addresses: Vec::new(),
+                ttl: Duration::ZERO,
+            })
+        }
+        rcode => return Err(ServerError::Protocol(format!("DNS server failed with code {}", rcode))),
+    }
+    
+    let questions = u16::from_be_bytes([message[4], message[5]]);
+    let answers = u16::from_be_bytes([message[6], message[7]]);
+    let mut pos = 12;
+    for _ in 0..questions {
+        pos = skip_name(message, pos).ok_or_else(malformed)? + 4;
+    }
+    
+    let mut addresses = Vec::new();
+    let mut ttl: Option<u32> = None;
+    for _ in 0..answers {
+        pos = skip_name(message, pos).ok_or_else(malformed)?;
+        let fixed = message.get(pos..pos + 10).ok_or_else(malformed)?;
+        let rtype = u16::from_be_bytes([fixed[0], fixed[1]]);
+        let record_ttl = u32::from_be_bytes([fixed[4], fixed[5], fixed[6], fixed[7]]);
+        let length = u16::from_be_bytes([fixed[8], fixed[9]]) as usize;
+        let data = message.get(pos + 10..pos + 10 + length).ok_or_else(malformed)?;
+        pos += 10 + length;
+        
+        // Other records, such as the CNAMEs leading here, are skipped
+        let address = match (rtype, data.len()) {
+            (TYPE_A, 4) if rtype == record_type => IpAddr::from(<[u8; 4]>::try_from(data).unwrap()),
+            (TYPE_AAAA, 16) if rtype == record_type => IpAddr::from(<[u8; 16]>::try_from(data).unwrap()),
+            _ => continue,
+        };
+        addresses.push(address);
+        ttl = Some(ttl.map_or(record_ttl, |ttl| ttl.min(record_ttl)));
+    }
+    
+    Ok(Answer {
+        addresses,
+        ttl: Duration::from_secs(ttl.unwrap_or(0) as u64),
+    })
+}
+
+/// Get the position just past a possibly compressed name
+fn skip_name(message: &[u8], mut pos: usize) -> Option<usize> {
+    loop {
+        let len = *message.get(pos)? as usize;
+        match len {
+            0 => return Some(pos + 1),
+            // A pointer ends the name
+            _ if len & 0xc0 == 0xc0 => return Some(pos + 2),
+            _ => pos += 1 + len,
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,368 @@
+//! Client for the Redis protocol (RESP)
+//!
+//! Shared state such as sessions, cache entries and rate-limit buckets can
+//! live in Redis or anything speaking its protocol. `decode` works on
+//! whatever bytes have arrived so far and reports when it needs more, so it
+//! can be fed from a non-blocking socket; `RespClient` drives it over one
+//! connection for handlers, which run synchronously on worker threads.
+
+use crate::error::{ServerError, ServerResult};
+use crate::resolver;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// A value sent by the server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RespValue {
+    /// A status reply such as `OK`
+    Simple(String),
+    
+    /// An error reply such as `ERR unknown command`
+    Error(String),
+    
+    Integer(i64),
+    
+    /// A bulk string, or None for nil
+    Bulk(Option<Vec<u8>>),
+    
+    /// An array, or None for a nil array
+    Array(Option<Vec<RespValue>>),
+}
+
+impl RespValue {
+    /// Get the value as an integer, if it is one
+    pub fn as_integer(&self) -> Option<i64> {
+        match self {
+            RespValue::Integer(value) => Some(*value),
+            _ => None,
+        }
+    }
+    
+    /// Get the bytes of a bulk or status reply
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            RespValue::Bulk(Some(bytes)) => Some(bytes),
+            RespValue::Simple(text) => Some(text.as_bytes()),
+            _ => None,
+        }
+    }
+    
+    /// Check whether the value is a nil bulk string or array
+    pub fn is_nil(&self) -> bool {
+        matches!(self, RespValue::Bulk(None) | RespValue::Array(None))
+    }
+    
+    /// Turn an error reply into an `Err`, passing other values through
+    pub fn into_result(self) -> ServerResult<RespValue> {
+        match self {
+            RespValue::Error(message) => Err(ServerError::Protocol(format!("Redis error: {}", message))),
+            value => Ok(value),
+        }
+    }
+}
+
+/// Append a command as an array of bulk strings to `out`
+pub fn encode_command<A: AsRef<[u8]>>(args: &[A], out: &mut Vec<u8>) {
+    out.extend_from_slice(format!("*{}\r\n", args.len()).as_bytes());
+    for arg in args {
+        let arg = arg.as_ref();
+        out.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+        out.extend_from_slice(arg);
+        out.extend_from_slice(b"\r\n");
+    }
+}
+
+/// Decode one value from the start of `buf`
+///
+/// Returns the value and the number of bytes it took, or None if `buf`
+/// holds only part of a value so far.
+pub fn decode(buf: &[u8]) -> ServerResult<Option<(RespValue, usize)>> {
+    let line_end = match buf.windows(2).position(|window| window == b"\r\n") {
+        Some(0) => return Err(ServerError::Protocol("Redis reply has no type".to_string())),
+        Some(end) => end,
+        None => return Ok(None),
+    };
+    let line = std::str::from_utf8(&buf[1..line_end])
+        .map_err(|_| ServerError::Protocol("Redis reply line is not UTF-8".to_string()))?;
+    let rest = line_end + 2;
+    
+    let value = match buf[0] {
+        b'+' => RespValue::Simple(line.to_string()),
+        b'-' => RespValue::Error(line.to_string()),
+        b':' => RespValue::Integer(parse_integer(line)?),
+        b'$' => {
+            let length = parse_integer(line)?;
+            if length < 0 {
+                return Ok(Some((RespValue::Bulk(None), rest)));
+            }
+            let end = rest + length as usize;
+            if buf.len() < end + 2 {
+                return Ok(None);
+            }
+            if &buf[end..end + 2] != b"\r\n" {
+                return Err(ServerError::Protocol("Redis bulk string is missing its terminator".to_string()));
+            }
+            return Ok(Some((RespValue::Bulk(Some(buf[rest..end].to_vec())), end + 2)));
+        }
+        b'*' => {
+            let count = parse_integer(line)?;
+            if count < 0 {
+                return Ok(Some((RespValue::Array(None), rest)));
+            }
+            let mut items = Vec::with_capacity((count as usize).min(1024));
+            let mut offset = rest;
+            for _ in 0..count {
+                match decode(&buf[offset..])? {
+                    Some((item, used)) => {
+                        items.push(item);
+                        offset += used;
+                    }
+                    None => return Ok(None),
+                }
+            }
+            return Ok(Some((RespValue::Array(Some(items)), offset)));
+        }
+        other => {
+            return Err(ServerError::Protocol(format!("Unknown Redis reply type {:?}", other as char)));
+        }
+    };
+    Ok(Some((value, rest)))
+}
+
+fn parse_integer(value: &str) -> ServerResult<i64> {
+    value
+        .parse()
+        .map_err(|_| ServerError::Protocol(format!("Invalid Redis integer {:?}", value)))
+}
+
+/// Commands sent together, with their replies read back together
+///
+/// Saves a round trip per command; the server runs them in order but not
+/// atomically.
+#[derive(Debug, Clone, Default)]
+pub struct Pipeline {
+    buf: Vec<u8>,
+    count: usize,
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Add a command given as its arguments, such as `["SET", "key", "value"]`
+    pub fn cmd<A: AsRef<[u8]>>(&mut self, args: &[A]) -> &mut Self {
+        encode_command(args, &mut self.buf);
+        self.count += 1;
+        self
+    }
+    
+    /// Get the number of commands added
+    pub fn len(&self) -> usize {
+        self.count
+    }
+    
+    /// Check whether no commands were added
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+}
+
+/// An open connection and the bytes read from it but not yet decoded
+#[derive(Debug)]
+struct RespConnection {
+    stream: TcpStream,
+    buf: Vec<u8>,
+}
+
+impl RespConnection {
+    /// Read `count` replies
+    fn read_replies(&mut self, count: usize) -> ServerResult<Vec<RespValue>> {
+        let mut replies = Vec::with_capacity(count);
+        let mut chunk = [0u8; 4096];
+        while replies.len() < count {
+            match decode(&self.buf)? {
+                Some((value, used)) => {
+                    self.buf.drain(..used);
+                    replies.push(value);
+                }
+                None => {
+                    let read = self.stream.read(&mut chunk)?;
+                    if read == 0 {
+                        return Err(ServerError::Io(io::Error::new(
+                            io::ErrorKind::UnexpectedEof,
+                            "Redis closed the connection",
+                        )));
+                    }
+                    self.buf.extend_from_slice(&chunk[..read]);
+                }
+            }
+        }
+        Ok(replies)
+    }
+}
+
+/// A client keeping one connection to a Redis server
+///
+/// Callers take turns on the connection. It is opened on first use and
+/// after an error; a call that fails on a connection left from earlier is
+/// tried once more on a new one, since idle connections are often closed by
+/// the server. Every read and write gives up after the timeout.
+#[derive(Debug)]
+pub struct RespClient {
+    address: String,
+    timeout: Duration,
+    password: Option<String>,
+    database: u32,
+    connection: Mutex<Option<RespConnection>>,
+}
+
+impl RespClient {
+    /// Create a client for the server at `address` (`host:port`)
+    pub fn new(address: &str) -> Self {
+        Self {
+            address: address.to_string(),
+            timeout: Duration::from_millis(500),
+            password: None,
+            database: 0,
+            connection: Mutex::new(None),
+        }
+    }
+    
+    /// Give up on the server after `timeout`, connecting included
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+    
+    /// Send `AUTH password` on every new connection
+    pub fn with_password(mut self, password: &str) -> Self {
+        self.password = Some(password.to_string());
+        self
+    }
+    
+    /// Send `SELECT database` on every new connection
+    pub fn with_database(mut self, database: u32) -> Self {
+        self.database = database;
+        self
+    }
+    
+    /// Get the server address
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+    
+    fn connect(&self) -> ServerResult<RespConnection> {
+        let address = resolver::shared()
+            .resolve_address(&self.address)?
+            .into_iter()
+            .next()
+            .ok_or_else(|| ServerError::Config(format!("Redis address {} resolves to nothing", self.address)))?;
+        let stream = TcpStream::connect_timeout(&address, self.timeout)?;
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+        stream.set_nodelay(true)?;
+        let mut connection = RespConnection { stream, buf: Vec::new() };
+        
+        let mut setup = Pipeline::new();
+        if let Some(password) = &self.password {
+            setup.cmd(&["AUTH", password.as_str()]);
+        }
+        if self.database != 0 {
+            setup.cmd(&["SELECT".to_string(), self.database.to_string()]);
+        }
+        if !setup.is_empty() {
+            connection.stream.write_all(&setup.buf)?;
+            for reply in connection.read_replies(setup.count)? {
+                reply.into_result()?;
+            }
+        }
+        Ok(connection)
+    }
+    
+    /// Send the commands in `pipeline` and read back one reply for each
+    ///
+    /// Error replies are returned as values, so one failed command doesn't
+    /// hide the others' replies.
+    pub fn pipeline(&self, pipeline: &Pipeline) -> ServerResult<Vec<RespValue>> {
+        if pipeline.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut connection = self.connection.lock().unwrap();
+        let mut retry = connection.is_some();
+        loop {
+            if connection.is_none() {
+                *connection = Some(self.connect()?);
+            }
+            let open = connection.as_mut().unwrap();
+            let result = open
+                .stream
+                .write_all(&pipeline.buf)
+                .map_err(ServerError::from)
+                .and_then(|_| open.read_replies(pipeline.count));
+            match result {
+                Ok(replies) => return Ok(replies),
+                Err(e) => {
+                    *connection = None;
+                    if !retry {
+                        return Err(e);
+                    }
+                    retry = false;
+                    log::debug!("Redis connection to {} went stale, reconnecting: {}", self.address, e);
+                }
+            }
+        }
+    }
+    
+    /// Send one command, given as its arguments, and read its reply
+    ///
+    /// Error replies come back as `Err`.
+    pub fn command<A: AsRef<[u8]>>(&self, args: &[A]) -> ServerResult<RespValue> {
+        let mut pipeline = Pipeline::new();
+        pipeline.cmd(args);
+        self.pipeline(&pipeline)?.remove(0).into_result()
+    }
+    
+    /// Get the value of `key`
+    pub fn get(&self, key: &str) -> ServerResult<Option<Vec<u8>>> {
+        match self.command(&["GET", key])? {
+            RespValue::Bulk(value) => Ok(value),
+            other => Err(unexpected("GET", &other)),
+        }
+    }
+    
+    /// Set `key` to `value`, expiring after `ttl` if given
+    pub fn set(&self, key: &str, value: &[u8], ttl: Option<Duration>) -> ServerResult<()> {
+        let mut args: Vec<&[u8]> = vec![b"SET", key.as_bytes(), value];
+        let millis = ttl.map(|ttl| ttl.as_millis().max(1).to_string());
+        if let Some(millis) = &millis {
+            args.extend_from_slice(&[b"PX", millis.as_bytes()]);
+        }
+        self.command(&args).map(|_| ())
+    }
+    
+    /// Add one to the integer at `key`, starting from zero, and get the result
+    pub fn incr(&self, key: &str) -> ServerResult<i64> {
+        let reply = self.command(&["INCR", key])?;
+        reply.as_integer().ok_or_else(|| unexpected("INCR", &reply))
+    }
+    
+    /// Expire `key` after `ttl`, returning whether the key exists
+    pub fn expire(&self, key: &str, ttl: Duration) -> ServerResult<bool> {
+        let millis = ttl.as_millis().max(1).to_string();
+        let reply = self.command(&["PEXPIRE", key, millis.as_str()])?;
+        reply.as_integer().map(|set| set == 1).ok_or_else(|| unexpected("PEXPIRE", &reply))
+    }
+    
+    /// Delete `key`, returning whether it existed
+    pub fn del(&self, key: &str) -> ServerResult<bool> {
+        let reply = self.command(&["DEL", key])?;
+        reply.as_integer().map(|deleted| deleted > 0).ok_or_else(|| unexpected("DEL", &reply))
+    }
+}
+
+fn unexpected(command: &str, reply: &RespValue) -> ServerError {
+    ServerError::Protocol(format!("Unexpected reply to {}: {:?}", command, reply))
+}
\ No newline at end of file
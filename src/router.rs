@@ -1,12 +1,55 @@
-use crate::error::ServerResult;
-use crate::http::{Method, Request, Response, Status};
+use crate::cached_response::CachedResponse;
+use crate::error::{ServerError, ServerResult};
+use crate::http::{Method, Request, RequestTarget, Response, Status};
+use crate::openapi::{self, OpenApiInfo, RouteDoc};
+use crate::validation::{self, ParamRule, RouteValidator};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::sync::Arc;
+use std::sync::{Arc, RwLock, Weak};
 use std::fmt;
 
 /// A handler function for processing HTTP requests
 pub type HandlerFn = Arc<dyn Fn(&Request) -> ServerResult<Response> + Send + Sync>;
 
+/// How a request path ending in `/` is matched against a route that doesn't (and vice versa)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TrailingSlash {
+    /// `/users/` and `/users` are the same route
+    #[default]
+    Ignore,
+    /// The trailing slash must agree with the route pattern
+    Strict,
+    /// Answer with a 301 redirect to the form used by the route pattern
+    Redirect,
+}
+
+/// Rules for matching request paths against route patterns
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MatchPolicy {
+    pub trailing_slash: TrailingSlash,
+    /// Compare literal segments ignoring ASCII case; captured parameters keep their case
+    pub case_insensitive: bool,
+}
+
+impl MatchPolicy {
+    /// Create the default policy: trailing slashes ignored, case-sensitive
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Set how trailing slashes are handled
+    pub fn with_trailing_slash(mut self, trailing_slash: TrailingSlash) -> Self {
+        self.trailing_slash = trailing_slash;
+        self
+    }
+    
+    /// Enable or disable case-insensitive matching
+    pub fn with_case_insensitive(mut self, case_insensitive: bool) -> Self {
+        self.case_insensitive = case_insensitive;
+        self
+    }
+}
+
 /// A route entry in the router
 #[derive(Clone)]
 struct RouteEntry {
@@ -18,6 +61,18 @@ struct RouteEntry {
     
     /// The handler function for this route
     handler: HandlerFn,
+    
+    /// Optional documentation for generated API documents
+    doc: Option<RouteDoc>,
+    
+    /// Parameter constraints checked before the handler runs
+    validator: Option<RouteValidator>,
+    
+    /// Precedence among matching routes, from `specificity`
+    specificity: Vec<u8>,
+    
+    /// Matching rules overriding the router's, for routes added through a group
+    policy: Option<MatchPolicy>,
 }
 
 // Custom Debug implementation for RouteEntry since handler can't be automatically derived
@@ -27,6 +82,9 @@ impl fmt::Debug for RouteEntry {
             .field("method", &self.method)
             .field("path", &self.path)
             .field("handler", &"<function>")
+            .field("doc", &self.doc)
+            .field("validator", &self.validator)
+            .field("policy", &self.policy)
             .finish()
     }
 }
@@ -39,6 +97,9 @@ pub struct Router {
     
     /// The handler to use when no route matches
     not_found_handler: HandlerFn,
+    
+    /// Matching rules for routes without their own
+    policy: MatchPolicy,
 }
 
 // Custom Debug implementation for Router
@@ -47,6 +108,7 @@ impl fmt::Debug for Router {
         f.debug_struct("Router")
             .field("routes", &self.routes)
             .field("not_found_handler", &"<function>")
+            .field("policy", &self.policy)
             .finish()
     }
 }
@@ -64,10 +126,18 @@ impl Router {
         Self {
             routes: Vec::new(),
             not_found_handler,
+            policy: MatchPolicy::default(),
         }
     }
     
     /// Add a route to the router
+    ///
+    /// Path parameters may declare a type, as in `/users/:id<u64>`; requests
+    /// whose value doesn't parse get a 422 response without reaching the handler.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a parameter type is not one of `string`, `u64`, `i64`, `f64` or `bool`.
     pub fn add_route<F>(&mut self, method: Method, path: &str, handler: F) -> &mut Self
     where
         F: Fn(&Request) -> ServerResult<Response> + Send + Sync + 'static,
@@ -76,11 +146,64 @@ impl Router {
             method,
             path: path.to_string(),
             handler: Arc::new(handler),
+            doc: None,
+            validator: RouteValidator::from_pattern(path),
+            specificity: specificity(path),
+            policy: None,
         });
         
         self
     }
     
+    /// Set the matching rules used by every route not added through a group with its own
+    pub fn set_match_policy(&mut self, policy: MatchPolicy) -> &mut Self {
+        self.policy = policy;
+        self
+    }
+    
+    /// Start a group of routes sharing a path prefix and, optionally, matching rules
+    ///
+    /// ```ignore
+    /// router
+    ///     .group("/api")
+    ///     .with_policy(MatchPolicy::new().with_trailing_slash(TrailingSlash::Redirect))
+    ///     .get("/users", list_users);
+    /// ```
+    pub fn group(&mut self, prefix: &str) -> RouteGroup<'_> {
+        RouteGroup {
+            router: self,
+            prefix: prefix.trim_end_matches('/').to_string(),
+            policy: None,
+        }
+    }
+    
+    /// Attach documentation to the most recently added route
+    ///
+    /// ```ignore
+    /// router.get("/users/:id", get_user).describe(RouteDoc::new("Fetch a user"));
+    /// ```
+    pub fn describe(&mut self, doc: RouteDoc) -> &mut Self {
+        if let Some(route) = self.routes.last_mut() {
+            route.doc = Some(doc);
+        }
+        self
+    }
+    
+    /// Add a parameter constraint to the most recently added route
+    ///
+    /// ```ignore
+    /// router
+    ///     .get("/articles/:slug", list_articles)
+    ///     .validate(ParamRule::path("slug", ParamType::String).with_pattern("[a-z0-9-]+")?)
+    ///     .validate(ParamRule::query("page", ParamType::U64).with_range(1..=1000));
+    /// ```
+    pub fn validate(&mut self, rule: ParamRule) -> &mut Self {
+        if let Some(route) = self.routes.last_mut() {
+            route.validator.get_or_insert_with(RouteValidator::default).add_rule(rule);
+        }
+        self
+    }
+    
     /// Add a GET route
     pub fn get<F>(&mut self, path: &str, handler: F) -> &mut Self
     where
@@ -113,6 +236,56 @@ impl Router {
         self.add_route(Method::Delete, path, handler)
     }
     
+    /// Add a GET route answering with a fixed response, serialized once
+    ///
+    /// Returns the cached response, whose `invalidate` makes the route
+    /// serialize it again. For content that changes, register
+    /// `CachedResponse::with_builder(..).handler()` instead.
+    pub fn get_cached(&mut self, path: &str, response: Response) -> CachedResponse {
+        let cached = CachedResponse::new(response);
+        self.get(path, cached.handler());
+        cached
+    }
+    
+    /// Remove every route registered for `method` and `path`, returning whether any existed
+    pub fn remove_route(&mut self, method: Method, path: &str) -> bool {
+        let before = self.routes.len();
+        self.routes.retain(|route| !(route.method == method && route.path == path));
+        self.routes.len() != before
+    }
+    
+    /// List the registered routes as (method, path pattern) pairs, in registration order
+    pub fn routes(&self) -> Vec<(Method, String)> {
+        self.routes
+            .iter()
+            .map(|route| (route.method, route.path.clone()))
+            .collect()
+    }
+    
+    /// Iterate over routes with their documentation, in registration order
+    pub(crate) fn documented_routes(&self) -> impl Iterator<Item = (Method, &str, Option<&RouteDoc>, &[ParamRule])> {
+        self.routes.iter().map(|route| {
+            let rules = route.validator.as_ref().map(|v| v.rules()).unwrap_or(&[]);
+            (route.method, route.path.as_str(), route.doc.as_ref(), rules)
+        })
+    }
+    
+    /// Serve an OpenAPI document at `/openapi.json` and Swagger UI at `/docs`
+    ///
+    /// The document is generated from the routes registered so far, so call
+    /// this after adding them. For routes changed at runtime use
+    /// [`RouteTable::mount_openapi`], which regenerates on every request.
+    pub fn serve_openapi(&mut self, info: OpenApiInfo) -> &mut Self {
+        let document = openapi::generate(self, &info);
+        let title = info.title;
+        
+        self.get("/openapi.json", move |_| Ok(openapi::document_response(&document)))
+            .describe(RouteDoc::hidden());
+        self.get("/docs", move |_| Ok(openapi::swagger_ui_response(&title, "/openapi.json")))
+            .describe(RouteDoc::hidden());
+        self
+    }
+    
     /// Set the not found handler
     pub fn set_not_found_handler<F>(&mut self, handler: F) -> &mut Self
     where
@@ -124,86 +297,490 @@ impl Router {
     
     /// Handle a request
     pub fn handle_request(&self, request: &Request) -> ServerResult<Response> {
-        // Simple path matching for now - just exact matches
-        // A more advanced implementation would use a trie or radix tree
+        // Server-wide OPTIONS doesn't target any route
+        if request.target_form == RequestTarget::Asterisk {
+            return Ok(self.server_options_response());
+        }
+        
+        // Among the routes that match, the most specific wins (see `specificity`).
+        // Routes that only match with the trailing slash toggled are kept aside
+        // in case nothing matches outright and their policy asks for a redirect.
+        let path = request.path();
+        let mut best: Option<(&RouteEntry, HashMap<String, String>)> = None;
+        let mut redirect: Option<&RouteEntry> = None;
         for route in &self.routes {
-            if route.method == request.method && self.path_matches(&route.path, &request.uri) {
-                return (route.handler)(request);
+            if route.method != request.method {
+                continue;
+            }
+            
+            let policy = route.policy.unwrap_or(self.policy);
+            let params = match match_pattern(&route.path, path, policy.case_insensitive) {
+                Some(params) => params,
+                None => continue,
+            };
+            
+            if slash_mismatch(&route.path, path) {
+                match policy.trailing_slash {
+                    TrailingSlash::Ignore => {}
+                    TrailingSlash::Strict => continue,
+                    TrailingSlash::Redirect => {
+                        if redirect.is_none_or(|current| route.specificity > current.specificity) {
+                            redirect = Some(route);
+                        }
+                        continue;
+                    }
+                }
             }
+            
+            if best.as_ref().is_none_or(|(current, _)| route.specificity > current.specificity) {
+                best = Some((route, params));
+            }
+        }
+        
+        if let Some((route, params)) = best {
+            if let Some(validator) = &route.validator {
+                let errors = validator.validate(request, &params);
+                if !errors.is_empty() {
+                    return Ok(validation::validation_error_response(&errors));
+                }
+            }
+            return (route.handler)(request);
+        }
+        
+        if redirect.is_some() {
+            return Ok(trailing_slash_redirect(request));
         }
         
         // No route matched, use the not found handler
         (self.not_found_handler)(request)
     }
     
-    /// Check if a path matches a route pattern
-    fn path_matches(&self, pattern: &str, path: &str) -> bool {
-        // Simple matching for now
-        // This could be extended to support path parameters and wildcards
-        
-        // Check for exact match
-        if pattern == path {
-            return true;
+    /// Build the response for `OPTIONS *`, listing every method the router serves
+    fn server_options_response(&self) -> Response {
+        let mut methods: Vec<&'static str> = vec![Method::Options.as_str()];
+        for route in &self.routes {
+            let name = route.method.as_str();
+            if !methods.contains(&name) {
+                methods.push(name);
+            }
         }
         
-        // Check for wildcard match at end (e.g., "/users/*")
-        if pattern.ends_with('*') {
-            let prefix = &pattern[0..pattern.len() - 1];
-            return path.starts_with(prefix);
+        let mut response = Response::new(Status::NoContent);
+        response.set_header("Allow", &methods.join(", "));
+        response.set_header("Content-Length", "0");
+        response
+    }
+    
+    /// Extract path parameters from a request URI based on a route pattern
+    ///
+    /// Returns an empty map if the path doesn't match the pattern.
+    pub fn extract_params(&self, pattern: &str, path: &str) -> HashMap<String, String> {
+        match_pattern(pattern, path, self.policy.case_insensitive).unwrap_or_default()
+    }
+}
+
+/// Match a path against a route pattern, returning the captured parameters
+///
+/// Patterns are made of segments that are either literal, a parameter
+/// (`:id`, optionally typed as `:id<u64>`) or, as the last segment, a named
+/// catch-all (`*path`) capturing one or more remaining segments joined by
+/// `/`. A pattern ending in a bare `*` matches any path with that prefix.
+fn match_pattern(pattern: &str, path: &str, case_insensitive: bool) -> Option<HashMap<String, String>> {
+    let mut params = HashMap::new();
+    let literal_eq = |a: &str, b: &str| if case_insensitive { a.eq_ignore_ascii_case(b) } else { a == b };
+    
+    if literal_eq(pattern, path) {
+        return Some(params);
+    }
+    
+    // Prefix wildcard (e.g. "/users/*")
+    if let Some(prefix) = pattern.strip_suffix('*') {
+        let matches = path.len() >= prefix.len()
+            && path.is_char_boundary(prefix.len())
+            && literal_eq(&path[..prefix.len()], prefix);
+        return matches.then_some(params);
+    }
+    
+    let pattern_segments: Vec<&str> = pattern.split('/').filter(|s| !s.is_empty()).collect();
+    let path_segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    
+    for (i, pattern_seg) in pattern_segments.iter().enumerate() {
+        if let Some(name) = pattern_seg.strip_prefix('*') {
+            // Catch-alls only make sense at the end and need something to capture
+            if i + 1 != pattern_segments.len() || path_segments.len() <= i {
+                return None;
+            }
+            params.insert(name.to_string(), path_segments[i..].join("/"));
+            return Some(params);
         }
         
-        // Check for path parameter match (e.g., "/users/:id")
-        // For simplicity, we'll just check if the segments match in number and non-param segments match exactly
-        let pattern_segments: Vec<&str> = pattern.split('/').filter(|s| !s.is_empty()).collect();
-        let path_segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
-        
-        if pattern_segments.len() != path_segments.len() {
-            return false;
+        let path_seg = path_segments.get(i)?;
+        if let Some(name) = pattern_seg.strip_prefix(':') {
+            let (name, _) = validation::split_typed_param(name);
+            params.insert(name.to_string(), path_seg.to_string());
+        } else if !literal_eq(pattern_seg, path_seg) {
+            return None;
         }
-        
-        for (i, pattern_seg) in pattern_segments.iter().enumerate() {
-            if !pattern_seg.starts_with(':') && pattern_seg != &path_segments[i] {
-                return false;
+    }
+    
+    if pattern_segments.len() != path_segments.len() {
+        return None;
+    }
+    
+    Some(params)
+}
+
+/// Check whether a matched path disagrees with its pattern about a trailing slash
+///
+/// Wildcard patterns accept anything after their prefix, so they never disagree.
+fn slash_mismatch(pattern: &str, path: &str) -> bool {
+    if pattern == "/" || path == "/" {
+        return false;
+    }
+    
+    let last = pattern.rsplit('/').find(|s| !s.is_empty()).unwrap_or("");
+    if last.starts_with('*') || last.ends_with('*') {
+        return false;
+    }
+    
+    pattern.ends_with('/') != path.ends_with('/')
+}
+
+/// Redirect to the request path with its trailing slash added or removed
+fn trailing_slash_redirect(request: &Request) -> Response {
+    let path = request.path();
+    let mut location = match path.strip_suffix('/') {
+        Some(trimmed) => trimmed.to_string(),
+        None => format!("{}/", path),
+    };
+    if let Some(query) = request.uri.find('?').map(|pos| &request.uri[pos..]) {
+        location.push_str(query);
+    }
+    
+    let mut response = Response::new(Status::MovedPermanently);
+    response.set_header("Location", &location);
+    response.set_header("Content-Length", "0");
+    response
+}
+
+/// Rank a pattern so that more specific routes win when several match
+///
+/// Segments score 3 when literal, 2 for parameters and 1 for wildcards, and
+/// patterns compare segment by segment: `/static/exact` beats `/static/:file`,
+/// which beats `/static/*path`, and `/a/b/*rest` beats `/a/*rest`. Routes
+/// with equal rank keep registration order.
+fn specificity(pattern: &str) -> Vec<u8> {
+    pattern
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .map(|segment| {
+            if segment.starts_with('*') || segment.ends_with('*') {
+                1
+            } else if segment.starts_with(':') {
+                2
+            } else {
+                3
             }
-        }
-        
-        true
+        })
+        .collect()
+}
+
+impl Default for Router {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Routes registered under a shared prefix, see [`Router::group`]
+pub struct RouteGroup<'a> {
+    router: &'a mut Router,
+    prefix: String,
+    policy: Option<MatchPolicy>,
+}
+
+impl RouteGroup<'_> {
+    /// Use these matching rules for the group's routes instead of the router's
+    pub fn with_policy(mut self, policy: MatchPolicy) -> Self {
+        self.policy = Some(policy);
+        self
     }
     
-    /// Extract path parameters from a request URI based on a route pattern
-    pub fn extract_params(&self, pattern: &str, path: &str) -> HashMap<String, String> {
-        let mut params = HashMap::new();
+    /// Add a route under the group's prefix
+    pub fn add_route<F>(&mut self, method: Method, path: &str, handler: F) -> &mut Self
+    where
+        F: Fn(&Request) -> ServerResult<Response> + Send + Sync + 'static,
+    {
+        let full_path = if path == "/" && !self.prefix.is_empty() {
+            self.prefix.clone()
+        } else {
+            format!("{}{}", self.prefix, path)
+        };
         
-        // If not a parametrized path, return empty map
-        if !pattern.contains(':') {
-            return params;
+        self.router.add_route(method, &full_path, handler);
+        if let Some(route) = self.router.routes.last_mut() {
+            route.policy = self.policy;
         }
+        self
+    }
+    
+    /// Add a GET route under the group's prefix
+    pub fn get<F>(&mut self, path: &str, handler: F) -> &mut Self
+    where
+        F: Fn(&Request) -> ServerResult<Response> + Send + Sync + 'static,
+    {
+        self.add_route(Method::Get, path, handler)
+    }
+    
+    /// Add a POST route under the group's prefix
+    pub fn post<F>(&mut self, path: &str, handler: F) -> &mut Self
+    where
+        F: Fn(&Request) -> ServerResult<Response> + Send + Sync + 'static,
+    {
+        self.add_route(Method::Post, path, handler)
+    }
+    
+    /// Add a PUT route under the group's prefix
+    pub fn put<F>(&mut self, path: &str, handler: F) -> &mut Self
+    where
+        F: Fn(&Request) -> ServerResult<Response> + Send + Sync + 'static,
+    {
+        self.add_route(Method::Put, path, handler)
+    }
+    
+    /// Add a DELETE route under the group's prefix
+    pub fn delete<F>(&mut self, path: &str, handler: F) -> &mut Self
+    where
+        F: Fn(&Request) -> ServerResult<Response> + Send + Sync + 'static,
+    {
+        self.add_route(Method::Delete, path, handler)
+    }
+    
+    /// Attach documentation to the group's most recently added route
+    pub fn describe(&mut self, doc: RouteDoc) -> &mut Self {
+        self.router.describe(doc);
+        self
+    }
+    
+    /// Add a parameter constraint to the group's most recently added route
+    pub fn validate(&mut self, rule: ParamRule) -> &mut Self {
+        self.router.validate(rule);
+        self
+    }
+}
+
+/// A route table that can be changed while event loops are serving from it
+///
+/// Readers take a cheap snapshot of the current router; writers clone it,
+/// apply their change and swap the new copy in. Requests already being
+/// handled keep the snapshot they started with.
+#[derive(Debug, Default)]
+pub struct RouteTable {
+    current: RwLock<Arc<Router>>,
+}
+
+impl RouteTable {
+    /// Create a route table serving `router`
+    pub fn new(router: Router) -> Self {
+        Self {
+            current: RwLock::new(Arc::new(router)),
+        }
+    }
+    
+    /// Get the router currently in effect
+    pub fn snapshot(&self) -> Arc<Router> {
+        self.current.read().unwrap().clone()
+    }
+    
+    /// Apply a change to a copy of the router and publish it
+    pub fn update<F, T>(&self, change: F) -> T
+    where
+        F: FnOnce(&mut Router) -> T,
+    {
+        let mut current = self.current.write().unwrap();
+        let mut router = Router::clone(&current);
+        let result = change(&mut router);
+        *current = Arc::new(router);
+        result
+    }
+    
+    /// Replace the whole router
+    pub fn replace(&self, router: Router) {
+        *self.current.write().unwrap() = Arc::new(router);
+    }
+    
+    /// Add a route to the live table
+    pub fn add_route<F>(&self, method: Method, path: &str, handler: F)
+    where
+        F: Fn(&Request) -> ServerResult<Response> + Send + Sync + 'static,
+    {
+        self.update(|router| {
+            router.add_route(method, path, handler);
+        });
+    }
+    
+    /// Remove a route from the live table, returning whether it existed
+    pub fn remove_route(&self, method: Method, path: &str) -> bool {
+        self.update(|router| router.remove_route(method, path))
+    }
+    
+    /// List the routes currently in effect
+    pub fn routes(&self) -> Vec<(Method, String)> {
+        self.snapshot().routes()
+    }
+    
+    /// Handle a request with the router currently in effect
+    pub fn handle_request(&self, request: &Request) -> ServerResult<Response> {
+        self.snapshot().handle_request(request)
+    }
+    
+    /// Serve a live OpenAPI document at `/openapi.json` and Swagger UI at `/docs`
+    pub fn mount_openapi(table: &Arc<Self>, info: OpenApiInfo) {
+        let weak: Weak<Self> = Arc::downgrade(table);
+        let title = info.title.clone();
         
-        let pattern_segments: Vec<&str> = pattern.split('/').filter(|s| !s.is_empty()).collect();
-        let path_segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+        table.update(|router| {
+            router
+                .get("/openapi.json", move |_| {
+                    let table = weak.upgrade().ok_or_else(table_gone)?;
+                    Ok(openapi::document_response(&openapi::generate(&table.snapshot(), &info)))
+                })
+                .describe(RouteDoc::hidden());
+            router
+                .get("/docs", move |_| Ok(openapi::swagger_ui_response(&title, "/openapi.json")))
+                .describe(RouteDoc::hidden());
+        });
+    }
+    
+    /// Register admin routes under `prefix` for managing this table over HTTP
+    ///
+    /// - `GET <prefix>` lists the routes as JSON
+    /// - `POST <prefix>` adds a route serving a fixed response, described by a
+    ///   JSON body like `{"method": "GET", "path": "/hello", "status": 200, "body": "hi"}`
+    /// - `DELETE <prefix>?method=GET&path=/hello` removes a route
+    ///
+    /// These routes can change what the server serves, so put them behind
+    /// authentication middleware or a listener that is not publicly reachable.
+    pub fn mount_admin(table: &Arc<Self>, prefix: &str) {
+        // The handlers live inside the table, so hold it weakly to avoid a cycle
+        let list: Weak<Self> = Arc::downgrade(table);
+        let add = list.clone();
+        let remove = list.clone();
         
-        if pattern_segments.len() != path_segments.len() {
-            return params;
-        }
+        table.add_route(Method::Get, prefix, move |_| {
+            let table = list.upgrade().ok_or_else(table_gone)?;
+            let routes: Vec<RouteInfo> = table
+                .routes()
+                .into_iter()
+                .map(|(method, path)| RouteInfo {
+                    method: method.as_str().to_string(),
+                    path,
+                })
+                .collect();
+            json_response(Status::Ok, &serde_json::to_vec(&routes)?)
+        });
         
-        for (i, pattern_seg) in pattern_segments.iter().enumerate() {
-            if pattern_seg.starts_with(':') {
-                let param_name = &pattern_seg[1..];
-                let param_value = path_segments[i];
-                params.insert(param_name.to_string(), param_value.to_string());
+        table.add_route(Method::Post, prefix, move |req| {
+            let table = add.upgrade().ok_or_else(table_gone)?;
+            let spec: StaticRoute = match serde_json::from_slice(&req.body) {
+                Ok(spec) => spec,
+                Err(e) => return error_response(Status::BadRequest, &format!("Invalid route: {}", e)),
+            };
+            let method = match Method::from_str(&spec.method) {
+                Ok(method) => method,
+                Err(e) => return error_response(Status::BadRequest, &e.to_string()),
+            };
+            if !spec.path.starts_with('/') {
+                return error_response(Status::BadRequest, "Route path must start with '/'");
             }
-        }
+            let status = match Status::from_code(spec.status) {
+                Some(status) => status,
+                None => return error_response(Status::BadRequest, &format!("Unsupported status: {}", spec.status)),
+            };
+            
+            let body = serde_json::to_vec(&RouteInfo {
+                method: spec.method.clone(),
+                path: spec.path.clone(),
+            })?;
+            let path = spec.path.clone();
+            table.add_route(method, &path, move |_| Ok(spec.response(status)));
+            json_response(Status::Created, &body)
+        });
         
-        params
+        table.add_route(Method::Delete, prefix, move |req| {
+            let table = remove.upgrade().ok_or_else(table_gone)?;
+            let (method, path) = match (req.query_params.get("method"), req.query_params.get("path")) {
+                (Some(method), Some(path)) => (method, path),
+                _ => return error_response(Status::BadRequest, "Both 'method' and 'path' are required"),
+            };
+            let method = match Method::from_str(method) {
+                Ok(method) => method,
+                Err(e) => return error_response(Status::BadRequest, &e.to_string()),
+            };
+            
+            if table.remove_route(method, path) {
+                let mut response = Response::new(Status::NoContent);
+                response.set_header("Content-Length", "0");
+                Ok(response)
+            } else {
+                error_response(Status::NotFound, "No such route")
+            }
+        });
     }
 }
 
-impl Default for Router {
-    fn default() -> Self {
-        Self::new()
+/// A route as reported by the admin endpoint
+#[derive(Serialize)]
+struct RouteInfo {
+    method: String,
+    path: String,
+}
+
+/// A fixed-response route added through the admin endpoint
+#[derive(Deserialize)]
+struct StaticRoute {
+    method: String,
+    path: String,
+    #[serde(default = "default_static_status")]
+    status: u16,
+    #[serde(default)]
+    body: String,
+    #[serde(default = "default_static_content_type")]
+    content_type: String,
+}
+
+fn default_static_status() -> u16 {
+    200
+}
+
+fn default_static_content_type() -> String {
+    "text/plain".to_string()
+}
+
+impl StaticRoute {
+    fn response(&self, status: Status) -> Response {
+        let mut response = Response::new(status);
+        response.set_body(self.body.as_bytes());
+        response.set_header("Content-Type", &self.content_type);
+        response
     }
 }
 
+fn table_gone() -> ServerError {
+    ServerError::Protocol("Route table is no longer available".to_string())
+}
+
+fn json_response(status: Status, body: &[u8]) -> ServerResult<Response> {
+    let mut response = Response::new(status);
+    response.set_body(body);
+    response.set_header("Content-Type", "application/json");
+    Ok(response)
+}
+
+fn error_response(status: Status, message: &str) -> ServerResult<Response> {
+    let body = serde_json::to_vec(&serde_json::json!({ "error": message }))?;
+    json_response(status, &body)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -292,4 +869,186 @@ mod tests {
         let params = router.extract_params("/users", "/users");
         assert_eq!(params.len(), 0);
     }
+    
+    #[test]
+    fn test_router_absolute_form_target() {
+        let mut router = Router::new();
+        
+        router.get("/users", |req| {
+            let mut response = Response::new(Status::Ok);
+            response.set_body(req.host.as_deref().unwrap_or("").as_bytes());
+            Ok(response)
+        });
+        
+        let request = Request::new(Method::Get, "http://example.com/users?page=2");
+        let response = router.handle_request(&request).unwrap();
+        assert_eq!(response.status, Status::Ok);
+        assert_eq!(response.body, b"example.com");
+    }
+    
+    #[test]
+    fn test_router_options_asterisk() {
+        let mut router = Router::new();
+        
+        router.get("/", |_| Ok(Response::new(Status::Ok)));
+        router.post("/submit", |_| Ok(Response::new(Status::Ok)));
+        
+        let request = Request::new(Method::Options, "*");
+        let response = router.handle_request(&request).unwrap();
+        assert_eq!(response.status, Status::NoContent);
+        assert_eq!(response.headers.get("Allow").unwrap(), "OPTIONS, GET, POST");
+    }
+    
+    #[test]
+    fn test_route_table_hot_update() {
+        let table = RouteTable::new(Router::new());
+        let request = Request::new(Method::Get, "/live");
+        
+        let before = table.snapshot();
+        table.add_route(Method::Get, "/live", |_| Ok(Response::new(Status::Ok)));
+        
+        // Snapshots taken earlier are unaffected; new requests see the route
+        assert_eq!(before.handle_request(&request).unwrap().status, Status::NotFound);
+        assert_eq!(table.handle_request(&request).unwrap().status, Status::Ok);
+        
+        assert!(table.remove_route(Method::Get, "/live"));
+        assert!(!table.remove_route(Method::Get, "/live"));
+        assert_eq!(table.handle_request(&request).unwrap().status, Status::NotFound);
+    }
+    
+    #[test]
+    fn test_route_table_admin_endpoint() {
+        let table = Arc::new(RouteTable::new(Router::new()));
+        RouteTable::mount_admin(&table, "/admin/routes");
+        
+        let mut add = Request::new(Method::Post, "/admin/routes");
+        add.set_body(br#"{"method": "GET", "path": "/hello", "body": "hi"}"#);
+        assert_eq!(table.handle_request(&add).unwrap().status, Status::Created);
+        
+        let response = table.handle_request(&Request::new(Method::Get, "/hello")).unwrap();
+        assert_eq!(response.status, Status::Ok);
+        assert_eq!(response.body, b"hi");
+        
+        let list = table.handle_request(&Request::new(Method::Get, "/admin/routes")).unwrap();
+        let routes: serde_json::Value = serde_json::from_slice(&list.body).unwrap();
+        assert!(routes.as_array().unwrap().iter().any(|route| route["path"] == "/hello"));
+        
+        let mut invalid = Request::new(Method::Post, "/admin/routes");
+        invalid.set_body(br#"{"method": "FETCH", "path": "/x"}"#);
+        assert_eq!(table.handle_request(&invalid).unwrap().status, Status::BadRequest);
+        
+        let remove = Request::new(Method::Delete, "/admin/routes?method=GET&path=/hello");
+        assert_eq!(table.handle_request(&remove).unwrap().status, Status::NoContent);
+        let response = table.handle_request(&Request::new(Method::Get, "/hello")).unwrap();
+        assert_eq!(response.status, Status::NotFound);
+    }
+    
+    #[test]
+    fn test_route_table_admin_content_type() {
+        let table = Arc::new(RouteTable::new(Router::new()));
+        RouteTable::mount_admin(&table, "/admin/routes");
+        
+        let mut add = Request::new(Method::Post, "/admin/routes");
+        add.set_body(br#"{"method": "GET", "path": "/page", "body": "<p>hi</p>", "content_type": "text/html"}"#);
+        let response = table.handle_request(&add).unwrap();
+        assert_eq!(response.headers.get("Content-Type").unwrap(), "application/json");
+        
+        let response = table.handle_request(&Request::new(Method::Get, "/page")).unwrap();
+        assert_eq!(response.headers.get("Content-Type").unwrap(), "text/html");
+    }
+    
+    #[test]
+    fn test_router_catch_all_capture() {
+        let router = Router::new();
+        
+        let params = router.extract_params("/static/*path", "/static/css/site.css");
+        assert_eq!(params.get("path").unwrap(), "css/site.css");
+        
+        // A catch-all needs at least one segment to capture
+        assert!(router.extract_params("/static/*path", "/static").is_empty());
+        assert!(router.extract_params("/static/*path", "/assets/app.js").is_empty());
+    }
+    
+    #[test]
+    fn test_router_specificity() {
+        let mut router = Router::new();
+        
+        // Registered from least to most specific on purpose
+        router.get("/static/*path", |_| {
+            let mut response = Response::new(Status::Ok);
+            response.set_body(b"catch-all");
+            Ok(response)
+        });
+        router.get("/static/:file", |_| {
+            let mut response = Response::new(Status::Ok);
+            response.set_body(b"param");
+            Ok(response)
+        });
+        router.get("/static/exact", |_| {
+            let mut response = Response::new(Status::Ok);
+            response.set_body(b"exact");
+            Ok(response)
+        });
+        router.get("/static/css/*path", |_| {
+            let mut response = Response::new(Status::Ok);
+            response.set_body(b"css");
+            Ok(response)
+        });
+        
+        let body = |uri: &str| router.handle_request(&Request::new(Method::Get, uri)).unwrap().body;
+        assert_eq!(body("/static/exact"), b"exact");
+        assert_eq!(body("/static/logo.png"), b"param");
+        assert_eq!(body("/static/img/logo.png"), b"catch-all");
+        assert_eq!(body("/static/css/site.css"), b"css");
+    }
+    
+    #[test]
+    fn test_router_trailing_slash_policies() {
+        let mut router = Router::new();
+        router.get("/users", |_| Ok(Response::new(Status::Ok)));
+        router
+            .group("/strict")
+            .with_policy(MatchPolicy::new().with_trailing_slash(TrailingSlash::Strict))
+            .get("/items", |_| Ok(Response::new(Status::Ok)));
+        router
+            .group("/api")
+            .with_policy(MatchPolicy::new().with_trailing_slash(TrailingSlash::Redirect))
+            .get("/orders/", |_| Ok(Response::new(Status::Ok)));
+        
+        let status = |uri: &str| router.handle_request(&Request::new(Method::Get, uri)).unwrap().status;
+        
+        // The default policy ignores the trailing slash
+        assert_eq!(status("/users/"), Status::Ok);
+        
+        assert_eq!(status("/strict/items"), Status::Ok);
+        assert_eq!(status("/strict/items/"), Status::NotFound);
+        
+        assert_eq!(status("/api/orders/"), Status::Ok);
+        let response = router.handle_request(&Request::new(Method::Get, "/api/orders?page=2")).unwrap();
+        assert_eq!(response.status, Status::MovedPermanently);
+        assert_eq!(response.headers.get("Location").unwrap(), "/api/orders/?page=2");
+    }
+    
+    #[test]
+    fn test_router_case_insensitive_policy() {
+        let mut router = Router::new();
+        router.set_match_policy(MatchPolicy::new().with_case_insensitive(true));
+        router.get("/Users/:name", |req| {
+            let mut response = Response::new(Status::Ok);
+            response.set_body(req.path().as_bytes());
+            Ok(response)
+        });
+        router
+            .group("/docs")
+            .with_policy(MatchPolicy::new())
+            .get("/Guide", |_| Ok(Response::new(Status::Ok)));
+        
+        let response = router.handle_request(&Request::new(Method::Get, "/users/Ada")).unwrap();
+        assert_eq!(response.status, Status::Ok);
+        assert_eq!(router.extract_params("/Users/:name", "/USERS/Ada").get("name").unwrap(), "Ada");
+        
+        // The group's own policy stays case-sensitive
+        let response = router.handle_request(&Request::new(Method::Get, "/docs/guide")).unwrap();
+        assert_eq!(response.status, Status::NotFound);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,199 @@
+//! Byte scanning for the HTTP parser
+//!
+//! Finding the end of a request head and the colon of each header line,
+//! and checking that header bytes are allowed, are the parser's inner
+//! loops. Searches go through `memchr`, which picks SSE2, AVX2 or NEON
+//! code for the CPU at runtime. Validation checks 16 bytes at a time with
+//! SSE2 or NEON when the CPU has them, and falls back to `scalar` when it
+//! doesn't.
+
+/// Find where a request head ends, at the start of its blank line
+pub fn find_head_end(data: &[u8]) -> Option<usize> {
+    memchr::memmem::find(data, b"\r\n\r\n")
+}
+
+/// Find the first occurrence of `byte`
+pub fn find_byte(byte: u8, data: &[u8]) -> Option<usize> {
+    memchr::memchr(byte, data)
+}
+
+/// Check whether `bytes` is a token, as header names must be (RFC 9110 section 5.6.2)
+pub fn is_token(bytes: &[u8]) -> bool {
+    #[cfg(target_arch = "x86_64")]
+    if std::is_x86_feature_detected!("sse2") {
+        return !bytes.is_empty() && unsafe { sse2::all_token(bytes) };
+    }
+    #[cfg(target_arch = "aarch64")]
+    if std::arch::is_aarch64_feature_detected!("neon") {
+        return !bytes.is_empty() && unsafe { neon::all_token(bytes) };
+    }
+    scalar::is_token(bytes)
+}
+
+/// Check whether `bytes` holds only visible characters, spaces, tabs and obs-text
+pub fn is_header_value(bytes: &[u8]) -> bool {
+    #[cfg(target_arch = "x86_64")]
+    if std::is_x86_feature_detected!("sse2") {
+        return unsafe { sse2::all_header_value(bytes) };
+    }
+    #[cfg(target_arch = "aarch64")]
+    if std::arch::is_aarch64_feature_detected!("neon") {
+        return unsafe { neon::all_header_value(bytes) };
+    }
+    scalar::is_header_value(bytes)
+}
+
+/// Check whether this CPU takes the vectorized validation path
+pub fn simd_available() -> bool {
+    #[cfg(target_arch = "x86_64")]
+    if std::is_x86_feature_detected!("sse2") {
+        return true;
+    }
+    #[cfg(target_arch = "aarch64")]
+    if std::arch::is_aarch64_feature_detected!("neon") {
+        return true;
+    }
+    false
+}
+
+/// The byte-at-a-time versions, used on CPUs without SIMD and as a baseline
+pub mod scalar {
+    /// Find where a request head ends, at the start of its blank line
+    pub fn find_head_end(data: &[u8]) -> Option<usize> {
+        data.windows(4).position(|window| window == b"\r\n\r\n")
+    }
+    
+    /// Find the first occurrence of `byte`
+    pub fn find_byte(byte: u8, data: &[u8]) -> Option<usize> {
+        data.iter().position(|&b| b == byte)
+    }
+    
+    /// Check whether `bytes` is a token
+    pub fn is_token(bytes: &[u8]) -> bool {
+        !bytes.is_empty() && bytes.iter().all(|&byte| is_token_byte(byte))
+    }
+    
+    /// Check whether `bytes` holds only visible characters, spaces, tabs and obs-text
+    pub fn is_header_value(bytes: &[u8]) -> bool {
+        bytes.iter().all(|&byte| is_header_value_byte(byte))
+    }
+    
+    /// Check whether a byte may appear in a token
+    pub fn is_token_byte(byte: u8) -> bool {
+        byte.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&byte)
+    }
+    
+    /// Check whether a byte may appear in a header value
+    pub fn is_header_value_byte(byte: u8) -> bool {
+        byte == b'\t' || (byte >= 0x20 && byte != 0x7f)
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod sse2 {
+    use super::scalar;
+    use std::arch::x86_64::*;
+    
+    /// Check a token 16 bytes at a time
+    ///
+    /// Only letters, digits and '-' are matched in vector registers, since
+    /// they make up nearly every real header name; a chunk holding anything
+    /// else is checked byte by byte.
+    ///
+    /// # Safety
+    ///
+    /// The CPU must support SSE2.
+    #[target_feature(enable = "sse2")]
+    pub(super) unsafe fn all_token(bytes: &[u8]) -> bool {
+        let mut chunks = bytes.chunks_exact(16);
+        for chunk in &mut chunks {
+            let v = _mm_loadu_si128(chunk.as_ptr() as *const __m128i);
+            let letter = in_range(_mm_or_si128(v, _mm_set1_epi8(0x20)), b'a', b'z');
+            let digit = in_range(v, b'0', b'9');
+            let dash = _mm_cmpeq_epi8(v, _mm_set1_epi8(b'-' as i8));
+            let common = _mm_or_si128(_mm_or_si128(letter, digit), dash);
+            if _mm_movemask_epi8(common) != 0xffff && !chunk.iter().all(|&byte| scalar::is_token_byte(byte)) {
+                return false;
+            }
+        }
+        chunks.remainder().iter().all(|&byte| scalar::is_token_byte(byte))
+    }
+    
+    /// Check a header value 16 bytes at a time
+    ///
+    /// # Safety
+    ///
+    /// The CPU must support SSE2.
+    #[target_feature(enable = "sse2")]
+    pub(super) unsafe fn all_header_value(bytes: &[u8]) -> bool {
+        let mut chunks = bytes.chunks_exact(16);
+        for chunk in &mut chunks {
+            let v = _mm_loadu_si128(chunk.as_ptr() as *const __m128i);
+            // Compares are signed, so obs-text (0x80 and up) is excluded from controls by the first one
+            let control = _mm_and_si128(_mm_cmpgt_epi8(v, _mm_set1_epi8(-1)), _mm_cmplt_epi8(v, _mm_set1_epi8(0x20)));
+            let tab = _mm_cmpeq_epi8(v, _mm_set1_epi8(b'\t' as i8));
+            let delete = _mm_cmpeq_epi8(v, _mm_set1_epi8(0x7f));
+            if _mm_movemask_epi8(_mm_or_si128(_mm_andnot_si128(tab, control), delete)) != 0 {
+                return false;
+            }
+        }
+        chunks.remainder().iter().all(|&byte| scalar::is_header_value_byte(byte))
+    }
+    
+    /// Mark the lanes holding a byte in `low..=high`, both below 0x80
+    #[target_feature(enable = "sse2")]
+    unsafe fn in_range(v: __m128i, low: u8, high: u8) -> __m128i {
+        _mm_and_si128(
+            _mm_cmpgt_epi8(v, _mm_set1_epi8(low as i8 - 1)),
+            _mm_cmplt_epi8(v, _mm_set1_epi8(high as i8 + 1)),
+        )
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod neon {
+    use super::scalar;
+    use std::arch::aarch64::*;
+    
+    /// Check a token 16 bytes at a time, like the SSE2 version
+    ///
+    /// # Safety
+    ///
+    /// The CPU must support NEON.
+    #[target_feature(enable = "neon")]
+    pub(super) unsafe fn all_token(bytes: &[u8]) -> bool {
+        let mut chunks = bytes.chunks_exact(16);
+        for chunk in &mut chunks {
+            let v = vld1q_u8(chunk.as_ptr());
+            let lower = vorrq_u8(v, vdupq_n_u8(0x20));
+            let letter = vcleq_u8(vsubq_u8(lower, vdupq_n_u8(b'a')), vdupq_n_u8(b'z' - b'a'));
+            let digit = vcleq_u8(vsubq_u8(v, vdupq_n_u8(b'0')), vdupq_n_u8(b'9' - b'0'));
+            let dash = vceqq_u8(v, vdupq_n_u8(b'-'));
+            let common = vorrq_u8(vorrq_u8(letter, digit), dash);
+            if vminvq_u8(common) != 0xff && !chunk.iter().all(|&byte| scalar::is_token_byte(byte)) {
+                return false;
+            }
+        }
+        chunks.remainder().iter().all(|&byte| scalar::is_token_byte(byte))
+    }
+    
+    /// Check a header value 16 bytes at a time
+    ///
+    /// # Safety
+    ///
+    /// The CPU must support NEON.
+    #[target_feature(enable = "neon")]
+    pub(super) unsafe fn all_header_value(bytes: &[u8]) -> bool {
+        let mut chunks = bytes.chunks_exact(16);
+        for chunk in &mut chunks {
+            let v = vld1q_u8(chunk.as_ptr());
+            let control = vcltq_u8(v, vdupq_n_u8(0x20));
+            let tab = vceqq_u8(v, vdupq_n_u8(b'\t'));
+            let delete = vceqq_u8(v, vdupq_n_u8(0x7f));
+            if vmaxvq_u8(vorrq_u8(vbicq_u8(control, tab), delete)) != 0 {
+                return false;
+            }
+        }
+        chunks.remainder().iter().all(|&byte| scalar::is_header_value_byte(byte))
+    }
+}
\ No newline at end of file
@@ -0,0 +1,536 @@
+//! Background jobs run on a schedule
+//!
+//! Jobs such as pruning caches, flushing metrics or checking certificates
+//! are registered with a `Scheduler` and run one after another on its own
+//! thread, either every fixed interval or at times given by a cron
+//! expression. Cron times are in UTC.
+
+use crate::error::{ServerError, ServerResult};
+use crate::metrics::MetricsRegistry;
+use crate::preconditions::{civil_from_days, days_from_civil};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Most missed runs made up for at once with `MissedRuns::RunAll`
+const MAX_CATCH_UP: usize = 100;
+
+/// How late a run may start before `MissedRuns::Skip` drops it
+const LATE_GRACE: Duration = Duration::from_secs(1);
+
+/// Minutes searched for the next cron time before giving up (about eight years)
+const MAX_CRON_STEPS: usize = 100_000;
+
+/// A set of allowed values for one cron field, as a bit per value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct CronField(u64);
+
+impl CronField {
+    fn contains(self, value: u32) -> bool {
+        self.0 & (1 << value) != 0
+    }
+    
+    /// Parse one field, such as `*/15`, `1-5` or `MON,WED`, over `min..=max`
+    fn parse(field: &str, min: u32, max: u32, names: &[&str]) -> Option<Self> {
+        let value = |text: &str| -> Option<u32> {
+            let named = names.iter().position(|name| name.eq_ignore_ascii_case(text)).map(|i| i as u32 + min);
+            named.or_else(|| text.parse().ok()).filter(|value| (min..=max).contains(value))
+        };
+        let mut bits = 0u64;
+        for part in field.split(',') {
+            let (range, step) = match part.split_once('/') {
+                Some((range, step)) => (range, step.parse::<u32>().ok().filter(|&step| step > 0)?),
+                None => (part, 1),
+            };
+            let (start, end) = match range {
+                "*" => (min, max),
+                _ => match range.split_once('-') {
+                    Some((start, end)) => (value(start)?, value(end)?),
+                    // `5/15` runs from 5 to the end
+                    None if part.contains('/') => (value(range)?, max),
+                    None => (value(range)?, value(range)?),
+                },
+            };
+            if start > end {
+                return None;
+            }
+            for value in (start..=end).step_by(step as usize) {
+                bits |= 1 << value;
+            }
+        }
+        Some(Self(bits))
+    }
+}
+
+/// A parsed cron expression
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cron {
+    minute: CronField,
+    hour: CronField,
+    day: CronField,
+    month: CronField,
+    weekday: CronField,
+    /// Whether the day of month or day of week was left as `*`, which
+    /// decides how the two combine
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl Cron {
+    /// Parse a five-field expression (`minute hour day month weekday`) or a
+    /// shortcut such as `@daily`
+    ///
+    /// Fields take `*`, numbers, ranges (`1-5`), steps (`*/15`, `0-30/10`)
+    /// and lists (`1,15`). Months and weekdays also take names (`JAN`,
+    /// `MON`), and Sunday is both 0 and 7. As in standard cron, when both
+    /// the day of month and the day of week are given, either may match.
+    pub fn parse(expression: &str) -> ServerResult<Self> {
+        let expanded = match expression.trim() {
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            "@monthly" => "0 0 1 * *",
+            "@weekly" => "0 0 * * 0",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@hourly" => "0 * * * *",
+            other => other,
+        };
+        let invalid = || ServerError::Config(format!("Invalid cron expression {:?}", expression));
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(invalid());
+        }
+        
+        const MONTHS: [&str; 12] = ["JAN", "FEB", "MAR", "APR", "MAY", "JUN", "JUL", "AUG", "SEP", "OCT", "NOV", "DEC"];
+        const WEEKDAYS: [&str; 7] = ["SUN", "MON", "TUE", "WED", "THU", "FRI", "SAT"];
+        let mut weekday = CronField::parse(fields[4], 0, 7, &WEEKDAYS).ok_or_else(invalid)?;
+        if weekday.contains(7) {
+            weekday.0 |= 1;
+        }
+        Ok(Self {
+            minute: CronField::parse(fields[0], 0, 59, &[]).ok_or_else(invalid)?,
+            hour: CronField::parse(fields[1], 0, 23, &[]).ok_or_else(invalid)?,
+            day: CronField::parse(fields[2], 1, 31, &[]).ok_or_else(invalid)?,
+            month: CronField::parse(fields[3], 1, 12, &MONTHS).ok_or_else(invalid)?,
+            weekday,
+            any_day: fields[2] == "*",
+            any_weekday: fields[4] == "*",
+        })
+    }
+    
+    fn day_matches(&self, day: u32, weekday: u32) -> bool {
+        match (self.any_day, self.any_weekday) {
+            (false, false) => self.day.contains(day) || self.weekday.contains(weekday),
+            _ => self.day.contains(day) && self.weekday.contains(weekday),
+        }
+    }
+    
+    /// Get the first matching minute after `time`, or None if there is none in the next few years
+    pub fn next_after(&self, time: SystemTime) -> Option<SystemTime> {
+        let secs = time.duration_since(UNIX_EPOCH).ok()?.as_secs() as i64;
+        let mut minute = secs.div_euclid(60) + 1;
+        for _ in 0..MAX_CRON_STEPS {
+            let days = minute.div_euclid(1440);
+            let (year, month, day) = civil_from_days(days);
+            let hour = (minute.rem_euclid(1440) / 60) as u32;
+            // The epoch fell on a Thursday
+            let weekday = (days + 4).rem_euclid(7) as u32;
+            
+            if !self.month.contains(month) {
+                let (year, month) = if month == 12 { (year + 1, 1) } else { (year, month + 1) };
+                minute = days_from_civil(year, month, 1) * 1440;
+            } else if !self.day_matches(day, weekday) {
+                minute = (days + 1) * 1440;
+            } else if !self.hour.contains(hour) {
+                minute = minute - minute.rem_euclid(60) + 60;
+            } else if !self.minute.contains(minute.rem_euclid(60) as u32) {
+                minute += 1;
+            } else {
+                return Some(UNIX_EPOCH + Duration::from_secs(minute as u64 * 60));
+            }
+        }
+        None
+    }
+}
+
+/// When a job runs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Schedule {
+    /// Every interval, starting one interval after the job is added
+    Every(Duration),
+    
+    /// At the times a cron expression matches
+    Cron(Cron),
+}
+
+impl Schedule {
+    /// Run every `interval`
+    pub fn every(interval: Duration) -> Self {
+        Schedule::Every(interval.max(Duration::from_millis(1)))
+    }
+    
+    /// Run at the times `expression` matches; see `Cron::parse`
+    pub fn cron(expression: &str) -> ServerResult<Self> {
+        Cron::parse(expression).map(Schedule::Cron)
+    }
+    
+    /// Get the first run time after `time`
+    pub fn next_after(&self, time: SystemTime) -> Option<SystemTime> {
+        match self {
+            Schedule::Every(interval) => Some(time + *interval),
+            Schedule::Cron(cron) => cron.next_after(time),
+        }
+    }
+}
+
+/// What to do about runs that came due while the job couldn't run, such as
+/// while an earlier run or another job overran or the machine was asleep
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MissedRuns {
+    /// Drop them; a run is only made if it would start within a second of
+    /// its time
+    Skip,
+    
+    /// Make a single run for all of them
+    #[default]
+    RunOnce,
+    
+    /// Make every one of them, back to back (at most 100)
+    RunAll,
+}
+
+type Task = Box<dyn FnMut() -> ServerResult<()> + Send>;
+
+/// A task and when to run it
+pub struct Job {
+    name: String,
+    schedule: Schedule,
+    task: Task,
+    jitter: Duration,
+    missed: MissedRuns,
+}
+
+impl Job {
+    /// Create a job running `task` on `schedule`
+    ///
+    /// Errors and panics from the task are logged and counted, and don't
+    /// stop later runs.
+    pub fn new<F>(name: &str, schedule: Schedule, task: F) -> Self
+    where
+        F: FnMut() -> ServerResult<()> + Send + 'static,
+    {
+        Self {
+            name: name.to_string(),
+            schedule,
+            task: Box::new(task),
+            jitter: Duration::ZERO,
+            missed: MissedRuns::default(),
+        }
+    }
+    
+    /// Delay each run by a random time up to `jitter`, so replicas don't all run at once
+    pub fn with_jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+    
+    /// Handle missed runs as `policy` says
+    pub fn with_missed_runs(mut self, policy: MissedRuns) -> Self {
+        self.missed = policy;
+        self
+    }
+}
+
+impl std::fmt::Debug for Job {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Job")
+            .field("name", &self.name)
+            .field("schedule", &self.schedule)
+            .field("jitter", &self.jitter)
+            .field("missed", &self.missed)
+            .finish()
+    }
+}
+
+/// A job and its next run
+struct Entry {
+    job: Job,
+    /// When the next run is scheduled, before jitter
+    next: SystemTime,
+    /// When the next run starts, after jitter
+    run_at: SystemTime,
+}
+
+struct Shared {
+    entries: Mutex<Vec<Entry>>,
+    wake: Condvar,
+    running: AtomicBool,
+    rng: AtomicU64,
+    metrics: Option<Arc<MetricsRegistry>>,
+}
+
+impl Shared {
+    /// Get a random delay up to `jitter`
+    fn jitter(&self, jitter: Duration) -> Duration {
+        if jitter.is_zero() {
+            return Duration::ZERO;
+        }
+        // xorshift; the jitter only has to spread replicas out
+        let mut x = self.rng.load(Ordering::Relaxed);
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.rng.store(x, Ordering::Relaxed);
+        jitter.mul_f64((x % 10_000) as f64 / 10_000.0)
+    }
+    
+    fn counter(&self, job: &str, name: &str, value: usize) {
+        if let Some(metrics) = &self.metrics {
+            if value > 0 {
+                metrics.counter(&format!("scheduler.{}.{}", job, name)).increment(value);
+            }
+        }
+    }
+}
+
+/// Runs jobs on a dedicated thread
+///
+/// Jobs run one at a time, so a slow job delays the others; hand long work
+/// to a thread of its own from the task. With metrics, each job counts
+/// `scheduler.<name>.runs`, `.failures` and `.missed`, and records
+/// `scheduler.<name>.duration_us`.
+///
+/// ```no_run
+/// # use high_performance_server::scheduler::{Job, Schedule, Scheduler};
+/// # use std::time::Duration;
+/// let scheduler = Scheduler::new();
+/// scheduler.add(Job::new("prune", Schedule::cron("*/5 * * * *")?, || Ok(())))?;
+/// scheduler.add(Job::new("flush", Schedule::every(Duration::from_secs(10)), || Ok(())))?;
+/// scheduler.start();
+/// # Ok::<(), high_performance_server::ServerError>(())
+/// ```
+pub struct Scheduler {
+    shared: Arc<Shared>,
+    thread: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::build(None)
+    }
+}
+
+impl Scheduler {
+    /// Create a scheduler with no jobs; call `start` to begin running them
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Create a scheduler recording job metrics in `registry`
+    pub fn with_metrics(registry: Arc<MetricsRegistry>) -> Self {
+        Self::build(Some(registry))
+    }
+    
+    fn build(metrics: Option<Arc<MetricsRegistry>>) -> Self {
+        let seed = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |now| now.as_nanos() as u64);
+        Self {
+            shared: Arc::new(Shared {
+                entries: Mutex::new(Vec::new()),
+                wake: Condvar::new(),
+                running: AtomicBool::new(false),
+                rng: AtomicU64::new(seed | 1),
+                metrics,
+            }),
+            thread: Mutex::new(None),
+        }
+    }
+    
+    /// Add a job, replacing any job of the same name
+    ///
+    /// Fails if the schedule never comes due.
+    pub fn add(&self, job: Job) -> ServerResult<()> {
+        let next = job
+            .schedule
+            .next_after(SystemTime::now())
+            .ok_or_else(|| ServerError::Config(format!("Job {} is never scheduled to run", job.name)))?;
+        let run_at = next + self.shared.jitter(job.jitter);
+        let mut entries = self.shared.entries.lock().unwrap();
+        entries.retain(|entry| entry.job.name != job.name);
+        entries.push(Entry { job, next, run_at });
+        self.shared.wake.notify_all();
+        Ok(())
+    }
+    
+    /// Remove the job called `name`, returning whether there was one
+    pub fn remove(&self, name: &str) -> bool {
+        let mut entries = self.shared.entries.lock().unwrap();
+        let before = entries.len();
+        entries.retain(|entry| entry.job.name != name);
+        entries.len() != before
+    }
+    
+    /// Get when the job called `name` next runs, jitter included
+    pub fn next_run(&self, name: &str) -> Option<SystemTime> {
+        let entries = self.shared.entries.lock().unwrap();
+        entries.iter().find(|entry| entry.job.name == name).map(|entry| entry.run_at)
+    }
+    
+    /// Run the jobs due at `now` on this thread, returning the number of runs made
+    ///
+    /// The scheduler thread does this on its own; call it directly to drive
+    /// a scheduler that wasn't started, such as from another loop or a test.
+    pub fn run_due(&self, now: SystemTime) -> usize {
+        run_due(&self.shared, now)
+    }
+    
+    /// Start running jobs on the scheduler thread, if it isn't running already
+    pub fn start(&self) {
+        let mut thread = self.thread.lock().unwrap();
+        if thread.is_some() {
+            return;
+        }
+        self.shared.running.store(true, Ordering::SeqCst);
+        let shared = self.shared.clone();
+        let handle = thread::Builder::new().name("scheduler".to_string()).spawn(move || {
+            while shared.running.load(Ordering::SeqCst) {
+                run_due(&shared, SystemTime::now());
+                wait_for_next(&shared);
+            }
+        });
+        match handle {
+            Ok(handle) => *thread = Some(handle),
+            Err(e) => log::error!("Failed to start the scheduler thread: {}", e),
+        }
+    }
+    
+    /// Stop the scheduler thread once any running job finishes, and wait for it
+    pub fn stop(&self) {
+        self.shared.running.store(false, Ordering::SeqCst);
+        {
+            let _entries = self.shared.entries.lock().unwrap();
+            self.shared.wake.notify_all();
+        }
+        if let Some(thread) = self.thread.lock().unwrap().take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for Scheduler {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Sleep until the earliest job is due, a job is added or the scheduler stops
+fn wait_for_next(shared: &Shared) {
+    let entries = shared.entries.lock().unwrap();
+    if !shared.running.load(Ordering::SeqCst) {
+        return;
+    }
+    let now = SystemTime::now();
+    let timeout = entries
+        .iter()
+        .map(|entry| entry.run_at.duration_since(now).unwrap_or_default())
+        .min()
+        // Wake now and then even when idle, in case the clock jumped
+        .unwrap_or(Duration::from_secs(60))
+        .min(Duration::from_secs(60));
+    if !timeout.is_zero() {
+        let _ = shared.wake.wait_timeout(entries, timeout);
+    }
+}
+
+fn run_due(shared: &Shared, now: SystemTime) -> usize {
+    // Jobs are taken out while they run so adding or removing jobs never waits on one
+    let due: Vec<(Entry, usize)> = {
+        let mut entries = shared.entries.lock().unwrap();
+        let mut due = Vec::new();
+        let mut i = 0;
+        while i < entries.len() {
+            if entries[i].run_at <= now {
+                let mut entry = entries.swap_remove(i);
+                let runs = plan_runs(shared, &mut entry, now);
+                due.push((entry, runs));
+            } else {
+                i += 1;
+            }
+        }
+        due
+    };
+    
+    let mut total = 0;
+    for (mut entry, runs) in due {
+        for _ in 0..runs {
+            run_job(shared, &mut entry.job);
+        }
+        total += runs;
+        let mut entries = shared.entries.lock().unwrap();
+        // A job added under the same name while this one ran replaces it
+        if !entries.iter().any(|other| other.job.name == entry.job.name) {
+            entries.push(entry);
+        }
+    }
+    total
+}
+
+/// Work out how many runs a due job makes and move it on to its next time
+fn plan_runs(shared: &Shared, entry: &mut Entry, now: SystemTime) -> usize {
+    let schedule = &entry.job.schedule;
+    let mut latest = entry.next;
+    let mut occurrences = 1;
+    let mut upcoming = schedule.next_after(latest);
+    while let Some(time) = upcoming.filter(|&time| time <= now) {
+        if occurrences >= MAX_CATCH_UP {
+            // Far behind: count the rest without visiting each one
+            if let Schedule::Every(interval) = schedule {
+                let behind = now.duration_since(time).unwrap_or_default();
+                occurrences += (behind.as_nanos() / interval.as_nanos()) as usize;
+            }
+            latest = now;
+            upcoming = schedule.next_after(now);
+            break;
+        }
+        occurrences += 1;
+        latest = time;
+        upcoming = schedule.next_after(time);
+    }
+    
+    let runs = match entry.job.missed {
+        MissedRuns::Skip => usize::from(now.duration_since(latest).unwrap_or_default() <= LATE_GRACE),
+        MissedRuns::RunOnce => 1,
+        MissedRuns::RunAll => occurrences.min(MAX_CATCH_UP),
+    };
+    shared.counter(&entry.job.name, "missed", occurrences - runs);
+    if occurrences > 1 {
+        log::debug!("Job {} missed {} runs", entry.job.name, occurrences - 1);
+    }
+    
+    // A cron schedule that has run out is left far in the future
+    entry.next = upcoming.unwrap_or(now + Duration::from_secs(100 * 365 * 86_400));
+    entry.run_at = entry.next + shared.jitter(entry.job.jitter);
+    runs
+}
+
+fn run_job(shared: &Shared, job: &mut Job) {
+    let start = Instant::now();
+    let result = panic::catch_unwind(AssertUnwindSafe(|| (job.task)()));
+    let failed = match result {
+        Ok(Ok(())) => false,
+        Ok(Err(e)) => {
+            log::warn!("Scheduled job {} failed: {}", job.name, e);
+            true
+        }
+        Err(_) => {
+            log::error!("Scheduled job {} panicked", job.name);
+            true
+        }
+    };
+    shared.counter(&job.name, "runs", 1);
+    shared.counter(&job.name, "failures", usize::from(failed));
+    if let Some(metrics) = &shared.metrics {
+        metrics
+            .exponential_histogram(&format!("scheduler.{}.duration_us", job.name), 1.0, 2.0, 24)
+            .record(start.elapsed().as_micros() as f64);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,124 @@
+//! Secret config values kept out of the config file
+//!
+//! A secret-bearing value can be written as `@file:/run/secrets/api-key`
+//! to read it from a file, or `@env:API_KEY` to read it from the
+//! environment, so the main config file can be checked in or shared. The
+//! value is resolved whenever the config is loaded, so reloading the config
+//! picks up a rotated secret.
+
+use crate::error::{ServerError, ServerResult};
+use crate::redaction::REDACTED;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::env;
+use std::fmt;
+use std::fs;
+
+/// A config value that may come from a file or an environment variable
+///
+/// Serializes back to the reference it was loaded from rather than the
+/// secret itself, and never shows the secret in `Debug` output.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct Secret {
+    /// What the config said: a literal, or an `@file:` or `@env:` reference
+    source: String,
+    value: String,
+}
+
+impl Secret {
+    /// Resolve a config value
+    ///
+    /// `@file:PATH` reads the file, dropping one trailing newline, and
+    /// `@env:NAME` reads the variable. A value starting with `@@` stands for
+    /// itself minus the first `@`; any other value starting with `@` is an
+    /// error, to catch misspelt references.
+    pub fn resolve(source: &str) -> ServerResult<Self> {
+        let value = if let Some(path) = source.strip_prefix("@file:") {
+            let mut value = fs::read_to_string(path)
+                .map_err(|e| ServerError::Config(format!("Secret file {} could not be read: {}", path, e)))?;
+            if value.ends_with('\n') {
+                value.pop();
+                if value.ends_with('\r') {
+                    value.pop();
+                }
+            }
+            value
+        } else if let Some(name) = source.strip_prefix("@env:") {
+            env::var(name).map_err(|_| ServerError::Config(format!("Environment variable {} is not set", name)))?
+        } else if let Some(literal) = source.strip_prefix('@') {
+            if !literal.starts_with('@') {
+                return Err(ServerError::Config(format!(
+                    "Unknown secret reference {:?}; use @file:PATH, @env:NAME, or @@ for a leading @",
+                    source
+                )));
+            }
+            literal.to_string()
+        } else {
+            source.to_string()
+        };
+        Ok(Self {
+            source: source.to_string(),
+            value,
+        })
+    }
+    
+    /// Wrap a value given directly rather than loaded from config
+    pub fn literal(value: &str) -> Self {
+        let source = if value.starts_with('@') {
+            format!("@{}", value)
+        } else {
+            value.to_string()
+        };
+        Self {
+            source,
+            value: value.to_string(),
+        }
+    }
+    
+    /// Get the secret itself
+    pub fn expose(&self) -> &str {
+        &self.value
+    }
+    
+    /// Check whether the value came from a file or the environment
+    pub fn is_reference(&self) -> bool {
+        self.source.starts_with("@file:") || self.source.starts_with("@env:")
+    }
+}
+
+impl From<&str> for Secret {
+    fn from(value: &str) -> Self {
+        Self::literal(value)
+    }
+}
+
+impl From<String> for Secret {
+    fn from(value: String) -> Self {
+        Self::literal(&value)
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_reference() {
+            write!(f, "Secret({})", self.source)
+        } else {
+            write!(f, "Secret({})", REDACTED)
+        }
+    }
+}
+
+impl Serialize for Secret {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.source)
+    }
+}
+
+impl<'de> Deserialize<'de> for Secret {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let source = String::deserialize(deserializer)?;
+        Secret::resolve(&source).map_err(|e| match e {
+            ServerError::Config(message) => de::Error::custom(message),
+            other => de::Error::custom(other),
+        })
+    }
+}
\ No newline at end of file
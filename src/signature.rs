@@ -0,0 +1,208 @@
+use crate::error::ServerResult;
+use crate::hash::{self, Sha256};
+use crate::http::{Request, Response, Status};
+use crate::middleware::MiddlewareNext;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How requests are signed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignatureScheme {
+    /// GitHub webhooks: `X-Hub-Signature-256: sha256=<hex>` over the raw body
+    ///
+    /// This scheme carries no timestamp, so it has no replay protection.
+    GitHub,
+    
+    /// A SigV4-like scheme covering the timestamp, method, target and body
+    ///
+    /// The signature header holds `sha256=<hex>` of the HMAC over
+    /// `<timestamp>\n<METHOD>\n<target>\n<hex sha256 of body>`, and the
+    /// timestamp header holds Unix seconds.
+    Timestamped {
+        signature_header: String,
+        timestamp_header: String,
+    },
+}
+
+/// Settings for request signature verification
+#[derive(Debug, Clone)]
+pub struct SignatureConfig {
+    pub scheme: SignatureScheme,
+    
+    /// Accepted secrets; several can be listed while rotating keys
+    pub secrets: Vec<Vec<u8>>,
+    
+    /// How far a signed timestamp may be from the server clock
+    pub tolerance: Duration,
+}
+
+impl SignatureConfig {
+    /// Verify GitHub-style webhook signatures
+    pub fn github(secret: &[u8]) -> Self {
+        Self {
+            scheme: SignatureScheme::GitHub,
+            secrets: vec![secret.to_vec()],
+            tolerance: Duration::from_secs(300),
+        }
+    }
+    
+    /// Verify timestamped signatures in `X-Signature` and `X-Signature-Timestamp`
+    pub fn timestamped(secret: &[u8]) -> Self {
+        Self {
+            scheme: SignatureScheme::Timestamped {
+                signature_header: "X-Signature".to_string(),
+                timestamp_header: "X-Signature-Timestamp".to_string(),
+            },
+            secrets: vec![secret.to_vec()],
+            tolerance: Duration::from_secs(300),
+        }
+    }
+    
+    /// Use different header names for the timestamped scheme
+    pub fn with_headers(mut self, signature_header: &str, timestamp_header: &str) -> Self {
+        self.scheme = SignatureScheme::Timestamped {
+            signature_header: signature_header.to_string(),
+            timestamp_header: timestamp_header.to_string(),
+        };
+        self
+    }
+    
+    /// Also accept signatures made with another secret
+    pub fn with_secret(mut self, secret: &[u8]) -> Self {
+        self.secrets.push(secret.to_vec());
+        self
+    }
+    
+    /// Set how much clock skew (and request age) to accept
+    pub fn with_tolerance(mut self, tolerance: Duration) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+}
+
+/// Build the string signed by the timestamped scheme
+fn canonical_string(timestamp: u64, method: &str, target: &str, body: &[u8]) -> String {
+    format!(
+        "{}\n{}\n{}\n{}",
+        timestamp,
+        method,
+        target,
+        hash::to_hex(&Sha256::digest(body))
+    )
+}
+
+/// Compute the header value a client sends for the timestamped scheme
+pub fn sign_timestamped(secret: &[u8], timestamp: u64, method: &str, target: &str, body: &[u8]) -> String {
+    let canonical = canonical_string(timestamp, method, target, body);
+    format!("sha256={}", hash::to_hex(&hash::hmac_sha256(secret, canonical.as_bytes())))
+}
+
+/// Compute the `X-Hub-Signature-256` value for a body
+pub fn sign_github(secret: &[u8], body: &[u8]) -> String {
+    format!("sha256={}", hash::to_hex(&hash::hmac_sha256(secret, body)))
+}
+
+/// Verifies request signatures and remembers recent ones to stop replays
+pub struct SignatureVerifier {
+    config: SignatureConfig,
+    /// Signatures accepted within the tolerance window, with their timestamps
+    seen: Mutex<HashMap<String, u64>>,
+}
+
+impl SignatureVerifier {
+    /// Create a verifier
+    pub fn new(config: SignatureConfig) -> Self {
+        Self {
+            config,
+            seen: Mutex::new(HashMap::new()),
+        }
+    }
+    
+    /// Check a request's signature at the current time
+    pub fn verify(&self, request: &Request) -> Result<(), String> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        self.verify_at(request, now)
+    }
+    
+    /// Check a request's signature as if the clock read `now` (Unix seconds)
+    pub fn verify_at(&self, request: &Request, now: u64) -> Result<(), String> {
+        match &self.config.scheme {
+            SignatureScheme::GitHub => {
+                let provided = request
+                    .get_header("X-Hub-Signature-256")
+                    .ok_or_else(|| "Missing X-Hub-Signature-256 header".to_string())?;
+                self.check_any_secret(provided, |secret| sign_github(secret, &request.body))
+            }
+            SignatureScheme::Timestamped {
+                signature_header,
+                timestamp_header,
+            } => {
+                let provided = request
+                    .get_header(signature_header)
+                    .ok_or_else(|| format!("Missing {} header", signature_header))?;
+                let timestamp: u64 = request
+                    .get_header(timestamp_header)
+                    .ok_or_else(|| format!("Missing {} header", timestamp_header))?
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("Invalid {} header", timestamp_header))?;
+                
+                let tolerance = self.config.tolerance.as_secs();
+                if timestamp.abs_diff(now) > tolerance {
+                    return Err("Signature timestamp is outside the allowed window".to_string());
+                }
+                
+                self.check_any_secret(provided, |secret| {
+                    sign_timestamped(secret, timestamp, request.method.as_str(), &request.uri, &request.body)
+                })?;
+                
+                // A valid signature may only be used once within its window
+                let mut seen = self.seen.lock().unwrap();
+                seen.retain(|_, signed_at| signed_at.abs_diff(now) <= tolerance);
+                if seen.insert(provided.clone(), timestamp).is_some() {
+                    return Err("Signature has already been used".to_string());
+                }
+                Ok(())
+            }
+        }
+    }
+    
+    fn check_any_secret<F>(&self, provided: &str, sign: F) -> Result<(), String>
+    where
+        F: Fn(&[u8]) -> String,
+    {
+        let provided = provided.trim();
+        let matched = self
+            .config
+            .secrets
+            .iter()
+            .any(|secret| hash::constant_time_eq(sign(secret).as_bytes(), provided.as_bytes()));
+        
+        if matched {
+            Ok(())
+        } else {
+            Err("Signature does not match".to_string())
+        }
+    }
+}
+
+/// Signature middleware - rejects requests without a valid HMAC signature with 401
+pub fn signature_middleware(
+    config: SignatureConfig,
+) -> impl Fn(&Request, MiddlewareNext) -> ServerResult<Response> + Send + Sync {
+    let verifier = SignatureVerifier::new(config);
+    
+    move |request, next| {
+        if let Err(message) = verifier.verify(request) {
+            let mut response = Response::new(Status::Unauthorized);
+            response.set_body(message.as_bytes());
+            return Ok(response);
+        }
+        
+        next(request)
+    }
+}
\ No newline at end of file
@@ -0,0 +1,180 @@
+use crate::error::ServerResult;
+use crate::http::{Method, Request, Response};
+use crate::middleware::MiddlewareNext;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+
+/// Request headers that change what a response looks like
+const KEY_HEADERS: [&str; 2] = ["accept", "accept-encoding"];
+
+/// How an in-flight call ended
+#[derive(Clone)]
+enum Outcome {
+    /// A response every waiter may use
+    Shared(Response),
+    /// The handler failed, panicked or returned something per-client; each
+    /// waiter runs the handler itself
+    Unshared,
+}
+
+/// A handler call that identical requests are waiting on
+#[derive(Default)]
+struct Call {
+    outcome: Mutex<Option<Outcome>>,
+    done: Condvar,
+}
+
+impl Call {
+    fn finish(&self, outcome: Outcome) {
+        *self.outcome.lock().unwrap() = Some(outcome);
+        self.done.notify_all();
+    }
+    
+    fn wait(&self) -> Outcome {
+        let mut outcome = self.outcome.lock().unwrap();
+        loop {
+            match &*outcome {
+                Some(result) => return result.clone(),
+                None => outcome = self.done.wait(outcome).unwrap(),
+            }
+        }
+    }
+}
+
+/// Coalesces identical concurrent GET requests into one handler call
+///
+/// While a request is being handled, identical requests wait for it and get
+/// a copy of its response instead of running the handler again, so a burst
+/// on a hot, expensive resource costs one call. Requests are identical when
+/// their method, host, URI, `Accept` and `Accept-Encoding` match.
+///
+/// Only anonymous GET and HEAD requests are coalesced; those carrying
+/// `Authorization` or `Cookie` may get personal answers and always run on
+/// their own. Responses that set cookies, are marked `private` or
+/// `no-store`, or switch protocols are never shared, and neither are
+/// errors; waiters then run the handler themselves.
+#[derive(Default)]
+pub struct SingleFlight {
+    calls: Mutex<HashMap<String, Arc<Call>>>,
+    coalesced: AtomicUsize,
+}
+
+impl SingleFlight {
+    /// Create an empty single-flight group
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Get how many requests were answered with another request's response
+    pub fn coalesced(&self) -> usize {
+        self.coalesced.load(Ordering::Relaxed)
+    }
+    
+    /// Handle `request` with `handler`, sharing the call with identical requests
+    pub fn run<F>(&self, request: &Request, handler: F) -> ServerResult<Response>
+    where
+        F: Fn(&Request) -> ServerResult<Response>,
+    {
+        let key = match coalescing_key(request) {
+            Some(key) => key,
+            None => return handler(request),
+        };
+        
+        let (call, leader) = {
+            let mut calls = self.calls.lock().unwrap();
+            match calls.get(&key) {
+                Some(call) => (call.clone(), false),
+                None => {
+                    let call = Arc::new(Call::default());
+                    calls.insert(key.clone(), call.clone());
+                    (call, true)
+                }
+            }
+        };
+        
+        if !leader {
+            return match call.wait() {
+                Outcome::Shared(response) => {
+                    self.coalesced.fetch_add(1, Ordering::Relaxed);
+                    Ok(response)
+                }
+                Outcome::Unshared => handler(request),
+            };
+        }
+        
+        // Release the waiters even if the handler panics
+        let mut guard = Leader {
+            flight: self,
+            key: &key,
+            call: &call,
+            outcome: Outcome::Unshared,
+        };
+        let result = handler(request);
+        if let Ok(response) = &result {
+            if is_shareable(response) {
+                guard.outcome = Outcome::Shared(response.clone());
+            }
+        }
+        result
+    }
+}
+
+/// Finishes the leader's call when it goes out of scope
+struct Leader<'a> {
+    flight: &'a SingleFlight,
+    key: &'a str,
+    call: &'a Call,
+    outcome: Outcome,
+}
+
+impl Drop for Leader<'_> {
+    fn drop(&mut self) {
+        // Later arrivals start a fresh call rather than reuse this one
+        self.flight.calls.lock().unwrap().remove(self.key);
+        let outcome = std::mem::replace(&mut self.outcome, Outcome::Unshared);
+        self.call.finish(outcome);
+    }
+}
+
+/// Build the key identical requests share, or None if the request must run alone
+fn coalescing_key(request: &Request) -> Option<String> {
+    if !matches!(request.method, Method::Get | Method::Head) {
+        return None;
+    }
+    if request.get_header("authorization").is_some() || request.get_header("cookie").is_some() {
+        return None;
+    }
+    
+    let mut key = format!(
+        "{} {} {}",
+        request.method.as_str(),
+        request.host.as_deref().unwrap_or(""),
+        request.uri
+    );
+    for name in KEY_HEADERS {
+        key.push('\n');
+        key.push_str(request.get_header(name).map_or("", String::as_str));
+    }
+    Some(key)
+}
+
+/// Check whether a response may be handed to other clients
+fn is_shareable(response: &Response) -> bool {
+    // Header names match in any case, and prepared heads are checked too
+    if response.upgrade.is_some() || response.header("Set-Cookie").is_some() {
+        return false;
+    }
+    let cache_control = response.header("Cache-Control").unwrap_or("");
+    !cache_control
+        .split(',')
+        .map(str::trim)
+        .any(|directive| directive.eq_ignore_ascii_case("private") || directive.eq_ignore_ascii_case("no-store"))
+}
+
+/// Single-flight middleware - lets identical concurrent GETs share one call to the rest of the chain
+pub fn single_flight_middleware(
+    flight: Arc<SingleFlight>,
+) -> impl Fn(&Request, MiddlewareNext) -> ServerResult<Response> + Send + Sync {
+    move |request, next| flight.run(request, &*next)
+}
\ No newline at end of file
@@ -1,12 +1,25 @@
-use crate::error::ServerResult;
+use crate::error::{ServerError, ServerResult};
+use crate::hash::{to_hex, Sha256};
 use crate::http::{Method, Request, Response, Status};
+use crate::protocol_upgrade::UpgradeResponse;
 use crate::router::Router;
 use std::collections::HashMap;
 use std::fs;
+use std::io::{self, Write};
 use std::path::{Path, PathBuf};
+use std::str;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, OnceLock, RwLock, Weak};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 
-/// A map of file extensions to content types
-fn content_type_map() -> HashMap<&'static str, &'static str> {
+/// A map of file extensions to content types, built on first use
+fn content_type_map() -> &'static HashMap<&'static str, &'static str> {
+    static CONTENT_TYPES: OnceLock<HashMap<&'static str, &'static str>> = OnceLock::new();
+    CONTENT_TYPES.get_or_init(build_content_type_map)
+}
+
+fn build_content_type_map() -> HashMap<&'static str, &'static str> {
     let mut map = HashMap::new();
     
     // Text types
@@ -58,9 +71,91 @@ fn content_type_map() -> HashMap<&'static str, &'static str> {
 fn get_content_type(path: &Path) -> &'static str {
     let ext = path.extension()
         .and_then(|e| e.to_str())
-        .unwrap_or("");
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    
+    content_type_map().get(ext.as_str()).copied().unwrap_or("application/octet-stream")
+}
+
+/// Guess the content type of a file from its first bytes
+///
+/// Only types a browser won't run script from are recognised, so a file
+/// can't become HTML by what it contains.
+fn sniff_content_type(contents: &[u8]) -> Option<&'static str> {
+    const SIGNATURES: &[(&[u8], &str)] = &[
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"GIF87a", "image/gif"),
+        (b"GIF89a", "image/gif"),
+        (b"\xff\xd8\xff", "image/jpeg"),
+        (b"%PDF-", "application/pdf"),
+        (b"PK\x03\x04", "application/zip"),
+        (b"\x1f\x8b", "application/gzip"),
+        (b"\0asm", "application/wasm"),
+        (b"wOFF", "font/woff"),
+        (b"wOF2", "font/woff2"),
+        (b"OggS", "audio/ogg"),
+        (b"ID3", "audio/mpeg"),
+        (b"\x1a\x45\xdf\xa3", "video/webm"),
+    ];
+    
+    if let Some((_, content_type)) = SIGNATURES.iter().find(|(magic, _)| contents.starts_with(magic)) {
+        return Some(content_type);
+    }
+    if contents.len() >= 12 && &contents[..4] == b"RIFF" && &contents[8..12] == b"WEBP" {
+        return Some("image/webp");
+    }
+    if contents.len() >= 8 && &contents[4..8] == b"ftyp" {
+        return Some("video/mp4");
+    }
+    
+    // Text is anything that is UTF-8 without control characters
+    let is_text = str::from_utf8(contents)
+        .is_ok_and(|text| !text.chars().any(|c| c.is_control() && !matches!(c, '\t' | '\n' | '\r' | '\x0c')));
+    is_text.then_some("text/plain; charset=utf-8")
+}
+
+/// How the static server picks a file's Content-Type
+#[derive(Clone, Debug)]
+struct ContentTypes {
+    custom: HashMap<String, String>,
+    sniff: bool,
+    nosniff: bool,
+}
+
+impl ContentTypes {
+    fn from_config(config: &StaticFileConfig) -> Self {
+        Self {
+            custom: config
+                .mime_types
+                .iter()
+                .map(|(ext, content_type)| (ext.trim_start_matches('.').to_ascii_lowercase(), content_type.clone()))
+                .collect(),
+            sniff: config.sniff_content_types,
+            nosniff: config.nosniff,
+        }
+    }
+    
+    /// Get the content type for a file, preferring custom mappings
+    fn for_file<'a>(&'a self, path: &Path, contents: &[u8]) -> &'a str {
+        let ext = path.extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase());
+        if let Some(content_type) = ext.as_ref().and_then(|ext| self.custom.get(ext)) {
+            return content_type;
+        }
+        if ext.is_none() && self.sniff {
+            if let Some(content_type) = sniff_content_type(contents) {
+                return content_type;
+            }
+        }
+        get_content_type(path)
+    }
     
-    content_type_map().get(ext).copied().unwrap_or("application/octet-stream")
+    /// Set the Content-Type for a file, telling clients not to second-guess it
+    fn apply(&self, response: &mut Response, content_type: &str) {
+        response.set_header("Content-Type", content_type);
+        if self.nosniff {
+            response.set_header("X-Content-Type-Options", "nosniff");
+        }
+    }
 }
 
 /// Configuration for the static file server
@@ -86,6 +181,55 @@ pub struct StaticFileConfig {
     
     /// Cache control header value
     pub cache_control: String,
+    
+    /// Cache-Control values for files matching a pattern, used instead of
+    /// `cache_control`; the first matching rule wins
+    pub cache_rules: Vec<CacheRule>,
+    
+    /// Cross-origin headers for files matching a pattern, e.g. fonts and
+    /// WebAssembly loaded from other origins; every matching rule applies
+    pub cors_rules: Vec<CorsRule>,
+    
+    /// Maximum bytes per second to send each file at (None = unlimited)
+    pub bandwidth_limit: Option<u64>,
+    
+    /// Assets each HTML page should preload, keyed by the page's path under
+    /// `root_dir` (e.g. `index.html`); the assets are URLs as the browser
+    /// requests them and are announced in a `Link: rel=preload` header
+    pub preload: HashMap<String, Vec<String>>,
+    
+    /// Fingerprinted URLs to serve files under, set up by `enable_fingerprinting`
+    pub assets: Option<AssetManifest>,
+    
+    /// Content types for extensions, e.g. `"mjs"` to `"text/javascript"`,
+    /// taking precedence over the built-in table
+    pub mime_types: HashMap<String, String>,
+    
+    /// Whether to guess the type of files without an extension from their
+    /// first bytes; only non-executable types such as images are detected
+    pub sniff_content_types: bool,
+    
+    /// Whether to send `X-Content-Type-Options: nosniff`, so browsers use
+    /// the type we send rather than guessing their own
+    pub nosniff: bool,
+    
+    /// HTML pages to answer error statuses with instead of a plain-text
+    /// message; relative paths are taken from `root_dir`
+    pub error_pages: HashMap<Status, PathBuf>,
+    
+    /// How long a generated directory listing is reused while the directory
+    /// is unchanged (zero = always regenerate)
+    pub listing_cache_ttl: Duration,
+    
+    /// Development mode: watch `root_dir` and reload open pages whenever a
+    /// file changes, by injecting a small script into served HTML that
+    /// listens for server-sent events at `LIVE_RELOAD_PATH`; also turns
+    /// off caching
+    pub live_reload: bool,
+    
+    /// Files compiled into the binary to serve instead of `root_dir`; see
+    /// `EmbeddedDir`
+    pub embedded: Option<EmbeddedDir>,
 }
 
 impl Default for StaticFileConfig {
@@ -98,12 +242,878 @@ impl Default for StaticFileConfig {
             directory_listing: false,
             max_file_size: 10 * 1024 * 1024, // 10 MB
             cache_control: "public, max-age=3600".to_string(),
+            cache_rules: Vec::new(),
+            cors_rules: Vec::new(),
+            bandwidth_limit: None,
+            preload: HashMap::new(),
+            assets: None,
+            mime_types: HashMap::new(),
+            sniff_content_types: false,
+            nosniff: true,
+            error_pages: HashMap::new(),
+            listing_cache_ttl: Duration::from_secs(2),
+            live_reload: false,
+            embedded: None,
         }
     }
 }
 
+impl StaticFileConfig {
+    /// Load the preload manifest from a JSON file mapping pages to asset URLs
+    ///
+    /// ```json
+    /// { "index.html": ["/static/app.css", "/static/app.js"] }
+    /// ```
+    pub fn load_preload_manifest<P: AsRef<Path>>(&mut self, path: P) -> ServerResult<()> {
+        let content = fs::read_to_string(path)?;
+        self.preload = serde_json::from_str(&content)
+            .map_err(|e| ServerError::Config(format!("Invalid preload manifest: {}", e)))?;
+        Ok(())
+    }
+    
+    /// Also serve every file under a URL containing a hash of its contents
+    ///
+    /// Fingerprinted URLs are cached by clients for good, so a changed file
+    /// is fetched again only because its URL changes. Returns the manifest
+    /// for handlers and templates to look those URLs up in.
+    pub fn enable_fingerprinting(&mut self) -> ServerResult<AssetManifest> {
+        let assets = AssetManifest::build(&self.root_dir, &self.path_prefix, self.follow_symlinks)?;
+        self.assets = Some(assets.clone());
+        Ok(assets)
+    }
+}
+
+/// A Cache-Control value for the files matching a glob pattern
+///
+/// Patterns containing a `/` are matched against a file's whole URL path,
+/// e.g. `/static/vendor/*`; others against its name alone, e.g. `*.html`.
+/// `*` matches any run of characters, including `/`, and `?` any single one.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CacheRule {
+    pub pattern: String,
+    pub cache_control: String,
+}
+
+impl CacheRule {
+    /// Create a rule sending `cache_control` for files matching `pattern`
+    pub fn new(pattern: &str, cache_control: &str) -> Self {
+        Self {
+            pattern: pattern.to_string(),
+            cache_control: cache_control.to_string(),
+        }
+    }
+
+}
+
+/// Match a file's URL path against a rule pattern (see `CacheRule`)
+fn path_pattern_matches(pattern: &str, url_path: &str) -> bool {
+    if pattern.contains('/') {
+        return glob_match(pattern.as_bytes(), url_path.as_bytes());
+    }
+    let name = url_path.rsplit('/').next().unwrap_or(url_path);
+    glob_match(pattern.as_bytes(), name.as_bytes())
+}
+
+/// Build the URL path a file under `root_dir` is served at
+fn file_url_path(path_prefix: &str, root_dir: &Path, file: &Path) -> String {
+    let relative = file.strip_prefix(root_dir).unwrap_or(file);
+    let mut url_path = path_prefix.to_string();
+    for component in relative.components() {
+        url_path.push('/');
+        url_path.push_str(&component.as_os_str().to_string_lossy());
+    }
+    url_path
+}
+
+/// Match `text` against a pattern of literals, `*` and `?`
+fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // Where to resume after the last `*` if the rest fails to match
+    let mut backtrack: Option<(usize, usize)> = None;
+    
+    while t < text.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                backtrack = Some((p + 1, t));
+                p += 1;
+            }
+            Some(&c) if c == b'?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((after_star, matched)) => {
+                    p = after_star;
+                    t = matched + 1;
+                    backtrack = Some((after_star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+/// Picks the Cache-Control value for each file served
+#[derive(Clone, Debug)]
+struct CachePolicy {
+    path_prefix: String,
+    default: String,
+    rules: Vec<CacheRule>,
+}
+
+impl CachePolicy {
+    fn from_config(config: &StaticFileConfig) -> Self {
+        Self {
+            path_prefix: config.path_prefix.trim_end_matches('/').to_string(),
+            default: config.cache_control.clone(),
+            rules: config.cache_rules.clone(),
+        }
+    }
+    
+    /// Turn off caching altogether, for development
+    fn no_store() -> Self {
+        Self {
+            path_prefix: String::new(),
+            default: "no-store".to_string(),
+            rules: Vec::new(),
+        }
+    }
+    
+    /// Get the value for a file, given by its path under `root_dir`
+    fn for_file(&self, root_dir: &Path, file: &Path) -> &str {
+        if self.rules.is_empty() {
+            return &self.default;
+        }
+        
+        let url_path = file_url_path(&self.path_prefix, root_dir, file);
+        self.rules
+            .iter()
+            .find(|rule| path_pattern_matches(&rule.pattern, &url_path))
+            .map_or(&self.default, |rule| &rule.cache_control)
+    }
+}
+
+/// Cross-origin headers for the files matching a glob pattern
+///
+/// Patterns work as for `CacheRule`, e.g. `*.woff2` or `/static/wasm/*`.
+/// The headers are sent whether or not the request has an `Origin`, so
+/// shared caches hold one copy that works for every page.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CorsRule {
+    pub pattern: String,
+    /// `Access-Control-Allow-Origin` value, `*` or a single origin
+    pub allow_origin: String,
+    /// `Cross-Origin-Resource-Policy` value, e.g. `cross-origin` or `same-site`
+    pub resource_policy: Option<String>,
+}
+
+impl CorsRule {
+    /// Allow any origin to load the files matching `pattern`
+    pub fn new(pattern: &str) -> Self {
+        Self {
+            pattern: pattern.to_string(),
+            allow_origin: "*".to_string(),
+            resource_policy: Some("cross-origin".to_string()),
+        }
+    }
+    
+    /// Allow only `origin` to load the files
+    pub fn with_origin(mut self, origin: &str) -> Self {
+        self.allow_origin = origin.to_string();
+        self
+    }
+    
+    /// Set the `Cross-Origin-Resource-Policy`, or None to send none
+    pub fn with_resource_policy(mut self, policy: Option<&str>) -> Self {
+        self.resource_policy = policy.map(str::to_string);
+        self
+    }
+}
+
+/// Adds cross-origin headers to the files matching a `CorsRule`
+#[derive(Clone, Debug)]
+struct CorsPolicy {
+    path_prefix: String,
+    rules: Vec<CorsRule>,
+}
+
+impl CorsPolicy {
+    fn from_config(config: &StaticFileConfig) -> Self {
+        Self {
+            path_prefix: config.path_prefix.trim_end_matches('/').to_string(),
+            rules: config.cors_rules.clone(),
+        }
+    }
+    
+    /// Add the headers for a file, given by its path under `root_dir`
+    fn apply(&self, response: &mut Response, root_dir: &Path, file: &Path) {
+        if self.rules.is_empty() {
+            return;
+        }
+        
+        let url_path = file_url_path(&self.path_prefix, root_dir, file);
+        for rule in self.rules.iter().filter(|rule| path_pattern_matches(&rule.pattern, &url_path)) {
+            response.set_header("Access-Control-Allow-Origin", &rule.allow_origin);
+            if rule.allow_origin != "*" {
+                response.append_header("Vary", "Origin");
+            }
+            if let Some(policy) = &rule.resource_policy {
+                response.set_header("Cross-Origin-Resource-Policy", policy);
+            }
+        }
+    }
+}
+
+/// Error pages the static server answers with, by status
+#[derive(Clone, Debug)]
+struct ErrorPages {
+    pages: HashMap<Status, PathBuf>,
+}
+
+impl ErrorPages {
+    fn from_config(config: &StaticFileConfig) -> Self {
+        Self {
+            pages: config
+                .error_pages
+                .iter()
+                .map(|(status, page)| (*status, config.root_dir.join(page)))
+                .collect(),
+        }
+    }
+    
+    /// Build an error response, from the status's page if one is set and readable
+    fn response(&self, status: Status, message: &str) -> Response {
+        let mut response = Response::new(status);
+        let page = self.pages.get(&status).and_then(|path| match fs::read(path) {
+            Ok(page) => Some(page),
+            Err(e) => {
+                log::warn!("Failed to read error page {}: {}", path.display(), e);
+                None
+            }
+        });
+        
+        match page {
+            Some(page) => {
+                response.set_body(&page);
+                response.set_header("Content-Type", "text/html; charset=utf-8");
+            }
+            None => response.set_body(message.as_bytes()),
+        }
+        response
+    }
+}
+
+/// Most directory listings kept at once
+const MAX_CACHED_LISTINGS: usize = 1024;
+
+/// A generated directory listing and the directory state it reflects
+struct CachedListing {
+    modified: SystemTime,
+    generated: Instant,
+    html: Arc<String>,
+}
+
+/// Directory listings keyed by directory and the path they were requested as
+type ListingMap = HashMap<(PathBuf, String), CachedListing>;
+
+/// Generated directory listings, reused until the directory changes
+///
+/// An entry is used only while the directory's modification time matches
+/// and it is younger than the TTL, which covers changes within the same
+/// timestamp. With the `fs-notify` feature on Linux, entries are also
+/// dropped as soon as inotify reports a change.
+struct ListingCache {
+    ttl: Duration,
+    entries: Arc<Mutex<ListingMap>>,
+    #[cfg(all(feature = "fs-notify", target_os = "linux"))]
+    watcher: Option<crate::fs_watch::DirWatcher>,
+}
+
+impl ListingCache {
+    fn new(ttl: Duration) -> Self {
+        let entries: Arc<Mutex<ListingMap>> = Arc::new(Mutex::new(HashMap::new()));
+        
+        #[cfg(all(feature = "fs-notify", target_os = "linux"))]
+        let watcher = if ttl.is_zero() {
+            None
+        } else {
+            let changed = entries.clone();
+            crate::fs_watch::DirWatcher::new(move |dir| {
+                changed.lock().unwrap().retain(|(path, _), _| path != dir);
+            })
+            .map_err(|e| log::warn!("Directory listings won't be invalidated by inotify: {}", e))
+            .ok()
+        };
+        
+        Self {
+            ttl,
+            entries,
+            #[cfg(all(feature = "fs-notify", target_os = "linux"))]
+            watcher,
+        }
+    }
+    
+    /// Get a listing, generating it if there is no current one
+    fn get_or_render(&self, dir_path: &Path, path_prefix: &str, relative_path: &str) -> io::Result<Arc<String>> {
+        let modified = match fs::metadata(dir_path).and_then(|metadata| metadata.modified()) {
+            Ok(modified) if !self.ttl.is_zero() => modified,
+            _ => return render_directory_listing(dir_path, path_prefix, relative_path).map(Arc::new),
+        };
+        
+        let key = (dir_path.to_path_buf(), relative_path.to_string());
+        if let Some(cached) = self.entries.lock().unwrap().get(&key) {
+            if cached.modified == modified && cached.generated.elapsed() < self.ttl {
+                return Ok(cached.html.clone());
+            }
+        }
+        
+        let html = Arc::new(render_directory_listing(dir_path, path_prefix, relative_path)?);
+        #[cfg(all(feature = "fs-notify", target_os = "linux"))]
+        if let Some(watcher) = &self.watcher {
+            if let Err(e) = watcher.watch(dir_path) {
+                log::warn!("Failed to watch {} for changes: {}", dir_path.display(), e);
+            }
+        }
+        
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= MAX_CACHED_LISTINGS {
+            let ttl = self.ttl;
+            entries.retain(|_, cached| cached.generated.elapsed() < ttl);
+            if entries.len() >= MAX_CACHED_LISTINGS {
+                entries.clear();
+            }
+        }
+        entries.insert(key, CachedListing {
+            modified,
+            generated: Instant::now(),
+            html: html.clone(),
+        });
+        Ok(html)
+    }
+}
+
+/// A directory of files compiled into the binary
+///
+/// Generate the file list from a build script with `write_embedded_dir`,
+/// then include it with `include_static_dir!`:
+///
+/// ```ignore
+/// // build.rs
+/// high_performance_server::static_files::write_embedded_dir(
+///     "static".as_ref(),
+///     &std::path::Path::new(&std::env::var("OUT_DIR").unwrap()).join("static_files.rs"),
+/// ).unwrap();
+///
+/// // main.rs
+/// let config = StaticFileConfig {
+///     embedded: Some(high_performance_server::include_static_dir!("static_files.rs")),
+///     ..StaticFileConfig::default()
+/// };
+/// ```
+///
+/// Embedded files are served with the same content types, cache headers,
+/// error pages and preloads as files on disk. Directory listings,
+/// fingerprinting and live reload need the filesystem and are not
+/// available.
+#[derive(Clone, Copy, Debug)]
+pub struct EmbeddedDir {
+    /// Paths under the embedded root with their contents, sorted by path
+    files: &'static [(&'static str, &'static [u8])],
+}
+
+impl EmbeddedDir {
+    /// Wrap a list of `(path, contents)` pairs sorted by path
+    pub const fn new(files: &'static [(&'static str, &'static [u8])]) -> Self {
+        Self { files }
+    }
+    
+    /// Get the contents of a file
+    pub fn get(&self, path: &str) -> Option<&'static [u8]> {
+        let path = path.trim_matches('/');
+        self.files
+            .binary_search_by(|(name, _)| (*name).cmp(path))
+            .ok()
+            .map(|index| self.files[index].1)
+    }
+    
+    /// Check whether any file lives under `path`
+    pub fn is_dir(&self, path: &str) -> bool {
+        let path = path.trim_matches('/');
+        path.is_empty() || self.files.iter().any(|(name, _)| {
+            name.strip_prefix(path).is_some_and(|rest| rest.starts_with('/'))
+        })
+    }
+    
+    /// Iterate over every file's path and contents
+    pub fn files(&self) -> impl Iterator<Item = (&'static str, &'static [u8])> {
+        self.files.iter().copied()
+    }
+    
+    /// Get the number of files
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+    
+    /// Check whether there are no files
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+}
+
+/// Include a file list written by `write_embedded_dir` into `OUT_DIR`
+#[macro_export]
+macro_rules! include_static_dir {
+    ($file:expr) => {
+        $crate::static_files::EmbeddedDir::new(include!(concat!(env!("OUT_DIR"), "/", $file)))
+    };
+}
+
+/// Write the Rust source embedding every file under `root_dir` to `out_file`
+///
+/// Meant for build scripts; it also tells Cargo to rebuild when anything
+/// under `root_dir` changes.
+pub fn write_embedded_dir(root_dir: &Path, out_file: &Path) -> ServerResult<()> {
+    let root_dir = fs::canonicalize(root_dir)?;
+    let mut files = Vec::new();
+    collect_files(&root_dir, "", false, &mut files)?;
+    files.sort();
+    
+    let mut source = String::from("&[\n");
+    for path in &files {
+        let absolute = root_dir.join(path);
+        source.push_str(&format!(
+            "    ({:?}, include_bytes!({:?}) as &[u8]),\n",
+            path,
+            absolute.to_string_lossy()
+        ));
+    }
+    source.push(']');
+    fs::write(out_file, source)?;
+    
+    println!("cargo:rerun-if-changed={}", root_dir.display());
+    for path in &files {
+        println!("cargo:rerun-if-changed={}", root_dir.join(path).display());
+    }
+    Ok(())
+}
+
+/// Serves files from an `EmbeddedDir` with the static server's settings
+#[derive(Clone)]
+struct EmbeddedSite {
+    dir: EmbeddedDir,
+    index_file: String,
+    cache_policy: CachePolicy,
+    cors_policy: CorsPolicy,
+    bandwidth_limit: Option<u64>,
+    preload: HashMap<String, Vec<String>>,
+    content_types: ContentTypes,
+    error_pages: ErrorPages,
+}
+
+impl EmbeddedSite {
+    fn from_config(config: &StaticFileConfig) -> Option<Self> {
+        Some(Self {
+            dir: config.embedded?,
+            index_file: config.index_file.clone(),
+            cache_policy: CachePolicy::from_config(config),
+            cors_policy: CorsPolicy::from_config(config),
+            bandwidth_limit: config.bandwidth_limit,
+            preload: config.preload.clone(),
+            content_types: ContentTypes::from_config(config),
+            error_pages: ErrorPages::from_config(config),
+        })
+    }
+    
+    /// Serve the file at `path`, or the index file of the directory there
+    ///
+    /// Returns None if there is nothing at `path`.
+    fn serve(&self, path: &str) -> Option<Response> {
+        let segments: Vec<&str> = path
+            .split('/')
+            .filter(|segment| !segment.is_empty() && *segment != "." && *segment != "..")
+            .collect();
+        let mut path = segments.join("/");
+        
+        if self.dir.get(&path).is_none() && self.dir.is_dir(&path) {
+            let index = if path.is_empty() { self.index_file.clone() } else { format!("{}/{}", path, self.index_file) };
+            if self.dir.get(&index).is_none() {
+                return Some(self.error_pages.response(Status::Forbidden, "Directory listing not allowed"));
+            }
+            path = index;
+        }
+        let contents = self.dir.get(&path)?;
+        
+        let file = Path::new(&path);
+        let content_type = self.content_types.for_file(file, contents);
+        let mut response = Response::new(Status::Ok);
+        response.set_header("Cache-Control", self.cache_policy.for_file(Path::new(""), file));
+        response.set_body(contents);
+        self.content_types.apply(&mut response, content_type);
+        self.cors_policy.apply(&mut response, Path::new(""), file);
+        if let Some(links) = preload_links(&self.preload, Path::new(""), file, content_type) {
+            response.set_header("Link", &links);
+        }
+        if let Some(limit) = self.bandwidth_limit {
+            response.set_bandwidth_limit(limit);
+        }
+        Some(response)
+    }
+}
+
+/// Path the live-reload script receives reload events from
+pub const LIVE_RELOAD_PATH: &str = "/__livereload";
+
+/// Script injected into HTML pages in development mode
+const LIVE_RELOAD_SCRIPT: &str = "<script>new EventSource(\"/__livereload\")\
+    .addEventListener(\"reload\",function(){location.reload()})</script>";
+
+/// How often the root directory is scanned for changes in development mode
+const LIVE_RELOAD_POLL_INTERVAL: Duration = Duration::from_millis(300);
+
+/// How often an idle event stream is sent a comment to keep it open
+const LIVE_RELOAD_KEEPALIVE: Duration = Duration::from_secs(15);
+
+/// Tells browsers to reload when files under the root directory change
+struct LiveReload {
+    clients: Mutex<Vec<Sender<()>>>,
+}
+
+impl LiveReload {
+    /// Start scanning `root_dir` for changes until the returned value is dropped
+    fn start(root_dir: PathBuf, follow_symlinks: bool) -> Arc<Self> {
+        let live_reload = Arc::new(Self {
+            clients: Mutex::new(Vec::new()),
+        });
+        
+        let weak = Arc::downgrade(&live_reload);
+        let spawned = thread::Builder::new()
+            .name("live-reload".to_string())
+            .spawn(move || watch_for_reload(&root_dir, follow_symlinks, weak));
+        if let Err(e) = spawned {
+            log::warn!("Failed to start the live-reload watcher: {}", e);
+        }
+        live_reload
+    }
+    
+    /// Get notified of every change from now on
+    fn subscribe(&self) -> Receiver<()> {
+        let (tx, rx) = mpsc::channel();
+        self.clients.lock().unwrap().push(tx);
+        rx
+    }
+    
+    /// Tell every connected browser to reload, forgetting those that left
+    fn notify(&self) {
+        self.clients.lock().unwrap().retain(|client| client.send(()).is_ok());
+    }
+    
+    /// Answer a browser's request for reload events with an event stream
+    fn events_response(&self) -> Response {
+        let changes = self.subscribe();
+        
+        let mut response = Response::new(Status::Ok);
+        response.set_header("Content-Type", "text/event-stream");
+        response.set_header("Cache-Control", "no-store");
+        UpgradeResponse::stream(response, move |mut conn| {
+            if conn.stream().set_nonblocking(false).is_err() {
+                return;
+            }
+            let stream = conn.stream_mut();
+            if stream.write_all(b": connected\n\n").is_err() {
+                return;
+            }
+            loop {
+                let event: &[u8] = match changes.recv_timeout(LIVE_RELOAD_KEEPALIVE) {
+                    Ok(()) => b"event: reload\ndata: \n\n",
+                    Err(RecvTimeoutError::Timeout) => b": keep-alive\n\n",
+                    Err(RecvTimeoutError::Disconnected) => return,
+                };
+                if stream.write_all(event).is_err() {
+                    return;
+                }
+            }
+        })
+        .into()
+    }
+}
+
+/// Scan `root_dir` and notify `live_reload` of changes while it is alive
+fn watch_for_reload(root_dir: &Path, follow_symlinks: bool, live_reload: Weak<LiveReload>) {
+    let mut last = snapshot_files(root_dir, follow_symlinks);
+    loop {
+        thread::sleep(LIVE_RELOAD_POLL_INTERVAL);
+        let live_reload = match live_reload.upgrade() {
+            Some(live_reload) => live_reload,
+            None => return,
+        };
+        
+        let current = snapshot_files(root_dir, follow_symlinks);
+        if current != last {
+            live_reload.notify();
+            last = current;
+        }
+    }
+}
+
+/// Record the size and modification time of every file under `root_dir`
+fn snapshot_files(root_dir: &Path, follow_symlinks: bool) -> HashMap<String, (Option<SystemTime>, u64)> {
+    let mut files = Vec::new();
+    if collect_files(root_dir, "", follow_symlinks, &mut files).is_err() {
+        return HashMap::new();
+    }
+    
+    files
+        .into_iter()
+        .filter_map(|path| {
+            let metadata = fs::metadata(root_dir.join(&path)).ok()?;
+            Some((path, (metadata.modified().ok(), metadata.len())))
+        })
+        .collect()
+}
+
+/// Add the live-reload script to an HTML page, just before `</body>` if it has one
+fn inject_live_reload(html: &[u8]) -> Vec<u8> {
+    let lower = html.to_ascii_lowercase();
+    let at = lower.windows(7).rposition(|window| window == b"</body>").unwrap_or(html.len());
+    
+    let mut page = Vec::with_capacity(html.len() + LIVE_RELOAD_SCRIPT.len());
+    page.extend_from_slice(&html[..at]);
+    page.extend_from_slice(LIVE_RELOAD_SCRIPT.as_bytes());
+    page.extend_from_slice(&html[at..]);
+    page
+}
+
+/// Cache-Control sent with files served under a fingerprinted URL
+const IMMUTABLE_CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+
+/// A file's fingerprint and the state of the file it was taken from
+#[derive(Debug, Clone)]
+struct AssetEntry {
+    fingerprinted: String,
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+#[derive(Debug, Default)]
+struct AssetTable {
+    /// Fingerprint entries keyed by path under the root directory
+    by_path: HashMap<String, AssetEntry>,
+    /// Paths under the root directory keyed by fingerprinted path
+    by_fingerprint: HashMap<String, String>,
+}
+
+/// Maps static files to URLs that embed a hash of their contents
+///
+/// `js/app.js` is also served as `js/app.3f9ab2c1.js`. Files are hashed when
+/// the manifest is built; a file found to have changed since is hashed
+/// again on its next lookup, and `refresh` rescans the whole directory.
+#[derive(Debug, Clone)]
+pub struct AssetManifest {
+    root_dir: PathBuf,
+    path_prefix: String,
+    follow_symlinks: bool,
+    table: Arc<RwLock<AssetTable>>,
+}
+
+impl AssetManifest {
+    /// Fingerprint every file under `root_dir`, served under `path_prefix`
+    pub fn build(root_dir: &Path, path_prefix: &str, follow_symlinks: bool) -> ServerResult<Self> {
+        let manifest = Self {
+            root_dir: root_dir.to_path_buf(),
+            path_prefix: path_prefix.trim_end_matches('/').to_string(),
+            follow_symlinks,
+            table: Arc::new(RwLock::new(AssetTable::default())),
+        };
+        manifest.refresh()?;
+        Ok(manifest)
+    }
+    
+    /// Rescan the root directory, picking up new, changed and removed files
+    pub fn refresh(&self) -> ServerResult<()> {
+        let mut files = Vec::new();
+        collect_files(&self.root_dir, "", self.follow_symlinks, &mut files)?;
+        
+        let mut table = AssetTable::default();
+        for path in files {
+            if let Ok(entry) = fingerprint_file(&self.root_dir, &path) {
+                table.by_fingerprint.insert(entry.fingerprinted.clone(), path.clone());
+                table.by_path.insert(path, entry);
+            }
+        }
+        *self.table.write().unwrap() = table;
+        Ok(())
+    }
+    
+    /// Get the fingerprinted form of a path under the root directory
+    pub fn fingerprinted(&self, path: &str) -> Option<String> {
+        let path = path.trim_start_matches('/');
+        let entry = self.table.read().unwrap().by_path.get(path).cloned()?;
+        if self.is_current(path, &entry) {
+            return Some(entry.fingerprinted);
+        }
+        self.update(path).map(|entry| entry.fingerprinted)
+    }
+    
+    /// Get the URL to reference a file by, fingerprinted when possible
+    ///
+    /// `url_for("js/app.js")` gives e.g. `/static/js/app.3f9ab2c1.js`.
+    pub fn url_for(&self, path: &str) -> String {
+        let path = path.trim_start_matches('/');
+        let path = self.fingerprinted(path).unwrap_or_else(|| path.to_string());
+        format!("{}/{}", self.path_prefix, path)
+    }
+    
+    /// Find the file a fingerprinted path refers to, if it is still current
+    pub fn resolve(&self, fingerprinted: &str) -> Option<String> {
+        let fingerprinted = fingerprinted.trim_start_matches('/');
+        let path = self.table.read().unwrap().by_fingerprint.get(fingerprinted).cloned()?;
+        (self.fingerprinted(&path)? == fingerprinted).then_some(path)
+    }
+    
+    /// Get every path under the root directory with its fingerprinted form
+    pub fn entries(&self) -> HashMap<String, String> {
+        self.table
+            .read()
+            .unwrap()
+            .by_path
+            .iter()
+            .map(|(path, entry)| (path.clone(), entry.fingerprinted.clone()))
+            .collect()
+    }
+    
+    fn is_current(&self, path: &str, entry: &AssetEntry) -> bool {
+        match fs::metadata(self.root_dir.join(path)) {
+            Ok(metadata) => metadata.len() == entry.len && metadata.modified().ok() == entry.modified,
+            Err(_) => false,
+        }
+    }
+    
+    /// Hash a changed file again, or forget it if it is gone
+    fn update(&self, path: &str) -> Option<AssetEntry> {
+        let entry = fingerprint_file(&self.root_dir, path).ok();
+        
+        let mut table = self.table.write().unwrap();
+        if let Some(old) = table.by_path.remove(path) {
+            table.by_fingerprint.remove(&old.fingerprinted);
+        }
+        if let Some(entry) = &entry {
+            table.by_fingerprint.insert(entry.fingerprinted.clone(), path.to_string());
+            table.by_path.insert(path.to_string(), entry.clone());
+        }
+        entry
+    }
+}
+
+/// List the files under `dir` as `/`-separated paths relative to the root
+fn collect_files(dir: &Path, prefix: &str, follow_symlinks: bool, files: &mut Vec<String>) -> ServerResult<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = match entry.file_name().into_string() {
+            Ok(name) => name,
+            Err(_) => continue,
+        };
+        let file_type = entry.file_type()?;
+        if file_type.is_symlink() && !follow_symlinks {
+            continue;
+        }
+        
+        let path = if prefix.is_empty() { name } else { format!("{}/{}", prefix, name) };
+        let entry_path = entry.path();
+        if entry_path.is_dir() {
+            // Symlinked directories aren't descended into, avoiding loops
+            if !file_type.is_symlink() {
+                collect_files(&entry_path, &path, follow_symlinks, files)?;
+            }
+        } else if entry_path.is_file() {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Hash a file and name its fingerprinted form, e.g. `app.js` to `app.3f9ab2c1.js`
+fn fingerprint_file(root_dir: &Path, path: &str) -> ServerResult<AssetEntry> {
+    let file = root_dir.join(path);
+    let metadata = fs::metadata(&file)?;
+    let hash = to_hex(&Sha256::digest(&fs::read(&file)?)[..4]);
+    
+    let (dir, name) = match path.rsplit_once('/') {
+        Some((dir, name)) => (format!("{}/", dir), name),
+        None => (String::new(), path),
+    };
+    let fingerprinted = match name.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => format!("{}{}.{}.{}", dir, stem, hash, ext),
+        _ => format!("{}{}.{}", dir, name, hash),
+    };
+    
+    Ok(AssetEntry {
+        fingerprinted,
+        modified: metadata.modified().ok(),
+        len: metadata.len(),
+    })
+}
+
+/// Build the `Link` header preloading a page's assets, if it is HTML with any listed
+fn preload_links(preload: &HashMap<String, Vec<String>>, root_dir: &Path, file: &Path, content_type: &str) -> Option<String> {
+    if preload.is_empty() || !content_type.starts_with("text/html") {
+        return None;
+    }
+    
+    let relative = file.strip_prefix(root_dir).ok()?;
+    let page: Vec<_> = relative.components().filter_map(|c| c.as_os_str().to_str()).collect();
+    let assets = preload.get(&page.join("/")).filter(|assets| !assets.is_empty())?;
+    
+    let links: Vec<String> = assets
+        .iter()
+        .map(|asset| {
+            let mut link = format!("<{}>; rel=preload", asset);
+            if let Some(destination) = preload_destination(asset) {
+                link.push_str("; as=");
+                link.push_str(destination);
+                // Fonts and fetches are always requested in CORS mode
+                if destination == "font" || destination == "fetch" {
+                    link.push_str("; crossorigin");
+                }
+            }
+            link
+        })
+        .collect();
+    Some(links.join(", "))
+}
+
+/// Pick the `as` attribute for a preloaded asset from its extension
+fn preload_destination(asset: &str) -> Option<&'static str> {
+    let path = asset.split(['?', '#']).next().unwrap_or(asset);
+    let ext = path.rsplit_once('.').map(|(_, ext)| ext.to_ascii_lowercase())?;
+    
+    match ext.as_str() {
+        "css" => Some("style"),
+        "js" | "mjs" => Some("script"),
+        "woff" | "woff2" | "ttf" | "otf" => Some("font"),
+        "png" | "jpg" | "jpeg" | "gif" | "svg" | "webp" | "avif" | "ico" => Some("image"),
+        "json" => Some("fetch"),
+        _ => None,
+    }
+}
+
 /// Add static file routes to a router
 pub fn add_static_file_routes(router: &mut Router, config: StaticFileConfig) {
+    if let Some(site) = EmbeddedSite::from_config(&config) {
+        let prefix = config.path_prefix.clone();
+        let wild = site.clone();
+        router.get(&format!("{}/*", config.path_prefix), move |req| {
+            let path = req.path().strip_prefix(prefix.as_str()).unwrap_or_default();
+            Ok(wild.serve(path).unwrap_or_else(|| {
+                wild.error_pages.response(Status::NotFound, &format!("File not found: {}", path.trim_start_matches('/')))
+            }))
+        });
+        router.get(&config.path_prefix, move |_| {
+            Ok(site.serve("").unwrap_or_else(|| site.error_pages.response(Status::NotFound, "File not found")))
+        });
+        return;
+    }
+    
     // Create local copies of the configuration
     let root_dir = config.root_dir.clone();
     let path_prefix = config.path_prefix.clone();
@@ -111,7 +1121,19 @@ pub fn add_static_file_routes(router: &mut Router, config: StaticFileConfig) {
     let follow_symlinks = config.follow_symlinks;
     let directory_listing = config.directory_listing;
     let max_file_size = config.max_file_size;
-    let cache_control = config.cache_control.clone();
+    let bandwidth_limit = config.bandwidth_limit;
+    let preload = config.preload.clone();
+    let assets = config.assets.clone();
+    let content_types = ContentTypes::from_config(&config);
+    let error_pages = ErrorPages::from_config(&config);
+    let listings = Arc::new(ListingCache::new(config.listing_cache_ttl));
+    let live_reload = config.live_reload.then(|| LiveReload::start(root_dir.clone(), follow_symlinks));
+    let cache_policy = if live_reload.is_some() { CachePolicy::no_store() } else { CachePolicy::from_config(&config) };
+    
+    if let Some(live_reload) = &live_reload {
+        let live_reload = live_reload.clone();
+        router.get(LIVE_RELOAD_PATH, move |_| Ok(live_reload.events_response()));
+    }
     
     // Wildcard route to match all requests to the path prefix
     let wildcard_path = format!("{}/*", path_prefix);
@@ -120,10 +1142,19 @@ pub fn add_static_file_routes(router: &mut Router, config: StaticFileConfig) {
     let root_dir_wild = root_dir.clone();
     let path_prefix_wild = path_prefix.clone();
     let index_file_wild = index_file.clone();
-    let cache_control_wild = cache_control.clone();
+    let cache_policy_wild = cache_policy.clone();
     let directory_listing_wild = directory_listing;
     let follow_symlinks_wild = follow_symlinks;
     let max_file_size_wild = max_file_size;
+    let bandwidth_limit_wild = bandwidth_limit;
+    let preload_wild = preload.clone();
+    let cors_policy = CorsPolicy::from_config(&config);
+    let cors_policy_wild = cors_policy.clone();
+    let assets_wild = assets;
+    let content_types_wild = content_types.clone();
+    let error_pages_wild = error_pages.clone();
+    let listings_wild = listings.clone();
+    let live_reload_wild = live_reload.clone();
     
     router.get(&wildcard_path, move |req| {
         // Extract the path from the request
@@ -140,11 +1171,15 @@ pub fn add_static_file_routes(router: &mut Router, config: StaticFileConfig) {
             fs_path.push(segment);
         }
         
+        // A fingerprinted URL names one version of a file, so it can be cached for good
+        let fingerprinted = assets_wild.as_ref().and_then(|assets| assets.resolve(path));
+        if let Some(original) = &fingerprinted {
+            fs_path = root_dir_wild.join(original);
+        }
+        
         // Check if the path exists
         if !fs_path.exists() {
-            let mut response = Response::new(Status::NotFound);
-            response.set_body(format!("File not found: {}", path).as_bytes());
-            return Ok(response);
+            return Ok(error_pages_wild.response(Status::NotFound, &format!("File not found: {}", path)));
         }
         
         // Check if it's a directory
@@ -155,20 +1190,16 @@ pub fn add_static_file_routes(router: &mut Router, config: StaticFileConfig) {
                 fs_path = index_path;
             } else if directory_listing_wild {
                 // Generate a directory listing
-                return serve_directory_listing(&fs_path, &path_prefix_wild, path);
+                return serve_directory_listing(&fs_path, &path_prefix_wild, path, &error_pages_wild, &listings_wild);
             } else {
                 // Directory listing not allowed
-                let mut response = Response::new(Status::Forbidden);
-                response.set_body(b"Directory listing not allowed");
-                return Ok(response);
+                return Ok(error_pages_wild.response(Status::Forbidden, "Directory listing not allowed"));
             }
         }
         
         // Check if it's a symlink and whether symlinks are allowed
         if fs_path.is_symlink() && !follow_symlinks_wild {
-            let mut response = Response::new(Status::Forbidden);
-            response.set_body(b"Symlinks not allowed");
-            return Ok(response);
+            return Ok(error_pages_wild.response(Status::Forbidden, "Symlinks not allowed"));
         }
         
         // Try to read the file
@@ -176,26 +1207,38 @@ pub fn add_static_file_routes(router: &mut Router, config: StaticFileConfig) {
             Ok(contents) => {
                 // Check file size
                 if contents.len() > max_file_size_wild {
-                    let mut response = Response::new(Status::PayloadTooLarge);
-                    response.set_body(b"File too large");
-                    return Ok(response);
+                    return Ok(error_pages_wild.response(Status::PayloadTooLarge, "File too large"));
                 }
                 
                 // Set content type based on file extension
-                let content_type = get_content_type(&fs_path);
+                let content_type = content_types_wild.for_file(&fs_path, &contents);
                 
                 // Create the response
                 let mut response = Response::new(Status::Ok);
-                response.set_header("Content-Type", content_type);
-                response.set_header("Cache-Control", &cache_control_wild);
-                response.set_body(&contents);
+                let cache_control = if fingerprinted.is_some() {
+                    IMMUTABLE_CACHE_CONTROL
+                } else {
+                    cache_policy_wild.for_file(&root_dir_wild, &fs_path)
+                };
+                response.set_header("Cache-Control", cache_control);
+                if live_reload_wild.is_some() && content_type.starts_with("text/html") {
+                    response.set_body(&inject_live_reload(&contents));
+                } else {
+                    response.set_body(&contents);
+                }
+                content_types_wild.apply(&mut response, content_type);
+                cors_policy_wild.apply(&mut response, &root_dir_wild, &fs_path);
+                if let Some(links) = preload_links(&preload_wild, &root_dir_wild, &fs_path, content_type) {
+                    response.set_header("Link", &links);
+                }
+                if let Some(limit) = bandwidth_limit_wild {
+                    response.set_bandwidth_limit(limit);
+                }
                 
                 Ok(response)
             }
             Err(_) => {
-                let mut response = Response::new(Status::InternalServerError);
-                response.set_body(b"Error reading file");
-                Ok(response)
+                Ok(error_pages_wild.response(Status::InternalServerError, "Error reading file"))
             }
         }
     });
@@ -204,8 +1247,15 @@ pub fn add_static_file_routes(router: &mut Router, config: StaticFileConfig) {
     let root_dir_root = root_dir.clone();
     let path_prefix_root = path_prefix.clone();
     let index_file_root = index_file.clone();
-    let cache_control_root = cache_control.clone();
+    let cache_policy_root = cache_policy;
     let directory_listing_root = directory_listing;
+    let bandwidth_limit_root = bandwidth_limit;
+    let preload_root = preload;
+    let cors_policy_root = cors_policy;
+    let content_types_root = content_types;
+    let error_pages_root = error_pages;
+    let listings_root = listings;
+    let live_reload_root = live_reload;
     
     router.get(&path_prefix, move |req| {
         // Try to serve the index file from the root directory
@@ -213,45 +1263,68 @@ pub fn add_static_file_routes(router: &mut Router, config: StaticFileConfig) {
         if index_path.exists() && index_path.is_file() {
             match fs::read(&index_path) {
                 Ok(contents) => {
-                    let content_type = get_content_type(&index_path);
+                    let content_type = content_types_root.for_file(&index_path, &contents);
                     
                     let mut response = Response::new(Status::Ok);
-                    response.set_header("Content-Type", content_type);
-                    response.set_header("Cache-Control", &cache_control_root);
-                    response.set_body(&contents);
+                    response.set_header("Cache-Control", cache_policy_root.for_file(&root_dir_root, &index_path));
+                    if live_reload_root.is_some() && content_type.starts_with("text/html") {
+                        response.set_body(&inject_live_reload(&contents));
+                    } else {
+                        response.set_body(&contents);
+                    }
+                    content_types_root.apply(&mut response, content_type);
+                    cors_policy_root.apply(&mut response, &root_dir_root, &index_path);
+                    if let Some(links) = preload_links(&preload_root, &root_dir_root, &index_path, content_type) {
+                        response.set_header("Link", &links);
+                    }
+                    if let Some(limit) = bandwidth_limit_root {
+                        response.set_bandwidth_limit(limit);
+                    }
                     
                     Ok(response)
                 }
                 Err(_) => {
-                    let mut response = Response::new(Status::InternalServerError);
-                    response.set_body(b"Error reading index file");
-                    Ok(response)
+                    Ok(error_pages_root.response(Status::InternalServerError, "Error reading index file"))
                 }
             }
         } else if directory_listing_root {
             // Generate a directory listing for the root directory
-            serve_directory_listing(&root_dir_root, &path_prefix_root, "")
+            serve_directory_listing(&root_dir_root, &path_prefix_root, "", &error_pages_root, &listings_root)
         } else {
             // Directory listing not allowed
-            let mut response = Response::new(Status::Forbidden);
-            response.set_body(b"Directory listing not allowed");
-            Ok(response)
+            Ok(error_pages_root.response(Status::Forbidden, "Directory listing not allowed"))
         }
     });
 }
 
 /// Serve a directory listing
-fn serve_directory_listing(dir_path: &Path, path_prefix: &str, relative_path: &str) -> ServerResult<Response> {
-    // Read the directory
-    let entries = match fs::read_dir(dir_path) {
-        Ok(entries) => entries,
+fn serve_directory_listing(
+    dir_path: &Path,
+    path_prefix: &str,
+    relative_path: &str,
+    error_pages: &ErrorPages,
+    listings: &ListingCache,
+) -> ServerResult<Response> {
+    let html = match listings.get_or_render(dir_path, path_prefix, relative_path) {
+        Ok(html) => html,
         Err(_) => {
-            let mut response = Response::new(Status::InternalServerError);
-            response.set_body(b"Error reading directory");
-            return Ok(response);
+            return Ok(error_pages.response(Status::InternalServerError, "Error reading directory"));
         }
     };
     
+    // Create the response
+    let mut response = Response::new(Status::Ok);
+    response.set_body(html.as_bytes());
+    response.set_header("Content-Type", "text/html");
+    
+    Ok(response)
+}
+
+/// Generate the HTML listing a directory's entries
+fn render_directory_listing(dir_path: &Path, path_prefix: &str, relative_path: &str) -> io::Result<String> {
+    // Read the directory
+    let entries = fs::read_dir(dir_path)?;
+    
     // Build the HTML for the directory listing
     let mut html = String::new();
     html.push_str("<!DOCTYPE html><html><head><title>Directory Listing</title>");
@@ -336,12 +1409,7 @@ fn serve_directory_listing(dir_path: &Path, path_prefix: &str, relative_path: &s
     
     html.push_str("</ul></body></html>");
     
-    // Create the response
-    let mut response = Response::new(Status::Ok);
-    response.set_header("Content-Type", "text/html");
-    response.set_body(html.as_bytes());
-    
-    Ok(response)
+    Ok(html)
 }
 
 /// Create a static file server middleware
@@ -355,9 +1423,36 @@ pub fn static_files_middleware(
     let follow_symlinks = config.follow_symlinks;
     let directory_listing = config.directory_listing;
     let max_file_size = config.max_file_size;
-    let cache_control = config.cache_control.clone();
+    let bandwidth_limit = config.bandwidth_limit;
+    let content_types = ContentTypes::from_config(&config);
+    let error_pages = ErrorPages::from_config(&config);
+    let listings = ListingCache::new(config.listing_cache_ttl);
+    let embedded = EmbeddedSite::from_config(&config);
+    let live_reload = (config.live_reload && embedded.is_none())
+        .then(|| LiveReload::start(root_dir.clone(), follow_symlinks));
+    let cache_policy = if live_reload.is_some() { CachePolicy::no_store() } else { CachePolicy::from_config(&config) };
+    let cors_policy = CorsPolicy::from_config(&config);
+    let preload = config.preload;
+    let assets = config.assets;
     
     move |req, next| {
+        if let Some(site) = &embedded {
+            let path = match req.path().strip_prefix(path_prefix.as_str()) {
+                Some(path) if req.method == Method::Get && (path.is_empty() || path.starts_with('/')) => path,
+                _ => return next(req),
+            };
+            return match site.serve(path) {
+                Some(response) => Ok(response),
+                None => next(req),
+            };
+        }
+        
+        if let Some(live_reload) = &live_reload {
+            if req.method == Method::Get && req.path() == LIVE_RELOAD_PATH {
+                return Ok(live_reload.events_response());
+            }
+        }
+        
         // Check if the request is for a static file
         if req.method == Method::Get && req.uri.starts_with(&path_prefix) {
             // Extract the path from the request
@@ -374,6 +1469,12 @@ pub fn static_files_middleware(
                 fs_path.push(segment);
             }
             
+            // A fingerprinted URL names one version of a file, so it can be cached for good
+            let fingerprinted = assets.as_ref().and_then(|assets| assets.resolve(path));
+            if let Some(original) = &fingerprinted {
+                fs_path = root_dir.join(original);
+            }
+            
             // If the path exists, serve it
             if fs_path.exists() {
                 // Check if it's a directory
@@ -384,7 +1485,7 @@ pub fn static_files_middleware(
                         fs_path = index_path;
                     } else if directory_listing {
                         // Generate a directory listing
-                        return serve_directory_listing(&fs_path, &path_prefix, path);
+                        return serve_directory_listing(&fs_path, &path_prefix, path, &error_pages, &listings);
                     } else {
                         // Directory listing not allowed, pass to next middleware
                         return next(req);
@@ -401,19 +1502,33 @@ pub fn static_files_middleware(
                     Ok(contents) => {
                         // Check file size
                         if contents.len() > max_file_size {
-                            let mut response = Response::new(Status::PayloadTooLarge);
-                            response.set_body(b"File too large");
-                            return Ok(response);
+                            return Ok(error_pages.response(Status::PayloadTooLarge, "File too large"));
                         }
                         
                         // Set content type based on file extension
-                        let content_type = get_content_type(&fs_path);
+                        let content_type = content_types.for_file(&fs_path, &contents);
                         
                         // Create the response
                         let mut response = Response::new(Status::Ok);
-                        response.set_header("Content-Type", content_type);
-                        response.set_header("Cache-Control", &cache_control);
-                        response.set_body(&contents);
+                        let cache_control = if fingerprinted.is_some() {
+                            IMMUTABLE_CACHE_CONTROL
+                        } else {
+                            cache_policy.for_file(&root_dir, &fs_path)
+                        };
+                        response.set_header("Cache-Control", cache_control);
+                        if live_reload.is_some() && content_type.starts_with("text/html") {
+                            response.set_body(&inject_live_reload(&contents));
+                        } else {
+                            response.set_body(&contents);
+                        }
+                        content_types.apply(&mut response, content_type);
+                        cors_policy.apply(&mut response, &root_dir, &fs_path);
+                        if let Some(links) = preload_links(&preload, &root_dir, &fs_path, content_type) {
+                            response.set_header("Link", &links);
+                        }
+                        if let Some(limit) = bandwidth_limit {
+                            response.set_bandwidth_limit(limit);
+                        }
                         
                         return Ok(response);
                     }
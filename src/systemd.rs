@@ -0,0 +1,112 @@
+use crate::acceptor::ConnectionAcceptor;
+use crate::error::{ServerError, ServerResult};
+use crate::upgrade::set_cloexec;
+use std::env;
+use std::net::TcpListener;
+use std::os::unix::io::{FromRawFd, RawFd};
+use std::os::unix::net::UnixDatagram;
+use std::time::Duration;
+
+/// The first file descriptor passed by systemd socket activation
+const SD_LISTEN_FDS_START: RawFd = 3;
+
+/// Take over the listening sockets passed by systemd socket activation
+///
+/// Returns an empty list when the process wasn't socket activated. The
+/// `LISTEN_*` variables are removed so child processes don't pick them up.
+pub fn listen_fds() -> ServerResult<Vec<ConnectionAcceptor>> {
+    let listen_pid = env::var("LISTEN_PID").ok();
+    let listen_fds = env::var("LISTEN_FDS").ok();
+    
+    env::remove_var("LISTEN_PID");
+    env::remove_var("LISTEN_FDS");
+    env::remove_var("LISTEN_FDNAMES");
+    
+    // The sockets are only meant for us if the pid matches
+    let pid_matches = listen_pid
+        .and_then(|pid| pid.parse::<u32>().ok())
+        .map(|pid| pid == std::process::id())
+        .unwrap_or(false);
+    
+    let count = match listen_fds {
+        Some(count) if pid_matches => count.parse::<RawFd>().map_err(|_| {
+            ServerError::Config(format!("Invalid LISTEN_FDS value: {}", count))
+        })?,
+        _ => return Ok(Vec::new()),
+    };
+    
+    let mut acceptors = Vec::with_capacity(count as usize);
+    for fd in SD_LISTEN_FDS_START..SD_LISTEN_FDS_START + count {
+        set_cloexec(fd, true)?;
+        let listener = unsafe { TcpListener::from_raw_fd(fd) };
+        acceptors.push(ConnectionAcceptor::from_listener(listener)?);
+    }
+    
+    Ok(acceptors)
+}
+
+/// Send a state update to the service manager
+///
+/// Returns `Ok(false)` when not running under systemd (no `NOTIFY_SOCKET`).
+pub fn notify(state: &str) -> ServerResult<bool> {
+    let path = match env::var("NOTIFY_SOCKET") {
+        Ok(path) if !path.is_empty() => path,
+        _ => return Ok(false),
+    };
+    
+    let socket = UnixDatagram::unbound()?;
+    
+    if let Some(name) = path.strip_prefix('@') {
+        // Abstract namespace socket
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::linux::net::SocketAddrExt;
+            let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            socket.send_to_addr(state.as_bytes(), &addr)?;
+        }
+        
+        #[cfg(not(target_os = "linux"))]
+        {
+            return Err(ServerError::Config(format!(
+                "Abstract notify socket not supported on this platform: @{}",
+                name
+            )));
+        }
+    } else {
+        socket.send_to(state.as_bytes(), &path)?;
+    }
+    
+    Ok(true)
+}
+
+/// Tell systemd the server has finished starting up
+pub fn notify_ready() -> ServerResult<bool> {
+    notify(&format!("READY=1\nMAINPID={}", std::process::id()))
+}
+
+/// Tell systemd the server is shutting down
+pub fn notify_stopping() -> ServerResult<bool> {
+    notify("STOPPING=1")
+}
+
+/// Send a watchdog keep-alive ping
+pub fn notify_watchdog() -> ServerResult<bool> {
+    notify("WATCHDOG=1")
+}
+
+/// Get the watchdog timeout configured for this service, if any
+///
+/// Pings should be sent at least every half of this interval.
+pub fn watchdog_interval() -> Option<Duration> {
+    if let Ok(pid) = env::var("WATCHDOG_PID") {
+        if pid.parse::<u32>().ok() != Some(std::process::id()) {
+            return None;
+        }
+    }
+    
+    env::var("WATCHDOG_USEC")
+        .ok()
+        .and_then(|usec| usec.parse::<u64>().ok())
+        .filter(|&usec| usec > 0)
+        .map(Duration::from_micros)
+}
\ No newline at end of file
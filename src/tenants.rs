@@ -0,0 +1,407 @@
+//! Multi-tenant request handling
+//!
+//! Each request is matched to a tenant by API key, hostname or tenant
+//! header, then held to that tenant's quotas before it goes on. Tenants can
+//! have some paths routed elsewhere, such as to their own upstream.
+
+use crate::error::{ErrorResponse, ServerError, ServerResult};
+use crate::http::{Request, Response, Status};
+use crate::http_client::HttpUrl;
+use crate::metrics::{Counter, Gauge, Histogram, MetricsRegistry};
+use crate::middleware::MiddlewareNext;
+use crate::proxy::{proxy_handler, Proxy};
+use crate::secrets::Secret;
+use crate::rate_limit::{MemoryRateLimiter, RateLimit, RateLimitBackend, SyncedRateLimiter};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// How often tenant quotas are synced with a shared rate limiter
+pub const RATE_LIMIT_SYNC_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Settings for one tenant
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TenantConfig {
+    /// Name the tenant goes by in metrics and the tenant header
+    pub id: String,
+    
+    /// Hostnames served for the tenant; `*.example.com` matches any subdomain
+    pub hosts: Vec<String>,
+    
+    /// API keys that identify the tenant, each of which may be an `@file:`
+    /// or `@env:` reference
+    pub api_keys: Vec<Secret>,
+    
+    /// Requests per second allowed on average (None = unlimited)
+    pub rate_per_sec: Option<u64>,
+    
+    /// Requests allowed at once above the average rate (defaults to one second's worth)
+    pub burst: Option<u64>,
+    
+    /// Requests handled at the same time (None = unlimited)
+    pub max_concurrent: Option<usize>,
+    
+    /// Path prefixes proxied to a tenant's own upstream instead (prefix -> URL);
+    /// `/api` covers `/api` and `/api/...` but not `/apis`
+    pub routes: BTreeMap<String, String>,
+}
+
+/// Settings for telling tenants apart
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TenantsConfig {
+    /// Header carrying a tenant's API key
+    pub api_key_header: String,
+    
+    /// Header naming the tenant outright (None = not trusted). Only enable
+    /// this behind a gateway that sets it, since any client can send it.
+    pub tenant_header: Option<String>,
+    
+    /// Reject requests that match no tenant instead of passing them on
+    pub require_tenant: bool,
+    
+    pub tenants: Vec<TenantConfig>,
+}
+
+impl Default for TenantsConfig {
+    fn default() -> Self {
+        Self {
+            api_key_header: "X-Api-Key".to_string(),
+            tenant_header: None,
+            require_tenant: false,
+            tenants: Vec::new(),
+        }
+    }
+}
+
+/// The tenant a request was matched to, attached as a request extension
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TenantId(pub String);
+
+impl TenantId {
+    /// Get the tenant a request was matched to, if any
+    pub fn of(request: &Request) -> Option<&str> {
+        request.extensions.get::<TenantId>().map(|tenant| tenant.0.as_str())
+    }
+}
+
+/// A tenant and its quota state
+struct Tenant {
+    id: String,
+    /// Key of the tenant's rate limit bucket, `tenant:<id>`
+    bucket: String,
+    limit: Option<RateLimit>,
+    max_concurrent: Option<usize>,
+    in_flight: AtomicUsize,
+    /// Path prefixes handled differently for this tenant, longest first
+    routes: Vec<(String, MiddlewareNext)>,
+    /// Set by `Tenants::with_metrics`
+    metrics: Option<TenantMetrics>,
+}
+
+/// A tenant's metric handles, looked up once rather than per request
+struct TenantMetrics {
+    requests: Arc<Counter>,
+    rejected: Arc<Counter>,
+    errors: Arc<Counter>,
+    in_flight: Arc<Gauge>,
+    latency_us: Arc<Histogram>,
+}
+
+impl TenantMetrics {
+    fn new(registry: &MetricsRegistry, id: &str) -> Self {
+        Self {
+            requests: registry.counter(&format!("tenant.{}.requests", id)),
+            rejected: registry.counter(&format!("tenant.{}.rejected", id)),
+            errors: registry.counter(&format!("tenant.{}.errors", id)),
+            in_flight: registry.gauge(&format!("tenant.{}.in_flight", id)),
+            latency_us: registry.exponential_histogram(&format!("tenant.{}.latency_us", id), 1.0, 2.0, 24),
+        }
+    }
+}
+
+/// Why a request was turned away
+enum Rejection {
+    Rate(u64),
+    Concurrency,
+}
+
+/// Tenants known to the server, and how to tell which one sent a request
+///
+/// Requests are matched by API key first, then by hostname, then by the
+/// tenant header if one is trusted. Rate quotas are kept in memory unless
+/// another backend is given, such as Redis to share them between replicas;
+/// their buckets are keyed `tenant:<id>`. A shared backend is synced in the
+/// background rather than asked on every request (see `SyncedRateLimiter`),
+/// so admitting a request never waits on the network. With metrics, each tenant gets
+/// `tenant.<id>.requests`, `.rejected`, `.errors` (5xx or handler errors)
+/// and `.latency_us`, and a `tenant.<id>.in_flight` gauge.
+pub struct Tenants {
+    tenants: Vec<Tenant>,
+    by_key: HashMap<String, usize>,
+    by_host: HashMap<String, usize>,
+    /// Wildcard host suffixes (such as `.example.com`), longest first
+    by_host_suffix: Vec<(String, usize)>,
+    by_id: HashMap<String, usize>,
+    api_key_header: String,
+    tenant_header: Option<String>,
+    require_tenant: bool,
+    limiter: Arc<dyn RateLimitBackend>,
+}
+
+impl Tenants {
+    /// Set up the tenants in `config`
+    ///
+    /// Fails on duplicate tenant IDs, API keys or hosts, and on route URLs
+    /// that don't parse.
+    pub fn new(config: TenantsConfig) -> ServerResult<Self> {
+        let mut tenants = Self {
+            tenants: Vec::new(),
+            by_key: HashMap::new(),
+            by_host: HashMap::new(),
+            by_host_suffix: Vec::new(),
+            by_id: HashMap::new(),
+            api_key_header: config.api_key_header,
+            tenant_header: config.tenant_header,
+            require_tenant: config.require_tenant,
+            limiter: Arc::new(MemoryRateLimiter::new()),
+        };
+        for tenant in config.tenants {
+            tenants.add(tenant)?;
+        }
+        Ok(tenants)
+    }
+    
+    /// Record per-tenant metrics in `registry`
+    pub fn with_metrics(mut self, registry: Arc<MetricsRegistry>) -> Self {
+        for tenant in &mut self.tenants {
+            tenant.metrics = Some(TenantMetrics::new(&registry, &tenant.id));
+        }
+        self
+    }
+    
+    /// Share rate quotas through `limiter` instead of keeping them only in memory
+    ///
+    /// Requests are admitted from local buckets that are synced with
+    /// `limiter` every `RATE_LIMIT_SYNC_INTERVAL`.
+    pub fn with_rate_limiter(mut self, limiter: Arc<dyn RateLimitBackend>) -> Self {
+        self.limiter = Arc::new(SyncedRateLimiter::new(limiter, RATE_LIMIT_SYNC_INTERVAL));
+        self
+    }
+    
+    /// Handle requests from `tenant` under `prefix` with `handler` instead of the usual route
+    pub fn with_route<F>(mut self, tenant: &str, prefix: &str, handler: F) -> ServerResult<Self>
+    where
+        F: Fn(&Request) -> ServerResult<Response> + Send + Sync + 'static,
+    {
+        let index = *self
+            .by_id
+            .get(tenant)
+            .ok_or_else(|| ServerError::Config(format!("Unknown tenant {}", tenant)))?;
+        self.tenants[index].add_route(prefix, Arc::new(handler));
+        Ok(self)
+    }
+    
+    fn add(&mut self, config: TenantConfig) -> ServerResult<()> {
+        let index = self.tenants.len();
+        if config.id.is_empty() || self.by_id.insert(config.id.clone(), index).is_some() {
+            return Err(ServerError::Config(format!("Tenant ID {:?} is empty or taken", config.id)));
+        }
+        for key in &config.api_keys {
+            if self.by_key.insert(key.expose().to_string(), index).is_some() {
+                return Err(ServerError::Config(format!("API key of tenant {} is shared", config.id)));
+            }
+        }
+        for host in &config.hosts {
+            let host = host.to_ascii_lowercase();
+            let taken = match host.strip_prefix('*') {
+                Some(suffix) => {
+                    let taken = self.by_host_suffix.iter().any(|(existing, _)| *existing == suffix);
+                    self.by_host_suffix.push((suffix.to_string(), index));
+                    taken
+                }
+                None => self.by_host.insert(host.clone(), index).is_some(),
+            };
+            if taken {
+                return Err(ServerError::Config(format!("Host {} is claimed by two tenants", host)));
+            }
+        }
+        self.by_host_suffix.sort_by_key(|(suffix, _)| std::cmp::Reverse(suffix.len()));
+        
+        let limit = config.rate_per_sec.map(|rate| RateLimit {
+            rate_per_sec: rate,
+            burst: config.burst.unwrap_or(rate),
+        });
+        let mut tenant = Tenant {
+            bucket: format!("tenant:{}", config.id),
+            id: config.id,
+            limit,
+            max_concurrent: config.max_concurrent,
+            in_flight: AtomicUsize::new(0),
+            routes: Vec::new(),
+            metrics: None,
+        };
+        for (prefix, url) in &config.routes {
+            let proxy = Arc::new(Proxy::new(HttpUrl::parse(url)?));
+            tenant.add_route(prefix, Arc::new(proxy_handler(proxy)));
+        }
+        self.tenants.push(tenant);
+        Ok(())
+    }
+    
+    /// Get the IDs of every tenant
+    pub fn ids(&self) -> Vec<&str> {
+        self.tenants.iter().map(|tenant| tenant.id.as_str()).collect()
+    }
+    
+    /// Get the number of requests `tenant` has in progress
+    pub fn in_flight(&self, tenant: &str) -> Option<usize> {
+        let index = *self.by_id.get(tenant)?;
+        Some(self.tenants[index].in_flight.load(Ordering::Relaxed))
+    }
+    
+    /// Find the tenant that sent `request`
+    pub fn identify(&self, request: &Request) -> Option<&str> {
+        self.identify_index(request).map(|index| self.tenants[index].id.as_str())
+    }
+    
+    fn identify_index(&self, request: &Request) -> Option<usize> {
+        if let Some(index) = request.get_header(&self.api_key_header).and_then(|key| self.by_key.get(key.trim())) {
+            return Some(*index);
+        }
+        
+        let host = request.get_header("host").or(request.host.as_ref());
+        if let Some(host) = host {
+            let host = strip_port(host).to_ascii_lowercase();
+            if let Some(index) = self.by_host.get(&host) {
+                return Some(*index);
+            }
+            let wildcard = self.by_host_suffix.iter().find(|(suffix, _)| host.ends_with(suffix.as_str()));
+            if let Some((_, index)) = wildcard {
+                return Some(*index);
+            }
+        }
+        
+        let header = self.tenant_header.as_deref()?;
+        request.get_header(header).and_then(|id| self.by_id.get(id.trim())).copied()
+    }
+}
+
+impl Tenant {
+    fn add_route(&mut self, prefix: &str, handler: MiddlewareNext) {
+        self.routes.push((prefix.to_string(), handler));
+        self.routes.sort_by_key(|(prefix, _)| std::cmp::Reverse(prefix.len()));
+    }
+    
+    /// Find the route with the longest prefix covering `path`, whole segments only
+    fn route(&self, path: &str) -> Option<&MiddlewareNext> {
+        self.routes
+            .iter()
+            .find(|(prefix, _)| path_has_prefix(path, prefix))
+            .map(|(_, handler)| handler)
+    }
+    
+    fn record_in_flight(&self) {
+        if let Some(metrics) = &self.metrics {
+            metrics.in_flight.set(self.in_flight.load(Ordering::Relaxed));
+        }
+    }
+    
+    /// Take a slot for a request, or say why there is none
+    ///
+    /// Requests are let through if the rate limiter fails, so a broken
+    /// custom backend doesn't take every tenant down with it.
+    fn admit(&self, limiter: &dyn RateLimitBackend) -> Result<(), Rejection> {
+        if let Some(limit) = self.limit {
+            match limiter.acquire(&self.bucket, limit, 1) {
+                Ok(decision) if !decision.allowed => {
+                    return Err(Rejection::Rate(decision.retry_after.as_secs_f64().ceil().max(1.0) as u64));
+                }
+                Ok(_) => {}
+                Err(e) => log::warn!("Rate limiter failed for tenant {}, allowing request: {}", self.id, e),
+            }
+        }
+        let in_flight = self.in_flight.fetch_add(1, Ordering::Relaxed) + 1;
+        if self.max_concurrent.is_some_and(|max| in_flight > max) {
+            self.in_flight.fetch_sub(1, Ordering::Relaxed);
+            return Err(Rejection::Concurrency);
+        }
+        Ok(())
+    }
+}
+
+/// Check whether `path` is `prefix` or lies below it
+fn path_has_prefix(path: &str, prefix: &str) -> bool {
+    let prefix = prefix.trim_end_matches('/');
+    path.strip_prefix(prefix).is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// Drop any port from a `Host` value, keeping IPv6 literals whole
+fn strip_port(host: &str) -> &str {
+    match host.rsplit_once(':') {
+        Some((name, port)) if !name.is_empty() && !port.contains(']') && port.bytes().all(|b| b.is_ascii_digit()) => {
+            name
+        }
+        _ => host,
+    }
+}
+
+/// Tenant middleware - matches requests to tenants and holds them to their quotas
+///
+/// Matched requests go on with a `TenantId` extension. Requests over a
+/// tenant's rate get `429` with `Retry-After`, as do requests over its
+/// concurrency limit; a request counts against the limit until its handler
+/// returns. Requests on one of the tenant's own routes go there instead of
+/// down the chain. Unmatched requests pass through untouched, or get `401`
+/// when a tenant is required.
+pub fn tenant_middleware(
+    tenants: Arc<Tenants>,
+) -> impl Fn(&Request, MiddlewareNext) -> ServerResult<Response> + Send + Sync {
+    move |request, next| {
+        let index = match tenants.identify_index(request) {
+            Some(index) => index,
+            None if tenants.require_tenant => {
+                let body = ErrorResponse::new("unknown_tenant", "The request matches no tenant");
+                return Ok(body.into_response(Status::Unauthorized));
+            }
+            None => return next(request),
+        };
+        let tenant = &tenants.tenants[index];
+        
+        if let Err(rejection) = tenant.admit(tenants.limiter.as_ref()) {
+            if let Some(metrics) = &tenant.metrics {
+                metrics.rejected.increment(1);
+            }
+            let (code, message, retry_after) = match rejection {
+                Rejection::Rate(seconds) => ("rate_limited", "Tenant request rate exceeded", seconds),
+                Rejection::Concurrency => ("concurrency_limited", "Too many requests in progress for tenant", 1),
+            };
+            let mut response = ErrorResponse::new(code, message).into_response(Status::TooManyRequests);
+            response.set_header("Retry-After", &retry_after.to_string());
+            return Ok(response);
+        }
+        tenant.record_in_flight();
+        
+        let start = Instant::now();
+        let mut request = request.clone();
+        request.extensions.insert(TenantId(tenant.id.clone()));
+        let response = match tenant.route(request.path()) {
+            Some(handler) => handler(&request),
+            None => next(&request),
+        };
+        tenant.in_flight.fetch_sub(1, Ordering::Relaxed);
+        tenant.record_in_flight();
+        
+        if let Some(metrics) = &tenant.metrics {
+            metrics.requests.increment(1);
+            if response.as_ref().map_or(true, |response| response.status as u16 >= 500) {
+                metrics.errors.increment(1);
+            }
+            metrics.latency_us.record(start.elapsed().as_micros() as f64);
+        }
+        response
+    }
+}
\ No newline at end of file
@@ -0,0 +1,87 @@
+use std::time::{Duration, Instant};
+
+/// A token bucket used to shape outbound traffic to a fixed byte rate
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    /// Refill rate in bytes per second
+    rate: u64,
+    
+    /// Maximum number of tokens the bucket can hold
+    burst: u64,
+    
+    /// Tokens currently available
+    tokens: f64,
+    
+    /// When the bucket was last refilled
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    /// Create a new bucket that allows `rate` bytes per second with a burst of one second
+    pub fn new(rate: u64) -> Self {
+        Self::with_burst(rate, rate)
+    }
+    
+    /// Create a new bucket with an explicit burst size
+    pub fn with_burst(rate: u64, burst: u64) -> Self {
+        let burst = burst.max(1);
+        
+        Self {
+            rate: rate.max(1),
+            burst,
+            tokens: burst as f64,
+            last_refill: Instant::now(),
+        }
+    }
+    
+    /// Add the tokens accumulated since the last refill
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate as f64).min(self.burst as f64);
+        self.last_refill = now;
+    }
+    
+    /// Get the number of bytes that can be sent right now
+    pub fn available(&mut self) -> usize {
+        self.refill();
+        self.tokens as usize
+    }
+    
+    /// Consume tokens for bytes that were actually sent
+    pub fn consume(&mut self, bytes: usize) {
+        self.tokens = (self.tokens - bytes as f64).max(0.0);
+    }
+    
+    /// Set the tokens available now, such as to match a bucket kept elsewhere
+    pub fn set_available(&mut self, tokens: u64) {
+        self.refill();
+        self.tokens = tokens.min(self.burst) as f64;
+    }
+    
+    /// Get the time until at least one byte can be sent
+    pub fn time_until_available(&mut self) -> Duration {
+        self.time_until(1)
+    }
+    
+    /// Get the time until `tokens` can be consumed at once, capped at the burst size
+    pub fn time_until(&mut self, tokens: u64) -> Duration {
+        self.refill();
+        let needed = tokens.min(self.burst) as f64;
+        if self.tokens >= needed {
+            return Duration::ZERO;
+        }
+        
+        Duration::from_secs_f64((needed - self.tokens) / self.rate as f64)
+    }
+    
+    /// Get the configured rate in bytes per second
+    pub fn rate(&self) -> u64 {
+        self.rate
+    }
+    
+    /// Get the maximum number of tokens the bucket can hold
+    pub fn burst(&self) -> u64 {
+        self.burst
+    }
+}
\ No newline at end of file
@@ -0,0 +1,193 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// A point in the life of a request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// The connection was accepted, or the previous response on it completed
+    Accepted,
+    HeadersParsed,
+    HandlerStart,
+    HandlerEnd,
+    FirstByteWritten,
+    Completed,
+}
+
+impl Phase {
+    /// All phases in the order they happen
+    pub const ALL: [Phase; 6] = [
+        Phase::Accepted,
+        Phase::HeadersParsed,
+        Phase::HandlerStart,
+        Phase::HandlerEnd,
+        Phase::FirstByteWritten,
+        Phase::Completed,
+    ];
+    
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// The named spans between phases
+const SPANS: [(&str, Phase, Phase); 6] = [
+    ("parse", Phase::Accepted, Phase::HeadersParsed),
+    ("queue", Phase::HeadersParsed, Phase::HandlerStart),
+    ("handler", Phase::HandlerStart, Phase::HandlerEnd),
+    ("first_byte", Phase::HandlerEnd, Phase::FirstByteWritten),
+    ("send", Phase::FirstByteWritten, Phase::Completed),
+    ("total", Phase::Accepted, Phase::Completed),
+];
+
+/// Timestamped phase markers for a single request
+#[derive(Debug, Clone)]
+pub struct RequestTimeline {
+    marks: [Option<Instant>; 6],
+}
+
+impl RequestTimeline {
+    /// Names of the spans, in the order `span_durations` returns them
+    pub const SPAN_NAMES: [&'static str; 6] = {
+        let mut names = [""; 6];
+        let mut index = 0;
+        while index < SPANS.len() {
+            names[index] = SPANS[index].0;
+            index += 1;
+        }
+        names
+    };
+    
+    /// Start a new timeline, marking the request as accepted now
+    pub fn new() -> Self {
+        let mut timeline = Self { marks: [None; 6] };
+        timeline.mark(Phase::Accepted);
+        timeline
+    }
+    
+    /// Record that a phase was reached now, keeping the first timestamp
+    pub fn mark(&mut self, phase: Phase) {
+        let slot = &mut self.marks[phase.index()];
+        if slot.is_none() {
+            *slot = Some(Instant::now());
+        }
+    }
+    
+    /// Get when a phase was reached
+    pub fn get(&self, phase: Phase) -> Option<Instant> {
+        self.marks[phase.index()]
+    }
+    
+    /// Check whether a phase was reached
+    pub fn has(&self, phase: Phase) -> bool {
+        self.get(phase).is_some()
+    }
+    
+    /// Get the time between two phases, if both were reached
+    pub fn between(&self, from: Phase, to: Phase) -> Option<Duration> {
+        match (self.get(from), self.get(to)) {
+            (Some(from), Some(to)) => Some(to.saturating_duration_since(from)),
+            _ => None,
+        }
+    }
+    
+    /// Get the named spans between phases that have been reached
+    ///
+    /// `parse` covers reading and parsing the request, `queue` the wait until
+    /// the handler runs, `handler` the handler itself, `first_byte` encoding
+    /// until the first byte hit the socket, `send` the rest of the response,
+    /// and `total` the whole request.
+    pub fn spans(&self) -> Vec<(&'static str, Duration)> {
+        SPANS
+            .iter()
+            .filter_map(|&(name, from, to)| self.between(from, to).map(|d| (name, d)))
+            .collect()
+    }
+    
+    /// Get every span, or None where it wasn't reached, without allocating
+    ///
+    /// The spans are in the order of `SPAN_NAMES`.
+    pub fn span_durations(&self) -> [Option<Duration>; 6] {
+        SPANS.map(|(_, from, to)| self.between(from, to))
+    }
+    
+    /// Format the spans known so far as a `Server-Timing` header value
+    ///
+    /// Called before the response is written, so `total` is measured up to
+    /// the end of the handler.
+    pub fn server_timing(&self) -> String {
+        let mut entries: Vec<String> = self
+            .spans()
+            .into_iter()
+            .map(|(name, duration)| format_server_timing(name, duration))
+            .collect();
+        
+        if !self.has(Phase::Completed) {
+            if let Some(total) = self.between(Phase::Accepted, Phase::HandlerEnd) {
+                entries.push(format_server_timing("total", total));
+            }
+        }
+        
+        entries.join(", ")
+    }
+}
+
+impl Default for RequestTimeline {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Format one `Server-Timing` entry with the duration in milliseconds
+pub fn format_server_timing(name: &str, duration: Duration) -> String {
+    format!("{};dur={:.3}", name, duration.as_secs_f64() * 1000.0)
+}
+
+/// Named timings collected while handling a request, for the `Server-Timing` header
+///
+/// Clones share the same entries, so handlers can record timings through the
+/// `&Request` they are given and middleware sees them after `next` returns.
+#[derive(Debug, Clone, Default)]
+pub struct ServerTimings {
+    entries: Arc<Mutex<Vec<(String, Duration)>>>,
+}
+
+impl ServerTimings {
+    /// Create an empty set of timings
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Record a named duration
+    pub fn record(&self, name: &str, duration: Duration) {
+        self.entries.lock().unwrap().push((name.to_string(), duration));
+    }
+    
+    /// Record a named duration given in milliseconds
+    pub fn record_ms(&self, name: &str, millis: f64) {
+        self.record(name, Duration::from_secs_f64(millis.max(0.0) / 1000.0));
+    }
+    
+    /// Time a closure and record how long it took
+    pub fn measure<T>(&self, name: &str, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.record(name, start.elapsed());
+        result
+    }
+    
+    /// Check whether nothing has been recorded
+    pub fn is_empty(&self) -> bool {
+        self.entries.lock().unwrap().is_empty()
+    }
+    
+    /// Format the recorded timings as a `Server-Timing` header value
+    pub fn header_value(&self) -> String {
+        self.entries
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, duration)| format_server_timing(name, *duration))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
\ No newline at end of file
@@ -0,0 +1,140 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// A hashed timer wheel holding one deadline per id
+///
+/// An id goes in the slot for the tick its deadline falls in, and expiring
+/// only visits the slots for the ticks that have passed, so neither costs
+/// more with more ids waiting. Deadlines are rounded up to a whole tick:
+/// ids come out up to one tick late, never early. Deadlines further off
+/// than a turn of the wheel stay in their slot while it comes round.
+///
+/// Moving a deadline later or cancelling it leaves its old entry behind,
+/// to be dropped when its slot comes up; callers that re-check an expired
+/// id and schedule it again never have to find it first.
+#[derive(Debug)]
+pub struct TimerWheel {
+    tick: Duration,
+    start: Instant,
+    /// Entries by slot, each with the tick it was scheduled for
+    slots: Vec<Vec<(usize, u64)>>,
+    /// The tick each id is due in
+    due: HashMap<usize, u64>,
+    /// The first tick not yet expired
+    current: u64,
+}
+
+impl TimerWheel {
+    /// Create a wheel of `slots` slots of `tick` each
+    pub fn new(tick: Duration, slots: usize) -> Self {
+        Self {
+            tick: tick.max(Duration::from_micros(1)),
+            start: Instant::now(),
+            slots: vec![Vec::new(); slots.max(1)],
+            due: HashMap::new(),
+            current: 0,
+        }
+    }
+    
+    /// Get the number of ids with a deadline
+    pub fn len(&self) -> usize {
+        self.due.len()
+    }
+    
+    /// Check whether no id has a deadline
+    pub fn is_empty(&self) -> bool {
+        self.due.is_empty()
+    }
+    
+    /// Get the deadline `id` is due by, rounded up to its tick
+    pub fn deadline(&self, id: usize) -> Option<Instant> {
+        self.due.get(&id).map(|&tick| self.instant_of(tick))
+    }
+    
+    /// Set `id`'s deadline to `at`, unless it already has one as early
+    ///
+    /// A deadline already past is due once the next tick starts.
+    pub fn schedule(&mut self, id: usize, at: Instant) {
+        let tick = self.tick_of(at).max(self.current);
+        if self.due.get(&id).is_some_and(|&due| due <= tick) {
+            return;
+        }
+        self.due.insert(id, tick);
+        let slot = self.slot_of(tick);
+        self.slots[slot].push((id, tick));
+    }
+    
+    /// Drop `id`'s deadline
+    pub fn cancel(&mut self, id: usize) {
+        self.due.remove(&id);
+    }
+    
+    /// Take the ids whose deadlines have passed by `now`
+    pub fn expire(&mut self, now: Instant) -> Vec<usize> {
+        let mut expired = Vec::new();
+        let last = self.elapsed_ticks(now);
+        if last < self.current {
+            return expired;
+        }
+        
+        // After a whole turn or more, every slot holds something due
+        let turns = (last - self.current + 1).min(self.slots.len() as u64);
+        for tick in self.current..self.current + turns {
+            let slot = self.slot_of(tick);
+            let due = &mut self.due;
+            self.slots[slot].retain(|&(id, scheduled)| {
+                if scheduled > last {
+                    return true;
+                }
+                // Entries left behind by a cancelled or moved deadline just go
+                if due.get(&id) == Some(&scheduled) {
+                    due.remove(&id);
+                    expired.push(id);
+                }
+                false
+            });
+        }
+        self.current = last + 1;
+        expired
+    }
+    
+    /// Get when the next deadline due within `horizon` is, if there is one
+    ///
+    /// Only the slots for ticks up to `horizon` away are looked at, so this
+    /// costs the same however many ids are waiting further off.
+    pub fn next_expiry(&self, horizon: Duration) -> Option<Instant> {
+        if self.due.is_empty() {
+            return None;
+        }
+        
+        let ticks = (horizon.as_nanos().div_ceil(self.tick.as_nanos()) as u64).min(self.slots.len() as u64);
+        (self.current..=self.current + ticks)
+            .find(|&tick| {
+                self.slots[self.slot_of(tick)]
+                    .iter()
+                    .any(|&(id, scheduled)| scheduled == tick && self.due.get(&id) == Some(&scheduled))
+            })
+            .map(|tick| self.instant_of(tick))
+    }
+    
+    /// Get the slot a tick's entries go in
+    fn slot_of(&self, tick: u64) -> usize {
+        (tick % self.slots.len() as u64) as usize
+    }
+    
+    /// Get the first tick starting at or after `at`
+    fn tick_of(&self, at: Instant) -> u64 {
+        let offset = at.saturating_duration_since(self.start);
+        offset.as_nanos().div_ceil(self.tick.as_nanos()) as u64
+    }
+    
+    /// Get the last tick started by `now`
+    fn elapsed_ticks(&self, now: Instant) -> u64 {
+        (now.saturating_duration_since(self.start).as_nanos() / self.tick.as_nanos()) as u64
+    }
+    
+    /// Get when a tick starts
+    fn instant_of(&self, tick: u64) -> Instant {
+        self.start + Duration::from_nanos((self.tick.as_nanos() as u64).saturating_mul(tick))
+    }
+}
\ No newline at end of file
@@ -0,0 +1,401 @@
+use crate::error::{ServerError, ServerResult};
+use crate::http::{Request, Response, Status};
+use crate::metrics::{MetricsRegistry, WindowedCounter};
+use crate::middleware::MiddlewareNext;
+use crate::router::Router;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Header naming the variant that served a response
+pub const VARIANT_HEADER: &str = "X-Variant";
+
+/// How long a sticky variant cookie lasts, in seconds (30 days)
+const STICKY_MAX_AGE: u64 = 30 * 24 * 60 * 60;
+
+/// When a canary is taken out of rotation
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RollbackPolicy {
+    /// Highest share of 5xx responses tolerated, from 0 to 1
+    pub max_error_rate: f64,
+    
+    /// How far back the error rate is measured
+    pub window: Duration,
+    
+    /// Requests needed within the window before the rate is trusted
+    pub min_requests: usize,
+}
+
+impl Default for RollbackPolicy {
+    fn default() -> Self {
+        Self {
+            max_error_rate: 0.05,
+            window: Duration::from_secs(60),
+            min_requests: 20,
+        }
+    }
+}
+
+/// One of the handlers traffic is split between
+struct Variant {
+    name: String,
+    weight: AtomicU32,
+    /// None sends the request on down the middleware chain
+    handler: Option<MiddlewareNext>,
+    canary: bool,
+    rolled_back: AtomicBool,
+    /// Requests and 5xx responses over time, for judging canaries
+    requests: WindowedCounter,
+    errors: WindowedCounter,
+}
+
+/// Splits requests between variants by weight, for A/B tests
+///
+/// Requests are spread by smooth weighted round robin, so a 90/10 split
+/// sends one request in ten to the second variant, evenly interleaved.
+/// With a sticky cookie, a client that has been assigned a variant keeps it
+/// for as long as that variant's weight is above zero. Every response is
+/// tagged with an `X-Variant` header, and with metrics each variant gets
+/// `split.<experiment>.<variant>.requests`, `.errors` (5xx or handler errors)
+/// and `.latency_us`.
+///
+/// Variants added with `canary` are watched: once a canary's share of 5xx
+/// responses over the rollback window exceeds the policy's limit, its
+/// weight drops to zero and its clients move to the other variants.
+/// Giving it a weight again with `set_weight` puts it back in rotation.
+///
+/// ```ignore
+/// let split = TrafficSplit::new("checkout")
+///     .variant("v1", 90, checkout_v1)
+///     .variant("v2", 10, checkout_v2)
+///     .sticky("checkout_variant");
+/// router.get("/checkout", move |req| split.handle(req));
+/// ```
+pub struct TrafficSplit {
+    experiment: String,
+    variants: Vec<Variant>,
+    cookie: Option<String>,
+    /// Running scores for smooth weighted round robin, one per variant
+    scores: Mutex<Vec<i64>>,
+    metrics: Option<Arc<MetricsRegistry>>,
+    rollback: RollbackPolicy,
+}
+
+impl TrafficSplit {
+    /// Create a split with no variants
+    pub fn new(experiment: &str) -> Self {
+        Self {
+            experiment: experiment.to_string(),
+            variants: Vec::new(),
+            cookie: None,
+            scores: Mutex::new(Vec::new()),
+            metrics: None,
+            rollback: RollbackPolicy::default(),
+        }
+    }
+    
+    /// Add a variant served by `handler`, getting `weight` shares of the traffic
+    pub fn variant<F>(mut self, name: &str, weight: u32, handler: F) -> Self
+    where
+        F: Fn(&Request) -> ServerResult<Response> + Send + Sync + 'static,
+    {
+        self.push_variant(name, weight, Some(Arc::new(handler)), false);
+        self
+    }
+    
+    /// Add a canary variant, rolled back automatically when it fails too often
+    pub fn canary<F>(mut self, name: &str, weight: u32, handler: F) -> Self
+    where
+        F: Fn(&Request) -> ServerResult<Response> + Send + Sync + 'static,
+    {
+        self.push_variant(name, weight, Some(Arc::new(handler)), true);
+        self
+    }
+    
+    /// Add a variant that passes requests on to the rest of the middleware chain
+    ///
+    /// Only useful with `traffic_split_middleware`; `handle` has no chain to
+    /// pass to and fails for these requests.
+    pub fn passthrough(mut self, name: &str, weight: u32) -> Self {
+        self.push_variant(name, weight, None, false);
+        self
+    }
+    
+    fn push_variant(&mut self, name: &str, weight: u32, handler: Option<MiddlewareNext>, canary: bool) {
+        self.variants.push(Variant {
+            name: name.to_string(),
+            weight: AtomicU32::new(weight),
+            handler,
+            canary,
+            rolled_back: AtomicBool::new(false),
+            requests: WindowedCounter::new(),
+            errors: WindowedCounter::new(),
+        });
+        self.scores.get_mut().unwrap().push(0);
+    }
+    
+    /// Keep each client on one variant with a cookie of this name
+    pub fn sticky(mut self, cookie: &str) -> Self {
+        self.cookie = Some(cookie.to_string());
+        self
+    }
+    
+    /// Record per-variant metrics in `registry`
+    pub fn with_metrics(mut self, registry: Arc<MetricsRegistry>) -> Self {
+        self.metrics = Some(registry);
+        self
+    }
+    
+    /// Set when canaries are rolled back
+    pub fn with_rollback(mut self, policy: RollbackPolicy) -> Self {
+        self.rollback = policy;
+        self
+    }
+    
+    /// Get the experiment name
+    pub fn experiment(&self) -> &str {
+        &self.experiment
+    }
+    
+    /// Get each variant's name and current weight
+    pub fn weights(&self) -> Vec<(String, u32)> {
+        self.variants
+            .iter()
+            .map(|variant| (variant.name.clone(), variant.weight.load(Ordering::Relaxed)))
+            .collect()
+    }
+    
+    /// Change a variant's weight, returning whether it exists
+    ///
+    /// A rolled back canary given a weight is back in rotation, judged only
+    /// on requests from then on.
+    pub fn set_weight(&self, name: &str, weight: u32) -> bool {
+        match self.variants.iter().find(|variant| variant.name == name) {
+            Some(variant) => {
+                if weight > 0 && variant.rolled_back.swap(false, Ordering::SeqCst) {
+                    variant.requests.reset();
+                    variant.errors.reset();
+                }
+                variant.weight.store(weight, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+    
+    /// Check whether a canary has been rolled back
+    pub fn is_rolled_back(&self, name: &str) -> bool {
+        self.variants
+            .iter()
+            .any(|variant| variant.name == name && variant.rolled_back.load(Ordering::SeqCst))
+    }
+    
+    /// Describe every variant and its recent traffic
+    pub fn status(&self) -> Vec<VariantStatus> {
+        let window = self.rollback.window;
+        self.variants
+            .iter()
+            .map(|variant| VariantStatus {
+                name: variant.name.clone(),
+                weight: variant.weight.load(Ordering::Relaxed),
+                canary: variant.canary,
+                rolled_back: variant.rolled_back.load(Ordering::SeqCst),
+                requests: variant.requests.total(window),
+                errors: variant.errors.total(window),
+            })
+            .collect()
+    }
+    
+    /// Serve a request with the variant chosen for it
+    pub fn handle(&self, request: &Request) -> ServerResult<Response> {
+        self.dispatch(request, None)
+    }
+    
+    fn dispatch(&self, request: &Request, next: Option<&MiddlewareNext>) -> ServerResult<Response> {
+        let (index, assigned) = match self.sticky_variant(request) {
+            Some(index) => (index, false),
+            None => (self.next_variant()?, true),
+        };
+        let variant = &self.variants[index];
+        let handler = variant.handler.as_ref().or(next).ok_or_else(|| {
+            ServerError::Config(format!("Variant {} of {} has no handler", variant.name, self.experiment))
+        })?;
+        
+        let start = Instant::now();
+        let result = handler(request);
+        self.record(variant, &result, start);
+        if variant.canary {
+            self.check_canary(variant);
+        }
+        
+        let mut response = result?;
+        response.set_header(VARIANT_HEADER, &variant.name);
+        if let (true, Some(cookie)) = (assigned, &self.cookie) {
+            // Headers hold one value each, so leave a handler's own cookie alone
+            if !response.headers.contains_key("Set-Cookie") {
+                response.set_header(
+                    "Set-Cookie",
+                    &format!("{}={}; Path=/; Max-Age={}; HttpOnly", cookie, variant.name, STICKY_MAX_AGE),
+                );
+            }
+        }
+        Ok(response)
+    }
+    
+    /// Find the variant named by the request's sticky cookie, if it still gets traffic
+    fn sticky_variant(&self, request: &Request) -> Option<usize> {
+        let name = request.cookie(self.cookie.as_deref()?)?;
+        self.variants
+            .iter()
+            .position(|variant| variant.name == name && variant.weight.load(Ordering::Relaxed) > 0)
+    }
+    
+    /// Pick the next variant by smooth weighted round robin
+    fn next_variant(&self) -> ServerResult<usize> {
+        let weights: Vec<i64> = self
+            .variants
+            .iter()
+            .map(|variant| variant.weight.load(Ordering::Relaxed) as i64)
+            .collect();
+        let total: i64 = weights.iter().sum();
+        if total == 0 {
+            return Err(ServerError::Config(format!("No variant of {} has any weight", self.experiment)));
+        }
+        
+        let mut scores = self.scores.lock().unwrap();
+        for (score, weight) in scores.iter_mut().zip(&weights) {
+            *score += weight;
+        }
+        let chosen = (0..scores.len()).max_by_key(|&i| (scores[i], std::cmp::Reverse(i))).unwrap();
+        scores[chosen] -= total;
+        Ok(chosen)
+    }
+    
+    fn record(&self, variant: &Variant, result: &ServerResult<Response>, start: Instant) {
+        let failed = result.as_ref().map_or(true, |response| response.status as u16 >= 500);
+        variant.requests.increment(1);
+        if failed {
+            variant.errors.increment(1);
+        }
+        
+        let registry = match &self.metrics {
+            Some(registry) => registry,
+            None => return,
+        };
+        
+        let prefix = format!("split.{}.{}", self.experiment, variant.name);
+        registry.counter(&format!("{}.requests", prefix)).increment(1);
+        if failed {
+            registry.counter(&format!("{}.errors", prefix)).increment(1);
+        }
+        registry
+            .exponential_histogram(&format!("{}.latency_us", prefix), 1.0, 2.0, 24)
+            .record(start.elapsed().as_micros() as f64);
+    }
+    
+    /// Roll a canary back if its recent error rate is over the limit
+    fn check_canary(&self, variant: &Variant) {
+        let policy = &self.rollback;
+        let requests = variant.requests.total(policy.window);
+        if requests == 0 || requests < policy.min_requests {
+            return;
+        }
+        let error_rate = variant.errors.total(policy.window) as f64 / requests as f64;
+        if error_rate <= policy.max_error_rate {
+            return;
+        }
+        
+        // Only the request that trips the limit reports it
+        if variant.rolled_back.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        variant.weight.store(0, Ordering::Relaxed);
+        log::warn!(
+            "Rolled back canary {} of {}: {:.1}% of {} requests failed",
+            variant.name,
+            self.experiment,
+            error_rate * 100.0,
+            requests
+        );
+        if let Some(registry) = &self.metrics {
+            registry.counter(&format!("split.{}.{}.rollbacks", self.experiment, variant.name)).increment(1);
+        }
+    }
+}
+
+/// A variant's state as reported by the admin route
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VariantStatus {
+    pub name: String,
+    pub weight: u32,
+    pub canary: bool,
+    pub rolled_back: bool,
+    /// Requests within the rollback window
+    pub requests: usize,
+    /// 5xx responses and handler errors within the rollback window
+    pub errors: usize,
+}
+
+/// Traffic split middleware - sends each request to one of `split`'s variants
+///
+/// Variants added with `passthrough` continue down the chain, so the
+/// existing handler can serve as the control group.
+pub fn traffic_split_middleware(
+    split: Arc<TrafficSplit>,
+) -> impl Fn(&Request, MiddlewareNext) -> ServerResult<Response> + Send + Sync {
+    move |request, next| split.dispatch(request, Some(&next))
+}
+
+/// New weight accepted by the admin route
+#[derive(Deserialize)]
+struct WeightUpdate {
+    weight: u32,
+}
+
+/// Register admin routes for `split` at `path`
+///
+/// - `GET <path>` reports each variant's weight, canary state and recent
+///   requests and errors as JSON
+/// - `PUT <path>?variant=v2` sets a variant's weight from a JSON body like
+///   `{"weight": 25}`; a rolled back canary given a weight is back in rotation
+///
+/// Put these routes behind authentication middleware or a listener that is
+/// not publicly reachable.
+pub fn mount_traffic_split(router: &mut Router, path: &str, split: Arc<TrafficSplit>) {
+    let status = split.clone();
+    router.get(path, move |_| {
+        let body = serde_json::to_vec(&serde_json::json!({
+            "experiment": status.experiment(),
+            "variants": status.status(),
+        }))?;
+        let mut response = Response::new(Status::Ok);
+        response.set_body(&body);
+        response.set_header("Content-Type", "application/json");
+        Ok(response)
+    });
+    
+    router.put(path, move |req| {
+        let name = match req.query_params.get("variant") {
+            Some(name) => name,
+            None => return Ok(text_response(Status::BadRequest, "A variant name is required\n")),
+        };
+        let update: WeightUpdate = match serde_json::from_slice(&req.body) {
+            Ok(update) => update,
+            Err(e) => return Ok(text_response(Status::BadRequest, &format!("Invalid weight: {}\n", e))),
+        };
+        
+        if !split.set_weight(name, update.weight) {
+            return Ok(text_response(Status::NotFound, "No such variant\n"));
+        }
+        let mut response = Response::new(Status::NoContent);
+        response.set_header("Content-Length", "0");
+        Ok(response)
+    });
+}
+
+fn text_response(status: Status, body: &str) -> Response {
+    let mut response = Response::new(status);
+    response.set_body(body.as_bytes());
+    response
+}
\ No newline at end of file
@@ -0,0 +1,107 @@
+use crate::acceptor::ConnectionAcceptor;
+use crate::error::{ServerError, ServerResult};
+use std::env;
+use std::io;
+use std::net::TcpListener;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::process::{Child, Command};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Environment variable listing the listening socket FDs handed to a new process
+pub const INHERITED_FDS_ENV: &str = "HPS_INHERITED_FDS";
+
+/// Set by the SIGUSR2 handler when an upgrade has been requested
+static UPGRADE_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn handle_upgrade_signal(_signal: libc::c_int) {
+    UPGRADE_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+/// Install a SIGUSR2 handler that requests a binary upgrade
+pub fn install_upgrade_signal() -> ServerResult<()> {
+    let handler = handle_upgrade_signal as extern "C" fn(libc::c_int);
+    let previous = unsafe { libc::signal(libc::SIGUSR2, handler as libc::sighandler_t) };
+    if previous == libc::SIG_ERR {
+        return Err(ServerError::Io(io::Error::last_os_error()));
+    }
+    
+    Ok(())
+}
+
+/// Check (and clear) whether an upgrade has been requested since the last call
+pub fn take_upgrade_request() -> bool {
+    UPGRADE_REQUESTED.swap(false, Ordering::SeqCst)
+}
+
+/// Take over the listening sockets passed down by a previous server process
+///
+/// Returns an empty list when the process wasn't started by an upgrade.
+pub fn inherited_acceptors() -> ServerResult<Vec<ConnectionAcceptor>> {
+    let fds = match env::var(INHERITED_FDS_ENV) {
+        Ok(fds) => fds,
+        Err(_) => return Ok(Vec::new()),
+    };
+    
+    // Don't pass the same sockets on to our own children by accident
+    env::remove_var(INHERITED_FDS_ENV);
+    
+    let mut acceptors = Vec::new();
+    for fd in fds.split(',').filter(|s| !s.is_empty()) {
+        let fd: RawFd = fd.trim().parse().map_err(|_| {
+            ServerError::Config(format!("Invalid inherited file descriptor: {}", fd))
+        })?;
+        
+        set_cloexec(fd, true)?;
+        let listener = unsafe { TcpListener::from_raw_fd(fd) };
+        acceptors.push(ConnectionAcceptor::from_listener(listener)?);
+    }
+    
+    Ok(acceptors)
+}
+
+/// Re-exec the current binary, handing it the listening sockets of `acceptors`
+///
+/// The child is started with the same arguments and learns about its sockets
+/// through `INHERITED_FDS_ENV`. Once this returns, the caller should stop
+/// accepting and drain its existing connections.
+pub fn spawn_successor(acceptors: &[Arc<ConnectionAcceptor>]) -> ServerResult<Child> {
+    let fds: Vec<RawFd> = acceptors.iter().map(|a| a.as_raw_fd()).collect();
+    let fd_list = fds.iter().map(|fd| fd.to_string()).collect::<Vec<_>>().join(",");
+    
+    // The sockets must survive exec in the child
+    for &fd in &fds {
+        set_cloexec(fd, false)?;
+    }
+    
+    let result = Command::new(env::current_exe()?)
+        .args(env::args_os().skip(1))
+        .env(INHERITED_FDS_ENV, fd_list)
+        .spawn();
+    
+    for &fd in &fds {
+        set_cloexec(fd, true)?;
+    }
+    
+    Ok(result?)
+}
+
+/// Set or clear FD_CLOEXEC on a file descriptor
+pub(crate) fn set_cloexec(fd: RawFd, enabled: bool) -> ServerResult<()> {
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFD) };
+    if flags < 0 {
+        return Err(ServerError::Io(io::Error::last_os_error()));
+    }
+    
+    let new_flags = if enabled {
+        flags | libc::FD_CLOEXEC
+    } else {
+        flags & !libc::FD_CLOEXEC
+    };
+    
+    if unsafe { libc::fcntl(fd, libc::F_SETFD, new_flags) } < 0 {
+        return Err(ServerError::Io(io::Error::last_os_error()));
+    }
+    
+    Ok(())
+}
\ No newline at end of file
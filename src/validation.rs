@@ -0,0 +1,288 @@
+use crate::error::{ServerError, ServerResult};
+use crate::http::{Request, Response, Status};
+use crate::openapi::ParamLocation;
+use regex::Regex;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+
+/// The type a parameter value must parse as
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParamType {
+    String,
+    U64,
+    I64,
+    F64,
+    Bool,
+}
+
+impl ParamType {
+    /// Look up a type by the name used in route patterns (`:id<u64>`)
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "string" | "str" => Some(ParamType::String),
+            "u64" => Some(ParamType::U64),
+            "i64" => Some(ParamType::I64),
+            "f64" => Some(ParamType::F64),
+            "bool" => Some(ParamType::Bool),
+            _ => None,
+        }
+    }
+    
+    /// Get the JSON schema describing values of this type
+    pub fn schema(&self) -> Value {
+        match *self {
+            ParamType::String => json!({ "type": "string" }),
+            ParamType::U64 => json!({ "type": "integer", "minimum": 0 }),
+            ParamType::I64 => json!({ "type": "integer" }),
+            ParamType::F64 => json!({ "type": "number" }),
+            ParamType::Bool => json!({ "type": "boolean" }),
+        }
+    }
+    
+    /// Parse a value, returning its numeric value for numeric types
+    fn parse(&self, value: &str) -> Result<Option<f64>, String> {
+        match *self {
+            ParamType::String => Ok(None),
+            ParamType::U64 => value
+                .parse::<u64>()
+                .map(|n| Some(n as f64))
+                .map_err(|_| "must be a non-negative integer".to_string()),
+            ParamType::I64 => value
+                .parse::<i64>()
+                .map(|n| Some(n as f64))
+                .map_err(|_| "must be an integer".to_string()),
+            ParamType::F64 => value
+                .parse::<f64>()
+                .ok()
+                .filter(|n| n.is_finite())
+                .map(Some)
+                .ok_or_else(|| "must be a number".to_string()),
+            ParamType::Bool => match value {
+                "true" | "false" => Ok(None),
+                _ => Err("must be true or false".to_string()),
+            },
+        }
+    }
+}
+
+/// Split a pattern segment like `:id<u64>` into its name and type
+///
+/// # Panics
+///
+/// Panics if the type name is not one of `string`, `u64`, `i64`, `f64` or
+/// `bool`, so a typo in a route pattern fails at registration.
+pub fn split_typed_param(segment: &str) -> (&str, Option<ParamType>) {
+    match segment.find('<') {
+        Some(open) if segment.ends_with('>') => {
+            let type_name = &segment[open + 1..segment.len() - 1];
+            let kind = ParamType::from_name(type_name)
+                .unwrap_or_else(|| panic!("Unknown parameter type `{}` in route segment `{}`", type_name, segment));
+            (&segment[..open], Some(kind))
+        }
+        _ => (segment, None),
+    }
+}
+
+/// A constraint on one path, query or header parameter
+#[derive(Debug, Clone)]
+pub struct ParamRule {
+    pub name: String,
+    pub location: ParamLocation,
+    pub kind: ParamType,
+    pub required: bool,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub pattern: Option<Regex>,
+}
+
+impl ParamRule {
+    fn new(name: &str, location: ParamLocation, kind: ParamType) -> Self {
+        Self {
+            name: name.to_string(),
+            location,
+            kind,
+            required: location == ParamLocation::Path,
+            min: None,
+            max: None,
+            pattern: None,
+        }
+    }
+    
+    /// Constrain a path parameter
+    pub fn path(name: &str, kind: ParamType) -> Self {
+        Self::new(name, ParamLocation::Path, kind)
+    }
+    
+    /// Constrain an optional query parameter
+    pub fn query(name: &str, kind: ParamType) -> Self {
+        Self::new(name, ParamLocation::Query, kind)
+    }
+    
+    /// Constrain an optional header
+    pub fn header(name: &str, kind: ParamType) -> Self {
+        Self::new(name, ParamLocation::Header, kind)
+    }
+    
+    /// Reject requests that leave the parameter out
+    pub fn required(mut self) -> Self {
+        self.required = true;
+        self
+    }
+    
+    /// Require a numeric value within `range`, or a string length within it
+    pub fn with_range(mut self, range: RangeInclusive<i64>) -> Self {
+        self.min = Some(*range.start() as f64);
+        self.max = Some(*range.end() as f64);
+        self
+    }
+    
+    /// Require the whole value to match a regular expression
+    pub fn with_pattern(mut self, pattern: &str) -> ServerResult<Self> {
+        let anchored = format!("^(?:{})$", pattern);
+        let regex = Regex::new(&anchored)
+            .map_err(|e| ServerError::Config(format!("Invalid pattern for parameter {}: {}", self.name, e)))?;
+        self.pattern = Some(regex);
+        Ok(self)
+    }
+    
+    /// Check a single value against the rule
+    fn check(&self, value: &str) -> Result<(), String> {
+        let number = self.kind.parse(value)?;
+        
+        // Ranges bound numbers by value and strings by length
+        let measured = number.or_else(|| match self.kind {
+            ParamType::String => Some(value.chars().count() as f64),
+            _ => None,
+        });
+        if let Some(measured) = measured {
+            let what = if number.is_some() { "" } else { "length " };
+            if let Some(min) = self.min {
+                if measured < min {
+                    return Err(format!("{}must be at least {}", what, min));
+                }
+            }
+            if let Some(max) = self.max {
+                if measured > max {
+                    return Err(format!("{}must be at most {}", what, max));
+                }
+            }
+        }
+        
+        if let Some(pattern) = &self.pattern {
+            if !pattern.is_match(value) {
+                return Err(format!("must match {}", pattern.as_str()));
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Get the JSON schema for OpenAPI documents
+    pub fn schema(&self) -> Value {
+        let mut schema = self.kind.schema();
+        let (min_key, max_key) = match self.kind {
+            ParamType::String => ("minLength", "maxLength"),
+            _ => ("minimum", "maximum"),
+        };
+        if let Some(min) = self.min {
+            schema[min_key] = json!(min as i64);
+        }
+        if let Some(max) = self.max {
+            schema[max_key] = json!(max as i64);
+        }
+        if let Some(pattern) = &self.pattern {
+            schema["pattern"] = json!(pattern.as_str());
+        }
+        schema
+    }
+}
+
+/// One rejected parameter, as reported in 422 responses
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ValidationError {
+    pub param: String,
+    pub location: &'static str,
+    pub message: String,
+}
+
+/// The parameter rules of a route
+#[derive(Debug, Clone, Default)]
+pub struct RouteValidator {
+    rules: Vec<ParamRule>,
+}
+
+impl RouteValidator {
+    /// Build rules for the typed segments of a route pattern, if it has any
+    pub fn from_pattern(pattern: &str) -> Option<Self> {
+        let rules: Vec<ParamRule> = pattern
+            .split('/')
+            .filter_map(|segment| segment.strip_prefix(':'))
+            .filter_map(|segment| match split_typed_param(segment) {
+                (name, Some(kind)) => Some(ParamRule::path(name, kind)),
+                _ => None,
+            })
+            .collect();
+        
+        if rules.is_empty() {
+            None
+        } else {
+            Some(Self { rules })
+        }
+    }
+    
+    /// Add a rule, replacing any earlier rule for the same parameter
+    pub fn add_rule(&mut self, rule: ParamRule) {
+        self.rules
+            .retain(|existing| !(existing.name == rule.name && existing.location == rule.location));
+        self.rules.push(rule);
+    }
+    
+    /// Get the rules
+    pub fn rules(&self) -> &[ParamRule] {
+        &self.rules
+    }
+    
+    /// Check a request and its extracted path parameters against every rule
+    pub fn validate(&self, request: &Request, path_params: &HashMap<String, String>) -> Vec<ValidationError> {
+        let mut errors = Vec::new();
+        
+        for rule in &self.rules {
+            let value = match rule.location {
+                ParamLocation::Path => path_params.get(&rule.name),
+                ParamLocation::Query => request.query_params.get(&rule.name),
+                ParamLocation::Header => request.get_header(&rule.name),
+            };
+            
+            let result = match value {
+                Some(value) => rule.check(value),
+                None if rule.required => Err("is required".to_string()),
+                None => Ok(()),
+            };
+            
+            if let Err(message) = result {
+                errors.push(ValidationError {
+                    param: rule.name.clone(),
+                    location: rule.location.as_str(),
+                    message,
+                });
+            }
+        }
+        
+        errors
+    }
+}
+
+/// Build the 422 response listing every rejected parameter
+pub fn validation_error_response(errors: &[ValidationError]) -> Response {
+    let body = json!({
+        "error": "validation_failed",
+        "details": errors,
+    });
+    
+    let mut response = Response::new(Status::UnprocessableEntity);
+    response.set_body(body.to_string().as_bytes());
+    response.set_header("Content-Type", "application/json");
+    response
+}
\ No newline at end of file
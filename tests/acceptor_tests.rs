@@ -0,0 +1,202 @@
+use high_performance_server::{ConnectionAcceptor, IpGuard, IpLimits, IpRejection, ServerConfig, TcpOptions};
+use socket2::SockRef;
+use std::io::{ErrorKind, Read};
+use std::net::{IpAddr, Ipv4Addr, TcpStream};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn test_acceptor_tcp_options() {
+    let options = TcpOptions {
+        recv_buffer_size: Some(64 * 1024),
+        keepalive_time: Some(Duration::from_secs(60)),
+        keepalive_interval: Some(Duration::from_secs(10)),
+        keepalive_probes: Some(3),
+        ..TcpOptions::default()
+    };
+    
+    let acceptor = ConnectionAcceptor::with_options("127.0.0.1:0", 16, options).unwrap();
+    assert_eq!(acceptor.backlog_size(), 16);
+    
+    let addr = acceptor.local_addr().unwrap();
+    let _client = TcpStream::connect(addr).unwrap();
+    
+    let conn = loop {
+        match acceptor.accept() {
+            Ok(conn) => break conn,
+            Err(_) => thread::sleep(Duration::from_millis(5)),
+        }
+    };
+    
+    let socket = SockRef::from(conn.stream());
+    assert!(socket.keepalive().unwrap());
+    assert!(socket.recv_buffer_size().unwrap() >= 64 * 1024);
+    
+    #[cfg(target_os = "linux")]
+    {
+        assert_eq!(socket.keepalive_time().unwrap(), Duration::from_secs(60));
+        assert_eq!(socket.keepalive_interval().unwrap(), Duration::from_secs(10));
+        assert_eq!(socket.keepalive_retries().unwrap(), 3);
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_acceptor_fast_open_and_defer_accept() {
+    let options = TcpOptions {
+        fast_open_queue: Some(128),
+        defer_accept: Some(Duration::from_secs(5)),
+        ..TcpOptions::default()
+    };
+    
+    assert!(ConnectionAcceptor::with_options("127.0.0.1:0", 128, options).is_ok());
+}
+
+#[test]
+fn test_listen_on_ipv4_and_ipv6() {
+    let options = TcpOptions {
+        ipv6_only: Some(true),
+        ..TcpOptions::default()
+    };
+    
+    let v4 = ConnectionAcceptor::with_options("127.0.0.1:0", 128, options.clone()).unwrap();
+    let port = v4.local_addr().unwrap().port();
+    
+    // With IPV6_V6ONLY the IPv6 socket can share the port with the IPv4 one
+    let v6 = match ConnectionAcceptor::with_options(format!("[::1]:{}", port), 128, options) {
+        Ok(acceptor) => acceptor,
+        Err(_) => return, // No IPv6 support on this host
+    };
+    
+    #[cfg(unix)]
+    {
+        use std::os::unix::io::{AsRawFd, BorrowedFd};
+        
+        let fd = unsafe { BorrowedFd::borrow_raw(v6.as_raw_fd()) };
+        assert!(SockRef::from(&fd).only_v6().unwrap());
+    }
+    assert_eq!(v6.local_addr().unwrap().port(), port);
+}
+
+#[test]
+fn test_config_socket_addresses() {
+    let config = ServerConfig::new().with_address("127.0.0.1", 8080);
+    assert_eq!(config.socket_addresses(), vec!["127.0.0.1:8080".to_string()]);
+    
+    let config = config.with_listen_addresses(["0.0.0.0:8080", "[::]:8080"]);
+    assert_eq!(config.socket_addresses(), vec!["0.0.0.0:8080".to_string(), "[::]:8080".to_string()]);
+}
+
+#[test]
+fn test_ip_guard_cap_and_release() {
+    let guard = Arc::new(IpGuard::new(IpLimits::new().with_max_connections_per_ip(2)));
+    let ip = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+    let other = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+    
+    let first = IpGuard::admit(&guard, ip).unwrap();
+    let _second = IpGuard::admit(&guard, ip).unwrap();
+    assert_eq!(IpGuard::admit(&guard, ip).unwrap_err(), IpRejection::OverLimit);
+    assert!(IpGuard::admit(&guard, other).is_ok());
+    assert_eq!(guard.active(ip), 2);
+    
+    // Dropping a slot frees room for another connection
+    drop(first);
+    assert_eq!(guard.active(ip), 1);
+    assert!(IpGuard::admit(&guard, ip).is_ok());
+    assert_eq!(guard.rejected_over_limit(), 1);
+}
+
+#[test]
+fn test_ip_guard_bans_garbage_senders() {
+    let limits = IpLimits::new().with_ban(3, Duration::from_secs(60), Duration::from_secs(60));
+    let guard = Arc::new(IpGuard::new(limits));
+    let ip = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 3));
+    
+    assert!(!guard.report_garbage(ip));
+    assert!(!guard.report_garbage(ip));
+    assert!(guard.report_garbage(ip));
+    assert!(guard.is_banned(ip));
+    assert_eq!(IpGuard::admit(&guard, ip).unwrap_err(), IpRejection::Banned);
+    assert_eq!(guard.rejected_banned(), 1);
+}
+
+#[test]
+fn test_acceptor_resets_connections_over_ip_limit() {
+    let acceptor = ConnectionAcceptor::new("127.0.0.1:0")
+        .unwrap()
+        .with_ip_limits(IpLimits::new().with_max_connections_per_ip(1));
+    let addr = acceptor.local_addr().unwrap();
+    
+    let _first_client = TcpStream::connect(addr).unwrap();
+    let first = loop {
+        match acceptor.accept() {
+            Ok(conn) => break conn,
+            Err(_) => thread::sleep(Duration::from_millis(5)),
+        }
+    };
+    
+    let mut second_client = TcpStream::connect(addr).unwrap();
+    thread::sleep(Duration::from_millis(50));
+    assert!(matches!(acceptor.accept(), Err(ref e) if e.kind() == ErrorKind::WouldBlock));
+    
+    // The refused client sees its connection reset or closed
+    second_client.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+    let mut buf = [0u8; 1];
+    assert!(matches!(second_client.read(&mut buf), Ok(0) | Err(_)));
+    
+    let guard = acceptor.ip_guard().unwrap();
+    assert_eq!(guard.rejected_over_limit(), 1);
+    assert_eq!(guard.active(first.peer_addr().ip()), 1);
+    
+    drop(first);
+    assert_eq!(guard.active(addr.ip()), 0);
+}
+#[test]
+fn test_accepted_sockets_are_nonblocking_and_batches_drain_bursts() {
+    use high_performance_server::{EventLoop, Response, Router, Status};
+    use std::io::Write;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    
+    let acceptor = ConnectionAcceptor::new("127.0.0.1:0").unwrap();
+    let _client = TcpStream::connect(acceptor.local_addr().unwrap()).unwrap();
+    thread::sleep(Duration::from_millis(20));
+    let conn = acceptor.accept().unwrap();
+    assert!(SockRef::from(conn.stream()).nonblocking().unwrap());
+    
+    // One connection per batch still gets through a burst, as the batch grows to its ceiling
+    let mut router = Router::new();
+    router.get("/", |_| {
+        let mut response = Response::new(Status::Ok);
+        response.set_body(b"ok");
+        Ok(response)
+    });
+    let acceptor = Arc::new(ConnectionAcceptor::new("127.0.0.1:0").unwrap());
+    let addr = acceptor.local_addr().unwrap();
+    let drain = Arc::new(AtomicBool::new(false));
+    let server = {
+        let drain = drain.clone();
+        thread::spawn(move || {
+            let config = ServerConfig::default().with_max_accept_batch(1);
+            let mut event_loop = EventLoop::with_config(0, acceptor, config);
+            event_loop.set_router(Arc::new(router));
+            event_loop.set_drain_signal(drain);
+            event_loop.run().unwrap();
+        })
+    };
+    
+    let mut clients: Vec<TcpStream> = (0..20).map(|_| TcpStream::connect(addr).unwrap()).collect();
+    for client in &mut clients {
+        client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        client.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").unwrap();
+    }
+    for client in &mut clients {
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200") && response.ends_with("ok"), "{}", response);
+    }
+    
+    drain.store(true, Ordering::SeqCst);
+    server.join().unwrap();
+    assert!(ServerConfig::default().with_max_accept_batch(0).validate().is_err());
+}
\ No newline at end of file
@@ -0,0 +1,99 @@
+use high_performance_server::{
+    access_log_middleware, AccessLog, AccessLogRules, Method, MiddlewareChain, Request, Response, Status,
+};
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// A writer that keeps everything written to it for inspection
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+    
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl SharedBuffer {
+    fn lines(&self) -> Vec<String> {
+        String::from_utf8(self.0.lock().unwrap().clone())
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+#[test]
+fn test_success_sampling() {
+    let log = AccessLog::stdout(AccessLogRules::new().with_success_sample_rate(0.1));
+    
+    let logged = (0..100).filter(|_| log.should_log("/", 200, Duration::ZERO)).count();
+    assert_eq!(logged, 10);
+    
+    // Errors are never sampled away
+    assert!((0..10).all(|_| log.should_log("/", 503, Duration::ZERO)));
+    assert!((0..10).all(|_| log.should_log("/", 404, Duration::ZERO)));
+}
+
+#[test]
+fn test_slow_requests_and_excluded_paths() {
+    let rules = AccessLogRules::new()
+        .with_success_sample_rate(0.0)
+        .with_slow_threshold(Duration::from_millis(500))
+        .exclude_path("/health");
+    let log = AccessLog::stdout(rules);
+    
+    assert!(!log.should_log("/", 200, Duration::from_millis(10)));
+    assert!(log.should_log("/", 200, Duration::from_secs(1)));
+    assert!(!log.should_log("/health", 200, Duration::ZERO));
+    assert!(!log.should_log("/health", 304, Duration::ZERO));
+    assert!(log.should_log("/health", 500, Duration::ZERO));
+}
+
+#[test]
+fn test_access_log_middleware() {
+    let buffer = SharedBuffer::default();
+    let rules = AccessLogRules::new().exclude_path("/health");
+    let log = Arc::new(AccessLog::with_writer(rules, buffer.clone()));
+    
+    let mut chain = MiddlewareChain::new();
+    chain.add(access_log_middleware(log));
+    chain.set_handler(|request| {
+        let status = if request.path() == "/missing" { Status::NotFound } else { Status::Ok };
+        Ok(Response::new(status))
+    });
+    
+    chain.handle(&Request::new(Method::Get, "/index.html")).unwrap();
+    chain.handle(&Request::new(Method::Get, "/health")).unwrap();
+    chain.handle(&Request::new(Method::Get, "/missing")).unwrap();
+    
+    let lines = buffer.lines();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].starts_with("[Access] GET /index.html 200 "));
+    assert!(lines[1].starts_with("[Access] GET /missing 404 "));
+}
+
+#[test]
+fn test_secrets_are_masked_before_writing() {
+    let buffer = SharedBuffer::default();
+    let rules = AccessLogRules::new().with_header("Authorization").with_header("User-Agent");
+    let log = AccessLog::with_writer(rules, buffer.clone());
+    
+    let mut request = Request::new(Method::Get, "/login?user=ann&password=hunter2");
+    request.set_header("Authorization", "Bearer abc123");
+    request.set_header("User-Agent", "curl/8");
+    log.record(&request, 200, Duration::ZERO);
+    
+    let line = &buffer.lines()[0];
+    assert!(line.starts_with("[Access] GET /login?user=ann&password=[REDACTED] 200 "), "{}", line);
+    assert!(line.contains(" authorization=\"[REDACTED]\""), "{}", line);
+    assert!(line.contains(" user-agent=\"curl/8\""), "{}", line);
+    assert!(!line.contains("hunter2") && !line.contains("abc123"), "{}", line);
+}
\ No newline at end of file
@@ -0,0 +1,246 @@
+use high_performance_server::tenants::TenantId;
+use high_performance_server::{
+    acl_middleware, basic_auth_store_middleware, tenant_middleware, Acl, AclConfig, AuthIdentity,
+    ConnectionAcceptor, EventLoop, IpRange, Method, MiddlewareChain, Request, Response, ServerConfig, ServerError,
+    StaticCredentials, Status, TenantConfig, Tenants, TenantsConfig,
+};
+use std::io::{Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+fn ip(address: &str) -> IpAddr {
+    address.parse().unwrap()
+}
+
+#[test]
+fn test_ip_ranges() {
+    let private = IpRange::parse("10.0.0.0/8").unwrap();
+    assert!(private.contains(ip("10.20.30.40")));
+    assert!(!private.contains(ip("11.0.0.1")));
+    // IPv4 clients of a dual-stack listener show up as mapped addresses
+    assert!(private.contains(ip("::ffff:10.1.2.3")));
+    assert!(!private.contains(ip("fd00::1")));
+    
+    let docs = IpRange::parse("2001:db8::/32").unwrap();
+    assert!(docs.contains(ip("2001:db8:1::5")));
+    assert!(!docs.contains(ip("2001:db9::5")));
+    assert!(IpRange::parse("127.0.0.1").unwrap().contains(ip("127.0.0.1")));
+    assert!(!IpRange::parse("127.0.0.1").unwrap().contains(ip("127.0.0.2")));
+    assert!(IpRange::parse("0.0.0.0/0").unwrap().contains(ip("203.0.113.9")));
+    
+    for bad in ["10.0.0.0/33", "::/129", "10.0.0/8", "localhost", "10.0.0.0/x"] {
+        assert!(IpRange::parse(bad).is_err(), "{} parsed", bad);
+    }
+}
+
+fn acl() -> Acl {
+    let config: AclConfig = serde_json::from_str(
+        r#"{
+            "roles": {"admin": ["alice", "ops-tenant"], "viewer": ["bob"]},
+            "rules": [
+                {"path": "/admin/**", "ip_ranges": ["10.0.0.0/8", "127.0.0.1"], "roles": ["admin"]},
+                {"path": "/reports/*/export", "methods": ["get"], "auth": "basic", "roles": ["admin", "viewer"]},
+                {"path": "/api/**", "auth": "api_key"},
+                {"path": "/health"}
+            ]
+        }"#,
+    )
+    .unwrap();
+    Acl::new(&config).unwrap().with_credentials(Arc::new(StaticCredentials::new("alice", "wonderland")))
+}
+
+fn request(path: &str, from: &str) -> Request {
+    let mut request = Request::new(Method::Get, path);
+    request.peer_addr = Some(format!("{}:40000", from).parse().unwrap());
+    request
+}
+
+fn with_basic(mut request: Request, user: &str, password: &str) -> Request {
+    let encoded = base64::encode(format!("{}:{}", user, password));
+    request.headers.insert("authorization".into(), format!("Basic {}", encoded));
+    request
+}
+
+fn status(result: Result<Option<Request>, Box<Response>>) -> Status {
+    match result {
+        Ok(_) => Status::Ok,
+        Err(response) => response.status,
+    }
+}
+
+#[test]
+fn test_rules_check_address_auth_and_roles() {
+    let acl = acl();
+    
+    // Uncovered paths pass untouched
+    assert!(matches!(acl.check(&request("/index.html", "198.51.100.7")), Ok(None)));
+    assert!(matches!(acl.check(&request("/health", "198.51.100.7")), Ok(None)));
+    
+    // Admin paths need an allowed address first, then an admin
+    assert_eq!(status(acl.check(&request("/admin", "198.51.100.7"))), Status::Forbidden);
+    let challenged = acl.check(&request("/admin/users", "10.1.1.1")).unwrap_err();
+    assert_eq!(challenged.status, Status::Unauthorized);
+    assert!(challenged.headers.contains_key("WWW-Authenticate"));
+    let wrong_password = with_basic(request("/admin/users", "10.1.1.1"), "alice", "guess");
+    assert_eq!(status(acl.check(&wrong_password)), Status::Unauthorized);
+    
+    let alice = with_basic(request("/admin/users", "10.1.1.1"), "alice", "wonderland");
+    let passed = acl.check(&alice).unwrap().unwrap();
+    assert_eq!(AuthIdentity::of(&passed).unwrap().username, "alice");
+    
+    // An API key tenant holding the role also gets in
+    let mut tenant = request("/admin/users", "127.0.0.1");
+    tenant.extensions.insert(TenantId("ops-tenant".to_string()));
+    assert!(matches!(acl.check(&tenant), Ok(None)));
+    
+    // Identities from earlier auth middleware are used as they are
+    let mut bob = request("/reports/q3/export", "198.51.100.7");
+    bob.extensions.insert(AuthIdentity {
+        username: "bob".to_string(),
+    });
+    assert!(matches!(acl.check(&bob), Ok(None)));
+    bob.uri = "/admin".to_string();
+    assert_eq!(status(acl.check(&bob)), Status::Forbidden);
+    
+    // Rules only cover the methods they list
+    let mut post = request("/reports/q3/export", "198.51.100.7");
+    post.method = Method::Post;
+    assert!(matches!(acl.check(&post), Ok(None)));
+    
+    // API key rules ignore basic credentials
+    let basic = with_basic(request("/api/orders", "198.51.100.7"), "alice", "wonderland");
+    assert_eq!(status(acl.check(&basic)), Status::Unauthorized);
+    let mut keyed = request("/api/orders", "198.51.100.7");
+    keyed.extensions.insert(TenantId("acme".to_string()));
+    assert!(matches!(acl.check(&keyed), Ok(None)));
+}
+
+#[test]
+fn test_rules_cover_paths_a_lenient_router_matches() {
+    let acl = acl();
+    
+    // A router matching case-insensitively or ignoring trailing slashes routes these to /admin
+    for path in ["/ADMIN/users", "/Admin", "/admin/", "//admin//users/", "/aDmIn/Users?x=1"] {
+        assert_eq!(status(acl.check(&request(path, "198.51.100.7"))), Status::Forbidden, "{}", path);
+    }
+    assert_eq!(status(acl.check(&request("/REPORTS/q3/Export/", "198.51.100.7"))), Status::Unauthorized);
+    assert!(matches!(acl.check(&request("/administrator", "198.51.100.7")), Ok(None)));
+}
+
+#[test]
+fn test_middleware_passes_the_identity_on() {
+    let mut chain = MiddlewareChain::new();
+    chain.add(acl_middleware(Arc::new(acl())));
+    chain.set_handler(|req| {
+        let mut response = Response::new(Status::Ok);
+        let user = AuthIdentity::of(req).map_or("anonymous", |identity| identity.username.as_str());
+        response.set_body(user.as_bytes());
+        Ok(response)
+    });
+    
+    let response = chain.handle(&with_basic(request("/admin", "10.0.0.1"), "alice", "wonderland")).unwrap();
+    assert_eq!(response.body, b"alice");
+    assert_eq!(chain.handle(&request("/", "10.0.0.1")).unwrap().body, b"anonymous");
+    assert_eq!(chain.handle(&request("/admin", "10.0.0.1")).unwrap().status, Status::Unauthorized);
+}
+
+/// Send a request with `headers` and get the status of the response
+fn status_of(addr: SocketAddr, path: &str, headers: &str) -> u16 {
+    let mut client = TcpStream::connect(addr).unwrap();
+    client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n{}Connection: close\r\n\r\n", path, headers);
+    client.write_all(request.as_bytes()).unwrap();
+    let mut response = String::new();
+    client.read_to_string(&mut response).unwrap();
+    response[9..12].parse().unwrap()
+}
+
+#[test]
+fn test_server_checks_rules_after_auth_middleware() {
+    // The ACL has no credentials of its own, so only the middleware can identify clients
+    let config: AclConfig = serde_json::from_str(
+        r#"{
+            "roles": {"admin": ["alice"], "partners": ["acme"]},
+            "rules": [
+                {"path": "/admin/**", "roles": ["admin"]},
+                {"path": "/api/**", "auth": "api_key", "roles": ["partners"]}
+            ]
+        }"#,
+    )
+    .unwrap();
+    let tenants = Tenants::new(TenantsConfig {
+        tenants: ["acme", "globex"]
+            .into_iter()
+            .map(|id| TenantConfig {
+                id: id.to_string(),
+                api_keys: vec![format!("{}-key", id).as_str().into()],
+                ..TenantConfig::default()
+            })
+            .collect(),
+        ..TenantsConfig::default()
+    })
+    .unwrap();
+    let mut chain = MiddlewareChain::new();
+    chain.add(tenant_middleware(Arc::new(tenants)));
+    chain.add(basic_auth_store_middleware(
+        Arc::new(StaticCredentials::new("alice", "wonderland")),
+        "Server".to_string(),
+        None,
+    ));
+    chain.set_handler(|_| Ok(Response::new(Status::Ok)));
+    
+    let acceptor = Arc::new(ConnectionAcceptor::new("127.0.0.1:0").unwrap());
+    let addr = acceptor.local_addr().unwrap();
+    let drain = Arc::new(AtomicBool::new(false));
+    let server = {
+        let drain = drain.clone();
+        thread::spawn(move || {
+            let mut event_loop = EventLoop::new(0, acceptor);
+            event_loop.set_middleware_chain(Arc::new(chain));
+            event_loop.set_acl(Arc::new(Acl::new(&config).unwrap()));
+            event_loop.set_drain_signal(drain);
+            event_loop.run().unwrap();
+        })
+    };
+    
+    let alice = format!("Authorization: Basic {}\r\n", base64::encode("alice:wonderland"));
+    assert_eq!(status_of(addr, "/admin/users", &alice), 200);
+    assert_eq!(status_of(addr, "/api/orders", &format!("{}X-Api-Key: acme-key\r\n", alice)), 200);
+    assert_eq!(status_of(addr, "/api/orders", &format!("{}X-Api-Key: globex-key\r\n", alice)), 403);
+    assert_eq!(status_of(addr, "/api/orders", &alice), 401);
+    assert_eq!(status_of(addr, "/admin/users", ""), 401);
+    
+    drain.store(true, Ordering::SeqCst);
+    server.join().unwrap();
+}
+
+#[test]
+fn test_mistakes_are_caught_at_startup() {
+    let cases = [
+        (r#"{"rules": [{"path": "/admin/**", "roles": ["root"]}]}"#, "unknown role"),
+        (r#"{"rules": [{"path": "/admin", "ip_ranges": ["10.0.0.0/40"]}]}"#, "Invalid IP range"),
+        (r#"{"rules": [{"path": "admin"}]}"#, "must start with /"),
+        (r#"{"rules": [{"path": "/a/**/b"}]}"#, "may only end in **"),
+    ];
+    for (json, expected) in cases {
+        let config: AclConfig = serde_json::from_str(json).unwrap();
+        match Acl::new(&config) {
+            Err(ServerError::Config(message)) => assert!(message.contains(expected), "{}", message),
+            other => panic!("{} compiled: {:?}", json, other),
+        }
+        
+        let server = ServerConfig {
+            acl: config,
+            ..ServerConfig::default()
+        };
+        let message = server.validate().unwrap_err().to_string();
+        assert!(message.contains("`acl` is invalid") && message.contains(expected), "{}", message);
+    }
+    
+    let config: AclConfig = serde_json::from_str(r#"{"htpasswd": "/nonexistent/hps/htpasswd"}"#).unwrap();
+    assert!(Acl::new(&config).is_err());
+    assert!(serde_json::from_str::<AclConfig>(r#"{"rules": [{"path": "/", "auth": "kerberos"}]}"#).is_err());
+}
\ No newline at end of file
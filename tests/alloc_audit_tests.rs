@@ -0,0 +1,100 @@
+#![cfg(feature = "alloc-audit")]
+
+use high_performance_server::alloc_audit;
+use high_performance_server::{ConnectionAcceptor, EventLoop, MetricsCollector, Response, Router, Status};
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn test_allocations_are_counted_per_thread() {
+    alloc_audit::start_request();
+    let boxes: Vec<Box<u64>> = (0..100).map(Box::new).collect();
+    let counted = alloc_audit::request_allocations();
+    // One per box, plus the vector itself
+    assert!((101..110).contains(&counted), "{}", counted);
+    drop(boxes);
+    
+    // Another thread's allocations don't show up here
+    alloc_audit::start_request();
+    let other = thread::scope(|scope| {
+        let other = scope.spawn(|| {
+            let before = alloc_audit::thread_allocations();
+            let boxes: Vec<Box<u64>> = (0..1000).map(Box::new).collect();
+            drop(boxes);
+            alloc_audit::thread_allocations() - before
+        });
+        // Spawning allocates on this thread; wait outside the count
+        let spawned = alloc_audit::request_allocations();
+        (other.join().unwrap(), spawned)
+    });
+    assert!(other.0 >= 1000, "{}", other.0);
+    assert!(other.1 < 100, "{}", other.1);
+}
+
+#[test]
+fn test_request_allocations_reach_metrics() {
+    let mut router = Router::new();
+    router.get_cached("/light", {
+        let mut response = Response::new(Status::Ok);
+        response.set_body(b"ok");
+        response
+    });
+    router.get("/heavy", |_| {
+        let boxes: Vec<Box<u64>> = (0..2000).map(Box::new).collect();
+        let mut response = Response::new(Status::Ok);
+        response.set_body(if boxes.len() == 2000 { b"ok" } else { b"no" });
+        Ok(response)
+    });
+    let acceptor = Arc::new(ConnectionAcceptor::new("127.0.0.1:0").unwrap());
+    let addr = acceptor.local_addr().unwrap();
+    let drain = Arc::new(AtomicBool::new(false));
+    let metrics = Arc::new(MetricsCollector::new());
+    let server = {
+        let (drain, metrics) = (drain.clone(), metrics.clone());
+        thread::spawn(move || {
+            let mut event_loop = EventLoop::new(0, acceptor);
+            event_loop.set_router(Arc::new(router));
+            event_loop.set_drain_signal(drain);
+            event_loop.set_metrics(metrics);
+            event_loop.run().unwrap();
+        })
+    };
+    
+    let mut client = TcpStream::connect(addr).unwrap();
+    client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let mut get = |path: &str| {
+        client
+            .write_all(format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).as_bytes())
+            .unwrap();
+        let mut response = Vec::new();
+        let mut byte = [0u8; 1];
+        while !response.ends_with(b"\r\n\r\nok") {
+            client.read_exact(&mut byte).unwrap();
+            response.push(byte[0]);
+        }
+    };
+    let allocations = metrics.registry().windowed_histogram("request_allocations");
+    
+    for _ in 0..10 {
+        get("/light");
+    }
+    let light = allocations.snapshot(Duration::from_secs(60));
+    assert_eq!(light.count, 10);
+    assert!(light.p99 < 2000.0, "{:?}", light);
+    
+    for _ in 0..10 {
+        get("/heavy");
+    }
+    let all = allocations.snapshot(Duration::from_secs(60));
+    assert_eq!(all.count, 20);
+    assert!(all.p99 >= 2000.0, "{:?}", all);
+    assert!(metrics.format().contains("request_allocations (1m): count=20"));
+    
+    drop(client);
+    drain.store(true, Ordering::SeqCst);
+    server.join().unwrap();
+}
\ No newline at end of file
@@ -0,0 +1,146 @@
+use high_performance_server::audit::{audit_middleware, verify_audit_log, AuditEntry, AuditLog};
+use high_performance_server::credentials::{basic_auth_store_middleware, AuthIdentity, StaticCredentials};
+use high_performance_server::{Method, MiddlewareChain, Request, Response, Status};
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+/// A writer that keeps everything written to it for inspection
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+    
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl SharedBuffer {
+    fn contents(&self) -> String {
+        String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+    }
+}
+
+fn authed_request(method: Method, uri: &str, username: &str) -> Request {
+    let mut request = Request::new(method, uri);
+    let encoded = base64::encode(format!("{}:secret", username));
+    request.set_header("Authorization", &format!("Basic {}", encoded));
+    request
+}
+
+#[test]
+fn test_audit_middleware_records_identity() {
+    let buffer = SharedBuffer::default();
+    let audit = Arc::new(AuditLog::with_writer(buffer.clone()));
+    
+    let mut chain = MiddlewareChain::new();
+    chain.add(basic_auth_store_middleware(
+        Arc::new(StaticCredentials::new("alice", "secret")),
+        "Admin".to_string(),
+        None,
+    ));
+    chain.add(audit_middleware(audit));
+    chain.set_handler(|request| {
+        assert_eq!(AuthIdentity::of(request).unwrap().username, "alice");
+        Ok(Response::new(Status::Created))
+    });
+    
+    let mut request = authed_request(Method::Post, "/users?token=abc", "alice");
+    request.set_header("X-Request-Id", "req-42");
+    assert_eq!(chain.handle(&request).unwrap().status, Status::Created);
+    
+    let contents = buffer.contents();
+    let entry: AuditEntry = serde_json::from_str(contents.lines().next().unwrap()).unwrap();
+    assert_eq!(entry.seq, 1);
+    assert_eq!(entry.identity.as_deref(), Some("alice"));
+    assert_eq!(entry.method, "POST");
+    assert_eq!(entry.path, "/users");
+    assert_eq!(entry.status, 201);
+    assert_eq!(entry.request_id.as_deref(), Some("req-42"));
+    assert_eq!(entry.hash, entry.compute_hash());
+}
+
+#[test]
+fn test_authenticated_only() {
+    let buffer = SharedBuffer::default();
+    let audit = AuditLog::with_writer(buffer.clone()).with_authenticated_only(true);
+    
+    assert!(audit.record(&Request::new(Method::Get, "/"), 200).is_none());
+    assert!(buffer.contents().is_empty());
+}
+
+#[test]
+fn test_tampering_breaks_chain() {
+    let buffer = SharedBuffer::default();
+    let audit = AuditLog::with_writer(buffer.clone());
+    for path in ["/a", "/b", "/c"] {
+        audit.record(&Request::new(Method::Delete, path), 204).unwrap();
+    }
+    
+    let contents = buffer.contents();
+    assert_eq!(verify_audit_log(contents.as_bytes()), Ok(3));
+    
+    // Editing a field invalidates that entry
+    let edited = contents.replacen("\"/b\"", "\"/x\"", 1);
+    assert_eq!(verify_audit_log(edited.as_bytes()), Err("Audit chain broken at line 2".to_string()));
+    
+    // Dropping an entry breaks the link from the next one
+    let lines: Vec<&str> = contents.lines().collect();
+    let removed = format!("{}\n{}\n", lines[0], lines[2]);
+    assert_eq!(verify_audit_log(removed.as_bytes()), Err("Audit chain broken at line 2".to_string()));
+}
+
+#[test]
+fn test_open_continues_chain() {
+    let path = env::temp_dir().join(format!("hps-audit-{}.log", std::process::id()));
+    let _ = fs::remove_file(&path);
+    
+    let audit = AuditLog::open(&path).unwrap();
+    audit.record(&Request::new(Method::Put, "/one"), 200).unwrap();
+    drop(audit);
+    
+    let audit = AuditLog::open(&path).unwrap();
+    let entry = audit.record(&Request::new(Method::Put, "/two"), 200).unwrap();
+    assert_eq!(entry.seq, 2);
+    drop(audit);
+    
+    let contents = fs::read_to_string(&path).unwrap();
+    assert_eq!(verify_audit_log(contents.as_bytes()), Ok(2));
+    
+    // A tampered file is refused rather than extended
+    fs::write(&path, contents.replacen("/one", "/uno", 1)).unwrap();
+    assert!(AuditLog::open(&path).is_err());
+    
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_kept_headers_and_bodies_are_redacted() {
+    let buffer = SharedBuffer::default();
+    let audit = AuditLog::with_writer(buffer.clone())
+        .with_header("Authorization")
+        .with_header("X-Client")
+        .with_request_bodies(true);
+    
+    let mut request = authed_request(Method::Post, "/users", "alice");
+    request.set_header("X-Client", "cli");
+    request.set_header("Content-Type", "application/json");
+    request.body = br#"{"name":"bob","password":"hunter2","profile":{"ssn":"123-45-6789"}}"#.to_vec();
+    let entry = audit.record(&request, 201).unwrap();
+    
+    assert_eq!(entry.headers["authorization"], "[REDACTED]");
+    assert_eq!(entry.headers["x-client"], "cli");
+    let body: serde_json::Value = serde_json::from_str(entry.body.as_deref().unwrap()).unwrap();
+    assert_eq!(
+        body,
+        serde_json::json!({"name": "bob", "password": "[REDACTED]", "profile": {"ssn": "[REDACTED]"}})
+    );
+    assert!(!buffer.contents().contains("hunter2"));
+    assert_eq!(verify_audit_log(buffer.contents().as_bytes()), Ok(1));
+}
\ No newline at end of file
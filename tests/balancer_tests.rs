@@ -0,0 +1,203 @@
+use high_performance_server::http_client::HttpUrl;
+use high_performance_server::{
+    mount_proxy, Balancer, ConnectionAcceptor, EventBus, EventLoop, HealthPolicy, Method, Proxy, Request, Router,
+    ServerEvent, Stickiness,
+};
+use std::collections::HashSet;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+fn balancer(count: usize) -> Balancer {
+    let upstreams = (0..count)
+        .map(|i| HttpUrl::parse(&format!("http://10.0.0.{}:8080", i + 1)).unwrap())
+        .collect();
+    Balancer::new(upstreams)
+}
+
+fn request_from(ip: &str) -> Request {
+    let mut request = Request::new(Method::Get, "/");
+    request.peer_addr = Some(SocketAddr::new(ip.parse().unwrap(), 40000));
+    request
+}
+
+/// Mark the upstream at `index` down under the default policy
+fn take_down(balancer: &Balancer, index: usize) {
+    for _ in 0..HealthPolicy::default().max_failures {
+        balancer.report_failure(index);
+    }
+}
+
+#[test]
+fn test_round_robin_visits_every_upstream() {
+    let balancer = balancer(3);
+    let request = Request::new(Method::Get, "/");
+    
+    let picked: Vec<usize> = (0..6).map(|_| balancer.pick(&request, &[]).unwrap().index).collect();
+    assert_eq!(picked, vec![0, 1, 2, 0, 1, 2]);
+    assert!(balancer.pick(&request, &[]).unwrap().pin.is_none());
+}
+
+#[test]
+fn test_pick_skips_tried_upstreams() {
+    let balancer = balancer(2);
+    let request = Request::new(Method::Get, "/");
+    
+    assert_eq!(balancer.pick(&request, &[0]).unwrap().index, 1);
+    assert!(balancer.pick(&request, &[0, 1]).is_none());
+}
+
+#[test]
+fn test_ip_hash_only_moves_clients_of_a_down_upstream() {
+    let balancer = balancer(4).sticky(Stickiness::IpHash);
+    let ips: Vec<String> = (0..200).map(|i| format!("192.168.{}.{}", i / 250, i % 250)).collect();
+    let before: Vec<usize> = ips.iter().map(|ip| balancer.pick(&request_from(ip), &[]).unwrap().index).collect();
+    
+    // Stable, and spread over every upstream
+    for (ip, index) in ips.iter().zip(&before) {
+        assert_eq!(balancer.pick(&request_from(ip), &[]).unwrap().index, *index);
+    }
+    assert_eq!(before.iter().collect::<HashSet<_>>().len(), 4);
+    
+    take_down(&balancer, 2);
+    assert!(!balancer.is_healthy(2));
+    for (ip, index) in ips.iter().zip(&before) {
+        let now = balancer.pick(&request_from(ip), &[]).unwrap().index;
+        if *index == 2 {
+            assert_ne!(now, 2);
+        } else {
+            assert_eq!(now, *index);
+        }
+    }
+}
+
+#[test]
+fn test_upstream_taken_down_is_reported() {
+    let events = Arc::new(EventBus::new());
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let received_clone = received.clone();
+    events.subscribe(move |event| received_clone.lock().unwrap().push(event.clone()));
+    
+    let balancer = balancer(2).with_events(events);
+    take_down(&balancer, 1);
+    // Failures while it is already down don't report it again
+    balancer.report_failure(1);
+    
+    let received = received.lock().unwrap();
+    assert_eq!(received.len(), 1);
+    assert!(matches!(
+        &received[0],
+        ServerEvent::UpstreamUnhealthy { upstream, .. } if upstream == "10.0.0.2:8080"
+    ));
+}
+
+#[test]
+fn test_cookie_pins_client_and_repins_when_down() {
+    let balancer = balancer(3).sticky(Stickiness::Cookie("backend".to_string()));
+    
+    let first = balancer.pick(&request_from("10.1.1.1"), &[]).unwrap();
+    let cookie = first.pin.clone().unwrap();
+    assert!(cookie.starts_with("backend="), "{}", cookie);
+    assert!(cookie.contains("; Path=/; Max-Age=86400; HttpOnly"), "{}", cookie);
+    
+    // The cookie wins over the client's address
+    let mut request = request_from("10.9.9.9");
+    request.set_header("Cookie", &format!("theme=dark; {}", cookie.split(';').next().unwrap()));
+    for _ in 0..5 {
+        let pick = balancer.pick(&request, &[]).unwrap();
+        assert_eq!(pick.index, first.index);
+        assert!(pick.pin.is_none());
+    }
+    
+    take_down(&balancer, first.index);
+    let moved = balancer.pick(&request, &[]).unwrap();
+    assert_ne!(moved.index, first.index);
+    assert!(moved.pin.unwrap() != cookie);
+    
+    // Unknown ids are treated as no cookie at all
+    request.set_header("Cookie", "backend=nope");
+    assert!(balancer.pick(&request, &[]).unwrap().pin.is_some());
+}
+
+#[test]
+fn test_all_down_still_picks_and_recovers() {
+    let balancer = balancer(2).with_health(HealthPolicy {
+        max_failures: 1,
+        cooldown: Duration::from_millis(100),
+    });
+    let request = Request::new(Method::Get, "/");
+    
+    balancer.report_failure(0);
+    balancer.report_failure(1);
+    assert!(!balancer.is_healthy(0) && !balancer.is_healthy(1));
+    assert!(balancer.pick(&request, &[]).is_some());
+    
+    thread::sleep(Duration::from_millis(150));
+    assert!(balancer.is_healthy(0) && balancer.is_healthy(1));
+    
+    // One success clears the failure count
+    balancer.report_failure(0);
+    balancer.report_success(0);
+    assert!(balancer.is_healthy(0));
+}
+
+#[test]
+fn test_proxy_fails_over_to_a_reachable_upstream() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let good_url = HttpUrl::parse(&format!("http://{}", listener.local_addr().unwrap())).unwrap();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut request = Vec::new();
+            let mut byte = [0u8; 1];
+            while !request.ends_with(b"\r\n\r\n") && stream.read(&mut byte).unwrap_or(0) == 1 {
+                request.push(byte[0]);
+            }
+            let _ = stream.write_all(b"HTTP/1.1 204 No Content\r\n\r\n");
+        }
+    });
+    
+    // Find a refused upstream the client's address hashes to, so every request has to fail over from it
+    let sticky = Stickiness::Cookie("backend".to_string());
+    let (refused, refused_url) = loop {
+        let refused = TcpListener::bind("127.0.0.1:0").unwrap();
+        let refused_url = HttpUrl::parse(&format!("http://{}", refused.local_addr().unwrap())).unwrap();
+        let probe = Balancer::new(vec![refused_url.clone(), good_url.clone()]).sticky(sticky.clone());
+        if probe.pick(&request_from("127.0.0.1"), &[]).unwrap().index == 0 {
+            break (refused, refused_url);
+        }
+    };
+    let balancer = Balancer::new(vec![refused_url, good_url]).sticky(sticky);
+    let proxy = Arc::new(Proxy::balanced(balancer));
+    let mut router = Router::new();
+    mount_proxy(&mut router, "/api", proxy.clone());
+    let acceptor = Arc::new(ConnectionAcceptor::new("127.0.0.1:0").unwrap());
+    let addr = acceptor.local_addr().unwrap();
+    // Only free the refused port once the proxy can't be given it
+    drop(refused);
+    let drain = Arc::new(AtomicBool::new(false));
+    let drain_clone = drain.clone();
+    thread::spawn(move || {
+        let mut event_loop = EventLoop::new(0, acceptor);
+        event_loop.set_router(Arc::new(router));
+        event_loop.set_drain_signal(drain_clone);
+        event_loop.run().unwrap();
+    });
+    
+    // Without a cookie, every request tries the refused upstream first
+    for _ in 0..3 {
+        let mut client = TcpStream::connect(addr).unwrap();
+        client.write_all(b"GET /api/items HTTP/1.1\r\nHost: a\r\n\r\n").unwrap();
+        let mut response = Vec::new();
+        client.read_to_end(&mut response).unwrap();
+        let response = String::from_utf8_lossy(&response);
+        assert!(response.starts_with("HTTP/1.1 204 No Content\r\n"), "{}", response);
+        assert!(response.contains("Set-Cookie: backend="), "{}", response);
+    }
+    assert!(!proxy.balancer().is_healthy(0));
+    assert!(proxy.balancer().is_healthy(1));
+    drain.store(true, Ordering::SeqCst);
+}
\ No newline at end of file
@@ -100,4 +100,116 @@ fn test_buffer_reset() {
     buffer.reset();
     assert_eq!(buffer.available_data(), 0);
     assert_eq!(buffer.remaining_capacity(), 1024);
+}
+
+#[test]
+fn test_buffer_max_capacity() {
+    let mut buffer = Buffer::new(16);
+    buffer.set_max_capacity(40);
+    
+    buffer.write(&[1u8; 30]).unwrap();
+    assert_eq!(buffer.capacity(), 40);
+    
+    // Writes beyond the limit are cut short
+    assert_eq!(buffer.write(&[2u8; 30]).unwrap(), 10);
+    assert_eq!(buffer.capacity(), 40);
+    assert!(buffer.is_full());
+    
+    // A full buffer refuses to read rather than reporting EOF
+    let mut reader = Cursor::new(vec![3u8; 10]);
+    let error = buffer.read_from(&mut reader).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::OutOfMemory);
+}
+
+#[test]
+fn test_buffer_peek_and_consume() {
+    let mut buffer = Buffer::new(16);
+    buffer.write(b"hello world").unwrap();
+    
+    assert_eq!(buffer.peek(5), b"hello");
+    assert_eq!(buffer.peek(100), b"hello world");
+    assert_eq!(buffer.available_data(), 11);
+    
+    buffer.consume(6).unwrap();
+    assert_eq!(buffer.peek(5), b"world");
+    assert!(buffer.consume(6).is_err());
+}
+
+#[test]
+fn test_buffer_mark_and_rollback() {
+    let mut buffer = Buffer::new(8);
+    buffer.write(b"abcdef").unwrap();
+    buffer.consume(2).unwrap();
+    
+    buffer.mark();
+    buffer.consume(4).unwrap();
+    assert_eq!(buffer.available_data(), 0);
+    
+    // Growing the buffer keeps the marked bytes
+    buffer.write(&[b'x'; 20]).unwrap();
+    assert!(buffer.rollback());
+    assert_eq!(buffer.peek(6), b"cdefxx");
+    assert!(!buffer.rollback());
+    
+    // Unmarking commits what was consumed
+    buffer.mark();
+    buffer.consume(24).unwrap();
+    buffer.unmark();
+    assert!(!buffer.rollback());
+    assert_eq!(buffer.available_data(), 0);
+}
+
+#[test]
+fn test_ring_buffer_wraps_around() {
+    let mut buffer = Buffer::ring(4096);
+    let capacity = buffer.capacity();
+    assert!(capacity >= 4096);
+    
+    // Walk the data across the end of the ring several times
+    let chunk: Vec<u8> = (0..=255u8).cycle().take(capacity / 3 + 7).collect();
+    for _ in 0..10 {
+        assert_eq!(buffer.write(&chunk).unwrap(), chunk.len());
+        assert_eq!(buffer.write(b"tail").unwrap(), 4);
+        assert_eq!(buffer.peek(chunk.len()), &chunk[..]);
+        buffer.consume(chunk.len()).unwrap();
+        assert_eq!(buffer.slice(), b"tail");
+        buffer.consume(2).unwrap();
+        assert_eq!(buffer.slice(), b"il");
+        buffer.consume(2).unwrap();
+    }
+    assert_eq!(buffer.capacity(), capacity);
+}
+
+#[test]
+fn test_ring_buffer_mark_and_growth() {
+    let mut buffer = Buffer::ring(4096);
+    let capacity = buffer.capacity();
+    
+    buffer.write(&vec![b'a'; capacity - 10]).unwrap();
+    buffer.consume(capacity - 20).unwrap();
+    buffer.mark();
+    buffer.consume(10).unwrap();
+    
+    // Wrapping writes keep the marked bytes
+    buffer.write(&[b'b'; 20]).unwrap();
+    assert!(buffer.rollback());
+    assert_eq!(buffer.peek(30), [&[b'a'; 10][..], &[b'b'; 20][..]].concat());
+    
+    // Filling the ring grows it without losing data
+    buffer.write(&vec![b'c'; capacity]).unwrap();
+    assert!(buffer.capacity() > capacity);
+    assert_eq!(buffer.available_data(), 30 + capacity);
+    assert_eq!(&buffer.slice()[..30], &[&[b'a'; 10][..], &[b'b'; 20][..]].concat()[..]);
+}
+
+#[test]
+fn test_ring_buffer_respects_max_capacity() {
+    let mut buffer = Buffer::ring(4096);
+    let capacity = buffer.capacity();
+    buffer.set_max_capacity(capacity);
+    
+    assert_eq!(buffer.write(&vec![0; capacity + 100]).unwrap(), capacity);
+    assert!(buffer.is_full());
+    let error = buffer.read_from(&mut Cursor::new(vec![0; 10])).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::OutOfMemory);
 }
\ No newline at end of file
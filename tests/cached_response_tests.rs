@@ -0,0 +1,82 @@
+use high_performance_server::cached_response::CachedResponse;
+use high_performance_server::http::{Method, Request, Response, Status};
+use high_performance_server::Router;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+fn health_response() -> Response {
+    let mut response = Response::new(Status::Ok);
+    response.set_body(b"ok");
+    response.set_header("Cache-Control", "no-store");
+    response.set_header("Connection", "close");
+    response
+}
+
+#[test]
+fn test_prepared_response_serializes_like_the_original() {
+    let cached = CachedResponse::new(health_response());
+    let mut response = cached.response();
+    assert!(response.is_prepared());
+    assert_eq!(response.status, Status::Ok);
+    assert_eq!(response.header("Cache-Control"), Some("no-store"));
+    assert_eq!(response.body_bytes(), b"ok");
+    
+    // Connection is left to the server, and new headers follow the prepared ones
+    assert_eq!(response.header("Connection"), None);
+    response.set_header("Connection", "keep-alive");
+    assert!(response.is_prepared());
+    let mut served = Vec::new();
+    response.serialize(&mut served).unwrap();
+    
+    let mut expected = health_response();
+    expected.set_header("Connection", "keep-alive");
+    let mut expected_bytes = Vec::new();
+    expected.serialize(&mut expected_bytes).unwrap();
+    let lines = |bytes: &[u8]| {
+        let text = String::from_utf8(bytes.to_vec()).unwrap();
+        let (head, body) = text.split_once("\r\n\r\n").unwrap();
+        let mut lines: Vec<String> = head.split("\r\n").map(str::to_string).collect();
+        lines[1..].sort();
+        (lines, body.to_string())
+    };
+    assert_eq!(lines(&served), lines(&expected_bytes));
+    
+    // Changing a prepared header or the body turns it back into an ordinary response
+    response.set_header("Cache-Control", "max-age=5");
+    assert!(!response.is_prepared());
+    assert_eq!(response.headers.get("Cache-Control").unwrap(), "max-age=5");
+    assert_eq!(response.headers.get("Connection").unwrap(), "keep-alive");
+    assert_eq!(response.body, b"ok");
+}
+
+#[test]
+fn test_invalidation_rebuilds_on_next_request() {
+    let builds = Arc::new(AtomicUsize::new(0));
+    let counter = builds.clone();
+    let cached = CachedResponse::with_builder(move || {
+        let build = counter.fetch_add(1, Ordering::SeqCst) + 1;
+        let mut response = Response::new(Status::Ok);
+        response.set_body(format!("build {}", build).as_bytes());
+        response
+    });
+    
+    let mut router = Router::new();
+    router.get("/version", cached.handler());
+    let fixed = router.get_cached("/health", health_response());
+    let request = Request::new(Method::Get, "/version");
+    
+    assert!(!cached.is_cached());
+    for _ in 0..3 {
+        assert_eq!(router.handle_request(&request).unwrap().body_bytes(), b"build 1");
+    }
+    assert_eq!(builds.load(Ordering::SeqCst), 1);
+    
+    cached.invalidate();
+    assert!(!cached.is_cached());
+    assert_eq!(router.handle_request(&request).unwrap().body_bytes(), b"build 2");
+    assert_eq!(builds.load(Ordering::SeqCst), 2);
+    
+    let health = router.handle_request(&Request::new(Method::Get, "/health")).unwrap();
+    assert!(health.is_prepared() && fixed.is_cached());
+    assert_eq!(health.body_bytes(), b"ok");
+}
\ No newline at end of file
@@ -0,0 +1,52 @@
+use high_performance_server::capacity::{self, CapacityPlan, FdLimit, RESERVED_FDS};
+use high_performance_server::ServerConfig;
+
+#[test]
+fn test_connection_cap_derived_from_fd_limit() {
+    let config = ServerConfig::default().with_worker_threads(4);
+    let limit = FdLimit { soft: 1024, hard: 4096 };
+    let plan = CapacityPlan::new(&config, limit, 1);
+    
+    // Four pollers, and a listener with its spare descriptor, come out of the reserve too
+    assert_eq!(plan.reserved_fds, RESERVED_FDS + 4 + 2);
+    assert_eq!(plan.max_connections_per_worker, (1024 - plan.reserved_fds as usize) / 4);
+    assert_eq!(plan.poll_events, plan.max_connections_per_worker + 1);
+    assert!(plan.warnings.is_empty());
+    
+    // Even a tiny limit leaves every worker room for a connection
+    let plan = CapacityPlan::new(&config, FdLimit { soft: 16, hard: 16 }, 1);
+    assert_eq!(plan.max_connections_per_worker, 1);
+    assert_eq!(plan.poll_events, capacity::MIN_POLL_EVENTS);
+    
+    // Without a cap, pollers keep their usual size
+    assert_eq!(capacity::poll_events(None, 1), capacity::DEFAULT_POLL_EVENTS);
+    assert_eq!(capacity::poll_events(Some(1_000_000), 1), capacity::MAX_POLL_EVENTS);
+}
+
+#[test]
+fn test_configured_cap_beyond_fd_limit_warns() {
+    let config = ServerConfig::default().with_worker_threads(2).with_max_connections_per_worker(1000);
+    
+    let plan = CapacityPlan::new(&config, FdLimit { soft: 1024, hard: 65536 }, 1);
+    assert_eq!(plan.max_connections_per_worker, 1000);
+    assert_eq!(plan.warnings.len(), 1);
+    assert!(plan.warnings[0].contains("needs 2068 file descriptors"), "{}", plan.warnings[0]);
+    assert!(plan.warnings[0].contains("raise_fd_limit"), "{}", plan.warnings[0]);
+    
+    // Raising the soft limit wouldn't help when the hard limit is too low
+    let plan = CapacityPlan::new(&config, FdLimit { soft: 1024, hard: 1024 }, 1);
+    assert!(!plan.warnings[0].contains("raise_fd_limit"), "{}", plan.warnings[0]);
+    
+    let plan = CapacityPlan::new(&config, FdLimit { soft: 4096, hard: 4096 }, 1);
+    assert!(plan.warnings.is_empty());
+    
+    // The process's own limit can be read, and a raise it already covers changes nothing
+    #[cfg(unix)]
+    {
+        let limit = FdLimit::current().unwrap();
+        assert!(limit.soft <= limit.hard);
+        assert_eq!(limit.raise_to(limit.soft).unwrap(), limit);
+    }
+    
+    assert!(ServerConfig::default().with_max_connections_per_worker(0).validate().is_err());
+}
\ No newline at end of file
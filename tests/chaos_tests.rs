@@ -0,0 +1,169 @@
+use high_performance_server::{
+    chaos_middleware, Chaos, ChaosConfig, ChaosRule, Fault, Method, MiddlewareChain, Request, Response,
+    Status,
+};
+use high_performance_server::metrics::MetricsRegistry;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+fn config(rules: Vec<ChaosRule>) -> ChaosConfig {
+    ChaosConfig {
+        enabled: true,
+        rules,
+        seed: Some(42),
+    }
+}
+
+fn rule(path: &str) -> ChaosRule {
+    ChaosRule {
+        path: path.to_string(),
+        ..ChaosRule::default()
+    }
+}
+
+fn chain(chaos: Arc<Chaos>) -> MiddlewareChain {
+    let mut chain = MiddlewareChain::new();
+    chain.add(chaos_middleware(chaos));
+    chain.set_handler(|_| {
+        let mut response = Response::new(Status::Ok);
+        response.set_body(b"0123456789");
+        Ok(response)
+    });
+    chain
+}
+
+#[test]
+fn test_rules_apply_by_path_prefix() {
+    let chaos = Chaos::new(config(vec![
+        ChaosRule {
+            error_probability: 1.0,
+            ..rule("/api/")
+        },
+        ChaosRule {
+            reset_probability: 1.0,
+            ..rule("/api/admin")
+        },
+    ]))
+    .unwrap();
+    
+    assert_eq!(chaos.decide("/api").1, Fault::Error(Status::ServiceUnavailable));
+    assert_eq!(chaos.decide("/api/users").1, Fault::Error(Status::ServiceUnavailable));
+    // The longest prefix wins, and prefixes cover whole segments only
+    assert_eq!(chaos.decide("/api/admin/keys").1, Fault::Reset);
+    assert_eq!(chaos.decide("/apis").1, Fault::None);
+    assert_eq!(chaos.decide("/").1, Fault::None);
+    
+    chaos.set_enabled(false);
+    assert!(!chaos.is_enabled());
+    assert_eq!(chaos.decide("/api/users"), (Duration::ZERO, Fault::None));
+    
+    let invalid = [
+        ChaosRule {
+            latency_probability: 1.5,
+            ..rule("/")
+        },
+        ChaosRule {
+            error_probability: 0.6,
+            reset_probability: 0.6,
+            ..rule("/")
+        },
+        ChaosRule {
+            error_status: 404,
+            ..rule("/")
+        },
+    ];
+    for rule in invalid {
+        assert!(Chaos::new(config(vec![rule])).is_err());
+    }
+}
+
+#[test]
+fn test_faults_follow_their_probabilities() {
+    let mixed = ChaosRule {
+        latency_probability: 0.5,
+        latency: Duration::from_millis(100),
+        latency_jitter: Duration::from_millis(50),
+        error_probability: 0.2,
+        truncate_probability: 0.2,
+        reset_probability: 0.1,
+        ..rule("/")
+    };
+    let chaos = Chaos::new(config(vec![mixed.clone()])).unwrap();
+    let (mut delayed, mut errors, mut truncated, mut resets) = (0i32, 0, 0, 0);
+    for _ in 0..10_000 {
+        let (delay, fault) = chaos.decide("/anything");
+        if !delay.is_zero() {
+            assert!(delay >= Duration::from_millis(100) && delay <= Duration::from_millis(150));
+            delayed += 1;
+        }
+        match fault {
+            Fault::Error(_) => errors += 1,
+            Fault::Truncate => truncated += 1,
+            Fault::Reset => resets += 1,
+            Fault::None => {}
+        }
+    }
+    for (count, expected) in [(delayed, 5000), (errors, 2000), (truncated, 2000), (resets, 1000)] {
+        assert!((count - expected).abs() < 300, "{} vs {}", count, expected);
+    }
+    
+    // The same seed makes the same choices
+    let again = Chaos::new(config(vec![mixed.clone()])).unwrap();
+    let first = Chaos::new(config(vec![mixed])).unwrap();
+    for _ in 0..100 {
+        assert_eq!(again.decide("/"), first.decide("/"));
+    }
+}
+
+#[test]
+fn test_middleware_injects_faults() {
+    let registry = Arc::new(MetricsRegistry::new());
+    let chaos = Arc::new(
+        Chaos::with_metrics(
+            config(vec![
+                ChaosRule {
+                    latency_probability: 1.0,
+                    latency: Duration::from_millis(50),
+                    ..rule("/slow")
+                },
+                ChaosRule {
+                    error_probability: 1.0,
+                    error_status: 502,
+                    ..rule("/error")
+                },
+                ChaosRule {
+                    truncate_probability: 1.0,
+                    ..rule("/truncate")
+                },
+                ChaosRule {
+                    reset_probability: 1.0,
+                    ..rule("/reset")
+                },
+            ]),
+            registry.clone(),
+        )
+        .unwrap(),
+    );
+    let chain = chain(chaos);
+    let handle = |path: &str| chain.handle(&Request::new(Method::Get, path)).unwrap();
+    
+    let started = Instant::now();
+    assert_eq!(handle("/slow").body, b"0123456789");
+    assert!(started.elapsed() >= Duration::from_millis(50));
+    
+    let error = handle("/error");
+    assert_eq!(error.status, Status::BadGateway);
+    assert!(String::from_utf8_lossy(&error.body).contains("chaos"));
+    
+    let truncated = handle("/truncate");
+    assert_eq!(truncated.body, b"01234");
+    assert_eq!(truncated.headers.get("Content-Length").map(String::as_str), Some("10"));
+    assert_eq!(truncated.headers.get("Connection").map(String::as_str), Some("close"));
+    
+    assert!(handle("/reset").reset_connection);
+    assert!(!handle("/other").reset_connection);
+    
+    for name in ["chaos.delayed", "chaos.errors", "chaos.truncated", "chaos.resets"] {
+        assert_eq!(registry.counter(name).value(), 1, "{}", name);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,56 @@
+#![cfg(target_os = "linux")]
+
+use high_performance_server::{ConnectionAcceptor, EventPoller};
+use std::collections::BTreeSet;
+use std::fs;
+use std::net::TcpStream;
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+
+/// The descriptors this process has open
+fn open_fds() -> BTreeSet<i32> {
+    fs::read_dir("/proc/self/fd")
+        .unwrap()
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+        .collect()
+}
+
+/// The descriptors among `fds` that a spawned child process still has open
+fn inherited_by_child(fds: &BTreeSet<i32>) -> BTreeSet<i32> {
+    let list: Vec<String> = fds.iter().map(|fd| fd.to_string()).collect();
+    // A shell builtin checks each one, so the child opens nothing that could reuse a number
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(format!("for fd in {}; do [ -e /proc/$$/fd/$fd ] && echo $fd; done; true", list.join(" ")))
+        .output()
+        .unwrap();
+    String::from_utf8(output.stdout).unwrap().lines().map(|line| line.parse().unwrap()).collect()
+}
+
+#[test]
+fn test_server_descriptors_are_not_inherited() {
+    let before = open_fds();
+    
+    // A listener with its spare descriptor, an accepted connection, and a poller, replaced once
+    let acceptor = ConnectionAcceptor::new("127.0.0.1:0").unwrap();
+    let client = TcpStream::connect(acceptor.local_addr().unwrap()).unwrap();
+    thread::sleep(Duration::from_millis(20));
+    let connection = acceptor.accept().unwrap();
+    let mut poller = EventPoller::new(16).unwrap();
+    poller.replace().unwrap();
+    
+    let opened: BTreeSet<i32> = open_fds().difference(&before).copied().collect();
+    assert!(opened.len() >= 5, "{:?}", opened);
+    assert_eq!(inherited_by_child(&opened), BTreeSet::new());
+    
+    // A descriptor without close-on-exec does reach the child, so the check can fail
+    let leaked = unsafe { libc::dup(0) };
+    assert!(leaked >= 0);
+    assert_eq!(inherited_by_child(&BTreeSet::from([leaked])), BTreeSet::from([leaked]));
+    unsafe {
+        libc::close(leaked);
+    }
+    
+    drop((connection, client, poller));
+}
\ No newline at end of file
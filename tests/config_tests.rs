@@ -0,0 +1,229 @@
+use high_performance_server::{
+    basic_auth_middleware, compression_middleware, deadline_middleware, AdminConfig, Deadline, DeadlineConfig, Method,
+    MiddlewareChain, Request, Response, RouteConfig, RouteOverrides, RouteSettings, ServerConfig, ServerError, Status,
+    WriteBatching,
+};
+use std::env;
+use std::fs;
+use std::time::Duration;
+
+fn config_from_json(routes: &str) -> ServerConfig {
+    let mut json = serde_json::to_value(ServerConfig::default()).unwrap();
+    json["max_request_size"] = 1000.into();
+    json["routes"] = serde_json::from_str(routes).unwrap();
+    let path = env::temp_dir().join(format!("hps-config-{}.json", std::process::id()));
+    fs::write(&path, json.to_string()).unwrap();
+    let config = ServerConfig::from_json_file(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    config
+}
+
+#[test]
+fn test_overrides_merge_over_enclosing_prefixes() {
+    let config = config_from_json(
+        r#"{
+            "/api": {"compression": false, "request_timeout": {"secs": 5, "nanos": 0}},
+            "/api/uploads/": {"max_request_size": 500000},
+            "/health": {"require_auth": false}
+        }"#,
+    );
+    let routes = RouteConfig::new(&config);
+    
+    let global = routes.settings("/index.html");
+    assert_eq!(
+        global,
+        RouteSettings {
+            request_timeout: None,
+            max_request_size: 1000,
+            compression: true,
+            require_auth: true,
+            stream_body: false,
+        }
+    );
+    
+    let api = routes.settings("/api/users?page=2");
+    assert!(!api.compression);
+    assert_eq!(api.request_timeout, Some(Duration::from_secs(5)));
+    assert_eq!(api.max_request_size, 1000);
+    assert_eq!(routes.settings("/api"), api);
+    
+    // Uploads keep the API settings apart from the body limit
+    let uploads = routes.settings("/api/uploads/photo.jpg");
+    assert_eq!(uploads.max_request_size, 500_000);
+    assert!(!uploads.compression);
+    assert_eq!(uploads.request_timeout, Some(Duration::from_secs(5)));
+    
+    // Prefixes match whole segments
+    assert_eq!(routes.settings("/apis"), global);
+    assert_eq!(routes.settings("/api/uploadsx"), api);
+    assert!(!routes.settings("/health/").require_auth);
+    
+    // Spellings a lenient router sends to the same handler get the same settings
+    assert_eq!(routes.settings("/API/Uploads/photo.jpg"), uploads);
+    assert_eq!(routes.settings("//api//uploads/"), uploads);
+    assert!(!routes.settings("/HEALTH").require_auth);
+}
+
+#[test]
+fn test_root_override_changes_the_defaults() {
+    let config = config_from_json(r#"{"/": {"compression": false}, "/assets": {"compression": true}}"#);
+    let routes = RouteConfig::new(&config);
+    assert!(!routes.settings("/").compression);
+    assert!(!routes.settings("/page").compression);
+    assert!(routes.settings("/assets/app.js").compression);
+}
+
+fn request_with(path: &str, settings: RouteSettings) -> Request {
+    let mut request = Request::new(Method::Get, path);
+    request.headers.insert("accept-encoding".into(), "gzip".to_string());
+    request.extensions.insert(settings);
+    request
+}
+
+#[test]
+fn test_middleware_follows_route_settings() {
+    let mut chain = MiddlewareChain::new();
+    chain.add(deadline_middleware(DeadlineConfig::default()));
+    chain.add(basic_auth_middleware("admin".to_string(), "secret".to_string()));
+    chain.add(compression_middleware);
+    chain.set_handler(|req| {
+        let mut response = Response::new(Status::Ok);
+        let remaining = Deadline::of(req).map_or(0, |deadline| deadline.remaining().as_millis());
+        response.set_header("X-Remaining", &remaining.to_string());
+        response.set_body(&[b'a'; 4096]);
+        Ok(response)
+    });
+    
+    let routes = RouteConfig::new(&ServerConfig::default().with_route(
+        "/public",
+        RouteOverrides {
+            request_timeout: Some(Duration::from_millis(500)),
+            compression: Some(false),
+            require_auth: Some(false),
+            ..Default::default()
+        },
+    ));
+    
+    let private = chain.handle(&request_with("/private", routes.settings("/private"))).unwrap();
+    assert_eq!(private.status, Status::Unauthorized);
+    
+    let public = chain.handle(&request_with("/public/page", routes.settings("/public/page"))).unwrap();
+    assert_eq!(public.status, Status::Ok);
+    assert_eq!(public.body.len(), 4096);
+    assert!(!public.headers.contains_key("Content-Encoding"));
+    let remaining: u64 = public.headers["X-Remaining"].parse().unwrap();
+    assert!(remaining > 400 && remaining <= 500);
+}
+
+fn config_error(json: &str) -> String {
+    match ServerConfig::from_json(json) {
+        Err(ServerError::Config(message)) => message,
+        other => panic!("expected a configuration error, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_unknown_keys_are_reported_with_a_suggestion() {
+    let mut json = serde_json::to_string_pretty(&ServerConfig::default()).unwrap();
+    json = json.replacen("\"port\"", "\"prot\"", 1);
+    let message = config_error(&json);
+    assert!(message.contains("unknown key `prot`; did you mean `port`?"), "{}", message);
+    assert!(message.contains("\"prot\": 8080"), "{}", message);
+    let line = json.lines().position(|line| line.contains("prot")).unwrap() + 1;
+    assert!(message.starts_with(&format!("line {},", line)), "{}", message);
+    
+    // Nested sections are checked too
+    let json = json.replacen("\"prot\"", "\"port\"", 1);
+    let message = config_error(&json.replacen("\"keepalive_time\"", "\"keepalive_tme\"", 1));
+    assert!(message.contains("did you mean `keepalive_time`?"), "{}", message);
+    let export = r#""metrics_export": {"target": {"type": "stats", "address": "127.0.0.1:8125"}}"#;
+    let message = config_error(&json.replacen("\"metrics_export\": null", export, 1));
+    assert!(message.contains("unknown value `stats`; did you mean `statsd`?"), "{}", message);
+    
+    // Nothing close enough to suggest
+    let message = config_error(&json.replacen("\"port\"", "\"completely_unrelated\"", 1));
+    assert!(message.contains("unknown key `completely_unrelated`\n"), "{}", message);
+}
+
+#[test]
+fn test_validation_names_every_bad_key() {
+    assert!(ServerConfig::default().validate().is_ok());
+    
+    let mut config = ServerConfig::default()
+        .with_address("127.0.0.1", 0)
+        .with_worker_threads(0)
+        .with_connection_timeout(Duration::ZERO)
+        .with_max_header_size(4 * 1024 * 1024)
+        .with_write_batching(Some(WriteBatching {
+            max_delay: Duration::from_secs(1),
+            max_bytes: 0,
+        }))
+        .with_route(
+            "api",
+            RouteOverrides {
+                max_request_size: Some(64 * 1024 * 1024),
+                ..RouteOverrides::default()
+            },
+        );
+    config.event_webhook = Some("https://hooks.example.com/server".into());
+    let message = match config.validate() {
+        Err(ServerError::Config(message)) => message,
+        other => panic!("expected a configuration error, got {:?}", other),
+    };
+    for key in [
+        "`port`",
+        "`worker_threads`",
+        "`connection_timeout`",
+        "`max_header_size`",
+        "`event_webhook`",
+        "`write_batching.max_delay`",
+        "`write_batching.max_bytes`",
+        "`routes[\"api\"]`",
+        "`routes[\"api\"].max_request_size`",
+    ] {
+        assert!(message.contains(key), "{} missing from {}", key, message);
+    }
+    assert_eq!(message.lines().count(), 10, "{}", message);
+    
+    // The port is unused when listen addresses are given
+    let config = ServerConfig::default().with_address("127.0.0.1", 0).with_listen_addresses(["[::1]:8080", "nope"]);
+    let message = config.validate().unwrap_err().to_string();
+    assert!(message.contains("`listen_addresses[1]`") && !message.contains("`port`"), "{}", message);
+}
+
+#[test]
+fn test_admin_routes_need_credentials() {
+    let admin = AdminConfig {
+        maintenance_path: Some("/admin/maintenance".to_string()),
+        ..AdminConfig::default()
+    };
+    let message = ServerConfig::default().with_admin(admin.clone()).validate().unwrap_err().to_string();
+    assert!(message.contains("`admin.username`") && message.contains("`admin.password`"), "{}", message);
+    
+    let admin = AdminConfig {
+        username: "ops".to_string(),
+        password: "s3cret".into(),
+        ..admin
+    };
+    assert!(ServerConfig::default().with_admin(admin.clone()).validate().is_ok());
+    
+    let admin = AdminConfig {
+        maintenance_path: Some("/admin/:switch".to_string()),
+        ..admin
+    };
+    let message = ServerConfig::default().with_admin(admin).validate().unwrap_err().to_string();
+    assert!(message.contains("`admin.maintenance_path`"), "{}", message);
+}
+
+#[test]
+fn test_saved_config_loads_back() {
+    let path = env::temp_dir().join(format!("hps-config-saved-{}.json", std::process::id()));
+    let config = ServerConfig::default().with_worker_threads(3);
+    config.save_to_json_file(&path).unwrap();
+    assert_eq!(ServerConfig::from_json_file(&path).unwrap().worker_threads, 3);
+    
+    fs::write(&path, "{\"worker_threads\": 0}").unwrap();
+    let message = ServerConfig::from_json_file(&path).unwrap_err().to_string();
+    assert!(message.contains(&path.display().to_string()) && message.contains("missing field"), "{}", message);
+    fs::remove_file(&path).unwrap();
+}
\ No newline at end of file
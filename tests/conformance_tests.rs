@@ -0,0 +1,203 @@
+//! Runs the server against the HTTP/1.1 conformance corpus in `tests/conformance`
+//!
+//! Each fixture file holds a list of cases. A case sends raw bytes on a
+//! fresh connection, in one write or split into `chunks`, and lists the
+//! statuses (and optionally bodies) of the responses it must get back in
+//! order. `connection` says whether the server must then keep the
+//! connection open, checked with a follow-up request, or close it.
+
+use high_performance_server::{ConnectionAcceptor, EventLoop, Response, Router, Status};
+use serde::Deserialize;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Case {
+    name: String,
+    #[serde(default)]
+    send: Option<String>,
+    #[serde(default)]
+    chunks: Vec<String>,
+    responses: Vec<u16>,
+    #[serde(default)]
+    bodies: Vec<String>,
+    connection: Expect,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum Expect {
+    Open,
+    Closed,
+}
+
+/// A response read off the wire
+struct Received {
+    status: u16,
+    body: Vec<u8>,
+}
+
+fn spawn_server() -> (SocketAddr, Arc<AtomicBool>, thread::JoinHandle<()>) {
+    let mut router = Router::new();
+    router.get("/", |_| {
+        let mut response = Response::new(Status::Ok);
+        response.set_body(b"ok");
+        Ok(response)
+    });
+    router.post("/echo", |request| {
+        let mut response = Response::new(Status::Ok);
+        response.set_body(&request.body);
+        Ok(response)
+    });
+    
+    let acceptor = Arc::new(ConnectionAcceptor::new("127.0.0.1:0").unwrap());
+    let addr = acceptor.local_addr().unwrap();
+    let drain = Arc::new(AtomicBool::new(false));
+    let drain_clone = drain.clone();
+    let handle = thread::spawn(move || {
+        let mut event_loop = EventLoop::new(0, acceptor);
+        event_loop.set_router(Arc::new(router));
+        event_loop.set_drain_signal(drain_clone);
+        event_loop.run().unwrap();
+    });
+    (addr, drain, handle)
+}
+
+/// Read one response, or `None` if the server closed the connection first
+fn read_response(stream: &mut TcpStream) -> Result<Option<Received>, String> {
+    let mut head = Vec::new();
+    let mut byte = [0u8; 1];
+    while !head.ends_with(b"\r\n\r\n") {
+        match stream.read(&mut byte) {
+            Ok(0) if head.is_empty() => return Ok(None),
+            Ok(0) => return Err(format!("connection closed mid-head: {:?}", String::from_utf8_lossy(&head))),
+            Ok(_) => head.push(byte[0]),
+            Err(e) if head.is_empty() && e.kind() == ErrorKind::ConnectionReset => return Ok(None),
+            Err(e) => return Err(format!("reading head: {}", e)),
+        }
+    }
+    
+    let head = String::from_utf8_lossy(&head).into_owned();
+    let mut lines = head.split("\r\n");
+    let status_line = lines.next().unwrap_or_default();
+    let status = status_line
+        .strip_prefix("HTTP/1.1 ")
+        .and_then(|rest| rest.get(..3))
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| format!("bad status line {:?}", status_line))?;
+    let length = lines
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.eq_ignore_ascii_case("Content-Length"))
+        .map(|(_, value)| value.trim().parse::<usize>())
+        .transpose()
+        .map_err(|e| format!("bad Content-Length: {}", e))?
+        .unwrap_or(0);
+    
+    let mut body = vec![0u8; length];
+    stream.read_exact(&mut body).map_err(|e| format!("reading body: {}", e))?;
+    Ok(Some(Received { status, body }))
+}
+
+/// Check that the server closes the connection without sending anything more
+fn expect_closed(stream: &mut TcpStream) -> Result<(), String> {
+    let mut rest = Vec::new();
+    match stream.read_to_end(&mut rest) {
+        Ok(_) if rest.is_empty() => Ok(()),
+        Ok(_) => Err(format!("unexpected data before close: {:?}", String::from_utf8_lossy(&rest))),
+        Err(e) if e.kind() == ErrorKind::ConnectionReset => Ok(()),
+        Err(e) => Err(format!("connection left open: {}", e)),
+    }
+}
+
+fn run_case(addr: SocketAddr, case: &Case) -> Result<(), String> {
+    let mut stream = TcpStream::connect(addr).map_err(|e| e.to_string())?;
+    stream.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+    stream.set_nodelay(true).unwrap();
+    
+    let chunks = match &case.send {
+        Some(send) => std::slice::from_ref(send),
+        None => &case.chunks[..],
+    };
+    for (i, chunk) in chunks.iter().enumerate() {
+        if i > 0 {
+            thread::sleep(Duration::from_millis(20));
+        }
+        stream.write_all(chunk.as_bytes()).map_err(|e| format!("sending: {}", e))?;
+    }
+    
+    for (i, &expected) in case.responses.iter().enumerate() {
+        let received = read_response(&mut stream)?
+            .ok_or_else(|| format!("connection closed before response {}", i + 1))?;
+        if received.status != expected {
+            return Err(format!("response {}: expected {}, got {}", i + 1, expected, received.status));
+        }
+        if let Some(body) = case.bodies.get(i) {
+            if received.body != body.as_bytes() {
+                return Err(format!(
+                    "response {}: expected body {:?}, got {:?}",
+                    i + 1,
+                    body,
+                    String::from_utf8_lossy(&received.body)
+                ));
+            }
+        }
+    }
+    
+    match case.connection {
+        Expect::Closed => expect_closed(&mut stream),
+        Expect::Open => {
+            stream
+                .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+                .map_err(|e| format!("connection closed: {}", e))?;
+            match read_response(&mut stream)? {
+                Some(received) if received.status == 200 => Ok(()),
+                Some(received) => Err(format!("follow-up request got {}", received.status)),
+                None => Err("connection closed, expected it to stay open".to_string()),
+            }
+        }
+    }
+}
+
+#[test]
+fn test_conformance_corpus() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/conformance");
+    let mut fixtures: Vec<_> = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    fixtures.sort();
+    assert!(!fixtures.is_empty(), "no fixtures in {}", dir.display());
+    
+    let (addr, drain, server) = spawn_server();
+    let mut cases = 0;
+    let mut failures = Vec::new();
+    for fixture in &fixtures {
+        let file = fixture.file_name().unwrap().to_string_lossy().into_owned();
+        let text = std::fs::read_to_string(fixture).unwrap();
+        let fixture_cases: Vec<Case> =
+            serde_json::from_str(&text).unwrap_or_else(|e| panic!("{}: {}", file, e));
+        for case in &fixture_cases {
+            assert!(
+                case.send.is_some() == case.chunks.is_empty(),
+                "{}: {}: give exactly one of send and chunks",
+                file,
+                case.name
+            );
+            cases += 1;
+            if let Err(reason) = run_case(addr, case) {
+                failures.push(format!("{}: {}: {}", file, case.name, reason));
+            }
+        }
+    }
+    
+    drain.store(true, Ordering::SeqCst);
+    server.join().unwrap();
+    assert!(failures.is_empty(), "{} of {} cases failed:\n{}", failures.len(), cases, failures.join("\n"));
+}
\ No newline at end of file
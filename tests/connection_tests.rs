@@ -0,0 +1,226 @@
+use high_performance_server::{Connection, ConnectionState, WriteStatus};
+use high_performance_server::http::{Response, Status};
+use high_performance_server::Phase;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+fn connection_pair() -> (Connection, TcpStream) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (stream, peer_addr) = listener.accept().unwrap();
+    stream.set_nonblocking(true).unwrap();
+    
+    (Connection::new(stream, peer_addr, 1).unwrap(), client)
+}
+
+#[test]
+fn test_write_all_complete() {
+    let (mut conn, mut client) = connection_pair();
+    
+    assert_eq!(conn.write_all(b"hello").unwrap(), WriteStatus::Complete);
+    assert!(!conn.has_pending_writes());
+    
+    let mut buf = [0u8; 5];
+    client.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"hello");
+}
+
+#[test]
+fn test_write_all_queues_partial_writes() {
+    let (mut conn, mut client) = connection_pair();
+    
+    // Far more than the socket buffers can hold while nobody is reading
+    let data = vec![b'x'; 16 * 1024 * 1024];
+    assert_eq!(conn.write_all(&data).unwrap(), WriteStatus::WouldBlock);
+    assert!(conn.has_pending_writes());
+    assert!(conn.pending_write_bytes() < data.len());
+    
+    let reader = thread::spawn(move || {
+        let mut received = Vec::new();
+        client.read_to_end(&mut received).unwrap();
+        received.len()
+    });
+    
+    loop {
+        match conn.flush().unwrap() {
+            WriteStatus::Complete => break,
+            _ => thread::sleep(Duration::from_millis(1)),
+        }
+    }
+    
+    assert_eq!(conn.pending_write_bytes(), 0);
+    conn.close().unwrap();
+    assert_eq!(reader.join().unwrap(), data.len());
+}
+
+#[test]
+fn test_write_all_throttled() {
+    let (mut conn, _client) = connection_pair();
+    conn.set_rate_limit(Some(100));
+    
+    assert_eq!(conn.write_all(&[0u8; 250]).unwrap(), WriteStatus::Throttled);
+    assert_eq!(conn.pending_write_bytes(), 150);
+}
+
+#[test]
+fn test_write_deadline_detects_stalled_reader() {
+    let (mut conn, client) = connection_pair();
+    conn.set_write_deadline(Some(Duration::from_millis(50)));
+    
+    // The client never reads, so the socket buffers fill and stay full
+    let data = vec![b'x'; 16 * 1024 * 1024];
+    assert_eq!(conn.write_all(&data).unwrap(), WriteStatus::WouldBlock);
+    assert!(!conn.is_write_stalled());
+    
+    thread::sleep(Duration::from_millis(100));
+    assert!(conn.is_write_stalled());
+    
+    drop(client);
+}
+
+#[test]
+fn test_write_deadline_ignores_finished_and_throttled_writes() {
+    let (mut conn, _client) = connection_pair();
+    conn.set_write_deadline(Some(Duration::from_millis(20)));
+    
+    assert_eq!(conn.write_all(b"done").unwrap(), WriteStatus::Complete);
+    thread::sleep(Duration::from_millis(40));
+    assert!(!conn.is_write_stalled());
+    
+    // Waiting on our own rate limit keeps resetting the deadline
+    conn.set_rate_limit(Some(1));
+    conn.write_all(&[b'y'; 64]).unwrap();
+    thread::sleep(Duration::from_millis(40));
+    assert_eq!(conn.flush().unwrap(), WriteStatus::Throttled);
+    assert!(!conn.is_write_stalled());
+}
+
+#[test]
+fn test_write_response_coalesces_head_and_body() {
+    let (mut conn, mut client) = connection_pair();
+    
+    let head = b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\n";
+    assert_eq!(conn.write_response(head, b"hello").unwrap(), WriteStatus::Complete);
+    assert!(!conn.has_pending_writes());
+    
+    let mut buf = vec![0u8; head.len() + 5];
+    client.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf[..head.len()], head);
+    assert_eq!(&buf[head.len()..], b"hello");
+}
+
+#[test]
+fn test_write_response_queues_the_rest_in_order() {
+    let (mut conn, mut client) = connection_pair();
+    
+    let head = b"HTTP/1.1 200 OK\r\n\r\n".to_vec();
+    let body: Vec<u8> = (0..16 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+    assert_eq!(conn.write_response(&head, &body).unwrap(), WriteStatus::WouldBlock);
+    
+    let expected_len = head.len() + body.len();
+    let reader = thread::spawn(move || {
+        let mut received = Vec::new();
+        client.read_to_end(&mut received).unwrap();
+        received
+    });
+    
+    while conn.flush().unwrap() != WriteStatus::Complete {
+        thread::sleep(Duration::from_millis(1));
+    }
+    conn.close().unwrap();
+    
+    let received = reader.join().unwrap();
+    assert_eq!(received.len(), expected_len);
+    assert_eq!(&received[..head.len()], &head[..]);
+    assert!(received[head.len()..] == body[..]);
+}
+
+#[test]
+fn test_send_response_serializes_into_the_write_buffer() {
+    let (mut conn, mut client) = connection_pair();
+    
+    let mut response = Response::new(Status::Ok);
+    response.set_body(b"hello");
+    response.set_header("X-Bad", "a\r\nInjected: yes");
+    let mut expected = Vec::new();
+    response.serialize(&mut expected).unwrap();
+    assert!(response.head_len_hint() >= expected.len() - response.body.len());
+    
+    // Once on its own, once behind a queued response and once queued itself
+    assert_eq!(conn.send_response(&response).unwrap(), WriteStatus::Complete);
+    conn.queue_response(&response).unwrap();
+    assert_eq!(conn.send_response(&response).unwrap(), WriteStatus::Complete);
+    assert!(!conn.has_pending_writes());
+    
+    let mut buf = vec![0u8; expected.len() * 3];
+    client.read_exact(&mut buf).unwrap();
+    for chunk in buf.chunks(expected.len()) {
+        assert_eq!(chunk, &expected[..]);
+    }
+}
+
+#[test]
+fn test_state_transitions() {
+    assert!(ConnectionState::New.can_transition_to(ConnectionState::Reading));
+    assert!(ConnectionState::Writing.can_transition_to(ConnectionState::HalfClosed));
+    assert!(!ConnectionState::New.can_transition_to(ConnectionState::Writing));
+    assert!(!ConnectionState::Closing.can_transition_to(ConnectionState::Reading));
+    assert!(!ConnectionState::Closed.can_transition_to(ConnectionState::Closing));
+    
+    let (mut conn, _client) = connection_pair();
+    assert!(conn.transition_to(ConnectionState::Reading));
+    assert!(conn.transition_to(ConnectionState::Processing));
+    
+    // Invalid transitions leave the state alone and are counted
+    assert!(!conn.transition_to(ConnectionState::Reading));
+    assert_eq!(conn.state(), ConnectionState::Processing);
+    assert_eq!(conn.invalid_transitions(), 1);
+}
+
+#[test]
+fn test_keep_alive_transition_waits_for_pending_writes() {
+    let (mut conn, mut client) = connection_pair();
+    conn.transition_to(ConnectionState::Reading);
+    conn.transition_to(ConnectionState::Processing);
+    
+    let data = vec![b'x'; 16 * 1024 * 1024];
+    assert_eq!(conn.write_all(&data).unwrap(), WriteStatus::WouldBlock);
+    assert_eq!(conn.state(), ConnectionState::Writing);
+    
+    // The next request can't start while the response is still queued
+    assert!(!conn.transition_to(ConnectionState::Reading));
+    assert_eq!(conn.state(), ConnectionState::Writing);
+    
+    let reader = thread::spawn(move || {
+        let mut received = vec![0u8; 16 * 1024 * 1024];
+        client.read_exact(&mut received).unwrap();
+    });
+    while conn.flush().unwrap() != WriteStatus::Complete {
+        thread::sleep(Duration::from_millis(1));
+    }
+    reader.join().unwrap();
+    
+    assert!(conn.transition_to(ConnectionState::Reading));
+    assert_eq!(conn.invalid_transitions(), 1);
+}
+
+#[test]
+fn test_connection_parses_buffered_request() {
+    let (mut conn, mut client) = connection_pair();
+    
+    client.write_all(b"GET /hello HTTP/1.1\r\nHost: local").unwrap();
+    thread::sleep(Duration::from_millis(20));
+    conn.read().unwrap();
+    conn.parse().unwrap();
+    assert!(!conn.parser().is_complete());
+    
+    client.write_all(b"host\r\n\r\n").unwrap();
+    thread::sleep(Duration::from_millis(20));
+    conn.read().unwrap();
+    conn.parse().unwrap();
+    assert!(conn.parser().is_complete());
+    assert_eq!(conn.parser().get_request().unwrap().path(), "/hello");
+    assert!(conn.timeline().has(Phase::HeadersParsed));
+}
\ No newline at end of file
@@ -0,0 +1,261 @@
+use high_performance_server::credentials::{
+    basic_auth_store_middleware, parse_basic_auth, AuthIdentity, CredentialStore, HtpasswdFile, LdapBind,
+    LockoutPolicy, LoginLockout, StaticCredentials,
+};
+use high_performance_server::http::{Method, Request, Response, Status};
+use high_performance_server::middleware::MiddlewareChain;
+use high_performance_server::password::{apr1_hash, bcrypt_cost, bcrypt_hash, dummy_hash_like, verify_password};
+use high_performance_server::{ConnectionAcceptor, EventLoop};
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn basic_request(username: &str, password: &str) -> Request {
+    let mut request = Request::new(Method::Get, "/private");
+    let encoded = base64::encode(format!("{}:{}", username, password));
+    request.set_header("Authorization", &format!("Basic {}", encoded));
+    request
+}
+
+#[test]
+fn test_apr1_matches_openssl() {
+    assert_eq!(apr1_hash("password", "abcdefgh"), "$apr1$abcdefgh$FBwExRW4dCc8aL.OvjpIE1");
+    assert_eq!(apr1_hash("", "xy"), "$apr1$xy$43..WIhbfuznGvwoCyUek/");
+    assert!(verify_password("password", "$apr1$abcdefgh$FBwExRW4dCc8aL.OvjpIE1"));
+    assert!(!verify_password("Password", "$apr1$abcdefgh$FBwExRW4dCc8aL.OvjpIE1"));
+}
+
+#[test]
+fn test_bcrypt_known_vectors() {
+    assert!(verify_password("U*U", "$2a$05$CCCCCCCCCCCCCCCCCCCCC.E5YPO9kmyuRGyh0XouQYb4YMJKvyOeW"));
+    assert!(verify_password("", "$2a$06$DCq7YPn5Rq63x1Lad4cll.TV4S6ytwfsfvkgY8jIucDrjc8deX1s."));
+    assert!(!verify_password("U*V", "$2a$05$CCCCCCCCCCCCCCCCCCCCC.E5YPO9kmyuRGyh0XouQYb4YMJKvyOeW"));
+    
+    let hash = bcrypt_hash("hunter2", 4, &[7; 16]).unwrap();
+    assert!(verify_password("hunter2", &hash));
+    assert!(!verify_password("hunter3", &hash));
+}
+
+#[test]
+fn test_htpasswd_file() {
+    let content = format!(
+        "# users\nalice:{}\n\nbob:{}\n",
+        apr1_hash("wonderland", "saltsalt"),
+        bcrypt_hash("builder", 4, &[1; 16]).unwrap()
+    );
+    let store = HtpasswdFile::parse(&content).unwrap();
+    assert_eq!(store.len(), 2);
+    
+    assert!(store.verify("alice", "wonderland").unwrap());
+    assert!(store.verify("bob", "builder").unwrap());
+    assert!(!store.verify("alice", "builder").unwrap());
+    assert!(!store.verify("carol", "wonderland").unwrap());
+    
+    // Unknown users are checked against a hash as slow as the slowest entry's
+    let slow = bcrypt_hash("builder", 6, &[2; 16]).unwrap();
+    let dummy = dummy_hash_like(&slow).unwrap();
+    assert_eq!(bcrypt_cost(&dummy), Some(6));
+    assert!(!verify_password("builder", &dummy));
+    assert!(dummy_hash_like(&apr1_hash("wonderland", "saltsalt")).unwrap().starts_with("$apr1$"));
+    assert_eq!(bcrypt_cost(&apr1_hash("wonderland", "saltsalt")), None);
+    
+    // Formats we can't check are rejected when loading
+    assert!(HtpasswdFile::parse("dave:{SHA}W6ph5Mm5Pz8GgiULbPgzG37mj9g=").is_err());
+    assert!(HtpasswdFile::parse("no-separator").is_err());
+}
+
+#[test]
+fn test_parse_basic_auth() {
+    let request = basic_request("user", "pa:ss");
+    assert_eq!(parse_basic_auth(&request), Some(("user".to_string(), "pa:ss".to_string())));
+    
+    let mut bearer = Request::new(Method::Get, "/");
+    bearer.set_header("Authorization", "Bearer abc");
+    assert_eq!(parse_basic_auth(&bearer), None);
+}
+
+#[test]
+fn test_lockout_after_failures() {
+    let lockout = LoginLockout::new(LockoutPolicy {
+        max_failures: 3,
+        window: Duration::from_secs(60),
+        lockout: Duration::from_secs(60),
+    });
+    
+    assert!(!lockout.record_failure("alice"));
+    assert!(!lockout.record_failure("alice"));
+    assert_eq!(lockout.failures("alice"), 2);
+    assert!(lockout.locked_for("alice").is_none());
+    
+    lockout.record_success("alice");
+    assert_eq!(lockout.failures("alice"), 0);
+    
+    for _ in 0..2 {
+        lockout.record_failure("alice");
+    }
+    assert!(lockout.record_failure("alice"));
+    assert!(lockout.locked_for("alice").is_some());
+    assert!(lockout.locked_for("bob").is_none());
+}
+
+#[test]
+fn test_store_middleware_locks_out() {
+    let store: Arc<dyn CredentialStore> = Arc::new(StaticCredentials::new("admin", "s3cret"));
+    let policy = LockoutPolicy {
+        max_failures: 2,
+        window: Duration::from_secs(60),
+        lockout: Duration::from_secs(30),
+    };
+    
+    let mut chain = MiddlewareChain::new();
+    chain.add(basic_auth_store_middleware(store, "Admin".to_string(), Some(policy)));
+    chain.set_handler(|_| Ok(Response::new(Status::Ok)));
+    
+    let missing = chain.handle(&Request::new(Method::Get, "/private")).unwrap();
+    assert_eq!(missing.status, Status::Unauthorized);
+    assert_eq!(missing.headers.get("WWW-Authenticate").unwrap(), "Basic realm=\"Admin\"");
+    
+    assert_eq!(chain.handle(&basic_request("admin", "s3cret")).unwrap().status, Status::Ok);
+    assert_eq!(chain.handle(&basic_request("admin", "wrong")).unwrap().status, Status::Unauthorized);
+    assert_eq!(chain.handle(&basic_request("admin", "wrong")).unwrap().status, Status::Unauthorized);
+    
+    // Even the right password is refused while locked out
+    let locked = chain.handle(&basic_request("admin", "s3cret")).unwrap();
+    assert_eq!(locked.status, Status::TooManyRequests);
+    assert!(locked.headers.contains_key("Retry-After"));
+}
+
+/// Answer one simple bind, succeeding only for the expected DN and password
+fn mock_ldap_server(expected_dn: &'static str, expected_password: &'static str) -> String {
+    slow_ldap_server(expected_dn, expected_password, Duration::ZERO)
+}
+
+/// Answer one simple bind after `delay`
+fn slow_ldap_server(expected_dn: &'static str, expected_password: &'static str, delay: Duration) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    
+    thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        thread::sleep(delay);
+        let mut request = vec![0u8; 512];
+        let len = stream.read(&mut request).unwrap();
+        let request = &request[..len];
+        
+        let contains = |needle: &[u8]| request.windows(needle.len()).any(|w| w == needle);
+        let ok = contains(expected_dn.as_bytes()) && request.ends_with(expected_password.as_bytes());
+        let code = if ok { 0 } else { 49 };
+        
+        // BindResponse: messageID 1, resultCode, empty matchedDN and diagnosticMessage
+        let response = [0x30, 0x0c, 0x02, 0x01, 0x01, 0x61, 0x07, 0x0a, 0x01, code, 0x04, 0x00, 0x04, 0x00];
+        stream.write_all(&response).unwrap();
+    });
+    
+    address
+}
+
+#[test]
+fn test_ldap_bind() {
+    let template = "uid={username},ou=people,dc=example,dc=com";
+    
+    let address = mock_ldap_server("uid=alice,ou=people,dc=example,dc=com", "wonderland");
+    let store = LdapBind::new(&address, template).with_timeout(Duration::from_secs(2));
+    assert!(store.verify("alice", "wonderland").unwrap());
+    
+    let address = mock_ldap_server("uid=alice,ou=people,dc=example,dc=com", "wonderland");
+    let store = LdapBind::new(&address, template);
+    assert!(!store.verify("alice", "guess").unwrap());
+    
+    // Empty passwords never reach the server, since they'd be anonymous binds
+    assert!(!store.verify("alice", "").unwrap());
+}
+
+#[test]
+fn test_ldap_successful_binds_are_cached() {
+    let template = "uid={username},ou=people,dc=example,dc=com";
+    
+    // The mock server answers once, so later binds that reach it fail
+    let address = mock_ldap_server("uid=alice,ou=people,dc=example,dc=com", "wonderland");
+    let store = LdapBind::new(&address, template);
+    assert!(store.verify("alice", "wonderland").unwrap());
+    assert!(store.verify("alice", "wonderland").unwrap());
+    assert!(store.verify("alice", "guess").is_err());
+    
+    let address = mock_ldap_server("uid=alice,ou=people,dc=example,dc=com", "wonderland");
+    let store = LdapBind::new(&address, template).with_cache_ttl(Duration::ZERO);
+    assert!(store.verify("alice", "wonderland").unwrap());
+    assert!(store.verify("alice", "wonderland").is_err());
+}
+
+#[test]
+fn test_ldap_dn_escaping() {
+    let store = LdapBind::new("127.0.0.1:389", "uid={username},dc=example,dc=com");
+    assert_eq!(store.bind_dn("a,b=c"), "uid=a\\,b\\=c,dc=example,dc=com");
+    assert_eq!(store.bind_dn(" x "), "uid=\\ x\\ ,dc=example,dc=com");
+}
+
+/// Send a GET for `/private` and read the whole response, which ends with the connection
+fn fetch(addr: SocketAddr, auth: &str) -> String {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    write!(stream, "GET /private HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n{}\r\n", auth).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+#[test]
+fn test_ldap_binds_happen_off_the_event_loop() {
+    let address = slow_ldap_server(
+        "uid=alice,ou=people,dc=example,dc=com",
+        "wonderland",
+        Duration::from_millis(500),
+    );
+    let store = LdapBind::new(&address, "uid={username},ou=people,dc=example,dc=com");
+    let mut chain = MiddlewareChain::new();
+    chain.add(basic_auth_store_middleware(Arc::new(store), "Server".to_string(), None));
+    chain.set_handler(|req| {
+        let mut response = Response::new(Status::Ok);
+        response.set_body(AuthIdentity::of(req).unwrap().username.as_bytes());
+        Ok(response)
+    });
+    
+    let acceptor = Arc::new(ConnectionAcceptor::new("127.0.0.1:0").unwrap());
+    let addr = acceptor.local_addr().unwrap();
+    let drain = Arc::new(AtomicBool::new(false));
+    let server = {
+        let drain = drain.clone();
+        thread::spawn(move || {
+            let mut event_loop = EventLoop::new(0, acceptor);
+            event_loop.set_middleware_chain(Arc::new(chain));
+            event_loop.set_drain_signal(drain);
+            event_loop.run().unwrap();
+        })
+    };
+    
+    let alice = format!("Authorization: Basic {}\r\n", base64::encode("alice:wonderland"));
+    let binding = {
+        let alice = alice.clone();
+        thread::spawn(move || fetch(addr, &alice))
+    };
+    thread::sleep(Duration::from_millis(100));
+    
+    // Other clients are answered while the bind waits on the LDAP server
+    let started = Instant::now();
+    assert!(fetch(addr, "").starts_with("HTTP/1.1 401"));
+    assert!(started.elapsed() < Duration::from_millis(300), "waited {:?}", started.elapsed());
+    
+    let response = binding.join().unwrap();
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+    assert!(response.ends_with("\r\n\r\nalice"), "{}", response);
+    
+    // The mock server is gone, so only the cache can let this one in
+    let response = fetch(addr, &alice);
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+    
+    drain.store(true, Ordering::SeqCst);
+    server.join().unwrap();
+}
\ No newline at end of file
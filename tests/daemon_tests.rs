@@ -0,0 +1,26 @@
+#![cfg(unix)]
+
+use high_performance_server::daemon::PidFile;
+use std::env;
+use std::fs;
+
+#[test]
+fn test_pid_file_lifecycle() {
+    let path = env::temp_dir().join(format!("hps-test-{}.pid", std::process::id()));
+    let _ = fs::remove_file(&path);
+    
+    let pid_file = PidFile::acquire(&path).unwrap();
+    let contents = fs::read_to_string(&path).unwrap();
+    assert_eq!(contents.trim(), std::process::id().to_string());
+    
+    // A second instance is refused while the lock is held
+    assert!(PidFile::check(&path).is_err());
+    assert!(PidFile::acquire(&path).is_err());
+    
+    // Dropping the pid file releases the lock and removes the file
+    drop(pid_file);
+    assert!(!path.exists());
+    assert!(PidFile::check(&path).is_ok());
+    
+    let _ = fs::remove_file(&path);
+}
\ No newline at end of file
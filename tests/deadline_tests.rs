@@ -0,0 +1,126 @@
+use high_performance_server::http_client::{send_idempotent, HttpUrl, RetryPolicy};
+use high_performance_server::{
+    deadline_middleware, Deadline, DeadlineConfig, ErrorKind, Method, MiddlewareChain, Request, Response, Status,
+};
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A chain whose handler reports the milliseconds left on its deadline
+fn chain(config: DeadlineConfig) -> MiddlewareChain {
+    let mut chain = MiddlewareChain::new();
+    chain.add(deadline_middleware(config));
+    chain.set_handler(|req| {
+        let mut response = Response::new(Status::Ok);
+        let remaining = Deadline::of(req).map_or("none".to_string(), |d| d.remaining().as_millis().to_string());
+        response.set_body(remaining.as_bytes());
+        Ok(response)
+    });
+    chain
+}
+
+fn remaining_ms(response: &Response) -> u64 {
+    String::from_utf8_lossy(&response.body).parse().unwrap()
+}
+
+/// Answer every request after `delay` with the request head as the body
+fn echo_upstream(delay: Duration) -> HttpUrl {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = HttpUrl::parse(&format!("http://{}/item", listener.local_addr().unwrap())).unwrap();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            thread::spawn(move || {
+                let mut request = Vec::new();
+                let mut byte = [0u8; 1];
+                while !request.ends_with(b"\r\n\r\n") && stream.read(&mut byte).unwrap_or(0) == 1 {
+                    request.push(byte[0]);
+                }
+                thread::sleep(delay);
+                let _ = write!(stream, "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", request.len());
+                let _ = stream.write_all(&request);
+            });
+        }
+    });
+    url
+}
+
+#[test]
+fn test_middleware_attaches_deadline() {
+    let chain = chain(DeadlineConfig {
+        timeout: Duration::from_secs(2),
+        ..DeadlineConfig::default()
+    });
+    
+    let remaining = remaining_ms(&chain.handle(&Request::new(Method::Get, "/")).unwrap());
+    assert!(remaining > 1900 && remaining <= 2000);
+}
+
+#[test]
+fn test_incoming_header_shortens_budget() {
+    let chain = chain(DeadlineConfig::default());
+    
+    let mut request = Request::new(Method::Get, "/");
+    request.set_header("X-Request-Deadline", "500");
+    assert!(remaining_ms(&chain.handle(&request).unwrap()) <= 500);
+    
+    // A larger budget than ours is capped
+    request.set_header("X-Request-Deadline", "999999999");
+    assert!(remaining_ms(&chain.handle(&request).unwrap()) <= 30_000);
+    
+    // Junk is ignored
+    request.set_header("X-Request-Deadline", "soon");
+    assert!(remaining_ms(&chain.handle(&request).unwrap()) > 29_000);
+}
+
+#[test]
+fn test_spent_budget_gets_gateway_timeout() {
+    let chain = chain(DeadlineConfig::default());
+    
+    let mut request = Request::new(Method::Get, "/");
+    request.set_header("X-Request-Deadline", "0");
+    let response = chain.handle(&request).unwrap();
+    assert_eq!(response.status, Status::GatewayTimeout);
+    assert!(String::from_utf8_lossy(&response.body).contains("\"code\":\"timeout\""));
+}
+
+#[test]
+fn test_incoming_header_can_be_ignored() {
+    let chain = chain(DeadlineConfig {
+        honor_incoming: false,
+        ..DeadlineConfig::default()
+    });
+    
+    let mut request = Request::new(Method::Get, "/");
+    request.set_header("X-Request-Deadline", "0");
+    assert!(remaining_ms(&chain.handle(&request).unwrap()) > 29_000);
+}
+
+#[test]
+fn test_deadline_is_passed_upstream() {
+    let upstream = echo_upstream(Duration::ZERO);
+    let deadline = Deadline::after(Duration::from_secs(3)).with_header(Some("X-Budget-Ms"));
+    
+    let response = send_idempotent("GET", &[upstream], &RetryPolicy::default().within(&deadline)).unwrap();
+    let head = String::from_utf8(response.body).unwrap();
+    let budget: u64 = head
+        .lines()
+        .find_map(|line| line.strip_prefix("X-Budget-Ms: "))
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(budget > 2500 && budget <= 3000);
+}
+
+#[test]
+fn test_deadline_bounds_upstream_wait() {
+    let upstream = echo_upstream(Duration::from_secs(2));
+    let deadline = Deadline::after(Duration::from_millis(300));
+    
+    let start = Instant::now();
+    let error = send_idempotent("GET", &[upstream], &RetryPolicy::default().within(&deadline)).unwrap_err();
+    assert!(start.elapsed() < Duration::from_secs(1));
+    assert_eq!(error.kind(), ErrorKind::Timeout);
+    assert_eq!(error.status(), Status::GatewayTimeout);
+}
\ No newline at end of file
@@ -0,0 +1,104 @@
+use high_performance_server::digest::{
+    content_md5_value, digest_middleware, repr_digest_value, verify_request_digest, DigestConfig,
+};
+use high_performance_server::hash::{hmac_sha256, to_hex, Md5, Sha256};
+use high_performance_server::http::{Method, Request, Response, Status};
+use high_performance_server::middleware::MiddlewareChain;
+
+#[test]
+fn test_sha256_vectors() {
+    assert_eq!(
+        to_hex(&Sha256::digest(b"")),
+        "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+    );
+    assert_eq!(
+        to_hex(&Sha256::digest(b"abc")),
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+    assert_eq!(
+        to_hex(&Sha256::digest(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq")),
+        "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+    );
+    
+    // Feeding data in pieces gives the same result as one call
+    let data = vec![b'a'; 1000];
+    let mut hasher = Sha256::new();
+    for chunk in data.chunks(7) {
+        hasher.update(chunk);
+    }
+    assert_eq!(hasher.finalize(), Sha256::digest(&data));
+}
+
+#[test]
+fn test_md5_vectors() {
+    assert_eq!(to_hex(&Md5::digest(b"")), "d41d8cd98f00b204e9800998ecf8427e");
+    assert_eq!(
+        to_hex(&Md5::digest(b"The quick brown fox jumps over the lazy dog")),
+        "9e107d9d372bb6826bd81d3542a419d6"
+    );
+    assert_eq!(
+        to_hex(&Md5::digest(
+            b"12345678901234567890123456789012345678901234567890123456789012345678901234567890"
+        )),
+        "57edf4a22be3c955ac49da2e2107b67a"
+    );
+}
+
+#[test]
+fn test_hmac_sha256_vectors() {
+    // RFC 4231 test cases 1 and 2
+    assert_eq!(
+        to_hex(&hmac_sha256(&[0x0b; 20], b"Hi There")),
+        "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7"
+    );
+    assert_eq!(
+        to_hex(&hmac_sha256(b"Jefe", b"what do ya want for nothing?")),
+        "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+    );
+}
+
+#[test]
+fn test_digest_header_values() {
+    assert_eq!(
+        repr_digest_value(b"hello"),
+        "sha-256=:LPJNul+wow4m6DsqxbninhsWHlwfp0JecwQzYpOLmCQ=:"
+    );
+    assert_eq!(content_md5_value(b"hello"), "XUFAKrxLKna5cZ2REBfFkg==");
+}
+
+#[test]
+fn test_verify_request_digest() {
+    let mut request = Request::new(Method::Put, "/artifacts/1");
+    request.set_body(b"hello");
+    assert!(verify_request_digest(&request, false).is_ok());
+    assert!(verify_request_digest(&request, true).is_err());
+    
+    request.set_header("Repr-Digest", &format!("sha-512=:AAAA:, {}", repr_digest_value(b"hello")));
+    assert!(verify_request_digest(&request, true).is_ok());
+    
+    request.set_header("Content-MD5", &content_md5_value(b"tampered"));
+    assert!(verify_request_digest(&request, false).is_err());
+}
+
+#[test]
+fn test_digest_middleware() {
+    let mut chain = MiddlewareChain::new();
+    chain.add(digest_middleware(DigestConfig::new().with_content_md5(true)));
+    chain.set_handler(|_| {
+        let mut response = Response::new(Status::Ok);
+        response.set_body(b"hello");
+        Ok(response)
+    });
+    
+    let mut request = Request::new(Method::Post, "/upload");
+    request.set_body(b"payload");
+    request.set_header("Repr-Digest", &repr_digest_value(b"other payload"));
+    let response = chain.handle(&request).unwrap();
+    assert_eq!(response.status, Status::BadRequest);
+    
+    request.set_header("Repr-Digest", &repr_digest_value(b"payload"));
+    let response = chain.handle(&request).unwrap();
+    assert_eq!(response.status, Status::Ok);
+    assert_eq!(response.headers.get("Repr-Digest").unwrap(), &repr_digest_value(b"hello"));
+    assert_eq!(response.headers.get("Content-MD5").unwrap(), "XUFAKrxLKna5cZ2REBfFkg==");
+}
\ No newline at end of file
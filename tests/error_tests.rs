@@ -0,0 +1,67 @@
+use high_performance_server::error::{ErrorKind, ErrorResponse, ServerError};
+use high_performance_server::middleware::error_middleware;
+use high_performance_server::{Method, MiddlewareChain, Request, Status};
+use std::io;
+
+#[test]
+fn test_error_kinds_map_to_statuses() {
+    let buffer = ServerError::Buffer("full".to_string());
+    let protocol = ServerError::Protocol("bad reply".to_string());
+    assert_eq!(buffer.kind(), ErrorKind::TooLarge);
+    assert_eq!(buffer.status(), Status::PayloadTooLarge);
+    assert_eq!(protocol.kind(), ErrorKind::Upstream);
+    assert_eq!(protocol.status(), Status::BadGateway);
+    
+    assert_eq!(ServerError::HttpParse("junk".to_string()).status(), Status::BadRequest);
+    assert_eq!(ServerError::Memory("exhausted".to_string()).status(), Status::ServiceUnavailable);
+    assert_eq!(ServerError::Config("missing".to_string()).status(), Status::InternalServerError);
+    
+    // I/O errors are mapped by what went wrong
+    let missing = ServerError::Io(io::Error::new(io::ErrorKind::NotFound, "gone"));
+    let denied = ServerError::Io(io::Error::new(io::ErrorKind::PermissionDenied, "nope"));
+    let broken = ServerError::Io(io::Error::new(io::ErrorKind::BrokenPipe, "pipe"));
+    let slow = ServerError::Io(io::Error::new(io::ErrorKind::TimedOut, "slow"));
+    assert_eq!(missing.kind(), ErrorKind::NotFound);
+    assert_eq!(denied.kind(), ErrorKind::Forbidden);
+    assert_eq!(broken.kind(), ErrorKind::Internal);
+    assert_eq!(slow.status(), Status::GatewayTimeout);
+}
+
+#[test]
+fn test_error_response_body() {
+    let mut request = Request::new(Method::Get, "/items");
+    request.set_header("X-Request-Id", "req-7");
+    
+    let response = ServerError::HttpParse("Invalid UTF-8".to_string()).to_response(&request);
+    assert_eq!(response.status, Status::BadRequest);
+    assert_eq!(response.headers.get("Content-Type").unwrap(), "application/json");
+    assert_eq!(response.headers.get("X-Request-Id").unwrap(), "req-7");
+    
+    let body: ErrorResponse = serde_json::from_slice(&response.body).unwrap();
+    assert_eq!(body, ErrorResponse::new("bad_request", "HTTP parsing error: Invalid UTF-8").with_request_id("req-7"));
+    
+    // Server-side failures don't leak their details
+    let response = ServerError::Config("/etc/secret.toml is unreadable".to_string()).to_response(&request);
+    let body: ErrorResponse = serde_json::from_slice(&response.body).unwrap();
+    assert_eq!(body.code, "internal_error");
+    assert_eq!(body.message, "Internal Server Error");
+    
+    // The request ID is null when the client sent none
+    let response = ServerError::Buffer("full".to_string()).to_response(&Request::new(Method::Get, "/"));
+    let body: serde_json::Value = serde_json::from_slice(&response.body).unwrap();
+    assert_eq!(body["code"], "payload_too_large");
+    assert!(body["request_id"].is_null());
+    assert!(!response.headers.contains_key("X-Request-Id"));
+}
+
+#[test]
+fn test_error_middleware_converts_errors() {
+    let mut chain = MiddlewareChain::new();
+    chain.add(error_middleware);
+    chain.set_handler(|_| Err(ServerError::Protocol("upstream hung up".to_string())));
+    
+    let response = chain.handle(&Request::new(Method::Get, "/proxy")).unwrap();
+    assert_eq!(response.status, Status::BadGateway);
+    let body: ErrorResponse = serde_json::from_slice(&response.body).unwrap();
+    assert_eq!(body.code, "upstream_error");
+}
\ No newline at end of file
@@ -0,0 +1,110 @@
+use high_performance_server::http_client::HttpUrl;
+use high_performance_server::{EventBus, ServerEvent};
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn test_event_bus_subscribers() {
+    let bus = EventBus::new();
+    let received = Arc::new(Mutex::new(Vec::new()));
+    
+    let received_clone = received.clone();
+    bus.subscribe(move |event| received_clone.lock().unwrap().push(event.clone()));
+    assert_eq!(bus.subscriber_count(), 1);
+    
+    bus.emit(ServerEvent::ConfigReloaded { path: Some("server.json".to_string()) });
+    bus.emit(ServerEvent::WorkerPanicked { worker: 3, message: "boom".to_string() });
+    
+    let received = received.lock().unwrap();
+    assert_eq!(received.len(), 2);
+    assert_eq!(received[1], ServerEvent::WorkerPanicked { worker: 3, message: "boom".to_string() });
+}
+
+#[test]
+fn test_webhook_delivery() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/hooks/server", listener.local_addr().unwrap());
+    
+    let bus = EventBus::new();
+    bus.subscribe_webhook(&url).unwrap();
+    bus.emit(ServerEvent::UpstreamUnhealthy {
+        upstream: "10.0.0.5:8080".to_string(),
+        reason: "connection refused".to_string(),
+    });
+    
+    let (mut stream, _) = listener.accept().unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+    
+    let mut request = Vec::new();
+    let mut buf = [0u8; 4096];
+    while !request.ends_with(b"}") {
+        let n = stream.read(&mut buf).unwrap();
+        if n == 0 {
+            break;
+        }
+        request.extend_from_slice(&buf[..n]);
+    }
+    stream.write_all(b"HTTP/1.1 204 No Content\r\n\r\n").unwrap();
+    
+    let request = String::from_utf8(request).unwrap();
+    assert!(request.starts_with("POST /hooks/server HTTP/1.1\r\n"));
+    assert!(request.contains("Content-Type: application/json"));
+    assert!(request.contains("\"event\":\"upstream_unhealthy\""));
+    assert!(request.contains("\"upstream\":\"10.0.0.5:8080\""));
+    assert!(request.contains("\"timestamp\":"));
+}
+
+/// Emits an event when dropped, as a panic unwinds past it
+struct EmitOnDrop(Arc<EventBus>);
+
+impl Drop for EmitOnDrop {
+    fn drop(&mut self) {
+        self.0.emit(ServerEvent::WorkerPanicked { worker: 1, message: "boom".to_string() });
+    }
+}
+
+#[test]
+fn test_webhook_delivers_before_a_panic_ends() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let bus = Arc::new(EventBus::new());
+    bus.subscribe_webhook(&format!("http://{}/hook", listener.local_addr().unwrap())).unwrap();
+    
+    let panicking = thread::spawn(move || {
+        let _guard = EmitOnDrop(bus);
+        panic!("boom");
+    });
+    
+    // The panicking thread waits for the webhook's answer, since the process may abort after it
+    let (mut stream, _) = listener.accept().unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+    let mut request = Vec::new();
+    let mut buf = [0u8; 4096];
+    while !request.ends_with(b"}") {
+        let n = stream.read(&mut buf).unwrap();
+        assert!(n > 0);
+        request.extend_from_slice(&buf[..n]);
+    }
+    assert!(String::from_utf8(request).unwrap().contains("\"event\":\"worker_panicked\""));
+    thread::sleep(Duration::from_millis(50));
+    assert!(!panicking.is_finished());
+    
+    stream.write_all(b"HTTP/1.1 204 No Content\r\n\r\n").unwrap();
+    assert!(panicking.join().is_err());
+}
+
+#[test]
+fn test_http_url_parse() {
+    let url = HttpUrl::parse("http://example.com/hook?x=1").unwrap();
+    assert_eq!(url.address, "example.com:80");
+    assert_eq!(url.host, "example.com");
+    assert_eq!(url.path, "/hook?x=1");
+    
+    let url = HttpUrl::parse("http://[::1]:9091").unwrap();
+    assert_eq!(url.address, "[::1]:9091");
+    assert_eq!(url.path, "/");
+    
+    assert!(HttpUrl::parse("https://example.com/").is_err());
+}
\ No newline at end of file
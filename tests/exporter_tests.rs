@@ -0,0 +1,77 @@
+use high_performance_server::metrics::MetricsRegistry;
+use high_performance_server::{MetricsExportConfig, MetricsExporter};
+use std::io::{Read, Write};
+use std::net::{TcpListener, UdpSocket};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn test_statsd_counter_deltas_and_tags() {
+    let registry = Arc::new(MetricsRegistry::new());
+    registry.counter("requests").increment(5);
+    registry.gauge("connections").set(3);
+    
+    let config = MetricsExportConfig::statsd("127.0.0.1:8125").with_prefix("web");
+    let mut exporter = MetricsExporter::new(registry.clone(), config);
+    let tags = vec![("env".to_string(), "prod".to_string())];
+    
+    let lines = exporter.statsd_lines(&tags);
+    assert!(lines.contains(&"web.requests:5|c|#env:prod".to_string()));
+    assert!(lines.contains(&"web.connections:3|g|#env:prod".to_string()));
+    
+    registry.counter("requests").increment(2);
+    let lines = exporter.statsd_lines(&[]);
+    assert!(lines.contains(&"web.requests:2|c".to_string()));
+}
+
+#[test]
+fn test_statsd_flush_over_udp() {
+    let agent = UdpSocket::bind("127.0.0.1:0").unwrap();
+    agent.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+    let address = agent.local_addr().unwrap().to_string();
+    
+    let registry = Arc::new(MetricsRegistry::new());
+    registry.counter("hits").increment(1);
+    
+    let mut exporter = MetricsExporter::new(registry, MetricsExportConfig::statsd(&address));
+    exporter.flush().unwrap();
+    
+    let mut buf = [0u8; 1500];
+    let n = agent.recv(&mut buf).unwrap();
+    assert_eq!(&buf[..n], b"hits:1|c");
+}
+
+#[test]
+fn test_pushgateway_flush() {
+    let gateway = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = gateway.local_addr().unwrap().to_string();
+    
+    let server = thread::spawn(move || {
+        let (mut stream, _) = gateway.accept().unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        
+        let mut request = Vec::new();
+        let mut buf = [0u8; 4096];
+        while !String::from_utf8_lossy(&request).contains("requests_total 4") {
+            let n = stream.read(&mut buf).unwrap();
+            if n == 0 {
+                break;
+            }
+            request.extend_from_slice(&buf[..n]);
+        }
+        
+        stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n").unwrap();
+        String::from_utf8(request).unwrap()
+    });
+    
+    let registry = Arc::new(MetricsRegistry::new());
+    registry.counter("requests.total").increment(4);
+    
+    let mut exporter = MetricsExporter::new(registry, MetricsExportConfig::pushgateway(&address, "server"));
+    exporter.flush().unwrap();
+    
+    let request = server.join().unwrap();
+    assert!(request.starts_with("PUT /metrics/job/server HTTP/1.1\r\n"));
+    assert!(request.contains("# TYPE requests_total counter\nrequests_total 4\n"));
+}
\ No newline at end of file
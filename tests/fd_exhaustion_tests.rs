@@ -0,0 +1,123 @@
+//! Running out of file descriptors changes the whole process, so this
+//! binary holds a single test.
+#![cfg(unix)]
+
+use high_performance_server::acceptor::is_fd_exhaustion;
+use high_performance_server::{ConnectionAcceptor, EventLoop, MetricsCollector, Response, Router, Status};
+use std::fs::File;
+use std::io::{ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Open descriptors until the process runs out
+fn exhaust_fds() -> Vec<File> {
+    let mut files = Vec::new();
+    loop {
+        match File::open("/dev/null") {
+            Ok(file) => files.push(file),
+            Err(e) => {
+                assert!(is_fd_exhaustion(&e), "{}", e);
+                return files;
+            }
+        }
+    }
+}
+
+/// Check that the server closed or reset a connection without answering it
+fn assert_shed(client: &mut TcpStream) {
+    match client.read(&mut [0u8; 1]) {
+        Ok(0) => {}
+        Err(e) if e.kind() == ErrorKind::ConnectionReset => {}
+        other => panic!("connection wasn't shed: {:?}", other),
+    }
+}
+
+fn get(client: &mut TcpStream) -> Vec<u8> {
+    client.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+    let mut response = Vec::new();
+    let mut byte = [0u8; 1];
+    while !response.ends_with(b"\r\n\r\nok") {
+        client.read_exact(&mut byte).unwrap();
+        response.push(byte[0]);
+    }
+    response
+}
+
+#[test]
+fn test_fd_exhaustion_sheds_and_recovers() {
+    // Keep the limit low so exhausting it is quick
+    let mut limit = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+    assert_eq!(unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) }, 0);
+    limit.rlim_cur = limit.rlim_cur.min(256);
+    assert_eq!(unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &limit) }, 0);
+    
+    // The acceptor sheds the waiting connection, and accepts again once descriptors are freed
+    let acceptor = ConnectionAcceptor::new("127.0.0.1:0").unwrap();
+    let addr = acceptor.local_addr().unwrap();
+    let mut first = TcpStream::connect(addr).unwrap();
+    let mut second = TcpStream::connect(addr).unwrap();
+    first.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    thread::sleep(Duration::from_millis(50));
+    
+    let files = exhaust_fds();
+    let error = acceptor.accept().err().expect("accept should fail without descriptors");
+    assert!(is_fd_exhaustion(&error), "{}", error);
+    assert_eq!(acceptor.shed_connections(), 1);
+    assert_shed(&mut first);
+    drop(files);
+    assert_eq!(acceptor.accept().unwrap().peer_addr(), second.local_addr().unwrap());
+    second.write_all(b"x").unwrap();
+    drop(acceptor);
+    
+    // The event loop survives running out, pauses accepting, and serves again afterwards
+    let mut router = Router::new();
+    router.get("/", |_| {
+        let mut response = Response::new(Status::Ok);
+        response.set_body(b"ok");
+        Ok(response)
+    });
+    let acceptor = Arc::new(ConnectionAcceptor::new("127.0.0.1:0").unwrap());
+    let addr = acceptor.local_addr().unwrap();
+    let drain = Arc::new(AtomicBool::new(false));
+    let metrics = Arc::new(MetricsCollector::new());
+    let server = {
+        let (drain, metrics) = (drain.clone(), metrics.clone());
+        thread::spawn(move || {
+            let mut event_loop = EventLoop::new(0, acceptor);
+            event_loop.set_router(Arc::new(router));
+            event_loop.set_drain_signal(drain);
+            event_loop.set_metrics(metrics);
+            event_loop.run().unwrap();
+        })
+    };
+    let mut served = TcpStream::connect(addr).unwrap();
+    served.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    get(&mut served);
+    
+    // Leave room for one client socket, which the server then can't accept
+    let mut files = exhaust_fds();
+    files.pop();
+    let mut refused = TcpStream::connect(addr).unwrap();
+    refused.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    assert_shed(&mut refused);
+    // The client can see the shed before the worker counts it
+    let counted = Instant::now() + Duration::from_secs(5);
+    while metrics.registry().counter("connections.fd_exhausted").value() == 0 {
+        assert!(Instant::now() < counted, "running out of descriptors wasn't counted");
+        thread::sleep(Duration::from_millis(10));
+    }
+    drop(files);
+    
+    // Connections already open were unaffected, and new ones are accepted after the pause
+    get(&mut served);
+    let mut later = TcpStream::connect(addr).unwrap();
+    later.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    get(&mut later);
+    
+    drop((served, later));
+    drain.store(true, Ordering::SeqCst);
+    server.join().unwrap();
+}
\ No newline at end of file
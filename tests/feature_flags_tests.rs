@@ -0,0 +1,156 @@
+use high_performance_server::{
+    feature_flags_middleware, flag_enabled, mount_feature_flags, EventBus, FeatureFlags, Flag, FlagSet, KvStore,
+    MemoryKvStore, Method, MiddlewareChain, Request, Response, Router, ServerEvent, Status,
+};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+fn request_from(ip: &str) -> Request {
+    let mut request = Request::new(Method::Get, "/checkout");
+    request.peer_addr = Some(format!("{}:40000", ip).parse().unwrap());
+    request
+}
+
+fn flag_set(flags: &[(&str, Flag)]) -> FlagSet {
+    FlagSet {
+        cookie: Some("uid".to_string()),
+        flags: flags.iter().map(|(name, flag)| (name.to_string(), flag.clone())).collect::<BTreeMap<_, _>>(),
+    }
+}
+
+#[test]
+fn test_percentage_rollout_is_stable() {
+    let flags = FeatureFlags::new(flag_set(&[
+        ("everyone", Flag::default()),
+        ("nobody", Flag::disabled()),
+        ("quarter", Flag::rollout(25.0)),
+    ]));
+    
+    let clients: Vec<Request> = (0..1000).map(|i| request_from(&format!("10.0.{}.{}", i / 250, i % 250))).collect();
+    let enabled = clients.iter().filter(|req| flags.enabled("quarter", req)).count();
+    assert!((150..350).contains(&enabled), "{} of 1000 clients enabled", enabled);
+    
+    // The same client always gets the same answer
+    for req in &clients[..50] {
+        assert_eq!(flags.enabled("quarter", req), flags.enabled("quarter", req));
+        assert!(flags.enabled("everyone", req));
+        assert!(!flags.enabled("nobody", req));
+        assert!(!flags.enabled("unknown", req));
+    }
+    
+    // Raising the percentage only adds clients
+    let before: Vec<bool> = clients.iter().map(|req| flags.enabled("quarter", req)).collect();
+    flags.set("quarter", Flag::rollout(60.0)).unwrap();
+    for (req, was_enabled) in clients.iter().zip(before) {
+        assert!(!was_enabled || flags.enabled("quarter", req));
+    }
+}
+
+#[test]
+fn test_rollout_prefers_cookie_over_ip() {
+    let flags = FeatureFlags::new(flag_set(&[("half", Flag::rollout(50.0))]));
+    
+    // Requests with the same cookie agree whatever their address
+    for i in 0..20 {
+        let mut first = request_from("192.0.2.1");
+        first.set_header("Cookie", &format!("theme=dark; uid=user-{}", i));
+        let mut second = request_from("198.51.100.7");
+        second.set_header("Cookie", &format!("uid=user-{}", i));
+        assert_eq!(flags.enabled("half", &first), flags.enabled("half", &second));
+    }
+    
+    // A client with neither is only in full rollouts
+    assert!(!flags.enabled("half", &Request::new(Method::Get, "/")));
+}
+
+#[test]
+fn test_flag_file_hot_reload() {
+    let path = env::temp_dir().join(format!("flags_test_{}.json", std::process::id()));
+    fs::write(&path, r#"{"flags": {"new_checkout": {"enabled": false}}}"#).unwrap();
+    
+    let events = Arc::new(EventBus::new());
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let received_clone = received.clone();
+    events.subscribe(move |event| received_clone.lock().unwrap().push(event.clone()));
+    
+    let flags = FeatureFlags::load(&path).unwrap().with_events(events);
+    let request = request_from("10.1.1.1");
+    assert!(!flags.enabled("new_checkout", &request));
+    assert!(received.lock().unwrap().is_empty());
+    
+    fs::write(&path, r#"{"flags": {"new_checkout": {"enabled": true}}}"#).unwrap();
+    thread::sleep(Duration::from_millis(1100));
+    assert!(flags.enabled("new_checkout", &request));
+    let reloaded = ServerEvent::ConfigReloaded {
+        path: Some(path.display().to_string()),
+    };
+    assert_eq!(*received.lock().unwrap(), vec![reloaded]);
+    
+    // A broken file keeps the flags that were in effect
+    fs::write(&path, "{ not json").unwrap();
+    assert!(flags.reload().is_err());
+    thread::sleep(Duration::from_millis(1100));
+    assert!(flags.enabled("new_checkout", &request));
+    
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_feature_flags_middleware_and_admin_route() {
+    let flags = Arc::new(FeatureFlags::new(flag_set(&[("new_checkout", Flag::disabled())])));
+    
+    let mut chain = MiddlewareChain::new();
+    chain.add(feature_flags_middleware(flags.clone()));
+    chain.set_handler(|req| {
+        let mut response = Response::new(Status::Ok);
+        response.set_body(if flag_enabled(req, "new_checkout") { b"new" } else { b"old" });
+        Ok(response)
+    });
+    assert_eq!(chain.handle(&request_from("10.0.0.1")).unwrap().body, b"old");
+    
+    let mut router = Router::new();
+    mount_feature_flags(&mut router, "/admin/flags", flags.clone());
+    
+    let mut update = Request::new(Method::Put, "/admin/flags?name=new_checkout");
+    update.body = br#"{"enabled": true}"#.to_vec();
+    assert_eq!(router.handle_request(&update).unwrap().status, Status::NoContent);
+    assert_eq!(chain.handle(&request_from("10.0.0.1")).unwrap().body, b"new");
+    
+    let listing = router.handle_request(&Request::new(Method::Get, "/admin/flags")).unwrap();
+    let state: FlagSet = serde_json::from_slice(&listing.body).unwrap();
+    assert_eq!(state.flags["new_checkout"], Flag::default());
+    
+    let mut invalid = Request::new(Method::Put, "/admin/flags?name=new_checkout");
+    invalid.body = br#"{"rollout": 150}"#.to_vec();
+    assert_eq!(router.handle_request(&invalid).unwrap().status, Status::BadRequest);
+    
+    // Handlers outside the middleware see every flag as off
+    assert!(!flag_enabled(&request_from("10.0.0.1"), "new_checkout"));
+}
+#[test]
+fn test_flags_shared_through_a_store() {
+    let store: Arc<dyn KvStore> = Arc::new(MemoryKvStore::new());
+    let one = FeatureFlags::with_store(store.clone(), "flags").unwrap();
+    let two = FeatureFlags::with_store(store.clone(), "flags").unwrap();
+    let request = request_from("10.1.1.1");
+    assert!(!one.enabled("new_checkout", &request));
+    
+    // A change on one replica is stored and picked up by the other
+    one.set("new_checkout", Flag::default()).unwrap();
+    one.set("dark_mode", Flag::disabled()).unwrap();
+    assert!(one.enabled("new_checkout", &request));
+    let stored: FlagSet = serde_json::from_slice(&store.get("flags").unwrap().unwrap()).unwrap();
+    assert_eq!(stored.flags.len(), 2);
+    assert!(two.reload().unwrap());
+    assert!(two.enabled("new_checkout", &request));
+    
+    // Invalid stored flags keep the flags that were in effect
+    store.set("flags", b"{ not json", None).unwrap();
+    thread::sleep(Duration::from_millis(1100));
+    assert!(two.enabled("new_checkout", &request));
+    assert!(two.set("other", Flag::default()).is_err());
+}
\ No newline at end of file
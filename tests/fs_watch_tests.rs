@@ -0,0 +1,33 @@
+#![cfg(all(feature = "fs-notify", target_os = "linux"))]
+
+use high_performance_server::fs_watch::DirWatcher;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::mpsc::channel;
+use std::time::Duration;
+
+#[test]
+fn test_watcher_reports_changed_directory() {
+    let dir = env::temp_dir().join(format!("hps-watch-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    
+    let (tx, rx) = channel::<PathBuf>();
+    let tx = std::sync::Mutex::new(tx);
+    let watcher = DirWatcher::new(move |changed| {
+        let _ = tx.lock().unwrap().send(changed.to_path_buf());
+    })
+    .unwrap();
+    watcher.watch(&dir).unwrap();
+    watcher.watch(&dir).unwrap();
+    assert_eq!(watcher.watched(), 1);
+    
+    fs::write(dir.join("new.txt"), "hello").unwrap();
+    assert_eq!(rx.recv_timeout(Duration::from_secs(5)).unwrap(), dir);
+    
+    // Removing the directory ends its watch
+    fs::remove_dir_all(&dir).unwrap();
+    while rx.recv_timeout(Duration::from_millis(500)).is_ok() {}
+    assert_eq!(watcher.watched(), 0);
+}
\ No newline at end of file
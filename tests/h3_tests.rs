@@ -0,0 +1,147 @@
+#![cfg(feature = "h3")]
+
+use bytes::{Buf, Bytes};
+use high_performance_server::h3::{H3Config, H3Listener};
+use high_performance_server::{EventBus, Response, Router, ServerEvent, Status};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::CertificateDer;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::time::Duration;
+
+const CERT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/h3/cert.pem");
+const KEY: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/h3/key.pem");
+
+/// Send one request over HTTP/3 and return the status, headers and body
+async fn fetch(addr: std::net::SocketAddr, request: http::Request<()>, body: &[u8]) -> (u16, http::HeaderMap, Vec<u8>) {
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(CertificateDer::from_pem_file(CERT).unwrap()).unwrap();
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let mut tls = rustls::ClientConfig::builder_with_provider(provider)
+        .with_protocol_versions(&[&rustls::version::TLS13])
+        .unwrap()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    tls.alpn_protocols = vec![b"h3".to_vec()];
+    let crypto = quinn::crypto::rustls::QuicClientConfig::try_from(tls).unwrap();
+    
+    let mut endpoint = quinn::Endpoint::client("127.0.0.1:0".parse().unwrap()).unwrap();
+    endpoint.set_default_client_config(quinn::ClientConfig::new(Arc::new(crypto)));
+    let connection = endpoint.connect(addr, "localhost").unwrap().await.unwrap();
+    
+    let (mut driver, mut send_request) = h3::client::new(h3_quinn::Connection::new(connection)).await.unwrap();
+    tokio::spawn(async move { std::future::poll_fn(|cx| driver.poll_close(cx)).await });
+    
+    let mut stream = send_request.send_request(request).await.unwrap();
+    if !body.is_empty() {
+        stream.send_data(Bytes::copy_from_slice(body)).await.unwrap();
+    }
+    stream.finish().await.unwrap();
+    
+    let response = stream.recv_response().await.unwrap();
+    let mut received = Vec::new();
+    while let Some(mut chunk) = stream.recv_data().await.unwrap() {
+        while chunk.has_remaining() {
+            let part = chunk.chunk();
+            received.extend_from_slice(part);
+            let len = part.len();
+            chunk.advance(len);
+        }
+    }
+    (response.status().as_u16(), response.headers().clone(), received)
+}
+
+fn spawn_listener(config: H3Config) -> (high_performance_server::h3::H3Handle, std::net::SocketAddr) {
+    let mut router = Router::new();
+    router.post("/echo/:name", |req| {
+        let mut response = Response::new(Status::Created);
+        response.set_header("X-Host", req.host.as_deref().unwrap_or(""));
+        response.set_header("Connection", "keep-alive");
+        let mut body = req.get_header("x-greeting").cloned().unwrap_or_default().into_bytes();
+        body.extend_from_slice(&req.body);
+        response.set_body(&body);
+        Ok(response)
+    });
+    
+    let listener = H3Listener::bind(&config, Arc::new(router)).unwrap();
+    let addr = listener.local_addr().unwrap();
+    (listener.spawn(), addr)
+}
+
+#[test]
+fn test_h3_requests_reach_the_router() {
+    let (handle, addr) = spawn_listener(H3Config::new("127.0.0.1:0".parse().unwrap(), CERT, KEY));
+    
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let request = http::Request::post(format!("https://localhost:{}/echo/ada", addr.port()))
+        .header("X-Greeting", "hello ")
+        .body(())
+        .unwrap();
+    let (status, headers, body) = runtime.block_on(fetch(addr, request, b"world"));
+    
+    assert_eq!(status, 201);
+    assert_eq!(body, b"hello world");
+    assert_eq!(headers.get("x-host").unwrap(), &format!("localhost:{}", addr.port()));
+    assert!(headers.get("connection").is_none(), "connection headers aren't allowed in HTTP/3");
+    
+    handle.stop();
+}
+
+#[test]
+fn test_h3_rejects_oversized_bodies() {
+    let config = H3Config::new("127.0.0.1:0".parse().unwrap(), CERT, KEY).with_max_body_size(4);
+    let (handle, addr) = spawn_listener(config);
+    
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let request = http::Request::post(format!("https://localhost:{}/echo/ada", addr.port())).body(()).unwrap();
+    let (status, _, _) = runtime.block_on(fetch(addr, request, b"too long"));
+    
+    assert_eq!(status, 413);
+    handle.stop();
+}
+
+#[test]
+fn test_h3_bind_reports_missing_certificate() {
+    let config = H3Config::new("127.0.0.1:0".parse().unwrap(), "/nonexistent/cert.pem", KEY);
+    let error = H3Listener::bind(&config, Arc::new(Router::new())).err().unwrap();
+    assert!(error.to_string().contains("/nonexistent/cert.pem"), "{}", error);
+}
+
+#[test]
+fn test_h3_reloads_a_changed_certificate() {
+    let dir = std::env::temp_dir().join(format!("hps-h3-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (cert, key) = (dir.join("cert.pem"), dir.join("key.pem"));
+    std::fs::copy(CERT, &cert).unwrap();
+    std::fs::copy(KEY, &key).unwrap();
+    
+    let events = Arc::new(EventBus::new());
+    let (tx, rx) = mpsc::channel();
+    let tx = std::sync::Mutex::new(tx);
+    events.subscribe(move |event| {
+        let _ = tx.lock().unwrap().send(event.clone());
+    });
+    
+    let config = H3Config::new("127.0.0.1:0".parse().unwrap(), cert.to_str().unwrap(), key.to_str().unwrap())
+        .with_domain("localhost")
+        .with_cert_check_interval(Duration::from_millis(20));
+    let listener = H3Listener::bind(&config, Arc::new(Router::new())).unwrap().with_events(events);
+    let addr = listener.local_addr().unwrap();
+    let handle = listener.spawn();
+    
+    // Nothing is reported until the files change
+    assert!(rx.recv_timeout(Duration::from_millis(100)).is_err());
+    std::thread::sleep(Duration::from_millis(10));
+    std::fs::write(&cert, std::fs::read(CERT).unwrap()).unwrap();
+    let event = rx.recv_timeout(Duration::from_secs(2)).unwrap();
+    assert_eq!(event, ServerEvent::CertificateRenewed { domain: "localhost".to_string() });
+    
+    // The reloaded certificate still serves requests
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let request = http::Request::get(format!("https://localhost:{}/", addr.port())).body(()).unwrap();
+    let (status, _, _) = runtime.block_on(fetch(addr, request, b""));
+    assert_eq!(status, 404);
+    
+    handle.stop();
+    std::fs::remove_dir_all(&dir).unwrap();
+}
\ No newline at end of file
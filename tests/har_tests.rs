@@ -0,0 +1,157 @@
+use high_performance_server::{
+    har_middleware, read_har, HarConfig, HarRecorder, Method, MiddlewareChain, Request, Response, Status,
+};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+fn capture_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("hps-har-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn config(dir: &Path) -> HarConfig {
+    HarConfig {
+        path: dir.join("capture.har").display().to_string(),
+        ..HarConfig::default()
+    }
+}
+
+fn echo_chain(recorder: Arc<HarRecorder>) -> MiddlewareChain {
+    let mut chain = MiddlewareChain::new();
+    chain.add(har_middleware(recorder));
+    chain.set_handler(|req| {
+        let mut response = Response::new(Status::Created);
+        response.set_body(format!(r#"{{"path":"{}","token":"t0k3n"}}"#, req.path()).as_bytes());
+        response.set_header("Content-Type", "application/json");
+        response.set_header("Set-Cookie", "session=abc123");
+        Ok(response)
+    });
+    chain
+}
+
+#[test]
+fn test_exchanges_are_captured_redacted() {
+    let dir = capture_dir("redacted");
+    let recorder = Arc::new(HarRecorder::new(config(&dir)).unwrap());
+    let chain = echo_chain(recorder.clone());
+    
+    let mut request = Request::new(Method::Post, "/login?user=alice&api_key=k3y");
+    request.set_header("Host", "example.com");
+    request.set_header("Authorization", "Bearer s3cr3t");
+    request.set_header("Content-Type", "application/json");
+    request.set_body(br#"{"user":"alice","password":"hunter2"}"#);
+    assert_eq!(chain.handle(&request).unwrap().status, Status::Created);
+    
+    let mut binary = Request::new(Method::Put, "/blob");
+    binary.set_body(&[0xff, 0x00, 0xfe]);
+    chain.handle(&binary).unwrap();
+    
+    // The file is readable while still being written
+    let path = dir.join("capture.har");
+    let entries = read_har(&path).unwrap();
+    assert_eq!(entries.len(), 2);
+    
+    let login = &entries[0];
+    assert_eq!(login.request.method, "POST");
+    assert_eq!(login.request.url, "http://example.com/login?user=alice&api_key=[REDACTED]");
+    let header = |name: &str| login.request.headers.iter().find(|h| h.name == name).unwrap().value.clone();
+    assert_eq!(header("authorization"), "[REDACTED]");
+    assert_eq!(header("host"), "example.com");
+    let post_data = login.request.post_data.as_ref().unwrap();
+    assert_eq!(post_data.text, r#"{"password":"[REDACTED]","user":"alice"}"#);
+    assert_eq!(login.response.status, 201);
+    assert_eq!(login.response.content.text.as_deref(), Some(r#"{"path":"/login","token":"[REDACTED]"}"#));
+    assert!(login.response.headers.iter().any(|h| h.name == "Set-Cookie" && h.value == "[REDACTED]"));
+    assert!(login.started_date_time.ends_with('Z') && login.started_date_time.len() == 24);
+    
+    let blob = entries[1].request.post_data.as_ref().unwrap();
+    assert_eq!(blob.encoding.as_deref(), Some("base64"));
+    assert_eq!(base64::decode(&blob.text).unwrap(), [0xff, 0x00, 0xfe]);
+    
+    // Dropping the recorder closes the document
+    drop(chain);
+    drop(recorder);
+    let content = fs::read_to_string(&path).unwrap();
+    let document: serde_json::Value = serde_json::from_str(&content).unwrap();
+    assert_eq!(document["log"]["version"], "1.2");
+    assert_eq!(document["log"]["entries"].as_array().unwrap().len(), 2);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_sampling_paths_and_body_limit() {
+    let dir = capture_dir("sampling");
+    let recorder = Arc::new(
+        HarRecorder::new(HarConfig {
+            percent: 25.0,
+            paths: vec!["/api".to_string()],
+            max_body_size: 8,
+            ..config(&dir)
+        })
+        .unwrap(),
+    );
+    let chain = echo_chain(recorder);
+    for i in 0..8 {
+        chain.handle(&Request::new(Method::Get, &format!("/api/items/{}", i))).unwrap();
+        chain.handle(&Request::new(Method::Get, "/static/app.js")).unwrap();
+    }
+    
+    let entries = read_har(dir.join("capture.har")).unwrap();
+    let urls: Vec<&str> = entries.iter().map(|entry| entry.request.url.as_str()).collect();
+    assert_eq!(urls, ["http://localhost/api/items/3", "http://localhost/api/items/7"]);
+    
+    let content = &entries[0].response.content;
+    assert_eq!(content.text.as_deref(), Some("{\"path\":"));
+    assert_eq!(content.size, 39);
+    assert_eq!(content.comment.as_deref(), Some("truncated to 8 bytes"));
+    
+    assert!(HarRecorder::new(HarConfig {
+        percent: 150.0,
+        ..config(&dir)
+    })
+    .is_err());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_full_files_rotate_into_complete_documents() {
+    let dir = capture_dir("rotation");
+    let path = dir.join("capture.har");
+    fs::write(&path, "left over from an earlier run").unwrap();
+    
+    let recorder = Arc::new(
+        HarRecorder::new(HarConfig {
+            max_file_size: 2048,
+            compress: true,
+            ..config(&dir)
+        })
+        .unwrap(),
+    );
+    // The leftover file was moved away rather than appended to
+    assert!(dir.join("capture.har.1.gz").exists());
+    
+    let chain = echo_chain(recorder);
+    for i in 0..6 {
+        chain.handle(&Request::new(Method::Get, &format!("/page/{}", i))).unwrap();
+    }
+    drop(chain);
+    
+    // Rotated files hold whole documents, oldest entries in the highest numbers
+    let newest = read_har(dir.join("capture.har.1.gz")).unwrap();
+    let current = read_har(&path).unwrap();
+    assert!(!newest.is_empty() && !current.is_empty());
+    assert_eq!(current.last().unwrap().request.url, "http://localhost/page/5");
+    let mut total = newest.len() + current.len();
+    let mut index = 2;
+    while let Ok(entries) = read_har(dir.join(format!("capture.har.{}.gz", index))) {
+        total += entries.len();
+        index += 1;
+    }
+    assert_eq!(total, 6);
+    assert!(fs::metadata(&path).unwrap().len() <= 2048);
+    fs::remove_dir_all(&dir).unwrap();
+}
\ No newline at end of file
@@ -0,0 +1,47 @@
+use high_performance_server::header_name::{HeaderName, StandardHeader};
+use high_performance_server::http::{is_valid_header_name, HttpParser};
+use std::collections::HashMap;
+
+#[test]
+fn test_common_names_are_interned_in_any_case() {
+    for &standard in StandardHeader::ALL {
+        let name = standard.as_str();
+        assert!(is_valid_header_name(name) && name == name.to_lowercase(), "{}", name);
+        assert_eq!(StandardHeader::from_lowercase(name), Some(standard));
+        assert_eq!(HeaderName::new(&name.to_uppercase()).standard(), Some(standard));
+    }
+    
+    assert_eq!(HeaderName::new("Content-Type"), HeaderName::CONTENT_TYPE);
+    let custom = HeaderName::new("X-Custom-Thing");
+    assert_eq!(custom.standard(), None);
+    assert_eq!(custom, "x-custom-thing");
+    assert_eq!(custom, HeaderName::new("x-CUSTOM-thing"));
+    assert_ne!(custom, HeaderName::HOST);
+    
+    // Names too long to lowercase on the stack are still lowercased
+    let long = "X-".repeat(40);
+    assert_eq!(HeaderName::new(&long).as_str(), long.to_lowercase());
+}
+
+#[test]
+fn test_maps_are_looked_up_by_name_or_constant() {
+    let mut headers = HashMap::new();
+    headers.insert(HeaderName::new("Host"), "example.com".to_string());
+    headers.insert(HeaderName::new("X-Trace"), "abc".to_string());
+    
+    assert_eq!(headers.get(&HeaderName::HOST).unwrap(), "example.com");
+    assert_eq!(headers.get("host").unwrap(), "example.com");
+    assert_eq!(headers.get("x-trace").unwrap(), "abc");
+    assert!(!headers.contains_key("Host"));
+    
+    let mut parser = HttpParser::new();
+    parser
+        .parse(b"GET / HTTP/1.1\r\nHOST: example.com\r\nUser-Agent: test\r\nX-Trace: abc\r\n\r\n")
+        .unwrap();
+    let request = parser.get_request().unwrap();
+    let mut standard: Vec<Option<StandardHeader>> = request.headers.keys().map(HeaderName::standard).collect();
+    standard.sort_by_key(|header| header.map(StandardHeader::as_str));
+    assert_eq!(standard, [None, Some(StandardHeader::Host), Some(StandardHeader::UserAgent)]);
+    assert_eq!(request.get_header("X-TRACE").unwrap(), "abc");
+    assert_eq!(request.host.as_deref(), Some("example.com"));
+}
\ No newline at end of file
@@ -0,0 +1,205 @@
+use high_performance_server::http_client::{
+    connect_happy_eyeballs, interleave_families, send_idempotent, HttpUrl, RetryPolicy,
+};
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Serve every connection with `status` after `delay`, counting the requests
+fn fake_upstream(status: u16, delay: Duration) -> (HttpUrl, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = HttpUrl::parse(&format!("http://{}/item", listener.local_addr().unwrap())).unwrap();
+    let hits = Arc::new(AtomicUsize::new(0));
+    
+    let counter = hits.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            counter.fetch_add(1, Ordering::SeqCst);
+            thread::spawn(move || {
+                let mut request = Vec::new();
+                let mut byte = [0u8; 1];
+                while !request.ends_with(b"\r\n\r\n") && stream.read(&mut byte).unwrap_or(0) == 1 {
+                    request.push(byte[0]);
+                }
+                thread::sleep(delay);
+                let body = format!("status {}", status);
+                let _ = write!(stream, "HTTP/1.1 {} X\r\nContent-Length: {}\r\n\r\n{}", status, body.len(), body);
+            });
+        }
+    });
+    
+    (url, hits)
+}
+
+#[test]
+fn test_retries_move_to_the_next_upstream() {
+    let (failing, failing_hits) = fake_upstream(503, Duration::ZERO);
+    let (healthy, healthy_hits) = fake_upstream(200, Duration::ZERO);
+    
+    let policy = RetryPolicy {
+        initial_backoff: Duration::from_millis(10),
+        ..RetryPolicy::default()
+    };
+    let response = send_idempotent("GET", &[failing, healthy], &policy).unwrap();
+    assert_eq!(response.status, 200);
+    assert_eq!(response.body, b"status 200");
+    assert_eq!(failing_hits.load(Ordering::SeqCst), 1);
+    assert_eq!(healthy_hits.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn test_retries_give_up_after_max_attempts() {
+    let (failing, hits) = fake_upstream(502, Duration::ZERO);
+    
+    let policy = RetryPolicy {
+        max_attempts: 3,
+        initial_backoff: Duration::from_millis(20),
+        ..RetryPolicy::default()
+    };
+    let start = Instant::now();
+    let response = send_idempotent("HEAD", &[failing], &policy).unwrap();
+    assert_eq!(response.status, 502);
+    assert_eq!(hits.load(Ordering::SeqCst), 3);
+    
+    // Backoff doubles: 20ms, then 40ms
+    assert!(start.elapsed() >= Duration::from_millis(60));
+    
+    // Client errors are final
+    let (missing, hits) = fake_upstream(404, Duration::ZERO);
+    assert_eq!(send_idempotent("GET", &[missing], &policy).unwrap().status, 404);
+    assert_eq!(hits.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn test_hedged_request_beats_slow_upstream() {
+    let (slow, slow_hits) = fake_upstream(200, Duration::from_secs(2));
+    let (fast, fast_hits) = fake_upstream(200, Duration::ZERO);
+    
+    let policy = RetryPolicy {
+        hedge_after: Some(Duration::from_millis(50)),
+        ..RetryPolicy::default()
+    };
+    let start = Instant::now();
+    let response = send_idempotent("GET", &[slow, fast], &policy).unwrap();
+    assert_eq!(response.status, 200);
+    assert!(start.elapsed() < Duration::from_secs(1));
+    assert_eq!(slow_hits.load(Ordering::SeqCst), 1);
+    assert_eq!(fast_hits.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn test_deadline_bounds_the_whole_exchange() {
+    let (slow, _) = fake_upstream(200, Duration::from_secs(3));
+    
+    let policy = RetryPolicy {
+        deadline: Duration::from_millis(200),
+        ..RetryPolicy::default()
+    };
+    let start = Instant::now();
+    assert!(send_idempotent("GET", &[slow], &policy).is_err());
+    assert!(start.elapsed() < Duration::from_secs(1));
+    
+    // Requests with side effects are never retried
+    assert!(send_idempotent("POST", &[], &policy).is_err());
+}
+
+/// An address nothing is listening on
+fn refused_address() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.local_addr().unwrap()
+}
+
+#[test]
+fn test_interleave_families_alternates() {
+    let addrs: Vec<SocketAddr> = [
+        "[2001:db8::1]:80",
+        "[2001:db8::2]:80",
+        "192.0.2.1:80",
+        "192.0.2.2:80",
+        "192.0.2.3:80",
+    ]
+    .iter()
+    .map(|addr| addr.parse().unwrap())
+    .collect();
+    let ordered: Vec<String> = interleave_families(&addrs).iter().map(ToString::to_string).collect();
+    assert_eq!(
+        ordered,
+        ["[2001:db8::1]:80", "192.0.2.1:80", "[2001:db8::2]:80", "192.0.2.2:80", "192.0.2.3:80"]
+    );
+    
+    // The first family stays first
+    let ordered = interleave_families(&[addrs[2], addrs[0]]);
+    assert_eq!(ordered, vec![addrs[2], addrs[0]]);
+}
+
+#[test]
+fn test_happy_eyeballs_skips_failed_address() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let good = listener.local_addr().unwrap();
+    
+    let start = Instant::now();
+    let deadline = start + Duration::from_secs(5);
+    let stream = connect_happy_eyeballs(&[refused_address(), good], Duration::from_secs(5), deadline).unwrap();
+    assert_eq!(stream.peer_addr().unwrap(), good);
+    // A refused attempt doesn't wait out the attempt delay
+    assert!(start.elapsed() < Duration::from_secs(1));
+}
+
+#[test]
+fn test_happy_eyeballs_returns_a_blocking_stream() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let good = listener.local_addr().unwrap();
+    let mut stream =
+        connect_happy_eyeballs(&[good], Duration::from_millis(200), Instant::now() + Duration::from_secs(5)).unwrap();
+    
+    // A read with nothing to read waits out its timeout instead of failing at once
+    stream.set_read_timeout(Some(Duration::from_millis(100))).unwrap();
+    let start = Instant::now();
+    assert!(stream.read(&mut [0u8; 1]).is_err());
+    assert!(start.elapsed() >= Duration::from_millis(80), "{:?}", start.elapsed());
+}
+
+/// A listener whose accept queue is full, so new connections hang
+fn unresponsive_address() -> (socket2::Socket, Vec<std::net::TcpStream>, SocketAddr) {
+    let socket = socket2::Socket::new(socket2::Domain::IPV4, socket2::Type::STREAM, None).unwrap();
+    socket.bind(&"127.0.0.1:0".parse::<SocketAddr>().unwrap().into()).unwrap();
+    socket.listen(0).unwrap();
+    let addr = socket.local_addr().unwrap().as_socket().unwrap();
+    
+    let mut queued = Vec::new();
+    while let Ok(stream) = std::net::TcpStream::connect_timeout(&addr, Duration::from_millis(100)) {
+        queued.push(stream);
+    }
+    (socket, queued, addr)
+}
+
+#[test]
+fn test_happy_eyeballs_bounds_unresponsive_address() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let good = listener.local_addr().unwrap();
+    let (_socket, _queued, unresponsive) = unresponsive_address();
+    
+    let start = Instant::now();
+    let stream = connect_happy_eyeballs(
+        &[unresponsive, good],
+        Duration::from_millis(200),
+        start + Duration::from_secs(10),
+    )
+    .unwrap();
+    assert_eq!(stream.peer_addr().unwrap(), good);
+    assert!(start.elapsed() >= Duration::from_millis(200));
+    assert!(start.elapsed() < Duration::from_secs(1));
+}
+
+#[test]
+fn test_happy_eyeballs_reports_failure() {
+    let start = Instant::now();
+    let deadline = start + Duration::from_secs(5);
+    let result = connect_happy_eyeballs(&[refused_address(), refused_address()], Duration::from_millis(200), deadline);
+    assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::ConnectionRefused);
+    assert!(connect_happy_eyeballs(&[], Duration::from_millis(200), deadline).is_err());
+}
\ No newline at end of file
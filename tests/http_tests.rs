@@ -1,4 +1,8 @@
-use high_performance_server::http::{HttpParser, Method, Request, Response, Status};
+use high_performance_server::buffer::Buffer;
+use high_performance_server::http::{
+    is_valid_header_name, is_valid_header_value, sanitize_header_value, HttpParser, HttpParserState, Method, Request,
+    RequestTarget, Response, Status,
+};
 use std::io::Cursor;
 
 #[test]
@@ -88,6 +92,50 @@ fn test_http_parser_reset() {
     assert_eq!(request.method, Method::Post);
 }
 
+#[test]
+fn test_http_parser_absolute_form() {
+    let mut parser = HttpParser::new();
+    parser.parse(b"GET http://example.com:8080/path?x=1 HTTP/1.1\r\nHost: other.com\r\n\r\n").unwrap();
+    assert!(parser.is_complete());
+    
+    let request = parser.get_request().unwrap();
+    assert_eq!(request.target_form, RequestTarget::Absolute);
+    assert_eq!(request.uri, "/path?x=1");
+    assert_eq!(request.path(), "/path");
+    assert_eq!(request.host.as_deref(), Some("example.com:8080"));
+    assert_eq!(request.query_params.get("x").unwrap(), "1");
+}
+
+#[test]
+fn test_http_parser_authority_form() {
+    let mut parser = HttpParser::new();
+    parser.parse(b"CONNECT example.com:443 HTTP/1.1\r\nHost: example.com:443\r\n\r\n").unwrap();
+    
+    let request = parser.get_request().unwrap();
+    assert_eq!(request.method, Method::Connect);
+    assert_eq!(request.target_form, RequestTarget::Authority);
+    assert_eq!(request.host.as_deref(), Some("example.com:443"));
+    
+    // CONNECT must use authority-form
+    let mut parser = HttpParser::new();
+    assert!(parser.parse(b"CONNECT /path HTTP/1.1\r\n\r\n").is_err());
+}
+
+#[test]
+fn test_http_parser_asterisk_form() {
+    let mut parser = HttpParser::new();
+    parser.parse(b"OPTIONS * HTTP/1.1\r\nHost: example.com\r\n\r\n").unwrap();
+    
+    let request = parser.get_request().unwrap();
+    assert_eq!(request.target_form, RequestTarget::Asterisk);
+    assert_eq!(request.uri, "*");
+    assert_eq!(request.host.as_deref(), Some("example.com"));
+    
+    // Asterisk-form is only valid for OPTIONS
+    let mut parser = HttpParser::new();
+    assert!(parser.parse(b"GET * HTTP/1.1\r\n\r\n").is_err());
+}
+
 #[test]
 fn test_request_methods() {
     let mut request = Request::new(Method::Get, "/api/data");
@@ -137,4 +185,154 @@ fn test_different_status_codes() {
         let response_str = String::from_utf8_lossy(&buffer);
         assert!(response_str.starts_with(&format!("HTTP/1.1 {} {}\r\n", code, text)));
     }
+}
+
+#[test]
+fn test_header_byte_validation() {
+    assert!(is_valid_header_name("X-Custom_Header.1"));
+    assert!(!is_valid_header_name(""));
+    assert!(!is_valid_header_name("Bad Name"));
+    assert!(!is_valid_header_name("Bad:Name"));
+    assert!(!is_valid_header_name("Bad\r\nName"));
+    
+    assert!(is_valid_header_value("text/html; charset=utf-8"));
+    assert!(is_valid_header_value("tab\tseparated"));
+    assert!(is_valid_header_value("caf\u{e9}"));
+    assert!(!is_valid_header_value("a\r\nSet-Cookie: x=1"));
+    assert!(!is_valid_header_value("nul\0byte"));
+    assert!(!is_valid_header_value("del\x7f"));
+    
+    assert_eq!(sanitize_header_value("a\r\nb"), "ab");
+    assert_eq!(sanitize_header_value("fine"), "fine");
+}
+
+#[test]
+fn test_http_parser_rejects_invalid_headers() {
+    let cases: [&[u8]; 4] = [
+        b"GET / HTTP/1.1\r\nBad Name: x\r\n\r\n",
+        b"GET / HTTP/1.1\r\nHost : example.com\r\n\r\n",
+        b"GET / HTTP/1.1\r\nX-Test: a\x01b\r\n\r\n",
+        b"GET / HTTP/1.1\r\nX-Test: a\r\n folded\r\n\r\n",
+    ];
+    
+    for data in cases {
+        let mut parser = HttpParser::new();
+        assert!(parser.parse(data).is_err(), "accepted {:?}", String::from_utf8_lossy(data));
+    }
+}
+
+#[test]
+fn test_set_header_sanitizes() {
+    let mut request = Request::new(Method::Get, "/");
+    request.set_header("X-Forwarded-For", "1.2.3.4\r\nX-Admin: yes");
+    assert_eq!(request.get_header("x-forwarded-for").unwrap(), "1.2.3.4X-Admin: yes");
+    request.set_header("Bad Name", "x");
+    assert!(request.get_header("bad name").is_none());
+    assert!(request.try_set_header("X-Test", "a\nb").is_err());
+    
+    let mut response = Response::new(Status::Ok);
+    response.set_header("Location", "/next\r\nSet-Cookie: session=stolen");
+    assert_eq!(response.headers.get("Location").unwrap(), "/nextSet-Cookie: session=stolen");
+    response.set_header("X-Bad\r\nHeader", "x");
+    assert!(!response.headers.keys().any(|name| name.contains('\n')));
+    assert!(response.try_set_header("X-Test", "ok").is_ok());
+    assert!(response.try_set_header("X-Test", "bad\r").is_err());
+}
+
+/// Split a serialized response into its header lines
+fn header_lines(response: &Response) -> Vec<String> {
+    let mut encoded = Vec::new();
+    response.serialize(&mut encoded).unwrap();
+    let text = String::from_utf8(encoded).unwrap();
+    let head = text.split("\r\n\r\n").next().unwrap().to_string();
+    head.split("\r\n").skip(1).map(str::to_string).collect()
+}
+
+#[test]
+fn test_serialize_neutralizes_response_splitting() {
+    let mut response = Response::new(Status::Found);
+    
+    // Inserted directly, bypassing set_header's sanitizing
+    response
+        .headers
+        .insert("Location".to_string(), "/home\r\nSet-Cookie: admin=1\r\n\r\n<script>".to_string());
+    response.headers.insert("X-Evil\r\nSet-Cookie".to_string(), "admin=1".to_string());
+    response.headers.insert("X-Lone-LF".to_string(), "a\nb".to_string());
+    
+    let lines = header_lines(&response);
+    assert!(lines.iter().all(|line| !line.starts_with("Set-Cookie")));
+    assert!(lines.contains(&"Location: /homeSet-Cookie: admin=1<script>".to_string()));
+    assert!(lines.contains(&"X-Lone-LF: ab".to_string()));
+    assert!(!lines.iter().any(|line| line.contains("X-Evil")));
+    
+    // Exactly one blank line ends the head, so no body can be smuggled in
+    let mut encoded = Vec::new();
+    response.serialize(&mut encoded).unwrap();
+    let text = String::from_utf8(encoded).unwrap();
+    assert_eq!(text.matches("\r\n\r\n").count(), 1);
+    assert!(text.ends_with("\r\n\r\n"));
+}
+
+#[test]
+fn test_response_header_lookup_ignores_case() {
+    let mut response = Response::new(Status::Ok);
+    response.set_header("connection", "close");
+    assert_eq!(response.header("Connection"), Some("close"));
+    assert_eq!(response.header("CONNECTION"), Some("close"));
+    assert_eq!(response.header("server"), Some("High-Performance-Server/0.1"));
+    assert!(response.header("Content-Type").is_none());
+}
+
+#[test]
+fn test_set_header_splitting_attempt_via_untrusted_input() {
+    // A handler echoing a query parameter into a header
+    let request = Request::new(Method::Get, "/redirect?to=/x%0d%0a");
+    let target = format!("{}\r\nSet-Cookie: session=attacker", request.query_params["to"]);
+    
+    let mut response = Response::new(Status::Found);
+    response.set_header("Location", &target);
+    
+    let lines = header_lines(&response);
+    assert_eq!(lines.iter().filter(|line| line.starts_with("Location:")).count(), 1);
+    assert!(lines.iter().all(|line| !line.starts_with("Set-Cookie")));
+}
+
+#[test]
+fn test_parse_buffer_incrementally() {
+    let mut buffer = Buffer::new(64);
+    let mut parser = HttpParser::new();
+    
+    // A partial head consumes nothing
+    buffer.write(b"POST /upload HTTP/1.1\r\nHost: a\r\nContent-Le").unwrap();
+    parser.parse_buffer(&mut buffer).unwrap();
+    assert!(parser.in_head());
+    assert_eq!(buffer.available_data(), 42);
+    
+    // The body can arrive separately
+    buffer.write(b"ngth: 5\r\n\r\nhe").unwrap();
+    parser.parse_buffer(&mut buffer).unwrap();
+    assert_eq!(parser.state, HttpParserState::Body);
+    assert_eq!(buffer.available_data(), 0);
+    
+    buffer.write(b"lloGET /next HTTP/1.1\r\nHost: a\r\n\r\n").unwrap();
+    parser.parse_buffer(&mut buffer).unwrap();
+    assert!(parser.is_complete());
+    assert_eq!(parser.get_request().unwrap().body, b"hello");
+    
+    // The pipelined request is left for the next call
+    parser.parse_buffer(&mut buffer).unwrap();
+    assert!(parser.is_complete());
+    assert_eq!(parser.get_request().unwrap().path(), "/next");
+    assert_eq!(buffer.available_data(), 0);
+}
+
+#[test]
+fn test_request_cookie() {
+    let mut request = Request::new(Method::Get, "/");
+    assert_eq!(request.cookie("uid"), None);
+    
+    request.set_header("Cookie", "theme=dark; uid=\"abc123\";session=xyz");
+    assert_eq!(request.cookie("uid"), Some("abc123"));
+    assert_eq!(request.cookie("session"), Some("xyz"));
+    assert_eq!(request.cookie("the"), None);
 }
\ No newline at end of file
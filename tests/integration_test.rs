@@ -213,4 +213,752 @@ fn test_concurrent_connections() {
     
     // Wait for the server to finish
     server_thread.join().unwrap();
+}
+
+/// Run an event loop with the given router, returning its address and a way to stop it
+fn spawn_server(
+    router: high_performance_server::Router,
+) -> (std::net::SocketAddr, std::sync::Arc<std::sync::atomic::AtomicBool>, thread::JoinHandle<()>) {
+    spawn_server_with_config(router, high_performance_server::ServerConfig::default())
+}
+
+/// Run an event loop with the given router and configuration
+fn spawn_server_with_config(
+    router: high_performance_server::Router,
+    config: high_performance_server::ServerConfig,
+) -> (std::net::SocketAddr, std::sync::Arc<std::sync::atomic::AtomicBool>, thread::JoinHandle<()>) {
+    use high_performance_server::{ConnectionAcceptor, EventLoop};
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+    
+    let acceptor = Arc::new(ConnectionAcceptor::new("127.0.0.1:0").unwrap());
+    let addr = acceptor.local_addr().unwrap();
+    let drain = Arc::new(AtomicBool::new(false));
+    
+    let drain_clone = drain.clone();
+    let handle = thread::spawn(move || {
+        let mut event_loop = EventLoop::with_config(0, acceptor, config);
+        event_loop.set_router(Arc::new(router));
+        event_loop.set_drain_signal(drain_clone);
+        event_loop.run().unwrap();
+    });
+    
+    (addr, drain, handle)
+}
+
+/// Run an event loop serving a large response, returning its address and a way to stop it
+fn spawn_large_response_server(
+    body_len: usize,
+) -> (std::net::SocketAddr, std::sync::Arc<std::sync::atomic::AtomicBool>, thread::JoinHandle<()>) {
+    use high_performance_server::{Response, Router, Status};
+    
+    let mut router = Router::new();
+    router.get("/large", move |_| {
+        let mut response = Response::new(Status::Ok);
+        response.set_body(&vec![b'z'; body_len]);
+        Ok(response)
+    });
+    spawn_server(router)
+}
+
+/// Run an event loop answering `GET /hello`
+fn spawn_hello_server() -> (std::net::SocketAddr, std::sync::Arc<std::sync::atomic::AtomicBool>, thread::JoinHandle<()>) {
+    use high_performance_server::{Response, Router, Status};
+    
+    let mut router = Router::new();
+    router.get("/hello", |_| {
+        let mut response = Response::new(Status::Ok);
+        response.set_body(b"hello");
+        Ok(response)
+    });
+    spawn_server(router)
+}
+
+/// Read one response with a 5-byte body, returning its head
+fn read_hello_response(client: &mut TcpStream) -> String {
+    let mut received = Vec::new();
+    let mut byte = [0u8; 1];
+    while !received.ends_with(b"\r\n\r\n") {
+        client.read_exact(&mut byte).unwrap();
+        received.push(byte[0]);
+    }
+    let mut body = [0u8; 5];
+    client.read_exact(&mut body).unwrap();
+    assert_eq!(&body, b"hello");
+    String::from_utf8(received).unwrap()
+}
+
+// A client that half-closes after sending its request still gets the whole response
+#[test]
+fn test_half_close_receives_full_response() {
+    let body_len = 8 * 1024 * 1024;
+    let (addr, drain, server) = spawn_large_response_server(body_len);
+    
+    let mut client = TcpStream::connect(addr).unwrap();
+    client.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+    client.write_all(b"GET /large HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+    client.shutdown(std::net::Shutdown::Write).unwrap();
+    
+    // The server finishes the response, then closes its side cleanly
+    let mut received = Vec::new();
+    client.read_to_end(&mut received).unwrap();
+    
+    let head_end = received.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+    assert!(received.starts_with(b"HTTP/1.1 200 OK"));
+    assert_eq!(received.len() - head_end, body_len);
+    
+    drop(client);
+    drain.store(true, std::sync::atomic::Ordering::SeqCst);
+    server.join().unwrap();
+}
+
+// A half-close arriving while the server is blocked mid-response, which reads
+// end-of-stream and reports a hangup in the same event, doesn't cut it short
+#[test]
+fn test_half_close_during_response_write() {
+    let body_len = 8 * 1024 * 1024;
+    let (addr, drain, server) = spawn_large_response_server(body_len);
+    
+    let mut client = TcpStream::connect(addr).unwrap();
+    client.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+    client.write_all(b"GET /large HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+    
+    // Take the first bytes, then let the socket buffers fill so the server waits to write
+    let mut received = vec![0u8; 4096];
+    client.read_exact(&mut received).unwrap();
+    thread::sleep(Duration::from_millis(100));
+    client.shutdown(std::net::Shutdown::Write).unwrap();
+    thread::sleep(Duration::from_millis(100));
+    client.read_to_end(&mut received).unwrap();
+    
+    let head_end = received.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+    assert!(received.starts_with(b"HTTP/1.1 200 OK"));
+    assert_eq!(received.len() - head_end, body_len);
+    
+    drop(client);
+    drain.store(true, std::sync::atomic::Ordering::SeqCst);
+    server.join().unwrap();
+}
+
+// Thousands of requests pipelined on one connection are all answered, with
+// the stack the worker needs not growing with their number
+#[test]
+fn test_deep_pipelining_is_answered() {
+    use high_performance_server::{ConnectionAcceptor, EventLoop, Response, Router, Status};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    
+    let mut router = Router::new();
+    router.get("/h", |_| {
+        let mut response = Response::new(Status::Ok);
+        response.set_body(b"hello");
+        Ok(response)
+    });
+    router.get("/slow", |_| {
+        thread::sleep(Duration::from_millis(300));
+        Ok(Response::new(Status::Ok))
+    });
+    
+    // A small stack, which requests answered recursively would soon overflow
+    let acceptor = Arc::new(ConnectionAcceptor::new("127.0.0.1:0").unwrap());
+    let addr = acceptor.local_addr().unwrap();
+    let drain = Arc::new(AtomicBool::new(false));
+    let drain_clone = drain.clone();
+    let server = thread::Builder::new()
+        .stack_size(256 * 1024)
+        .spawn(move || {
+            let mut event_loop = EventLoop::new(0, acceptor);
+            event_loop.set_router(Arc::new(router));
+            event_loop.set_drain_signal(drain_clone);
+            event_loop.run().unwrap();
+        })
+        .unwrap();
+    
+    // The first request holds the worker up while the rest arrive, so each read takes hundreds
+    let mut client = TcpStream::connect(addr).unwrap();
+    client.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+    let mut writer = client.try_clone().unwrap();
+    let sender = thread::spawn(move || {
+        writer.write_all(b"GET /slow HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        thread::sleep(Duration::from_millis(50));
+        writer.write_all(&b"GET /h HTTP/1.1\r\nHost:a\r\n\r\n".repeat(5000)).unwrap();
+        writer.write_all(b"GET /h HTTP/1.1\r\nHost:a\r\nConnection: close\r\n\r\n").unwrap();
+    });
+    let mut received = Vec::new();
+    client.read_to_end(&mut received).unwrap();
+    sender.join().unwrap();
+    assert_eq!(received.windows(15).filter(|w| w == b"HTTP/1.1 200 OK").count(), 5002);
+    assert!(received.ends_with(b"hello"));
+    
+    drain.store(true, Ordering::SeqCst);
+    server.join().unwrap();
+}
+
+// HTTP/1.1 connections serve several requests unless the client asks to close
+#[test]
+fn test_keep_alive_and_connection_close() {
+    let (addr, drain, server) = spawn_hello_server();
+    
+    let mut client = TcpStream::connect(addr).unwrap();
+    client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    for _ in 0..3 {
+        client.write_all(b"GET /hello HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        assert!(read_hello_response(&mut client).contains("Connection: keep-alive\r\n"));
+    }
+    
+    // Asking to close gets one last response, then the server hangs up
+    client.write_all(b"GET /hello HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").unwrap();
+    assert!(read_hello_response(&mut client).contains("Connection: close\r\n"));
+    let mut rest = Vec::new();
+    client.read_to_end(&mut rest).unwrap();
+    assert!(rest.is_empty());
+    
+    // HTTP/1.0 closes unless keep-alive is requested
+    let mut client = TcpStream::connect(addr).unwrap();
+    client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    client.write_all(b"GET /hello HTTP/1.0\r\n\r\n").unwrap();
+    assert!(read_hello_response(&mut client).contains("Connection: close\r\n"));
+    
+    drop(client);
+    drain.store(true, std::sync::atomic::Ordering::SeqCst);
+    server.join().unwrap();
+}
+
+// Draining closes idle keep-alive connections instead of waiting out their timeout
+#[test]
+fn test_drain_closes_idle_keep_alive_connections() {
+    let (addr, drain, server) = spawn_hello_server();
+    
+    let mut client = TcpStream::connect(addr).unwrap();
+    client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    client.write_all(b"GET /hello HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+    read_hello_response(&mut client);
+    
+    drain.store(true, std::sync::atomic::Ordering::SeqCst);
+    let mut rest = Vec::new();
+    client.read_to_end(&mut rest).unwrap();
+    assert!(rest.is_empty());
+    server.join().unwrap();
+}
+
+// Requests that outgrow the buffer limits are refused and the connection closed
+#[test]
+fn test_oversized_requests_are_rejected() {
+    use high_performance_server::{Response, Router, ServerConfig, Status};
+    
+    let mut router = Router::new();
+    router.post("/upload", |_| Ok(Response::new(Status::Ok)));
+    let config = ServerConfig::default()
+        .with_max_header_size(1024)
+        .with_max_request_size(16 * 1024)
+        .with_max_buffer_size(8 * 1024);
+    let (addr, drain, server) = spawn_server_with_config(router, config);
+    
+    let read_status = |client: &mut TcpStream| {
+        let mut received = Vec::new();
+        let _ = client.read_to_end(&mut received);
+        String::from_utf8_lossy(&received).lines().next().unwrap_or_default().to_string()
+    };
+    
+    // Headers that never end
+    let mut client = TcpStream::connect(addr).unwrap();
+    client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    client.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n").unwrap();
+    for i in 0..100 {
+        if client.write_all(format!("X-Filler-{}: {}\r\n", i, "a".repeat(64)).as_bytes()).is_err() {
+            break;
+        }
+    }
+    assert_eq!(read_status(&mut client), "HTTP/1.1 431 Request Header Fields Too Large");
+    
+    // A body larger than the buffer may hold
+    let mut client = TcpStream::connect(addr).unwrap();
+    client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    client.write_all(b"POST /upload HTTP/1.1\r\nHost: localhost\r\nContent-Length: 65536\r\n\r\n").unwrap();
+    let _ = client.write_all(&[b'x'; 65536]);
+    assert_eq!(read_status(&mut client), "HTTP/1.1 413 Payload Too Large");
+    
+    drain.store(true, std::sync::atomic::Ordering::SeqCst);
+    server.join().unwrap();
+}
+
+// Route overrides set the body limit and reach handlers as a request extension
+#[test]
+fn test_route_overrides_apply_per_prefix() {
+    use high_performance_server::{Response, RouteOverrides, RouteSettings, Router, ServerConfig, Status};
+    
+    let mut router = Router::new();
+    let echo_limit = |req: &high_performance_server::Request| {
+        let mut response = Response::new(Status::Ok);
+        let limit = RouteSettings::of(req).map_or(0, |settings| settings.max_request_size);
+        response.set_body(limit.to_string().as_bytes());
+        Ok(response)
+    };
+    router.post("/upload", echo_limit);
+    router.post("/upload/avatar", echo_limit);
+    let avatar = RouteOverrides {
+        max_request_size: Some(100),
+        ..RouteOverrides::default()
+    };
+    let config = ServerConfig::default().with_max_request_size(16 * 1024).with_route("/upload/avatar", avatar);
+    let (addr, drain, server) = spawn_server_with_config(router, config);
+    
+    let post = |path: &str, size: usize| {
+        let mut client = TcpStream::connect(addr).unwrap();
+        client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let head = format!("POST {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n", path);
+        let mut request = format!("{}Content-Length: {}\r\n\r\n", head, size).into_bytes();
+        request.resize(request.len() + size, b'x');
+        client.write_all(&request).unwrap();
+        let mut received = Vec::new();
+        let _ = client.read_to_end(&mut received);
+        String::from_utf8_lossy(&received).to_string()
+    };
+    
+    let response = post("/upload", 200);
+    assert!(response.starts_with("HTTP/1.1 200 OK") && response.ends_with("16384"), "{}", response);
+    let response = post("/upload/avatar", 50);
+    assert!(response.starts_with("HTTP/1.1 200 OK") && response.ends_with("100"), "{}", response);
+    assert!(post("/upload/avatar", 200).starts_with("HTTP/1.1 413 Payload Too Large"));
+    // Absolute-form targets get the settings of their path
+    let response = post("http://localhost/upload/avatar", 50);
+    assert!(response.starts_with("HTTP/1.1 200 OK") && response.ends_with("100"), "{}", response);
+    assert!(post("http://localhost/upload/avatar", 200).starts_with("HTTP/1.1 413 Payload Too Large"));
+    
+    drain.store(true, std::sync::atomic::Ordering::SeqCst);
+    server.join().unwrap();
+}
+
+// Injected faults reach the client as resets and short bodies
+#[test]
+fn test_chaos_faults_reach_the_client() {
+    use high_performance_server::{
+        chaos_middleware, Chaos, ChaosConfig, ChaosRule, MiddlewareChain, Response, Router, Status,
+    };
+    use std::sync::Arc;
+    
+    let config = ChaosConfig {
+        enabled: true,
+        rules: vec![
+            ChaosRule {
+                path: "/reset".to_string(),
+                reset_probability: 1.0,
+                ..ChaosRule::default()
+            },
+            ChaosRule {
+                path: "/truncate".to_string(),
+                truncate_probability: 1.0,
+                ..ChaosRule::default()
+            },
+        ],
+        seed: Some(7),
+    };
+    let mut chain = MiddlewareChain::new();
+    chain.add(chaos_middleware(Arc::new(Chaos::new(config).unwrap())));
+    chain.set_handler(|_| {
+        let mut response = Response::new(Status::Ok);
+        response.set_body(b"0123456789");
+        Ok(response)
+    });
+    let chain = Arc::new(chain);
+    let mut router = Router::new();
+    for path in ["/reset", "/truncate"] {
+        let chain = chain.clone();
+        router.get(path, move |req| chain.handle(req));
+    }
+    let (addr, drain, server) = spawn_server(router);
+    
+    let get = |path: &str| {
+        let mut client = TcpStream::connect(addr).unwrap();
+        client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        client.write_all(format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).as_bytes()).unwrap();
+        let mut received = Vec::new();
+        client.read_to_end(&mut received).map(|_| String::from_utf8_lossy(&received).to_string())
+    };
+    
+    let reset = get("/reset").unwrap_err();
+    assert_eq!(reset.kind(), std::io::ErrorKind::ConnectionReset, "{}", reset);
+    let truncated = get("/truncate").unwrap();
+    assert!(truncated.contains("Content-Length: 10\r\n"), "{}", truncated);
+    assert!(truncated.contains("Connection: close\r\n"), "{}", truncated);
+    assert!(truncated.ends_with("\r\n\r\n01234"), "{}", truncated);
+    
+    drain.store(true, std::sync::atomic::Ordering::SeqCst);
+    server.join().unwrap();
+}
+
+// Pipelined requests and bodies split across reads are all answered in order
+#[test]
+fn test_pipelined_requests_and_split_bodies() {
+    let (addr, drain, server) = spawn_hello_server();
+    
+    let mut client = TcpStream::connect(addr).unwrap();
+    client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    client
+        .write_all(b"GET /hello HTTP/1.1\r\nHost: localhost\r\n\r\nGET /hello HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .unwrap();
+    read_hello_response(&mut client);
+    read_hello_response(&mut client);
+    
+    client.write_all(b"GET /hello HTTP/1.1\r\nHost: localhost\r\nContent-Length: 4\r\n\r\nab").unwrap();
+    thread::sleep(Duration::from_millis(50));
+    client.write_all(b"cd").unwrap();
+    read_hello_response(&mut client);
+    
+    drop(client);
+    drain.store(true, std::sync::atomic::Ordering::SeqCst);
+    server.join().unwrap();
+}
+
+// With write batching on, pipelined responses still arrive whole and in order
+#[test]
+fn test_write_batching_keeps_pipelined_responses_in_order() {
+    use high_performance_server::{Response, Router, ServerConfig, Status, WriteBatching};
+    use std::time::Instant;
+    
+    let mut router = Router::new();
+    router.get("/seq", |req| {
+        let mut response = Response::new(Status::Ok);
+        response.set_body(req.get_header("X-Seq").unwrap().as_bytes());
+        Ok(response)
+    });
+    router.get("/large", |_| {
+        let mut response = Response::new(Status::Ok);
+        response.set_body(&[b'z'; 32 * 1024]);
+        Ok(response)
+    });
+    let config = ServerConfig::default().with_write_batching(Some(WriteBatching::default()));
+    let (addr, drain, server) = spawn_server_with_config(router, config);
+    
+    let mut client = TcpStream::connect(addr).unwrap();
+    client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let read_body = |client: &mut TcpStream| {
+        let mut head = Vec::new();
+        let mut byte = [0u8; 1];
+        while !head.ends_with(b"\r\n\r\n") {
+            client.read_exact(&mut byte).unwrap();
+            head.push(byte[0]);
+        }
+        let head = String::from_utf8(head).unwrap();
+        let length = head.split("\r\n").find_map(|line| line.strip_prefix("Content-Length: ")).unwrap();
+        let mut body = vec![0u8; length.parse().unwrap()];
+        client.read_exact(&mut body).unwrap();
+        body
+    };
+    
+    // One response too big to batch lands in the middle of the small ones
+    let mut requests = String::new();
+    for seq in 0..20 {
+        let path = if seq == 10 { "/large" } else { "/seq" };
+        requests.push_str(&format!("GET {} HTTP/1.1\r\nHost: localhost\r\nX-Seq: {}\r\n\r\n", path, seq));
+    }
+    client.write_all(requests.as_bytes()).unwrap();
+    for seq in 0..20 {
+        let body = read_body(&mut client);
+        if seq == 10 {
+            assert_eq!(body.len(), 32 * 1024);
+        } else {
+            assert_eq!(body, seq.to_string().as_bytes());
+        }
+    }
+    
+    // A lone request isn't held back waiting for company
+    let started = Instant::now();
+    client.write_all(b"GET /seq HTTP/1.1\r\nHost: localhost\r\nX-Seq: last\r\n\r\n").unwrap();
+    assert_eq!(read_body(&mut client), b"last");
+    assert!(started.elapsed() < Duration::from_millis(500), "{:?}", started.elapsed());
+    
+    drop(client);
+    drain.store(true, std::sync::atomic::Ordering::SeqCst);
+    server.join().unwrap();
+}
+
+// A cached route goes out with the headers the server adds, batched or not
+#[test]
+fn test_cached_routes_serve_prepared_bytes() {
+    use high_performance_server::{Response, Router, ServerConfig, Status, WriteBatching};
+    
+    let mut router = Router::new();
+    let mut health = Response::new(Status::Ok);
+    health.set_body(b"ok\n");
+    let cached = router.get_cached("/health", health);
+    let config = ServerConfig::default().with_write_batching(Some(WriteBatching::default()));
+    let (addr, drain, server) = spawn_server_with_config(router, config);
+    
+    let mut client = TcpStream::connect(addr).unwrap();
+    client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let request = b"GET /health HTTP/1.1\r\nHost: localhost\r\n\r\n";
+    client.write_all(&[&request[..], &request[..]].concat()).unwrap();
+    for _ in 0..2 {
+        let mut head = Vec::new();
+        let mut byte = [0u8; 1];
+        while !head.ends_with(b"\r\n\r\n") {
+            client.read_exact(&mut byte).unwrap();
+            head.push(byte[0]);
+        }
+        let head = String::from_utf8(head).unwrap();
+        assert!(head.starts_with("HTTP/1.1 200 OK\r\n"), "{}", head);
+        assert!(head.contains("Connection: keep-alive\r\n"), "{}", head);
+        assert_eq!(head.matches("Content-Length: 3\r\n").count(), 1, "{}", head);
+        let mut body = [0u8; 3];
+        client.read_exact(&mut body).unwrap();
+        assert_eq!(&body, b"ok\n");
+    }
+    assert!(cached.is_cached());
+    
+    drop(client);
+    drain.store(true, std::sync::atomic::Ordering::SeqCst);
+    server.join().unwrap();
+}
+
+// A handler error becomes a JSON error response and the connection stays usable
+#[test]
+fn test_handler_errors_become_error_responses() {
+    use high_performance_server::{Router, ServerError};
+    
+    let mut router = Router::new();
+    router.get("/fail", |_| Err(ServerError::Buffer("Request body too large".to_string())));
+    let (addr, drain, server) = spawn_server(router);
+    
+    let mut client = TcpStream::connect(addr).unwrap();
+    client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    client.write_all(b"GET /fail HTTP/1.1\r\nHost: localhost\r\nX-Request-Id: abc\r\n\r\n").unwrap();
+    
+    let mut received = Vec::new();
+    let mut chunk = [0u8; 1024];
+    while !received.ends_with(b"}") {
+        let n = client.read(&mut chunk).unwrap();
+        assert!(n > 0, "connection closed early");
+        received.extend_from_slice(&chunk[..n]);
+    }
+    let response = String::from_utf8(received).unwrap();
+    assert!(response.starts_with("HTTP/1.1 413"));
+    assert!(response.contains("Connection: keep-alive"));
+    assert!(response.ends_with(r#"{"code":"payload_too_large","message":"Buffer error: Request body too large","request_id":"abc"}"#));
+    
+    drop(client);
+    drain.store(true, std::sync::atomic::Ordering::SeqCst);
+    server.join().unwrap();
+}
+
+// A worker at its connection cap leaves new connections in the backlog until one closes
+#[test]
+fn test_connection_cap_holds_back_new_connections() {
+    use high_performance_server::{Response, Router, ServerConfig, Status};
+    
+    let mut router = Router::new();
+    router.get("/", |_| {
+        let mut response = Response::new(Status::Ok);
+        response.set_body(b"ok");
+        Ok(response)
+    });
+    let config = ServerConfig::default().with_max_connections_per_worker(1);
+    let (addr, drain, server) = spawn_server_with_config(router, config);
+    
+    let get = |client: &mut TcpStream| {
+        client.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let mut response = Vec::new();
+        let mut byte = [0u8; 1];
+        while !response.ends_with(b"\r\n\r\nok") {
+            client.read_exact(&mut byte).unwrap();
+            response.push(byte[0]);
+        }
+    };
+    let mut first = TcpStream::connect(addr).unwrap();
+    first.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    get(&mut first);
+    
+    let mut second = TcpStream::connect(addr).unwrap();
+    second.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+    second.set_read_timeout(Some(Duration::from_millis(300))).unwrap();
+    let waiting = second.read(&mut [0u8; 1]).unwrap_err();
+    assert!(matches!(waiting.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut));
+    
+    // Closing the first connection makes room for the second, request and all
+    drop(first);
+    second.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let mut response = Vec::new();
+    let mut byte = [0u8; 1];
+    while !response.ends_with(b"\r\n\r\nok") {
+        second.read_exact(&mut byte).unwrap();
+        response.push(byte[0]);
+    }
+    get(&mut second);
+    
+    drop(second);
+    drain.store(true, std::sync::atomic::Ordering::SeqCst);
+    server.join().unwrap();
+}
+
+#[test]
+fn test_protocol_upgrade_hands_over_connection() {
+    use high_performance_server::{Router, UpgradeResponse};
+    
+    let mut router = Router::new();
+    router.get("/echo", |_| {
+        Ok(UpgradeResponse::new("echo", |mut conn| {
+            // Echo what arrived with the upgrade request, then everything after it
+            conn.stream().set_nonblocking(false).unwrap();
+            let early = conn.buffer().slice().to_vec();
+            conn.stream_mut().write_all(&early).unwrap();
+            let mut reader = conn.stream().try_clone().unwrap();
+            let _ = std::io::copy(&mut reader, conn.stream_mut());
+        })
+        .with_header("X-Protocol-Version", "1")
+        .into())
+    });
+    let (addr, drain, server) = spawn_server(router);
+    
+    // Without an upgrade request the handler's 101 is refused
+    let mut client = TcpStream::connect(addr).unwrap();
+    client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    client.write_all(b"GET /echo HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+    let mut buf = [0; 1024];
+    let n = client.read(&mut buf).unwrap();
+    let response = String::from_utf8_lossy(&buf[..n]).to_string();
+    assert!(response.starts_with("HTTP/1.1 426"), "{}", response);
+    assert!(response.contains("Upgrade: echo\r\n"), "{}", response);
+    
+    let mut client = TcpStream::connect(addr).unwrap();
+    client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    client
+        .write_all(b"GET /echo HTTP/1.1\r\nHost: localhost\r\nConnection: Upgrade\r\nUpgrade: echo\r\n\r\nearly")
+        .unwrap();
+    
+    let mut received = Vec::new();
+    while !received.ends_with(b"early") {
+        let n = client.read(&mut buf).unwrap();
+        assert!(n > 0, "connection closed early");
+        received.extend_from_slice(&buf[..n]);
+    }
+    let head = String::from_utf8_lossy(&received).to_string();
+    assert!(head.starts_with("HTTP/1.1 101 Switching Protocols\r\n"), "{}", head);
+    assert!(head.contains("Connection: Upgrade\r\n"), "{}", head);
+    assert!(head.contains("Upgrade: echo\r\n"), "{}", head);
+    assert!(head.contains("X-Protocol-Version: 1\r\n"), "{}", head);
+    assert!(head.ends_with("\r\n\r\nearly"), "{}", head);
+    
+    // The connection now speaks the new protocol
+    client.write_all(b"ping").unwrap();
+    let mut echoed = [0; 4];
+    client.read_exact(&mut echoed).unwrap();
+    assert_eq!(&echoed, b"ping");
+    
+    drop(client);
+    drain.store(true, std::sync::atomic::Ordering::SeqCst);
+    server.join().unwrap();
+}
+#[test]
+fn test_live_reload_streams_change_events() {
+    use high_performance_server::{Router, StaticFileConfig, add_static_file_routes};
+    
+    let dir = std::env::temp_dir().join(format!("hps-live-reload-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("index.html"), "<p>v1</p>").unwrap();
+    
+    let mut router = Router::new();
+    add_static_file_routes(&mut router, StaticFileConfig {
+        root_dir: dir.clone(),
+        live_reload: true,
+        ..StaticFileConfig::default()
+    });
+    let (addr, drain, server) = spawn_server(router);
+    
+    let mut client = TcpStream::connect(addr).unwrap();
+    client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    client.write_all(b"GET /__livereload HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+    
+    let mut received = Vec::new();
+    let mut buf = [0; 1024];
+    let mut read_until = |client: &mut TcpStream, marker: &str, received: &mut Vec<u8>| {
+        while !String::from_utf8_lossy(received).contains(marker) {
+            let n = client.read(&mut buf).unwrap();
+            assert!(n > 0, "stream closed early");
+            received.extend_from_slice(&buf[..n]);
+        }
+    };
+    read_until(&mut client, ": connected\n\n", &mut received);
+    let head = String::from_utf8_lossy(&received).to_string();
+    assert!(head.starts_with("HTTP/1.1 200 OK\r\n"), "{}", head);
+    assert!(head.contains("Content-Type: text/event-stream\r\n"), "{}", head);
+    assert!(head.contains("Connection: close\r\n"), "{}", head);
+    assert!(!head.contains("Content-Length"), "{}", head);
+    
+    thread::sleep(Duration::from_millis(50));
+    std::fs::write(dir.join("index.html"), "<p>version 2</p>").unwrap();
+    read_until(&mut client, "event: reload\n", &mut received);
+    
+    drop(client);
+    drain.store(true, std::sync::atomic::Ordering::SeqCst);
+    server.join().unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+// A configured Alt-Svc header is added to responses that don't set their own
+#[test]
+fn test_alt_svc_header_is_advertised() {
+    use high_performance_server::{Response, Router, ServerConfig, Status};
+    
+    let mut router = Router::new();
+    router.get("/", |_| Ok(Response::new(Status::Ok)));
+    router.get("/own", |_| {
+        let mut response = Response::new(Status::Ok);
+        response.set_header("Alt-Svc", "clear");
+        Ok(response)
+    });
+    router.get("/lower", |_| {
+        let mut response = Response::new(Status::Ok);
+        response.set_header("alt-svc", "clear");
+        Ok(response)
+    });
+    let config = ServerConfig::default().with_alt_svc("h3=\":443\"; ma=86400");
+    let (addr, drain, server) = spawn_server_with_config(router, config);
+    
+    let head = |path: &str| {
+        let mut client = TcpStream::connect(addr).unwrap();
+        client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        write!(client, "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n", path).unwrap();
+        let mut received = String::new();
+        client.read_to_string(&mut received).unwrap();
+        received
+    };
+    assert!(head("/").contains("\r\nAlt-Svc: h3=\":443\"; ma=86400\r\n"));
+    let own = head("/own");
+    assert!(own.contains("\r\nAlt-Svc: clear\r\n"));
+    assert!(!own.contains("h3="));
+    let lower = head("/lower");
+    assert!(lower.contains("\r\nalt-svc: clear\r\n"));
+    assert!(!lower.contains("h3="));
+    
+    assert!(ServerConfig::default().with_alt_svc("h3=\":443\"\r\nX-Injected: 1").validate().is_err());
+    
+    drain.store(true, std::sync::atomic::Ordering::SeqCst);
+    server.join().unwrap();
+}
+
+// A handler asking to close is honoured whatever case it spells Connection in
+#[test]
+fn test_handler_connection_close_in_any_case() {
+    use high_performance_server::{Response, Router, Status};
+    
+    let mut router = Router::new();
+    router.get("/bye", |_| {
+        let mut response = Response::new(Status::Ok);
+        response.set_header("connection", "close");
+        Ok(response)
+    });
+    let (addr, drain, server) = spawn_server(router);
+    
+    let mut client = TcpStream::connect(addr).unwrap();
+    client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    client.write_all(b"GET /bye HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+    let mut received = String::new();
+    client.read_to_string(&mut received).unwrap();
+    let lowercase = received.to_ascii_lowercase();
+    assert_eq!(lowercase.matches("\r\nconnection:").count(), 1, "{}", received);
+    assert!(received.contains("\r\nConnection: close\r\n"));
+    
+    drain.store(true, std::sync::atomic::Ordering::SeqCst);
+    server.join().unwrap();
 }
\ No newline at end of file
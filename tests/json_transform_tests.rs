@@ -0,0 +1,130 @@
+use high_performance_server::{
+    json_transform_middleware, JsonRule, JsonTransformConfig, Method, MiddlewareChain, Request, Response, Status,
+};
+use serde_json::{json, Value};
+
+/// A chain whose handler echoes the request body back as JSON
+fn chain(config: JsonTransformConfig) -> MiddlewareChain {
+    let mut chain = MiddlewareChain::new();
+    chain.add(json_transform_middleware(config));
+    chain.set_handler(|req| {
+        let mut response = Response::new(Status::Ok);
+        response.set_body(&req.body);
+        response.set_header("Content-Type", "application/json; charset=utf-8");
+        response.set_etag("\"v1\"");
+        Ok(response)
+    });
+    chain
+}
+
+fn json_request(body: &Value) -> Request {
+    let mut request = Request::new(Method::Post, "/api/users");
+    request.set_header("Content-Type", "application/json");
+    request.set_body(&serde_json::to_vec(body).unwrap());
+    request
+}
+
+fn body(response: &Response) -> Value {
+    serde_json::from_slice(&response.body).unwrap()
+}
+
+#[test]
+fn test_rules_rewrite_request_body() {
+    let config: JsonTransformConfig = serde_json::from_value(json!({
+        "request": [
+            {"op": "add", "path": "/meta/source", "value": "gateway"},
+            {"op": "remove", "path": "/debug"},
+            {"op": "rename", "path": "/user/mail", "to": "email"},
+            {"op": "add", "path": "/tags/-", "value": "new"},
+        ]
+    }))
+    .unwrap();
+    let chain = chain(config);
+    
+    let request = json_request(&json!({"user": {"mail": "a@b.c"}, "debug": true, "tags": ["old"]}));
+    let response = chain.handle(&request).unwrap();
+    assert_eq!(
+        body(&response),
+        json!({"user": {"email": "a@b.c"}, "tags": ["old", "new"], "meta": {"source": "gateway"}})
+    );
+    assert_eq!(response.headers["Content-Length"], response.body.len().to_string());
+}
+
+#[test]
+fn test_secrets_are_redacted_in_responses() {
+    let chain = chain(JsonTransformConfig {
+        response: vec![
+            JsonRule::Redact {
+                path: "/users/*/password".to_string(),
+                with: json!("[REDACTED]"),
+            },
+            JsonRule::Redact {
+                path: "/token".to_string(),
+                with: Value::Null,
+            },
+            JsonRule::Redact {
+                path: "/a~1b".to_string(),
+                with: json!("***"),
+            },
+        ],
+        ..JsonTransformConfig::default()
+    });
+    
+    let request = json_request(&json!({
+        "users": [{"name": "ann", "password": "hunter2"}, {"name": "bob"}],
+        "token": "abc",
+        "a/b": 1
+    }));
+    let response = chain.handle(&request).unwrap();
+    assert_eq!(
+        body(&response),
+        json!({
+            "users": [{"name": "ann", "password": "[REDACTED]"}, {"name": "bob"}],
+            "token": null,
+            "a/b": "***"
+        })
+    );
+    assert_eq!(response.headers["Content-Type"], "application/json; charset=utf-8");
+    assert_eq!(response.headers["ETag"], "W/\"v1\"");
+}
+
+#[test]
+fn test_redact_rule_defaults_its_replacement() {
+    let rule: JsonRule = serde_json::from_value(json!({"op": "redact", "path": "/ssn"})).unwrap();
+    let mut value = json!({"ssn": "123-45-6789", "name": "ann"});
+    rule.apply(&mut value);
+    assert_eq!(value, json!({"ssn": "[REDACTED]", "name": "ann"}));
+}
+
+#[test]
+fn test_non_json_and_invalid_bodies_pass_through() {
+    let chain = chain(JsonTransformConfig {
+        request: vec![JsonRule::Remove {
+            path: "/secret".to_string(),
+        }],
+        ..JsonTransformConfig::default()
+    });
+    
+    let mut request = Request::new(Method::Post, "/api/users");
+    request.set_header("Content-Type", "text/plain");
+    request.set_body(b"{\"secret\":1}");
+    assert_eq!(chain.handle(&request).unwrap().body, b"{\"secret\":1}");
+    
+    request.set_header("Content-Type", "application/json");
+    request.set_body(b"{\"secret\":");
+    assert_eq!(chain.handle(&request).unwrap().body, b"{\"secret\":");
+}
+
+#[test]
+fn test_rules_only_apply_under_configured_paths() {
+    let chain = chain(JsonTransformConfig {
+        response: vec![JsonRule::Remove {
+            path: "/secret".to_string(),
+        }],
+        paths: vec!["/internal".to_string()],
+        ..JsonTransformConfig::default()
+    });
+    
+    let response = chain.handle(&json_request(&json!({"secret": 1}))).unwrap();
+    assert_eq!(body(&response), json!({"secret": 1}));
+}
\ No newline at end of file
@@ -0,0 +1,117 @@
+use high_performance_server::{FileKvStore, KvStore, MemoryKvStore, RedisKvStore, RespClient};
+use std::env;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+fn temp_dir(name: &str) -> std::path::PathBuf {
+    let dir = env::temp_dir().join(format!("hps-kv-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+}
+
+/// Run the behaviour every store shares
+fn check_store(store: &dyn KvStore) {
+    assert_eq!(store.get("a").unwrap(), None);
+    store.set("a", b"1", None).unwrap();
+    assert_eq!(store.get("a").unwrap(), Some(b"1".to_vec()));
+    assert_eq!(store.ttl("a").unwrap(), None);
+    
+    // Compare-and-swap only applies over the expected value
+    assert!(!store.cas("a", Some(b"2"), Some(b"3"), None).unwrap());
+    assert!(!store.cas("a", None, Some(b"3"), None).unwrap());
+    assert!(store.cas("a", Some(b"1"), Some(b"3"), None).unwrap());
+    assert_eq!(store.get("a").unwrap(), Some(b"3".to_vec()));
+    assert!(store.cas("b", None, Some(b"new"), None).unwrap());
+    assert!(store.cas("b", Some(b"new"), None, None).unwrap());
+    assert_eq!(store.get("b").unwrap(), None);
+    
+    assert!(store.del("a").unwrap());
+    assert!(!store.del("a").unwrap());
+    
+    let long_key = "k".repeat(300);
+    store.set(&long_key, b"long", None).unwrap();
+    assert_eq!(store.get(&long_key).unwrap(), Some(b"long".to_vec()));
+    
+    // Expired keys behave as if they were never set
+    store.set("brief", b"x", Some(Duration::from_millis(150))).unwrap();
+    let ttl = store.ttl("brief").unwrap().unwrap();
+    assert!(ttl > Duration::ZERO && ttl <= Duration::from_millis(150));
+    thread::sleep(Duration::from_millis(250));
+    assert_eq!(store.get("brief").unwrap(), None);
+    assert_eq!(store.ttl("brief").unwrap(), None);
+    assert!(store.cas("brief", None, Some(b"y"), None).unwrap());
+}
+
+#[test]
+fn test_memory_store() {
+    check_store(&MemoryKvStore::new());
+}
+
+#[test]
+fn test_file_store_survives_reopening() {
+    let dir = temp_dir("reopen");
+    let store = FileKvStore::open(&dir).unwrap();
+    check_store(&store);
+    
+    store.set("kept", b"value\nwith lines", None).unwrap();
+    drop(store);
+    let reopened = FileKvStore::open(&dir).unwrap();
+    assert_eq!(reopened.get("kept").unwrap(), Some(b"value\nwith lines".to_vec()));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Answer commands with `replies` in turn, passing the commands to the receiver
+fn scripted_redis(replies: &'static [&'static str]) -> (String, mpsc::Receiver<Vec<Vec<u8>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut stream = listener.incoming().next().unwrap().unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        for reply in replies {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            let count: usize = line.trim_end()[1..].parse().unwrap();
+            let mut args = Vec::new();
+            for _ in 0..count {
+                line.clear();
+                reader.read_line(&mut line).unwrap();
+                let length: usize = line.trim_end()[1..].parse().unwrap();
+                let mut arg = vec![0u8; length + 2];
+                reader.read_exact(&mut arg).unwrap();
+                arg.truncate(length);
+                args.push(arg);
+            }
+            tx.send(args).unwrap();
+            stream.write_all(reply.as_bytes()).unwrap();
+        }
+    });
+    (address, rx)
+}
+
+#[test]
+fn test_redis_store_commands() {
+    let (address, commands) = scripted_redis(&[":-1\r\n", ":2500\r\n", ":-2\r\n", ":0\r\n", ":1\r\n"]);
+    let store = RedisKvStore::with_client(Arc::new(RespClient::new(&address))).with_prefix("app:");
+    
+    assert_eq!(store.ttl("forever").unwrap(), None);
+    assert_eq!(store.ttl("soon").unwrap(), Some(Duration::from_millis(2500)));
+    assert_eq!(store.ttl("missing").unwrap(), None);
+    assert!(!store.cas("k", None, Some(b"v"), Some(Duration::from_secs(3))).unwrap());
+    assert!(store.cas("k", Some(b"v"), None, None).unwrap());
+    
+    let sent: Vec<Vec<String>> = commands
+        .iter()
+        .take(5)
+        .map(|args| args.iter().map(|arg| String::from_utf8_lossy(arg).to_string()).collect())
+        .collect();
+    assert_eq!(sent[0], ["PTTL", "app:forever"]);
+    assert_eq!(sent[3][0], "EVAL");
+    assert_eq!(&sent[3][2..], ["1", "app:k", "0", "", "1", "v", "3000"]);
+    assert_eq!(&sent[4][2..], ["1", "app:k", "1", "v", "0", "", "0"]);
+}
\ No newline at end of file
@@ -0,0 +1,42 @@
+use high_performance_server::buffer::Buffer;
+use high_performance_server::connection::Connection;
+use high_performance_server::leaks::{self, Tracked};
+use high_performance_server::metrics::MetricsRegistry;
+use high_performance_server::{HttpParser, MemoryManager};
+use std::net::{TcpListener, TcpStream};
+
+// The counts are process-wide, so everything is checked in one test
+#[test]
+fn test_instances_are_counted_until_dropped() {
+    let baseline = leaks::snapshot();
+    
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let _client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (stream, peer) = listener.accept().unwrap();
+    let connection = Connection::new(stream, peer, 1).unwrap();
+    let parser = HttpParser::new();
+    let buffer = Buffer::new(64);
+    let memory = MemoryManager::new();
+    let handle = memory.allocate(100).unwrap();
+    
+    // A connection owns a parser and two buffers of its own
+    let live = |kind| leaks::counts(kind).live() - baseline.iter().find(|(k, _)| *k == kind).unwrap().1.live();
+    assert_eq!(live(Tracked::Connection), 1);
+    assert_eq!(live(Tracked::HttpParser), 2);
+    assert_eq!(live(Tracked::Buffer), 3);
+    assert_eq!(live(Tracked::MemoryHandle), 1);
+    
+    let error = leaks::check_balanced(&baseline).unwrap_err().to_string();
+    assert!(error.contains("1 connection") && error.contains("3 buffer"), "{}", error);
+    
+    let registry = MetricsRegistry::new();
+    leaks::publish(&registry);
+    let created = registry.gauge("instances.connection.created").value();
+    assert_eq!(created, leaks::counts(Tracked::Connection).created);
+    assert_eq!(registry.gauge("instances.memory_handle.live").value(), leaks::counts(Tracked::MemoryHandle).live());
+    
+    drop((connection, parser, buffer, handle));
+    leaks::check_balanced(&baseline).unwrap();
+    let counts = leaks::counts(Tracked::Connection);
+    assert_eq!((counts.created, counts.destroyed), (created, created));
+}
\ No newline at end of file
@@ -0,0 +1,80 @@
+use high_performance_server::{Lifecycle, ServerError};
+use std::sync::{Arc, Mutex};
+
+fn recorder() -> (Arc<Mutex<Vec<String>>>, impl Fn(&str) + Clone + Send + Sync + 'static) {
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let writer = log.clone();
+    (log, move |entry: &str| writer.lock().unwrap().push(entry.to_string()))
+}
+
+#[test]
+fn test_hooks_run_in_order() {
+    let (log, record) = recorder();
+    let (a, b, c, d) = (record.clone(), record.clone(), record.clone(), record);
+    let lifecycle = Lifecycle::new()
+        .on_start("cache", move || {
+            a("warm cache");
+            Ok(())
+        })
+        .on_worker_start("pool", move |worker| {
+            b(&format!("open pool {}", worker));
+            Ok(())
+        })
+        .on_shutdown("pool", move || {
+            c("close pools");
+            Ok(())
+        })
+        .on_shutdown("state", move || {
+            d("flush state");
+            Ok(())
+        });
+    
+    lifecycle.start().unwrap();
+    lifecycle.start_worker(0).unwrap();
+    lifecycle.start_worker(1).unwrap();
+    lifecycle.shutdown().unwrap();
+    assert_eq!(*log.lock().unwrap(), ["warm cache", "open pool 0", "open pool 1", "flush state", "close pools"]);
+}
+
+#[test]
+fn test_failures_become_startup_errors() {
+    let (log, record) = recorder();
+    let lifecycle = Lifecycle::new()
+        .on_start("database", || Err(ServerError::Config("no database URL".to_string())))
+        .on_start("after", move || {
+            record("after");
+            Ok(())
+        })
+        .on_worker_start("pinning", |worker| match worker {
+            0 => Ok(()),
+            _ => panic!("no core for worker"),
+        });
+    
+    match lifecycle.start() {
+        Err(ServerError::Startup(message)) => {
+            assert!(message.contains("database") && message.contains("no database URL"), "{}", message);
+        }
+        other => panic!("expected a startup error, got {:?}", other),
+    }
+    assert!(log.lock().unwrap().is_empty());
+    
+    assert!(lifecycle.start_worker(0).is_ok());
+    match lifecycle.start_worker(3) {
+        Err(ServerError::Startup(message)) => assert!(message.contains("worker 3") && message.contains("no core")),
+        other => panic!("expected a startup error, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_every_shutdown_hook_runs() {
+    let (log, record) = recorder();
+    let lifecycle = Lifecycle::new()
+        .on_shutdown("last", move || {
+            record("last");
+            Ok(())
+        })
+        .on_shutdown("broken", || Err(ServerError::Config("disk full".to_string())));
+    
+    assert!(matches!(lifecycle.shutdown(), Err(ServerError::Startup(message)) if message.contains("disk full")));
+    assert_eq!(*log.lock().unwrap(), ["last"]);
+}
\ No newline at end of file
@@ -0,0 +1,70 @@
+use flate2::read::GzDecoder;
+use high_performance_server::log_file::{request_reopen, RotatingFile};
+use std::env;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+
+fn test_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("hps-log-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn test_size_rotation_and_retention() {
+    let dir = test_dir("size");
+    let path = dir.join("access.log");
+    
+    let mut log = RotatingFile::open(&path).unwrap().with_max_size(10).with_keep(2);
+    for line in ["first\n", "second\n", "third\n", "fourth\n"] {
+        log.write_all(line.as_bytes()).unwrap();
+    }
+    log.flush().unwrap();
+    
+    assert_eq!(fs::read_to_string(&path).unwrap(), "fourth\n");
+    assert_eq!(fs::read_to_string(dir.join("access.log.1")).unwrap(), "third\n");
+    assert_eq!(fs::read_to_string(dir.join("access.log.2")).unwrap(), "second\n");
+    assert!(!dir.join("access.log.3").exists());
+    
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_compressed_rotation() {
+    let dir = test_dir("gzip");
+    let path = dir.join("server.log");
+    
+    let mut log = RotatingFile::open(&path).unwrap().with_max_size(8).with_compression(true);
+    log.write_all(b"old line\n").unwrap();
+    log.write_all(b"new line\n").unwrap();
+    
+    let mut decoded = String::new();
+    GzDecoder::new(fs::File::open(dir.join("server.log.1.gz")).unwrap())
+        .read_to_string(&mut decoded)
+        .unwrap();
+    assert_eq!(decoded, "old line\n");
+    assert!(!dir.join("server.log.1").exists());
+    
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_reopen_after_external_move() {
+    let dir = test_dir("reopen");
+    let path = dir.join("server.log");
+    
+    let mut log = RotatingFile::open(&path).unwrap();
+    log.write_all(b"before\n").unwrap();
+    
+    // Simulate logrotate moving the file away, then signalling us
+    fs::rename(&path, dir.join("moved.log")).unwrap();
+    request_reopen();
+    log.write_all(b"after\n").unwrap();
+    
+    assert_eq!(fs::read_to_string(&path).unwrap(), "after\n");
+    assert_eq!(fs::read_to_string(dir.join("moved.log")).unwrap(), "before\n");
+    
+    let _ = fs::remove_dir_all(&dir);
+}
\ No newline at end of file
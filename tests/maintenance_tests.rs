@@ -0,0 +1,124 @@
+use high_performance_server::{
+    mount_maintenance, ConnectionAcceptor, EventLoop, MaintenanceConfig, MaintenanceMode, Method, Request, Response,
+    Router, ServerConfig, Status,
+};
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+fn hello_router() -> Router {
+    let mut router = Router::new();
+    router.get("/hello", |_| {
+        let mut response = Response::new(Status::Ok);
+        response.set_body(b"hello");
+        Ok(response)
+    });
+    router
+}
+
+#[test]
+fn test_maintenance_mode_answers_503() {
+    let maintenance = MaintenanceMode::new(MaintenanceConfig {
+        enabled: true,
+        retry_after_secs: Some(120),
+        body: "Back soon\n".to_string(),
+        exempt_paths: vec!["/admin".to_string()],
+    });
+    
+    let response = maintenance.check(&Request::new(Method::Get, "/hello?x=1")).unwrap();
+    assert_eq!(response.status, Status::ServiceUnavailable);
+    assert_eq!(response.headers.get("Retry-After").unwrap(), "120");
+    assert_eq!(response.body, b"Back soon\n");
+    
+    // Exempt prefixes match whole path segments
+    assert!(maintenance.check(&Request::new(Method::Get, "/admin")).is_none());
+    assert!(maintenance.check(&Request::new(Method::Get, "/admin/routes")).is_none());
+    assert!(maintenance.check(&Request::new(Method::Get, "/administrator")).is_some());
+    
+    maintenance.set_retry_after(None);
+    let response = maintenance.check(&Request::new(Method::Get, "/hello")).unwrap();
+    assert!(!response.headers.contains_key("Retry-After"));
+    
+    maintenance.disable();
+    assert!(maintenance.check(&Request::new(Method::Get, "/hello")).is_none());
+}
+
+#[test]
+fn test_maintenance_admin_route() {
+    let maintenance = Arc::new(MaintenanceMode::default());
+    let mut router = hello_router();
+    mount_maintenance(&mut router, "/admin/maintenance", maintenance.clone());
+    
+    let mut enable = Request::new(Method::Put, "/admin/maintenance");
+    enable.body = br#"{"enabled": true, "retry_after_secs": 600, "body": "Upgrading\n"}"#.to_vec();
+    let response = router.handle_request(&enable).unwrap();
+    assert_eq!(response.status, Status::Ok);
+    let settings: MaintenanceConfig = serde_json::from_slice(&response.body).unwrap();
+    assert!(settings.enabled);
+    assert_eq!(settings.retry_after_secs, Some(600));
+    assert!(maintenance.is_enabled());
+    
+    // Fields left out are unchanged, and null drops the header
+    let mut update = Request::new(Method::Put, "/admin/maintenance");
+    update.body = br#"{"retry_after_secs": null}"#.to_vec();
+    router.handle_request(&update).unwrap();
+    let settings = maintenance.settings();
+    assert_eq!(settings.retry_after_secs, None);
+    assert_eq!(settings.body, "Upgrading\n");
+    assert!(settings.enabled);
+    
+    // The admin route itself stays reachable
+    let status = Request::new(Method::Get, "/admin/maintenance");
+    assert!(maintenance.check(&status).is_none());
+    
+    let mut invalid = Request::new(Method::Put, "/admin/maintenance");
+    invalid.body = b"{".to_vec();
+    assert_eq!(router.handle_request(&invalid).unwrap().status, Status::BadRequest);
+}
+
+fn get(addr: std::net::SocketAddr, path: &str) -> String {
+    let mut client = TcpStream::connect(addr).unwrap();
+    client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    write!(client, "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n", path).unwrap();
+    let mut response = String::new();
+    client.read_to_string(&mut response).unwrap();
+    response
+}
+
+#[test]
+fn test_event_loop_honours_maintenance_mode() {
+    let maintenance = Arc::new(MaintenanceMode::default());
+    let mut router = hello_router();
+    mount_maintenance(&mut router, "/admin/maintenance", maintenance.clone());
+    
+    let acceptor = Arc::new(ConnectionAcceptor::new("127.0.0.1:0").unwrap());
+    let addr = acceptor.local_addr().unwrap();
+    let drain = Arc::new(AtomicBool::new(false));
+    let drain_clone = drain.clone();
+    let shared = maintenance.clone();
+    let server = thread::spawn(move || {
+        let mut event_loop = EventLoop::with_config(0, acceptor, ServerConfig::default());
+        event_loop.set_router(Arc::new(router));
+        event_loop.set_maintenance(shared);
+        event_loop.set_drain_signal(drain_clone);
+        event_loop.run().unwrap();
+    });
+    
+    assert!(get(addr, "/hello").starts_with("HTTP/1.1 200"));
+    
+    maintenance.enable();
+    let response = get(addr, "/hello");
+    assert!(response.starts_with("HTTP/1.1 503"));
+    assert!(response.contains("Retry-After: 300\r\n"));
+    assert!(get(addr, "/admin/maintenance").starts_with("HTTP/1.1 200"));
+    
+    // Service resumes on the same listener
+    maintenance.disable();
+    assert!(get(addr, "/hello").ends_with("hello"));
+    
+    drain.store(true, Ordering::SeqCst);
+    server.join().unwrap();
+}
\ No newline at end of file
@@ -142,4 +142,16 @@ fn test_create_buffer() {
     for i in 0..10 {
         assert_eq!(data[i], i as u8);
     }
+}
+
+#[test]
+fn test_memory_manager_pool_stats() {
+    let manager = MemoryManager::new();
+    let _small = manager.allocate(10).unwrap();
+    let _large = manager.allocate(1000).unwrap();
+    
+    let stats = manager.pool_stats();
+    let in_use: Vec<_> = stats.iter().filter(|pool| pool.in_use > 0).map(|pool| pool.size_class).collect();
+    assert_eq!(in_use, vec![16, 1024]);
+    assert_eq!(stats.iter().map(|pool| pool.in_use_bytes()).sum::<usize>(), 16 + 1024);
 }
\ No newline at end of file
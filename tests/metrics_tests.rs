@@ -1,7 +1,12 @@
-use high_performance_server::metrics::{Counter, Histogram, MetricsCollector, MetricsRegistry, Timer};
+use high_performance_server::metrics::{
+    Counter, EventLoopMetrics, Histogram, MetricsCollector, MetricsRegistry, Timer, WindowedCounter, WindowedHistogram,
+};
+use high_performance_server::http::Method;
+use high_performance_server::timeline::{Phase, RequestTimeline};
+use high_performance_server::WorkerLoad;
 use std::sync::Arc;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 #[test]
 fn test_counter() {
@@ -82,6 +87,34 @@ fn test_histogram() {
     assert_eq!(buckets[4].1, 5);
 }
 
+#[test]
+fn test_histogram_concurrent() {
+    // More threads than shards, so some record into the same one
+    let histogram = Arc::new(Histogram::new(&[10.0, 100.0]));
+    let handles: Vec<_> = (1..=80)
+        .map(|thread_index| {
+            let histogram = histogram.clone();
+            thread::spawn(move || {
+                for _ in 0..100 {
+                    histogram.record(thread_index as f64);
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    
+    assert_eq!(histogram.count(), 8000);
+    assert_eq!(histogram.sum(), 100 * (1..=80).sum::<usize>());
+    assert_eq!(histogram.min(), 1);
+    assert_eq!(histogram.max(), 80);
+    assert_eq!(histogram.buckets(), [(10.0, 1000), (100.0, 8000)]);
+    
+    let counter = Counter::new(7);
+    assert_eq!(counter.value(), 7);
+}
+
 #[test]
 fn test_exponential_histogram() {
     let histogram = Histogram::exponential(1.0, 2.0, 5);
@@ -196,6 +229,39 @@ fn test_metrics_collector() {
     assert!(metrics_str.contains("request_time.GET: count=1"));
 }
 
+#[test]
+fn test_request_handles_share_registry_metrics() {
+    let collector = MetricsCollector::new();
+    let registry = collector.registry();
+    let requests = collector.requests();
+    
+    requests.record(Method::Get, 200);
+    requests.record(Method::Get, 200);
+    collector.record_request("DELETE", 503);
+    // Outside the pre-registered range, or not a method, it still counts
+    requests.record(Method::Patch, 999);
+    collector.record_request("BREW", 418);
+    requests.record_batched();
+    
+    assert_eq!(registry.counter("requests.GET.200").value(), 2);
+    assert_eq!(registry.counter("requests.DELETE.503").value(), 1);
+    assert_eq!(registry.counter("requests.PATCH.999").value(), 1);
+    assert_eq!(registry.counter("requests.BREW.418").value(), 1);
+    assert_eq!(registry.counter("responses.batched").value(), 1);
+    assert_eq!(registry.windowed_counter("requests").total(Duration::from_secs(60)), 5);
+    
+    let mut timeline = RequestTimeline::new();
+    for phase in Phase::ALL {
+        timeline.mark(phase);
+    }
+    requests.record_timeline(&timeline);
+    let histograms = registry.histograms();
+    for name in RequestTimeline::SPAN_NAMES {
+        let (_, histogram) = histograms.iter().find(|(n, _)| *n == format!("request_phase.{}", name)).unwrap();
+        assert_eq!(histogram.count(), 1, "{}", name);
+    }
+}
+
 #[test]
 fn test_registry_concurrent_access() {
     let registry = Arc::new(MetricsRegistry::new());
@@ -231,4 +297,112 @@ fn test_registry_concurrent_access() {
         let histogram = registry.exponential_histogram(&histogram_name, 1.0, 2.0, 3);
         assert_eq!(histogram.count(), 1);
     }
+}
+
+#[test]
+fn test_gauge() {
+    let registry = MetricsRegistry::new();
+    let gauge = registry.gauge("connections.active");
+    
+    gauge.increment(3);
+    gauge.decrement(1);
+    assert_eq!(gauge.value(), 2);
+    
+    // Decrementing past zero saturates
+    gauge.decrement(5);
+    assert_eq!(gauge.value(), 0);
+    
+    gauge.set(7);
+    assert_eq!(registry.gauge("connections.active").value(), 7);
+    assert!(registry.format().contains("connections.active: 7"));
+}
+
+#[test]
+fn test_worker_load_skip_and_skew() {
+    let load = WorkerLoad::new(3);
+    
+    for _ in 0..6 {
+        load.connection_opened(0);
+    }
+    load.connection_opened(1);
+    
+    // Mean is 7/3, so worker 0 is well above it and should back off
+    assert!(load.should_skip_accept(0));
+    assert!(!load.should_skip_accept(1));
+    assert!(!load.should_skip_accept(2));
+    assert_eq!(load.skew(), 6);
+    
+    let registry = MetricsRegistry::new();
+    load.publish(&registry);
+    assert_eq!(registry.gauge("workers.0.active_connections").value(), 6);
+    assert_eq!(registry.gauge("workers.0.skipped_accepts").value(), 1);
+    assert_eq!(registry.gauge("workers.load_skew").value(), 6);
+    
+    load.connection_closed(0);
+    assert_eq!(load.active(0), 5);
+}
+
+#[test]
+fn test_windowed_counter_rates() {
+    let counter = WindowedCounter::new();
+    let start = Instant::now();
+    
+    // 120 events in the first second, 60 more four minutes later
+    counter.increment_at(120, start);
+    counter.increment_at(60, start + Duration::from_secs(240));
+    
+    let now = start + Duration::from_secs(240);
+    assert_eq!(counter.total_at(Duration::from_secs(60), now), 60);
+    assert_eq!(counter.total_at(Duration::from_secs(300), now), 180);
+    assert_eq!(counter.rate_at(Duration::from_secs(60), now), 1.0);
+    
+    // Everything has aged out of the window 20 minutes later
+    let later = start + Duration::from_secs(1200);
+    assert_eq!(counter.total_at(Duration::from_secs(900), later), 0);
+}
+
+#[test]
+fn test_windowed_histogram_percentiles() {
+    let histogram = WindowedHistogram::new();
+    let start = Instant::now();
+    
+    for _ in 0..90 {
+        histogram.record_at(100.0, start);
+    }
+    for _ in 0..10 {
+        histogram.record_at(5000.0, start);
+    }
+    
+    let snapshot = histogram.snapshot_at(Duration::from_secs(60), start);
+    assert_eq!(snapshot.count, 100);
+    assert_eq!(snapshot.p50, 128.0);
+    assert_eq!(snapshot.p90, 128.0);
+    assert_eq!(snapshot.p99, 8192.0);
+    
+    // Old samples no longer count once they leave the window
+    let later = start + Duration::from_secs(120);
+    histogram.record_at(1.0, later);
+    let snapshot = histogram.snapshot_at(Duration::from_secs(60), later);
+    assert_eq!(snapshot.count, 1);
+    assert_eq!(snapshot.p99, 1.0);
+}
+
+#[test]
+fn test_event_loop_metrics() {
+    let registry = MetricsRegistry::new();
+    let loop_metrics = EventLoopMetrics::new(&registry, 2);
+    
+    loop_metrics.record_iteration(Duration::from_micros(150), Duration::from_micros(100), 4);
+    loop_metrics.set_queue_depth(10, 3, 4096);
+    
+    let iteration = registry.exponential_histogram("workers.2.loop_iteration_us", 1.0, 2.0, 24);
+    assert_eq!(iteration.count(), 1);
+    assert_eq!(iteration.sum(), 150);
+    
+    let events = registry.exponential_histogram("workers.2.events_per_wake", 1.0, 2.0, 11);
+    assert_eq!(events.sum(), 4);
+    
+    assert_eq!(registry.gauge("workers.2.open_connections").value(), 10);
+    assert_eq!(registry.gauge("workers.2.pending_write_connections").value(), 3);
+    assert_eq!(registry.gauge("workers.2.pending_write_bytes").value(), 4096);
 }
\ No newline at end of file
@@ -0,0 +1,153 @@
+use high_performance_server::http_client::HttpUrl;
+use high_performance_server::metrics::MetricsRegistry;
+use high_performance_server::{
+    mount_proxy, ConnectionAcceptor, EventLoop, Method, Mirror, MirrorConfig, Proxy, Request, Router,
+};
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Read a request head and `Content-Length` body off `stream`
+fn read_request(stream: &mut impl Read) -> String {
+    let mut request = Vec::new();
+    let mut byte = [0u8; 1];
+    while !request.ends_with(b"\r\n\r\n") && stream.read(&mut byte).unwrap_or(0) == 1 {
+        request.push(byte[0]);
+    }
+    let head = String::from_utf8_lossy(&request).to_string();
+    let length = head
+        .lines()
+        .find_map(|line| line.strip_prefix("Content-Length: "))
+        .map_or(0, |length| length.trim().parse::<usize>().unwrap());
+    let mut body = vec![0u8; length];
+    stream.read_exact(&mut body).unwrap();
+    head + &String::from_utf8_lossy(&body)
+}
+
+/// Answer every request with `response` after `delay`, passing the requests to the receiver
+fn upstream(response: &'static str, delay: Duration) -> (String, mpsc::Receiver<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let tx = tx.clone();
+            thread::spawn(move || {
+                let _ = tx.send(read_request(&mut stream));
+                thread::sleep(delay);
+                let _ = stream.write_all(response.as_bytes());
+            });
+        }
+    });
+    (url, rx)
+}
+
+const OK: &str = "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok";
+
+#[test]
+fn test_mirrors_an_even_share() {
+    let (shadow, copies) = upstream(OK, Duration::ZERO);
+    let registry = Arc::new(MetricsRegistry::new());
+    let config = MirrorConfig {
+        upstream: shadow,
+        percent: 25.0,
+        ..MirrorConfig::default()
+    };
+    let mirror = Mirror::with_metrics(config, registry.clone()).unwrap();
+    
+    let request = Request::new(Method::Get, "/items");
+    let picked: Vec<bool> = (0..8).map(|_| mirror.mirror(&request)).collect();
+    assert_eq!(picked, vec![false, false, false, true, false, false, false, true]);
+    
+    for _ in 0..2 {
+        let copy = copies.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(copy.starts_with("GET /items HTTP/1.1\r\n"), "{}", copy);
+    }
+    assert!(copies.recv_timeout(Duration::from_millis(200)).is_err());
+    
+    let start = Instant::now();
+    while registry.counter("mirror.sent").value() < 2 && start.elapsed() < Duration::from_secs(5) {
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(registry.counter("mirror.sent").value(), 2);
+}
+
+#[test]
+fn test_proxy_copies_requests_without_waiting_for_the_shadow() {
+    let (primary, _) = upstream("HTTP/1.1 204 No Content\r\n\r\n", Duration::ZERO);
+    let (shadow, copies) = upstream(OK, Duration::from_secs(2));
+    let mirror = Mirror::new(MirrorConfig {
+        upstream: format!("{}/shadow", shadow),
+        ..MirrorConfig::default()
+    })
+    .unwrap();
+    let proxy = Proxy::new(HttpUrl::parse(&primary).unwrap()).with_mirror(Arc::new(mirror));
+    let mut router = Router::new();
+    mount_proxy(&mut router, "/orders", Arc::new(proxy));
+    let acceptor = Arc::new(ConnectionAcceptor::new("127.0.0.1:0").unwrap());
+    let addr = acceptor.local_addr().unwrap();
+    let drain = Arc::new(AtomicBool::new(false));
+    let drain_clone = drain.clone();
+    thread::spawn(move || {
+        let mut event_loop = EventLoop::new(0, acceptor);
+        event_loop.set_router(Arc::new(router));
+        event_loop.set_drain_signal(drain_clone);
+        event_loop.run().unwrap();
+    });
+    
+    let start = Instant::now();
+    let mut client = TcpStream::connect(addr).unwrap();
+    client
+        .write_all(b"POST /orders HTTP/1.1\r\nHost: a\r\nX-Trace: abc\r\nContent-Length: 8\r\n\r\n{\"id\":1}")
+        .unwrap();
+    let mut response = Vec::new();
+    client.read_to_end(&mut response).unwrap();
+    let response = String::from_utf8_lossy(&response);
+    assert!(response.starts_with("HTTP/1.1 204 No Content\r\n"), "{}", response);
+    assert!(start.elapsed() < Duration::from_secs(1));
+    
+    let copy = copies.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert!(copy.starts_with("POST /shadow/orders HTTP/1.1\r\n"), "{}", copy);
+    assert!(copy.contains("x-trace: abc\r\n"), "{}", copy);
+    assert!(copy.ends_with("\r\n\r\n{\"id\":1}"), "{}", copy);
+    drain.store(true, Ordering::SeqCst);
+}
+
+#[test]
+fn test_full_queue_drops_copies() {
+    let (shadow, _copies) = upstream(OK, Duration::from_secs(2));
+    let registry = Arc::new(MetricsRegistry::new());
+    let config = MirrorConfig {
+        upstream: shadow,
+        queue_size: 1,
+        threads: 1,
+        ..MirrorConfig::default()
+    };
+    let mirror = Mirror::with_metrics(config, registry.clone()).unwrap();
+    
+    let request = Request::new(Method::Get, "/");
+    let queued = (0..10).filter(|_| mirror.mirror(&request)).count();
+    assert!(queued < 10);
+    assert_eq!(registry.counter("mirror.dropped").value(), 10 - queued);
+}
+
+#[test]
+fn test_invalid_config_is_rejected() {
+    let config = MirrorConfig {
+        upstream: "http://127.0.0.1:1".to_string(),
+        percent: 150.0,
+        ..MirrorConfig::default()
+    };
+    assert!(Mirror::new(config).is_err());
+    
+    let config = MirrorConfig {
+        upstream: "https://shadow.example".to_string(),
+        ..MirrorConfig::default()
+    };
+    assert!(Mirror::new(config).is_err());
+}
\ No newline at end of file
@@ -0,0 +1,95 @@
+use high_performance_server::http::{Method, Request, Response, Status};
+use high_performance_server::openapi::{self, schema_of, OpenApiInfo, ParamLocation, RouteDoc};
+use high_performance_server::router::{RouteTable, Router};
+use serde::Serialize;
+use serde_json::json;
+use std::sync::Arc;
+
+#[derive(Serialize)]
+struct User {
+    id: u64,
+    name: String,
+    tags: Vec<String>,
+}
+
+fn example_user() -> User {
+    User {
+        id: 1,
+        name: "ada".to_string(),
+        tags: vec!["admin".to_string()],
+    }
+}
+
+#[test]
+fn test_schema_of_example() {
+    let schema = schema_of(&example_user());
+    assert_eq!(schema["type"], "object");
+    assert_eq!(schema["properties"]["id"]["type"], "integer");
+    assert_eq!(schema["properties"]["name"]["type"], "string");
+    assert_eq!(schema["properties"]["tags"]["items"]["type"], "string");
+}
+
+#[test]
+fn test_generate_document() {
+    let mut router = Router::new();
+    router
+        .get("/users/:id", |_| Ok(Response::new(Status::Ok)))
+        .describe(
+            RouteDoc::new("Fetch a user")
+                .with_tag("users")
+                .with_param("id", ParamLocation::Path, json!({ "type": "integer" }), "User ID")
+                .with_response_example(200, "The user", &example_user())
+                .with_response(404, "No such user"),
+        );
+    router.post("/users", |_| Ok(Response::new(Status::Created)));
+    router
+        .get("/internal", |_| Ok(Response::new(Status::Ok)))
+        .describe(RouteDoc::hidden());
+    
+    let document = openapi::generate(&router, &OpenApiInfo::new("Users", "1.0"));
+    assert_eq!(document["openapi"], "3.0.3");
+    assert_eq!(document["info"]["title"], "Users");
+    
+    let get = &document["paths"]["/users/{id}"]["get"];
+    assert_eq!(get["summary"], "Fetch a user");
+    assert_eq!(get["parameters"][0]["name"], "id");
+    assert_eq!(get["parameters"][0]["schema"]["type"], "integer");
+    assert_eq!(
+        get["responses"]["200"]["content"]["application/json"]["schema"]["properties"]["name"]["type"],
+        "string"
+    );
+    assert!(get["responses"]["404"].is_object());
+    
+    // Undocumented routes still appear, hidden ones don't
+    assert!(document["paths"]["/users"]["post"].is_object());
+    assert!(document["paths"].get("/internal").is_none());
+}
+
+#[test]
+fn test_serve_openapi_routes() {
+    let mut router = Router::new();
+    router.get("/health", |_| Ok(Response::new(Status::Ok)));
+    router.serve_openapi(OpenApiInfo::new("Service", "2.0"));
+    
+    let response = router.handle_request(&Request::new(Method::Get, "/openapi.json")).unwrap();
+    assert_eq!(response.headers.get("Content-Type").unwrap(), "application/json");
+    let document: serde_json::Value = serde_json::from_slice(&response.body).unwrap();
+    assert!(document["paths"]["/health"]["get"].is_object());
+    assert!(document["paths"].get("/openapi.json").is_none());
+    
+    let page = router.handle_request(&Request::new(Method::Get, "/docs")).unwrap();
+    let html = String::from_utf8(page.body).unwrap();
+    assert!(html.contains("SwaggerUIBundle"));
+    assert!(html.contains("/openapi.json"));
+}
+
+#[test]
+fn test_route_table_openapi_is_live() {
+    let table = Arc::new(RouteTable::new(Router::new()));
+    RouteTable::mount_openapi(&table, OpenApiInfo::new("Live", "1.0"));
+    table.add_route(Method::Get, "/added", |_| Ok(Response::new(Status::Ok)));
+    
+    let response = table.handle_request(&Request::new(Method::Get, "/openapi.json")).unwrap();
+    let document: serde_json::Value = serde_json::from_slice(&response.body).unwrap();
+    assert!(document["paths"]["/added"]["get"].is_object());
+}
\ No newline at end of file
@@ -0,0 +1,221 @@
+use high_performance_server::connection::Connection;
+use high_performance_server::{
+    ConnectionAcceptor, EventLoop, EventPoller, MetricsCollector, PollerFault, Response, Router, ServerConfig,
+    ServerError, Status,
+};
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// One test closes an event loop's poller behind its back, so none may run alongside it
+static SERIAL: Mutex<()> = Mutex::new(());
+
+fn connection_pair() -> (Connection, TcpStream) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (stream, peer_addr) = listener.accept().unwrap();
+    stream.set_nonblocking(true).unwrap();
+    
+    (Connection::new(stream, peer_addr, 7).unwrap(), client)
+}
+
+fn errno(code: i32) -> ServerError {
+    ServerError::Io(io::Error::from_raw_os_error(code))
+}
+
+/// The descriptors of every epoll instance this process has open
+fn epoll_fds() -> HashSet<i32> {
+    fs::read_dir("/proc/self/fd")
+        .unwrap()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| fs::read_link(entry.path()).is_ok_and(|target| target.to_string_lossy().contains("eventpoll")))
+        .filter_map(|entry| entry.file_name().to_str()?.parse().ok())
+        .collect()
+}
+
+#[test]
+fn test_errors_are_classified_and_the_poller_replaced() {
+    let _serial = SERIAL.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    
+    assert_eq!(PollerFault::classify(&errno(libc::EBADF)), PollerFault::ClosedDescriptor);
+    assert_eq!(PollerFault::classify(&errno(libc::ENOSPC)), PollerFault::Exhausted);
+    assert_eq!(PollerFault::classify(&ServerError::EventLoop("boom".to_string())), PollerFault::Other);
+    
+    let mut poller = EventPoller::new(16).unwrap();
+    let (conn, mut client) = connection_pair();
+    poller.register(&conn).unwrap();
+    let again = poller.register(&conn).unwrap_err();
+    assert_eq!(PollerFault::classify(&again), PollerFault::AlreadyRegistered);
+    
+    // A fresh poller knows nothing of the connection until it is registered again
+    poller.replace().unwrap();
+    assert!(poller.is_healthy());
+    let lost = poller.set_write_interest(&conn, true).unwrap_err();
+    assert_eq!(PollerFault::classify(&lost), PollerFault::NotRegistered);
+    poller.register(&conn).unwrap();
+    client.write_all(b"ping").unwrap();
+    let events = poller.poll(1000).unwrap();
+    assert!(events.iter().any(|&(id, _)| id == 7), "{:?}", events);
+}
+
+#[test]
+fn test_event_loop_survives_losing_its_poller() {
+    let _serial = SERIAL.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    
+    let mut router = Router::new();
+    router.get("/hello", |_| {
+        let mut response = Response::new(Status::Ok);
+        response.set_body(b"hello");
+        Ok(response)
+    });
+    let acceptor = Arc::new(ConnectionAcceptor::new("127.0.0.1:0").unwrap());
+    let addr = acceptor.local_addr().unwrap();
+    let drain = Arc::new(AtomicBool::new(false));
+    let metrics = Arc::new(MetricsCollector::new());
+    let before = epoll_fds();
+    let server = {
+        let (drain, metrics) = (drain.clone(), metrics.clone());
+        thread::spawn(move || {
+            let mut event_loop = EventLoop::new(0, acceptor);
+            event_loop.set_router(Arc::new(router));
+            event_loop.set_drain_signal(drain);
+            event_loop.set_metrics(metrics);
+            event_loop.run().unwrap();
+        })
+    };
+    
+    let mut client = TcpStream::connect(addr).unwrap();
+    client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let exchange = |client: &mut TcpStream| {
+        client.write_all(b"GET /hello HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let mut response = [0u8; 512];
+        let read = client.read(&mut response).unwrap();
+        String::from_utf8_lossy(&response[..read]).to_string()
+    };
+    assert!(exchange(&mut client).ends_with("hello"));
+    
+    // Close the loop's epoll descriptor, as a stray close elsewhere in the process might
+    let lost: Vec<i32> = epoll_fds().difference(&before).copied().collect();
+    assert_eq!(lost.len(), 1);
+    unsafe {
+        libc::close(lost[0]);
+    }
+    // Give the loop a poll to notice before anything can reuse the number
+    thread::sleep(Duration::from_millis(300));
+    
+    // The keep-alive connection was registered with the new poller
+    assert!(exchange(&mut client).ends_with("hello"));
+    let mut fresh = TcpStream::connect(addr).unwrap();
+    fresh.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    assert!(exchange(&mut fresh).ends_with("hello"));
+    
+    let registry = metrics.registry();
+    assert_eq!(registry.counter("poller.recreations").value(), 1);
+    // Whichever poller call ran first found the descriptor closed
+    let noticed = registry
+        .counters()
+        .iter()
+        .any(|(name, count)| name.starts_with("poller.errors.") && name.ends_with(".closed_descriptor") && *count > 0);
+    assert!(noticed, "{:?}", registry.counters());
+    
+    drain.store(true, Ordering::SeqCst);
+    server.join().unwrap();
+}
+
+#[test]
+fn test_idle_loop_answers_new_connections_at_once() {
+    let _serial = SERIAL.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    
+    let mut router = Router::new();
+    router.get("/hello", |_| {
+        let mut response = Response::new(Status::Ok);
+        response.set_body(b"hello");
+        Ok(response)
+    });
+    let acceptor = Arc::new(ConnectionAcceptor::new("127.0.0.1:0").unwrap());
+    let addr = acceptor.local_addr().unwrap();
+    let drain = Arc::new(AtomicBool::new(false));
+    let server = {
+        let drain = drain.clone();
+        thread::spawn(move || {
+            let mut event_loop = EventLoop::new(0, acceptor);
+            event_loop.set_router(Arc::new(router));
+            event_loop.set_drain_signal(drain);
+            event_loop.run().unwrap();
+        })
+    };
+    
+    // Each connection arrives while the loop is blocked in a poll with nothing to do
+    for _ in 0..5 {
+        thread::sleep(Duration::from_millis(30));
+        let started = Instant::now();
+        let mut client = TcpStream::connect(addr).unwrap();
+        client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        client.write_all(b"GET /hello HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        assert!(response.ends_with("hello"), "{}", response);
+        assert!(started.elapsed() < Duration::from_millis(50), "{:?}", started.elapsed());
+    }
+    
+    drain.store(true, Ordering::SeqCst);
+    server.join().unwrap();
+}
+#[test]
+fn test_poller_grows_while_polls_stay_full() {
+    let _serial = SERIAL.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    
+    let mut poller = EventPoller::new(2).unwrap();
+    poller.grow(8);
+    poller.grow(4);
+    assert_eq!(poller.max_events(), 8);
+    
+    let mut router = Router::new();
+    router.get("/hello", |_| {
+        let mut response = Response::new(Status::Ok);
+        response.set_body(b"hello");
+        Ok(response)
+    });
+    let acceptor = Arc::new(ConnectionAcceptor::new("127.0.0.1:0").unwrap());
+    let addr = acceptor.local_addr().unwrap();
+    let drain = Arc::new(AtomicBool::new(false));
+    let metrics = Arc::new(MetricsCollector::new());
+    let server = {
+        let (drain, metrics) = (drain.clone(), metrics.clone());
+        thread::spawn(move || {
+            let config = ServerConfig::default().with_poll_events(1);
+            let mut event_loop = EventLoop::with_config(0, acceptor, config);
+            event_loop.set_router(Arc::new(router));
+            event_loop.set_drain_signal(drain);
+            event_loop.set_metrics(metrics);
+            event_loop.run().unwrap();
+        })
+    };
+    
+    // Requests arriving on many connections at once fill a one-event poll over and over
+    let mut clients: Vec<TcpStream> = (0..16).map(|_| TcpStream::connect(addr).unwrap()).collect();
+    for _ in 0..3 {
+        for client in &mut clients {
+            client.write_all(b"GET /hello HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        }
+        for client in &mut clients {
+            client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            let mut response = Vec::new();
+            let mut byte = [0u8; 1];
+            while !response.ends_with(b"\r\n\r\nhello") {
+                client.read_exact(&mut byte).unwrap();
+                response.push(byte[0]);
+            }
+        }
+    }
+    assert!(metrics.registry().counter("poller.growths").value() >= 1);
+    
+    drop(clients);
+    drain.store(true, Ordering::SeqCst);
+    server.join().unwrap();
+}
\ No newline at end of file
@@ -0,0 +1,97 @@
+use high_performance_server::http::{Method, Request, Response, Status};
+use high_performance_server::preconditions::{format_http_date, parse_http_date, PreconditionOutcome, Preconditions};
+use std::time::{Duration, UNIX_EPOCH};
+
+fn request(method: Method, headers: &[(&str, &str)]) -> Request {
+    let mut request = Request::new(method, "/resource");
+    for (name, value) in headers {
+        request.set_header(name, value);
+    }
+    request
+}
+
+#[test]
+fn test_http_date_round_trip() {
+    let time = UNIX_EPOCH + Duration::from_secs(784_111_777);
+    assert_eq!(format_http_date(time), "Sun, 06 Nov 1994 08:49:37 GMT");
+    assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"), Some(time));
+    
+    let leap = UNIX_EPOCH + Duration::from_secs(951_782_400);
+    assert_eq!(format_http_date(leap), "Tue, 29 Feb 2000 00:00:00 GMT");
+    assert_eq!(parse_http_date(&format_http_date(leap)), Some(leap));
+    
+    assert_eq!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"), None);
+    assert_eq!(parse_http_date("garbage"), None);
+}
+
+#[test]
+fn test_not_modified_if_etag() {
+    let mut response = Response::new(Status::Ok);
+    response.set_body(b"content");
+    response.set_etag("\"v1\"");
+    response.set_header("Cache-Control", "max-age=60");
+    
+    let fresh = request(Method::Get, &[("If-None-Match", "\"v0\", W/\"v1\"")]);
+    let not_modified = response.clone().not_modified_if(&fresh);
+    assert_eq!(not_modified.status, Status::NotModified);
+    assert!(not_modified.body.is_empty());
+    assert_eq!(not_modified.headers.get("ETag").unwrap(), "\"v1\"");
+    assert_eq!(not_modified.headers.get("Cache-Control").unwrap(), "max-age=60");
+    
+    let stale = request(Method::Get, &[("If-None-Match", "\"v0\"")]);
+    assert_eq!(response.not_modified_if(&stale).status, Status::Ok);
+}
+
+#[test]
+fn test_not_modified_if_last_modified() {
+    let modified = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    let mut response = Response::new(Status::Ok);
+    response.set_last_modified(modified);
+    
+    let same = request(Method::Get, &[("If-Modified-Since", &format_http_date(modified))]);
+    assert_eq!(response.clone().not_modified_if(&same).status, Status::NotModified);
+    
+    let earlier = format_http_date(modified - Duration::from_secs(1));
+    let older = request(Method::Get, &[("If-Modified-Since", &earlier)]);
+    assert_eq!(response.not_modified_if(&older).status, Status::Ok);
+}
+
+#[test]
+fn test_if_match_for_writes() {
+    let current = Preconditions::new().with_etag("\"v2\"");
+    
+    let stale = request(Method::Put, &[("If-Match", "\"v1\"")]);
+    let response = current.check(&stale).unwrap();
+    assert_eq!(response.status, Status::PreconditionFailed);
+    assert_eq!(response.headers.get("ETag").unwrap(), "\"v2\"");
+    
+    let up_to_date = request(Method::Put, &[("If-Match", "\"v2\"")]);
+    assert!(current.check(&up_to_date).is_none());
+    
+    // If-Match uses strong comparison
+    let weak = request(Method::Put, &[("If-Match", "W/\"v2\"")]);
+    assert_eq!(current.evaluate(&weak), PreconditionOutcome::PreconditionFailed);
+}
+
+#[test]
+fn test_if_unmodified_since_for_writes() {
+    let modified = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    let current = Preconditions::new().with_last_modified(modified);
+    
+    let before = format_http_date(modified - Duration::from_secs(60));
+    let stale = request(Method::Delete, &[("If-Unmodified-Since", &before)]);
+    assert_eq!(current.evaluate(&stale), PreconditionOutcome::PreconditionFailed);
+    
+    let after = format_http_date(modified + Duration::from_secs(60));
+    let fresh = request(Method::Delete, &[("If-Unmodified-Since", &after)]);
+    assert_eq!(current.evaluate(&fresh), PreconditionOutcome::Proceed);
+}
+
+#[test]
+fn test_if_none_match_star_for_create() {
+    let create = request(Method::Put, &[("If-None-Match", "*")]);
+    assert_eq!(Preconditions::new().evaluate(&create), PreconditionOutcome::Proceed);
+    
+    let existing = Preconditions::new().with_etag("\"v1\"");
+    assert_eq!(existing.evaluate(&create), PreconditionOutcome::PreconditionFailed);
+}
\ No newline at end of file
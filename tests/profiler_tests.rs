@@ -0,0 +1,79 @@
+use high_performance_server::http::{Method, Request, Status};
+use high_performance_server::memory::MemoryManager;
+use high_performance_server::profiler::{self, mount_profiling, Profile};
+use high_performance_server::Router;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Spin a registered thread through nested scopes until told to stop
+fn spawn_busy_thread(name: &'static str, stop: Arc<AtomicBool>) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        profiler::register_thread(name);
+        while !stop.load(Ordering::Relaxed) {
+            let _outer = profiler::scope("outer");
+            let _inner = profiler::scope("inner");
+            let start = Instant::now();
+            while start.elapsed() < Duration::from_millis(1) {}
+        }
+    })
+}
+
+#[test]
+fn test_capture_records_folded_stacks() {
+    let stop = Arc::new(AtomicBool::new(false));
+    let worker = spawn_busy_thread("busy-worker", stop.clone());
+    
+    let profile = Profile::capture(Duration::from_millis(300), 200);
+    stop.store(true, Ordering::Relaxed);
+    worker.join().unwrap();
+    
+    assert!(profile.total_samples() > 0);
+    assert!(profile.stacks().get("busy-worker;outer;inner").copied().unwrap_or(0) > 0);
+    
+    // Every line is a stack followed by its sample count
+    let folded = profile.to_folded();
+    assert!(folded.lines().any(|line| line.starts_with("busy-worker;outer;inner ")));
+    for line in folded.lines() {
+        let (_, count) = line.rsplit_once(' ').unwrap();
+        assert!(count.parse::<u64>().is_ok());
+    }
+}
+
+#[test]
+fn test_profiling_routes() {
+    let memory = Arc::new(MemoryManager::new());
+    let _held = memory.allocate(100).unwrap();
+    
+    let mut router = Router::new();
+    mount_profiling(&mut router, "/debug/pprof", Some(memory));
+    
+    let response = router.handle_request(&Request::new(Method::Get, "/debug/pprof/profile")).unwrap();
+    assert_eq!(response.status, Status::NotFound);
+    
+    let invalid = router.handle_request(&Request::new(Method::Post, "/debug/pprof/profile?seconds=0")).unwrap();
+    assert_eq!(invalid.status, Status::BadRequest);
+    
+    let start = router.handle_request(&Request::new(Method::Post, "/debug/pprof/profile?seconds=1&hz=50")).unwrap();
+    assert_eq!(start.status, Status::Accepted);
+    let again = router.handle_request(&Request::new(Method::Post, "/debug/pprof/profile?seconds=1")).unwrap();
+    assert_eq!(again.status, Status::Conflict);
+    
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let finished = loop {
+        let response = router.handle_request(&Request::new(Method::Get, "/debug/pprof/profile")).unwrap();
+        if response.status != Status::Accepted || Instant::now() > deadline {
+            break response;
+        }
+        thread::sleep(Duration::from_millis(50));
+    };
+    assert_eq!(finished.status, Status::Ok);
+    
+    let heap = router.handle_request(&Request::new(Method::Get, "/debug/pprof/heap")).unwrap();
+    assert_eq!(heap.status, Status::Ok);
+    let snapshot: serde_json::Value = serde_json::from_slice(&heap.body).unwrap();
+    assert_eq!(snapshot["in_use_bytes"], 128);
+    let pool = snapshot["pools"].as_array().unwrap().iter().find(|pool| pool["size_class"] == 128).unwrap();
+    assert_eq!(pool["in_use"], 1);
+}
\ No newline at end of file
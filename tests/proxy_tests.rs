@@ -0,0 +1,608 @@
+use high_performance_server::http_client::HttpUrl;
+use flate2::read::GzDecoder;
+use high_performance_server::http::{Response, Status};
+use high_performance_server::{
+    mount_proxy, ConnectionAcceptor, EventLoop, Proxy, ProxyCompression, ProxyConfig, RouteOverrides, Router,
+    ServerConfig,
+};
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Run an event loop proxying everything under `/api` to `upstream`
+fn spawn_proxy(upstream: &str) -> (SocketAddr, Arc<AtomicBool>) {
+    spawn_proxy_with(Proxy::new(HttpUrl::parse(upstream).unwrap()).with_chunk_size(4096))
+}
+
+/// Run an event loop proxying everything under `/api` through `proxy`
+fn spawn_proxy_with(proxy: Proxy) -> (SocketAddr, Arc<AtomicBool>) {
+    let mut router = Router::new();
+    mount_proxy(&mut router, "/api", Arc::new(proxy));
+    spawn_router(router, ServerConfig::default())
+}
+
+/// Run an event loop serving `router` with `config`
+fn spawn_router(router: Router, config: ServerConfig) -> (SocketAddr, Arc<AtomicBool>) {
+    let acceptor = Arc::new(ConnectionAcceptor::new("127.0.0.1:0").unwrap());
+    let addr = acceptor.local_addr().unwrap();
+    let drain = Arc::new(AtomicBool::new(false));
+    let drain_clone = drain.clone();
+    thread::spawn(move || {
+        let mut event_loop = EventLoop::with_config(0, acceptor, config);
+        event_loop.set_router(Arc::new(router));
+        event_loop.set_drain_signal(drain_clone);
+        event_loop.run().unwrap();
+    });
+    (addr, drain)
+}
+
+/// Accept one connection, pass the request it got to `requests` and answer with `respond`
+fn fake_upstream<F>(respond: F) -> (String, mpsc::Receiver<String>)
+where
+    F: FnOnce(&mut TcpStream) + Send + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = Vec::new();
+        let mut buf = [0u8; 4096];
+        while !String::from_utf8_lossy(&request).contains("\r\n\r\n") {
+            let n = stream.read(&mut buf).unwrap();
+            request.extend_from_slice(&buf[..n]);
+        }
+        // Pick up a body announced in the head
+        let text = String::from_utf8_lossy(&request).to_string();
+        let head_len = text.find("\r\n\r\n").unwrap() + 4;
+        let body_len = text
+            .lines()
+            .find_map(|line| line.strip_prefix("Content-Length: "))
+            .map_or(0, |len| len.trim().parse::<usize>().unwrap());
+        while request.len() < head_len + body_len {
+            let n = stream.read(&mut buf).unwrap();
+            request.extend_from_slice(&buf[..n]);
+        }
+        let _ = tx.send(String::from_utf8_lossy(&request).to_string());
+        respond(&mut stream);
+    });
+    (format!("http://{}", addr), rx)
+}
+
+/// Read until the peer closes, returning the head and the body
+fn read_all(client: &mut TcpStream) -> (String, Vec<u8>) {
+    let mut received = Vec::new();
+    client.read_to_end(&mut received).unwrap();
+    let head_end = received.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+    (
+        String::from_utf8_lossy(&received[..head_end]).to_string(),
+        received[head_end + 4..].to_vec(),
+    )
+}
+
+fn connect(addr: SocketAddr) -> TcpStream {
+    let client = TcpStream::connect(addr).unwrap();
+    client.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+    client
+}
+
+#[test]
+fn test_forwards_request_and_streams_response() {
+    let body = vec![b'x'; 1 << 20];
+    let sent = body.clone();
+    let (upstream, requests) = fake_upstream(move |stream| {
+        write!(stream, "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nX-Upstream: yes\r\n\r\n", sent.len()).unwrap();
+        stream.write_all(&sent).unwrap();
+    });
+    let (addr, drain) = spawn_proxy(&format!("{}/base", upstream));
+    
+    let mut client = connect(addr);
+    let request = concat!(
+        "POST /api/items?x=1 HTTP/1.1\r\nHost: front\r\nX-Custom: 1\r\n",
+        "Keep-Alive: 5\r\nContent-Length: 5\r\n\r\nhello"
+    );
+    client.write_all(request.as_bytes()).unwrap();
+    let (head, received) = read_all(&mut client);
+    assert!(head.starts_with("HTTP/1.1 200 OK\r\n"), "{}", head);
+    assert!(head.contains("X-Upstream: yes"), "{}", head);
+    assert!(head.contains(&format!("Content-Length: {}", body.len())), "{}", head);
+    assert_eq!(received, body);
+    
+    let request = requests.recv().unwrap();
+    assert!(request.starts_with("POST /base/api/items?x=1 HTTP/1.1\r\n"), "{}", request);
+    assert!(request.contains(&format!("Host: {}\r\n", upstream.trim_start_matches("http://"))));
+    assert!(request.contains("x-custom: 1\r\n"), "{}", request);
+    assert!(!request.to_lowercase().contains("keep-alive: 5"), "{}", request);
+    assert!(request.ends_with("\r\n\r\nhello"), "{}", request);
+    
+    drain.store(true, Ordering::SeqCst);
+}
+
+#[test]
+fn test_chunked_response_keeps_its_framing() {
+    let (upstream, _) = fake_upstream(|stream| {
+        stream.write_all(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n").unwrap();
+        for chunk in ["hello ", "world"] {
+            thread::sleep(Duration::from_millis(20));
+            write!(stream, "{:x}\r\n{}\r\n", chunk.len(), chunk).unwrap();
+        }
+        stream.write_all(b"0\r\n\r\n").unwrap();
+    });
+    let (addr, drain) = spawn_proxy(&upstream);
+    
+    let mut client = connect(addr);
+    client.write_all(b"GET /api/stream HTTP/1.1\r\nHost: front\r\n\r\n").unwrap();
+    let (head, body) = read_all(&mut client);
+    assert!(head.contains("Transfer-Encoding: chunked"), "{}", head);
+    assert!(!head.contains("Content-Length"), "{}", head);
+    assert_eq!(body, b"6\r\nhello \r\n5\r\nworld\r\n0\r\n\r\n");
+    
+    drain.store(true, Ordering::SeqCst);
+}
+
+#[test]
+fn test_slow_client_holds_back_upstream() {
+    const TOTAL: usize = 64 << 20;
+    let written = Arc::new(AtomicUsize::new(0));
+    let progress = written.clone();
+    let (upstream, _) = fake_upstream(move |stream| {
+        write!(stream, "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", TOTAL).unwrap();
+        let chunk = vec![b'y'; 64 * 1024];
+        for _ in 0..TOTAL / chunk.len() {
+            if stream.write_all(&chunk).is_err() {
+                return;
+            }
+            progress.fetch_add(chunk.len(), Ordering::SeqCst);
+        }
+    });
+    let (addr, drain) = spawn_proxy(&upstream);
+    
+    let mut client = connect(addr);
+    client.write_all(b"GET /api/big HTTP/1.1\r\nHost: front\r\n\r\n").unwrap();
+    
+    // Without the client reading, the upstream stalls once socket buffers fill
+    thread::sleep(Duration::from_millis(500));
+    let stalled = written.load(Ordering::SeqCst);
+    assert!(stalled < TOTAL / 2, "upstream wrote {} bytes", stalled);
+    
+    let (_, body) = read_all(&mut client);
+    assert_eq!(body.len(), TOTAL);
+    
+    drain.store(true, Ordering::SeqCst);
+}
+
+#[test]
+fn test_head_and_no_content_are_not_streamed() {
+    let (upstream, _) = fake_upstream(|stream| {
+        stream.write_all(b"HTTP/1.1 204 No Content\r\nX-Done: 1\r\n\r\n").unwrap();
+    });
+    let (addr, drain) = spawn_proxy(&upstream);
+    
+    let mut client = connect(addr);
+    client.write_all(b"DELETE /api/items/1 HTTP/1.1\r\nHost: front\r\n\r\n").unwrap();
+    let mut buf = [0u8; 1024];
+    let n = client.read(&mut buf).unwrap();
+    let head = String::from_utf8_lossy(&buf[..n]).to_string();
+    assert!(head.starts_with("HTTP/1.1 204 No Content\r\n"), "{}", head);
+    assert!(head.contains("X-Done: 1\r\n"), "{}", head);
+    // The exchange took the connection off the event loop, so it isn't kept open
+    assert!(head.contains("Connection: close\r\n"), "{}", head);
+    
+    drain.store(true, Ordering::SeqCst);
+}
+
+#[test]
+fn test_unreachable_upstream_is_bad_gateway() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let upstream = format!("http://{}", listener.local_addr().unwrap());
+    drop(listener);
+    let (addr, drain) = spawn_proxy(&upstream);
+    
+    let mut client = connect(addr);
+    client.write_all(b"GET /api/items HTTP/1.1\r\nHost: front\r\nConnection: close\r\n\r\n").unwrap();
+    let (head, _) = read_all(&mut client);
+    assert!(head.starts_with("HTTP/1.1 502 Bad Gateway\r\n"), "{}", head);
+    
+    drain.store(true, Ordering::SeqCst);
+}
+
+#[test]
+fn test_slow_upstream_does_not_hold_up_the_worker() {
+    let (upstream, _) = fake_upstream(|stream| {
+        thread::sleep(Duration::from_secs(1));
+        stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\nslow").unwrap();
+    });
+    let mut router = Router::new();
+    mount_proxy(&mut router, "/api", Arc::new(Proxy::new(HttpUrl::parse(&upstream).unwrap())));
+    router.get("/local", |_| {
+        let mut response = Response::new(Status::Ok);
+        response.set_body(b"local");
+        Ok(response)
+    });
+    let (addr, drain) = spawn_router(router, ServerConfig::default());
+    
+    let mut proxied = connect(addr);
+    proxied.write_all(b"GET /api/slow HTTP/1.1\r\nHost: front\r\n\r\n").unwrap();
+    thread::sleep(Duration::from_millis(100));
+    
+    // The same worker answers other requests while the upstream thinks
+    let started = Instant::now();
+    let mut local = connect(addr);
+    local.write_all(b"GET /local HTTP/1.1\r\nHost: front\r\nConnection: close\r\n\r\n").unwrap();
+    let (head, body) = read_all(&mut local);
+    assert!(head.starts_with("HTTP/1.1 200 OK\r\n"), "{}", head);
+    assert_eq!(body, b"local");
+    assert!(started.elapsed() < Duration::from_millis(500), "took {:?}", started.elapsed());
+    
+    let (head, body) = read_all(&mut proxied);
+    assert!(head.starts_with("HTTP/1.1 200 OK\r\n"), "{}", head);
+    assert_eq!(body, b"slow");
+    
+    drain.store(true, Ordering::SeqCst);
+}
+
+#[test]
+fn test_streamed_body_reaches_upstream_as_it_arrives() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let upstream = format!("http://{}", listener.local_addr().unwrap());
+    let (heads, head_rx) = mpsc::channel();
+    thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = Vec::new();
+        let mut buf = [0u8; 4096];
+        while !String::from_utf8_lossy(&request).contains("\r\n\r\n") {
+            let n = stream.read(&mut buf).unwrap();
+            request.extend_from_slice(&buf[..n]);
+        }
+        heads.send(String::from_utf8_lossy(&request).to_string()).unwrap();
+        
+        let head_len = request.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+        while request.len() < head_len + 10 {
+            let n = stream.read(&mut buf).unwrap();
+            request.extend_from_slice(&buf[..n]);
+        }
+        let body = request[head_len..].to_vec();
+        write!(stream, "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", body.len()).unwrap();
+        stream.write_all(&body).unwrap();
+    });
+    
+    let mut router = Router::new();
+    mount_proxy(&mut router, "/api", Arc::new(Proxy::new(HttpUrl::parse(&upstream).unwrap())));
+    let streaming = RouteOverrides {
+        stream_body: Some(true),
+        ..RouteOverrides::default()
+    };
+    let (addr, drain) = spawn_router(router, ServerConfig::default().with_route("/api", streaming));
+    
+    let mut client = connect(addr);
+    client.write_all(b"POST /api/upload HTTP/1.1\r\nHost: front\r\nContent-Length: 10\r\n\r\nhello").unwrap();
+    
+    // The upstream gets the request before the client has sent all of it
+    let head = head_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert!(head.starts_with("POST /api/upload HTTP/1.1\r\n"), "{}", head);
+    assert!(head.contains("Content-Length: 10\r\n"), "{}", head);
+    
+    client.write_all(b"world").unwrap();
+    let (head, body) = read_all(&mut client);
+    assert!(head.starts_with("HTTP/1.1 200 OK\r\n"), "{}", head);
+    assert_eq!(body, b"helloworld");
+    
+    drain.store(true, Ordering::SeqCst);
+}
+
+/// Read a response head from `client`, returning it and any bytes after it
+fn read_head(client: &mut TcpStream) -> (String, Vec<u8>) {
+    let mut received = Vec::new();
+    let mut buf = [0u8; 1024];
+    loop {
+        if let Some(end) = received.windows(4).position(|w| w == b"\r\n\r\n") {
+            let head = String::from_utf8_lossy(&received[..end]).to_string();
+            return (head, received[end + 4..].to_vec());
+        }
+        let n = client.read(&mut buf).unwrap();
+        assert!(n > 0, "connection closed early");
+        received.extend_from_slice(&buf[..n]);
+    }
+}
+
+#[test]
+fn test_websocket_upgrade_is_tunnelled() {
+    let (upstream, requests) = fake_upstream(|stream| {
+        let accept = concat!(
+            "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n",
+            "Sec-WebSocket-Accept: abc=\r\n\r\nhi"
+        );
+        stream.write_all(accept.as_bytes()).unwrap();
+        // Echo in upper case until the client goes away
+        let mut buf = [0u8; 1024];
+        loop {
+            match stream.read(&mut buf) {
+                Ok(0) | Err(_) => return,
+                Ok(n) => stream.write_all(&buf[..n].to_ascii_uppercase()).unwrap(),
+            }
+        }
+    });
+    let (addr, drain) = spawn_proxy(&upstream);
+    
+    let mut client = connect(addr);
+    let handshake = concat!(
+        "GET /api/socket HTTP/1.1\r\nHost: front\r\nConnection: keep-alive, Upgrade\r\n",
+        "Upgrade: websocket\r\nSec-WebSocket-Key: key==\r\nSec-WebSocket-Version: 13\r\n\r\n"
+    );
+    client.write_all(handshake.as_bytes()).unwrap();
+    let (head, mut early) = read_head(&mut client);
+    assert!(head.starts_with("HTTP/1.1 101 Switching Protocols\r\n"), "{}", head);
+    assert!(head.contains("Upgrade: websocket"), "{}", head);
+    assert!(head.contains("Connection: Upgrade"), "{}", head);
+    assert!(head.contains("Sec-WebSocket-Accept: abc="), "{}", head);
+    
+    let request = requests.recv().unwrap();
+    assert!(request.contains("Connection: Upgrade\r\nUpgrade: websocket\r\n"), "{}", request);
+    assert!(request.contains("sec-websocket-key: key==\r\n"), "{}", request);
+    
+    // Bytes the upstream sent with its 101 arrive first
+    while early.len() < 2 {
+        let mut buf = [0u8; 16];
+        let n = client.read(&mut buf).unwrap();
+        early.extend_from_slice(&buf[..n]);
+    }
+    assert_eq!(early, b"hi");
+    
+    client.write_all(b"ping").unwrap();
+    let mut echoed = [0u8; 4];
+    client.read_exact(&mut echoed).unwrap();
+    assert_eq!(&echoed, b"PING");
+    
+    // Closing our side closes the tunnel
+    client.shutdown(std::net::Shutdown::Write).unwrap();
+    let mut rest = Vec::new();
+    client.read_to_end(&mut rest).unwrap();
+    assert!(rest.is_empty());
+    
+    drain.store(true, Ordering::SeqCst);
+}
+
+#[test]
+fn test_declined_upgrade_is_an_ordinary_response() {
+    let (upstream, _) = fake_upstream(|stream| {
+        stream
+            .write_all(b"HTTP/1.1 400 Bad Request\r\nContent-Length: 7\r\nConnection: close\r\n\r\nno ws\r\n")
+            .unwrap();
+    });
+    let (addr, drain) = spawn_proxy(&upstream);
+    
+    let mut client = connect(addr);
+    client
+        .write_all(b"GET /api/socket HTTP/1.1\r\nHost: front\r\nConnection: Upgrade\r\nUpgrade: websocket\r\n\r\n")
+        .unwrap();
+    let (head, body) = read_all(&mut client);
+    assert!(head.starts_with("HTTP/1.1 400 Bad Request\r\n"), "{}", head);
+    assert_eq!(body, b"no ws\r\n");
+    
+    drain.store(true, Ordering::SeqCst);
+}
+
+#[test]
+fn test_header_rules_rewrite_both_ways() {
+    let (upstream, requests) = fake_upstream(|stream| {
+        let response = concat!(
+            "HTTP/1.1 204 No Content\r\nX-Internal: secret\r\nConnection: X-Upstream-Hop\r\n",
+            "X-Upstream-Hop: 1\r\nProxy-Authenticate: Basic\r\n\r\n"
+        );
+        stream.write_all(response.as_bytes()).unwrap();
+    });
+    let config: ProxyConfig = serde_json::from_str(&format!(
+        r#"{{
+            "upstreams": ["{}"],
+            "headers": {{
+                "forwarded_proto": "https",
+                "request": {{"remove": ["X-Secret"], "rename": {{"X-Old": "X-New"}}, "add": {{"X-Route": "api"}}}},
+                "response": {{"remove": ["x-internal"], "add": {{"X-Served-By": "proxy"}}}}
+            }}
+        }}"#,
+        upstream
+    ))
+    .unwrap();
+    let (addr, drain) = spawn_proxy_with(Proxy::from_config(&config).unwrap());
+    
+    let mut client = connect(addr);
+    let request = concat!(
+        "GET /api/items HTTP/1.1\r\nHost: front:8443\r\nConnection: close, X-Hop\r\nX-Hop: 1\r\n",
+        "Proxy-Authorization: Basic abc\r\nX-Forwarded-For: 10.0.0.9\r\nX-Secret: 1\r\nX-Old: kept\r\n\r\n"
+    );
+    client.write_all(request.as_bytes()).unwrap();
+    let mut buf = [0u8; 1024];
+    let n = client.read(&mut buf).unwrap();
+    let head = String::from_utf8_lossy(&buf[..n]).to_string();
+    assert!(head.starts_with("HTTP/1.1 204 No Content\r\n"), "{}", head);
+    assert!(head.contains("X-Served-By: proxy"), "{}", head);
+    assert!(!head.contains("X-Internal"), "{}", head);
+    assert!(!head.contains("X-Upstream-Hop"), "{}", head);
+    assert!(!head.contains("Proxy-Authenticate"), "{}", head);
+    
+    let request = requests.recv().unwrap();
+    assert!(request.contains("x-forwarded-for: 10.0.0.9, 127.0.0.1"), "{}", request);
+    assert!(request.contains("x-forwarded-proto: https"), "{}", request);
+    assert!(request.contains("x-forwarded-host: front:8443"), "{}", request);
+    assert!(request.contains("x-forwarded-port: 8443"), "{}", request);
+    assert!(request.contains("x-new: kept"), "{}", request);
+    assert!(request.contains("x-route: api"), "{}", request);
+    for dropped in ["x-hop", "proxy-authorization", "x-secret", "x-old"] {
+        assert!(!request.contains(&format!("{}:", dropped)), "{}", request);
+    }
+    
+    drain.store(true, Ordering::SeqCst);
+}
+
+#[test]
+fn test_proxy_config_needs_upstreams() {
+    assert!(Proxy::from_config(&ProxyConfig::default()).is_err());
+    
+    let config = ProxyConfig {
+        upstreams: vec!["https://example.com".to_string()],
+        ..ProxyConfig::default()
+    };
+    assert!(Proxy::from_config(&config).is_err());
+}
+
+/// Undo chunked framing, panicking on anything malformed
+fn dechunk(mut body: &[u8]) -> Vec<u8> {
+    let mut data = Vec::new();
+    loop {
+        let line_end = body.windows(2).position(|w| w == b"\r\n").unwrap();
+        let size = usize::from_str_radix(std::str::from_utf8(&body[..line_end]).unwrap(), 16).unwrap();
+        body = &body[line_end + 2..];
+        if size == 0 {
+            assert_eq!(body, b"\r\n");
+            return data;
+        }
+        data.extend_from_slice(&body[..size]);
+        assert_eq!(&body[size..size + 2], b"\r\n");
+        body = &body[size + 2..];
+    }
+}
+
+fn gunzip(data: &[u8]) -> Vec<u8> {
+    let mut plain = Vec::new();
+    GzDecoder::new(data).read_to_end(&mut plain).unwrap();
+    plain
+}
+
+fn compressing(upstream: &str) -> Proxy {
+    Proxy::new(HttpUrl::parse(upstream).unwrap()).with_compression(ProxyCompression {
+        enabled: true,
+        ..ProxyCompression::default()
+    })
+}
+
+const GZIP_REQUEST: &[u8] = b"GET /api/page HTTP/1.1\r\nHost: front\r\nAccept-Encoding: gzip, deflate\r\n\r\n";
+
+#[test]
+fn test_response_is_gzipped_in_flight() {
+    let page = "<p>hello proxy</p>\n".repeat(2000);
+    let sent = page.clone();
+    let (upstream, _) = fake_upstream(move |stream| {
+        let head = concat!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\n",
+            "ETag: \"v1\"\r\nVary: Cookie\r\nContent-Length: "
+        );
+        write!(stream, "{}{}\r\n\r\n", head, sent.len()).unwrap();
+        stream.write_all(sent.as_bytes()).unwrap();
+    });
+    let (addr, drain) = spawn_proxy_with(compressing(&upstream));
+    
+    let mut client = connect(addr);
+    client.write_all(GZIP_REQUEST).unwrap();
+    let (head, received) = read_all(&mut client);
+    assert!(head.contains("Content-Encoding: gzip"), "{}", head);
+    assert!(head.contains("Transfer-Encoding: chunked"), "{}", head);
+    assert!(head.contains("Vary: Cookie, Accept-Encoding"), "{}", head);
+    assert!(head.contains("ETag: W/\"v1\""), "{}", head);
+    assert!(!head.contains("Content-Length"), "{}", head);
+    
+    let compressed = dechunk(&received);
+    assert!(compressed.len() < page.len() / 10);
+    assert_eq!(gunzip(&compressed), page.as_bytes());
+    
+    drain.store(true, Ordering::SeqCst);
+}
+
+#[test]
+fn test_chunked_upstream_body_is_gzipped() {
+    let (upstream, _) = fake_upstream(|stream| {
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nTransfer-Encoding: chunked\r\n\r\n")
+            .unwrap();
+        for part in ["{\"items\":", "[1,2,3]", "}"] {
+            write!(stream, "{:x};ext=1\r\n{}\r\n", part.len(), part).unwrap();
+            thread::sleep(Duration::from_millis(20));
+        }
+        stream.write_all(b"0\r\nX-Trailer: 1\r\n\r\n").unwrap();
+    });
+    let (addr, drain) = spawn_proxy_with(compressing(&upstream));
+    
+    let mut client = connect(addr);
+    client.write_all(GZIP_REQUEST).unwrap();
+    let (head, received) = read_all(&mut client);
+    assert!(head.contains("Content-Encoding: gzip"), "{}", head);
+    assert_eq!(gunzip(&dechunk(&received)), b"{\"items\":[1,2,3]}");
+    
+    drain.store(true, Ordering::SeqCst);
+}
+
+/// Fetch a JSON page through a compressing proxy, accepting `accepted`
+fn fetch_compressed(page: &str, accepted: &str) -> (String, Vec<u8>) {
+    let sent = page.to_string();
+    let (upstream, _) = fake_upstream(move |stream| {
+        write!(stream, "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n", sent.len())
+            .unwrap();
+        stream.write_all(sent.as_bytes()).unwrap();
+    });
+    let (addr, drain) = spawn_proxy_with(compressing(&upstream));
+    
+    let mut client = connect(addr);
+    write!(client, "GET /api/page HTTP/1.1\r\nHost: front\r\nAccept-Encoding: {}\r\n\r\n", accepted).unwrap();
+    let (head, received) = read_all(&mut client);
+    drain.store(true, Ordering::SeqCst);
+    (head, dechunk(&received))
+}
+
+#[test]
+fn test_brotli_is_picked_when_the_client_prefers_it() {
+    let page = "{\"message\": \"hello proxy\"}\n".repeat(2000);
+    for accepted in ["br, gzip;q=0.8", "gzip, br", "*"] {
+        let (head, compressed) = fetch_compressed(&page, accepted);
+        assert!(head.contains("Content-Encoding: br"), "{}: {}", accepted, head);
+        assert!(head.contains("Vary: Accept-Encoding"), "{}", head);
+        assert!(compressed.len() < page.len() / 10);
+        
+        let mut plain = Vec::new();
+        brotli::Decompressor::new(&compressed[..], 4096).read_to_end(&mut plain).unwrap();
+        assert_eq!(plain, page.as_bytes());
+    }
+    
+    // A client preferring gzip gets it
+    let (head, compressed) = fetch_compressed(&page, "br;q=0.5, gzip");
+    assert!(head.contains("Content-Encoding: gzip"), "{}", head);
+    assert_eq!(gunzip(&compressed), page.as_bytes());
+}
+
+#[test]
+fn test_compression_is_skipped_when_not_allowed() {
+    let body = "x".repeat(4096);
+    
+    // The client takes neither coding, but caches still need to know it mattered
+    let sent = body.clone();
+    let (upstream, _) = fake_upstream(move |stream| {
+        write!(stream, "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: {}\r\n\r\n{}", sent.len(), sent)
+            .unwrap();
+    });
+    let (addr, drain) = spawn_proxy_with(compressing(&upstream));
+    let mut client = connect(addr);
+    client
+        .write_all(b"GET /api/a HTTP/1.1\r\nHost: front\r\nAccept-Encoding: gzip;q=0, br;q=0, deflate\r\n\r\n")
+        .unwrap();
+    let (head, received) = read_all(&mut client);
+    assert!(!head.contains("Content-Encoding"), "{}", head);
+    assert!(head.contains("Vary: Accept-Encoding"), "{}", head);
+    assert_eq!(received, body.as_bytes());
+    drain.store(true, Ordering::SeqCst);
+    
+    // The upstream forbids changing the body
+    let sent = body.clone();
+    let (upstream, _) = fake_upstream(move |stream| {
+        let head = "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nCache-Control: public, no-transform\r\n";
+        write!(stream, "{}Content-Length: {}\r\n\r\n{}", head, sent.len(), sent).unwrap();
+    });
+    let (addr, drain) = spawn_proxy_with(compressing(&upstream));
+    let mut client = connect(addr);
+    client.write_all(GZIP_REQUEST).unwrap();
+    let (head, received) = read_all(&mut client);
+    assert!(!head.contains("Content-Encoding"), "{}", head);
+    assert!(!head.contains("Vary"), "{}", head);
+    assert_eq!(received, body.as_bytes());
+    drain.store(true, Ordering::SeqCst);
+}
\ No newline at end of file
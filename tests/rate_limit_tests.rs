@@ -0,0 +1,232 @@
+use high_performance_server::{
+    tenant_middleware, MemoryRateLimiter, Method, MiddlewareChain, RateDecision, RateLimit, RateLimitBackend,
+    RedisRateLimiter, Request, RespClient, Response, ServerResult, Status, SyncedRateLimiter, TenantConfig, Tenants,
+    TenantsConfig,
+};
+use high_performance_server::tenants::RATE_LIMIT_SYNC_INTERVAL;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn test_memory_limiter_keeps_a_bucket_per_key() {
+    let limiter = MemoryRateLimiter::new();
+    let limit = RateLimit {
+        rate_per_sec: 1,
+        burst: 3,
+    };
+    
+    let first = limiter.acquire("a", limit, 2).unwrap();
+    assert!(first.allowed);
+    assert_eq!(first.remaining, 1);
+    
+    // Nothing is taken unless all of the cost is there
+    let second = limiter.acquire("a", limit, 2).unwrap();
+    assert!(!second.allowed);
+    assert_eq!(second.remaining, 1);
+    assert!(second.retry_after > Duration::ZERO && second.retry_after <= Duration::from_secs(1));
+    assert!(limiter.acquire("a", limit, 1).unwrap().allowed);
+    
+    assert!(limiter.acquire("b", limit, 3).unwrap().allowed);
+    assert_eq!(limiter.len(), 2);
+}
+
+/// Read one RESP array of bulk strings
+fn read_command(reader: &mut impl BufRead) -> Option<Vec<String>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line).ok()? == 0 {
+        return None;
+    }
+    let count: usize = line.trim_end().strip_prefix('*')?.parse().ok()?;
+    let mut args = Vec::new();
+    for _ in 0..count {
+        line.clear();
+        reader.read_line(&mut line).ok()?;
+        let length: usize = line.trim_end().strip_prefix('$')?.parse().ok()?;
+        let mut arg = vec![0u8; length + 2];
+        reader.read_exact(&mut arg).ok()?;
+        args.push(String::from_utf8_lossy(&arg[..length]).to_string());
+    }
+    Some(args)
+}
+
+/// A server answering every command with `reply`, passing the commands to the receiver
+///
+/// Each connection is closed after `per_connection` commands.
+fn fake_redis(reply: &'static str, per_connection: usize) -> (String, mpsc::Receiver<Vec<String>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            for _ in 0..per_connection {
+                match read_command(&mut reader) {
+                    Some(command) => {
+                        let _ = tx.send(command);
+                        let _ = stream.write_all(reply.as_bytes());
+                    }
+                    None => break,
+                }
+            }
+        }
+    });
+    (address, rx)
+}
+
+#[test]
+fn test_redis_limiter_runs_the_bucket_in_redis() {
+    let (address, commands) = fake_redis("*3\r\n:0\r\n:0\r\n:1500\r\n", 1);
+    let limiter = RedisRateLimiter::new(&address).with_prefix("rl:");
+    
+    let limit = RateLimit {
+        rate_per_sec: 5,
+        burst: 10,
+    };
+    let expected = RateDecision {
+        allowed: false,
+        remaining: 0,
+        retry_after: Duration::from_millis(1500),
+    };
+    assert_eq!(limiter.acquire("client", limit, 2).unwrap(), expected);
+    
+    let command = commands.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(command[0], "EVAL");
+    assert_eq!(&command[2..], ["1", "rl:client", "5", "10", "2"]);
+    
+    // The server closed the connection, so the next call reconnects
+    assert_eq!(limiter.acquire("client", limit, 2).unwrap(), expected);
+}
+
+#[test]
+fn test_redis_errors_are_reported() {
+    let (address, _commands) = fake_redis("-NOSCRIPT scripting is disabled\r\n", 10);
+    let limiter = RedisRateLimiter::new(&address);
+    assert!(limiter.acquire("client", RateLimit::per_second(1), 1).is_err());
+    
+    let client = RespClient::new("127.0.0.1:1").with_timeout(Duration::from_millis(200));
+    let unreachable = RedisRateLimiter::with_client(Arc::new(client));
+    assert!(unreachable.acquire("client", RateLimit::per_second(1), 1).is_err());
+}
+
+/// A backend shared by two tenant "replicas", recording what it was asked for
+struct SharedLimiter {
+    inner: MemoryRateLimiter,
+    calls: Mutex<Vec<(String, u64)>>,
+    delay: Duration,
+}
+
+impl SharedLimiter {
+    fn new(delay: Duration) -> Arc<Self> {
+        Arc::new(Self {
+            inner: MemoryRateLimiter::new(),
+            calls: Mutex::new(Vec::new()),
+            delay,
+        })
+    }
+}
+
+impl RateLimitBackend for SharedLimiter {
+    fn acquire(&self, key: &str, limit: RateLimit, cost: u64) -> ServerResult<RateDecision> {
+        thread::sleep(self.delay);
+        self.calls.lock().unwrap().push((key.to_string(), cost));
+        self.inner.acquire(key, limit, cost)
+    }
+}
+
+#[test]
+fn test_synced_limiter_answers_locally_and_charges_the_backend_later() {
+    let shared = SharedLimiter::new(Duration::ZERO);
+    let limiter = SyncedRateLimiter::new(shared.clone(), Duration::from_secs(3600));
+    let limit = RateLimit {
+        rate_per_sec: 1,
+        burst: 3,
+    };
+    
+    assert!(limiter.acquire("a", limit, 1).unwrap().allowed);
+    assert!(limiter.acquire("a", limit, 1).unwrap().allowed);
+    assert!(shared.calls.lock().unwrap().is_empty());
+    
+    limiter.sync();
+    assert_eq!(shared.calls.lock().unwrap().as_slice(), [("a".to_string(), 2)]);
+    assert_eq!(shared.inner.acquire("a", limit, 0).unwrap().remaining, 1);
+}
+
+#[test]
+fn test_synced_limiter_catches_up_with_other_replicas() {
+    let shared = SharedLimiter::new(Duration::ZERO);
+    let one = SyncedRateLimiter::new(shared.clone(), Duration::from_secs(3600));
+    let two = SyncedRateLimiter::new(shared.clone(), Duration::from_secs(3600));
+    let limit = RateLimit {
+        rate_per_sec: 1,
+        burst: 3,
+    };
+    
+    // Replica one spends the whole shared burst, replica two hears about it on its next sync
+    for _ in 0..3 {
+        assert!(one.acquire("a", limit, 1).unwrap().allowed);
+    }
+    one.sync();
+    assert!(two.acquire("a", limit, 1).unwrap().allowed);
+    two.sync();
+    let denied = two.acquire("a", limit, 1).unwrap();
+    assert!(!denied.allowed);
+    assert!(denied.retry_after > Duration::ZERO);
+}
+
+#[test]
+fn test_synced_limiter_does_not_wait_on_a_slow_backend() {
+    let shared = SharedLimiter::new(Duration::from_millis(500));
+    let limiter = Arc::new(SyncedRateLimiter::new(shared, Duration::from_secs(3600)));
+    let limit = RateLimit::per_second(100);
+    assert!(limiter.acquire("a", limit, 1).unwrap().allowed);
+    
+    let syncing = {
+        let limiter = limiter.clone();
+        thread::spawn(move || limiter.sync())
+    };
+    thread::sleep(Duration::from_millis(50));
+    let start = std::time::Instant::now();
+    assert!(limiter.acquire("a", limit, 1).unwrap().allowed);
+    assert!(start.elapsed() < Duration::from_millis(250));
+    syncing.join().unwrap();
+}
+
+#[test]
+fn test_replicas_share_tenant_quotas_through_a_backend() {
+    let shared = SharedLimiter::new(Duration::ZERO);
+    let replica = || {
+        let config = TenantsConfig {
+            tenants: vec![TenantConfig {
+                id: "acme".to_string(),
+                api_keys: vec!["acme-key".into()],
+                rate_per_sec: Some(1),
+                burst: Some(2),
+                ..TenantConfig::default()
+            }],
+            ..TenantsConfig::default()
+        };
+        let tenants = Tenants::new(config).unwrap().with_rate_limiter(shared.clone());
+        let mut chain = MiddlewareChain::new();
+        chain.add(tenant_middleware(Arc::new(tenants)));
+        chain.set_handler(|_| Ok(Response::new(Status::Ok)));
+        chain
+    };
+    let (one, two) = (replica(), replica());
+    
+    let mut request = Request::new(Method::Get, "/");
+    request.set_header("X-Api-Key", "acme-key");
+    assert_eq!(one.handle(&request).unwrap().status, Status::Ok);
+    assert_eq!(one.handle(&request).unwrap().status, Status::Ok);
+    
+    // Once both have synced, replica two knows the shared burst is spent
+    thread::sleep(RATE_LIMIT_SYNC_INTERVAL * 3);
+    assert_eq!(two.handle(&request).unwrap().status, Status::Ok);
+    thread::sleep(RATE_LIMIT_SYNC_INTERVAL * 3);
+    assert_eq!(two.handle(&request).unwrap().status, Status::TooManyRequests);
+    assert!(shared.calls.lock().unwrap().iter().all(|(key, _)| key == "tenant:acme"));
+}
\ No newline at end of file
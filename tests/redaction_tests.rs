@@ -0,0 +1,39 @@
+use high_performance_server::{RedactionConfig, Redactor};
+use serde_json::json;
+
+#[test]
+fn test_default_headers_are_masked() {
+    let redactor = Redactor::default();
+    assert_eq!(redactor.header("Authorization", "Basic abc"), "[REDACTED]");
+    assert_eq!(redactor.header("cookie", "session=1"), "[REDACTED]");
+    assert_eq!(redactor.header("Accept", "text/html"), "text/html");
+}
+
+#[test]
+fn test_query_parameters_are_masked() {
+    let redactor = Redactor::default();
+    assert_eq!(redactor.uri("/a?Token=x&page=2&api%5Fkey=k"), "/a?Token=[REDACTED]&page=2&api%5Fkey=[REDACTED]");
+    assert_eq!(redactor.uri("/a?page=2&flag"), "/a?page=2&flag");
+    assert_eq!(redactor.uri("/plain"), "/plain");
+}
+
+#[test]
+fn test_bodies_are_masked_by_type() {
+    let redactor = Redactor::new(RedactionConfig {
+        fields: vec!["pin".to_string()],
+        mask: "***".to_string(),
+        ..RedactionConfig::default()
+    });
+    
+    let json_body = br#"{"cards":[{"pin":"1234","last4":"9876"}],"PIN":1}"#;
+    let masked = redactor.body(Some("application/json; charset=utf-8"), json_body);
+    let masked: serde_json::Value = serde_json::from_slice(&masked).unwrap();
+    assert_eq!(masked, json!({"cards": [{"pin": "***", "last4": "9876"}], "PIN": "***"}));
+    
+    let form = redactor.body(Some("application/x-www-form-urlencoded"), b"user=ann&pin=1234");
+    assert_eq!(&*form, b"user=ann&pin=***");
+    
+    // Unparseable JSON could hold anything
+    assert_eq!(&*redactor.body(Some("application/json"), b"{\"pin\":"), b"***");
+    assert_eq!(&*redactor.body(Some("text/plain"), b"pin=1234"), b"pin=1234");
+}
\ No newline at end of file
@@ -0,0 +1,136 @@
+use high_performance_server::{
+    replay, HarConfig, HarEntry, HarRecorder, Method, ReplayOptions, Request, Response, Status,
+};
+use std::env;
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Read a request head and `Content-Length` body off `stream`
+fn read_request(stream: &mut impl Read) -> String {
+    let mut request = Vec::new();
+    let mut byte = [0u8; 1];
+    while !request.ends_with(b"\r\n\r\n") && stream.read(&mut byte).unwrap_or(0) == 1 {
+        request.push(byte[0]);
+    }
+    let head = String::from_utf8_lossy(&request).to_string();
+    let length = head
+        .lines()
+        .find_map(|line| line.strip_prefix("Content-Length: "))
+        .map_or(0, |length| length.trim().parse::<usize>().unwrap());
+    let mut body = vec![0u8; length];
+    stream.read_exact(&mut body).unwrap();
+    head + &String::from_utf8_lossy(&body)
+}
+
+/// Answer every request with 200, passing the requests to the receiver
+fn target() -> (String, mpsc::Receiver<(Instant, String)>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/staging", listener.local_addr().unwrap());
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let tx = tx.clone();
+            thread::spawn(move || {
+                let _ = tx.send((Instant::now(), read_request(&mut stream)));
+                let _ = stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok");
+            });
+        }
+    });
+    (url, rx)
+}
+
+/// Capture `request` as if it started `offset` after a fixed time and was answered with `status`
+fn captured(recorder: &HarRecorder, request: &Request, status: Status, offset: Duration) -> HarEntry {
+    let started = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000) + offset;
+    recorder.entry(request, &Response::new(status), started, Duration::from_millis(3))
+}
+
+fn recorder(name: &str) -> HarRecorder {
+    let path = env::temp_dir().join(format!("hps-replay-{}-{}.har", name, std::process::id()));
+    HarRecorder::new(HarConfig {
+        path: path.display().to_string(),
+        ..HarConfig::default()
+    })
+    .unwrap()
+}
+
+#[test]
+fn test_captured_requests_are_replayed() {
+    let (url, received) = target();
+    let recorder = recorder("requests");
+    
+    let mut login = Request::new(Method::Post, "/login?next=%2Fhome");
+    login.set_header("Host", "example.com");
+    login.set_header("Authorization", "Bearer s3cr3t");
+    login.set_header("X-Client", "mobile");
+    login.set_header("Content-Type", "application/x-www-form-urlencoded");
+    login.set_body(b"user=alice&password=hunter2");
+    let mut upload = Request::new(Method::Put, "/blob");
+    upload.set_body(&[0xff, 0x00, 0xfe]);
+    let entries = [
+        captured(&recorder, &login, Status::Ok, Duration::ZERO),
+        captured(&recorder, &upload, Status::Created, Duration::from_millis(10)),
+    ];
+    
+    let options = ReplayOptions {
+        target: url.clone(),
+        concurrency: 1,
+        ..ReplayOptions::default()
+    };
+    let report = replay(&entries, &options).unwrap();
+    assert_eq!((report.completed, report.failed), (2, 0));
+    // The upload was captured as a 201 but answered with a 200
+    assert_eq!(report.status_mismatches, 1);
+    assert_eq!(report.latencies.len(), 2);
+    assert!(report.percentile(50.0) <= report.percentile(100.0));
+    
+    let (_, login) = received.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert!(login.starts_with("POST /staging/login?next=%2Fhome HTTP/1.1\r\n"), "{}", login);
+    assert!(login.contains(&format!("Host: {}\r\n", url["http://".len()..].trim_end_matches("/staging"))));
+    assert!(login.contains("x-client: mobile\r\n"), "{}", login);
+    // Masked values are left out rather than sent as the mask
+    assert!(!login.to_lowercase().contains("authorization"), "{}", login);
+    assert!(login.ends_with("\r\n\r\nuser=alice&password=[REDACTED]"), "{}", login);
+    
+    let (_, upload) = received.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert!(upload.starts_with("PUT /staging/blob HTTP/1.1\r\n"), "{}", upload);
+    assert!(upload.contains("Content-Length: 3\r\n"), "{}", upload);
+}
+
+#[test]
+fn test_recorded_pace_is_kept_at_the_given_speed() {
+    let (url, received) = target();
+    let recorder = recorder("pace");
+    // Captured out of order, 400ms apart
+    let entries = [
+        captured(&recorder, &Request::new(Method::Get, "/second"), Status::Ok, Duration::from_millis(400)),
+        captured(&recorder, &Request::new(Method::Get, "/first"), Status::Ok, Duration::ZERO),
+    ];
+    
+    let options = ReplayOptions {
+        target: url.clone(),
+        speed: 2.0,
+        ..ReplayOptions::default()
+    };
+    let report = replay(&entries, &options).unwrap();
+    assert_eq!(report.completed, 2);
+    let (first_at, first) = received.recv_timeout(Duration::from_secs(5)).unwrap();
+    let (second_at, second) = received.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert!(first.starts_with("GET /staging/first ") && second.starts_with("GET /staging/second "));
+    let gap = second_at - first_at;
+    assert!(gap >= Duration::from_millis(180) && gap < Duration::from_millis(380), "{:?}", gap);
+    
+    // As fast as possible ignores the recorded gaps
+    let options = ReplayOptions { speed: 0.0, ..options };
+    let report = replay(&entries, &options).unwrap();
+    assert_eq!(report.completed, 2);
+    assert!(report.elapsed < Duration::from_millis(180), "{:?}", report.elapsed);
+    
+    for bad in [ReplayOptions { speed: -1.0, ..options.clone() }, ReplayOptions { concurrency: 0, ..options }] {
+        assert!(replay(&entries, &bad).is_err());
+    }
+}
\ No newline at end of file
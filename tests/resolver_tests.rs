@@ -0,0 +1,183 @@
+use high_performance_server::metrics::MetricsRegistry;
+use high_performance_server::resolver;
+use high_performance_server::{Resolver, ResolverConfig};
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Duration;
+
+/// A nameserver that knows `example.test` and counts the queries it gets
+///
+/// Answers carry a one-second TTL; every other name is NXDOMAIN.
+fn fake_nameserver() -> (SocketAddr, Arc<AtomicUsize>) {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = socket.local_addr().unwrap();
+    let queries = Arc::new(AtomicUsize::new(0));
+    let counter = queries.clone();
+    
+    thread::spawn(move || {
+        let mut buf = [0u8; 512];
+        while let Ok((len, from)) = socket.recv_from(&mut buf) {
+            counter.fetch_add(1, Ordering::SeqCst);
+            let query = &buf[..len];
+            let question = &query[12..];
+            let name_end = question.iter().position(|&b| b == 0).unwrap() + 1;
+            let record_type = u16::from_be_bytes([question[name_end], question[name_end + 1]]);
+            let known = &question[..name_end] == b"\x07example\x04test\x00";
+            
+            let mut answer = query[..2].to_vec();
+            answer.extend_from_slice(&[0x81, if known { 0x80 } else { 0x83 }, 0, 1]);
+            let records: u16 = if known { 2 } else { 0 };
+            answer.extend_from_slice(&records.to_be_bytes());
+            answer.extend_from_slice(&[0, 0, 0, 0]);
+            answer.extend_from_slice(&question[..name_end + 4]);
+            if known {
+                // A CNAME first, as recursive servers send, then the address
+                answer.extend_from_slice(&[0xc0, 12, 0, 5, 0, 1, 0, 0, 0, 60, 0, 2, 0xc0, 12]);
+                let data: Vec<u8> = if record_type == 1 {
+                    vec![192, 0, 2, 1]
+                } else {
+                    "2001:db8::1".parse::<std::net::Ipv6Addr>().unwrap().octets().to_vec()
+                };
+                answer.extend_from_slice(&[0xc0, 12]);
+                answer.extend_from_slice(&record_type.to_be_bytes());
+                answer.extend_from_slice(&[0, 1, 0, 0, 0, 1]);
+                answer.extend_from_slice(&(data.len() as u16).to_be_bytes());
+                answer.extend_from_slice(&data);
+            }
+            let _ = socket.send_to(&answer, from);
+        }
+    });
+    
+    (addr, queries)
+}
+
+fn config(nameserver: SocketAddr) -> ResolverConfig {
+    ResolverConfig {
+        nameservers: vec![nameserver],
+        timeout: Duration::from_millis(500),
+        ..ResolverConfig::default()
+    }
+}
+
+#[test]
+fn test_resolves_through_configured_nameserver() {
+    let (nameserver, _) = fake_nameserver();
+    let resolver = Resolver::new(config(nameserver));
+    
+    let addresses = resolver.resolve("example.test").unwrap();
+    assert_eq!(addresses, vec!["192.0.2.1".parse::<IpAddr>().unwrap(), "2001:db8::1".parse().unwrap()]);
+}
+
+#[test]
+fn test_answers_are_cached_for_their_ttl() {
+    let (nameserver, queries) = fake_nameserver();
+    let resolver = Resolver::new(config(nameserver));
+    
+    assert!(resolver.cached("example.test").is_none());
+    resolver.resolve("example.test").unwrap();
+    resolver.resolve("EXAMPLE.test").unwrap();
+    assert!(resolver.cached("example.test").unwrap().is_ok());
+    // One A and one AAAA query
+    assert_eq!(queries.load(Ordering::SeqCst), 2);
+    
+    thread::sleep(Duration::from_millis(1100));
+    assert!(resolver.cached("example.test").is_none());
+    resolver.resolve("example.test").unwrap();
+    assert_eq!(queries.load(Ordering::SeqCst), 4);
+}
+
+#[test]
+fn test_failures_are_cached_for_negative_ttl() {
+    let (nameserver, queries) = fake_nameserver();
+    let resolver = Resolver::new(ResolverConfig {
+        negative_ttl: Duration::from_millis(200),
+        ..config(nameserver)
+    });
+    
+    assert!(resolver.resolve("missing.test").is_err());
+    assert!(resolver.resolve("missing.test").is_err());
+    assert_eq!(queries.load(Ordering::SeqCst), 2);
+    
+    thread::sleep(Duration::from_millis(300));
+    assert!(resolver.resolve("missing.test").is_err());
+    assert_eq!(queries.load(Ordering::SeqCst), 4);
+}
+
+#[test]
+fn test_unreachable_nameserver_fails_over() {
+    let (nameserver, _) = fake_nameserver();
+    // Nothing answers on this port
+    let silent = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let resolver = Resolver::new(ResolverConfig {
+        nameservers: vec![silent.local_addr().unwrap(), nameserver],
+        timeout: Duration::from_millis(100),
+        ..ResolverConfig::default()
+    });
+    
+    assert_eq!(resolver.resolve("example.test").unwrap().len(), 2);
+}
+
+#[test]
+fn test_resolve_async_runs_on_pool() {
+    let (nameserver, _) = fake_nameserver();
+    let resolver = Resolver::new(config(nameserver));
+    
+    let (tx, rx) = mpsc::channel();
+    resolver.resolve_async("example.test", move |result| {
+        tx.send((thread::current().name().map(String::from), result.unwrap())).unwrap();
+    });
+    let (thread_name, addresses) = rx.recv_timeout(Duration::from_secs(2)).unwrap();
+    assert!(thread_name.unwrap().starts_with("resolver-"));
+    assert_eq!(addresses.len(), 2);
+    
+    // A cached answer is passed straight away
+    let (tx, rx) = mpsc::channel();
+    resolver.resolve_async("example.test", move |result| tx.send(result.unwrap()).unwrap());
+    assert_eq!(rx.try_recv().unwrap().len(), 2);
+}
+
+#[test]
+fn test_ip_literals_and_system_resolver() {
+    let resolver = Resolver::new(ResolverConfig::default());
+    
+    assert_eq!(resolver.resolve("10.1.2.3").unwrap(), vec!["10.1.2.3".parse::<IpAddr>().unwrap()]);
+    assert!(resolver.resolve("localhost").unwrap().iter().any(|ip| ip.is_loopback()));
+}
+
+#[test]
+fn test_resolve_address_keeps_the_port() {
+    let resolver = Resolver::new(ResolverConfig::default());
+    
+    assert_eq!(resolver.resolve_address("10.1.2.3:6379").unwrap(), vec!["10.1.2.3:6379".parse().unwrap()]);
+    assert_eq!(resolver.resolve_address("[::1]:8080").unwrap(), vec!["[::1]:8080".parse().unwrap()]);
+    let local = resolver.resolve_address("localhost:389").unwrap();
+    assert!(local.iter().all(|addr| addr.port() == 389 && addr.ip().is_loopback()), "{:?}", local);
+    assert!(resolver.resolve_address("localhost").is_err());
+    assert!(resolver.resolve_address("localhost:http").is_err());
+}
+
+#[test]
+fn test_clients_share_one_resolver() {
+    let shared = resolver::shared();
+    assert!(Arc::ptr_eq(&shared, &resolver::shared()));
+    // Too late to replace it once it is in use
+    assert!(resolver::set_shared(Arc::new(Resolver::new(ResolverConfig::default()))).is_err());
+}
+
+#[test]
+fn test_lookup_metrics() {
+    let (nameserver, _) = fake_nameserver();
+    let registry = Arc::new(MetricsRegistry::new());
+    let resolver = Resolver::with_metrics(config(nameserver), registry.clone());
+    
+    resolver.resolve("example.test").unwrap();
+    resolver.resolve("example.test").unwrap();
+    let _ = resolver.resolve("missing.test");
+    
+    assert_eq!(registry.counter("dns.cache_misses").value(), 2);
+    assert_eq!(registry.counter("dns.cache_hits").value(), 1);
+    assert_eq!(registry.counter("dns.failures").value(), 1);
+    assert_eq!(registry.exponential_histogram("dns.lookup_us", 1.0, 2.0, 24).count(), 2);
+}
\ No newline at end of file
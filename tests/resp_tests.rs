@@ -0,0 +1,141 @@
+use high_performance_server::resp::{decode, encode_command};
+use high_performance_server::{Pipeline, RespClient, RespValue};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn test_decode_waits_for_whole_values() {
+    let reply = b"*3\r\n$5\r\nhello\r\n:-7\r\n$-1\r\n+OK\r\n";
+    for end in 0..14 {
+        assert_eq!(decode(&reply[..end]).unwrap(), None, "decoded from {} bytes", end);
+    }
+    let (value, used) = decode(reply).unwrap().unwrap();
+    assert_eq!(
+        value,
+        RespValue::Array(Some(vec![
+            RespValue::Bulk(Some(b"hello".to_vec())),
+            RespValue::Integer(-7),
+            RespValue::Bulk(None),
+        ]))
+    );
+    assert_eq!(decode(&reply[used..]).unwrap(), Some((RespValue::Simple("OK".to_string()), 5)));
+    
+    assert!(decode(b"?what\r\n").is_err());
+    assert!(decode(b"$3\r\nabcd\r\n").is_err());
+    
+    let mut out = Vec::new();
+    encode_command(&["SET", "key", "a b"], &mut out);
+    assert_eq!(out, b"*3\r\n$3\r\nSET\r\n$3\r\nkey\r\n$3\r\na b\r\n");
+}
+
+/// Read one RESP array of bulk strings
+fn read_command(reader: &mut impl BufRead) -> Option<Vec<String>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line).ok()? == 0 {
+        return None;
+    }
+    let count: usize = line.trim_end().strip_prefix('*')?.parse().ok()?;
+    let mut args = Vec::new();
+    for _ in 0..count {
+        line.clear();
+        reader.read_line(&mut line).ok()?;
+        let length: usize = line.trim_end().strip_prefix('$')?.parse().ok()?;
+        let mut arg = vec![0u8; length + 2];
+        reader.read_exact(&mut arg).ok()?;
+        args.push(String::from_utf8_lossy(&arg[..length]).to_string());
+    }
+    Some(args)
+}
+
+/// A tiny Redis with GET, SET, INCR, PEXPIRE and DEL, logging every command
+fn fake_redis() -> (String, Arc<Mutex<Vec<String>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let store = Arc::new(Mutex::new(HashMap::<String, String>::new()));
+    let commands = log.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let (log, store) = (commands.clone(), store.clone());
+            thread::spawn(move || {
+                while let Some(args) = read_command(&mut reader) {
+                    log.lock().unwrap().push(args.join(" "));
+                    let mut store = store.lock().unwrap();
+                    let reply = match args[0].as_str() {
+                        "GET" => match store.get(&args[1]) {
+                            Some(value) => format!("${}\r\n{}\r\n", value.len(), value),
+                            None => "$-1\r\n".to_string(),
+                        },
+                        "SET" => {
+                            store.insert(args[1].clone(), args[2].clone());
+                            "+OK\r\n".to_string()
+                        }
+                        "INCR" => match store.get(&args[1]).map_or(Ok(0), |value| value.parse::<i64>()) {
+                            Ok(value) => {
+                                store.insert(args[1].clone(), (value + 1).to_string());
+                                format!(":{}\r\n", value + 1)
+                            }
+                            Err(_) => "-ERR value is not an integer\r\n".to_string(),
+                        },
+                        "PEXPIRE" => format!(":{}\r\n", store.contains_key(&args[1]) as i64),
+                        "DEL" => format!(":{}\r\n", store.remove(&args[1]).is_some() as i64),
+                        "AUTH" => "+OK\r\n".to_string(),
+                        _ => "-ERR unknown command\r\n".to_string(),
+                    };
+                    if stream.write_all(reply.as_bytes()).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+    (address, log)
+}
+
+#[test]
+fn test_client_commands() {
+    let (address, log) = fake_redis();
+    let client = RespClient::new(&address).with_password("hunter2");
+    
+    assert_eq!(client.get("missing").unwrap(), None);
+    client.set("greeting", b"hi", Some(Duration::from_secs(2))).unwrap();
+    assert_eq!(client.get("greeting").unwrap(), Some(b"hi".to_vec()));
+    assert_eq!(client.incr("hits").unwrap(), 1);
+    assert_eq!(client.incr("hits").unwrap(), 2);
+    assert!(client.expire("hits", Duration::from_secs(60)).unwrap());
+    assert!(!client.expire("missing", Duration::from_secs(60)).unwrap());
+    assert!(client.del("hits").unwrap());
+    assert!(client.incr("greeting").is_err());
+    
+    let log = log.lock().unwrap();
+    assert_eq!(log[0], "AUTH hunter2");
+    assert_eq!(log[2], "SET greeting hi PX 2000");
+    assert_eq!(log[6], "PEXPIRE hits 60000");
+    assert_eq!(log.iter().filter(|command| command.starts_with("AUTH")).count(), 1);
+}
+
+#[test]
+fn test_pipeline_reads_a_reply_per_command() {
+    let (address, log) = fake_redis();
+    let client = RespClient::new(&address);
+    
+    let mut pipeline = Pipeline::new();
+    pipeline.cmd(&["SET", "n", "x"]).cmd(&["INCR", "n"]).cmd(&["DEL", "n"]).cmd(&["INCR", "n"]);
+    let replies = client.pipeline(&pipeline).unwrap();
+    assert_eq!(
+        replies,
+        vec![
+            RespValue::Simple("OK".to_string()),
+            RespValue::Error("ERR value is not an integer".to_string()),
+            RespValue::Integer(1),
+            RespValue::Integer(1),
+        ]
+    );
+    assert_eq!(log.lock().unwrap().len(), 4);
+}
\ No newline at end of file
@@ -0,0 +1,45 @@
+use high_performance_server::scan::{self, scalar};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+#[test]
+fn test_every_byte_in_every_lane_matches_scalar() {
+    // Long enough for two full chunks and a remainder, so each lane of each path is covered
+    for len in [1, 15, 16, 17, 40] {
+        for position in 0..len {
+            for byte in 0..=255u8 {
+                let mut name = vec![b'a'; len];
+                name[position] = byte;
+                assert_eq!(scan::is_token(&name), scalar::is_token(&name), "{:?} at {} of {}", byte, position, len);
+                
+                let mut value = vec![b' '; len];
+                value[position] = byte;
+                assert_eq!(
+                    scan::is_header_value(&value),
+                    scalar::is_header_value(&value),
+                    "{:?} at {} of {}",
+                    byte,
+                    position,
+                    len
+                );
+            }
+        }
+    }
+    assert!(!scan::is_token(b""));
+    assert!(scan::is_header_value(b""));
+}
+
+#[test]
+fn test_random_heads_match_scalar() {
+    let mut rng = StdRng::seed_from_u64(3476);
+    let alphabet = b"aZ09-_:\r\n \t\x7f\x00\x80\xff";
+    for _ in 0..2000 {
+        let len = rng.gen_range(0..80);
+        let bytes: Vec<u8> = (0..len).map(|_| alphabet[rng.gen_range(0..alphabet.len())]).collect();
+        
+        assert_eq!(scan::find_head_end(&bytes), scalar::find_head_end(&bytes), "{:?}", bytes);
+        assert_eq!(scan::find_byte(b':', &bytes), scalar::find_byte(b':', &bytes), "{:?}", bytes);
+        assert_eq!(scan::is_token(&bytes), scalar::is_token(&bytes), "{:?}", bytes);
+        assert_eq!(scan::is_header_value(&bytes), scalar::is_header_value(&bytes), "{:?}", bytes);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,131 @@
+use high_performance_server::metrics::MetricsRegistry;
+use high_performance_server::{Job, MissedRuns, Schedule, Scheduler, ServerError};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// 2024-01-01T00:00:00Z, a Monday
+const NEW_YEAR: u64 = 1_704_067_200;
+
+fn at(secs: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(secs)
+}
+
+fn next(expression: &str, after: u64) -> Option<u64> {
+    let schedule = Schedule::cron(expression).unwrap();
+    schedule.next_after(at(after)).map(|time| time.duration_since(UNIX_EPOCH).unwrap().as_secs())
+}
+
+#[test]
+fn test_cron_next_times() {
+    assert_eq!(next("* * * * *", NEW_YEAR), Some(NEW_YEAR + 60));
+    assert_eq!(next("*/15 * * * *", NEW_YEAR + 61), Some(NEW_YEAR + 15 * 60));
+    assert_eq!(next("30 9 * * *", NEW_YEAR), Some(NEW_YEAR + 9 * 3600 + 30 * 60));
+    assert_eq!(next("@daily", NEW_YEAR), Some(NEW_YEAR + 86_400));
+    // Next Saturday, then Sunday written as 7
+    assert_eq!(next("0 0 * * SAT", NEW_YEAR), Some(NEW_YEAR + 5 * 86_400));
+    assert_eq!(next("0 0 * * 7", NEW_YEAR), Some(NEW_YEAR + 6 * 86_400));
+    assert_eq!(next("0 12 * FEB MON-FRI", NEW_YEAR), Some(NEW_YEAR + 31 * 86_400 + 12 * 3600));
+    // The 29th of February comes around in 2024
+    assert_eq!(next("0 0 29 2 *", NEW_YEAR), Some(NEW_YEAR + 59 * 86_400));
+    // With both day fields restricted, either matches: the 10th or the first Friday
+    assert_eq!(next("0 0 10 * FRI", NEW_YEAR), Some(NEW_YEAR + 4 * 86_400));
+    assert_eq!(next("0 0 30 2 *", NEW_YEAR), None);
+}
+
+#[test]
+fn test_invalid_cron_expressions() {
+    for expression in ["", "* * * *", "60 * * * *", "* 24 * * *", "* * 0 * *", "*/0 * * * *", "5-1 * * * *", "@often"] {
+        match Schedule::cron(expression) {
+            Err(ServerError::Config(_)) => {}
+            other => panic!("{:?} parsed as {:?}", expression, other),
+        }
+    }
+    let scheduler = Scheduler::new();
+    assert!(scheduler.add(Job::new("never", Schedule::cron("0 0 31 2 *").unwrap(), || Ok(()))).is_err());
+}
+
+fn counting_job(name: &str, policy: MissedRuns, runs: &Arc<AtomicUsize>) -> Job {
+    let runs = runs.clone();
+    Job::new(name, Schedule::every(Duration::from_secs(10)), move || {
+        runs.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    })
+    .with_missed_runs(policy)
+}
+
+#[test]
+fn test_missed_run_policies() {
+    let registry = Arc::new(MetricsRegistry::new());
+    let scheduler = Scheduler::with_metrics(registry.clone());
+    let counts: Vec<Arc<AtomicUsize>> = (0..3).map(|_| Arc::new(AtomicUsize::new(0))).collect();
+    scheduler.add(counting_job("skip", MissedRuns::Skip, &counts[0])).unwrap();
+    scheduler.add(counting_job("once", MissedRuns::RunOnce, &counts[1])).unwrap();
+    scheduler.add(counting_job("all", MissedRuns::RunAll, &counts[2])).unwrap();
+    
+    let first = scheduler.next_run("all").unwrap();
+    assert_eq!(scheduler.run_due(first - Duration::from_secs(1)), 0);
+    
+    // Five intervals late, a bit after the fifth run was due
+    let late = first + Duration::from_millis(40_500);
+    scheduler.run_due(late);
+    assert_eq!(counts[0].load(Ordering::SeqCst), 1);
+    assert_eq!(counts[1].load(Ordering::SeqCst), 1);
+    assert_eq!(counts[2].load(Ordering::SeqCst), 5);
+    assert_eq!(registry.counter("scheduler.once.missed").value(), 4);
+    assert_eq!(registry.counter("scheduler.all.runs").value(), 5);
+    assert!(scheduler.next_run("all").unwrap() > late);
+    
+    // Too late for Skip to run at all
+    let later = scheduler.next_run("skip").unwrap() + Duration::from_secs(5);
+    scheduler.run_due(later);
+    assert_eq!(counts[0].load(Ordering::SeqCst), 1);
+    assert_eq!(counts[1].load(Ordering::SeqCst), 2);
+    
+    assert!(scheduler.remove("all"));
+    assert!(!scheduler.remove("all"));
+    assert_eq!(scheduler.next_run("all"), None);
+}
+
+#[test]
+fn test_failures_are_counted_and_runs_continue() {
+    let registry = Arc::new(MetricsRegistry::new());
+    let scheduler = Scheduler::with_metrics(registry.clone());
+    scheduler
+        .add(Job::new("failing", Schedule::every(Duration::from_secs(1)), || Err(ServerError::Config("no".into()))))
+        .unwrap();
+    scheduler.add(Job::new("panicking", Schedule::every(Duration::from_secs(1)), || panic!("boom"))).unwrap();
+    
+    let mut now = SystemTime::now();
+    for _ in 0..3 {
+        now += Duration::from_secs(1);
+        scheduler.run_due(now + Duration::from_millis(500));
+    }
+    for job in ["failing", "panicking"] {
+        assert_eq!(registry.counter(&format!("scheduler.{}.runs", job)).value(), 3);
+        assert_eq!(registry.counter(&format!("scheduler.{}.failures", job)).value(), 3);
+    }
+}
+
+#[test]
+fn test_thread_runs_jobs_until_stopped() {
+    let scheduler = Scheduler::new();
+    let runs = Arc::new(AtomicUsize::new(0));
+    let counter = runs.clone();
+    let job = Job::new("tick", Schedule::every(Duration::from_millis(20)), move || {
+        counter.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    })
+    .with_jitter(Duration::from_millis(5));
+    scheduler.add(job).unwrap();
+    scheduler.start();
+    scheduler.start();
+    
+    thread::sleep(Duration::from_millis(300));
+    scheduler.stop();
+    let stopped = runs.load(Ordering::SeqCst);
+    assert!(stopped >= 3, "only {} runs", stopped);
+    thread::sleep(Duration::from_millis(100));
+    assert_eq!(runs.load(Ordering::SeqCst), stopped);
+}
\ No newline at end of file
@@ -0,0 +1,62 @@
+use high_performance_server::tenants::TenantsConfig;
+use high_performance_server::{Secret, ServerConfig, ServerError};
+use std::env;
+use std::fs;
+
+fn temp_file(name: &str, content: &str) -> String {
+    let path = env::temp_dir().join(format!("hps-secret-{}-{}", name, std::process::id()));
+    fs::write(&path, content).unwrap();
+    path.display().to_string()
+}
+
+#[test]
+fn test_references_resolve() {
+    let path = temp_file("key", "s3cr3t\n");
+    let secret = Secret::resolve(&format!("@file:{}", path)).unwrap();
+    assert_eq!(secret.expose(), "s3cr3t");
+    assert!(secret.is_reference());
+    // The reference is shown and saved, never the secret
+    assert_eq!(format!("{:?}", secret), format!("Secret(@file:{})", path));
+    assert_eq!(serde_json::to_string(&secret).unwrap(), format!("\"@file:{}\"", path));
+    fs::remove_file(&path).unwrap();
+    
+    env::set_var("HPS_TEST_SECRET", "from-env");
+    assert_eq!(Secret::resolve("@env:HPS_TEST_SECRET").unwrap().expose(), "from-env");
+    
+    let literal = Secret::resolve("plain").unwrap();
+    assert_eq!(literal.expose(), "plain");
+    assert!(!format!("{:?}", literal).contains("plain"));
+    assert_eq!(Secret::resolve("@@home").unwrap().expose(), "@home");
+    assert_eq!(serde_json::to_string(&Secret::literal("@home")).unwrap(), "\"@@home\"");
+}
+
+#[test]
+fn test_bad_references_are_errors() {
+    for source in ["@fle:/etc/key", "@env:HPS_TEST_SECRET_UNSET", "@file:/nonexistent/hps/key"] {
+        match Secret::resolve(source) {
+            Err(ServerError::Config(_)) => {}
+            other => panic!("{} resolved to {:?}", source, other),
+        }
+    }
+}
+
+#[test]
+fn test_configs_resolve_secrets_when_loaded() {
+    let path = temp_file("tenant", "acme-key\r\n");
+    let json = format!(r#"{{"tenants": [{{"id": "acme", "api_keys": ["@file:{}", "spare-key"]}}]}}"#, path);
+    let tenants: TenantsConfig = serde_json::from_str(&json).unwrap();
+    let keys: Vec<&str> = tenants.tenants[0].api_keys.iter().map(Secret::expose).collect();
+    assert_eq!(keys, ["acme-key", "spare-key"]);
+    fs::remove_file(&path).unwrap();
+    
+    env::set_var("HPS_TEST_WEBHOOK", "http://127.0.0.1:9/events?token=abc");
+    let mut json = serde_json::to_value(ServerConfig::default()).unwrap();
+    json["event_webhook"] = "@env:HPS_TEST_WEBHOOK".into();
+    let config = ServerConfig::from_json(&json.to_string()).unwrap();
+    assert_eq!(config.event_webhook.as_ref().unwrap().expose(), "http://127.0.0.1:9/events?token=abc");
+    assert!(!format!("{:?}", config).contains("token=abc"));
+    
+    json["event_webhook"] = "@env:HPS_TEST_WEBHOOK_UNSET".into();
+    let message = ServerConfig::from_json(&json.to_string()).unwrap_err().to_string();
+    assert!(message.contains("HPS_TEST_WEBHOOK_UNSET is not set"), "{}", message);
+}
\ No newline at end of file
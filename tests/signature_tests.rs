@@ -0,0 +1,79 @@
+use high_performance_server::http::{Method, Request, Response, Status};
+use high_performance_server::middleware::MiddlewareChain;
+use high_performance_server::signature::{
+    sign_github, sign_timestamped, signature_middleware, SignatureConfig, SignatureVerifier,
+};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+fn signed_request(secret: &[u8], timestamp: u64, body: &[u8]) -> Request {
+    let mut request = Request::new(Method::Post, "/hooks/deploy?env=prod");
+    request.set_body(body);
+    request.set_header("X-Signature-Timestamp", &timestamp.to_string());
+    request.set_header(
+        "X-Signature",
+        &sign_timestamped(secret, timestamp, "POST", "/hooks/deploy?env=prod", body),
+    );
+    request
+}
+
+#[test]
+fn test_github_signature() {
+    // Example from GitHub's webhook documentation
+    let secret = b"It's a Secret to Everybody";
+    assert_eq!(
+        sign_github(secret, b"Hello, World!"),
+        "sha256=757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17"
+    );
+    
+    let verifier = SignatureVerifier::new(SignatureConfig::github(secret));
+    let mut request = Request::new(Method::Post, "/webhook");
+    request.set_body(b"Hello, World!");
+    assert!(verifier.verify(&request).is_err());
+    
+    request.set_header("X-Hub-Signature-256", &sign_github(secret, b"Hello, World!"));
+    assert!(verifier.verify(&request).is_ok());
+    
+    request.set_body(b"Hello, World?");
+    assert!(verifier.verify(&request).is_err());
+}
+
+#[test]
+fn test_timestamped_signature_window_and_replay() {
+    let verifier = SignatureVerifier::new(SignatureConfig::timestamped(b"secret").with_tolerance(Duration::from_secs(60)));
+    let now = 1_700_000_000;
+    
+    let request = signed_request(b"secret", now - 30, b"{}");
+    assert!(verifier.verify_at(&request, now).is_ok());
+    
+    // The same signed request can't be replayed
+    assert_eq!(verifier.verify_at(&request, now).unwrap_err(), "Signature has already been used");
+    
+    let stale = signed_request(b"secret", now - 120, b"{}");
+    assert!(verifier.verify_at(&stale, now).is_err());
+    
+    let wrong_key = signed_request(b"other", now, b"{}");
+    assert_eq!(verifier.verify_at(&wrong_key, now).unwrap_err(), "Signature does not match");
+}
+
+#[test]
+fn test_secret_rotation() {
+    let verifier = SignatureVerifier::new(SignatureConfig::timestamped(b"new").with_secret(b"old"));
+    let now = 1_700_000_000;
+    
+    assert!(verifier.verify_at(&signed_request(b"new", now, b"a"), now).is_ok());
+    assert!(verifier.verify_at(&signed_request(b"old", now, b"b"), now).is_ok());
+}
+
+#[test]
+fn test_signature_middleware() {
+    let mut chain = MiddlewareChain::new();
+    chain.add(signature_middleware(SignatureConfig::timestamped(b"secret")));
+    chain.set_handler(|_| Ok(Response::new(Status::Ok)));
+    
+    let unsigned = Request::new(Method::Post, "/hooks/deploy?env=prod");
+    assert_eq!(chain.handle(&unsigned).unwrap().status, Status::Unauthorized);
+    
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    let signed = signed_request(b"secret", now, b"payload");
+    assert_eq!(chain.handle(&signed).unwrap().status, Status::Ok);
+}
\ No newline at end of file
@@ -0,0 +1,115 @@
+use high_performance_server::{
+    single_flight_middleware, Method, MiddlewareChain, Request, Response, ServerError, SingleFlight, Status,
+};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Barrier};
+use std::thread;
+use std::time::Duration;
+
+/// A slow handler counting its calls, answering with `headers` set
+fn slow_handler(
+    calls: Arc<AtomicUsize>,
+    headers: &'static [(&'static str, &'static str)],
+) -> impl Fn(&Request) -> Result<Response, ServerError> + Send + Sync {
+    move |req| {
+        let call = calls.fetch_add(1, Ordering::SeqCst) + 1;
+        thread::sleep(Duration::from_millis(100));
+        let mut response = Response::new(Status::Ok);
+        response.set_body(format!("{} call {}", req.uri, call).as_bytes());
+        for (name, value) in headers {
+            response.set_header(name, value);
+        }
+        Ok(response)
+    }
+}
+
+/// Send `count` copies of `request` through `chain` at once
+fn concurrent(chain: &Arc<MiddlewareChain>, request: Request, count: usize) -> Vec<Response> {
+    let barrier = Arc::new(Barrier::new(count));
+    let workers: Vec<_> = (0..count)
+        .map(|_| {
+            let chain = chain.clone();
+            let request = request.clone();
+            let barrier = barrier.clone();
+            thread::spawn(move || {
+                barrier.wait();
+                chain.handle(&request).unwrap()
+            })
+        })
+        .collect();
+    workers.into_iter().map(|worker| worker.join().unwrap()).collect()
+}
+
+fn chain_with<F>(flight: &Arc<SingleFlight>, handler: F) -> Arc<MiddlewareChain>
+where
+    F: Fn(&Request) -> Result<Response, ServerError> + Send + Sync + 'static,
+{
+    let mut chain = MiddlewareChain::new();
+    chain.add(single_flight_middleware(flight.clone()));
+    chain.set_handler(handler);
+    Arc::new(chain)
+}
+
+#[test]
+fn test_identical_gets_share_one_call() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let flight = Arc::new(SingleFlight::new());
+    let chain = chain_with(&flight, slow_handler(calls.clone(), &[]));
+    
+    let responses = concurrent(&chain, Request::new(Method::Get, "/hot"), 8);
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+    assert_eq!(flight.coalesced(), 7);
+    assert!(responses.iter().all(|response| response.body == b"/hot call 1"));
+    
+    // Once finished, the next request runs the handler again
+    assert_eq!(chain.handle(&Request::new(Method::Get, "/hot")).unwrap().body, b"/hot call 2");
+}
+
+#[test]
+fn test_different_or_personal_requests_run_alone() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let flight = Arc::new(SingleFlight::new());
+    let chain = chain_with(&flight, slow_handler(calls.clone(), &[]));
+    
+    let mut with_cookie = Request::new(Method::Get, "/hot");
+    with_cookie.set_header("Cookie", "session=abc");
+    concurrent(&chain, with_cookie, 3);
+    assert_eq!(calls.load(Ordering::SeqCst), 3);
+    
+    concurrent(&chain, Request::new(Method::Post, "/hot"), 3);
+    assert_eq!(calls.load(Ordering::SeqCst), 6);
+    
+    // Requests asking for other representations are not identical
+    let mut gzip = Request::new(Method::Get, "/hot");
+    gzip.set_header("Accept-Encoding", "gzip");
+    let plain = Request::new(Method::Get, "/hot");
+    let gzip_chain = chain.clone();
+    let worker = thread::spawn(move || gzip_chain.handle(&gzip).unwrap());
+    chain.handle(&plain).unwrap();
+    worker.join().unwrap();
+    assert_eq!(calls.load(Ordering::SeqCst), 8);
+    assert_eq!(flight.coalesced(), 0);
+}
+
+#[test]
+fn test_private_responses_are_not_shared() {
+    // Header names match in any case
+    let personal: [&'static [(&'static str, &'static str)]; 3] = [
+        &[("Cache-Control", "private, max-age=60")],
+        &[("cache-control", "no-store")],
+        &[("set-cookie", "session=abc")],
+    ];
+    for headers in personal {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let flight = Arc::new(SingleFlight::new());
+        let chain = chain_with(&flight, slow_handler(calls.clone(), headers));
+        
+        let responses = concurrent(&chain, Request::new(Method::Get, "/me"), 4);
+        assert_eq!(calls.load(Ordering::SeqCst), 4, "{:?}", headers);
+        assert_eq!(flight.coalesced(), 0);
+        let mut bodies: Vec<_> = responses.iter().map(|response| response.body.clone()).collect();
+        bodies.sort();
+        bodies.dedup();
+        assert_eq!(bodies.len(), 4);
+    }
+}
\ No newline at end of file
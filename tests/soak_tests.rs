@@ -0,0 +1,345 @@
+//! Long-running soak test of the connection lifecycle
+//!
+//! Ignored by default; run it with
+//! `cargo test --release --test soak_tests -- --ignored --nocapture`.
+//! `SOAK_SECONDS`, `SOAK_CLIENTS` and `SOAK_SEED` change how long it runs,
+//! how many keep-alive connections it holds and which choices it makes.
+
+use high_performance_server::leaks::{self, InstanceCounts};
+use high_performance_server::{
+    ConnectionAcceptor, EventLoop, MetricsCollector, Response, Router, ServerConfig, Status, Tracked, WorkerLoad,
+};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use socket2::SockRef;
+use std::env;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Barrier};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const WORKERS: usize = 2;
+const CLIENT_THREADS: usize = 16;
+const LARGE_BODY: usize = 256 * 1024;
+
+fn setting(name: &str, default: u64) -> u64 {
+    env::var(name).ok().and_then(|value| value.parse().ok()).unwrap_or(default)
+}
+
+/// Count this process's open file descriptors
+fn open_fds() -> usize {
+    fs::read_dir("/proc/self/fd").map_or(0, |entries| entries.count())
+}
+
+/// Get this process's resident set size in bytes
+fn resident_bytes() -> usize {
+    let statm = fs::read_to_string("/proc/self/statm").unwrap_or_default();
+    let pages: usize = statm.split_whitespace().nth(1).and_then(|pages| pages.parse().ok()).unwrap_or(0);
+    pages * 4096
+}
+
+/// Event loops serving the soak routes, with the accounting the test checks
+struct SoakServer {
+    addr: SocketAddr,
+    drain: Arc<AtomicBool>,
+    load: Arc<WorkerLoad>,
+    metrics: Arc<MetricsCollector>,
+    /// Instances alive once the loops were ready
+    instances: Vec<(Tracked, InstanceCounts)>,
+    workers: Vec<thread::JoinHandle<()>>,
+}
+
+impl SoakServer {
+    fn start() -> Self {
+        let mut router = Router::new();
+        router.get("/hello", |_| {
+            let mut response = Response::new(Status::Ok);
+            response.set_body(b"hello");
+            Ok(response)
+        });
+        router.get("/large", |_| {
+            let mut response = Response::new(Status::Ok);
+            response.set_body(&vec![b'z'; LARGE_BODY]);
+            Ok(response)
+        });
+        router.post("/echo", |req| {
+            let mut response = Response::new(Status::Ok);
+            response.set_body(&req.body);
+            Ok(response)
+        });
+        let router = Arc::new(router);
+        
+        let config = ServerConfig {
+            keep_alive_timeout: Duration::from_secs(120),
+            connection_timeout: Duration::from_secs(120),
+            ..ServerConfig::default()
+        };
+        let acceptor = Arc::new(ConnectionAcceptor::new("127.0.0.1:0").unwrap());
+        let addr = acceptor.local_addr().unwrap();
+        let drain = Arc::new(AtomicBool::new(false));
+        let load = Arc::new(WorkerLoad::new(WORKERS));
+        let metrics = Arc::new(MetricsCollector::new());
+        // Wait for every loop to open its poller, so descriptor counts start from a settled process
+        let ready = Arc::new(Barrier::new(WORKERS + 1));
+        let workers = (0..WORKERS)
+            .map(|id| {
+                let (acceptor, config, router) = (acceptor.clone(), config.clone(), router.clone());
+                let (drain, load, metrics, ready) = (drain.clone(), load.clone(), metrics.clone(), ready.clone());
+                thread::Builder::new()
+                    .name(format!("soak-worker-{}", id))
+                    .spawn(move || {
+                        let mut event_loop = EventLoop::with_config(id as u32, acceptor, config);
+                        event_loop.set_router(router);
+                        event_loop.set_drain_signal(drain);
+                        event_loop.set_worker_load(load);
+                        event_loop.set_metrics(metrics);
+                        ready.wait();
+                        event_loop.run().unwrap();
+                    })
+                    .unwrap()
+            })
+            .collect();
+        ready.wait();
+        
+        Self {
+            addr,
+            drain,
+            load,
+            metrics,
+            instances: leaks::snapshot(),
+            workers,
+        }
+    }
+    
+    /// Connections the event loops still hold, by their own and the metrics' count
+    fn held_connections(&self) -> (usize, usize) {
+        let registry = self.metrics.registry();
+        let gauged = (0..WORKERS)
+            .map(|id| registry.gauge(&format!("workers.{}.open_connections", id)).value())
+            .sum();
+        ((0..WORKERS).map(|id| self.load.active(id)).sum(), gauged)
+    }
+    
+    /// Wait until no connection is held and the process is back to `fds` descriptors
+    fn wait_until_idle(&self, fds: usize) {
+        let deadline = Instant::now() + Duration::from_secs(15);
+        while (self.held_connections() != (0, 0) || open_fds() > fds) && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(50));
+        }
+        assert_eq!(self.held_connections(), (0, 0), "connections left in the event loops");
+        assert_eq!(open_fds(), fds, "file descriptors left open");
+        let invalid = self.metrics.registry().counter("connections.invalid_state_transitions").value();
+        assert_eq!(invalid, 0, "connections made invalid state transitions");
+        leaks::check_balanced(&self.instances).unwrap();
+    }
+    
+    fn stop(self) {
+        self.drain.store(true, Ordering::SeqCst);
+        for worker in self.workers {
+            worker.join().unwrap();
+        }
+    }
+}
+
+/// Read one response off `stream`, returning its status and body length
+fn read_response(stream: &mut TcpStream, slow: bool) -> io::Result<(u16, usize)> {
+    let mut head = Vec::new();
+    let mut byte = [0u8; 1];
+    while !head.ends_with(b"\r\n\r\n") {
+        if stream.read(&mut byte)? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        head.push(byte[0]);
+    }
+    let head = String::from_utf8_lossy(&head);
+    let status = head.get(9..12).and_then(|status| status.parse().ok()).unwrap_or(0);
+    let length = head
+        .lines()
+        .find_map(|line| line.strip_prefix("Content-Length: "))
+        .map_or(0, |length| length.trim().parse().unwrap_or(0));
+    
+    let mut body = vec![0u8; length];
+    let chunk = if slow { 4096 } else { length.max(1) };
+    for part in body.chunks_mut(chunk) {
+        stream.read_exact(part)?;
+        if slow {
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+    Ok((status, length))
+}
+
+/// What the clients saw, for the report
+#[derive(Default)]
+struct Tally {
+    responses: AtomicUsize,
+    sabotaged: AtomicUsize,
+    failures: AtomicUsize,
+}
+
+/// One client thread's connections and the misbehaviour it picks for them
+struct Client {
+    addr: SocketAddr,
+    rng: StdRng,
+    connections: Vec<Option<TcpStream>>,
+}
+
+impl Client {
+    fn connect(&self) -> io::Result<TcpStream> {
+        let stream = TcpStream::connect(self.addr)?;
+        stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+        stream.set_write_timeout(Some(Duration::from_secs(10)))?;
+        Ok(stream)
+    }
+    
+    /// Exercise one randomly picked connection in one randomly picked way
+    fn step(&mut self, tally: &Tally) -> io::Result<()> {
+        let slot = self.rng.gen_range(0..self.connections.len());
+        let mut stream = match self.connections[slot].take() {
+            Some(stream) => stream,
+            None => self.connect()?,
+        };
+        
+        let kept = match self.rng.gen_range(0..100) {
+            // Plain keep-alive request
+            0..=49 => {
+                stream.write_all(b"GET /hello HTTP/1.1\r\nHost: soak\r\n\r\n")?;
+                self.expect(&mut stream, 5, false, tally)?;
+                true
+            }
+            // Request written a few bytes at a time
+            50..=59 => {
+                let request = b"POST /echo HTTP/1.1\r\nHost: soak\r\nContent-Length: 11\r\n\r\nhello world";
+                for part in request.chunks(7) {
+                    stream.write_all(part)?;
+                    stream.flush()?;
+                    thread::sleep(Duration::from_micros(200));
+                }
+                self.expect(&mut stream, 11, false, tally)?;
+                true
+            }
+            // Pipelined requests
+            60..=69 => {
+                stream.write_all(b"GET /hello HTTP/1.1\r\nHost: soak\r\n\r\n".repeat(3).as_slice())?;
+                for _ in 0..3 {
+                    self.expect(&mut stream, 5, false, tally)?;
+                }
+                true
+            }
+            // Slow reader of a large response
+            70..=74 => {
+                stream.write_all(b"GET /large HTTP/1.1\r\nHost: soak\r\n\r\n")?;
+                self.expect(&mut stream, LARGE_BODY, true, tally)?;
+                true
+            }
+            // Disconnect halfway through a request
+            75..=84 => {
+                stream.write_all(b"POST /echo HTTP/1.1\r\nHost: soak\r\nContent-Length: 100\r\n\r\npartial")?;
+                false
+            }
+            // Disconnect without reading a large response
+            85..=92 => {
+                stream.write_all(b"GET /large HTTP/1.1\r\nHost: soak\r\n\r\n")?;
+                false
+            }
+            // Reset halfway through reading a response
+            _ => {
+                stream.write_all(b"GET /large HTTP/1.1\r\nHost: soak\r\n\r\n")?;
+                let mut some = [0u8; 1024];
+                stream.read_exact(&mut some)?;
+                SockRef::from(&stream).set_linger(Some(Duration::ZERO))?;
+                false
+            }
+        };
+        
+        if kept {
+            self.connections[slot] = Some(stream);
+        } else {
+            tally.sabotaged.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+    
+    fn expect(&self, stream: &mut TcpStream, length: usize, slow: bool, tally: &Tally) -> io::Result<()> {
+        let response = read_response(stream, slow)?;
+        if response != (200, length) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("unexpected response {:?}", response)));
+        }
+        tally.responses.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+/// Run client threads against `addr` for `duration`, then drop every connection
+fn hammer(addr: SocketAddr, clients: usize, duration: Duration, seed: u64, tally: &Arc<Tally>) {
+    let deadline = Instant::now() + duration;
+    let threads: Vec<_> = (0..CLIENT_THREADS)
+        .map(|i| {
+            let tally = tally.clone();
+            let per_thread = (clients / CLIENT_THREADS).max(1);
+            thread::Builder::new()
+                .name(format!("soak-client-{}", i))
+                .spawn(move || {
+                    let mut client = Client {
+                        addr,
+                        rng: StdRng::seed_from_u64(seed.wrapping_add(i as u64)),
+                        connections: (0..per_thread).map(|_| None).collect(),
+                    };
+                    // Open every connection up front so they are all held at once
+                    for slot in 0..per_thread {
+                        client.connections[slot] = client.connect().ok();
+                    }
+                    while Instant::now() < deadline {
+                        if let Err(e) = client.step(&tally) {
+                            eprintln!("soak client {}: {}", i, e);
+                            tally.failures.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                })
+                .unwrap()
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+}
+
+#[test]
+#[ignore = "long-running; run with --ignored"]
+fn soak_connection_lifecycle() {
+    let duration = Duration::from_secs(setting("SOAK_SECONDS", 60));
+    let clients = setting("SOAK_CLIENTS", 2000) as usize;
+    let seed = setting("SOAK_SEED", 1);
+    
+    let server = SoakServer::start();
+    let fds = open_fds();
+    let tally = Arc::new(Tally::default());
+    
+    // Memory settles after the first half, so growth in the second half is a leak
+    hammer(server.addr, clients, duration / 2, seed, &tally);
+    server.wait_until_idle(fds);
+    let settled = resident_bytes();
+    
+    hammer(server.addr, clients, duration / 2, seed.wrapping_add(1000), &tally);
+    server.wait_until_idle(fds);
+    let grown = resident_bytes().saturating_sub(settled);
+    
+    let responses = tally.responses.load(Ordering::Relaxed);
+    let sabotaged = tally.sabotaged.load(Ordering::Relaxed);
+    let failures = tally.failures.load(Ordering::Relaxed);
+    println!(
+        "soak: {} responses, {} sabotaged connections, {} failures, {} KiB resident growth",
+        responses,
+        sabotaged,
+        failures,
+        grown / 1024
+    );
+    assert!(responses > 0 && sabotaged > 0);
+    assert_eq!(failures, 0, "well-behaved exchanges failed");
+    assert!(grown < 32 * 1024 * 1024, "resident memory grew by {} bytes", grown);
+    
+    server.stop();
+}
\ No newline at end of file
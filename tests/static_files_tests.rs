@@ -0,0 +1,382 @@
+use high_performance_server::http::{Method, Request, Status};
+use high_performance_server::static_files::{
+    add_static_file_routes, write_embedded_dir, CacheRule, CorsRule, EmbeddedDir, StaticFileConfig, LIVE_RELOAD_PATH,
+};
+use high_performance_server::Router;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+fn test_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("hps-static-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("docs")).unwrap();
+    dir
+}
+
+#[test]
+fn test_preload_manifest_adds_link_headers() {
+    let dir = test_dir("preload");
+    fs::write(dir.join("index.html"), "<html></html>").unwrap();
+    fs::write(dir.join("docs/guide.html"), "<html></html>").unwrap();
+    fs::write(dir.join("app.css"), "body {}").unwrap();
+    fs::write(
+        dir.join("manifest.json"),
+        r#"{"index.html": ["/static/app.css", "/static/app.js?v=2", "/static/font.woff2"], "app.css": ["/static/font.woff2"]}"#,
+    )
+    .unwrap();
+    
+    let mut config = StaticFileConfig {
+        root_dir: dir.clone(),
+        ..StaticFileConfig::default()
+    };
+    config.load_preload_manifest(dir.join("manifest.json")).unwrap();
+    let mut router = Router::new();
+    add_static_file_routes(&mut router, config);
+    
+    // Both the prefix and the page itself get the index's preloads
+    for uri in ["/static", "/static/index.html"] {
+        let response = router.handle_request(&Request::new(Method::Get, uri)).unwrap();
+        assert_eq!(response.status, Status::Ok);
+        assert_eq!(response.headers.get("Content-Type").unwrap(), "text/html");
+        assert_eq!(
+            response.headers.get("Link").unwrap(),
+            "</static/app.css>; rel=preload; as=style, \
+             </static/app.js?v=2>; rel=preload; as=script, \
+             </static/font.woff2>; rel=preload; as=font; crossorigin"
+        );
+    }
+    
+    // Pages without an entry and non-HTML files get none
+    let guide = router.handle_request(&Request::new(Method::Get, "/static/docs/guide.html")).unwrap();
+    assert!(!guide.headers.contains_key("Link"));
+    let css = router.handle_request(&Request::new(Method::Get, "/static/app.css")).unwrap();
+    assert_eq!(css.headers.get("Content-Type").unwrap(), "text/css");
+    assert!(!css.headers.contains_key("Link"));
+    
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_invalid_preload_manifest_is_rejected() {
+    let dir = test_dir("bad-manifest");
+    fs::write(dir.join("manifest.json"), r#"{"index.html": "/static/app.css"}"#).unwrap();
+    
+    let mut config = StaticFileConfig::default();
+    assert!(config.load_preload_manifest(dir.join("manifest.json")).is_err());
+    assert!(config.preload.is_empty());
+    
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_fingerprinted_assets() {
+    let dir = test_dir("fingerprint");
+    fs::write(dir.join("docs/app.js"), "console.log(1)").unwrap();
+    fs::write(dir.join("LICENSE"), "MIT").unwrap();
+    
+    let mut config = StaticFileConfig {
+        root_dir: dir.clone(),
+        ..StaticFileConfig::default()
+    };
+    let assets = config.enable_fingerprinting().unwrap();
+    let mut router = Router::new();
+    add_static_file_routes(&mut router, config);
+    
+    let url = assets.url_for("docs/app.js");
+    let hashed = url.strip_prefix("/static/docs/app.").unwrap().strip_suffix(".js").unwrap();
+    assert_eq!(hashed.len(), 8);
+    assert!(assets.fingerprinted("LICENSE").unwrap().starts_with("LICENSE."));
+    assert_eq!(assets.entries().len(), 2);
+    assert_eq!(assets.url_for("missing.css"), "/static/missing.css");
+    
+    // Fingerprinted URLs are cached for good, the plain ones as configured
+    let response = router.handle_request(&Request::new(Method::Get, &url)).unwrap();
+    assert_eq!(response.status, Status::Ok);
+    assert_eq!(response.body, b"console.log(1)");
+    assert_eq!(response.headers.get("Cache-Control").unwrap(), "public, max-age=31536000, immutable");
+    assert_eq!(response.headers.get("Content-Type").unwrap(), "text/javascript");
+    let plain = router.handle_request(&Request::new(Method::Get, "/static/docs/app.js")).unwrap();
+    assert_eq!(plain.headers.get("Cache-Control").unwrap(), "public, max-age=3600");
+    
+    // A changed file gets a new URL and the old one stops resolving
+    thread::sleep(Duration::from_millis(20));
+    fs::write(dir.join("docs/app.js"), "console.log(22)").unwrap();
+    let new_url = assets.url_for("docs/app.js");
+    assert_ne!(new_url, url);
+    let stale = router.handle_request(&Request::new(Method::Get, &url)).unwrap();
+    assert_eq!(stale.status, Status::NotFound);
+    let fresh = router.handle_request(&Request::new(Method::Get, &new_url)).unwrap();
+    assert_eq!(fresh.body, b"console.log(22)");
+    
+    // New files show up after a refresh
+    fs::write(dir.join("app.css"), "body {}").unwrap();
+    assert!(assets.fingerprinted("app.css").is_none());
+    assets.refresh().unwrap();
+    assert!(assets.fingerprinted("app.css").is_some());
+    
+    fs::remove_dir_all(&dir).unwrap();
+}
+#[test]
+fn test_content_type_mappings_and_sniffing() {
+    let dir = test_dir("mime");
+    fs::write(dir.join("logo"), b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR").unwrap();
+    fs::write(dir.join("README"), "plain words\n").unwrap();
+    fs::write(dir.join("page"), "<html><script>alert(1)</script></html>").unwrap();
+    fs::write(dir.join("blob"), [0u8, 1, 2, 3]).unwrap();
+    fs::write(dir.join("feed.ATOM"), "<feed/>").unwrap();
+    fs::write(dir.join("photo.JPG"), "not really").unwrap();
+    
+    let mut config = StaticFileConfig {
+        root_dir: dir.clone(),
+        sniff_content_types: true,
+        ..StaticFileConfig::default()
+    };
+    config.mime_types.insert(".atom".to_string(), "application/atom+xml".to_string());
+    let mut router = Router::new();
+    add_static_file_routes(&mut router, config);
+    
+    let content_type = |path: &str| {
+        let response = router.handle_request(&Request::new(Method::Get, path)).unwrap();
+        assert_eq!(response.headers.get("X-Content-Type-Options").unwrap(), "nosniff");
+        response.headers.get("Content-Type").unwrap().clone()
+    };
+    assert_eq!(content_type("/static/logo"), "image/png");
+    assert_eq!(content_type("/static/README"), "text/plain; charset=utf-8");
+    // Markup is never sniffed as HTML
+    assert_eq!(content_type("/static/page"), "text/plain; charset=utf-8");
+    assert_eq!(content_type("/static/blob"), "application/octet-stream");
+    assert_eq!(content_type("/static/feed.ATOM"), "application/atom+xml");
+    // Files with an extension are never sniffed
+    assert_eq!(content_type("/static/photo.JPG"), "image/jpeg");
+    
+    fs::remove_dir_all(&dir).unwrap();
+}
+#[test]
+fn test_custom_error_pages() {
+    let dir = test_dir("errors");
+    fs::write(dir.join("404.html"), "<h1>Lost?</h1>").unwrap();
+    
+    let mut config = StaticFileConfig {
+        root_dir: dir.clone(),
+        ..StaticFileConfig::default()
+    };
+    config.error_pages.insert(Status::NotFound, PathBuf::from("404.html"));
+    config.error_pages.insert(Status::Forbidden, dir.join("missing-403.html"));
+    let mut router = Router::new();
+    add_static_file_routes(&mut router, config);
+    
+    let missing = router.handle_request(&Request::new(Method::Get, "/static/nope.txt")).unwrap();
+    assert_eq!(missing.status, Status::NotFound);
+    assert_eq!(missing.body, b"<h1>Lost?</h1>");
+    assert_eq!(missing.headers.get("Content-Type").unwrap(), "text/html; charset=utf-8");
+    
+    // An unreadable page falls back to the plain-text message
+    let listing = router.handle_request(&Request::new(Method::Get, "/static/docs")).unwrap();
+    assert_eq!(listing.status, Status::Forbidden);
+    assert_eq!(listing.body, b"Directory listing not allowed");
+    assert_eq!(listing.headers.get("Content-Type").unwrap(), "text/plain");
+    
+    fs::remove_dir_all(&dir).unwrap();
+}
+#[test]
+fn test_directory_listings_follow_changes() {
+    let dir = test_dir("listing");
+    fs::write(dir.join("docs/a.txt"), "a").unwrap();
+    
+    let config = StaticFileConfig {
+        root_dir: dir.clone(),
+        directory_listing: true,
+        listing_cache_ttl: Duration::from_secs(60),
+        ..StaticFileConfig::default()
+    };
+    let mut router = Router::new();
+    add_static_file_routes(&mut router, config);
+    
+    let listing = |router: &Router| {
+        let response = router.handle_request(&Request::new(Method::Get, "/static/docs")).unwrap();
+        assert_eq!(response.headers.get("Content-Type").unwrap(), "text/html");
+        String::from_utf8(response.body).unwrap()
+    };
+    let first = listing(&router);
+    assert!(first.contains("a.txt"));
+    assert_eq!(listing(&router), first);
+    
+    // Adding an entry changes the directory's modification time
+    thread::sleep(Duration::from_millis(20));
+    fs::write(dir.join("docs/b.txt"), "b").unwrap();
+    let second = listing(&router);
+    assert!(second.contains("a.txt") && second.contains("b.txt"));
+    
+    fs::remove_dir_all(&dir).unwrap();
+}
+#[test]
+fn test_live_reload_injects_script_and_disables_caching() {
+    let dir = test_dir("live-reload");
+    fs::write(dir.join("index.html"), "<html><BODY>hi</BODY></html>").unwrap();
+    fs::write(dir.join("app.css"), "body {}").unwrap();
+    
+    let config = StaticFileConfig {
+        root_dir: dir.clone(),
+        live_reload: true,
+        ..StaticFileConfig::default()
+    };
+    let mut router = Router::new();
+    add_static_file_routes(&mut router, config);
+    
+    let page = router.handle_request(&Request::new(Method::Get, "/static/index.html")).unwrap();
+    let body = String::from_utf8(page.body.clone()).unwrap();
+    assert!(body.starts_with("<html><BODY>hi<script>"), "{}", body);
+    assert!(body.contains(LIVE_RELOAD_PATH));
+    assert!(body.ends_with("</script></BODY></html>"));
+    assert_eq!(page.headers.get("Content-Length").unwrap(), &body.len().to_string());
+    assert_eq!(page.headers.get("Cache-Control").unwrap(), "no-store");
+    
+    let css = router.handle_request(&Request::new(Method::Get, "/static/app.css")).unwrap();
+    assert_eq!(css.body, b"body {}");
+    
+    let events = router.handle_request(&Request::new(Method::Get, LIVE_RELOAD_PATH)).unwrap();
+    assert_eq!(events.headers.get("Content-Type").unwrap(), "text/event-stream");
+    assert!(events.upgrade.is_some());
+    
+    fs::remove_dir_all(&dir).unwrap();
+}
+static EMBEDDED_FILES: &[(&str, &[u8])] = &[
+    ("app.css", b"body {}"),
+    ("docs/guide.html", b"<p>guide</p>"),
+    ("docs/index.html", b"<p>docs</p>"),
+    ("images/logo.png", b"\x89PNG\r\n\x1a\n"),
+    ("index.html", b"<p>home</p>"),
+];
+
+#[test]
+fn test_embedded_files_are_served() {
+    let embedded = EmbeddedDir::new(EMBEDDED_FILES);
+    assert_eq!(embedded.len(), 5);
+    assert_eq!(embedded.get("/docs/guide.html"), Some(&b"<p>guide</p>"[..]));
+    assert!(embedded.is_dir("docs") && embedded.is_dir("images/"));
+    assert!(!embedded.is_dir("doc") && !embedded.is_dir("app.css"));
+    
+    let mut config = StaticFileConfig {
+        root_dir: PathBuf::from("/nonexistent"),
+        embedded: Some(embedded),
+        ..StaticFileConfig::default()
+    };
+    config.preload.insert("docs/index.html".to_string(), vec!["/static/app.css".to_string()]);
+    let mut router = Router::new();
+    add_static_file_routes(&mut router, config);
+    
+    let get = |uri: &str| router.handle_request(&Request::new(Method::Get, uri)).unwrap();
+    let home = get("/static");
+    assert_eq!(home.body, b"<p>home</p>");
+    assert_eq!(home.headers.get("Content-Type").unwrap(), "text/html");
+    
+    let docs = get("/static/docs/?v=1");
+    assert_eq!(docs.body, b"<p>docs</p>");
+    assert_eq!(docs.headers.get("Link").unwrap(), "</static/app.css>; rel=preload; as=style");
+    
+    let css = get("/static/../app.css");
+    assert_eq!(css.headers.get("Content-Type").unwrap(), "text/css");
+    assert_eq!(css.headers.get("Cache-Control").unwrap(), "public, max-age=3600");
+    
+    assert_eq!(get("/static/images").status, Status::Forbidden);
+    assert_eq!(get("/static/missing.js").status, Status::NotFound);
+}
+
+#[test]
+fn test_write_embedded_dir_lists_files() {
+    let dir = test_dir("embed");
+    fs::write(dir.join("index.html"), "<p>home</p>").unwrap();
+    fs::write(dir.join("docs/guide.html"), "<p>guide</p>").unwrap();
+    let out = dir.join("embedded.rs");
+    let root = dir.join("docs").join("..");
+    
+    write_embedded_dir(&root, &out).unwrap();
+    let source = fs::read_to_string(&out).unwrap();
+    let canonical = fs::canonicalize(&dir).unwrap();
+    let expected = format!(
+        "&[\n    (\"docs/guide.html\", include_bytes!({:?}) as &[u8]),\n    (\"index.html\", include_bytes!({:?}) as &[u8]),\n]",
+        canonical.join("docs/guide.html").to_string_lossy(),
+        canonical.join("index.html").to_string_lossy(),
+    );
+    assert_eq!(source, expected);
+    
+    fs::remove_dir_all(&dir).unwrap();
+}
+#[test]
+fn test_cache_rules_by_pattern() {
+    let dir = test_dir("cache-rules");
+    fs::create_dir_all(dir.join("vendor/lib")).unwrap();
+    fs::write(dir.join("vendor/lib/react.js"), "react").unwrap();
+    fs::write(dir.join("vendor/page.html"), "<p>vendor</p>").unwrap();
+    fs::write(dir.join("index.html"), "<p>home</p>").unwrap();
+    fs::write(dir.join("docs/guide.html"), "<p>guide</p>").unwrap();
+    fs::write(dir.join("app.js"), "app").unwrap();
+    fs::write(dir.join("v1.txt"), "1").unwrap();
+    
+    let config = StaticFileConfig {
+        root_dir: dir.clone(),
+        cache_rules: vec![
+            CacheRule::new("/static/vendor/*", "public, max-age=31536000, immutable"),
+            CacheRule::new("*.html", "no-cache"),
+            CacheRule::new("v?.txt", "max-age=60"),
+        ],
+        ..StaticFileConfig::default()
+    };
+    let mut router = Router::new();
+    add_static_file_routes(&mut router, config);
+    
+    let cache_control = |uri: &str| {
+        let response = router.handle_request(&Request::new(Method::Get, uri)).unwrap();
+        assert_eq!(response.status, Status::Ok, "{}", uri);
+        response.headers.get("Cache-Control").unwrap().clone()
+    };
+    assert_eq!(cache_control("/static/vendor/lib/react.js"), "public, max-age=31536000, immutable");
+    // Earlier rules win
+    assert_eq!(cache_control("/static/vendor/page.html"), "public, max-age=31536000, immutable");
+    assert_eq!(cache_control("/static/docs/guide.html"), "no-cache");
+    assert_eq!(cache_control("/static"), "no-cache");
+    assert_eq!(cache_control("/static/v1.txt"), "max-age=60");
+    assert_eq!(cache_control("/static/app.js"), "public, max-age=3600");
+    
+    fs::remove_dir_all(&dir).unwrap();
+}
+#[test]
+fn test_cors_rules_for_fonts_and_wasm() {
+    let dir = test_dir("cors");
+    fs::write(dir.join("font.woff2"), "font").unwrap();
+    fs::write(dir.join("docs/module.wasm"), "\0asm").unwrap();
+    fs::write(dir.join("app.js"), "app").unwrap();
+    
+    let config = StaticFileConfig {
+        root_dir: dir.clone(),
+        cors_rules: vec![
+            CorsRule::new("*.woff2"),
+            CorsRule::new("/static/docs/*")
+                .with_origin("https://app.example.com")
+                .with_resource_policy(Some("same-site")),
+        ],
+        ..StaticFileConfig::default()
+    };
+    let mut router = Router::new();
+    add_static_file_routes(&mut router, config);
+    
+    // Headers are sent with or without an Origin
+    let font = router.handle_request(&Request::new(Method::Get, "/static/font.woff2")).unwrap();
+    assert_eq!(font.headers.get("Access-Control-Allow-Origin").unwrap(), "*");
+    assert_eq!(font.headers.get("Cross-Origin-Resource-Policy").unwrap(), "cross-origin");
+    assert!(!font.headers.contains_key("Vary"));
+    
+    let wasm = router.handle_request(&Request::new(Method::Get, "/static/docs/module.wasm")).unwrap();
+    assert_eq!(wasm.headers.get("Access-Control-Allow-Origin").unwrap(), "https://app.example.com");
+    assert_eq!(wasm.headers.get("Cross-Origin-Resource-Policy").unwrap(), "same-site");
+    assert_eq!(wasm.headers.get("Vary").unwrap(), "Origin");
+    
+    let script = router.handle_request(&Request::new(Method::Get, "/static/app.js")).unwrap();
+    assert!(!script.headers.contains_key("Access-Control-Allow-Origin"));
+    assert!(!script.headers.contains_key("Cross-Origin-Resource-Policy"));
+    
+    fs::remove_dir_all(&dir).unwrap();
+}
\ No newline at end of file
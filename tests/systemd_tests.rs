@@ -0,0 +1,37 @@
+#![cfg(unix)]
+
+use high_performance_server::systemd;
+use std::env;
+use std::os::unix::net::UnixDatagram;
+use std::time::Duration;
+
+#[test]
+fn test_notify_ready() {
+    let path = env::temp_dir().join(format!("hps-notify-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let receiver = UnixDatagram::bind(&path).unwrap();
+    receiver.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+    
+    env::set_var("NOTIFY_SOCKET", &path);
+    assert!(systemd::notify_ready().unwrap());
+    
+    let mut buf = [0u8; 256];
+    let n = receiver.recv(&mut buf).unwrap();
+    let message = String::from_utf8_lossy(&buf[..n]);
+    assert!(message.starts_with("READY=1\n"));
+    assert!(message.contains(&format!("MAINPID={}", std::process::id())));
+    
+    env::remove_var("NOTIFY_SOCKET");
+    assert!(!systemd::notify_stopping().unwrap());
+    
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_listen_fds_ignores_other_pid() {
+    env::set_var("LISTEN_PID", "1");
+    env::set_var("LISTEN_FDS", "1");
+    
+    assert!(systemd::listen_fds().unwrap().is_empty());
+    assert!(env::var("LISTEN_FDS").is_err());
+}
\ No newline at end of file
@@ -0,0 +1,202 @@
+use high_performance_server::metrics::MetricsRegistry;
+use high_performance_server::{
+    tenant_middleware, Method, MiddlewareChain, Request, Response, Status, TenantConfig, TenantId, Tenants,
+    TenantsConfig,
+};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+fn config() -> TenantsConfig {
+    TenantsConfig {
+        tenant_header: Some("X-Tenant-Id".to_string()),
+        tenants: vec![
+            TenantConfig {
+                id: "acme".to_string(),
+                hosts: vec!["acme.example.com".to_string()],
+                api_keys: vec!["acme-key".into()],
+                ..TenantConfig::default()
+            },
+            TenantConfig {
+                id: "globex".to_string(),
+                hosts: vec!["*.globex.test".to_string()],
+                api_keys: vec!["globex-key".into()],
+                ..TenantConfig::default()
+            },
+        ],
+        ..TenantsConfig::default()
+    }
+}
+
+/// A chain whose handler answers with the tenant the request was matched to
+fn chain(tenants: Tenants) -> MiddlewareChain {
+    let mut chain = MiddlewareChain::new();
+    chain.add(tenant_middleware(Arc::new(tenants)));
+    chain.set_handler(|req| {
+        let mut response = Response::new(Status::Ok);
+        response.set_body(TenantId::of(req).unwrap_or("none").as_bytes());
+        Ok(response)
+    });
+    chain
+}
+
+fn request(header: &str, value: &str) -> Request {
+    let mut request = Request::new(Method::Get, "/items");
+    request.set_header(header, value);
+    request
+}
+
+#[test]
+fn test_identifies_tenants_by_key_host_and_header() {
+    let tenants = Tenants::new(config()).unwrap();
+    assert_eq!(tenants.identify(&request("X-Api-Key", "globex-key")), Some("globex"));
+    assert_eq!(tenants.identify(&request("Host", "ACME.example.com:8080")), Some("acme"));
+    assert_eq!(tenants.identify(&request("Host", "eu.globex.test")), Some("globex"));
+    assert_eq!(tenants.identify(&request("X-Tenant-Id", "acme")), Some("acme"));
+    assert_eq!(tenants.identify(&request("Host", "other.example.com")), None);
+    
+    // The API key wins over the host
+    let mut both = request("Host", "acme.example.com");
+    both.set_header("X-Api-Key", "globex-key");
+    assert_eq!(tenants.identify(&both), Some("globex"));
+    
+    // The tenant header is ignored unless trusted
+    let untrusted = Tenants::new(TenantsConfig {
+        tenant_header: None,
+        ..config()
+    })
+    .unwrap();
+    assert_eq!(untrusted.identify(&request("X-Tenant-Id", "acme")), None);
+}
+
+#[test]
+fn test_unknown_tenants_pass_through_unless_required() {
+    let response = chain(Tenants::new(config()).unwrap()).handle(&request("X-Api-Key", "nope")).unwrap();
+    assert_eq!(response.status, Status::Ok);
+    assert_eq!(response.body, b"none");
+    
+    let required = Tenants::new(TenantsConfig {
+        require_tenant: true,
+        ..config()
+    })
+    .unwrap();
+    let response = chain(required).handle(&request("X-Api-Key", "nope")).unwrap();
+    assert_eq!(response.status, Status::Unauthorized);
+    
+    let response = chain(Tenants::new(config()).unwrap()).handle(&request("X-Api-Key", "acme-key")).unwrap();
+    assert_eq!(response.body, b"acme");
+}
+
+#[test]
+fn test_rate_quota_is_per_tenant() {
+    let mut config = config();
+    config.tenants[0].rate_per_sec = Some(1);
+    config.tenants[0].burst = Some(2);
+    let registry = Arc::new(MetricsRegistry::new());
+    let chain = chain(Tenants::new(config).unwrap().with_metrics(registry.clone()));
+    
+    let statuses: Vec<Status> = (0..3)
+        .map(|_| chain.handle(&request("X-Api-Key", "acme-key")).unwrap().status)
+        .collect();
+    assert_eq!(statuses, vec![Status::Ok, Status::Ok, Status::TooManyRequests]);
+    let rejected = chain.handle(&request("X-Api-Key", "acme-key")).unwrap();
+    assert!(rejected.headers.contains_key("Retry-After"));
+    
+    // Other tenants keep their own quota
+    let response = chain.handle(&request("X-Api-Key", "globex-key")).unwrap();
+    assert_eq!(response.status, Status::Ok);
+    
+    assert_eq!(registry.counter("tenant.acme.requests").value(), 2);
+    assert_eq!(registry.counter("tenant.acme.rejected").value(), 2);
+    assert_eq!(registry.counter("tenant.globex.requests").value(), 1);
+}
+
+#[test]
+fn test_concurrency_quota_counts_requests_in_progress() {
+    let mut config = config();
+    config.tenants[0].max_concurrent = Some(1);
+    let tenants = Arc::new(Tenants::new(config).unwrap());
+    
+    let (entered_tx, entered) = mpsc::channel();
+    let (release, release_rx) = mpsc::channel::<()>();
+    let release_rx = Arc::new(std::sync::Mutex::new(release_rx));
+    let mut chain = MiddlewareChain::new();
+    chain.add(tenant_middleware(tenants.clone()));
+    chain.set_handler(move |_| {
+        let _ = entered_tx.send(());
+        let _ = release_rx.lock().unwrap().recv_timeout(Duration::from_secs(5));
+        Ok(Response::new(Status::Ok))
+    });
+    let chain = Arc::new(chain);
+    
+    let first = {
+        let chain = chain.clone();
+        thread::spawn(move || chain.handle(&request("X-Api-Key", "acme-key")).unwrap().status)
+    };
+    entered.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(tenants.in_flight("acme"), Some(1));
+    
+    let second = chain.handle(&request("X-Api-Key", "acme-key")).unwrap();
+    assert_eq!(second.status, Status::TooManyRequests);
+    
+    release.send(()).unwrap();
+    assert_eq!(first.join().unwrap(), Status::Ok);
+    assert_eq!(tenants.in_flight("acme"), Some(0));
+}
+
+#[test]
+fn test_tenant_routes_override_the_chain() {
+    let tenants = Tenants::new(config())
+        .unwrap()
+        .with_route("acme", "/items", |_| {
+            let mut response = Response::new(Status::Ok);
+            response.set_body(b"acme items");
+            Ok(response)
+        })
+        .unwrap();
+    let chain = chain(tenants);
+    
+    let response = chain.handle(&request("X-Api-Key", "acme-key")).unwrap();
+    assert_eq!(response.body, b"acme items");
+    let response = chain.handle(&request("X-Api-Key", "globex-key")).unwrap();
+    assert_eq!(response.body, b"globex");
+}
+
+#[test]
+fn test_tenant_routes_match_whole_segments() {
+    let tenants = Tenants::new(config())
+        .unwrap()
+        .with_route("acme", "/api", |_| {
+            let mut response = Response::new(Status::Ok);
+            response.set_body(b"acme api");
+            Ok(response)
+        })
+        .unwrap();
+    let chain = chain(tenants);
+    
+    let cases = [("/api", "acme api"), ("/api/orders", "acme api"), ("/apis", "acme"), ("/apiv2/x", "acme")];
+    for (path, expected) in cases {
+        let mut request = Request::new(Method::Get, path);
+        request.set_header("X-Api-Key", "acme-key");
+        let response = chain.handle(&request).unwrap();
+        assert_eq!(response.body, expected.as_bytes(), "{}", path);
+    }
+}
+
+#[test]
+fn test_conflicting_config_is_rejected() {
+    let mut duplicate_key = config();
+    duplicate_key.tenants[1].api_keys.push("acme-key".into());
+    assert!(Tenants::new(duplicate_key).is_err());
+    
+    let mut duplicate_id = config();
+    duplicate_id.tenants[1].id = "acme".to_string();
+    assert!(Tenants::new(duplicate_id).is_err());
+    
+    let mut bad_route = config();
+    bad_route.tenants[0].routes.insert("/api".to_string(), "https://acme.internal".to_string());
+    assert!(Tenants::new(bad_route).is_err());
+    
+    assert!(Tenants::new(config()).unwrap().with_route("initech", "/", |_| Ok(Response::new(Status::Ok))).is_err());
+}
\ No newline at end of file
@@ -0,0 +1,28 @@
+use high_performance_server::throttle::TokenBucket;
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn test_token_bucket_burst() {
+    let mut bucket = TokenBucket::new(1000);
+    
+    // A fresh bucket allows one second worth of data
+    assert_eq!(bucket.available(), 1000);
+    
+    bucket.consume(1000);
+    assert_eq!(bucket.available(), 0);
+    assert!(bucket.time_until_available() > Duration::ZERO);
+}
+
+#[test]
+fn test_token_bucket_refill() {
+    let mut bucket = TokenBucket::with_burst(10_000, 100);
+    bucket.consume(100);
+    
+    thread::sleep(Duration::from_millis(20));
+    
+    // Refill is capped at the burst size
+    let available = bucket.available();
+    assert!(available > 0);
+    assert!(available <= 100);
+}
\ No newline at end of file
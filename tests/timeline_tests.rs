@@ -0,0 +1,57 @@
+use high_performance_server::{MetricsCollector, Phase, RequestTimeline};
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn test_timeline_marks_keep_first_timestamp() {
+    let mut timeline = RequestTimeline::new();
+    assert!(timeline.has(Phase::Accepted));
+    assert!(!timeline.has(Phase::HeadersParsed));
+    
+    timeline.mark(Phase::HeadersParsed);
+    let first = timeline.get(Phase::HeadersParsed).unwrap();
+    thread::sleep(Duration::from_millis(1));
+    timeline.mark(Phase::HeadersParsed);
+    assert_eq!(timeline.get(Phase::HeadersParsed).unwrap(), first);
+}
+
+#[test]
+fn test_timeline_spans() {
+    let mut timeline = RequestTimeline::new();
+    for phase in Phase::ALL {
+        timeline.mark(phase);
+    }
+    
+    let names: Vec<&str> = timeline.spans().iter().map(|(name, _)| *name).collect();
+    assert_eq!(names, vec!["parse", "queue", "handler", "first_byte", "send", "total"]);
+}
+
+#[test]
+fn test_timeline_server_timing_before_completion() {
+    let mut timeline = RequestTimeline::new();
+    timeline.mark(Phase::HeadersParsed);
+    timeline.mark(Phase::HandlerStart);
+    thread::sleep(Duration::from_millis(2));
+    timeline.mark(Phase::HandlerEnd);
+    
+    let header = timeline.server_timing();
+    assert!(header.starts_with("parse;dur="));
+    assert!(header.contains("handler;dur="));
+    assert!(header.contains("total;dur="));
+    assert!(!header.contains("send"));
+}
+
+#[test]
+fn test_record_request_timeline() {
+    let metrics = MetricsCollector::new();
+    let mut timeline = RequestTimeline::new();
+    for phase in Phase::ALL {
+        timeline.mark(phase);
+    }
+    
+    metrics.record_request_timeline(&timeline);
+    
+    let registry = metrics.registry();
+    assert_eq!(registry.exponential_histogram("request_phase.total", 1.0, 2.0, 24).count(), 1);
+    assert_eq!(registry.exponential_histogram("request_phase.handler", 1.0, 2.0, 24).count(), 1);
+}
\ No newline at end of file
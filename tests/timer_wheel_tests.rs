@@ -0,0 +1,94 @@
+use high_performance_server::timer_wheel::TimerWheel;
+use std::time::{Duration, Instant};
+
+const TICK: Duration = Duration::from_millis(1);
+
+#[test]
+fn test_timers_expire_in_order_and_never_early() {
+    let mut wheel = TimerWheel::new(TICK, 64);
+    let now = Instant::now();
+    wheel.schedule(1, now + Duration::from_millis(30));
+    wheel.schedule(2, now + Duration::from_millis(10));
+    assert_eq!(wheel.len(), 2);
+    
+    assert!(wheel.expire(now + Duration::from_millis(9)).is_empty());
+    assert_eq!(wheel.expire(now + Duration::from_millis(11)), vec![2]);
+    assert!(wheel.expire(now + Duration::from_millis(29)).is_empty());
+    assert_eq!(wheel.expire(now + Duration::from_millis(31)), vec![1]);
+    assert!(wheel.is_empty());
+}
+
+#[test]
+fn test_the_earlier_deadline_wins() {
+    let mut wheel = TimerWheel::new(TICK, 64);
+    let now = Instant::now();
+    wheel.schedule(1, now + Duration::from_millis(20));
+    wheel.schedule(1, now + Duration::from_millis(40));
+    wheel.schedule(1, now + Duration::from_millis(5));
+    
+    let deadline = wheel.deadline(1).unwrap();
+    assert!(deadline >= now + Duration::from_millis(5));
+    assert!(deadline <= now + Duration::from_millis(6));
+    
+    // The entries left behind by the later deadlines don't fire again
+    assert_eq!(wheel.expire(now + Duration::from_millis(6)), vec![1]);
+    assert!(wheel.expire(now + Duration::from_millis(50)).is_empty());
+}
+
+#[test]
+fn test_cancelled_timers_never_fire() {
+    let mut wheel = TimerWheel::new(TICK, 64);
+    let now = Instant::now();
+    wheel.schedule(1, now + Duration::from_millis(5));
+    wheel.schedule(2, now + Duration::from_millis(5));
+    wheel.cancel(1);
+    
+    assert_eq!(wheel.deadline(1), None);
+    assert_eq!(wheel.expire(now + Duration::from_millis(10)), vec![2]);
+}
+
+#[test]
+fn test_deadlines_beyond_a_turn_wait_for_their_tick() {
+    // A turn of this wheel is 8ms
+    let mut wheel = TimerWheel::new(TICK, 8);
+    let now = Instant::now();
+    wheel.schedule(1, now + Duration::from_millis(21));
+    
+    // The slot comes round twice before the deadline is due
+    assert!(wheel.expire(now + Duration::from_millis(6)).is_empty());
+    assert!(wheel.expire(now + Duration::from_millis(14)).is_empty());
+    assert!(wheel.expire(now + Duration::from_millis(20)).is_empty());
+    assert_eq!(wheel.expire(now + Duration::from_millis(23)), vec![1]);
+    
+    // Jumping several turns at once still finds it
+    wheel.schedule(2, now + Duration::from_millis(40));
+    assert_eq!(wheel.expire(now + Duration::from_millis(100)), vec![2]);
+}
+
+#[test]
+fn test_past_deadlines_are_due_at_once() {
+    let mut wheel = TimerWheel::new(TICK, 64);
+    let now = Instant::now();
+    wheel.expire(now + Duration::from_millis(10));
+    wheel.schedule(1, now);
+    
+    assert_eq!(wheel.expire(now + Duration::from_millis(12)), vec![1]);
+}
+
+#[test]
+fn test_next_expiry_looks_within_the_horizon() {
+    let mut wheel = TimerWheel::new(TICK, 1024);
+    let now = Instant::now();
+    assert_eq!(wheel.next_expiry(Duration::from_secs(1)), None);
+    
+    wheel.schedule(1, now + Duration::from_millis(500));
+    wheel.schedule(2, now + Duration::from_millis(50));
+    assert_eq!(wheel.next_expiry(Duration::from_millis(10)), None);
+    
+    let next = wheel.next_expiry(Duration::from_millis(100)).unwrap();
+    assert_eq!(Some(next), wheel.deadline(2));
+    
+    wheel.cancel(2);
+    assert_eq!(wheel.next_expiry(Duration::from_millis(100)), None);
+    assert_eq!(wheel.next_expiry(Duration::from_secs(1)), wheel.deadline(1));
+}
\ No newline at end of file
@@ -0,0 +1,186 @@
+use high_performance_server::metrics::MetricsRegistry;
+use high_performance_server::{
+    mount_traffic_split, traffic_split_middleware, Method, MiddlewareChain, Request, Response, RollbackPolicy, Router,
+    ServerError, Status, TrafficSplit,
+};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+fn body_handler(body: &'static str) -> impl Fn(&Request) -> Result<Response, ServerError> + Send + Sync {
+    move |_| {
+        let mut response = Response::new(Status::Ok);
+        response.set_body(body.as_bytes());
+        Ok(response)
+    }
+}
+
+fn variant_of(response: &Response) -> &str {
+    response.headers.get("X-Variant").unwrap()
+}
+
+#[test]
+fn test_split_by_weight() {
+    let split = TrafficSplit::new("checkout")
+        .variant("a", 3, body_handler("A"))
+        .variant("b", 1, body_handler("B"));
+    
+    let request = Request::new(Method::Get, "/checkout");
+    let served: Vec<String> = (0..8)
+        .map(|_| {
+            let response = split.handle(&request).unwrap();
+            assert_eq!(response.body, variant_of(&response).to_uppercase().as_bytes());
+            variant_of(&response).to_string()
+        })
+        .collect();
+    
+    // Smooth weighted round robin interleaves the lighter variant
+    assert_eq!(served, ["a", "a", "b", "a", "a", "a", "b", "a"]);
+    
+    assert!(split.set_weight("b", 0));
+    assert!(!split.set_weight("c", 1));
+    assert!((0..10).all(|_| variant_of(&split.handle(&request).unwrap()) == "a"));
+    assert_eq!(split.weights(), [("a".to_string(), 3), ("b".to_string(), 0)]);
+}
+
+#[test]
+fn test_sticky_cookie_keeps_variant() {
+    let split = TrafficSplit::new("checkout")
+        .variant("a", 1, body_handler("A"))
+        .variant("b", 1, body_handler("B"))
+        .sticky("ab");
+    
+    // A new client is assigned a variant and told to remember it
+    let response = split.handle(&Request::new(Method::Get, "/")).unwrap();
+    assert_eq!(variant_of(&response), "a");
+    assert!(response.headers.get("Set-Cookie").unwrap().starts_with("ab=a; Path=/;"));
+    
+    let mut returning = Request::new(Method::Get, "/");
+    returning.set_header("Cookie", "ab=a");
+    for _ in 0..4 {
+        let response = split.handle(&returning).unwrap();
+        assert_eq!(variant_of(&response), "a");
+        assert!(!response.headers.contains_key("Set-Cookie"));
+    }
+    
+    // Clients on a variant that was switched off are moved
+    split.set_weight("a", 0);
+    let response = split.handle(&returning).unwrap();
+    assert_eq!(variant_of(&response), "b");
+    assert!(response.headers.get("Set-Cookie").unwrap().starts_with("ab=b;"));
+}
+
+#[test]
+fn test_split_metrics_per_variant() {
+    let registry = Arc::new(MetricsRegistry::new());
+    let split = TrafficSplit::new("search")
+        .variant("old", 1, body_handler("old"))
+        .variant("new", 1, |_| Err(ServerError::Protocol("backend down".to_string())))
+        .with_metrics(registry.clone());
+    
+    let request = Request::new(Method::Get, "/search");
+    for _ in 0..4 {
+        let _ = split.handle(&request);
+    }
+    
+    assert_eq!(registry.counter("split.search.old.requests").value(), 2);
+    assert_eq!(registry.counter("split.search.old.errors").value(), 0);
+    assert_eq!(registry.counter("split.search.new.requests").value(), 2);
+    assert_eq!(registry.counter("split.search.new.errors").value(), 2);
+    assert_eq!(registry.exponential_histogram("split.search.old.latency_us", 1.0, 2.0, 24).count(), 2);
+}
+
+#[test]
+fn test_split_middleware_passes_control_down_the_chain() {
+    let split = Arc::new(TrafficSplit::new("home").passthrough("control", 1).variant("redesign", 1, body_handler("new")));
+    
+    let mut chain = MiddlewareChain::new();
+    chain.add(traffic_split_middleware(split.clone()));
+    chain.set_handler(body_handler("old"));
+    
+    let request = Request::new(Method::Get, "/");
+    let first = chain.handle(&request).unwrap();
+    let second = chain.handle(&request).unwrap();
+    assert_eq!((variant_of(&first), first.body.as_slice()), ("control", &b"old"[..]));
+    assert_eq!((variant_of(&second), second.body.as_slice()), ("redesign", &b"new"[..]));
+    
+    // Outside a chain there is nothing to pass through to
+    assert!(split.handle(&request).is_err());
+}
+
+#[test]
+fn test_failing_canary_is_rolled_back() {
+    let healthy = Arc::new(AtomicBool::new(true));
+    let canary_health = healthy.clone();
+    let registry = Arc::new(MetricsRegistry::new());
+    let split = TrafficSplit::new("api")
+        .variant("stable", 3, body_handler("stable"))
+        .canary("canary", 1, move |_| {
+            let status = if canary_health.load(Ordering::SeqCst) { Status::Ok } else { Status::InternalServerError };
+            Ok(Response::new(status))
+        })
+        .with_rollback(RollbackPolicy {
+            max_error_rate: 0.5,
+            window: Duration::from_secs(60),
+            min_requests: 4,
+        })
+        .with_metrics(registry.clone());
+    
+    let request = Request::new(Method::Get, "/api");
+    for _ in 0..40 {
+        split.handle(&request).unwrap();
+    }
+    assert!(!split.is_rolled_back("canary"));
+    
+    // Failures stay within the limit until the canary has failed more than half its requests
+    healthy.store(false, Ordering::SeqCst);
+    let mut canary_served = 0;
+    while !split.is_rolled_back("canary") {
+        if split.handle(&request).unwrap().headers["X-Variant"] == "canary" {
+            canary_served += 1;
+        }
+    }
+    assert_eq!(canary_served, 11);
+    assert_eq!(registry.counter("split.api.canary.rollbacks").value(), 1);
+    assert!((0..20).all(|_| variant_of(&split.handle(&request).unwrap()) == "stable"));
+    
+    let status = split.status();
+    assert_eq!(status[1].weight, 0);
+    assert_eq!((status[1].requests, status[1].errors), (21, 11));
+    
+    // Restoring a weight starts the canary over with a clean record
+    healthy.store(true, Ordering::SeqCst);
+    assert!(split.set_weight("canary", 1));
+    assert!(!split.is_rolled_back("canary"));
+    assert_eq!(split.status()[1].errors, 0);
+    assert!((0..8).any(|_| variant_of(&split.handle(&request).unwrap()) == "canary"));
+}
+
+#[test]
+fn test_traffic_split_admin_routes() {
+    let split = Arc::new(
+        TrafficSplit::new("api").variant("stable", 9, body_handler("stable")).canary("canary", 1, body_handler("canary")),
+    );
+    let mut router = Router::new();
+    mount_traffic_split(&mut router, "/admin/split", split.clone());
+    
+    let mut update = Request::new(Method::Put, "/admin/split?variant=canary");
+    update.body = br#"{"weight": 5}"#.to_vec();
+    assert_eq!(router.handle_request(&update).unwrap().status, Status::NoContent);
+    assert_eq!(split.weights()[1], ("canary".to_string(), 5));
+    
+    let listing = router.handle_request(&Request::new(Method::Get, "/admin/split")).unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&listing.body).unwrap();
+    assert_eq!(body["experiment"], "api");
+    assert_eq!(body["variants"][1]["weight"], 5);
+    assert_eq!(body["variants"][1]["canary"], true);
+    assert_eq!(body["variants"][0]["canary"], false);
+    
+    let mut unknown = Request::new(Method::Put, "/admin/split?variant=v3");
+    unknown.body = br#"{"weight": 1}"#.to_vec();
+    assert_eq!(router.handle_request(&unknown).unwrap().status, Status::NotFound);
+    
+    let mut invalid = Request::new(Method::Put, "/admin/split?variant=canary");
+    invalid.body = br#"{"weight": -1}"#.to_vec();
+    assert_eq!(router.handle_request(&invalid).unwrap().status, Status::BadRequest);
+}
\ No newline at end of file
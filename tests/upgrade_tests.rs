@@ -0,0 +1,36 @@
+#![cfg(unix)]
+
+use high_performance_server::upgrade::{inherited_acceptors, INHERITED_FDS_ENV};
+use std::env;
+use std::net::{TcpListener, TcpStream};
+use std::os::unix::io::IntoRawFd;
+
+#[test]
+fn test_inherited_acceptors() {
+    // Nothing to inherit without the environment variable
+    assert!(inherited_acceptors().unwrap().is_empty());
+    
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let fd = listener.into_raw_fd();
+    
+    env::set_var(INHERITED_FDS_ENV, fd.to_string());
+    let acceptors = inherited_acceptors().unwrap();
+    assert_eq!(acceptors.len(), 1);
+    assert_eq!(acceptors[0].local_addr().unwrap(), addr);
+    
+    // The variable is consumed so it isn't passed on again
+    assert!(env::var(INHERITED_FDS_ENV).is_err());
+    
+    // The inherited socket still accepts connections
+    let _client = TcpStream::connect(addr).unwrap();
+    let mut accepted = None;
+    for _ in 0..100 {
+        if let Ok(conn) = acceptors[0].accept() {
+            accepted = Some(conn);
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    assert!(accepted.is_some());
+}
\ No newline at end of file
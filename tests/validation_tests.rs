@@ -0,0 +1,99 @@
+use high_performance_server::http::{Method, Request, Response, Status};
+use high_performance_server::openapi::{self, OpenApiInfo};
+use high_performance_server::router::Router;
+use high_performance_server::validation::{ParamRule, ParamType};
+use serde_json::Value;
+
+fn ok_router() -> Router {
+    let mut router = Router::new();
+    router.get("/users/:id<u64>", |_| Ok(Response::new(Status::Ok)));
+    router
+        .get("/articles/:slug", |_| Ok(Response::new(Status::Ok)))
+        .validate(ParamRule::path("slug", ParamType::String).with_pattern("[a-z0-9-]+").unwrap())
+        .validate(ParamRule::query("page", ParamType::U64).with_range(1..=1000));
+    router
+}
+
+fn details(response: &Response) -> Value {
+    let body: Value = serde_json::from_slice(&response.body).unwrap();
+    assert_eq!(body["error"], "validation_failed");
+    body["details"].clone()
+}
+
+#[test]
+fn test_typed_path_param() {
+    let router = ok_router();
+    
+    let response = router.handle_request(&Request::new(Method::Get, "/users/42")).unwrap();
+    assert_eq!(response.status, Status::Ok);
+    
+    let response = router.handle_request(&Request::new(Method::Get, "/users/abc")).unwrap();
+    assert_eq!(response.status, Status::UnprocessableEntity);
+    assert_eq!(response.headers.get("Content-Type").unwrap(), "application/json");
+    let details = details(&response);
+    assert_eq!(details[0]["param"], "id");
+    assert_eq!(details[0]["location"], "path");
+    
+    // Typed segments still extract under their plain name
+    let params = router.extract_params("/users/:id<u64>", "/users/42");
+    assert_eq!(params.get("id").unwrap(), "42");
+}
+
+#[test]
+fn test_query_range_and_pattern() {
+    let router = ok_router();
+    
+    let response = router.handle_request(&Request::new(Method::Get, "/articles/hello-world?page=3")).unwrap();
+    assert_eq!(response.status, Status::Ok);
+    
+    // Optional query parameters may be left out
+    let response = router.handle_request(&Request::new(Method::Get, "/articles/hello-world")).unwrap();
+    assert_eq!(response.status, Status::Ok);
+    
+    let response = router.handle_request(&Request::new(Method::Get, "/articles/Hello_World?page=0")).unwrap();
+    assert_eq!(response.status, Status::UnprocessableEntity);
+    let details = details(&response);
+    assert_eq!(details.as_array().unwrap().len(), 2);
+    assert_eq!(details[0]["param"], "slug");
+    assert_eq!(details[1]["param"], "page");
+    assert_eq!(details[1]["message"], "must be at least 1");
+}
+
+#[test]
+fn test_required_query_param() {
+    let mut router = Router::new();
+    router
+        .get("/search", |_| Ok(Response::new(Status::Ok)))
+        .validate(ParamRule::query("q", ParamType::String).required().with_range(1..=64));
+    
+    let response = router.handle_request(&Request::new(Method::Get, "/search")).unwrap();
+    assert_eq!(response.status, Status::UnprocessableEntity);
+    assert_eq!(details(&response)[0]["message"], "is required");
+    
+    let response = router.handle_request(&Request::new(Method::Get, "/search?q=rust")).unwrap();
+    assert_eq!(response.status, Status::Ok);
+}
+
+#[test]
+fn test_invalid_pattern_is_config_error() {
+    assert!(ParamRule::path("slug", ParamType::String).with_pattern("[a-").is_err());
+}
+
+#[test]
+#[should_panic(expected = "Unknown parameter type")]
+fn test_unknown_param_type_panics() {
+    let mut router = Router::new();
+    router.get("/users/:id<uuid4>", |_| Ok(Response::new(Status::Ok)));
+}
+
+#[test]
+fn test_rules_appear_in_openapi() {
+    let document = openapi::generate(&ok_router(), &OpenApiInfo::new("Validated", "1.0"));
+    
+    let user = &document["paths"]["/users/{id}"]["get"]["parameters"][0];
+    assert_eq!(user["schema"]["type"], "integer");
+    
+    let page = &document["paths"]["/articles/{slug}"]["get"]["parameters"][1];
+    assert_eq!(page["name"], "page");
+    assert_eq!(page["schema"]["maximum"], 1000);
+}
\ No newline at end of file